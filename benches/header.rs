@@ -0,0 +1,57 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+const FILES: usize = 10_000;
+
+fn builder() -> rpm::RPMBuilder {
+    let mut builder = rpm::RPMBuilder::new("many", "1.0.0", "MIT", "noarch", "many files");
+    for i in 0..FILES {
+        builder = builder
+            .with_file(
+                "./test_assets/awesome.toml",
+                rpm::RPMFileOptions::new(format!("/usr/share/many/{}/file-{}.toml", i % 100, i)),
+            )
+            .expect("test asset is readable");
+    }
+    builder
+}
+
+fn header(c: &mut Criterion) {
+    let pkg = builder().build().expect("package builds");
+    let len = {
+        let mut out = Vec::new();
+        pkg.metadata.write(&mut out).unwrap();
+        out.len()
+    };
+
+    let mut group = c.benchmark_group("header");
+    group.sample_size(10);
+    group.bench_function("write 10k files", |b| {
+        b.iter(|| {
+            let mut out = Vec::with_capacity(len);
+            pkg.metadata.write(&mut out).unwrap();
+            out
+        })
+    });
+    // every write is a syscall, as for a plain `File`
+    #[cfg(unix)]
+    {
+        let mut null = std::fs::OpenOptions::new()
+            .write(true)
+            .open("/dev/null")
+            .unwrap();
+        group.bench_function("write 10k files unbuffered", |b| {
+            b.iter(|| pkg.metadata.write(&mut null).unwrap())
+        });
+    }
+    group.bench_function("build 10k files", |b| {
+        b.iter_batched(
+            builder,
+            |builder| builder.build().unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, header);
+criterion_main!(benches);
@@ -81,8 +81,8 @@ mod pgp {
             .await?
             .epoch(1)
             .pre_install_script("echo preinst")
-            .add_changelog_entry("me", "was awesome, eh?", 123_123_123)
-            .add_changelog_entry("you", "yeah, it was", 12_312_312)
+            .add_changelog_entry("me", "was awesome, eh?", Timestamp::new(123_123_123))
+            .add_changelog_entry("you", "yeah, it was", Timestamp::new(12_312_312))
             .requires(Dependency::any("rpm-sign".to_string()))
             .build_and_sign(signer)?;
 
@@ -179,8 +179,8 @@ mod pgp {
             )?
             .epoch(1)
             .pre_install_script("echo preinst")
-            .add_changelog_entry("me", "was awesome, eh?", 123_123_123)
-            .add_changelog_entry("you", "yeah, it was", 12_312_312)
+            .add_changelog_entry("me", "was awesome, eh?", Timestamp::new(123_123_123))
+            .add_changelog_entry("you", "yeah, it was", Timestamp::new(12_312_312))
             .requires(Dependency::any("rpm-sign".to_string()))
             .build_and_sign(signer)?;
 
@@ -238,7 +238,7 @@ mod pgp {
             )?
             .epoch(3)
             .pre_install_script("echo preinst")
-            .add_changelog_entry("you", "yada yada", 12_317_712)
+            .add_changelog_entry("you", "yada yada", Timestamp::new(12_317_712))
             .requires(Dependency::any("rpm-sign".to_string()))
             .build_and_sign(&signer)?;
 
@@ -1,10 +1,26 @@
 //! RPM specific constants
 //!
 //! These constants were extracted from the rpm upstream project
-//! C headers.
+//! C headers: header tags of [`IndexTag`] and [`IndexSignatureTag`], the
+//! data types of [`IndexType`], the `RPMSENSE_*` flags of dependencies, the
+//! `RPMFILE_*` flags of files and the magic numbers of lead and header.
+//!
+//! All of them are also available from the crate root.
+//!
+//! ```rust
+//! use std::convert::TryFrom;
+//! use rpm::constants::{IndexTag, IndexType};
+//!
+//! assert_eq!(IndexTag::RPMTAG_NAME, IndexTag::try_from(1000).unwrap());
+//! assert_eq!(IndexType::StringArray, IndexType::try_from(8).unwrap());
+//! assert!(IndexTag::try_from(1).is_err());
+//! ```
 
+use std::convert::TryFrom;
 use std::fmt::Display;
 
+use crate::errors::RPMError;
+
 pub const HEADER_IMAGE: isize = 61;
 pub const HEADER_SIGNATURES: isize = 62;
 pub const HEADER_IMMUTABLE: isize = 63;
@@ -423,6 +439,18 @@ pub enum IndexSignatureTag {
 
     // A silly tag for a date.
     RPMTAG_INSTALLTIME = IndexTag::RPMTAG_INSTALLTIME as isize,
+
+    // Broken digests and signatures of rpm versions before 4.0, kept for parsing old packages.
+    RPMSIGTAG_BADSHA1_1 = IndexTag::RPMTAG_BADSHA1_1 as isize,
+    RPMSIGTAG_BADSHA1_2 = IndexTag::RPMTAG_BADSHA1_2 as isize,
+    RPMSIGTAG_LEMD5_1 = HEADER_TAGBASE + 1,
+    RPMSIGTAG_LEMD5_2 = HEADER_TAGBASE + 3,
+    RPMSIGTAG_PGP5 = HEADER_TAGBASE + 6,
+
+    // Not defined by rpm, a vendor tag from the range rpm leaves to external tags.
+    // The data is a DER encoded CMS (RFC 5652) signature detached over the Header section,
+    // as used by vendors signing with X.509 certificates instead of OpenPGP keys.
+    RPMSIGTAG_CMS = 1_000_001,
 }
 
 pub trait TypeName {
@@ -441,6 +469,66 @@ impl TypeName for IndexSignatureTag {
     }
 }
 
+macro_rules! try_from_u32 {
+    ($tag:ty) => {
+        impl TryFrom<u32> for $tag {
+            type Error = RPMError;
+
+            fn try_from(raw_tag: u32) -> Result<Self, Self::Error> {
+                num::FromPrimitive::from_u32(raw_tag).ok_or_else(|| RPMError::InvalidTag {
+                    raw_tag,
+                    store_type: <$tag>::type_name(),
+                })
+            }
+        }
+    };
+}
+
+try_from_u32!(IndexTag);
+try_from_u32!(IndexSignatureTag);
+
+/// Data types of header entries, `RPM_*_TYPE` in rpm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IndexType {
+    Null = 0,
+    Char = 1,
+    Int8 = 2,
+    Int16 = 3,
+    Int32 = 4,
+    Int64 = 5,
+    /// A single NUL terminated string.
+    String = 6,
+    Bin = 7,
+    StringArray = 8,
+    /// One string per locale of `RPMTAG_HEADERI18NTABLE`.
+    I18NString = 9,
+}
+
+impl TryFrom<u32> for IndexType {
+    type Error = RPMError;
+
+    fn try_from(raw_data_type: u32) -> Result<Self, Self::Error> {
+        Ok(match raw_data_type {
+            0 => IndexType::Null,
+            1 => IndexType::Char,
+            2 => IndexType::Int8,
+            3 => IndexType::Int16,
+            4 => IndexType::Int32,
+            5 => IndexType::Int64,
+            6 => IndexType::String,
+            7 => IndexType::Bin,
+            8 => IndexType::StringArray,
+            9 => IndexType::I18NString,
+            _ => {
+                return Err(RPMError::InvalidTagDataType {
+                    raw_data_type,
+                    store_type: "IndexType",
+                })
+            }
+        })
+    }
+}
+
 /// lead header size
 pub const LEAD_SIZE: usize = 96;
 /// rpm magic as part of the lead header
@@ -449,46 +537,87 @@ pub const RPM_MAGIC: [u8; 4] = [0xed, 0xab, 0xee, 0xdb];
 /// header magic recognition (not the lead!)
 pub const HEADER_MAGIC: [u8; 3] = [0x8e, 0xad, 0xe8];
 
+/// Lead package type of binary packages.
+pub const RPMLEAD_BINARY: u16 = 0;
+/// Lead package type of source packages.
+pub const RPMLEAD_SOURCE: u16 = 1;
+/// Lead operating system number of Linux, the only one rpm writes nowadays.
+pub const RPMLEAD_OS_LINUX: u16 = 1;
+/// Lead signature type announcing a signature header, the only one rpm reads.
+pub const RPMSIGTYPE_HEADERSIG: u16 = 5;
+
+/// Any version matches.
 pub const RPMSENSE_ANY: u32 = 0;
 pub const RPMSENSE_LESS: u32 = 1 << 1;
 pub const RPMSENSE_GREATER: u32 = 1 << 2;
 pub const RPMSENSE_EQUAL: u32 = 1 << 3;
-
-// there is no use yet for those constants. But they are part of the official package
-// so I will leave them in in case we need them later.
-
-// const RPMSENSE_POSTTRANS: u32 = (1 << 5);
-// const RPMSENSE_PREREQ: u32 = (1 << 6);
-// const RPMSENSE_PRETRANS: u32 = (1 << 7);
-// const RPMSENSE_INTERP: u32 = (1 << 8);
-// const RPMSENSE_SCRIPT_PRE: u32 = (1 << 9);
-// const RPMSENSE_SCRIPT_POST: u32 = (1 << 10);
-// const RPMSENSE_SCRIPT_PREUN: u32 = (1 << 11);
-// const RPMSENSE_SCRIPT_POSTUN: u32 = (1 << 12);
-// const RPMSENSE_SCRIPT_VERIFY: u32 = (1 << 13);
-// const RPMSENSE_FIND_REQUIRES: u32 = (1 << 14);
-// const RPMSENSE_FIND_PROVIDES: u32 = (1 << 15);
-// const RPMSENSE_TRIGGERIN: u32 = (1 << 16);
-// const RPMSENSE_TRIGGERUN: u32 = (1 << 17);
-// const RPMSENSE_TRIGGERPOSTUN: u32 = (1 << 18);
-// const RPMSENSE_MISSINGOK: u32 = (1 << 19);
-
-// // for some weird reason, centos packages have another value for rpm lib sense. We have to observe this.
-// const RPMSENSE_RPMLIB: u32 = (1 << 24); //0o100000012;
-// const RPMSENSE_TRIGGERPREIN: u32 = (1 << 25);
-// const RPMSENSE_KEYRING: u32 = (1 << 26);
-// const RPMSENSE_CONFIG: u32 = (1 << 28);
+/// Needed by `%posttrans`.
+pub const RPMSENSE_POSTTRANS: u32 = 1 << 5;
+/// Legacy `PreReq`, needed by the scriptlets.
+pub const RPMSENSE_PREREQ: u32 = 1 << 6;
+/// Needed by `%pretrans`.
+pub const RPMSENSE_PRETRANS: u32 = 1 << 7;
+/// Interpreter of a scriptlet.
+pub const RPMSENSE_INTERP: u32 = 1 << 8;
+pub const RPMSENSE_SCRIPT_PRE: u32 = 1 << 9;
+pub const RPMSENSE_SCRIPT_POST: u32 = 1 << 10;
+pub const RPMSENSE_SCRIPT_PREUN: u32 = 1 << 11;
+pub const RPMSENSE_SCRIPT_POSTUN: u32 = 1 << 12;
+pub const RPMSENSE_SCRIPT_VERIFY: u32 = 1 << 13;
+/// Generated by the dependency generators of rpmbuild.
+pub const RPMSENSE_FIND_REQUIRES: u32 = 1 << 14;
+pub const RPMSENSE_FIND_PROVIDES: u32 = 1 << 15;
+pub const RPMSENSE_TRIGGERIN: u32 = 1 << 16;
+pub const RPMSENSE_TRIGGERUN: u32 = 1 << 17;
+pub const RPMSENSE_TRIGGERPOSTUN: u32 = 1 << 18;
+/// Not installing the requirement is no error, as for `Requires(meta)` with missing providers.
+pub const RPMSENSE_MISSINGOK: u32 = 1 << 19;
+/// A feature of rpm itself, `rpmlib(...)`.
+pub const RPMSENSE_RPMLIB: u32 = 1 << 24;
+pub const RPMSENSE_TRIGGERPREIN: u32 = 1 << 25;
+/// A public key of the rpm keyring.
+pub const RPMSENSE_KEYRING: u32 = 1 << 26;
+/// Provided by a configuration file, `config(<name>)`.
+pub const RPMSENSE_CONFIG: u32 = 1 << 28;
 
 pub const RPMFILE_CONFIG: i32 = 1;
 pub const RPMFILE_DOC: i32 = 1 << 1;
-// const RPMFILE_DONOTUSE: i32 = (1 << 2);
-// const RPMFILE_MISSINGOK: i32 = (1 << 3);
-// const RPMFILE_NOREPLACE: i32 = (1 << 4);
-// const RPMFILE_SPECFILE: i32 = (1 << 5);
-// const RPMFILE_GHOST: i32 = (1 << 6);
-// const RPMFILE_LICENSE: i32 = (1 << 7);
-// const RPMFILE_README: i32 = (1 << 8);
-// const RPMFILE_EXCLUDE: i32 = (1 << 9);
+pub const RPMFILE_DONOTUSE: i32 = 1 << 2;
+/// Missing on verification is no error, `%config(missingok)`.
+pub const RPMFILE_MISSINGOK: i32 = 1 << 3;
+pub const RPMFILE_NOREPLACE: i32 = 1 << 4;
+pub const RPMFILE_SPECFILE: i32 = 1 << 5;
+/// Owned but not shipped in the payload, `%ghost`.
+pub const RPMFILE_GHOST: i32 = 1 << 6;
+pub const RPMFILE_LICENSE: i32 = 1 << 7;
+pub const RPMFILE_README: i32 = 1 << 8;
+pub const RPMFILE_EXCLUDE: i32 = 1 << 9;
+/// Only present in the payload of source packages.
+pub const RPMFILE_UNPATCHED: i32 = 1 << 10;
+pub const RPMFILE_PUBKEY: i32 = 1 << 11;
+/// Not written to disk, but part of the metadata, `%artifact`.
+pub const RPMFILE_ARTIFACT: i32 = 1 << 12;
+
+/// Digest of the file content, `5` in the output of `rpm -V`.
+pub const RPMVERIFY_FILEDIGEST: u32 = 1;
+/// `S`
+pub const RPMVERIFY_FILESIZE: u32 = 1 << 1;
+/// Target of a symlink, `L`.
+pub const RPMVERIFY_LINKTO: u32 = 1 << 2;
+/// `U`
+pub const RPMVERIFY_USER: u32 = 1 << 3;
+/// `G`
+pub const RPMVERIFY_GROUP: u32 = 1 << 4;
+/// `T`
+pub const RPMVERIFY_MTIME: u32 = 1 << 5;
+/// Permissions and file type, `M`.
+pub const RPMVERIFY_MODE: u32 = 1 << 6;
+/// Major and minor number of device files, `D`.
+pub const RPMVERIFY_RDEV: u32 = 1 << 7;
+/// File capabilities, `P`.
+pub const RPMVERIFY_CAPS: u32 = 1 << 8;
+/// Everything, what rpm records unless the spec says `%verify(not ...)`.
+pub const RPMVERIFY_ALL: u32 = !0;
 
 // copied from rpmpgp.h
 // should be technically equiv to
@@ -2,7 +2,7 @@ use std::io;
 
 use thiserror::Error;
 
-use crate::FileDigestAlgorithm;
+use crate::{FileDigestAlgorithm, LayoutIssue, Message, MessageCatalog};
 
 #[derive(Error, Debug)]
 #[non_exhaustive]
@@ -13,6 +13,10 @@ pub enum RPMError {
     #[error(transparent)]
     Hex(#[from] hex::FromHexError),
 
+    #[cfg(feature = "rpmdb-sqlite")]
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+
     #[error("{0}")]
     Nom(String),
     #[error(
@@ -58,6 +62,9 @@ pub enum RPMError {
     #[error("invalid type - expected 0 or 1 but got {0}")]
     InvalidLeadPKGType(u16),
 
+    #[error("lead name is not null terminated")]
+    InvalidLeadName,
+
     #[error("invalid os-type - expected 1 but got {0}")]
     InvalidLeadOSType(u16),
 
@@ -74,24 +81,39 @@ pub enum RPMError {
     NoSignatureFound,
 
     #[error("error creating signature: {0}")]
-    SignError(Box<dyn std::error::Error>),
+    SignError(Box<dyn std::error::Error + Send + Sync>),
 
     #[error("error parsing key - {details}. underlying error was: {source}")]
     KeyLoadError {
-        source: Box<dyn std::error::Error>,
+        source: Box<dyn std::error::Error + Send + Sync>,
         details: &'static str,
     },
 
     #[error("error verifying signature with key {key_ref}: {source}")]
     VerificationError {
-        source: Box<dyn std::error::Error>,
+        source: Box<dyn std::error::Error + Send + Sync>,
         key_ref: String,
     },
 
     #[error("unable to find key with key-ref: {key_ref}")]
     KeyNotFoundError { key_ref: String },
 
-    #[error("unknown compressor type {0} - only gzip and none are supported")]
+    #[error("key {key_ref} expired at {expired_at}")]
+    KeyExpired { key_ref: String, expired_at: String },
+
+    #[error("key {key_ref} was revoked - {reason}")]
+    KeyRevoked { key_ref: String, reason: String },
+
+    #[error("rejected by verification policy - {0}")]
+    PolicyViolation(String),
+
+    #[error("error generating key: {0}")]
+    KeyGenerationError(Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("invalid ascii armor - {0}")]
+    InvalidArmor(String),
+
+    #[error("unknown compressor type {0} - payloads are written with none, gzip or zstd and also read with xz, lzma or bzip2")]
     UnknownCompressorType(String),
 
     #[error("unsupported file digest algorithm {0:?}")]
@@ -99,6 +121,495 @@ pub enum RPMError {
 
     #[error("invalid file mode {raw_mode} - {reason}")]
     InvalidFileMode { raw_mode: i32, reason: &'static str },
+
+    #[error("{path} violates the rpm format - {reason}")]
+    SpecViolation { path: String, reason: &'static str },
+
+    #[error("{path} violates the mode policy - {reason}")]
+    ModePolicyViolation { path: String, reason: &'static str },
+
+    #[error("unsupported archive entry {path} - {reason}")]
+    UnsupportedArchiveEntry { path: String, reason: &'static str },
+
+    #[error("package {0} is not a gpg-pubkey pseudo package")]
+    NotAPublicKeyPackage(String),
+
+    #[error("invalid rpm database - {0}")]
+    InvalidDatabase(String),
+
+    #[error("unresolvable Requires(pre) loop between {packages:?}")]
+    DependencyCycle { packages: Vec<String> },
+
+    #[error("invalid owner {owner} of {path} - {reason}")]
+    InvalidOwner {
+        path: String,
+        owner: String,
+        reason: &'static str,
+    },
+
+    #[error("unsupported ELF file {path} - {reason}")]
+    UnsupportedElf { path: String, reason: &'static str },
+
+    #[error("{0} is not relocatable")]
+    NotRelocatable(String),
+
+    #[error("invalid header region {tag} - {reason}")]
+    InvalidRegion { tag: String, reason: &'static str },
+
+    #[error("operation cancelled")]
+    Cancelled,
+
+    #[error("tag {tag} is not valid UTF-8")]
+    InvalidUtf8 { tag: String },
+
+    #[error("{0} is not part of the package")]
+    FileNotFound(String),
+
+    #[error("{path} alone takes {size} bytes, more than the budget of {budget}")]
+    OverBudget {
+        path: String,
+        size: u64,
+        budget: u64,
+    },
+
+    #[error("{limit} of {actual} exceeds the limit of {max}")]
+    LimitExceeded {
+        limit: &'static str,
+        actual: u64,
+        max: u64,
+    },
+
+    #[error("{path} matches the forbidden path {pattern}")]
+    ForbiddenPath { path: String, pattern: String },
+
+    #[error("invalid chunk manifest - {0}")]
+    InvalidChunkManifest(String),
+
+    #[error("invalid repodata - {0}")]
+    InvalidRepodata(String),
+
+    #[error("{location} does not match the repodata - {reason}")]
+    RepodataMismatch { location: String, reason: String },
+
+    #[error("request for {url} failed - {reason}")]
+    Remote { url: String, reason: String },
+
+    #[error("invalid dependency {text:?} - {reason}")]
+    InvalidDependency { text: String, reason: &'static str },
+
+    #[error("header layout differs from rpm - {0}")]
+    NonCanonicalLayout(LayoutIssue),
+
+    #[error("refusing to write {0} in FIPS mode")]
+    BannedAlgorithm(String),
+
+    #[error("file name {0:?} is not valid UTF-8")]
+    NonUtf8FileName(String),
+
+    #[error("digest check failed - {0}")]
+    DigestMismatch(String),
+
+    #[error("invalid delta - {0}")]
+    InvalidDelta(String),
+
+    #[error("scriptlet {scriptlet} cannot be run - {reason}")]
+    InvalidScriptlet {
+        scriptlet: String,
+        reason: &'static str,
+    },
+
+    #[error("invalid {tag} - {reason}")]
+    InvalidTagValue {
+        tag: &'static str,
+        reason: &'static str,
+    },
+
+    #[error("invalid manifest line {line} - {reason}")]
+    InvalidManifest { line: usize, reason: &'static str },
+
+    #[error("invalid license {license} - {reason}")]
+    InvalidLicense { license: String, reason: String },
+
+    #[error("invalid key fingerprint {fingerprint}")]
+    InvalidFingerprint { fingerprint: String },
+
+    #[error("invalid trust store - {reason}")]
+    InvalidTrustStore { reason: String },
+
+    #[error("none of the keys is trusted for {origin}")]
+    UntrustedKey { origin: String },
+
+    #[error("{path} is an ELF binary for the unsupported machine type {machine}")]
+    UnsupportedMachine { path: String, machine: u16 },
+
+    #[error("invalid timestamp {seconds} - {reason}")]
+    InvalidTimestamp { seconds: i64, reason: &'static str },
+
+    #[error("invalid changelog in line {line} - {reason}")]
+    InvalidChangelog { line: usize, reason: String },
+
+    #[error("git - {reason}")]
+    Git { reason: String },
+
+    #[error("invalid compression dictionary - {reason}")]
+    InvalidDictionary { reason: String },
+
+    #[error("conformance check {check} failed - {reason}")]
+    Conformance { check: &'static str, reason: String },
+
+    #[error("payload segments need a zstd payload, not {compressor}")]
+    UnsegmentablePayload { compressor: String },
+
+    #[error("both {first} and {second} are remapped to {path}")]
+    RemapCollision {
+        path: String,
+        first: String,
+        second: String,
+    },
+
+    #[error("rpm cannot install packages with dictionary compressed payloads, accept that with BuildSession::accept_uninstallable")]
+    UninstallablePayloads,
+
+    #[error("malformed {what} - {reason}")]
+    Malformed { what: &'static str, reason: String },
+
+    #[error("{path} is a binary for {arch}, but another file for {other}")]
+    ConflictingArch {
+        path: String,
+        arch: String,
+        other: String,
+    },
+}
+
+impl RPMError {
+    /// Stable identifier like `FEZ-E0042`, see [`Message::code`].
+    pub fn code(&self) -> &'static str {
+        self.message().code
+    }
+
+    /// The code and the arguments of the English text.
+    pub fn message(&self) -> Message {
+        // codes follow the declaration order, new variants go last
+        let (code, args) = match self {
+            RPMError::Io(value) => ("FEZ-E0001", vec![("source", value.to_string())]),
+            RPMError::Hex(value) => ("FEZ-E0002", vec![("source", value.to_string())]),
+            #[cfg(feature = "rpmdb-sqlite")]
+            RPMError::Sqlite(value) => ("FEZ-E0003", vec![("source", value.to_string())]),
+            RPMError::Nom(value) => ("FEZ-E0004", vec![("reason", value.to_string())]),
+            RPMError::InvalidMagic {
+                expected, actual, ..
+            } => (
+                "FEZ-E0005",
+                vec![
+                    ("expected", expected.to_string()),
+                    ("actual", actual.to_string()),
+                ],
+            ),
+            RPMError::UnsupportedHeaderVersion(value) => {
+                ("FEZ-E0006", vec![("version", value.to_string())])
+            }
+            RPMError::InvalidTag {
+                raw_tag,
+                store_type,
+            } => (
+                "FEZ-E0007",
+                vec![
+                    ("raw_tag", raw_tag.to_string()),
+                    ("store_type", store_type.to_string()),
+                ],
+            ),
+            RPMError::InvalidTagDataType {
+                raw_data_type,
+                store_type,
+            } => (
+                "FEZ-E0008",
+                vec![
+                    ("raw_data_type", raw_data_type.to_string()),
+                    ("store_type", store_type.to_string()),
+                ],
+            ),
+            RPMError::TagNotFound(value) => ("FEZ-E0009", vec![("tag", value.to_string())]),
+            RPMError::UnexpectedTagDataType {
+                expected_data_type,
+                actual_data_type,
+                tag,
+            } => (
+                "FEZ-E0010",
+                vec![
+                    ("expected_data_type", expected_data_type.to_string()),
+                    ("actual_data_type", actual_data_type.to_string()),
+                    ("tag", tag.to_string()),
+                ],
+            ),
+            RPMError::InvalidTagIndex { tag, index, bound } => (
+                "FEZ-E0011",
+                vec![
+                    ("tag", tag.to_string()),
+                    ("index", index.to_string()),
+                    ("bound", bound.to_string()),
+                ],
+            ),
+            RPMError::InvalidTagValueEnumVariant { tag, variant } => (
+                "FEZ-E0012",
+                vec![("tag", tag.to_string()), ("variant", variant.to_string())],
+            ),
+            RPMError::InvalidLeadMajorVersion(value) => {
+                ("FEZ-E0013", vec![("version", value.to_string())])
+            }
+            RPMError::InvalidLeadMinorVersion(value) => {
+                ("FEZ-E0014", vec![("version", value.to_string())])
+            }
+            RPMError::InvalidLeadPKGType(value) => {
+                ("FEZ-E0015", vec![("package_type", value.to_string())])
+            }
+            RPMError::InvalidLeadName => ("FEZ-E0016", vec![]),
+            RPMError::InvalidLeadOSType(value) => ("FEZ-E0017", vec![("os", value.to_string())]),
+            RPMError::InvalidLeadSignatureType(value) => {
+                ("FEZ-E0018", vec![("signature_type", value.to_string())])
+            }
+            RPMError::InvalidReservedSpaceSize { expected, actual } => (
+                "FEZ-E0019",
+                vec![
+                    ("expected", expected.to_string()),
+                    ("actual", actual.to_string()),
+                ],
+            ),
+            RPMError::InvalidDestinationPath { path, desc } => (
+                "FEZ-E0020",
+                vec![("path", path.to_string()), ("desc", desc.to_string())],
+            ),
+            RPMError::NoSignatureFound => ("FEZ-E0021", vec![]),
+            RPMError::SignError(value) => ("FEZ-E0022", vec![("source", value.to_string())]),
+            RPMError::KeyLoadError { source, details } => (
+                "FEZ-E0023",
+                vec![
+                    ("source", source.to_string()),
+                    ("details", details.to_string()),
+                ],
+            ),
+            RPMError::VerificationError { source, key_ref } => (
+                "FEZ-E0024",
+                vec![
+                    ("source", source.to_string()),
+                    ("key_ref", key_ref.to_string()),
+                ],
+            ),
+            RPMError::KeyNotFoundError { key_ref } => {
+                ("FEZ-E0025", vec![("key_ref", key_ref.to_string())])
+            }
+            RPMError::KeyExpired {
+                key_ref,
+                expired_at,
+            } => (
+                "FEZ-E0026",
+                vec![
+                    ("key_ref", key_ref.to_string()),
+                    ("expired_at", expired_at.to_string()),
+                ],
+            ),
+            RPMError::KeyRevoked { key_ref, reason } => (
+                "FEZ-E0027",
+                vec![
+                    ("key_ref", key_ref.to_string()),
+                    ("reason", reason.to_string()),
+                ],
+            ),
+            RPMError::PolicyViolation(value) => ("FEZ-E0028", vec![("reason", value.to_string())]),
+            RPMError::KeyGenerationError(value) => {
+                ("FEZ-E0029", vec![("source", value.to_string())])
+            }
+            RPMError::InvalidArmor(value) => ("FEZ-E0030", vec![("reason", value.to_string())]),
+            RPMError::UnknownCompressorType(value) => {
+                ("FEZ-E0031", vec![("compressor", value.to_string())])
+            }
+            RPMError::UnsupportedFileDigestAlgorithm(value) => {
+                ("FEZ-E0032", vec![("algorithm", format!("{:?}", value))])
+            }
+            RPMError::InvalidFileMode { raw_mode, reason } => (
+                "FEZ-E0033",
+                vec![
+                    ("raw_mode", raw_mode.to_string()),
+                    ("reason", reason.to_string()),
+                ],
+            ),
+            RPMError::SpecViolation { path, reason } => (
+                "FEZ-E0034",
+                vec![("path", path.to_string()), ("reason", reason.to_string())],
+            ),
+            RPMError::ModePolicyViolation { path, reason } => (
+                "FEZ-E0035",
+                vec![("path", path.to_string()), ("reason", reason.to_string())],
+            ),
+            RPMError::UnsupportedArchiveEntry { path, reason } => (
+                "FEZ-E0036",
+                vec![("path", path.to_string()), ("reason", reason.to_string())],
+            ),
+            RPMError::NotAPublicKeyPackage(value) => {
+                ("FEZ-E0037", vec![("package", value.to_string())])
+            }
+            RPMError::InvalidDatabase(value) => ("FEZ-E0038", vec![("reason", value.to_string())]),
+            RPMError::DependencyCycle { packages } => {
+                ("FEZ-E0039", vec![("packages", format!("{:?}", packages))])
+            }
+            RPMError::InvalidOwner {
+                path,
+                owner,
+                reason,
+            } => (
+                "FEZ-E0040",
+                vec![
+                    ("path", path.to_string()),
+                    ("owner", owner.to_string()),
+                    ("reason", reason.to_string()),
+                ],
+            ),
+            RPMError::UnsupportedElf { path, reason } => (
+                "FEZ-E0041",
+                vec![("path", path.to_string()), ("reason", reason.to_string())],
+            ),
+            RPMError::NotRelocatable(value) => ("FEZ-E0042", vec![("package", value.to_string())]),
+            RPMError::InvalidRegion { tag, reason } => (
+                "FEZ-E0043",
+                vec![("tag", tag.to_string()), ("reason", reason.to_string())],
+            ),
+            RPMError::Cancelled => ("FEZ-E0044", vec![]),
+            RPMError::InvalidUtf8 { tag } => ("FEZ-E0045", vec![("tag", tag.to_string())]),
+            RPMError::FileNotFound(value) => ("FEZ-E0046", vec![("path", value.to_string())]),
+            RPMError::OverBudget { path, size, budget } => (
+                "FEZ-E0047",
+                vec![
+                    ("path", path.to_string()),
+                    ("size", size.to_string()),
+                    ("budget", budget.to_string()),
+                ],
+            ),
+            RPMError::LimitExceeded { limit, actual, max } => (
+                "FEZ-E0048",
+                vec![
+                    ("limit", limit.to_string()),
+                    ("actual", actual.to_string()),
+                    ("max", max.to_string()),
+                ],
+            ),
+            RPMError::ForbiddenPath { path, pattern } => (
+                "FEZ-E0049",
+                vec![("path", path.to_string()), ("pattern", pattern.to_string())],
+            ),
+            RPMError::InvalidChunkManifest(value) => {
+                ("FEZ-E0050", vec![("reason", value.to_string())])
+            }
+            RPMError::InvalidRepodata(value) => ("FEZ-E0051", vec![("reason", value.to_string())]),
+            RPMError::RepodataMismatch { location, reason } => (
+                "FEZ-E0052",
+                vec![
+                    ("location", location.to_string()),
+                    ("reason", reason.to_string()),
+                ],
+            ),
+            RPMError::Remote { url, reason } => (
+                "FEZ-E0053",
+                vec![("url", url.to_string()), ("reason", reason.to_string())],
+            ),
+            RPMError::InvalidDependency { text, reason } => (
+                "FEZ-E0054",
+                vec![("text", text.to_string()), ("reason", reason.to_string())],
+            ),
+            RPMError::NonCanonicalLayout(value) => {
+                ("FEZ-E0055", vec![("issue", value.to_string())])
+            }
+            RPMError::BannedAlgorithm(value) => {
+                ("FEZ-E0056", vec![("algorithm", value.to_string())])
+            }
+            RPMError::NonUtf8FileName(value) => ("FEZ-E0057", vec![("path", value.to_string())]),
+            RPMError::DigestMismatch(value) => ("FEZ-E0058", vec![("reason", value.to_string())]),
+            RPMError::InvalidDelta(value) => ("FEZ-E0059", vec![("reason", value.to_string())]),
+            RPMError::InvalidScriptlet { scriptlet, reason } => (
+                "FEZ-E0060",
+                vec![
+                    ("scriptlet", scriptlet.to_string()),
+                    ("reason", reason.to_string()),
+                ],
+            ),
+            RPMError::InvalidTagValue { tag, reason } => (
+                "FEZ-E0061",
+                vec![("tag", tag.to_string()), ("reason", reason.to_string())],
+            ),
+            RPMError::InvalidManifest { line, reason } => (
+                "FEZ-E0062",
+                vec![("line", line.to_string()), ("reason", reason.to_string())],
+            ),
+            RPMError::InvalidLicense { license, reason } => (
+                "FEZ-E0063",
+                vec![("license", license.clone()), ("reason", reason.clone())],
+            ),
+            RPMError::InvalidFingerprint { fingerprint } => {
+                ("FEZ-E0064", vec![("fingerprint", fingerprint.clone())])
+            }
+            RPMError::InvalidTrustStore { reason } => {
+                ("FEZ-E0065", vec![("reason", reason.clone())])
+            }
+            RPMError::UntrustedKey { origin } => ("FEZ-E0066", vec![("origin", origin.clone())]),
+            RPMError::UnsupportedMachine { path, machine } => (
+                "FEZ-E0067",
+                vec![("path", path.clone()), ("machine", machine.to_string())],
+            ),
+            RPMError::InvalidTimestamp { seconds, reason } => (
+                "FEZ-E0068",
+                vec![
+                    ("seconds", seconds.to_string()),
+                    ("reason", reason.to_string()),
+                ],
+            ),
+            RPMError::InvalidChangelog { line, reason } => (
+                "FEZ-E0069",
+                vec![("line", line.to_string()), ("reason", reason.clone())],
+            ),
+            RPMError::Git { reason } => ("FEZ-E0070", vec![("reason", reason.clone())]),
+            RPMError::InvalidDictionary { reason } => {
+                ("FEZ-E0071", vec![("reason", reason.clone())])
+            }
+            RPMError::Conformance { check, reason } => (
+                "FEZ-E0072",
+                vec![("check", check.to_string()), ("reason", reason.clone())],
+            ),
+            RPMError::UnsegmentablePayload { compressor } => {
+                ("FEZ-E0073", vec![("compressor", compressor.clone())])
+            }
+            RPMError::RemapCollision {
+                path,
+                first,
+                second,
+            } => (
+                "FEZ-E0074",
+                vec![
+                    ("path", path.clone()),
+                    ("first", first.clone()),
+                    ("second", second.clone()),
+                ],
+            ),
+            RPMError::UninstallablePayloads => ("FEZ-E0075", vec![]),
+            RPMError::Malformed { what, reason } => (
+                "FEZ-E0076",
+                vec![("what", what.to_string()), ("reason", reason.clone())],
+            ),
+            RPMError::ConflictingArch { path, arch, other } => (
+                "FEZ-E0077",
+                vec![
+                    ("path", path.clone()),
+                    ("arch", arch.clone()),
+                    ("other", other.clone()),
+                ],
+            ),
+        };
+        Message { code, args }
+    }
+
+    /// The text from `catalog`, or the English one if it has none.
+    pub fn localized(&self, catalog: &dyn MessageCatalog) -> String {
+        catalog
+            .render(&self.message())
+            .unwrap_or_else(|| self.to_string())
+    }
 }
 
 impl From<nom::Err<(&[u8], nom::error::ErrorKind)>> for RPMError {
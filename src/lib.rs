@@ -37,8 +37,8 @@
 //!                         .user("hugo"),
 //!             )?
 //!             .pre_install_script("echo preinst")
-//!             .add_changelog_entry("me", "was awesome, eh?", 123123123)
-//!             .add_changelog_entry("you", "yeah, it was", 12312312)
+//!             .add_changelog_entry("me", "was awesome, eh?", rpm::Timestamp::new(123123123))
+//!             .add_changelog_entry("you", "yeah, it was", rpm::Timestamp::new(12312312))
 //!             .requires(rpm::Dependency::any("wget"))
 //!             .build_and_sign(
 //!                 Signer::load_from_asc_bytes(&raw_secret_key)?
@@ -60,7 +60,7 @@
 mod errors;
 pub use crate::errors::*;
 
-pub(crate) mod constants;
+pub mod constants;
 pub use crate::constants::*;
 
 mod sequential_cursor;
@@ -0,0 +1,80 @@
+//! Users and groups of an installation root, as rpm resolves file owners by name.
+
+use std::path::Path;
+
+/// The entries of `etc/passwd` and `etc/group` below a root.
+///
+/// A root without account database is still being populated, only root can be told then.
+pub(crate) struct Accounts {
+    users: Option<Vec<(String, u32)>>,
+    groups: Option<Vec<(String, u32)>>,
+}
+
+impl Accounts {
+    pub(crate) fn read(root: &Path) -> Self {
+        let read = |file: &str| {
+            let content = std::fs::read_to_string(root.join(file)).ok()?;
+            Some(
+                content
+                    .lines()
+                    .filter_map(|line| {
+                        let mut fields = line.split(':');
+                        let name = fields.next()?;
+                        let id = fields.nth(1)?.parse().ok()?;
+                        Some((name.to_string(), id))
+                    })
+                    .collect(),
+            )
+        };
+        Accounts {
+            users: read("etc/passwd"),
+            groups: read("etc/group"),
+        }
+    }
+
+    /// Name of `uid`, empty if the database does not know it.
+    pub(crate) fn user(&self, uid: u32) -> Option<&str> {
+        name(&self.users, uid)
+    }
+
+    /// Name of `gid`, empty if the database does not know it.
+    pub(crate) fn group(&self, gid: u32) -> Option<&str> {
+        name(&self.groups, gid)
+    }
+
+    /// Id of the user `name`, root if the database does not know it like rpm does.
+    pub(crate) fn uid(&self, name: &str) -> Option<u32> {
+        id(&self.users, name)
+    }
+
+    /// Id of the group `name`, root if the database does not know it like rpm does.
+    pub(crate) fn gid(&self, name: &str) -> Option<u32> {
+        id(&self.groups, name)
+    }
+}
+
+fn name(entries: &Option<Vec<(String, u32)>>, id: u32) -> Option<&str> {
+    match entries {
+        Some(entries) => Some(
+            entries
+                .iter()
+                .find(|(_, entry)| *entry == id)
+                .map_or("", |(name, _)| name.as_str()),
+        ),
+        None if id == 0 => Some("root"),
+        None => None,
+    }
+}
+
+fn id(entries: &Option<Vec<(String, u32)>>, name: &str) -> Option<u32> {
+    match entries {
+        Some(entries) => Some(
+            entries
+                .iter()
+                .find(|(entry, _)| entry == name)
+                .map_or(0, |(_, id)| *id),
+        ),
+        None if name == "root" => Some(0),
+        None => None,
+    }
+}
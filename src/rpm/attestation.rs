@@ -0,0 +1,282 @@
+//! in-toto provenance statements for built packages, signed as DSSE envelopes.
+//!
+//! See <https://github.com/in-toto/attestation> and
+//! <https://github.com/secure-systems-lab/dsse> for the formats.
+
+use std::fmt::Write;
+
+use sha2::Digest;
+
+#[cfg(feature = "signature-meta")]
+use crate::errors::*;
+#[cfg(feature = "signature-meta")]
+use crate::signature;
+
+pub const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v0.1";
+pub const PROVENANCE_PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v0.2";
+/// Payload type of envelopes wrapping a statement.
+pub const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+/// A file going into or coming out of a build, identified by its sha256.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Material {
+    /// Path or URI of an input, the file name of a subject.
+    pub uri: String,
+    /// Lowercase hex.
+    pub sha256: String,
+}
+
+impl Material {
+    pub fn new<U: Into<String>>(uri: U, content: &[u8]) -> Self {
+        Material {
+            uri: uri.into(),
+            sha256: hex::encode(sha2::Sha256::digest(content)),
+        }
+    }
+
+    pub fn from_digest<U: Into<String>, D: Into<String>>(uri: U, sha256: D) -> Self {
+        Material {
+            uri: uri.into(),
+            sha256: sha256.into(),
+        }
+    }
+}
+
+/// SLSA provenance of packages, rendered as an in-toto statement by [`Display`](std::fmt::Display).
+///
+/// [`RPMBuilder::build_and_attest`](crate::RPMBuilder::build_and_attest) adds the
+/// files of the package as materials and the package as subject.
+///
+/// ```rust
+/// let provenance = rpm::Provenance::new("https://ci.example.com/runner", "https://example.com/rpm@v1")
+///     .material(rpm::Material::new("foo.spec", b"Name: foo"))
+///     .subject(rpm::Material::new("foo-1.0.0-1.x86_64.rpm", b"..."));
+/// assert!(provenance.to_string().contains(r#""uri":"foo.spec""#));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    builder_id: String,
+    build_type: String,
+    materials: Vec<Material>,
+    subjects: Vec<Material>,
+}
+
+impl Provenance {
+    /// `builder_id` identifies what ran the build, `build_type` how the inputs were turned
+    /// into packages, both are URIs chosen by the party running builds.
+    pub fn new<B: Into<String>, T: Into<String>>(builder_id: B, build_type: T) -> Self {
+        Provenance {
+            builder_id: builder_id.into(),
+            build_type: build_type.into(),
+            materials: Vec::new(),
+            subjects: Vec::new(),
+        }
+    }
+
+    pub fn material(mut self, material: Material) -> Self {
+        self.materials.push(material);
+        self
+    }
+
+    pub fn subject(mut self, subject: Material) -> Self {
+        self.subjects.push(subject);
+        self
+    }
+
+    /// Sign the statement with `signer`, `key_id` is put into the envelope as a hint
+    /// for verifiers and may be empty.
+    #[cfg(feature = "signature-meta")]
+    pub fn sign<S>(&self, signer: S, key_id: &str) -> Result<Envelope, RPMError>
+    where
+        S: signature::Signing<signature::algorithm::RSA>,
+    {
+        Envelope::sign(
+            IN_TOTO_PAYLOAD_TYPE,
+            self.to_string().into_bytes(),
+            signer,
+            key_id,
+        )
+    }
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn descriptors(materials: &[Material], name: &str) -> String {
+            let mut out = String::new();
+            for (i, material) in materials.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                let _ = write!(
+                    out,
+                    r#"{{"{}":{},"digest":{{"sha256":{}}}}}"#,
+                    name,
+                    json_string(&material.uri),
+                    json_string(&material.sha256)
+                );
+            }
+            out
+        }
+        write!(
+            f,
+            r#"{{"_type":{},"predicateType":{},"subject":[{}],"predicate":{{"builder":{{"id":{}}},"buildType":{},"materials":[{}]}}}}"#,
+            json_string(STATEMENT_TYPE),
+            json_string(PROVENANCE_PREDICATE_TYPE),
+            descriptors(&self.subjects, "name"),
+            json_string(&self.builder_id),
+            json_string(&self.build_type),
+            descriptors(&self.materials, "uri"),
+        )
+    }
+}
+
+/// A DSSE envelope, rendered as JSON by [`Display`](std::fmt::Display).
+#[cfg(feature = "signature-meta")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub payload_type: String,
+    pub payload: Vec<u8>,
+    pub signatures: Vec<EnvelopeSignature>,
+}
+
+#[cfg(feature = "signature-meta")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvelopeSignature {
+    pub key_id: String,
+    /// As returned by the signer, for the pgp signer a binary signature packet.
+    pub signature: Vec<u8>,
+}
+
+#[cfg(feature = "signature-meta")]
+impl Envelope {
+    /// Sign `payload` with `signer` into a new envelope.
+    pub fn sign<S>(
+        payload_type: &str,
+        payload: Vec<u8>,
+        signer: S,
+        key_id: &str,
+    ) -> Result<Self, RPMError>
+    where
+        S: signature::Signing<signature::algorithm::RSA>,
+    {
+        let signature =
+            signer.sign(pre_authentication_encoding(payload_type, &payload).as_slice())?;
+        Ok(Envelope {
+            payload_type: payload_type.to_string(),
+            payload,
+            signatures: vec![EnvelopeSignature {
+                key_id: key_id.to_string(),
+                signature: signature.as_ref().to_vec(),
+            }],
+        })
+    }
+
+    /// Verify that one of the signatures verifies with `verifier`, the error for the last
+    /// signature is returned otherwise.
+    pub fn verify<V>(&self, verifier: V) -> Result<(), RPMError>
+    where
+        V: signature::Verifying<signature::algorithm::RSA>,
+    {
+        let encoded = pre_authentication_encoding(&self.payload_type, &self.payload);
+        let mut result = Err(RPMError::NoSignatureFound);
+        for signature in &self.signatures {
+            result = verifier.verify(encoded.as_slice(), &signature.signature);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(feature = "signature-meta")]
+impl std::fmt::Display for Envelope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            r#"{{"payloadType":{},"payload":{},"signatures":["#,
+            json_string(&self.payload_type),
+            json_string(&base64::encode(&self.payload))
+        )?;
+        for (i, signature) in self.signatures.iter().enumerate() {
+            if i > 0 {
+                f.write_char(',')?;
+            }
+            write!(
+                f,
+                r#"{{"keyid":{},"sig":{}}}"#,
+                json_string(&signature.key_id),
+                json_string(&base64::encode(&signature.signature))
+            )?;
+        }
+        f.write_str("]}")
+    }
+}
+
+/// What DSSE signs instead of the bare payload, so it is bound to its type.
+#[cfg(feature = "signature-meta")]
+fn pre_authentication_encoding(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut encoded = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    encoded.extend_from_slice(payload);
+    encoded
+}
+
+pub(crate) fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_statement() {
+        let provenance = Provenance::new("https://ci.example.com/runner", "rpm")
+            .material(Material::from_digest("dir/\"quoted\"\n", "ab"))
+            .subject(Material::from_digest("foo.rpm", "cd"));
+        assert_eq!(
+            concat!(
+                r#"{"_type":"https://in-toto.io/Statement/v0.1","#,
+                r#""predicateType":"https://slsa.dev/provenance/v0.2","#,
+                r#""subject":[{"name":"foo.rpm","digest":{"sha256":"cd"}}],"#,
+                r#""predicate":{"builder":{"id":"https://ci.example.com/runner"},"#,
+                r#""buildType":"rpm","#,
+                r#""materials":[{"uri":"dir/\"quoted\"\n","digest":{"sha256":"ab"}}]}}"#
+            ),
+            provenance.to_string()
+        );
+        assert_eq!(r#""\u0001""#, json_string("\u{1}"));
+    }
+
+    #[cfg(feature = "signature-meta")]
+    #[test]
+    fn pre_authentication() {
+        // the example of the DSSE protocol
+        assert_eq!(
+            b"DSSEv1 29 http://example.com/HelloWorld 11 hello world".to_vec(),
+            pre_authentication_encoding("http://example.com/HelloWorld", b"hello world")
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Trying compressors on the files of a builder before picking one.
+//!
+//! ```rust
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let builder = rpm::RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo").with_file(
+//!     "./test_assets/awesome.toml",
+//!     rpm::RPMFileOptions::new("/etc/awesome/config.toml"),
+//! )?;
+//! let report = rpm::CompressionBenchmark::new()
+//!     .candidate(rpm::Candidate::Zstd(1))
+//!     .candidate(rpm::Candidate::Zstd(19))
+//!     .run(&builder)?;
+//! for trial in report.trials.iter() {
+//!     println!(
+//!         "{}: {} bytes in {:?}",
+//!         trial.candidate,
+//!         trial.expected_size(report.total_size),
+//!         trial.expected_duration(report.total_size)
+//!     );
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use crate::errors::*;
+
+use super::RPMBuilder;
+
+/// Sample size of [`CompressionBenchmark::new`].
+pub const DEFAULT_SAMPLE_SIZE: usize = 16 * 1024 * 1024;
+
+/// A compressor and level to try.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Candidate {
+    None,
+    /// Gzip at level 9, the only one the builder writes.
+    Gzip,
+    /// Zstd at a level from 1 to 22, see [`RPMBuilder::zstd_level`].
+    Zstd(i32),
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Candidate::None => write!(f, "none"),
+            Candidate::Gzip => write!(f, "gzip -9"),
+            Candidate::Zstd(level) => write!(f, "zstd -{}", level),
+        }
+    }
+}
+
+impl Candidate {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, RPMError> {
+        match self {
+            Candidate::None => Ok(data.to_vec()),
+            Candidate::Gzip => {
+                let mut encoder = libflate::gzip::Encoder::new(Vec::new())?;
+                encoder.write_all(data)?;
+                Ok(encoder.finish().into_result()?)
+            }
+            Candidate::Zstd(level) => Ok(zstd::stream::encode_all(data, *level)?),
+        }
+    }
+}
+
+/// The result of compressing the sample with one [`Candidate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionTrial {
+    pub candidate: Candidate,
+    pub sample_size: u64,
+    pub compressed_size: u64,
+    pub duration: Duration,
+}
+
+impl CompressionTrial {
+    /// Compressed size relative to the sample, below 1 the smaller the better.
+    pub fn ratio(&self) -> f64 {
+        if self.sample_size == 0 {
+            return 1.0;
+        }
+        self.compressed_size as f64 / self.sample_size as f64
+    }
+
+    /// Payload size to expect for `total_size` bytes of files compressing like the sample.
+    pub fn expected_size(&self, total_size: u64) -> u64 {
+        (total_size as f64 * self.ratio()).round() as u64
+    }
+
+    /// Compression time to expect for `total_size` bytes of files.
+    pub fn expected_duration(&self, total_size: u64) -> Duration {
+        if self.sample_size == 0 {
+            return Duration::default();
+        }
+        self.duration
+            .mul_f64(total_size as f64 / self.sample_size as f64)
+    }
+}
+
+/// What [`CompressionBenchmark::run`] measured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionReport {
+    /// Combined size of the files of the builder.
+    pub total_size: u64,
+    /// One trial per candidate, in the order they were given.
+    pub trials: Vec<CompressionTrial>,
+}
+
+impl CompressionReport {
+    /// The fastest trial with an expected payload of at most `max_size` bytes.
+    pub fn fastest_within(&self, max_size: u64) -> Option<&CompressionTrial> {
+        self.trials
+            .iter()
+            .filter(|trial| trial.expected_size(self.total_size) <= max_size)
+            .min_by_key(|trial| trial.duration)
+    }
+
+    /// The trial with the smallest payload.
+    pub fn smallest(&self) -> Option<&CompressionTrial> {
+        self.trials.iter().min_by_key(|trial| trial.compressed_size)
+    }
+}
+
+/// Compress a sample of the files of a builder with several compressors and
+/// levels, see the [module documentation](self).
+///
+/// The sample is the content of the files in payload order, cut off after
+/// [`sample_size`](Self::sample_size) bytes. Numbers for the whole payload are
+/// extrapolated from it, cpio headers are not taken into account.
+#[derive(Debug, Clone)]
+pub struct CompressionBenchmark {
+    candidates: Vec<Candidate>,
+    sample_size: usize,
+}
+
+impl Default for CompressionBenchmark {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CompressionBenchmark {
+    /// Without candidates, [`run`](Self::run) tries gzip and zstd at levels 3, 9 and 19.
+    pub fn new() -> Self {
+        CompressionBenchmark {
+            candidates: Vec::new(),
+            sample_size: DEFAULT_SAMPLE_SIZE,
+        }
+    }
+
+    pub fn candidate(mut self, candidate: Candidate) -> Self {
+        self.candidates.push(candidate);
+        self
+    }
+
+    /// Bytes of file content to compress, [`DEFAULT_SAMPLE_SIZE`] unless set.
+    pub fn sample_size(mut self, sample_size: usize) -> Self {
+        self.sample_size = sample_size;
+        self
+    }
+
+    /// Compress the sample of the files of `builder` with every candidate.
+    pub fn run(&self, builder: &RPMBuilder) -> Result<CompressionReport, RPMError> {
+        let mut sample = Vec::new();
+        let mut total_size = 0u64;
+        for entry in builder.files.values() {
+            if let Some(content) = entry.content.as_ref() {
+                total_size += content.len() as u64;
+                let wanted = self.sample_size.saturating_sub(sample.len());
+                sample.extend_from_slice(&content[..wanted.min(content.len())]);
+            }
+        }
+
+        let default = [
+            Candidate::Gzip,
+            Candidate::Zstd(3),
+            Candidate::Zstd(9),
+            Candidate::Zstd(19),
+        ];
+        let candidates = if self.candidates.is_empty() {
+            &default[..]
+        } else {
+            &self.candidates[..]
+        };
+        let mut trials = Vec::with_capacity(candidates.len());
+        for candidate in candidates {
+            if let Candidate::Zstd(level) = candidate {
+                if !(1..=22).contains(level) {
+                    return Err(RPMError::UnknownCompressorType(candidate.to_string()));
+                }
+            }
+            let start = Instant::now();
+            let compressed = candidate.compress(&sample)?;
+            trials.push(CompressionTrial {
+                candidate: *candidate,
+                sample_size: sample.len() as u64,
+                compressed_size: compressed.len() as u64,
+                duration: start.elapsed(),
+            });
+        }
+        Ok(CompressionReport { total_size, trials })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RPMFileOptions;
+
+    #[test]
+    fn benchmark() -> Result<(), Box<dyn std::error::Error>> {
+        let mut builder = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo");
+        for (index, path) in ["/usr/share/foo/a", "/usr/share/foo/b"].iter().enumerate() {
+            let content = format!("line {}\n", index).repeat(4096).into_bytes();
+            builder.add_data(
+                content,
+                crate::Timestamp::EPOCH,
+                RPMFileOptions::new(*path).into(),
+            )?;
+        }
+        let report = CompressionBenchmark::new()
+            .sample_size(10_000)
+            .candidate(Candidate::None)
+            .candidate(Candidate::Gzip)
+            .candidate(Candidate::Zstd(1))
+            .run(&builder)?;
+        assert_eq!(2 * 7 * 4096, report.total_size);
+        assert_eq!(3, report.trials.len());
+        assert!(report
+            .trials
+            .iter()
+            .all(|trial| trial.sample_size == 10_000));
+        assert_eq!(
+            report.total_size,
+            report.trials[0].expected_size(report.total_size)
+        );
+        assert!(report.trials[2].ratio() < 0.1);
+        assert_ne!(
+            Some(Candidate::None),
+            report.smallest().map(|trial| trial.candidate)
+        );
+        assert!(report.fastest_within(report.total_size).is_some());
+        assert!(report.fastest_within(0).is_none());
+
+        let defaults = CompressionBenchmark::new().run(&builder)?;
+        assert_eq!(
+            vec!["gzip -9", "zstd -3", "zstd -9", "zstd -19"],
+            defaults
+                .trials
+                .iter()
+                .map(|trial| trial.candidate.to_string())
+                .collect::<Vec<_>>()
+        );
+        assert!(matches!(
+            CompressionBenchmark::new()
+                .candidate(Candidate::Zstd(23))
+                .run(&builder),
+            Err(RPMError::UnknownCompressorType(_))
+        ));
+
+        // the builder writes the level of a zstd trial
+        let pkg = builder
+            .clone()
+            .zstd_level(3)
+            .compression(<crate::Compressor as std::str::FromStr>::from_str("zstd")?)
+            .build()?;
+        assert_eq!(
+            "3",
+            pkg.metadata
+                .header
+                .get_entry_string_data(crate::IndexTag::RPMTAG_PAYLOADFLAGS)?
+        );
+        assert!(matches!(
+            builder
+                .compression(<crate::Compressor as std::str::FromStr>::from_str("zstd")?)
+                .zstd_level(23)
+                .build(),
+            Err(RPMError::UnknownCompressorType(_))
+        ));
+        Ok(())
+    }
+}
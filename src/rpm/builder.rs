@@ -1,21 +1,40 @@
 use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
 
 use std::io::{Read, Write};
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
 use std::path::{Path, PathBuf};
-use std::time::UNIX_EPOCH;
+use std::sync::Arc;
 
 use crate::errors::*;
 
-use super::compressor::Compressor;
+use super::attestation::Material;
+#[cfg(feature = "signature-meta")]
+use super::attestation::{Envelope, Provenance};
+use super::checksum::PackageInfo;
+use super::compressor::{CompressionKind, Compressor, PayloadCompression};
+use super::cpio;
+use super::depgen::DepGenerator;
+use super::diagnostics::{Diagnostic, Diagnostics};
+use super::elf;
 use super::headers::*;
+use super::incremental::{Reuse, SegmentWriter};
+use super::license::{self, LicensePolicy};
+use super::limits::{self, Limits};
+use super::payload::CpioFormat;
+use super::policy::ModePolicy;
+use super::progress::{self, CancellableWriter, CancellationToken, Phase, Progress};
+use super::resources::ResourceReport;
+use super::scriptlet::{self, FileTriggerScript, Scriptlet, ScriptletFlags};
+use super::subpackage::SubPackage;
+use super::text;
+use super::timestamp::Timestamp;
+use super::users::UserCreation;
 use super::Lead;
 use crate::constants::*;
 
-#[cfg(feature = "signature-meta")]
-use crate::sequential_cursor::SeqCursor;
 #[cfg(feature = "signature-meta")]
 use crate::signature;
 
@@ -30,9 +49,9 @@ fn file_mode(file: &std::fs::File) -> Result<u32, RPMError> {
     Ok(file.metadata()?.permissions().mode())
 }
 
-#[cfg(windows)]
-fn file_mode(_file: &std::fs::File) -> Result<u32, RPMError> {
-    Ok(0)
+#[cfg(not(unix))]
+fn file_mode(file: &std::fs::File) -> Result<u32, RPMError> {
+    Ok(host_file_mode(&file.metadata()?))
 }
 
 #[cfg(all(unix, feature = "async-tokio"))]
@@ -40,44 +59,321 @@ async fn tokio_file_mode(file: &tokio::fs::File) -> Result<u32, RPMError> {
     Ok(file.metadata().await?.permissions().mode())
 }
 
-#[cfg(all(windows, feature = "async-tokio"))]
-async fn tokio_file_mode(_file: &tokio::fs::File) -> Result<u32, RPMError> {
-    Ok(0)
+#[cfg(all(not(unix), feature = "async-tokio"))]
+async fn tokio_file_mode(file: &tokio::fs::File) -> Result<u32, RPMError> {
+    Ok(host_file_mode(&file.metadata().await?))
+}
+
+/// Hosts without unix modes only know read-only files, those become `0444`, others
+/// `0644`. Use [`RPMFileOptionsBuilder::mode`] or [`RPMBuilder::default_attributes`]
+/// for executables.
+#[cfg(not(unix))]
+fn host_file_mode(metadata: &std::fs::Metadata) -> u32 {
+    let permissions = if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    };
+    FileMode::regular(permissions).into()
+}
+
+/// Longest path the kernel accepts, including the terminating nul byte.
+const PATH_MAX: usize = 4096;
+/// Longest single path component most filesystems accept.
+const NAME_MAX: usize = 255;
+
+/// Room for the signature header, enough for the digests and two 4096 bit signatures.
+const SIGNATURE_SIZE: u64 = 2048;
+/// The payload digest and its algorithm, which [`RPMBuilder::plan`] leaves out.
+const PAYLOAD_DIGEST_SIZE: u64 = 2 * 16 + 65 + 3 + 4;
+/// The tags every header carries and the dependencies added on rpm features used.
+const HEADER_BASE_SIZE: u64 = 4096;
+/// The per file arrays of the header: size, mode, rdev, mtime, digest, flags,
+/// verify flags, device, inode, language and directory index.
+const HEADER_FILE_SIZE: u64 = 4 + 2 + 2 + 4 + 65 + 4 + 4 + 4 + 4 + 1 + 4;
+/// The `newc` entry header, without the path.
+const NEWC_HEADER_SIZE: u64 = 110;
+/// The stripped entry header.
+const STRIPPED_HEADER_SIZE: u64 = 14;
+/// The build-id links added for each ELF file.
+const BUILD_ID_LINK_SIZE: u64 = 2 * (HEADER_FILE_SIZE + NEWC_HEADER_SIZE + 256);
+
+/// Bring a destination into the `/dir/base` form the header stores.
+///
+/// Duplicate slashes and `.` components are dropped, `..` is rejected since
+/// rpm does not resolve it either.
+fn normalize_destination(dest: &str) -> Result<String, RPMError> {
+    let invalid = |desc| {
+        Err(RPMError::InvalidDestinationPath {
+            path: dest.to_string(),
+            desc,
+        })
+    };
+    if !dest.starts_with("./") && !dest.starts_with('/') {
+        return invalid("invalid start, expected / or ./");
+    }
+    let mut normalized = String::with_capacity(dest.len());
+    for component in dest.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => return invalid("path must not contain .. components"),
+            _ if component.len() > NAME_MAX => return invalid("path component exceeds 255 bytes"),
+            _ => {
+                normalized.push('/');
+                normalized.push_str(component);
+            }
+        }
+    }
+    if normalized.is_empty() {
+        return invalid("no file name found");
+    }
+    if normalized.len() >= PATH_MAX {
+        return invalid("path exceeds 4095 bytes");
+    }
+    Ok(normalized)
+}
+
+/// Replace the leading `from` of `path` with `to`, if `from` matches whole components.
+pub(crate) fn remap_path(path: &str, from: &str, to: &str) -> Option<String> {
+    let from = from.trim_end_matches('/');
+    match path.strip_prefix(from) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            Some(format!("{}{}", to.trim_end_matches('/'), rest))
+        }
+        _ => None,
+    }
+}
+
+/// Where an archive entry at `path` is installed, `None` for the root directory.
+fn archive_destination(path: &str, prefix_map: &[(&str, &str)]) -> Option<String> {
+    let relative = path
+        .trim_start_matches("./")
+        .trim_start_matches('/')
+        .trim_end_matches('/');
+    if relative.is_empty() || relative == "." {
+        return None;
+    }
+    let path = format!("/{}", relative);
+    Some(
+        prefix_map
+            .iter()
+            .find_map(|(from, to)| remap_path(&path, from, to))
+            .unwrap_or(path),
+    )
+}
+
+/// Replace every occurrence of the path `from` in `text` which is not part of a longer path.
+fn remap_text(text: &str, from: &str, to: &str) -> String {
+    let from = from.trim_end_matches('/');
+    let to = to.trim_end_matches('/');
+    if from.is_empty() {
+        return text.to_string();
+    }
+    let is_path_char = |c: char| c.is_alphanumeric() || "_-.+".contains(c);
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(idx) = rest.find(from) {
+        let before = rest[..idx].chars().last().or_else(|| result.chars().last());
+        let after = rest[idx + from.len()..].chars().next();
+        result.push_str(&rest[..idx]);
+        let at_start = !before.map_or(false, |c| is_path_char(c) || c == '/');
+        let at_end = !after.map_or(false, is_path_char);
+        if at_start && at_end {
+            result.push_str(to);
+        } else {
+            result.push_str(from);
+        }
+        rest = &rest[idx + from.len()..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Pick the user or group name recorded for `path`, falling back from the name
+/// to the numeric id to the builder default.
+fn owner(
+    path: &str,
+    name: Option<String>,
+    id: Option<u32>,
+    default: &Option<String>,
+) -> Result<String, RPMError> {
+    let owner = name
+        .or_else(|| id.map(|id| id.to_string()))
+        .or_else(|| default.clone())
+        .unwrap_or_else(|| "root".to_string());
+    let invalid = |reason| {
+        Err(RPMError::InvalidOwner {
+            path: path.to_string(),
+            owner: owner.clone(),
+            reason,
+        })
+    };
+    if owner.is_empty() {
+        return invalid("must not be empty");
+    }
+    // the names end up in /etc/passwd style lookups, which can not deal with those
+    if owner
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control() || c == ':')
+    {
+        return invalid("must not contain whitespace, control characters or colons");
+    }
+    Ok(owner)
+}
+
+/// Format of an archive passed to [`RPMBuilder::with_archive`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// Uncompressed tar archive, ustar, GNU and pax flavours are understood.
+    #[cfg(feature = "tar")]
+    Tar,
+    /// Uncompressed cpio archive in the `newc` format, as used for rpm payloads.
+    Cpio,
+}
+
+/// What [`RPMBuilder::with_archive`] does with entry names and symlink targets that
+/// are not valid UTF-8, set with [`RPMBuilder::file_name_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileNamePolicy {
+    /// Fail with [`RPMError::NonUtf8FileName`], the default.
+    #[default]
+    Reject,
+    /// Write each invalid byte as `%` and two hex digits, e.g. `caf%E9` for latin-1 `café`.
+    Escape,
+    /// Replace invalid bytes with U+FFFD, different names may then collide.
+    Lossy,
+}
+
+impl FileNamePolicy {
+    pub(crate) fn apply(self, name: &[u8]) -> Result<String, RPMError> {
+        if let Ok(name) = std::str::from_utf8(name) {
+            return Ok(name.to_string());
+        }
+        match self {
+            FileNamePolicy::Reject => Err(RPMError::NonUtf8FileName(
+                String::from_utf8_lossy(name).into_owned(),
+            )),
+            FileNamePolicy::Lossy => Ok(String::from_utf8_lossy(name).into_owned()),
+            FileNamePolicy::Escape => {
+                let mut escaped = String::with_capacity(name.len() + 8);
+                let mut rest = name;
+                while !rest.is_empty() {
+                    match std::str::from_utf8(rest) {
+                        Ok(valid) => {
+                            escaped.push_str(valid);
+                            break;
+                        }
+                        Err(err) => {
+                            let (valid, invalid) = rest.split_at(err.valid_up_to());
+                            escaped.push_str(std::str::from_utf8(valid).expect("checked"));
+                            let len = err.error_len().unwrap_or(invalid.len());
+                            for byte in &invalid[..len] {
+                                escaped.push_str(&format!("%{:02X}", byte));
+                            }
+                            rest = &invalid[len..];
+                        }
+                    }
+                }
+                Ok(escaped)
+            }
+        }
+    }
+}
+
+/// What a package would contain, returned by [`RPMBuilder::plan`].
+#[derive(Debug)]
+pub struct BuildPlan {
+    /// The header the package would carry, except for the payload digest which
+    /// needs the compressed payload.
+    pub header: Header<IndexTag>,
+    /// The files of the payload, as listed in `header`.
+    pub files: Vec<FileEntry>,
+    /// An estimate of the size of the written package if signed with keys of at
+    /// most 4096 bits, from the exact size of `header` and the worst case of the
+    /// payload like [`RPMBuilder::estimated_size`].
+    pub estimated_size: u64,
+    /// Issues a build would report.
+    pub diagnostics: Diagnostics,
 }
 
 /// Builder pattern for a full rpm file.
 ///
 /// Prefered method of creating a rpm file.
+///
+/// Clones share the content of the files added so far, so a builder set up once can
+/// be cloned and finished with different versions or files, also on other threads.
+///
+/// The same inputs give the same package byte for byte, on every platform and whatever
+/// order files and dependencies were added in: files are written in order of their path,
+/// dependencies sorted by name, version and flags without duplicates, and header tags in
+/// order of their number. Files added from disk carry their modification time, so
+/// checkouts at different times differ there.
+#[derive(Clone)]
 pub struct RPMBuilder {
     name: String,
     epoch: i32,
     version: String,
     license: String,
-    arch: String,
+    pub(crate) arch: String,
     uid: Option<u32>,
     gid: Option<u32>,
     desc: String,
+    summary: Option<String>,
+    group: Option<String>,
     release: String,
 
     // File entries need to be sorted. The entries need to be in the same order as they come
     // in the cpio payload. Otherwise rpm will not be able to resolve those paths.
     // key is the directory, values are complete paths
-    files: BTreeMap<String, RPMFileEntry>,
+    pub(crate) files: BTreeMap<String, RPMFileEntry>,
     directories: BTreeSet<String>,
-    requires: Vec<Dependency>,
+    pub(crate) requires: Vec<Dependency>,
     obsoletes: Vec<Dependency>,
-    provides: Vec<Dependency>,
+    pub(crate) provides: Vec<Dependency>,
     conflicts: Vec<Dependency>,
 
-    pre_inst_script: Option<String>,
-    post_inst_script: Option<String>,
-    pre_uninst_script: Option<String>,
-    post_uninst_script: Option<String>,
+    pub(crate) scripts: BTreeMap<Scriptlet, String>,
+    pub(crate) script_programs: BTreeMap<Scriptlet, Vec<String>>,
+    /// Scriptlets generated shell was appended to.
+    pub(crate) shell_snippets: BTreeSet<Scriptlet>,
+    pub(crate) script_flags: BTreeMap<Scriptlet, ScriptletFlags>,
+    pub(crate) file_triggers: Vec<FileTriggerScript>,
 
     changelog_authors: Vec<String>,
     changelog_entries: Vec<String>,
-    changelog_times: Vec<i32>,
-    compressor: Compressor,
+    changelog_times: Vec<Timestamp>,
+    build_time: Option<Timestamp>,
+    vcs: Option<String>,
+    build_url: Option<String>,
+    pub(crate) compression: PayloadCompression,
+    cpio_format: CpioFormat,
+    pub(crate) strict: bool,
+    pub(crate) fips: bool,
+    prefix_remaps: Vec<(String, String)>,
+    pub(crate) prefixes: Vec<String>,
+    default_mode: Option<u16>,
+    default_user: Option<String>,
+    default_group: Option<String>,
+    pub(crate) mode_policy: Option<ModePolicy>,
+    pub(crate) limits: Limits,
+    pub(crate) user_creation: UserCreation,
+    pub(crate) build_id_links: bool,
+    pub(crate) subpackages: Vec<SubPackage>,
+    pub(crate) check_noarch: bool,
+    pub(crate) normalize_text: bool,
+    pub(crate) markdown_description: bool,
+    pub(crate) license_policy: Option<LicensePolicy>,
+    pub(crate) segment_size: Option<usize>,
+    pub(crate) reuse: Option<Arc<Reuse>>,
+    pub(crate) tag_log: Option<PathBuf>,
+    pub(crate) file_name_policy: FileNamePolicy,
+    pub(crate) shebang_requires: bool,
+    pub(crate) dep_generators: Vec<Arc<dyn DepGenerator>>,
+    progress: Option<Arc<dyn Progress>>,
+    cancellation: Option<CancellationToken>,
+    /// Source files added, for [`build_and_attest`](Self::build_and_attest).
+    materials: Vec<Material>,
 }
 
 impl RPMBuilder {
@@ -89,6 +385,8 @@ impl RPMBuilder {
             license: license.to_string(),
             arch: arch.to_string(),
             desc: desc.to_string(),
+            summary: None,
+            group: None,
             release: "1".to_string(),
             uid: None,
             gid: None,
@@ -96,16 +394,46 @@ impl RPMBuilder {
             provides: Vec::new(),
             obsoletes: Vec::new(),
             requires: Vec::new(),
-            pre_inst_script: None,
-            post_inst_script: None,
-            pre_uninst_script: None,
-            post_uninst_script: None,
+            scripts: BTreeMap::new(),
+            script_programs: BTreeMap::new(),
+            shell_snippets: BTreeSet::new(),
+            script_flags: BTreeMap::new(),
+            file_triggers: Vec::new(),
             files: BTreeMap::new(),
             changelog_authors: Vec::new(),
             changelog_entries: Vec::new(),
             changelog_times: Vec::new(),
-            compressor: Compressor::None(Vec::new()),
+            build_time: None,
+            vcs: None,
+            build_url: None,
+            compression: PayloadCompression::of(&Compressor::None(Vec::new())),
             directories: BTreeSet::new(),
+            cpio_format: CpioFormat::default(),
+            strict: false,
+            fips: false,
+            prefix_remaps: Vec::new(),
+            prefixes: Vec::new(),
+            default_mode: None,
+            default_user: None,
+            default_group: None,
+            mode_policy: None,
+            limits: Limits::default(),
+            user_creation: UserCreation::default(),
+            build_id_links: false,
+            subpackages: Vec::new(),
+            check_noarch: true,
+            normalize_text: false,
+            markdown_description: false,
+            license_policy: None,
+            segment_size: None,
+            reuse: None,
+            tag_log: None,
+            file_name_policy: FileNamePolicy::default(),
+            shebang_requires: false,
+            dep_generators: Vec::new(),
+            progress: None,
+            cancellation: None,
+            materials: Vec::new(),
         }
     }
 
@@ -114,12 +442,125 @@ impl RPMBuilder {
         self
     }
 
+    /// Compress the payload like `comp`.
+    ///
+    /// Only the kind of compressor is taken, the encoder is created when building and
+    /// zstd compresses at the level of [`zstd_level`](Self::zstd_level).
     pub fn compression(mut self, comp: Compressor) -> Self {
-        self.compressor = comp;
+        self.compression = PayloadCompression {
+            level: self.compression.level,
+            ..PayloadCompression::of(&comp)
+        };
+        self
+    }
+
+    /// Compress zstd payloads at `level`, from 1 to 22, instead of 19, e.g. the
+    /// level a [`CompressionBenchmark`](crate::CompressionBenchmark) picked.
+    ///
+    /// Gzip has a single level. Building fails for levels out of range.
+    pub fn zstd_level(mut self, level: i32) -> Self {
+        self.compression.level = level;
+        self
+    }
+
+    /// Report the files written into the payload as [`Phase::Build`].
+    pub fn progress<P: Progress + 'static>(mut self, progress: P) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Stop building with [`RPMError::Cancelled`] once `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Select the cpio flavour of the payload, see [`CpioFormat`].
+    ///
+    /// The stripped format adds a `rpmlib(LargeFiles)` requirement,
+    /// such that rpm versions not able to read it refuse the package.
+    pub fn cpio_format(mut self, format: CpioFormat) -> Self {
+        self.cpio_format = format;
         self
     }
 
-    pub fn add_changelog_entry<E, F>(mut self, author: E, entry: F, time: i32) -> Self
+    /// Refuse to build packages rpm itself would reject or mishandle on install,
+    /// instead of writing them anyway.
+    ///
+    /// The headers written are also audited to be laid out exactly like rpm
+    /// does, see [`Header::audit_layout`].
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Refuse to build packages with digests hosts enforcing FIPS do not accept:
+    /// file and payload digests other than SHA-2, SHA-1 header digests without a
+    /// SHA-256 one next to them, and OpenPGP signatures over SHA-1 or by DSA keys.
+    ///
+    /// Built packages always carry SHA-256 digests, the signer given to
+    /// [`build_and_sign`](Self::build_and_sign) is what is checked in practice. The
+    /// MD5 digest over header and payload written for older rpm versions is ignored
+    /// by such hosts and kept.
+    pub fn fips(mut self, fips: bool) -> Self {
+        self.fips = fips;
+        self
+    }
+
+    /// Refuse to build `noarch` packages containing ELF files, on by default.
+    ///
+    /// Like rpmbuild's "Arch dependent binaries in noarch package" error,
+    /// repositories reject such packages later on anyway. Turn it off to keep
+    /// building them, e.g. for firmware blobs which are ELF files.
+    pub fn check_noarch(mut self, check: bool) -> Self {
+        self.check_noarch = check;
+        self
+    }
+
+    /// How [`with_archive`](Self::with_archive) handles names that are not valid UTF-8,
+    /// set it before adding the archive.
+    pub fn file_name_policy(mut self, policy: FileNamePolicy) -> Self {
+        self.file_name_policy = policy;
+        self
+    }
+
+    /// Move everything below `from` to `to` when building, e.g. `/usr/local` to `/opt/app`.
+    ///
+    /// Applies to file destinations, symlink targets, path dependencies and scriptlet bodies,
+    /// no matter if they were added before or after this call. Only whole path components
+    /// are replaced, `/usr/localized` is left alone by a remap of `/usr/local`.
+    pub fn remap_prefix<F: Into<String>, T: Into<String>>(mut self, from: F, to: T) -> Self {
+        self.prefix_remaps.push((from.into(), to.into()));
+        self
+    }
+
+    /// Make the package relocatable below `prefix`, like `Prefix:` in a spec file.
+    ///
+    /// All files have to be located below one of the prefixes, the build fails otherwise.
+    pub fn prefix<T: Into<String>>(mut self, prefix: T) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Attributes for files added afterwards which do not set their own, like `%defattr`.
+    ///
+    /// `mode` replaces the permissions otherwise taken from the source file,
+    /// `None` keeps the previous default, just like `-` does for `%defattr`.
+    /// Without any defaults files are owned by `root:root`.
+    pub fn default_attributes(
+        mut self,
+        mode: Option<u16>,
+        user: Option<&str>,
+        group: Option<&str>,
+    ) -> Self {
+        self.default_mode = mode.or(self.default_mode);
+        self.default_user = user.map(str::to_string).or(self.default_user);
+        self.default_group = group.map(str::to_string).or(self.default_group);
+        self
+    }
+
+    /// Add a changelog entry written at `time`.
+    pub fn add_changelog_entry<E, F>(mut self, author: E, entry: F, time: Timestamp) -> Self
     where
         E: Into<String>,
         F: Into<String>,
@@ -130,28 +571,44 @@ impl RPMBuilder {
         self
     }
 
+    /// Record when the package was built in `RPMTAG_BUILDTIME`, which is left out
+    /// unless set to keep builds reproducible.
+    pub fn build_time(mut self, time: Timestamp) -> Self {
+        self.build_time = Some(time);
+        self
+    }
+
+    /// Where the sources were checked out from in `RPMTAG_VCS`, like
+    /// `git+https://git.example.com/foo.git#<commit>`.
+    pub fn vcs<T: Into<String>>(mut self, vcs: T) -> Self {
+        self.vcs = Some(vcs.into());
+        self
+    }
+
+    /// Link to the build which produced the package in `RPMTAG_DISTURL`.
+    pub fn build_url<T: Into<String>>(mut self, url: T) -> Self {
+        self.build_url = Some(url.into());
+        self
+    }
+
     #[cfg(feature = "async-tokio")]
     pub async fn with_file_async<T, P>(mut self, source: P, options: T) -> Result<Self, RPMError>
     where
         P: AsRef<Path>,
         T: Into<RPMFileOptions>,
     {
-        let mut input = tokio::fs::File::open(source).await?;
+        let uri = source.as_ref().display().to_string();
+        let mut input = tokio::fs::File::open(&source).await?;
         let mut content = Vec::new();
         input.read_to_end(&mut content).await?;
         let mut options = options.into();
         if options.inherit_permissions {
             options.mode = (tokio_file_mode(&input).await? as i32).into();
         }
+        self.materials.push(Material::new(uri, &content));
         self.add_data(
             content,
-            input
-                .metadata()
-                .await?
-                .modified()?
-                .duration_since(UNIX_EPOCH)
-                .expect("something really wrong with your time")
-                .as_secs() as i32,
+            Timestamp::try_from(input.metadata().await?.modified()?)?,
             options,
         )?;
         Ok(self)
@@ -162,78 +619,279 @@ impl RPMBuilder {
         P: AsRef<Path>,
         T: Into<RPMFileOptions>,
     {
-        let mut input = std::fs::File::open(source)?;
+        let uri = source.as_ref().display().to_string();
+        let mut input = std::fs::File::open(&source)?;
         let mut content = Vec::new();
         input.read_to_end(&mut content)?;
         let mut options = options.into();
         if options.inherit_permissions {
             options.mode = (file_mode(&input)? as i32).into();
         }
+        self.materials.push(Material::new(uri, &content));
         self.add_data(
             content,
-            input
-                .metadata()?
-                .modified()?
-                .duration_since(UNIX_EPOCH)
-                .expect("something really wrong with your time")
-                .as_secs() as i32,
+            Timestamp::try_from(input.metadata()?.modified()?)?,
             options,
         )?;
         Ok(self)
     }
 
-    fn add_data(
+    /// Add a `%ghost` file, which is owned by the package and removed with it
+    /// but neither shipped nor created on install.
+    ///
+    /// Mode, owner and group are recorded like for other files, the file is empty.
+    pub fn with_ghost<T: Into<RPMFileOptions>>(mut self, options: T) -> Result<Self, RPMError> {
+        let mut options = options.into();
+        options.flag |= RPMFILE_GHOST;
+        self.add_data(Vec::new(), Timestamp::EPOCH, options)?;
+        Ok(self)
+    }
+
+    /// Add all entries of an uncompressed archive, including directories and symlinks.
+    ///
+    /// Modes and modification times are kept, for tar archives the user and group names as well.
+    /// `prefix_map` relocates entries: the first `(from, to)` pair where `from` is a leading
+    /// path of the entry replaces that part with `to`. Entries not matching any pair are
+    /// installed relative to `/`.
+    pub fn with_archive<R: Read>(
+        mut self,
+        reader: R,
+        format: ArchiveFormat,
+        prefix_map: &[(&str, &str)],
+    ) -> Result<Self, RPMError> {
+        match format {
+            #[cfg(feature = "tar")]
+            ArchiveFormat::Tar => {
+                let mut archive = tar::Archive::new(reader);
+                for entry in archive.entries()? {
+                    let mut entry = entry?;
+                    let header = entry.header();
+                    let path = self.file_name_policy.apply(&entry.path_bytes())?;
+                    let permissions = (header.mode()? & 0o7777) as u16;
+                    let (mode, symlink) = match header.entry_type() {
+                        tar::EntryType::Regular | tar::EntryType::Continuous => {
+                            (FileMode::regular(permissions), String::new())
+                        }
+                        tar::EntryType::Directory => (FileMode::dir(permissions), String::new()),
+                        tar::EntryType::Symlink => {
+                            let target = entry.link_name_bytes().ok_or_else(|| {
+                                RPMError::UnsupportedArchiveEntry {
+                                    path: path.clone(),
+                                    reason: "symlink without target",
+                                }
+                            })?;
+                            (
+                                FileMode::symlink(permissions),
+                                self.file_name_policy.apply(&target)?,
+                            )
+                        }
+                        tar::EntryType::Link => {
+                            let target = entry.link_name_bytes().ok_or_else(|| {
+                                RPMError::UnsupportedArchiveEntry {
+                                    path: path.clone(),
+                                    reason: "hardlink without target",
+                                }
+                            })?;
+                            let target = self.file_name_policy.apply(&target)?;
+                            self.add_archive_hardlink(&path, &target, prefix_map)?;
+                            continue;
+                        }
+                        // extended headers are applied to the following entry by the tar crate
+                        tar::EntryType::XHeader | tar::EntryType::XGlobalHeader => continue,
+                        _ => {
+                            return Err(RPMError::UnsupportedArchiveEntry {
+                                path,
+                                reason: "only regular files, directories, symlinks and hardlinks are supported",
+                            })
+                        }
+                    };
+                    let user = header
+                        .username()
+                        .ok()
+                        .flatten()
+                        .filter(|name| !name.is_empty())
+                        .unwrap_or("root")
+                        .to_string();
+                    let group = header
+                        .groupname()
+                        .ok()
+                        .flatten()
+                        .filter(|name| !name.is_empty())
+                        .unwrap_or("root")
+                        .to_string();
+                    let mtime =
+                        Timestamp::try_from(i64::try_from(header.mtime()?).unwrap_or(i64::MAX))?;
+                    let mut content = Vec::new();
+                    if symlink.is_empty() {
+                        entry.read_to_end(&mut content)?;
+                    } else {
+                        content = symlink.as_bytes().to_vec();
+                    }
+                    self.add_archive_entry(
+                        &path, prefix_map, content, mtime, mode, symlink, user, group,
+                    )?;
+                }
+            }
+            ArchiveFormat::Cpio => {
+                for mut entry in super::payload::read_entries(reader)? {
+                    entry.path = self.file_name_policy.apply(&entry.raw_path)?;
+                    let symlink = if entry.is_symlink() {
+                        let target = self.file_name_policy.apply(&entry.content)?;
+                        entry.content = target.as_bytes().to_vec();
+                        target
+                    } else {
+                        String::new()
+                    };
+                    if let FileMode::Invalid { .. } = entry.mode {
+                        return Err(RPMError::UnsupportedArchiveEntry {
+                            path: entry.path,
+                            reason: "only regular files, directories and symlinks are supported",
+                        });
+                    }
+                    self.add_archive_entry(
+                        &entry.path,
+                        prefix_map,
+                        entry.content,
+                        entry.mtime,
+                        entry.mode,
+                        symlink,
+                        "root".to_string(),
+                        "root".to_string(),
+                    )?;
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Add `path` as a hardlink to the earlier archive entry `target`, sharing its
+    /// inode, content and attributes.
+    #[cfg(feature = "tar")]
+    fn add_archive_hardlink(
         &mut self,
+        path: &str,
+        target: &str,
+        prefix_map: &[(&str, &str)],
+    ) -> Result<(), RPMError> {
+        let unsupported = |reason| RPMError::UnsupportedArchiveEntry {
+            path: path.to_string(),
+            reason,
+        };
+        let destination = |path| match archive_destination(path, prefix_map) {
+            Some(destination) => normalize_destination(&destination),
+            None => Err(unsupported("hardlink to the root directory")),
+        };
+        let target = destination(target)?;
+        let next_set = self
+            .files
+            .values()
+            .filter_map(|entry| entry.hardlink)
+            .max()
+            .map_or(0, |set| set + 1);
+        let target = self
+            .files
+            .get_mut(&format!(".{}", target))
+            .ok_or_else(|| unsupported("hardlink to a file not earlier in the archive"))?;
+        if !matches!(target.mode, FileMode::Regular { .. }) {
+            return Err(unsupported(
+                "hardlink to something other than a regular file",
+            ));
+        }
+        let set = *target.hardlink.get_or_insert(next_set);
+        let target = target.clone();
+
+        let destination = destination(path)?;
+        let (dir, base_name) = destination.split_at(destination.rfind('/').unwrap() + 1);
+        self.directories.insert(dir.to_string());
+        self.files
+            .entry(format!(".{}", destination))
+            .or_insert(RPMFileEntry {
+                base_name: base_name.to_string(),
+                dir: dir.to_string(),
+                hardlink: Some(set),
+                ..target
+            });
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn add_archive_entry(
+        &mut self,
+        path: &str,
+        prefix_map: &[(&str, &str)],
         content: Vec<u8>,
-        modified_at: i32,
+        modified_at: Timestamp,
+        mode: FileMode,
+        symlink: String,
+        user: String,
+        group: String,
+    ) -> Result<(), RPMError> {
+        let destination = match archive_destination(path, prefix_map) {
+            Some(destination) => destination,
+            // the root directory itself is not part of a package
+            None => return Ok(()),
+        };
+        let options = RPMFileOptions {
+            destination,
+            user: Some(user),
+            group: Some(group),
+            uid: None,
+            gid: None,
+            symlink,
+            mode,
+            flag: 0,
+            inherit_permissions: false,
+        };
+        self.add_data(content, modified_at, options)
+    }
+
+    pub(crate) fn add_data(
+        &mut self,
+        content: Vec<u8>,
+        modified_at: Timestamp,
         options: RPMFileOptions,
     ) -> Result<(), RPMError> {
         use sha2::Digest;
 
-        let dest = options.destination;
-        if !dest.starts_with("./") && !dest.starts_with('/') {
-            return Err(RPMError::InvalidDestinationPath {
-                path: dest,
-                desc: "invalid start, expected / or ./",
-            });
-        }
+        let dest = normalize_destination(&options.destination)?;
+        let (dir, base_name) = dest.split_at(dest.rfind('/').unwrap() + 1);
+        let (cpio_path, dir, base_name) =
+            (format!(".{}", dest), dir.to_string(), base_name.to_string());
 
-        let pb = PathBuf::from(dest.clone());
-
-        let parent = pb
-            .parent()
-            .ok_or_else(|| RPMError::InvalidDestinationPath {
-                path: dest.clone(),
-                desc: "no parent directory found",
-            })?;
-        let (cpio_path, dir) = if dest.starts_with('.') {
-            (
-                dest.to_string(),
-                format!("/{}/", parent.strip_prefix(".").unwrap().to_string_lossy()),
-            )
-        } else {
-            (
-                format!(".{}", dest),
-                format!("{}/", parent.to_string_lossy()),
-            )
+        let mode = match (options.inherit_permissions, self.default_mode) {
+            (true, Some(permissions)) => FileMode::regular(permissions),
+            _ => options.mode,
+        };
+        let user = owner(&dest, options.user, options.uid, &self.default_user)?;
+        let group = owner(&dest, options.group, options.gid, &self.default_group)?;
+
+        // rpm only records digests of regular files it ships
+        let sha_checksum = match mode {
+            _ if options.flag & RPMFILE_GHOST != 0 => String::new(),
+            FileMode::Dir { .. } | FileMode::SymbolicLink { .. } => String::new(),
+            _ => {
+                let mut hasher = sha2::Sha256::default();
+                hasher.update(&content);
+                let hash_result = hasher.finalize();
+                hex::encode(hash_result) // encode as string
+            }
         };
-
-        let mut hasher = sha2::Sha256::default();
-        hasher.update(&content);
-        let hash_result = hasher.finalize();
-        let sha_checksum = hex::encode(hash_result); // encode as string
         let entry = RPMFileEntry {
-            base_name: pb.file_name().unwrap().to_string_lossy().to_string(),
+            base_name,
             size: content.len() as i32,
-            content: Some(content),
+            content: Some(content.into()),
             flag: options.flag,
-            user: options.user,
-            group: options.group,
-            mode: options.mode,
+            user,
+            group,
+            uid: options.uid,
+            gid: options.gid,
+            mode,
+            inherited_mode: options.inherit_permissions && self.default_mode.is_none(),
             link: options.symlink,
             modified_at,
             dir: dir.clone(),
             sha_checksum,
+            hardlink: None,
         };
 
         self.directories.insert(dir);
@@ -242,22 +900,139 @@ impl RPMBuilder {
     }
 
     pub fn pre_install_script<T: Into<String>>(mut self, content: T) -> Self {
-        self.pre_inst_script = Some(content.into());
+        self.scripts.insert(Scriptlet::PreInstall, content.into());
         self
     }
 
     pub fn post_install_script<T: Into<String>>(mut self, content: T) -> Self {
-        self.post_inst_script = Some(content.into());
+        self.scripts.insert(Scriptlet::PostInstall, content.into());
         self
     }
 
     pub fn pre_uninstall_script<T: Into<String>>(mut self, content: T) -> Self {
-        self.pre_uninst_script = Some(content.into());
+        self.scripts.insert(Scriptlet::PreUninstall, content.into());
         self
     }
 
     pub fn post_uninstall_script<T: Into<String>>(mut self, content: T) -> Self {
-        self.post_uninst_script = Some(content.into());
+        self.scripts
+            .insert(Scriptlet::PostUninstall, content.into());
+        self
+    }
+
+    /// A builder for a package accompanying this one, like `-debuginfo`,
+    /// sharing version, license, architecture and the settings of
+    /// [`inherit_settings`](Self::inherit_settings).
+    pub(crate) fn companion(&self, suffix: &str, desc: &str) -> Result<Self, RPMError> {
+        let companion = RPMBuilder::new(
+            &format!("{}-{}", self.name, suffix),
+            &self.version,
+            &self.license,
+            &self.arch,
+            desc,
+        )
+        .epoch(self.epoch)
+        .release(&self.release);
+        Ok(companion.inherit_settings(self))
+    }
+
+    /// Take how `parent` builds packages: payload format, checks and policies, default
+    /// attributes, relocation, generators, progress reporting and provenance. Name,
+    /// version, files, dependencies and scripts are left alone.
+    pub(crate) fn inherit_settings(mut self, parent: &RPMBuilder) -> Self {
+        self.compression = parent.compression.clone();
+        self.cpio_format = parent.cpio_format;
+        self.segment_size = parent.segment_size;
+        self.strict = parent.strict;
+        self.fips = parent.fips;
+        self.check_noarch = parent.check_noarch;
+        self.normalize_text = parent.normalize_text;
+        self.license_policy = parent.license_policy.clone();
+        self.mode_policy = parent.mode_policy.clone();
+        self.file_name_policy = parent.file_name_policy;
+        self.limits = parent.limits.clone();
+        self.uid = parent.uid;
+        self.gid = parent.gid;
+        self.default_mode = parent.default_mode;
+        self.default_user = parent.default_user.clone();
+        self.default_group = parent.default_group.clone();
+        self.user_creation = parent.user_creation;
+        self.prefix_remaps = parent.prefix_remaps.clone();
+        self.prefixes = parent.prefixes.clone();
+        self.build_id_links = parent.build_id_links;
+        self.shebang_requires = parent.shebang_requires;
+        self.dep_generators = parent.dep_generators.clone();
+        self.progress = parent.progress.clone();
+        self.cancellation = parent.cancellation.clone();
+        self.build_time = parent.build_time;
+        self.vcs = parent.vcs.clone();
+        self.build_url = parent.build_url.clone();
+        self
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The `%{name}-%{version}-%{release}.%{arch}` of the package being built.
+    pub(crate) fn nvra(&self) -> String {
+        format!(
+            "{}-{}-{}.{}",
+            self.name, self.version, self.release, self.arch
+        )
+    }
+
+    /// Take all files matching `predicate` out of the package.
+    pub(crate) fn take_files<F>(&mut self, mut predicate: F) -> Vec<(String, RPMFileEntry)>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let (taken, kept) = std::mem::take(&mut self.files)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(cpio_path, _)| predicate(cpio_path.trim_start_matches('.')));
+        self.directories = kept.iter().map(|(_, entry)| entry.dir.clone()).collect();
+        self.files = kept.into_iter().collect();
+        taken
+    }
+
+    /// Add a file taken from another package with [`take_files`](Self::take_files).
+    pub(crate) fn insert_file(&mut self, cpio_path: String, entry: RPMFileEntry) {
+        self.directories.insert(entry.dir.clone());
+        self.files.entry(cpio_path).or_insert(entry);
+    }
+
+    /// The `[epoch:]version-release` other packages use to require exactly this build.
+    pub(crate) fn evr(&self) -> String {
+        if self.epoch > 0 {
+            format!("{}:{}-{}", self.epoch, self.version, self.release)
+        } else {
+            format!("{}-{}", self.version, self.release)
+        }
+    }
+
+    /// Exchange the content of an already added file.
+    pub(crate) fn replace_content(&mut self, cpio_path: &str, content: Vec<u8>) {
+        use sha2::Digest;
+
+        if let Some(entry) = self.files.get_mut(cpio_path) {
+            entry.size = content.len() as i32;
+            entry.sha_checksum = hex::encode(sha2::Sha256::digest(&content));
+            entry.content = Some(content.into());
+        }
+    }
+
+    /// Append `body` to the scriptlet stored as `tag`, keeping what is already there.
+    pub(crate) fn append_script(mut self, tag: IndexTag, body: &str) -> Self {
+        let scriptlet = Scriptlet::from_tag(tag)
+            .unwrap_or_else(|| unreachable!("{:?} is not a scriptlet tag", tag));
+        self.shell_snippets.insert(scriptlet);
+        scriptlet::join(self.scripts.entry(scriptlet).or_default(), body);
+        self
+    }
+
+    /// Replace the version given to [`new`](Self::new), e.g. on a clone of a template.
+    pub fn version<T: ToString>(mut self, version: T) -> Self {
+        self.version = version.to_string();
         self
     }
 
@@ -266,6 +1041,20 @@ impl RPMBuilder {
         self
     }
 
+    /// One line summary, the description passed to [`new`](Self::new) is used
+    /// for both if not set.
+    pub fn summary<T: ToString>(mut self, summary: T) -> Self {
+        self.summary = Some(summary.to_string());
+        self
+    }
+
+    /// The `Group` tag, `Unspecified` unless set. Most distributions ignore it,
+    /// openSUSE expects one of its groups like `System/Libraries`.
+    pub fn group<T: ToString>(mut self, group: T) -> Self {
+        self.group = Some(group.to_string());
+        self
+    }
+
     pub fn requires(mut self, dep: Dependency) -> Self {
         self.requires.push(dep);
         self
@@ -286,26 +1075,203 @@ impl RPMBuilder {
         self
     }
 
+    /// An estimate of the size of the package [`build`](Self::build) writes, to
+    /// reserve space or pre-allocate buffers without building the package first.
+    ///
+    /// The size of the header and the payload is derived from the metadata and the files
+    /// added so far, with a fixed allowance for the tags every header carries.
+    /// [`plan`](Self::plan) estimates the size as well, only the header it derives is
+    /// exact. Compressed payloads are estimated with the worst case expansion of the
+    /// compressor on incompressible data, so the estimate is close for [`Compressor::None`]
+    /// and already compressed files but far too large for compressible files.
+    /// Files moved to subpackages are counted for this package, dependencies returned by
+    /// [`DepGenerator`](crate::DepGenerator)s are not accounted for.
+    pub fn estimated_size(&self) -> u64 {
+        LEAD_SIZE as u64
+            + SIGNATURE_SIZE
+            + self.estimated_header_size()
+            + self.estimated_payload_size()
+    }
+
+    fn estimated_header_size(&self) -> u64 {
+        let string = |value: &str| value.len() as u64 + 1;
+        let dependencies = |dependencies: &[Dependency]| -> u64 {
+            dependencies
+                .iter()
+                .map(|dependency| string(&dependency.dep_name) + string(&dependency.version) + 4)
+                .sum()
+        };
+
+        let mut size = HEADER_BASE_SIZE;
+        size += [
+            &self.name,
+            &self.version,
+            &self.release,
+            &self.license,
+            &self.arch,
+        ]
+        .iter()
+        .map(|value| string(value))
+        .sum::<u64>();
+        // the description is the summary as well unless one is set
+        size += string(&self.desc) + string(self.summary.as_deref().unwrap_or(&self.desc));
+        size += self
+            .prefixes
+            .iter()
+            .map(|prefix| string(prefix))
+            .sum::<u64>();
+
+        size += self.directories.iter().map(|dir| string(dir)).sum::<u64>();
+        for (cpio_path, entry) in &self.files {
+            size += self.estimated_entry_size(cpio_path, entry).0;
+        }
+
+        size += dependencies(&self.requires)
+            + dependencies(&self.provides)
+            + dependencies(&self.obsoletes)
+            + dependencies(&self.conflicts);
+
+        for (scriptlet, script) in &self.scripts {
+            size += string(script) + 4;
+            size += self
+                .script_programs
+                .get(scriptlet)
+                .map(|program| program.iter().map(|arg| string(arg)).sum::<u64>())
+                .unwrap_or_default();
+        }
+        if !self.file_triggers.is_empty() {
+            // up to sixteen more tags with their alignment, and rpmlib(FileTriggers)
+            size += 16 * (16 + 3) + string("rpmlib(FileTriggers)") + string("4.13.0-1") + 4;
+        }
+        for script in &self.file_triggers {
+            size += string(&script.body) + string(scriptlet::DEFAULT_INTERPRETER) + 8;
+            size += script
+                .prefixes
+                .iter()
+                .map(|prefix| string(prefix) + 9)
+                .sum::<u64>();
+        }
+        for (author, entry) in self.changelog_authors.iter().zip(&self.changelog_entries) {
+            size += string(author) + string(entry) + 4;
+        }
+        size
+    }
+
+    fn estimated_payload_size(&self) -> u64 {
+        // the trailer entry, padded to 4 bytes
+        let mut size = NEWC_HEADER_SIZE + "TRAILER!!!".len() as u64 + 1 + 3;
+        for (cpio_path, entry) in &self.files {
+            size += self.estimated_entry_size(cpio_path, entry).1;
+        }
+        self.compressed_bound(size)
+    }
+
+    /// What a file adds to the header and to the uncompressed payload, with its
+    /// build-id links.
+    pub(crate) fn estimated_entry_size(&self, cpio_path: &str, entry: &RPMFileEntry) -> (u64, u64) {
+        let string = |value: &str| value.len() as u64 + 1;
+        // an entry and its content are each padded to 4 bytes
+        let padded = |size: u64| size + 3;
+        let mut header = HEADER_FILE_SIZE
+            + string(&entry.base_name)
+            + string(&entry.link)
+            + string(&entry.user)
+            + string(&entry.group);
+        let cpio_header = match self.cpio_format {
+            CpioFormat::Newc => NEWC_HEADER_SIZE + cpio_path.len() as u64 + 1,
+            CpioFormat::Stripped => STRIPPED_HEADER_SIZE,
+        };
+        let content = entry
+            .content
+            .as_ref()
+            .map(|content| content.len() as u64)
+            .unwrap_or(entry.size as u64);
+        let mut payload = padded(cpio_header) + padded(content);
+        if self.build_id_links {
+            header += BUILD_ID_LINK_SIZE;
+            payload += BUILD_ID_LINK_SIZE;
+        }
+        (header, payload)
+    }
+
+    /// The size of `size` bytes of payload compressed in the worst case.
+    pub(crate) fn compressed_bound(&self, size: u64) -> u64 {
+        match self.compression.kind {
+            CompressionKind::None => size,
+            // deflate falls back to stored blocks of at most 64 KiB with 5 bytes each,
+            // the gzip framing takes 18 bytes
+            CompressionKind::Gzip => size + 5 * (size / 16384 + 1) + 18,
+            // ZSTD_COMPRESSBOUND
+            CompressionKind::Zstd => size + size / 256 + 128,
+        }
+    }
+
+    /// Resolve the files and dependencies and derive the header like
+    /// [`build_with_diagnostics`](Self::build_with_diagnostics), without writing or
+    /// compressing the payload.
+    ///
+    /// Fails where building would fail, except for the payload limits.
+    pub fn plan(&self) -> Result<BuildPlan, RPMError> {
+        let mut diagnostics = Diagnostics::new();
+        let (_, header, _) = self.clone().prepare_data(&mut diagnostics, false)?;
+        let estimated_size = LEAD_SIZE as u64
+            + SIGNATURE_SIZE
+            + header.serialized_len() as u64
+            + PAYLOAD_DIGEST_SIZE
+            + self.estimated_payload_size();
+        let files = match self.files.is_empty() {
+            true => Vec::new(),
+            false => header.get_file_entries()?,
+        };
+        Ok(BuildPlan {
+            files,
+            header,
+            estimated_size,
+            diagnostics,
+        })
+    }
+
     /// build without a signature
     ///
     /// ignores a present key, if any
     pub fn build(self) -> Result<RPMPackage, RPMError> {
-        let (lead, header_idx_tag, content) = self.prepare_data()?;
+        self.build_with_diagnostics(&mut Diagnostics::new())
+    }
 
-        let mut header = Vec::with_capacity(128);
+    /// Build without a signature and write the package to `out`, see [`RPMPackage::write_checksummed`].
+    pub fn build_to<W: Write>(self, out: &mut W) -> Result<PackageInfo, RPMError> {
+        self.build()?.write_checksummed(out)
+    }
+
+    /// Like [`build`](Self::build), collecting issues which do not stop the build in `diagnostics`.
+    ///
+    /// Unless [`strict`](Self::strict), files rpm would reject are reported here.
+    pub fn build_with_diagnostics(
+        self,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<RPMPackage, RPMError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("build", package = %self.nvra()).entered();
+        let strict = self.strict;
+        let fips = self.fips;
+        let tag_log = self.tag_log.clone();
+        let (lead, header_idx_tag, content) = self.prepare_data(diagnostics, true)?;
+
+        let mut header = Vec::with_capacity(header_idx_tag.serialized_len());
         header_idx_tag.write(&mut header)?;
         let header = header;
 
-        let (header_digest_sha1, header_and_content_digest_md5) =
+        let (header_digest, header_and_content_digest_md5) =
             Self::derive_hashes(header.as_slice(), content.as_slice())?;
 
         let header_and_content_len = header.len() + content.len();
 
         let digest_header = Header::<IndexSignatureTag>::builder()
             .add_digest(
-                header_digest_sha1.as_str(),
+                header_digest.sha1.as_str(),
                 header_and_content_digest_md5.as_slice(),
             )
+            .add_sha256_digest(header_digest.sha256.as_str())
             .build(header_and_content_len as i32);
 
         let metadata = RPMPackageMetadata {
@@ -313,59 +1279,169 @@ impl RPMBuilder {
             signature: digest_header,
             header: header_idx_tag,
         };
-        let pkg = RPMPackage { metadata, content };
+        Self::check_layout(strict, &metadata)?;
+        if fips {
+            Self::check_fips(&metadata)?;
+        }
+        Self::write_tag_log(tag_log, &metadata)?;
+        // the signature header is not serialized until the package is written
+        diagnostics.record_resources(ResourceReport {
+            header_store: header.capacity() as u64,
+            ..ResourceReport::default()
+        });
+        let pkg = RPMPackage::new(metadata, content);
         Ok(pkg)
     }
 
     /// use an external signer to sing and build
     ///
-    /// See `signature::Signing` for more details.
+    /// See `signature::Signing` for more details. Both steps, building and signing,
+    /// are available on their own through [`build_unsigned`](Self::build_unsigned).
     #[cfg(feature = "signature-meta")]
     pub fn build_and_sign<S>(self, signer: S) -> Result<RPMPackage, RPMError>
     where
         S: signature::Signing<crate::signature::algorithm::RSA>,
     {
-        let (lead, header_idx_tag, content) = self.prepare_data()?;
-
-        let mut header = Vec::with_capacity(128);
-        header_idx_tag.write(&mut header)?;
-        let header = header;
-
-        let (header_digest_sha1, header_and_content_digest_md5) =
-            Self::derive_hashes(header.as_slice(), content.as_slice())?;
-
-        let header_and_content_len = header.len() + content.len();
-
-        let builder = Header::<IndexSignatureTag>::builder().add_digest(
-            header_digest_sha1.as_str(),
-            header_and_content_digest_md5.as_slice(),
-        );
-
-        let signature_header = {
-            let rsa_sig_header_only = signer.sign(header.as_slice())?;
+        self.build_unsigned()?.finalize(signer)
+    }
 
-            let cursor = SeqCursor::new(&[header.as_slice(), content.as_slice()]);
-            let rsa_sig_header_and_archive = signer.sign(cursor)?;
+    /// Like [`build_and_sign`](Self::build_and_sign), writing the package to `out` and
+    /// signing `provenance` of it with the same signer within one pass.
+    ///
+    /// The files added from disk become materials of the provenance, the package its subject.
+    /// `key_id` is passed to [`Provenance::sign`].
+    #[cfg(feature = "signature-meta")]
+    pub fn build_and_attest<W, S>(
+        self,
+        out: &mut W,
+        provenance: Provenance,
+        signer: S,
+        key_id: &str,
+    ) -> Result<(PackageInfo, Envelope), RPMError>
+    where
+        W: Write,
+        S: signature::Signing<crate::signature::algorithm::RSA>,
+    {
+        let file_name = format!("{}.rpm", self.nvra());
+        let provenance = self
+            .materials
+            .iter()
+            .cloned()
+            .fold(provenance, Provenance::material);
+        let info = self.build_and_sign(&signer)?.write_checksummed(out)?;
+        let sha256 = info.sha256.clone().unwrap_or_default();
+        let envelope = provenance
+            .subject(Material::from_digest(file_name, sha256))
+            .sign(&signer, key_id)?;
+        Ok((info, envelope))
+    }
 
-            builder
-                .add_signature(
-                    rsa_sig_header_only.as_ref(),
-                    rsa_sig_header_and_archive.as_ref(),
-                )
-                .build(header_and_content_len as i32)
-        };
+    /// In strict mode, refuse headers not laid out like rpm writes them.
+    pub(crate) fn check_layout(
+        strict: bool,
+        metadata: &RPMPackageMetadata,
+    ) -> Result<(), RPMError> {
+        if !strict {
+            return Ok(());
+        }
+        match metadata.audit_layout().into_iter().next() {
+            Some(issue) => Err(RPMError::NonCanonicalLayout(issue)),
+            None => Ok(()),
+        }
+    }
 
-        let metadata = RPMPackageMetadata {
-            lead,
-            signature: signature_header,
-            header: header_idx_tag,
+    /// Fail on digests and signatures banned by [`fips`](Self::fips).
+    pub(crate) fn check_fips(metadata: &RPMPackageMetadata) -> Result<(), RPMError> {
+        let sha2 = |algorithm: FileDigestAlgorithm| {
+            matches!(
+                algorithm,
+                FileDigestAlgorithm::Sha2_224
+                    | FileDigestAlgorithm::Sha2_256
+                    | FileDigestAlgorithm::Sha2_384
+                    | FileDigestAlgorithm::Sha2_512
+            )
         };
-        let pkg = RPMPackage { metadata, content };
-        Ok(pkg)
+        let header = &metadata.header;
+        if header
+            .find_entry_or_err(&IndexTag::RPMTAG_BASENAMES)
+            .is_ok()
+        {
+            // a missing tag means md5
+            let algorithm = header.get_file_digest_algorithm().unwrap_or_default();
+            if !sha2(algorithm) {
+                return Err(RPMError::BannedAlgorithm(format!(
+                    "{:?} file digests",
+                    algorithm
+                )));
+            }
+        }
+        if header
+            .find_entry_or_err(&IndexTag::RPMTAG_PAYLOADDIGEST)
+            .is_err()
+        {
+            return Err(RPMError::BannedAlgorithm(
+                "payloads without a digest".to_string(),
+            ));
+        }
+        // a missing tag means sha256, like rpm assumes
+        let algorithm = header
+            .get_entry_i32_data(IndexTag::RPMTAG_PAYLOADDIGESTALGO)
+            .map_or(
+                Some(FileDigestAlgorithm::Sha2_256),
+                num::FromPrimitive::from_i32,
+            )
+            .unwrap_or_default();
+        if !sha2(algorithm) {
+            return Err(RPMError::BannedAlgorithm(format!(
+                "{:?} payload digests",
+                algorithm
+            )));
+        }
+        let signature = &metadata.signature;
+        if signature
+            .find_entry_or_err(&IndexSignatureTag::RPMSIGTAG_SHA256)
+            .is_err()
+        {
+            return Err(RPMError::BannedAlgorithm(
+                "header digests without SHA-256".to_string(),
+            ));
+        }
+        #[cfg(feature = "signature-pgp")]
+        for tag in [
+            IndexSignatureTag::RPMSIGTAG_RSA,
+            IndexSignatureTag::RPMSIGTAG_PGP,
+        ] {
+            use crate::signature::{DigestAlgorithm, KeyAlgorithm};
+            let bytes = match signature.get_entry_binary_data(tag) {
+                Ok(bytes) => bytes,
+                Err(_) => continue,
+            };
+            match signature::pgp::signature_algorithms(bytes)? {
+                (DigestAlgorithm::Md5 | DigestAlgorithm::Sha1 | DigestAlgorithm::Other, _) => {
+                    return Err(RPMError::BannedAlgorithm(format!(
+                        "{} hashing with a digest other than SHA-2",
+                        tag
+                    )))
+                }
+                (_, key @ (KeyAlgorithm::Dsa | KeyAlgorithm::Other)) => {
+                    return Err(RPMError::BannedAlgorithm(format!(
+                        "{} by a {:?} key",
+                        tag, key
+                    )))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
     }
 
     /// use prepared data but make sure the signatures are
-    fn derive_hashes(header: &[u8], content: &[u8]) -> Result<(String, Vec<u8>), RPMError> {
+    pub(crate) fn derive_hashes(
+        header: &[u8],
+        content: &[u8],
+    ) -> Result<(HeaderDigest, Vec<u8>), RPMError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("digest", bytes = header.len() + content.len()).entered();
         let digest_md5 = {
             use md5::Digest;
 
@@ -378,28 +1454,235 @@ impl RPMBuilder {
         };
 
         // header only, not the lead, just the header index
-        let digest_sha1 = {
-            use sha1::Digest;
+        let digest_header = HeaderDigest::of(header);
 
-            let mut hasher = sha1::Sha1::default();
-            hasher.update(&header);
-            let digest_sha1 = hasher.finalize();
-            hex::encode(digest_sha1)
+        Ok((digest_header, digest_md5))
+    }
+
+    /// Move files, symlink targets, path dependencies and scriptlet bodies as
+    /// [`remap_prefix`](Self::remap_prefix) describes.
+    ///
+    /// Fails if two files end up at the same destination.
+    fn apply_prefix_remaps(&mut self) -> Result<(), RPMError> {
+        if self.prefix_remaps.is_empty() {
+            return Ok(());
+        }
+        let remaps = std::mem::take(&mut self.prefix_remaps);
+        let remap = |path: &str| {
+            remaps
+                .iter()
+                .find_map(|(from, to)| remap_path(path, from, to))
         };
 
-        Ok((digest_sha1, digest_md5))
+        let files = std::mem::take(&mut self.files);
+        self.directories.clear();
+        let mut sources = std::collections::HashMap::new();
+        for (source, mut entry) in files {
+            let path = format!("{}{}", entry.dir, entry.base_name);
+            if let Some(path) = remap(&path) {
+                let dest = normalize_destination(&path)?;
+                let (dir, base_name) = dest.split_at(dest.rfind('/').unwrap() + 1);
+                entry.dir = dir.to_string();
+                entry.base_name = base_name.to_string();
+            }
+            if let Some(link) = remap(&entry.link) {
+                // the payload of a symlink is its target
+                if let FileMode::SymbolicLink { .. } = entry.mode {
+                    entry.content = Some(link.as_bytes().into());
+                    entry.size = link.len() as i32;
+                }
+                entry.link = link;
+            }
+            self.directories.insert(entry.dir.clone());
+            let cpio_path = format!(".{}{}", entry.dir, entry.base_name);
+            let source = source.trim_start_matches('.').to_string();
+            if let Some(first) = sources.insert(cpio_path.clone(), source.clone()) {
+                return Err(RPMError::RemapCollision {
+                    path: cpio_path.trim_start_matches('.').to_string(),
+                    first,
+                    second: source,
+                });
+            }
+            self.files.insert(cpio_path, entry);
+        }
+
+        for dep in self
+            .requires
+            .iter_mut()
+            .chain(self.provides.iter_mut())
+            .chain(self.conflicts.iter_mut())
+            .chain(self.obsoletes.iter_mut())
+        {
+            if let Some(name) = remap(&dep.dep_name) {
+                dep.dep_name = name;
+            }
+        }
+
+        for script in self.scripts.values_mut() {
+            for (from, to) in remaps.iter() {
+                *script = remap_text(script, from, to);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_prefixes(&mut self) -> Result<(), RPMError> {
+        if self.prefixes.is_empty() {
+            return Ok(());
+        }
+        for prefix in self.prefixes.iter_mut() {
+            let normalized = normalize_destination(prefix)?;
+            *prefix = normalized;
+        }
+        for entry in self.files.values() {
+            let path = format!("{}{}", entry.dir, entry.base_name);
+            if !self
+                .prefixes
+                .iter()
+                .any(|prefix| remap_path(&path, prefix, prefix).is_some())
+            {
+                return Err(RPMError::InvalidDestinationPath {
+                    path,
+                    desc: "not located below any prefix of the relocatable package",
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn check_arch_independent(cpio_path: &str, entry: &RPMFileEntry) -> Result<(), RPMError> {
+        let is_binary = match (&entry.mode, &entry.content) {
+            (FileMode::Regular { .. }, Some(content)) => elf::is_elf(content),
+            _ => false,
+        };
+        if is_binary {
+            return Err(RPMError::SpecViolation {
+                path: cpio_path.trim_start_matches('.').to_string(),
+                reason: "arch dependent binary in noarch package",
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks for combinations rpm refuses, or silently installs differently than described.
+    fn check_strict(cpio_path: &str, entry: &RPMFileEntry) -> Result<(), RPMError> {
+        let violation = |reason| {
+            Err(RPMError::SpecViolation {
+                path: cpio_path.trim_start_matches('.').to_string(),
+                reason,
+            })
+        };
+        let has_content = entry.content.as_ref().map_or(false, |c| !c.is_empty());
+        match entry.mode {
+            FileMode::Invalid { .. } => violation("unknown file type"),
+            FileMode::SymbolicLink { .. } if entry.link.is_empty() => {
+                violation("symlink without target")
+            }
+            FileMode::Regular { .. } | FileMode::Dir { .. } if !entry.link.is_empty() => {
+                violation("link target set on a file which is not a symlink")
+            }
+            FileMode::Dir { .. } if has_content => violation("directory with content"),
+            _ if entry.size < 0 => violation("file too large for the 32 bit size field"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Require `rpmlib(<feature>)` unless it already is.
+    pub(crate) fn require_rpmlib(&mut self, feature: &str, version: &str) {
+        let dependency = Dependency::rpmlib(feature, version);
+        if !self
+            .requires
+            .iter()
+            .any(|dep| dep.dep_name == dependency.dep_name)
+        {
+            self.requires.push(dependency);
+        }
+    }
+
+    /// The features of rpm the package relies on, as rpmbuild requires them.
+    ///
+    /// Older versions of rpm refuse the package up front instead of failing
+    /// in the middle of a transaction.
+    fn add_rpmlib_requires(&mut self) {
+        // paths are always split into dirnames and basenames
+        self.require_rpmlib("CompressedFileNames", "3.0.4-1");
+        match self.cpio_format {
+            CpioFormat::Newc => self.require_rpmlib("PayloadFilesHavePrefix", "4.0-1"),
+            CpioFormat::Stripped => self.require_rpmlib("LargeFiles", "4.12.0-1"),
+        }
+        // file digests are sha256, rpm before 4.6 only knows md5
+        if !self.files.is_empty() {
+            self.require_rpmlib("FileDigests", "4.6.0-1");
+        }
+        if self.compression.kind == CompressionKind::Zstd {
+            self.require_rpmlib("PayloadIsZstd", "5.4.18-1");
+        }
+        let rich = self
+            .requires
+            .iter()
+            .chain(self.conflicts.iter())
+            .chain(self.obsoletes.iter())
+            .chain(self.provides.iter())
+            .any(Dependency::is_rich);
+        if rich {
+            self.require_rpmlib("RichDependencies", "4.12.0-1");
+        }
     }
 
     /// prepapre all rpm headers including content
     ///
     /// @todo split this into multiple `fn`s, one per `IndexTag`-group.
-    fn prepare_data(mut self) -> Result<(Lead, Header<IndexTag>, Vec<u8>), RPMError> {
+    pub(crate) fn prepare_data(
+        mut self,
+        diagnostics: &mut Diagnostics,
+        write_payload: bool,
+    ) -> Result<(Lead, Header<IndexTag>, Vec<u8>), RPMError> {
         // signature depends on header and payload. So we build these two first.
         // then the signature. Then we stitch all toghether.
         // Lead is not important. just build it here
 
+        limits::check_package_tags(
+            &self.name,
+            &self.version,
+            &self.release,
+            &self.arch,
+            &self.desc,
+        )?;
+        if let Some(policy) = &self.license_policy {
+            license::enforce(&self.license, policy)?;
+        }
+        let summary = self.summary.take().unwrap_or_else(|| self.desc.clone());
+        let group = self
+            .group
+            .take()
+            .unwrap_or_else(|| "Unspecified".to_string());
+        let changelog_times = self
+            .changelog_times
+            .iter()
+            .map(|time| time.to_tag())
+            .collect::<Vec<_>>();
+        if self.markdown_description {
+            self.desc = text::strip_markdown(&self.desc);
+        }
+        if self.normalize_text {
+            text::check_summary(&summary)?;
+            self.desc = text::normalize_description(&self.desc);
+        }
         let lead = Lead::new(&self.name);
 
+        {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!("generate").entered();
+            self.apply_prefix_remaps()?;
+            self.check_prefixes()?;
+            self.apply_build_id_links()?;
+            self.apply_dep_generators()?;
+            self.check_scripts()?;
+            self.apply_mode_policy()?;
+            self.limits
+                .check_files(self.files.keys().map(|path| path.trim_start_matches('.')))?;
+        }
+
         let mut ino_index = 1;
 
         let mut file_sizes = Vec::new();
@@ -417,48 +1700,154 @@ impl RPMBuilder {
         let mut file_verify_flags = Vec::new();
         let mut dir_indixes = Vec::new();
         let mut base_names = Vec::new();
+        // DIRNAMES holds every directory once, files refer to it by index
+        let dir_indexes = self
+            .directories
+            .iter()
+            .enumerate()
+            .map(|(index, dir)| (dir.clone(), index as i32))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut combined_file_sizes = 0u64;
+        let mut compressor = self.compression.compressor()?;
+        let segments = match self.compression.details() {
+            _ if self.compression.kind == CompressionKind::Zstd => {
+                self.segment_writer(self.compression.level)
+            }
+            details if self.segmented() => {
+                return Err(RPMError::UnsegmentablePayload {
+                    compressor: details
+                        .map_or("none", |details| details.compression_name)
+                        .to_string(),
+                })
+            }
+            _ => None,
+        };
+        let cancellation = self.cancellation.clone();
+        let cancelled = |err| progress::cancelled_or(cancellation.as_ref(), err);
+        let mut archive = cpio::Writer::new(CancellableWriter::new(
+            match segments {
+                Some(segments) => PayloadWriter::Segments(segments),
+                None => PayloadWriter::Stream(&mut compressor),
+            },
+            self.cancellation.clone(),
+        ));
+        let total_size = self
+            .files
+            .values()
+            .map(|entry| entry.size.max(0) as u64)
+            .sum::<u64>();
+
+        // like rpm, the members of a hardlink set share the inode of the first one and
+        // only the last one carries the content
+        let mut hardlinks: BTreeMap<u32, (i32, &str, u32)> = BTreeMap::new();
+        for (index, (cpio_path, entry)) in self.files.iter().enumerate() {
+            if let Some(set) = entry.hardlink {
+                let (_, last, nlink) =
+                    hardlinks
+                        .entry(set)
+                        .or_insert((index as i32 + 1, cpio_path, 0));
+                *last = cpio_path;
+                *nlink += 1;
+            }
+        }
 
-        let mut combined_file_sizes = 0;
-
+        #[cfg(feature = "tracing")]
+        let payload_span = tracing::debug_span!("payload", files = self.files.len()).entered();
         for (cpio_path, entry) in self.files.iter() {
-            combined_file_sizes += entry.size;
+            progress::check(self.cancellation.as_ref())?;
+            match Self::check_strict(cpio_path, entry) {
+                Err(err) if self.strict => return Err(err),
+                Err(RPMError::SpecViolation { path, reason }) => {
+                    diagnostics.push(Diagnostic::SpecViolation { path, reason })
+                }
+                _ => {}
+            }
+            if self.check_noarch && self.arch == "noarch" {
+                Self::check_arch_independent(cpio_path, entry)?;
+            }
+            let (ino, has_content, nlink) = match entry.hardlink.map(|set| hardlinks[&set]) {
+                Some((ino, last, nlink)) => (ino, last == cpio_path.as_str(), nlink),
+                None => (ino_index, true, 1),
+            };
+            if has_content {
+                combined_file_sizes += entry.size.max(0) as u64;
+            }
             file_sizes.push(entry.size);
             file_modes.push(entry.mode.into());
             // I really do not know the difference. It seems like file_rdevice is always 0 and file_device number always 1.
             // Who knows, who cares.
             file_rdevs.push(0);
             file_devices.push(1);
-            file_mtimes.push(entry.modified_at);
+            file_mtimes.push(entry.modified_at.to_tag());
             file_hashes.push(entry.sha_checksum.to_owned());
             file_linktos.push(entry.link.to_owned());
             file_flags.push(entry.flag);
             file_usernames.push(entry.user.to_owned());
             file_groupnames.push(entry.group.to_owned());
-            file_inodes.push(ino_index as i32);
+            file_inodes.push(ino);
             file_langs.push("".to_string());
-            let index = self
-                .directories
-                .iter()
-                .position(|d| d == &entry.dir)
-                .unwrap();
-            dir_indixes.push(index as i32);
+            dir_indixes.push(dir_indexes[entry.dir.as_str()]);
             base_names.push(entry.base_name.to_owned());
             file_verify_flags.push(-1);
-            let content = entry.content.to_owned().unwrap();
-            let mut writer = cpio::newc::Builder::new(cpio_path)
-                .mode(entry.mode.into())
-                .ino(ino_index as u32)
-                .uid(self.uid.unwrap_or(0))
-                .gid(self.gid.unwrap_or(0))
-                .write(&mut self.compressor, content.len() as u32);
-
-            writer.write_all(&content)?;
-            writer.finish()?;
+            let content = match has_content {
+                true => entry.content.clone().unwrap(),
+                false => Arc::from(Vec::new()),
+            };
+            match self.cpio_format {
+                _ if !write_payload => {}
+                // ghosts are only in the header
+                _ if entry.flag & RPMFILE_GHOST != 0 => {}
+                CpioFormat::Newc => {
+                    let header = cpio::Entry {
+                        ino: ino as u32,
+                        nlink,
+                        mode: entry.mode.into(),
+                        uid: entry.uid.or(self.uid).unwrap_or(0),
+                        gid: entry.gid.or(self.gid).unwrap_or(0),
+                        mtime: entry.modified_at.seconds(),
+                        ..cpio::Entry::new(cpio_path.as_str())
+                    };
+                    archive.append(&header, &content).map_err(cancelled)?;
+                }
+                CpioFormat::Stripped => {
+                    // the file index is the position in the header arrays
+                    archive
+                        .append_stripped(ino_index as u32 - 1, &content)
+                        .map_err(cancelled)?;
+                }
+            }
+            if let PayloadWriter::Segments(segments) = archive.get_mut().get_mut() {
+                segments.file_written(cpio_path)?;
+            }
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                path = cpio_path.trim_start_matches('.'),
+                size = entry.size,
+                "file"
+            );
+            progress::report(
+                self.progress.as_deref(),
+                Phase::Build,
+                combined_file_sizes,
+                Some(total_size),
+                Some(cpio_path.trim_start_matches('.')),
+            );
 
             ino_index += 1;
         }
+        let segmented = match write_payload {
+            true => match archive.finish().map_err(cancelled)?.into_inner() {
+                PayloadWriter::Segments(segments) => Some(segments.finish()?),
+                PayloadWriter::Stream(_) => None,
+            },
+            false => None,
+        };
+        #[cfg(feature = "tracing")]
+        drop(payload_span);
 
         self.requires.push(Dependency::any("/bin/sh".to_string()));
+        self.add_rpmlib_requires();
 
         self.provides
             .push(Dependency::eq(self.name.clone(), self.version.clone()));
@@ -467,6 +1856,19 @@ impl RPMBuilder {
             self.version.clone(),
         ));
 
+        // like rpmbuild, sorted and without duplicates whatever order they were added in
+        for dependencies in [
+            &mut self.provides,
+            &mut self.obsoletes,
+            &mut self.requires,
+            &mut self.conflicts,
+        ] {
+            dependencies.sort_by(|a, b| {
+                (&a.dep_name, &a.version, a.sense).cmp(&(&b.dep_name, &b.version, b.sense))
+            });
+            dependencies.dedup();
+        }
+
         let mut provide_names = Vec::new();
         let mut provide_flags = Vec::new();
         let mut provide_versions = Vec::new();
@@ -540,18 +1942,14 @@ impl RPMBuilder {
                 IndexEntry::new(
                     IndexTag::RPMTAG_DESCRIPTION,
                     offset,
-                    IndexData::StringTag(self.desc.clone()),
-                ),
-                IndexEntry::new(
-                    IndexTag::RPMTAG_SUMMARY,
-                    offset,
                     IndexData::StringTag(self.desc),
                 ),
                 IndexEntry::new(
-                    IndexTag::RPMTAG_SIZE,
+                    IndexTag::RPMTAG_SUMMARY,
                     offset,
-                    IndexData::Int32(vec![combined_file_sizes]),
+                    IndexData::StringTag(summary),
                 ),
+                installed_size_entry(combined_file_sizes, offset),
                 IndexEntry::new(
                     IndexTag::RPMTAG_LICENSE,
                     offset,
@@ -567,7 +1965,7 @@ impl RPMBuilder {
                 IndexEntry::new(
                     IndexTag::RPMTAG_GROUP,
                     offset,
-                    IndexData::I18NString(vec!["Unspecified".to_string()]),
+                    IndexData::I18NString(vec![group.clone()]),
                 ),
                 IndexEntry::new(
                     IndexTag::RPMTAG_ARCH,
@@ -579,6 +1977,11 @@ impl RPMBuilder {
                     offset,
                     IndexData::StringTag("cpio".to_string()),
                 ),
+                IndexEntry::new(
+                    IndexTag::RPMTAG_PROVIDENAME,
+                    offset,
+                    IndexData::StringArray(provide_names),
+                ),
                 IndexEntry::new(
                     IndexTag::RPMTAG_PROVIDEVERSION,
                     offset,
@@ -620,18 +2023,14 @@ impl RPMBuilder {
                 IndexEntry::new(
                     IndexTag::RPMTAG_DESCRIPTION,
                     offset,
-                    IndexData::StringTag(self.desc.clone()),
-                ),
-                IndexEntry::new(
-                    IndexTag::RPMTAG_SUMMARY,
-                    offset,
                     IndexData::StringTag(self.desc),
                 ),
                 IndexEntry::new(
-                    IndexTag::RPMTAG_SIZE,
+                    IndexTag::RPMTAG_SUMMARY,
                     offset,
-                    IndexData::Int32(vec![combined_file_sizes]),
+                    IndexData::StringTag(summary),
                 ),
+                installed_size_entry(combined_file_sizes, offset),
                 IndexEntry::new(
                     IndexTag::RPMTAG_LICENSE,
                     offset,
@@ -647,7 +2046,7 @@ impl RPMBuilder {
                 IndexEntry::new(
                     IndexTag::RPMTAG_GROUP,
                     offset,
-                    IndexData::I18NString(vec!["Unspecified".to_string()]),
+                    IndexData::I18NString(vec![group.clone()]),
                 ),
                 IndexEntry::new(
                     IndexTag::RPMTAG_ARCH,
@@ -762,7 +2161,7 @@ impl RPMBuilder {
             ]
         };
 
-        let possible_compression_details = self.compressor.get_details();
+        let possible_compression_details = self.compression.details();
 
         if let Some(details) = possible_compression_details {
             actual_records.push(IndexEntry::new(
@@ -791,10 +2190,27 @@ impl RPMBuilder {
             actual_records.push(IndexEntry::new(
                 IndexTag::RPMTAG_CHANGELOGTIME,
                 offset,
-                IndexData::Int32(self.changelog_times),
+                IndexData::Int32(changelog_times),
+            ));
+        }
+
+        if let Some(build_time) = self.build_time {
+            actual_records.push(IndexEntry::new(
+                IndexTag::RPMTAG_BUILDTIME,
+                offset,
+                IndexData::Int32(vec![build_time.to_tag()]),
             ));
         }
 
+        for (tag, value) in [
+            (IndexTag::RPMTAG_VCS, self.vcs.take()),
+            (IndexTag::RPMTAG_DISTURL, self.build_url.take()),
+        ] {
+            if let Some(value) = value {
+                actual_records.push(IndexEntry::new(tag, offset, IndexData::StringTag(value)));
+            }
+        }
+
         if !obsolete_flags.is_empty() {
             actual_records.push(IndexEntry::new(
                 IndexTag::RPMTAG_OBSOLETENAME,
@@ -849,69 +2265,110 @@ impl RPMBuilder {
             ));
         }
 
-        if self.pre_inst_script.is_some() {
+        if !self.prefixes.is_empty() {
             actual_records.push(IndexEntry::new(
-                IndexTag::RPMTAG_PREIN,
+                IndexTag::RPMTAG_PREFIXES,
                 offset,
-                IndexData::StringTag(self.pre_inst_script.unwrap()),
-            ));
-        }
-        if self.post_inst_script.is_some() {
-            actual_records.push(IndexEntry::new(
-                IndexTag::RPMTAG_POSTIN,
-                offset,
-                IndexData::StringTag(self.post_inst_script.unwrap()),
+                IndexData::StringArray(self.prefixes),
             ));
         }
 
-        if self.pre_uninst_script.is_some() {
-            actual_records.push(IndexEntry::new(
-                IndexTag::RPMTAG_PREUN,
-                offset,
-                IndexData::StringTag(self.pre_uninst_script.unwrap()),
-            ));
+        for (tag, data) in
+            scriptlet::entries(self.scripts, self.script_programs, &self.script_flags)
+        {
+            actual_records.push(IndexEntry::new(tag, offset, data));
         }
-
-        if self.post_uninst_script.is_some() {
-            actual_records.push(IndexEntry::new(
-                IndexTag::RPMTAG_POSTUN,
-                offset,
-                IndexData::StringTag(self.post_uninst_script.unwrap()),
-            ));
+        for (tag, data) in scriptlet::file_trigger_entries(self.file_triggers) {
+            actual_records.push(IndexEntry::new(tag, offset, data));
         }
 
-        let header = Header::from_entries(actual_records, IndexTag::RPMTAG_HEADERIMMUTABLE);
-
-        //those parts seem to break on fedora installations, but it does not seem to matter for centos.
-        // if it turns out that those parts are not really required, we will delete the following comments
+        let payload = match write_payload {
+            true => {
+                #[cfg(feature = "tracing")]
+                let _span = tracing::debug_span!("compress").entered();
+                let (content, payload_archive) = match segmented {
+                    Some((content, segment_buffer)) => (content, segment_buffer),
+                    None => (compressor.finish_compression()?, 0),
+                };
+                self.limits.check_payload(&content)?;
+                // binds the payload to the header, and so to signatures over the header only
+                let digest = {
+                    use sha2::Digest;
+                    hex::encode(sha2::Sha256::digest(&content))
+                };
+                actual_records.push(IndexEntry::new(
+                    IndexTag::RPMTAG_PAYLOADDIGEST,
+                    offset,
+                    IndexData::StringArray(vec![digest]),
+                ));
+                actual_records.push(IndexEntry::new(
+                    IndexTag::RPMTAG_PAYLOADDIGESTALGO,
+                    offset,
+                    IndexData::Int32(vec![8]),
+                ));
+                Some((content, payload_archive))
+            }
+            false => None,
+        };
 
-        // self.requires.push(Dependency::rpm_lib(
-        //     "rpmlib(VersionedDependencies)".to_string(),
-        //     "3.0.3-1".to_string(),
-        // ));
+        let header = Header::from_entries(actual_records, IndexTag::RPMTAG_HEADERIMMUTABLE);
+        limits::check_header(&header)?;
+        let (content, payload_archive) = match payload {
+            Some(payload) => payload,
+            None => return Ok((lead, header, Vec::new())),
+        };
+        diagnostics.record_resources(ResourceReport {
+            staged_content: self
+                .files
+                .values()
+                .filter_map(|entry| entry.content.as_ref())
+                .map(|content| content.len() as u64)
+                .sum(),
+            payload_archive: payload_archive as u64,
+            payload: content.capacity() as u64,
+            ..ResourceReport::default()
+        });
 
-        // self.requires.push(Dependency::rpm_lib(
-        //     "rpmlib(PayloadFilesHavePrefix)".to_string(),
-        //     "4.0-1".to_string(),
-        // ));
+        Ok((lead, header, content))
+    }
+}
 
-        // self.requires.push(Dependency::rpm_lib(
-        //     "rpmlib(CompressedFileNames)".to_string(),
-        //     "3.0.4-1".to_string(),
-        // ));
+/// Where the cpio archive of the payload goes.
+enum PayloadWriter<'a> {
+    Stream(&'a mut Compressor),
+    Segments(SegmentWriter),
+}
 
-        // self.requires.push(Dependency::rpm_lib(
-        //     "rpmlib(PayloadIsXz)".to_string(),
-        //     "5.2-1".to_string(),
-        // ));
-        // self.requires.push(Dependency::rpm_lib(
-        //     "rpmlib(FileDigests)".to_string(),
-        //     "4.6.0-1".to_string(),
-        // ));
+impl Write for PayloadWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            PayloadWriter::Stream(compressor) => compressor.write(buf),
+            PayloadWriter::Segments(segments) => segments.write(buf),
+        }
+    }
 
-        self.compressor = cpio::newc::trailer(self.compressor)?;
-        let content = self.compressor.finish_compression()?;
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            PayloadWriter::Stream(compressor) => compressor.flush(),
+            PayloadWriter::Segments(segments) => segments.flush(),
+        }
+    }
+}
 
-        Ok((lead, header, content))
+/// `RPMTAG_SIZE`, or `RPMTAG_LONGSIZE` for packages installing 4GiB and more, like rpmbuild.
+fn installed_size_entry(size: u64, offset: i32) -> IndexEntry<IndexTag> {
+    if size <= u32::MAX as u64 {
+        // rpm reads the tag as unsigned
+        IndexEntry::new(
+            IndexTag::RPMTAG_SIZE,
+            offset,
+            IndexData::Int32(vec![size as u32 as i32]),
+        )
+    } else {
+        IndexEntry::new(
+            IndexTag::RPMTAG_LONGSIZE,
+            offset,
+            IndexData::Int64(vec![size as i64]),
+        )
     }
 }
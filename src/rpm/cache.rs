@@ -0,0 +1,523 @@
+//! An on-disk cache of parsed package metadata, so repeated [`scan`](super::scan)s of a
+//! large repository only parse the packages that changed. [`VerificationCache`] does
+//! the same for signature checks.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let cache = rpm::cache::HeaderCache::open("/var/cache/fez")?;
+//! let options = rpm::scan::ScanOptions::new().cache(cache);
+//! for scanned in rpm::scan::dir("/srv/repo", options)? {
+//!     // ...
+//! #   drop(scanned);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::Digest;
+
+use crate::errors::*;
+
+#[cfg(feature = "signature-pgp")]
+use super::RPMPackage;
+use super::RPMPackageMetadata;
+#[cfg(feature = "signature-pgp")]
+use crate::signature;
+
+/// Identifies the content of a package file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    /// Lowercase hex of the whole file.
+    pub sha256: String,
+    pub size: u64,
+}
+
+impl CacheKey {
+    pub fn of_file<P: AsRef<Path>>(path: P) -> Result<Self, RPMError> {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = sha2::Sha256::new();
+        let mut buf = vec![0; 64 * 1024];
+        let mut size = 0u64;
+        loop {
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+            size += read as u64;
+        }
+        Ok(CacheKey {
+            sha256: hex::encode(hasher.finalize()),
+            size,
+        })
+    }
+
+    fn file_name(&self) -> String {
+        format!("{}-{}", self.sha256, self.size)
+    }
+
+    fn parse_file_name(name: &str) -> Option<Self> {
+        let (sha256, size) = name.trim_end().split_once('-')?;
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(CacheKey {
+            sha256: sha256.to_string(),
+            size: size.parse().ok()?,
+        })
+    }
+}
+
+/// Path, size, modification time and inode of a file, which change whenever a
+/// package is replaced in a repository.
+fn file_stamp(path: &Path) -> Result<String, RPMError> {
+    let metadata = std::fs::metadata(path)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    #[cfg(unix)]
+    let inode = {
+        use std::os::unix::fs::MetadataExt;
+        format!("{}:{}", metadata.dev(), metadata.ino())
+    };
+    #[cfg(not(unix))]
+    let inode = "";
+    let path = std::fs::canonicalize(path)?;
+    let stamp = format!(
+        "{}\0{}\0{}.{:09}\0{}",
+        path.to_string_lossy(),
+        metadata.len(),
+        mtime.as_secs(),
+        mtime.subsec_nanos(),
+        inode
+    );
+    Ok(format!(
+        "stamp-{}",
+        hex::encode(sha2::Sha256::digest(stamp.as_bytes()))
+    ))
+}
+
+/// A directory holding the lead and headers of packages, one file per [`CacheKey`].
+///
+/// Entries are never invalidated, as a changed package has a different key. Entries
+/// that can not be read or parsed count as missing. The keys of files looked up with
+/// [`key_of`](Self::key_of) are kept alongside.
+#[derive(Debug, Clone)]
+pub struct HeaderCache {
+    dir: PathBuf,
+}
+
+impl HeaderCache {
+    /// Use `dir` as cache, creating it if needed.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, RPMError> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(HeaderCache {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    /// The directory holding the entries.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The key of the file at `path`.
+    ///
+    /// The file is only hashed if its path, size, modification time or inode changed
+    /// since the last call, otherwise the key recorded then is returned. A file
+    /// rewritten in place within the resolution of the modification time keeps its
+    /// old key.
+    pub fn key_of<P: AsRef<Path>>(&self, path: P) -> Result<CacheKey, RPMError> {
+        let stamp = file_stamp(path.as_ref())?;
+        let recorded = std::fs::read_to_string(self.dir.join(&stamp)).ok();
+        if let Some(key) = recorded.as_deref().and_then(CacheKey::parse_file_name) {
+            return Ok(key);
+        }
+        let key = CacheKey::of_file(path)?;
+        // a failure only costs hashing the file again
+        let _ = self.write_entry(&stamp, |out| Ok(out.write_all(key.file_name().as_bytes())?));
+        Ok(key)
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<RPMPackageMetadata> {
+        let file = std::fs::File::open(self.dir.join(key.file_name())).ok()?;
+        RPMPackageMetadata::parse(&mut std::io::BufReader::new(file)).ok()
+    }
+
+    /// Store `metadata` under `key`, replacing an existing entry.
+    pub fn insert(&self, key: &CacheKey, metadata: &RPMPackageMetadata) -> Result<(), RPMError> {
+        self.write_entry(&key.file_name(), |out| metadata.write(out))
+    }
+
+    fn write_entry<F>(&self, name: &str, write: F) -> Result<(), RPMError>
+    where
+        F: FnOnce(&mut std::io::BufWriter<std::fs::File>) -> Result<(), RPMError>,
+    {
+        // written aside and renamed, so concurrent scans never see a partial entry
+        let partial = self.dir.join(format!(
+            ".{}.{}.{:?}",
+            name,
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        let mut out = std::io::BufWriter::new(std::fs::File::create(&partial)?);
+        let written = write(&mut out).and_then(|_| out.flush().map_err(RPMError::from));
+        drop(out);
+        match written.and_then(|_| Ok(std::fs::rename(&partial, self.dir.join(name))?)) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let _ = std::fs::remove_file(&partial);
+                Err(err)
+            }
+        }
+    }
+
+    /// Remove all entries.
+    pub fn clear(&self) -> Result<(), RPMError> {
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Name of the file holding the policy of a [`VerificationCache`].
+const POLICY_FILE: &str = "policy";
+
+/// A directory recording which packages verified with which keys, so packages that did
+/// not change are not verified again with keys that are still used.
+///
+/// Entries are recorded for one policy, a label for the [`VerifyPolicy`](crate::signature::VerifyPolicy)
+/// and whatever else decides if a signature is accepted. Opening the cache with another
+/// label drops all entries, as does [`invalidate`](Self::invalidate).
+#[derive(Debug, Clone)]
+pub struct VerificationCache {
+    dir: PathBuf,
+}
+
+impl VerificationCache {
+    /// Use `dir` as cache for `policy`, creating it if needed.
+    pub fn open<P: AsRef<Path>>(dir: P, policy: &str) -> Result<Self, RPMError> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        let cache = VerificationCache {
+            dir: dir.as_ref().to_path_buf(),
+        };
+        match std::fs::read_to_string(cache.dir.join(POLICY_FILE)) {
+            Ok(current) if current == policy => {}
+            _ => cache.invalidate(policy)?,
+        }
+        Ok(cache)
+    }
+
+    /// The directory holding the entries and the policy.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether the package with `key` verified with the key with `fingerprint`.
+    pub fn contains(&self, key: &CacheKey, fingerprint: &str) -> bool {
+        match self.entry(key, fingerprint) {
+            Some(entry) => entry.is_file(),
+            None => false,
+        }
+    }
+
+    /// Record that the package with `key` verified with the key with `fingerprint`.
+    ///
+    /// The state of the key is not recorded, [`verify_file`](Self::verify_file) ignores
+    /// such entries.
+    pub fn insert(&self, key: &CacheKey, fingerprint: &str) -> Result<(), RPMError> {
+        let entry = self
+            .entry(key, fingerprint)
+            .ok_or_else(|| RPMError::InvalidFingerprint {
+                fingerprint: fingerprint.to_string(),
+            })?;
+        std::fs::write(entry, b"")?;
+        Ok(())
+    }
+
+    /// Drop all entries and record entries for `policy` from now on, for when the
+    /// policy changed while the cache is open.
+    pub fn invalidate(&self, policy: &str) -> Result<(), RPMError> {
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        std::fs::write(self.dir.join(POLICY_FILE), policy)?;
+        Ok(())
+    }
+
+    /// Verify the signature of the package at `path` with the keys of `keyring`, unless
+    /// it verified with one of them before, returning the fingerprint of that key.
+    ///
+    /// Entries only count for a key in the state it verified the package in: once it
+    /// is revoked, its expiry changed or its [`KeyValidity`](signature::pgp::KeyValidity)
+    /// differs, the package is verified again, as it is when a key checked
+    /// [`AtVerificationTime`](signature::pgp::KeyValidity::AtVerificationTime) expired.
+    ///
+    /// The file is read and hashed on every call, the cache saves parsing it and checking
+    /// the signatures. The error of the last key is returned if none of them matches.
+    #[cfg(feature = "signature-pgp")]
+    pub fn verify_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        keyring: &[signature::pgp::Verifier],
+    ) -> Result<String, RPMError> {
+        self.verify_file_with(path, keyring, &crate::VerifyOptions::new())
+    }
+
+    /// Like [`verify_file`](Self::verify_file), verifying as configured in `options`.
+    ///
+    /// Entries do not record the options, the policy passed to [`open`](Self::open)
+    /// has to change with them.
+    #[cfg(feature = "signature-pgp")]
+    pub fn verify_file_with<P: AsRef<Path>>(
+        &self,
+        path: P,
+        keyring: &[signature::pgp::Verifier],
+        options: &crate::VerifyOptions<'_>,
+    ) -> Result<String, RPMError> {
+        // hashed and parsed from the same bytes, the file may change meanwhile
+        let bytes = std::fs::read(path)?;
+        let key = CacheKey {
+            sha256: hex::encode(sha2::Sha256::digest(&bytes)),
+            size: bytes.len() as u64,
+        };
+        let states = keyring
+            .iter()
+            .map(|verifier| {
+                let until = verifier.valid_until().map(|until| until.timestamp());
+                Ok((verifier.fingerprint(), verifier.key_state()?, until))
+            })
+            .collect::<Result<Vec<_>, RPMError>>()?;
+        let now = i64::from(crate::Timestamp::now()?.seconds());
+        if let Some((fingerprint, _, _)) = states
+            .iter()
+            .find(|(fingerprint, state, _)| self.verified_in(&key, fingerprint, state, now))
+        {
+            return Ok(fingerprint.clone());
+        }
+
+        let package = RPMPackage::parse(&mut bytes.as_slice())?;
+        let mut result = Err(RPMError::KeyNotFoundError {
+            key_ref: "empty keyring".to_string(),
+        });
+        let mut verified = None;
+        for (verifier, state) in keyring.iter().zip(&states) {
+            result = package
+                .verify_signature_with(verifier, options)
+                .map(|_| state.0.clone());
+            if result.is_ok() {
+                verified = Some(state);
+                break;
+            }
+        }
+        let fingerprint = result?;
+        if let Some((_, state, until)) = verified {
+            self.record(&key, &fingerprint, state, *until)?;
+        }
+        Ok(fingerprint)
+    }
+
+    /// Whether the package with `key` verified with the key in `state`, which was
+    /// still valid at `now`.
+    #[cfg(feature = "signature-pgp")]
+    fn verified_in(&self, key: &CacheKey, fingerprint: &str, state: &str, now: i64) -> bool {
+        let recorded = match self.entry(key, fingerprint) {
+            Some(entry) => std::fs::read_to_string(entry).unwrap_or_default(),
+            None => return false,
+        };
+        let mut fields = recorded.split_whitespace();
+        fields.next() == Some(state)
+            && fields.next().map_or(true, |until| {
+                until.parse::<i64>().map_or(false, |until| now < until)
+            })
+    }
+
+    /// Like [`insert`](Self::insert), recording the state of the key and until when it
+    /// is valid for [`verified_in`](Self::verified_in).
+    #[cfg(feature = "signature-pgp")]
+    fn record(
+        &self,
+        key: &CacheKey,
+        fingerprint: &str,
+        state: &str,
+        until: Option<i64>,
+    ) -> Result<(), RPMError> {
+        let entry = self
+            .entry(key, fingerprint)
+            .ok_or_else(|| RPMError::InvalidFingerprint {
+                fingerprint: fingerprint.to_string(),
+            })?;
+        let recorded = match until {
+            Some(until) => format!("{} {}\n", state, until),
+            None => format!("{}\n", state),
+        };
+        std::fs::write(entry, recorded)?;
+        Ok(())
+    }
+
+    /// The entry of `key` and `fingerprint`, `None` for malformed fingerprints.
+    fn entry(&self, key: &CacheKey, fingerprint: &str) -> Option<PathBuf> {
+        if fingerprint.is_empty() || !fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(self.dir.join(format!(
+            "{}-{}",
+            key.file_name(),
+            fingerprint.to_uppercase()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cache_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = crate::tests::TestDir::new("cache");
+        let cache = HeaderCache::open(&dir)?;
+
+        let path = "./test_assets/389-ds-base-devel-1.3.8.4-15.el7.x86_64.rpm";
+        let key = CacheKey::of_file(path)?;
+        assert_eq!(std::fs::metadata(path)?.len(), key.size);
+        assert_eq!(64, key.sha256.len());
+        assert!(cache.get(&key).is_none());
+
+        let metadata =
+            RPMPackageMetadata::parse(&mut std::io::BufReader::new(std::fs::File::open(path)?))?;
+        cache.insert(&key, &metadata)?;
+        let cached = cache.get(&key).expect("cached");
+        assert_eq!(metadata.header.get_name()?, cached.header.get_name()?);
+        assert_eq!(1, std::fs::read_dir(&dir)?.count());
+
+        std::fs::write(dir.join(key.file_name()), b"garbage")?;
+        assert!(cache.get(&key).is_none());
+        cache.clear()?;
+        assert_eq!(0, std::fs::read_dir(&dir)?.count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn keys_of_unchanged_files() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = crate::tests::TestDir::new("cache-keys");
+        let cache = HeaderCache::open(dir.join("cache"))?;
+        let path = dir.join("foo.rpm");
+        std::fs::write(&path, b"foo")?;
+
+        let key = cache.key_of(&path)?;
+        assert_eq!(CacheKey::of_file(&path)?, key);
+        // answered from the recorded key, as shown by swapping it
+        let stamp = cache.dir().join(file_stamp(&path)?);
+        let swapped = CacheKey {
+            sha256: "0".repeat(64),
+            size: 3,
+        };
+        std::fs::write(&stamp, swapped.file_name())?;
+        assert_eq!(swapped, cache.key_of(&path)?);
+
+        // a different size is a different stamp
+        std::fs::write(&path, b"foobar")?;
+        let key = CacheKey::of_file(&path)?;
+        assert_eq!(key, cache.key_of(&path)?);
+        std::fs::write(cache.dir().join(file_stamp(&path)?), b"garbage")?;
+        assert_eq!(key, cache.key_of(&path)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn verification_cache() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = crate::tests::TestDir::new("verified");
+        let cache = VerificationCache::open(&dir, "sha256-only")?;
+        let key = CacheKey {
+            sha256: "00".repeat(32),
+            size: 1,
+        };
+        let fingerprint = "3e2ed16b6e2a2ee1a7ff3f2d9ab46c1e06e05e92";
+        assert!(!cache.contains(&key, fingerprint));
+        cache.insert(&key, fingerprint)?;
+        assert!(cache.contains(&key, &fingerprint.to_uppercase()));
+        assert!(!cache.contains(&key, "115DF9AEF857853EE8445D0A0727707EA15B79CC"));
+        assert!(!cache.contains(&key, "../policy"));
+        assert!(cache.insert(&key, "../policy").is_err());
+
+        let cache = VerificationCache::open(&dir, "sha256-only")?;
+        assert!(cache.contains(&key, fingerprint));
+        let cache = VerificationCache::open(&dir, "strict")?;
+        assert!(!cache.contains(&key, fingerprint));
+        cache.insert(&key, fingerprint)?;
+        cache.invalidate("stricter")?;
+        assert!(!cache.contains(&key, fingerprint));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "signature-pgp")]
+    #[test]
+    fn verify_file_cached() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = crate::tests::TestDir::new("verify-file");
+        let cache = VerificationCache::open(dir.join("cache"), "default")?;
+        let (signing_key, verification_key) = crate::signature::pgp::test::load_asc_keys();
+        let signer = signature::pgp::Signer::load_from_asc_bytes(&signing_key)?;
+        let verifier = signature::pgp::Verifier::load_from_asc_bytes(&verification_key)?;
+        let path = dir.join("foo.rpm");
+        let pkg = crate::RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "foo")
+            .build_and_sign(&signer)?;
+        pkg.write(&mut std::fs::File::create(&path)?)?;
+
+        let keyring = [verifier.clone()];
+        assert_eq!(verifier.fingerprint(), cache.verify_file(&path, &keyring)?);
+        let key = CacheKey::of_file(&path)?;
+        assert!(cache.contains(&key, &verifier.fingerprint()));
+        assert_eq!(verifier.fingerprint(), cache.verify_file(&path, &keyring)?);
+
+        // entries are for the key in the state it verified the package in
+        let now = i64::from(crate::Timestamp::now()?.seconds());
+        let state = verifier.key_state()?;
+        assert!(cache.verified_in(&key, &verifier.fingerprint(), &state, now));
+        assert!(!cache.verified_in(&key, &verifier.fingerprint(), "00", now));
+        cache.record(&key, &verifier.fingerprint(), &state, Some(now))?;
+        assert!(!cache.verified_in(&key, &verifier.fingerprint(), &state, now));
+        cache.insert(&key, &verifier.fingerprint())?;
+        assert!(!cache.verified_in(&key, &verifier.fingerprint(), &state, now));
+
+        // a revoked key verifies when validity is ignored, which does not carry over
+        let revoked = signature::pgp::Signer::load_from_asc(include_str!(
+            "../../test_assets/revoked_secret_key.asc"
+        ))?;
+        let revoked_verifier = || {
+            signature::pgp::Verifier::load_from_asc(include_str!(
+                "../../test_assets/revoked_public_key.asc"
+            ))
+        };
+        crate::RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "foo")
+            .build_and_sign(&revoked)?
+            .write(&mut std::fs::File::create(&path)?)?;
+        let ignoring = [revoked_verifier()?.key_validity(signature::pgp::KeyValidity::Ignore)];
+        cache.verify_file(&path, &ignoring)?;
+        assert!(matches!(
+            cache.verify_file(&path, &[revoked_verifier()?]),
+            Err(RPMError::KeyRevoked { .. })
+        ));
+
+        // an unsigned package with the same name is a different file
+        let unsigned = crate::RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "foo").build()?;
+        unsigned.write(&mut std::fs::File::create(&path)?)?;
+        assert!(cache.verify_file(&path, &keyring).is_err());
+        assert!(cache.verify_file(&path, &[]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+//! Reading changelogs written for humans into entries for [`RPMBuilder::changelog`].
+//!
+//! Two formats are understood, the `%changelog` section of spec files
+//!
+//! ```text
+//! * Wed May 17 2023 Jane Doe <jane@example.com> - 1.2.0-1
+//! - Support zstd
+//! ```
+//!
+//! and markdown release notes like those following [Keep a Changelog](https://keepachangelog.com):
+//!
+//! ```text
+//! ## [1.2.0] - 2023-05-17
+//! ### Added
+//! - Support zstd
+//! ```
+//!
+//! Both list the newest release first, as rpm expects. Dates carry no time, entries
+//! are dated at noon UTC so the day is the same in every time zone.
+//!
+//! ```rust
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let entries = rpm::changelog::parse_markdown(
+//!     "# Changelog\n\n## [Unreleased]\n\n## [1.2.0] - 2023-05-17\n### Added\n- Support zstd\n",
+//!     "Jane Doe <jane@example.com>",
+//! )?;
+//! assert_eq!("Jane Doe <jane@example.com> - 1.2.0", entries[0].author);
+//! assert_eq!("Added:\n- Support zstd", entries[0].text);
+//!
+//! let pkg = rpm::RPMBuilder::new("foo", "1.2.0", "MIT", "noarch", "A foo")
+//!     .changelog(entries)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::convert::TryFrom;
+
+use chrono::NaiveDate;
+
+use crate::errors::*;
+
+use super::timestamp::Timestamp;
+use super::RPMBuilder;
+
+/// One release in a changelog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangelogEntry {
+    pub time: Timestamp,
+    /// Who wrote the entry, followed by the version like `Jane Doe <jane@example.com> - 1.2.0-1`.
+    pub author: String,
+    /// The changes, usually one line starting with `- ` per change.
+    pub text: String,
+}
+
+fn invalid(line: usize, reason: &str) -> RPMError {
+    RPMError::InvalidChangelog {
+        line,
+        reason: reason.to_string(),
+    }
+}
+
+/// Noon UTC of `date`.
+fn at_noon(date: NaiveDate, line: usize) -> Result<Timestamp, RPMError> {
+    let seconds = date
+        .and_hms_opt(12, 0, 0)
+        .map(|time| time.and_utc().timestamp())
+        .ok_or_else(|| invalid(line, "invalid date"))?;
+    Timestamp::try_from(seconds).map_err(|err| invalid(line, &err.to_string()))
+}
+
+/// Parse the content of a `%changelog` section.
+///
+/// Every entry starts with a line `* <weekday> <month> <day> <year> <author>`,
+/// the weekday has to match the date. The lines up to the next entry are its text,
+/// comments and empty lines at the end are dropped.
+pub fn parse_spec(text: &str) -> Result<Vec<ChangelogEntry>, RPMError> {
+    let mut entries: Vec<ChangelogEntry> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        if let Some(heading) = line.strip_prefix('*') {
+            let words = heading.split_whitespace().collect::<Vec<_>>();
+            if words.len() < 5 {
+                return Err(invalid(number, "expected a date and an author"));
+            }
+            let date = NaiveDate::parse_from_str(&words[..4].join(" "), "%a %b %d %Y")
+                .map_err(|_| invalid(number, "expected a valid date like Wed May 17 2023"))?;
+            entries.push(ChangelogEntry {
+                time: at_noon(date, number)?,
+                author: words[4..].join(" "),
+                text: String::new(),
+            });
+        } else if line.trim_start().starts_with('#') {
+            continue;
+        } else if let Some(entry) = entries.last_mut() {
+            entry.text.push_str(line.trim_end());
+            entry.text.push('\n');
+        } else if !line.trim().is_empty() {
+            return Err(invalid(number, "text before the first entry"));
+        }
+    }
+    for entry in entries.iter_mut() {
+        entry.text = entry.text.trim().to_string();
+    }
+    Ok(entries)
+}
+
+/// Parse markdown release notes, every heading with a version and a `YYYY-MM-DD` date
+/// starts an entry by `author`.
+///
+/// Headings without a date, like `Unreleased`, and the text below them are skipped.
+/// Subheadings like `### Added` become lines `Added:`, list items are kept with `-`
+/// as marker and other paragraphs are kept as they are.
+pub fn parse_markdown(text: &str, author: &str) -> Result<Vec<ChangelogEntry>, RPMError> {
+    let mut entries: Vec<ChangelogEntry> = Vec::new();
+    // level of the headings of releases, the first heading with a date decides
+    let mut level = None;
+    let mut in_release = false;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let trimmed = line.trim();
+        let hashes = trimmed.chars().take_while(|c| *c == '#').count();
+        if hashes > 0 && trimmed[hashes..].starts_with(' ') {
+            let heading = trimmed[hashes..].trim();
+            if level.map_or(true, |level| hashes <= level) {
+                match release(heading, number)? {
+                    Some((version, time)) => {
+                        level = Some(hashes);
+                        in_release = true;
+                        entries.push(ChangelogEntry {
+                            time,
+                            author: format!("{} - {}", author, version),
+                            text: String::new(),
+                        });
+                        continue;
+                    }
+                    None => {
+                        in_release = false;
+                        continue;
+                    }
+                }
+            }
+            if in_release {
+                let entry = entries.last_mut().expect("entry of the release");
+                entry.text.push_str(&format!("{}:\n", heading));
+            }
+            continue;
+        }
+        if !in_release || (trimmed.starts_with('[') && trimmed.contains("]: ")) {
+            // link definitions of the version headings
+            continue;
+        }
+        let entry = entries.last_mut().expect("entry of the release");
+        let line = match trimmed
+            .strip_prefix("* ")
+            .or_else(|| trimmed.strip_prefix("+ "))
+        {
+            Some(item) if line.starts_with(|c: char| !c.is_whitespace()) => format!("- {}", item),
+            _ if line.starts_with(char::is_whitespace) && !trimmed.is_empty() => {
+                format!("  {}", trimmed)
+            }
+            _ => trimmed.to_string(),
+        };
+        entry.text.push_str(&line);
+        entry.text.push('\n');
+    }
+    for entry in entries.iter_mut() {
+        let lines = entry
+            .text
+            .lines()
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>();
+        entry.text = lines.join("\n");
+    }
+    Ok(entries)
+}
+
+/// The version and date of a release heading like `[1.2.0] - 2023-05-17`.
+fn release(heading: &str, line: usize) -> Result<Option<(String, Timestamp)>, RPMError> {
+    let words = heading
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .filter(|word| !word.is_empty() && *word != "-")
+        .collect::<Vec<_>>();
+    let date = match words
+        .iter()
+        .find_map(|word| NaiveDate::parse_from_str(word, "%Y-%m-%d").ok())
+    {
+        Some(date) => date,
+        None => return Ok(None),
+    };
+    let version = words
+        .first()
+        .map(|word| word.trim_start_matches('[').trim_end_matches(']'))
+        .filter(|version| NaiveDate::parse_from_str(version, "%Y-%m-%d").is_err())
+        .ok_or_else(|| invalid(line, "release heading without a version"))?;
+    Ok(Some((version.to_string(), at_noon(date, line)?)))
+}
+
+impl RPMBuilder {
+    /// Add `entries`, for example from [`parse_spec`] or [`parse_markdown`].
+    pub fn changelog<I>(mut self, entries: I) -> Self
+    where
+        I: IntoIterator<Item = ChangelogEntry>,
+    {
+        for entry in entries {
+            self = self.add_changelog_entry(entry.author, entry.text, entry.time);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spec() -> Result<(), Box<dyn std::error::Error>> {
+        let entries = parse_spec(
+            "* Wed May 17 2023 Jane Doe <jane@example.com> - 1.2.0-1\n\
+             - Support zstd\n\
+             - Faster\n\
+             \n\
+             * Mon Sep  14 2020 John Doe <john@example.com> - 1.0.0-1\n\
+             # a comment\n\
+             - Initial release\n",
+        )?;
+        assert_eq!(
+            vec![
+                ChangelogEntry {
+                    time: Timestamp::new(1_684_324_800),
+                    author: "Jane Doe <jane@example.com> - 1.2.0-1".to_string(),
+                    text: "- Support zstd\n- Faster".to_string(),
+                },
+                ChangelogEntry {
+                    time: Timestamp::new(1_600_084_800),
+                    author: "John Doe <john@example.com> - 1.0.0-1".to_string(),
+                    text: "- Initial release".to_string(),
+                },
+            ],
+            entries
+        );
+
+        for (text, line) in [
+            ("* Thu May 17 2023 Jane Doe\n", 1),
+            ("* May 17 2023 Jane Doe\n", 1),
+            ("- orphan\n", 1),
+            ("* Wed May 17 2023 Jane\n- a\n* Wed\n", 3),
+        ] {
+            assert!(
+                matches!(parse_spec(text), Err(RPMError::InvalidChangelog { line: l, .. }) if l == line),
+                "{:?}",
+                text
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn markdown() -> Result<(), Box<dyn std::error::Error>> {
+        let entries = parse_markdown(
+            "# Changelog\n\
+             All notable changes.\n\
+             \n\
+             ## [Unreleased]\n\
+             ### Added\n\
+             - Not yet\n\
+             \n\
+             ## [1.2.0] - 2023-05-17\n\
+             ### Added\n\
+             * Support zstd\n  across lines\n\
+             \n\
+             ### Fixed\n\
+             - Crash\n\
+             \n\
+             ## 1.0.0 (2020-09-14)\n\
+             Initial release.\n\
+             \n\
+             [1.2.0]: https://example.com/compare/v1.0.0...v1.2.0\n",
+            "Jane Doe <jane@example.com>",
+        )?;
+        assert_eq!(
+            vec![
+                ChangelogEntry {
+                    time: Timestamp::new(1_684_324_800),
+                    author: "Jane Doe <jane@example.com> - 1.2.0".to_string(),
+                    text: "Added:\n- Support zstd\n  across lines\nFixed:\n- Crash".to_string(),
+                },
+                ChangelogEntry {
+                    time: Timestamp::new(1_600_084_800),
+                    author: "Jane Doe <jane@example.com> - 1.0.0".to_string(),
+                    text: "Initial release.".to_string(),
+                },
+            ],
+            entries
+        );
+        assert!(matches!(
+            parse_markdown("## 2023-05-17\n", "me"),
+            Err(RPMError::InvalidChangelog { line: 1, .. })
+        ));
+
+        let pkg = RPMBuilder::new("foo", "1.2.0", "MIT", "noarch", "A foo")
+            .changelog(entries)
+            .build()?;
+        assert_eq!(
+            vec![Timestamp::new(1_684_324_800), Timestamp::new(1_600_084_800)],
+            pkg.metadata.header.get_changelog_times()?
+        );
+        Ok(())
+    }
+}
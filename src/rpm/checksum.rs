@@ -0,0 +1,121 @@
+//! Digests of a package computed while it is written.
+
+use std::io::Write;
+
+use sha2::Digest;
+
+/// Size and digests of a written package, e.g. to register it with an artifact store.
+///
+/// Digests are lowercase hex, `None` if they were not computed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageInfo {
+    pub size: u64,
+    pub sha256: Option<String>,
+    pub sha1: Option<String>,
+}
+
+/// Passes everything written to the inner writer and digests it on the way.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let pkg = rpm::RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "").build()?;
+/// let mut out = rpm::ChecksumWriter::new(Vec::new()).sha1(false);
+/// pkg.write(&mut out)?;
+/// let (bytes, info) = out.finish();
+/// assert_eq!(bytes.len() as u64, info.size);
+/// assert!(info.sha1.is_none());
+/// # Ok(())
+/// # }
+/// ```
+pub struct ChecksumWriter<W> {
+    inner: W,
+    size: u64,
+    sha256: Option<sha2::Sha256>,
+    sha1: Option<sha1::Sha1>,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    /// Compute all digests, see the setters to skip some of them.
+    pub fn new(inner: W) -> Self {
+        ChecksumWriter {
+            inner,
+            size: 0,
+            sha256: Some(sha2::Sha256::new()),
+            sha1: Some(sha1::Sha1::new()),
+        }
+    }
+
+    pub fn sha256(mut self, enabled: bool) -> Self {
+        self.sha256 = if enabled {
+            Some(sha2::Sha256::new())
+        } else {
+            None
+        };
+        self
+    }
+
+    pub fn sha1(mut self, enabled: bool) -> Self {
+        self.sha1 = if enabled {
+            Some(sha1::Sha1::new())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// The inner writer and what was written to it so far.
+    pub fn finish(self) -> (W, PackageInfo) {
+        let info = PackageInfo {
+            size: self.size,
+            sha256: self.sha256.map(|hasher| hex::encode(hasher.finalize())),
+            sha1: self.sha1.map(|hasher| hex::encode(hasher.finalize())),
+        };
+        (self.inner, info)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        let written = &buf[..len];
+        self.size += len as u64;
+        if let Some(hasher) = self.sha256.as_mut() {
+            hasher.update(written);
+        }
+        if let Some(hasher) = self.sha1.as_mut() {
+            hasher.update(written);
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn digests_what_is_written() -> std::io::Result<()> {
+        let mut out = ChecksumWriter::new(Vec::new());
+        out.write_all(b"hello ")?;
+        out.write_all(b"world")?;
+        let (bytes, info) = out.finish();
+        assert_eq!(b"hello world", bytes.as_slice());
+        assert_eq!(11, info.size);
+        assert_eq!(
+            Some("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"),
+            info.sha256.as_deref()
+        );
+        assert_eq!(
+            Some("2aae6c35c94fcfb415dbe95f408b9ce91ee846ed"),
+            info.sha1.as_deref()
+        );
+
+        let (_, info) = ChecksumWriter::new(std::io::sink()).sha256(false).finish();
+        assert_eq!(None, info.sha256);
+        Ok(())
+    }
+}
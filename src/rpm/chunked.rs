@@ -0,0 +1,310 @@
+//! Writing packages in fixed-size chunks, e.g. for multipart uploads.
+
+use std::io::Write;
+
+use sha2::Digest;
+
+use crate::errors::*;
+
+/// A complete chunk of the package, passed to the callback of [`ChunkedWriter`].
+#[derive(Debug)]
+pub struct Chunk<'a> {
+    /// Position of the chunk, starting at 0.
+    pub index: usize,
+    /// Offset of the first byte of the chunk in the package.
+    pub offset: u64,
+    pub data: &'a [u8],
+    /// Lowercase hex sha256 of `data`.
+    pub sha256: String,
+}
+
+/// Everything about a chunk but its data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkInfo {
+    pub index: usize,
+    pub offset: u64,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// The chunks a package was written in, returned by [`ChunkedWriter::finish`].
+///
+/// The manifest converts to and from a line based text format through
+/// [`Display`](std::fmt::Display) and [`FromStr`](std::str::FromStr), to keep it
+/// next to an upload and resume it later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkManifest {
+    pub chunk_size: u64,
+    /// Size of the whole package.
+    pub size: u64,
+    /// Lowercase hex sha256 of the whole package.
+    pub sha256: String,
+    pub chunks: Vec<ChunkInfo>,
+}
+
+/// Splits everything written to it into chunks of a fixed size and passes
+/// each of them to a callback, the last chunk may be smaller.
+///
+/// Only a single chunk is buffered, so a package can be uploaded while it is
+/// written without keeping all of it in memory or in a temporary file.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let pkg = rpm::RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "").build()?;
+/// let mut parts = Vec::new();
+/// let mut out = rpm::ChunkedWriter::new(1024, |chunk: rpm::Chunk| {
+///     parts.push((chunk.index + 1, chunk.data.to_vec()));
+///     Ok(())
+/// });
+/// pkg.write(&mut out)?;
+/// let manifest = out.finish()?;
+/// assert_eq!(manifest.chunks.len(), parts.len());
+/// # Ok(())
+/// # }
+/// ```
+pub struct ChunkedWriter<F> {
+    chunk_size: usize,
+    buffer: Vec<u8>,
+    emit: F,
+    offset: u64,
+    sha256: sha2::Sha256,
+    chunks: Vec<ChunkInfo>,
+    previous: Vec<ChunkInfo>,
+}
+
+impl<F> ChunkedWriter<F>
+where
+    F: FnMut(Chunk) -> std::io::Result<()>,
+{
+    /// Pass chunks of `chunk_size` bytes to `emit`, which is called once per chunk in order.
+    ///
+    /// # Panics
+    ///
+    /// If `chunk_size` is 0.
+    pub fn new(chunk_size: usize, emit: F) -> Self {
+        assert!(chunk_size > 0, "chunks must not be empty");
+        ChunkedWriter {
+            chunk_size,
+            buffer: Vec::with_capacity(chunk_size),
+            emit,
+            offset: 0,
+            sha256: sha2::Sha256::new(),
+            chunks: Vec::new(),
+            previous: Vec::new(),
+        }
+    }
+
+    /// Resume an interrupted upload, the complete chunks of `previous` are not passed
+    /// to the callback again.
+    ///
+    /// The package has to be written again from the start and the skipped chunks are
+    /// compared with `previous`, so it has to be written from the same package or
+    /// built again from unchanged files. Writing fails with
+    /// [`std::io::ErrorKind::InvalidData`] as soon as a chunk differs.
+    pub fn resume(mut self, previous: &ChunkManifest) -> Result<Self, RPMError> {
+        if previous.chunk_size != self.chunk_size as u64 {
+            return Err(RPMError::InvalidChunkManifest(format!(
+                "chunks of {} bytes can not resume chunks of {} bytes",
+                self.chunk_size, previous.chunk_size
+            )));
+        }
+        self.previous = previous
+            .chunks
+            .iter()
+            .filter(|chunk| chunk.size == previous.chunk_size)
+            .cloned()
+            .collect();
+        Ok(self)
+    }
+
+    /// Pass the last chunk to the callback and return the manifest of all chunks.
+    pub fn finish(mut self) -> std::io::Result<ChunkManifest> {
+        if !self.buffer.is_empty() {
+            self.emit_chunk()?;
+        }
+        Ok(ChunkManifest {
+            chunk_size: self.chunk_size as u64,
+            size: self.offset,
+            sha256: hex::encode(self.sha256.finalize()),
+            chunks: self.chunks,
+        })
+    }
+
+    fn emit_chunk(&mut self) -> std::io::Result<()> {
+        let index = self.chunks.len();
+        let info = ChunkInfo {
+            index,
+            offset: self.offset,
+            size: self.buffer.len() as u64,
+            sha256: hex::encode(sha2::Sha256::digest(&self.buffer)),
+        };
+        match self.previous.get(index) {
+            Some(previous) if previous == &info => {}
+            Some(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("chunk {} differs from the upload to resume", index),
+                ))
+            }
+            None => (self.emit)(Chunk {
+                index,
+                offset: info.offset,
+                data: &self.buffer,
+                sha256: info.sha256.clone(),
+            })?,
+        }
+        self.sha256.update(&self.buffer);
+        self.offset += info.size;
+        self.chunks.push(info);
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl<F> Write for ChunkedWriter<F>
+where
+    F: FnMut(Chunk) -> std::io::Result<()>,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(self.chunk_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == self.chunk_size {
+            self.emit_chunk()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ChunkManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} {} {}", self.chunk_size, self.size, self.sha256)?;
+        for chunk in &self.chunks {
+            writeln!(
+                f,
+                "{} {} {} {}",
+                chunk.index, chunk.offset, chunk.size, chunk.sha256
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for ChunkManifest {
+    type Err = RPMError;
+
+    fn from_str(manifest: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            |line: &str| RPMError::InvalidChunkManifest(format!("invalid line {:?}", line));
+        let number = |line: &str, field: Option<&str>| {
+            field
+                .and_then(|field| field.parse::<u64>().ok())
+                .ok_or_else(|| invalid(line))
+        };
+        let digest = |line: &str, field: Option<&str>| match field {
+            Some(field) if field.len() == 64 && hex::decode(field).is_ok() => Ok(field.to_string()),
+            _ => Err(invalid(line)),
+        };
+
+        let mut lines = manifest.lines().filter(|line| !line.trim().is_empty());
+        let first = lines.next().ok_or_else(|| invalid(""))?;
+        let mut fields = first.split_whitespace();
+        let mut parsed = ChunkManifest {
+            chunk_size: number(first, fields.next())?,
+            size: number(first, fields.next())?,
+            sha256: digest(first, fields.next())?,
+            chunks: Vec::new(),
+        };
+        let mut offset = 0;
+        for line in lines {
+            let mut fields = line.split_whitespace();
+            let chunk = ChunkInfo {
+                index: number(line, fields.next())? as usize,
+                offset: number(line, fields.next())?,
+                size: number(line, fields.next())?,
+                sha256: digest(line, fields.next())?,
+            };
+            if chunk.index != parsed.chunks.len()
+                || chunk.offset != offset
+                || chunk.size > parsed.chunk_size
+            {
+                return Err(RPMError::InvalidChunkManifest(format!(
+                    "chunk {:?} does not follow the previous chunks",
+                    line
+                )));
+            }
+            offset += chunk.size;
+            parsed.chunks.push(chunk);
+        }
+        if offset != parsed.size {
+            return Err(RPMError::InvalidChunkManifest(format!(
+                "chunks take {} bytes instead of {}",
+                offset, parsed.size
+            )));
+        }
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Compressor, RPMBuilder, RPMFileOptions};
+    use std::str::FromStr;
+
+    #[test]
+    fn write_chunks() -> Result<(), Box<dyn std::error::Error>> {
+        let package = RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "")
+            .compression(Compressor::from_str("gzip")?)
+            .with_file(
+                "./test_assets/awesome.py",
+                RPMFileOptions::new("/usr/bin/awesome"),
+            )?
+            .build()?;
+        let mut expected = Vec::new();
+        package.write(&mut expected)?;
+
+        let mut uploaded = Vec::new();
+        let mut out = ChunkedWriter::new(256, |chunk: Chunk| {
+            assert_eq!(uploaded.len() as u64, chunk.offset);
+            assert_eq!(hex::encode(sha2::Sha256::digest(chunk.data)), chunk.sha256);
+            uploaded.extend_from_slice(chunk.data);
+            Ok(())
+        });
+        package.write(&mut out)?;
+        let manifest = out.finish()?;
+        assert_eq!(expected, uploaded);
+        assert_eq!(expected.len() as u64, manifest.size);
+        assert_eq!(
+            hex::encode(sha2::Sha256::digest(&expected)),
+            manifest.sha256
+        );
+        assert_eq!((expected.len() + 255) / 256, manifest.chunks.len());
+        assert_eq!(manifest, ChunkManifest::from_str(&manifest.to_string())?);
+
+        // the upload failed after the first two chunks
+        let mut interrupted = manifest.clone();
+        interrupted.chunks.truncate(2);
+        let mut resumed = Vec::new();
+        let mut out = ChunkedWriter::new(256, |chunk: Chunk| {
+            resumed.push(chunk.index);
+            Ok(())
+        })
+        .resume(&interrupted)?;
+        package.write(&mut out)?;
+        assert_eq!(manifest, out.finish()?);
+        assert_eq!((2..manifest.chunks.len()).collect::<Vec<_>>(), resumed);
+
+        interrupted.chunks[1].sha256 = "0".repeat(64);
+        let mut out = ChunkedWriter::new(256, |_: Chunk| Ok(())).resume(&interrupted)?;
+        assert!(package.write(&mut out).is_err());
+        assert!(ChunkedWriter::new(10, |_: Chunk| Ok(()))
+            .resume(&manifest)
+            .is_err());
+        assert!(ChunkManifest::from_str("1000 10 abc").is_err());
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use crate::errors::*;
 use std::io::Write;
+use std::sync::Arc;
 
 pub enum Compressor {
     None(Vec<u8>),
@@ -51,19 +52,79 @@ impl Compressor {
     pub(crate) fn get_details(&self) -> Option<CompressionDetails> {
         match self {
             Compressor::None(_) => None,
-            Compressor::Gzip(_) => Some(CompressionDetails {
-                compression_level: "9",
-                compression_name: "gzip",
-            }),
-            Compressor::Zstd(_) => Some(CompressionDetails {
-                compression_level: "19",
-                compression_name: "zstd",
-            }),
+            Compressor::Gzip(_) | Compressor::Zstd(_) => PayloadCompression::of(self).details(),
         }
     }
 }
 
 pub(crate) struct CompressionDetails {
-    pub(crate) compression_level: &'static str,
+    pub(crate) compression_level: String,
     pub(crate) compression_name: &'static str,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CompressionKind {
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// How a builder compresses its payload. The encoder is only created when building,
+/// so builders can be cloned and shared between threads.
+#[derive(Debug, Clone)]
+pub(crate) struct PayloadCompression {
+    pub(crate) kind: CompressionKind,
+    /// The zstd level, also kept for other kinds. Gzip always compresses at 9.
+    pub(crate) level: i32,
+    /// Zstd dictionary the payload is compressed with.
+    pub(crate) dictionary: Option<Arc<[u8]>>,
+}
+
+impl PayloadCompression {
+    pub(crate) const DEFAULT_ZSTD_LEVEL: i32 = 19;
+
+    /// The kind of `compressor`, at the default level.
+    pub(crate) fn of(compressor: &Compressor) -> Self {
+        let kind = match compressor {
+            Compressor::None(_) => CompressionKind::None,
+            Compressor::Gzip(_) => CompressionKind::Gzip,
+            Compressor::Zstd(_) => CompressionKind::Zstd,
+        };
+        PayloadCompression {
+            kind,
+            level: Self::DEFAULT_ZSTD_LEVEL,
+            dictionary: None,
+        }
+    }
+
+    pub(crate) fn compressor(&self) -> Result<Compressor, RPMError> {
+        if self.kind == CompressionKind::Zstd && !(1..=22).contains(&self.level) {
+            return Err(RPMError::UnknownCompressorType(format!(
+                "zstd -{}",
+                self.level
+            )));
+        }
+        Ok(match self.kind {
+            CompressionKind::None => Compressor::None(Vec::new()),
+            CompressionKind::Gzip => Compressor::Gzip(libflate::gzip::Encoder::new(Vec::new())?),
+            CompressionKind::Zstd => Compressor::Zstd(match &self.dictionary {
+                Some(dictionary) => {
+                    zstd::stream::Encoder::with_dictionary(Vec::new(), self.level, dictionary)?
+                }
+                None => zstd::stream::Encoder::new(Vec::new(), self.level)?,
+            }),
+        })
+    }
+
+    pub(crate) fn details(&self) -> Option<CompressionDetails> {
+        let (compression_name, level) = match self.kind {
+            CompressionKind::None => return None,
+            CompressionKind::Gzip => ("gzip", 9),
+            CompressionKind::Zstd => ("zstd", self.level),
+        };
+        Some(CompressionDetails {
+            compression_level: level.to_string(),
+            compression_name,
+        })
+    }
+}
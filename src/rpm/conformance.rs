@@ -0,0 +1,466 @@
+//! Checks of a package with the `rpm` and `rpmsign` command line tools, to catch
+//! packages this crate reads fine but rpm does not.
+//!
+//! [`run`] writes the package to a temporary directory and runs
+//! - `rpm -K` on it, checking the digests and, with [`ConformanceOptions::key`], the signatures
+//! - `rpm -qp` for its name, and `rpm -qpl` for its files, comparing them with the header
+//! - `rpmsign --delsign` on a copy, which has to parse again with the header unchanged
+//! - `rpm -i` into an empty root and `rpm -V` of the installed files, only as root
+//!
+//! Checks whose tool is not installed are skipped. [`ConformanceReport::check`] fails
+//! if all of them were, unless [`ConformanceOptions::allow_skipped`] opts in to running
+//! the same battery on machines without rpm. Packages are checked as they are,
+//! `rpmbuild` is not needed.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let pkg = rpm::RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo")
+//!     .with_file(
+//!         "./test_assets/awesome.toml",
+//!         rpm::RPMFileOptions::new("/etc/awesome/config.toml"),
+//!     )?
+//!     .build()?;
+//! let report = rpm::conformance::run(&pkg)?;
+//! for check in &report.checks {
+//!     println!("{}: {:?}", check.name, check.outcome);
+//! }
+//! report.check()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::ErrorKind;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::errors::*;
+
+use super::RPMPackage;
+
+pub const CHECKSIG: &str = "checksig";
+pub const QUERY: &str = "query";
+pub const FILE_LIST: &str = "file-list";
+pub const DELSIGN: &str = "delsign";
+pub const INSTALL: &str = "install";
+
+/// Distinguishes the directories of runs at the same time.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// What became of one check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// What the tool printed or how its result differs from the package.
+    Failed(String),
+    /// Why the check could not run.
+    Skipped(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// One of [`CHECKSIG`], [`QUERY`], [`FILE_LIST`], [`DELSIGN`] and [`INSTALL`].
+    pub name: &'static str,
+    pub outcome: Outcome,
+}
+
+/// The outcome of every check of [`run`], in the order they ran.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConformanceReport {
+    pub checks: Vec<Check>,
+    allow_skipped: bool,
+}
+
+impl ConformanceReport {
+    /// The check called `name`, if it ran or was skipped.
+    pub fn outcome(&self, name: &str) -> Option<&Outcome> {
+        self.checks
+            .iter()
+            .find(|check| check.name == name)
+            .map(|check| &check.outcome)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks
+            .iter()
+            .filter(|check| matches!(check.outcome, Outcome::Failed(_)))
+    }
+
+    /// Fail with [`RPMError::Conformance`] for the first check which failed, or for
+    /// the first one if all were skipped and [`ConformanceOptions::allow_skipped`]
+    /// was not set.
+    pub fn check(&self) -> Result<(), RPMError> {
+        if let Some(Check {
+            name,
+            outcome: Outcome::Failed(reason),
+        }) = self.failures().next()
+        {
+            return Err(RPMError::Conformance {
+                check: name,
+                reason: reason.clone(),
+            });
+        }
+        let all_skipped = self
+            .checks
+            .iter()
+            .all(|check| matches!(check.outcome, Outcome::Skipped(_)));
+        match self.checks.first() {
+            Some(Check {
+                name,
+                outcome: Outcome::Skipped(reason),
+            }) if all_skipped && !self.allow_skipped => Err(RPMError::Conformance {
+                check: name,
+                reason: format!("every check was skipped, the first as {}", reason),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn record(&mut self, name: &'static str, outcome: Outcome) {
+        self.checks.push(Check { name, outcome });
+    }
+}
+
+/// Which tools [`run_with`] uses and how.
+#[derive(Debug, Clone)]
+pub struct ConformanceOptions {
+    rpm: PathBuf,
+    rpmsign: PathBuf,
+    keys: Vec<PathBuf>,
+    install: bool,
+    allow_skipped: bool,
+}
+
+impl Default for ConformanceOptions {
+    fn default() -> Self {
+        ConformanceOptions {
+            rpm: PathBuf::from("rpm"),
+            rpmsign: PathBuf::from("rpmsign"),
+            keys: Vec::new(),
+            install: true,
+            allow_skipped: false,
+        }
+    }
+}
+
+impl ConformanceOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The `rpm` binary, looked up in `PATH` unless set.
+    pub fn rpm<P: AsRef<Path>>(mut self, rpm: P) -> Self {
+        self.rpm = rpm.as_ref().to_path_buf();
+        self
+    }
+
+    /// The `rpmsign` binary, looked up in `PATH` unless set.
+    pub fn rpmsign<P: AsRef<Path>>(mut self, rpmsign: P) -> Self {
+        self.rpmsign = rpmsign.as_ref().to_path_buf();
+        self
+    }
+
+    /// Import the armored public key at `key` so `rpm -K` checks the signatures.
+    /// Without keys only the digests are checked.
+    pub fn key<P: AsRef<Path>>(mut self, key: P) -> Self {
+        self.keys.push(key.as_ref().to_path_buf());
+        self
+    }
+
+    /// Skip installing the package, e.g. for packages of another architecture.
+    pub fn install(mut self, install: bool) -> Self {
+        self.install = install;
+        self
+    }
+
+    /// Let [`ConformanceReport::check`] pass if every check was skipped, e.g. in
+    /// tests that also run on machines without rpm.
+    pub fn allow_skipped(mut self, allow_skipped: bool) -> Self {
+        self.allow_skipped = allow_skipped;
+        self
+    }
+}
+
+/// Run the checks of the [module documentation](self) with the default options.
+pub fn run(package: &RPMPackage) -> Result<ConformanceReport, RPMError> {
+    run_with(package, &ConformanceOptions::new())
+}
+
+/// Run the checks of the [module documentation](self).
+///
+/// Fails only if the package cannot be written, the results of the tools are in
+/// the report.
+pub fn run_with(
+    package: &RPMPackage,
+    options: &ConformanceOptions,
+) -> Result<ConformanceReport, RPMError> {
+    let dir = std::env::temp_dir().join(format!(
+        "fez-conformance-{}-{}",
+        std::process::id(),
+        RUNS.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&dir)?;
+    let report = run_in(package, options, &dir);
+    let _ = std::fs::remove_dir_all(&dir);
+    report
+}
+
+fn run_in(
+    package: &RPMPackage,
+    options: &ConformanceOptions,
+    dir: &Path,
+) -> Result<ConformanceReport, RPMError> {
+    let file = dir.join("package.rpm");
+    package.write(&mut std::fs::File::create(&file)?)?;
+    let mut report = ConformanceReport {
+        checks: Vec::new(),
+        allow_skipped: options.allow_skipped,
+    };
+    let header = &package.metadata.header;
+
+    let dbpath = dir.join("db");
+    std::fs::create_dir_all(&dbpath)?;
+    let checksig = options
+        .keys
+        .iter()
+        .try_for_each(|key| {
+            run_tool(&options.rpm, |cmd| {
+                cmd.arg("--dbpath").arg(&dbpath).arg("--import").arg(key)
+            })
+            .map(drop)
+        })
+        .and_then(|_| {
+            run_tool(&options.rpm, |cmd| {
+                cmd.arg("--dbpath").arg(&dbpath).arg("-K");
+                if options.keys.is_empty() {
+                    cmd.arg("--nosignature");
+                }
+                cmd.arg(&file)
+            })
+        })
+        .map(|_| Outcome::Passed);
+    report.record(CHECKSIG, checksig.unwrap_or_else(|outcome| outcome));
+
+    let query = run_tool(&options.rpm, |cmd| {
+        cmd.args(["-qp", "--qf", "%{NAME}"]).arg(&file)
+    })
+    .and_then(|name| compare("name", header.get_name()?, name.trim()));
+    report.record(QUERY, query.unwrap_or_else(|outcome| outcome));
+
+    let file_list = run_tool(&options.rpm, |cmd| cmd.arg("-qpl").arg(&file)).and_then(|list| {
+        let expected = header
+            .get_file_paths()
+            .unwrap_or_default()
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>();
+        let listed = list
+            .lines()
+            .filter(|line| *line != "(contains no files)")
+            .collect::<Vec<_>>();
+        compare("file list", &expected.join("\n"), &listed.join("\n"))
+    });
+    report.record(FILE_LIST, file_list.unwrap_or_else(|outcome| outcome));
+
+    let unsigned = dir.join("delsign.rpm");
+    std::fs::copy(&file, &unsigned)?;
+    let delsign =
+        run_tool(&options.rpmsign, |cmd| cmd.arg("--delsign").arg(&unsigned)).and_then(|_| {
+            let mut input = std::io::BufReader::new(std::fs::File::open(&unsigned)?);
+            match RPMPackage::parse(&mut input) {
+                Ok(parsed) if parsed.metadata.header == *header => Ok(Outcome::Passed),
+                Ok(_) => Ok(Outcome::Failed(
+                    "rpmsign --delsign changed the header".to_string(),
+                )),
+                Err(err) => Ok(Outcome::Failed(format!(
+                    "the package does not parse after rpmsign --delsign - {}",
+                    err
+                ))),
+            }
+        });
+    report.record(DELSIGN, delsign.unwrap_or_else(|outcome| outcome));
+
+    let install = if !options.install {
+        Outcome::Skipped("disabled".to_string())
+    } else if !is_root() {
+        Outcome::Skipped("installing needs root".to_string())
+    } else {
+        let root = dir.join("root");
+        std::fs::create_dir_all(&root)?;
+        let name = header.get_name()?;
+        run_tool(&options.rpm, |cmd| {
+            cmd.arg("--root")
+                .arg(&root)
+                .args([
+                    "-i",
+                    "--nodeps",
+                    "--nosignature",
+                    "--ignorearch",
+                    "--ignoreos",
+                ])
+                .arg(&file)
+        })
+        .and_then(|_| {
+            run_tool(&options.rpm, |cmd| {
+                cmd.arg("--root").arg(&root).args(["-V", name])
+            })
+        })
+        .map(|_| Outcome::Passed)
+        .unwrap_or_else(|outcome| outcome)
+    };
+    report.record(INSTALL, install);
+    Ok(report)
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    std::fs::metadata("/proc/self").map_or(false, |meta| meta.uid() == 0)
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+/// The standard output of `tool`, or the outcome of the check if it did not succeed.
+fn run_tool<F>(tool: &Path, args: F) -> Result<String, Outcome>
+where
+    F: FnOnce(&mut Command) -> &mut Command,
+{
+    let mut command = Command::new(tool);
+    let output = match args(&mut command).output() {
+        Ok(output) => output,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Err(Outcome::Skipped(format!("{} not found", tool.display())))
+        }
+        Err(err) => {
+            return Err(Outcome::Failed(format!(
+                "failed to run {} - {}",
+                tool.display(),
+                err
+            )))
+        }
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() {
+        return Err(Outcome::Failed(format!(
+            "{} - {}",
+            command_line(&command),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(stdout)
+}
+
+fn command_line(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn compare(what: &str, expected: &str, actual: &str) -> Result<Outcome, Outcome> {
+    if expected == actual {
+        Ok(Outcome::Passed)
+    } else {
+        Ok(Outcome::Failed(format!(
+            "rpm reports the {} {:?} instead of {:?}",
+            what, actual, expected
+        )))
+    }
+}
+
+impl From<RPMError> for Outcome {
+    fn from(err: RPMError) -> Self {
+        Outcome::Failed(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for Outcome {
+    fn from(err: std::io::Error) -> Self {
+        Outcome::Failed(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{RPMBuilder, RPMFileOptions};
+
+    fn packages() -> Result<Vec<RPMPackage>, RPMError> {
+        let mut with_files = RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "A foo");
+        with_files.add_data(
+            b"[foo]\n".to_vec(),
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/etc/foo/foo.toml").is_config().into(),
+        )?;
+        with_files.add_data(
+            Vec::new(),
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/usr/bin/foo").mode(0o100_755).into(),
+        )?;
+        Ok(vec![
+            RPMBuilder::new("empty", "1.0.0", "MIT", "noarch", "Nothing").build()?,
+            with_files.build()?,
+        ])
+    }
+
+    #[test]
+    fn conformance() -> Result<(), Box<dyn std::error::Error>> {
+        for pkg in packages()? {
+            let report = run_with(&pkg, &ConformanceOptions::new().allow_skipped(true))?;
+            assert_eq!(
+                vec![CHECKSIG, QUERY, FILE_LIST, DELSIGN, INSTALL],
+                report
+                    .checks
+                    .iter()
+                    .map(|check| check.name)
+                    .collect::<Vec<_>>()
+            );
+            report.check()?;
+        }
+
+        let pkg = &packages()?[1];
+        let missing = run_with(
+            pkg,
+            &ConformanceOptions::new()
+                .rpm("/nonexistent/rpm")
+                .rpmsign("/nonexistent/rpmsign")
+                .install(false),
+        )?;
+        assert!(missing
+            .checks
+            .iter()
+            .all(|check| matches!(check.outcome, Outcome::Skipped(_))));
+        assert!(matches!(
+            missing.check(),
+            Err(RPMError::Conformance {
+                check: CHECKSIG,
+                ..
+            })
+        ));
+        let allowed = run_with(
+            pkg,
+            &ConformanceOptions::new()
+                .rpm("/nonexistent/rpm")
+                .rpmsign("/nonexistent/rpmsign")
+                .install(false)
+                .allow_skipped(true),
+        )?;
+        allowed.check()?;
+
+        // succeeds without printing anything
+        let failing = run_with(pkg, &ConformanceOptions::new().rpm("true").install(false))?;
+        assert_eq!(Some(&Outcome::Passed), failing.outcome(CHECKSIG));
+        assert!(matches!(failing.outcome(QUERY), Some(Outcome::Failed(_))));
+        assert_eq!(2, failing.failures().count());
+        assert!(matches!(
+            failing.check(),
+            Err(RPMError::Conformance { check: QUERY, .. })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,509 @@
+//! Packaging what a container ships, a layer of an OCI image or a directory
+//! exported from a container, to install it on the host.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let installed = rpm::rpmdb::read_bdb("/var/lib/rpm/Packages")?;
+//! let options = rpm::ContainerOptions::new()
+//!     .prefix("/opt/awesome")
+//!     .installed(&installed)?;
+//! let pkg = rpm::RPMBuilder::new("awesome", "1.0.0", "MIT", "x86_64", "awesome from a container")
+//!     .with_container_dir("./rootfs", &options)?
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::path::Path;
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::builder::remap_path;
+use super::timestamp::Timestamp;
+use super::{Dependency, FileDigestAlgorithm, FileMode, Header, RPMBuilder, RPMFileOptions};
+
+/// Paths container runtimes mount or create at run time, never packaged.
+pub const CONTAINER_ONLY_PATHS: &[&str] = &[
+    "/dev",
+    "/proc",
+    "/sys",
+    "/run",
+    "/tmp",
+    "/var/tmp",
+    "/etc/hostname",
+    "/etc/hosts",
+    "/etc/resolv.conf",
+    "/.dockerenv",
+];
+
+/// How [`RPMBuilder::with_container_layer`] and [`RPMBuilder::with_container_dir`]
+/// turn container content into files.
+///
+/// Content is normalized, since ids and modes inside a container mean little on the
+/// host: files are owned by root, executables get `0755`, other files `0644` and
+/// directories `0755`. setuid and setgid bits are dropped. Paths below
+/// [`CONTAINER_ONLY_PATHS`], whiteouts and device nodes are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct ContainerOptions {
+    prefix: Option<String>,
+    keep_owners: bool,
+    installed: HashMap<String, Vec<(String, Installed)>>,
+}
+
+/// What an installed package has at a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Installed {
+    Dir,
+    /// The SHA-256 of the content, `None` for other digest algorithms.
+    File(Option<String>),
+    Symlink(String),
+}
+
+impl ContainerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Install everything below `prefix` instead of `/`, e.g. `/opt/<name>`.
+    pub fn prefix<T: Into<String>>(mut self, prefix: T) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Keep the user and group names of layer entries instead of using root.
+    /// Directories carry no names, their files are always owned by root.
+    pub fn keep_owners(mut self, keep: bool) -> Self {
+        self.keep_owners = keep;
+        self
+    }
+
+    /// Compare the content with the files of the packages installed on the host,
+    /// e.g. read with [`read_bdb`](crate::rpmdb::read_bdb).
+    ///
+    /// Directories an installed package owns are left to it. The package conflicts
+    /// with every installed package owning one of its other paths, unless both
+    /// ship the same file or the same symlink, as rpm would refuse the
+    /// installation anyway. Only SHA-256 digests are compared.
+    pub fn installed(mut self, headers: &[Header<IndexTag>]) -> Result<Self, RPMError> {
+        for header in headers {
+            let paths = match header.get_file_paths() {
+                Ok(paths) => paths,
+                Err(RPMError::TagNotFound(_)) => continue,
+                Err(err) => return Err(err),
+            };
+            let name = header.get_name()?.to_string();
+            let modes = header.get_entry_i16_array_data(IndexTag::RPMTAG_FILEMODES)?;
+            let digests = header
+                .get_entry_string_array_data(IndexTag::RPMTAG_FILEDIGESTS)
+                .unwrap_or_default();
+            let links = header
+                .get_entry_string_array_data(IndexTag::RPMTAG_FILELINKTOS)
+                .unwrap_or_default();
+            let sha256 = header.get_file_digest_algorithm().unwrap_or_default()
+                == FileDigestAlgorithm::Sha2_256;
+            for (index, path) in paths.iter().enumerate() {
+                let mode = modes.get(index).map_or(0, |mode| *mode as u16);
+                let installed = match FileMode::from(mode) {
+                    FileMode::Dir { .. } => Installed::Dir,
+                    FileMode::SymbolicLink { .. } => {
+                        Installed::Symlink(links.get(index).cloned().unwrap_or_default())
+                    }
+                    _ => Installed::File(digests.get(index).filter(|_| sha256).cloned()),
+                };
+                self.installed
+                    .entry(path.to_string_lossy().into_owned())
+                    .or_default()
+                    .push((name.clone(), installed));
+            }
+        }
+        Ok(self)
+    }
+}
+
+/// Content of a container entry, normalized.
+enum Content {
+    Dir,
+    File { data: Vec<u8>, executable: bool },
+    Symlink(String),
+}
+
+/// Adds converted entries to a builder and collects the packages they conflict with.
+struct Converter<'a> {
+    options: &'a ContainerOptions,
+    conflicts: BTreeSet<String>,
+}
+
+impl<'a> Converter<'a> {
+    fn new(options: &'a ContainerOptions) -> Self {
+        Converter {
+            options,
+            conflicts: BTreeSet::new(),
+        }
+    }
+
+    /// Add the entry at `path`, relative to the root of the container.
+    fn add(
+        &mut self,
+        builder: &mut RPMBuilder,
+        path: &str,
+        content: Content,
+        mtime: Timestamp,
+        owners: Option<(String, String)>,
+    ) -> Result<(), RPMError> {
+        let relative = path
+            .trim_start_matches("./")
+            .trim_start_matches('/')
+            .trim_end_matches('/');
+        let path = format!("/{}", relative);
+        let base_name = relative.rsplit('/').next().unwrap_or_default();
+        if relative.is_empty()
+            || relative == "."
+            || base_name.starts_with(".wh.")
+            || CONTAINER_ONLY_PATHS
+                .iter()
+                .any(|skipped| remap_path(&path, skipped, skipped).is_some())
+        {
+            return Ok(());
+        }
+        let destination = match &self.options.prefix {
+            Some(prefix) => remap_path(&path, "/", prefix).unwrap_or(path),
+            None => path,
+        };
+
+        let installed = self
+            .options
+            .installed
+            .get(&destination)
+            .map_or(&[][..], Vec::as_slice);
+        if matches!(content, Content::Dir)
+            && installed
+                .iter()
+                .any(|(_, installed)| *installed == Installed::Dir)
+        {
+            return Ok(());
+        }
+
+        let (mode, symlink, data) = match content {
+            Content::Dir => (FileMode::dir(0o755), String::new(), Vec::new()),
+            Content::File { data, executable } => {
+                let permissions = if executable { 0o755 } else { 0o644 };
+                (FileMode::regular(permissions), String::new(), data)
+            }
+            Content::Symlink(target) => {
+                let data = target.as_bytes().to_vec();
+                (FileMode::symlink(0o777), target, data)
+            }
+        };
+        let ours = match mode {
+            FileMode::Dir { .. } => Installed::Dir,
+            FileMode::SymbolicLink { .. } => Installed::Symlink(symlink.clone()),
+            _ => {
+                use sha2::Digest;
+                Installed::File(Some(hex::encode(sha2::Sha256::digest(&data))))
+            }
+        };
+        for (name, installed) in installed {
+            if *installed != ours {
+                self.conflicts.insert(name.clone());
+            }
+        }
+
+        let (user, group) = owners
+            .filter(|_| self.options.keep_owners)
+            .unwrap_or_else(|| ("root".to_string(), "root".to_string()));
+        builder.add_data(
+            data,
+            mtime,
+            RPMFileOptions {
+                destination,
+                user: Some(user),
+                group: Some(group),
+                uid: None,
+                gid: None,
+                symlink,
+                mode,
+                flag: 0,
+                inherit_permissions: false,
+            },
+        )
+    }
+
+    fn finish(self, mut builder: RPMBuilder) -> RPMBuilder {
+        for name in self.conflicts {
+            builder = builder.conflicts(Dependency::any(name));
+        }
+        builder
+    }
+}
+
+impl RPMBuilder {
+    /// Add the content of an OCI image layer, a tar archive which may be gzip or
+    /// zstd compressed, normalized as described for [`ContainerOptions`].
+    ///
+    /// Hard links become copies of the file they link to.
+    #[cfg(feature = "tar")]
+    pub fn with_container_layer<R: std::io::Read>(
+        mut self,
+        layer: R,
+        options: &ContainerOptions,
+    ) -> Result<Self, RPMError> {
+        use std::io::{BufRead, Read};
+
+        let mut reader = std::io::BufReader::new(layer);
+        let magic = reader.fill_buf()?;
+        let reader: Box<dyn Read> = if magic.starts_with(&[0x1f, 0x8b]) {
+            Box::new(libflate::gzip::Decoder::new(reader)?)
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Box::new(zstd::stream::read::Decoder::with_buffer(reader)?)
+        } else {
+            Box::new(reader)
+        };
+
+        let mut converter = Converter::new(options);
+        // content of the regular files so far, for hard links to them
+        let mut files: HashMap<String, (Vec<u8>, bool)> = HashMap::new();
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = self.file_name_policy.apply(&entry.path_bytes())?;
+            let header = entry.header();
+            let mtime = Timestamp::try_from(i64::try_from(header.mtime()?).unwrap_or(i64::MAX))?;
+            let executable = header.mode()? & 0o111 != 0;
+            let owners = match (header.username(), header.groupname()) {
+                (Ok(Some(user)), Ok(Some(group))) if !user.is_empty() && !group.is_empty() => {
+                    Some((user.to_string(), group.to_string()))
+                }
+                _ => None,
+            };
+            let content = match header.entry_type() {
+                tar::EntryType::Regular | tar::EntryType::Continuous => {
+                    let mut data = Vec::new();
+                    entry.read_to_end(&mut data)?;
+                    files.insert(link_key(&path), (data.clone(), executable));
+                    Content::File { data, executable }
+                }
+                tar::EntryType::Directory => Content::Dir,
+                tar::EntryType::Symlink => {
+                    let target = entry.link_name_bytes().ok_or_else(|| {
+                        RPMError::UnsupportedArchiveEntry {
+                            path: path.clone(),
+                            reason: "symlink without target",
+                        }
+                    })?;
+                    Content::Symlink(self.file_name_policy.apply(&target)?)
+                }
+                tar::EntryType::Link => {
+                    let target = entry
+                        .link_name_bytes()
+                        .map(|target| self.file_name_policy.apply(&target))
+                        .transpose()?
+                        .unwrap_or_default();
+                    let (data, executable) =
+                        files.get(&link_key(&target)).cloned().ok_or_else(|| {
+                            RPMError::UnsupportedArchiveEntry {
+                                path: path.clone(),
+                                reason: "hard link to a file not in the layer",
+                            }
+                        })?;
+                    Content::File { data, executable }
+                }
+                // device nodes and fifos are created by the runtime
+                _ => continue,
+            };
+            converter.add(&mut self, &path, content, mtime, owners)?;
+        }
+        Ok(converter.finish(self))
+    }
+
+    /// Add everything below `dir`, the root of a container file system, normalized
+    /// as described for [`ContainerOptions`].
+    pub fn with_container_dir<P: AsRef<Path>>(
+        mut self,
+        dir: P,
+        options: &ContainerOptions,
+    ) -> Result<Self, RPMError> {
+        let mut converter = Converter::new(options);
+        let mut pending = vec![dir.as_ref().to_path_buf()];
+        while let Some(current) = pending.pop() {
+            let mut children = std::fs::read_dir(&current)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            children.sort();
+            for child in children {
+                let metadata = std::fs::symlink_metadata(&child)?;
+                let relative = child.strip_prefix(dir.as_ref()).unwrap_or(&child);
+                let path = self.file_name_policy.apply(&path_bytes(relative))?;
+                // rpm cannot store times before 1970
+                let mtime = Timestamp::try_from(metadata.modified()?).unwrap_or(Timestamp::EPOCH);
+                let file_type = metadata.file_type();
+                let content = if file_type.is_dir() {
+                    pending.push(child.clone());
+                    Content::Dir
+                } else if file_type.is_symlink() {
+                    let target = std::fs::read_link(&child)?;
+                    Content::Symlink(self.file_name_policy.apply(&path_bytes(&target))?)
+                } else if file_type.is_file() {
+                    Content::File {
+                        data: std::fs::read(&child)?,
+                        executable: is_executable(&metadata),
+                    }
+                } else {
+                    continue;
+                };
+                converter.add(&mut self, &path, content, mtime, None)?;
+            }
+        }
+        Ok(converter.finish(self))
+    }
+}
+
+/// Layer paths as hard links name them, which may differ in a leading `./`.
+#[cfg(feature = "tar")]
+fn link_key(path: &str) -> String {
+    path.trim_start_matches("./")
+        .trim_start_matches('/')
+        .to_string()
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().replace('\\', "/").into_bytes()
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(all(test, feature = "tar"))]
+mod test {
+    use super::*;
+
+    fn layer(files: &[(&str, &[u8], u32)]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut tar = tar::Builder::new(libflate::gzip::Encoder::new(Vec::new())?);
+        for (path, content, mode) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(*mode);
+            header.set_mtime(1_600_000_000);
+            if path.ends_with('/') {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_size(0);
+                tar.append_data(&mut header, path, std::io::empty())?;
+            } else if let Some(target) = path.strip_suffix(" ->") {
+                header.set_entry_type(tar::EntryType::Link);
+                header.set_size(0);
+                tar.append_link(&mut header, target, std::str::from_utf8(content)?)?;
+            } else {
+                header.set_size(content.len() as u64);
+                tar.append_data(&mut header, path, *content)?;
+            }
+        }
+        Ok(tar.into_inner()?.finish().into_result()?)
+    }
+
+    fn builder(name: &str) -> RPMBuilder {
+        RPMBuilder::new(name, "1.0.0", "MIT", "x86_64", "from a container")
+    }
+
+    #[test]
+    fn convert_layer() -> Result<(), Box<dyn std::error::Error>> {
+        let distro = builder("distro")
+            .with_container_layer(
+                layer(&[
+                    ("usr/", b"", 0o755),
+                    ("usr/bin/", b"", 0o755),
+                    ("usr/bin/shared", b"same", 0o755),
+                    ("usr/bin/tool", b"older", 0o755),
+                ])?
+                .as_slice(),
+                &ContainerOptions::new(),
+            )?
+            .build()?;
+        let options = ContainerOptions::new().installed(&[distro.metadata.header])?;
+
+        let raw = layer(&[
+            ("./usr/", b"", 0o755),
+            ("./usr/bin/", b"", 0o700),
+            ("./usr/bin/tool", b"newer", 0o4750),
+            ("./usr/bin/tool-alias ->", b"./usr/bin/tool", 0o4750),
+            ("./usr/bin/shared", b"same", 0o755),
+            ("./usr/share/.wh.removed", b"", 0o644),
+            ("./etc/resolv.conf", b"nameserver 10.0.0.1", 0o644),
+            ("./etc/tool.conf", b"key = value", 0o600),
+        ])?;
+        let pkg = builder("tool")
+            .with_container_layer(raw.as_slice(), &options)?
+            .build()?;
+        let header = &pkg.metadata.header;
+        let entries = header.get_file_entries()?;
+        let modes = entries
+            .iter()
+            .map(|entry| (entry.path.to_string_lossy().into_owned(), entry.mode))
+            .collect::<Vec<_>>();
+        // the directories belong to the distro package
+        assert_eq!(
+            vec![
+                ("/etc/tool.conf".to_string(), FileMode::regular(0o644)),
+                ("/usr/bin/shared".to_string(), FileMode::regular(0o755)),
+                ("/usr/bin/tool".to_string(), FileMode::regular(0o755)),
+                ("/usr/bin/tool-alias".to_string(), FileMode::regular(0o755)),
+            ],
+            modes
+        );
+        assert!(entries
+            .iter()
+            .all(|entry| entry.ownership.user() == "root" && entry.ownership.group() == "root"));
+        assert_eq!(
+            vec!["distro".to_string()],
+            header
+                .get_conflicts()?
+                .iter()
+                .map(|dep| dep.name().to_string())
+                .collect::<Vec<_>>()
+        );
+
+        let pkg = builder("tool")
+            .with_container_layer(raw.as_slice(), &options.clone().prefix("/opt/tool"))?
+            .build()?;
+        let paths = pkg.metadata.header.get_file_paths()?;
+        assert_eq!(6, paths.len());
+        assert!(paths.iter().all(|path| path.starts_with("/opt/tool")));
+        assert!(pkg.metadata.header.get_conflicts()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn convert_dir() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = crate::tests::TestDir::new("container");
+        std::fs::create_dir_all(dir.join("usr/bin"))?;
+        std::fs::create_dir_all(dir.join("proc/1"))?;
+        std::fs::write(dir.join("usr/bin/tool"), b"#!/bin/sh\n")?;
+        std::fs::write(dir.join("proc/1/status"), b"running")?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("tool", dir.join("usr/bin/alias"))?;
+
+        let pkg = builder("tool")
+            .with_container_dir(&dir, &ContainerOptions::new())?
+            .build()?;
+        let paths = pkg.metadata.header.get_file_paths()?;
+        assert!(paths.contains(&std::path::PathBuf::from("/usr/bin/tool")));
+        assert!(!paths.iter().any(|path| path.starts_with("/proc")));
+        #[cfg(unix)]
+        assert_eq!(4, paths.len());
+
+        Ok(())
+    }
+}
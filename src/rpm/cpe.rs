@@ -0,0 +1,231 @@
+//! CPE names identifying the product a package ships, for matching it with CVE data.
+//!
+//! rpm has no tag for them, they are stored as provides `cpe(<name>)` which scanners
+//! and `rpm -q --whatprovides` can look up. Both the CPE 2.3 formatted string and the
+//! older 2.2 URI form are accepted.
+//!
+//! ```rust
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let cpe = rpm::Cpe::parse("cpe:2.3:a:acme:foo:1.2.0:*:*:*:*:*:*:*")?;
+//! let pkg = rpm::RPMBuilder::new("foo", "1.2.0", "MIT", "x86_64", "A foo")
+//!     .vcs("git+https://git.example.com/foo.git#1a2b3c4d")
+//!     .build_url("https://ci.example.com/foo/builds/42")
+//!     .cpe(cpe.clone())
+//!     .build()?;
+//! assert_eq!(vec![cpe], pkg.metadata.header.get_cpes()?);
+//! assert_eq!(Some("acme"), pkg.metadata.header.get_cpes()?[0].vendor());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::headers::*;
+use super::RPMBuilder;
+
+/// A CPE name, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cpe {
+    name: String,
+    /// Part, vendor, product, version and the rest, as written.
+    components: Vec<String>,
+}
+
+fn invalid(reason: &'static str) -> RPMError {
+    RPMError::InvalidTagValue { tag: "cpe", reason }
+}
+
+impl Cpe {
+    /// Check `name` is a CPE 2.3 formatted string like `cpe:2.3:a:vendor:product:1.0:*:*:*:*:*:*:*`
+    /// or a CPE 2.2 URI like `cpe:/a:vendor:product:1.0`.
+    pub fn parse(name: &str) -> Result<Self, RPMError> {
+        if name
+            .chars()
+            .any(|c| c.is_whitespace() || c == '(' || c == ')')
+        {
+            return Err(invalid("contains whitespace or parentheses"));
+        }
+        let components = if let Some(formatted) = name.strip_prefix("cpe:2.3:") {
+            let components = split_formatted(formatted);
+            if components.len() != 11 {
+                return Err(invalid("a CPE 2.3 name has 13 components"));
+            }
+            components
+        } else if let Some(uri) = name.strip_prefix("cpe:/") {
+            let components = uri.split(':').map(str::to_string).collect::<Vec<_>>();
+            if components.len() > 7 {
+                return Err(invalid("a CPE 2.2 name has at most 7 components"));
+            }
+            components
+        } else {
+            return Err(invalid("expected cpe:2.3: or cpe:/ at the start"));
+        };
+        if !matches!(components[0].as_str(), "a" | "o" | "h" | "*" | "-" | "") {
+            return Err(invalid("the part has to be a, o or h"));
+        }
+        Ok(Cpe {
+            name: name.to_string(),
+            components,
+        })
+    }
+
+    fn component(&self, index: usize) -> Option<&str> {
+        match self.components.get(index).map(String::as_str) {
+            None | Some("") | Some("*") | Some("-") => None,
+            Some(value) => Some(value),
+        }
+    }
+
+    /// `a` for applications, `o` for operating systems and `h` for hardware.
+    pub fn part(&self) -> Option<&str> {
+        self.component(0)
+    }
+
+    pub fn vendor(&self) -> Option<&str> {
+        self.component(1)
+    }
+
+    pub fn product(&self) -> Option<&str> {
+        self.component(2)
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.component(3)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Split at colons which are not escaped with a backslash.
+fn split_formatted(formatted: &str) -> Vec<String> {
+    let mut components = vec![String::new()];
+    let mut escaped = false;
+    for c in formatted.chars() {
+        match c {
+            ':' if !escaped => components.push(String::new()),
+            _ => components.last_mut().expect("component").push(c),
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    components
+}
+
+impl fmt::Display for Cpe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl std::str::FromStr for Cpe {
+    type Err = RPMError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Cpe::parse(name)
+    }
+}
+
+impl RPMBuilder {
+    /// Identify the product the package ships as `cpe`, stored as provides `cpe(<name>)`.
+    ///
+    /// Packages split off while building, like `-debuginfo`, do not inherit it.
+    pub fn cpe(self, cpe: Cpe) -> Self {
+        self.provides(Dependency::any(format!("cpe({})", cpe)))
+    }
+}
+
+impl Header<IndexTag> {
+    /// The CPE names of the package, see [`RPMBuilder::cpe`].
+    pub fn get_cpes(&self) -> Result<Vec<Cpe>, RPMError> {
+        let provides = match self.get_provides() {
+            Err(RPMError::TagNotFound(_)) => return Ok(Vec::new()),
+            provides => provides?,
+        };
+        provides
+            .iter()
+            .filter_map(|dep| {
+                dep.dep_name
+                    .strip_prefix("cpe(")
+                    .and_then(|name| name.strip_suffix(')'))
+            })
+            .map(Cpe::parse)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() -> Result<(), RPMError> {
+        let formatted = Cpe::parse(r"cpe:2.3:a:acme:foo\:bar:1.2.0:*:*:*:*:*:x86_64:*")?;
+        assert_eq!(Some("a"), formatted.part());
+        assert_eq!(Some(r"foo\:bar"), formatted.product());
+        assert_eq!(Some("1.2.0"), formatted.version());
+
+        let uri = Cpe::parse("cpe:/o:acme:os")?;
+        assert_eq!((Some("acme"), None), (uri.vendor(), uri.version()));
+
+        for name in [
+            "cpe:2.3:a:acme:foo",
+            "cpe:/x:acme:foo",
+            "acme:foo",
+            "cpe:/a:acme:foo bar",
+            "cpe:/a:acme:foo:1:2:3:4:5",
+        ] {
+            assert!(
+                matches!(
+                    Cpe::parse(name),
+                    Err(RPMError::InvalidTagValue { tag: "cpe", .. })
+                ),
+                "{}",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn provenance() -> Result<(), Box<dyn std::error::Error>> {
+        let builder = || RPMBuilder::new("foo", "1.2.0", "MIT", "x86_64", "A foo");
+        let pkg = builder()
+            .vcs("git+https://git.example.com/foo.git#1a2b3c4d")
+            .build_url("https://ci.example.com/foo/builds/42")
+            .cpe("cpe:/a:acme:foo:1.2.0".parse()?)
+            .cpe(Cpe::parse("cpe:2.3:a:acme:foo:1.2.0:*:*:*:*:*:*:*")?)
+            .build()?;
+        let header = &pkg.metadata.header;
+        assert_eq!(
+            "git+https://git.example.com/foo.git#1a2b3c4d",
+            header.get_vcs()?
+        );
+        assert_eq!(
+            "https://ci.example.com/foo/builds/42",
+            header.get_build_url()?
+        );
+        assert_eq!(
+            vec![
+                "cpe:/a:acme:foo:1.2.0",
+                "cpe:2.3:a:acme:foo:1.2.0:*:*:*:*:*:*:*"
+            ],
+            header
+                .get_cpes()?
+                .iter()
+                .map(Cpe::as_str)
+                .collect::<Vec<_>>()
+        );
+
+        let plain = builder().build()?;
+        assert!(matches!(
+            plain.metadata.header.get_vcs(),
+            Err(RPMError::TagNotFound(_))
+        ));
+        assert!(plain.metadata.header.get_cpes()?.is_empty());
+        Ok(())
+    }
+}
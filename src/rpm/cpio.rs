@@ -0,0 +1,514 @@
+//! Reading and writing cpio archives in the `newc` format and the stripped variant of
+//! it, which package payloads consist of.
+//!
+//! Readers and writers stream: the content of an entry is read from the [`Reader`]
+//! after [`Reader::next_entry`] and written to the [`Writer`] after
+//! [`Writer::start_entry`], or in one go with [`Writer::append`].
+//!
+//! ```rust
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use rpm::cpio::{Entry, Reader, Writer};
+//! use std::io::Read;
+//!
+//! let mut writer = Writer::new(Vec::new());
+//! let entry = Entry {
+//!     mode: 0o100644,
+//!     ..Entry::new("./etc/motd")
+//! };
+//! writer.append(&entry, b"hello\n")?;
+//! let archive = writer.finish()?;
+//!
+//! let mut reader = Reader::new(archive.as_slice());
+//! while let Some(entry) = reader.next_entry()? {
+//!     let mut content = Vec::new();
+//!     reader.read_to_end(&mut content)?;
+//!     assert_eq!(b"./etc/motd", entry.name.as_slice());
+//!     assert_eq!(b"hello\n", content.as_slice());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{Read, Write};
+
+use crate::errors::*;
+
+pub const NEWC_MAGIC: &[u8; 6] = b"070701";
+/// `newc` with a checksum of the content, read like `newc` without checking it.
+pub const CRC_MAGIC: &[u8; 6] = b"070702";
+pub const STRIPPED_MAGIC: &[u8; 6] = b"07070X";
+/// Name of the entry ending an archive.
+pub const TRAILER: &[u8] = b"TRAILER!!!";
+
+const NEWC_HEADER_LEN: usize = 110;
+
+/// Number of bytes needed to pad `len` to a multiple of four.
+pub(crate) fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+/// The header of a `newc` entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entry {
+    /// The name as stored, payloads use names like `./usr/bin/foo`. Need not be UTF-8.
+    pub name: Vec<u8>,
+    pub ino: u32,
+    /// Complete mode including the file type bits.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u32,
+    pub mtime: u32,
+    /// Length of the content, for symlinks the content is the link target.
+    pub file_size: u32,
+    pub dev_major: u32,
+    pub dev_minor: u32,
+    pub rdev_major: u32,
+    pub rdev_minor: u32,
+}
+
+impl Entry {
+    /// An empty entry owned by root, with a link count of one.
+    pub fn new<N: Into<Vec<u8>>>(name: N) -> Self {
+        Entry {
+            name: name.into(),
+            nlink: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_trailer(&self) -> bool {
+        self.name == TRAILER
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(NEWC_HEADER_LEN + self.name.len() + 1);
+        header.extend_from_slice(NEWC_MAGIC);
+        let fields = [
+            self.ino,
+            self.mode,
+            self.uid,
+            self.gid,
+            self.nlink,
+            self.mtime,
+            self.file_size,
+            self.dev_major,
+            self.dev_minor,
+            self.rdev_major,
+            self.rdev_minor,
+            self.name.len() as u32 + 1,
+            // the checksum, always 0 for newc
+            0,
+        ];
+        for field in &fields {
+            header.extend_from_slice(format!("{:08x}", field).as_bytes());
+        }
+        header.extend_from_slice(&self.name);
+        header.push(0);
+        header
+    }
+}
+
+fn invalid(reason: &str) -> RPMError {
+    RPMError::Malformed {
+        what: "newc cpio entry",
+        reason: reason.to_string(),
+    }
+}
+
+/// Reads the entries of a `newc` archive up to the trailer.
+pub struct Reader<R> {
+    inner: R,
+    position: usize,
+    /// Bytes of content of the current entry not read yet.
+    remaining: u64,
+    done: bool,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Reader {
+            inner,
+            position: 0,
+            remaining: 0,
+            done: false,
+        }
+    }
+
+    /// Skip what is left of the current entry and read the next header, `None` after the
+    /// trailer.
+    pub fn next_entry(&mut self) -> Result<Option<Entry>, RPMError> {
+        let magic = match self.next_magic()? {
+            Some(magic) => magic,
+            None => return Ok(None),
+        };
+        if &magic == STRIPPED_MAGIC {
+            return Err(invalid("stripped entry, read with `StrippedReader`"));
+        }
+        let entry = self.read_header(magic)?;
+        if entry.is_trailer() {
+            self.done = true;
+            return Ok(None);
+        }
+        self.remaining = entry.file_size as u64;
+        Ok(Some(entry))
+    }
+
+    /// Give back the inner reader, positioned after the trailer if it was reached.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Finish the current entry and read the magic of the next one.
+    fn next_magic(&mut self) -> Result<Option<[u8; 6]>, RPMError> {
+        if self.done {
+            return Ok(None);
+        }
+        let skipped = std::io::copy(
+            &mut (&mut self.inner).take(self.remaining),
+            &mut std::io::sink(),
+        )?;
+        if skipped != self.remaining {
+            return Err(invalid("truncated content"));
+        }
+        self.position += self.remaining as usize;
+        self.remaining = 0;
+        self.skip_padding()?;
+        let mut magic = [0u8; 6];
+        self.read_exact(&mut magic)?;
+        Ok(Some(magic))
+    }
+
+    fn read_header(&mut self, magic: [u8; 6]) -> Result<Entry, RPMError> {
+        if &magic != NEWC_MAGIC && &magic != CRC_MAGIC {
+            return Err(invalid("bad magic"));
+        }
+        let mut header = [0u8; NEWC_HEADER_LEN - 6];
+        self.read_exact(&mut header)?;
+        let field = |index: usize| {
+            std::str::from_utf8(&header[index * 8..(index + 1) * 8])
+                .ok()
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| invalid("malformed header field"))
+        };
+        let name_size = field(11)? as usize;
+        let mut entry = Entry {
+            name: Vec::new(),
+            ino: field(0)?,
+            mode: field(1)?,
+            uid: field(2)?,
+            gid: field(3)?,
+            nlink: field(4)?,
+            mtime: field(5)?,
+            file_size: field(6)?,
+            dev_major: field(7)?,
+            dev_minor: field(8)?,
+            rdev_major: field(9)?,
+            rdev_minor: field(10)?,
+        };
+        (&mut self.inner)
+            .take(name_size as u64)
+            .read_to_end(&mut entry.name)?;
+        self.position += entry.name.len();
+        if entry.name.len() != name_size || entry.name.last() != Some(&0) {
+            return Err(invalid("name is not NUL terminated"));
+        }
+        // some writers pad the name with more NUL bytes
+        while entry.name.last() == Some(&0) {
+            entry.name.pop();
+        }
+        self.skip_padding()?;
+        Ok(entry)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), RPMError> {
+        self.inner.read_exact(buf)?;
+        self.position += buf.len();
+        Ok(())
+    }
+
+    fn skip_padding(&mut self) -> Result<(), RPMError> {
+        let mut skip = [0u8; 3];
+        self.read_exact(&mut skip[..padding(self.position)])
+    }
+}
+
+/// Reads the content of the current entry.
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(self.remaining as usize);
+        // some decompressors fail reads into empty buffers
+        if len == 0 {
+            return Ok(0);
+        }
+        let read = self.inner.read(&mut buf[..len])?;
+        self.remaining -= read as u64;
+        self.position += read;
+        Ok(read)
+    }
+}
+
+/// Reads the entries of a stripped archive, which only carry the index of the file in
+/// the package header.
+pub struct StrippedReader<R> {
+    reader: Reader<R>,
+    file_sizes: Vec<u64>,
+}
+
+impl<R: Read> StrippedReader<R> {
+    /// `file_sizes` are the sizes of the files in the package header, the entries do not
+    /// carry them.
+    pub fn new(inner: R, file_sizes: Vec<u64>) -> Self {
+        StrippedReader {
+            reader: Reader::new(inner),
+            file_sizes,
+        }
+    }
+
+    /// Skip what is left of the current entry and return the file index of the next one,
+    /// `None` after the trailer.
+    pub fn next_entry(&mut self) -> Result<Option<u32>, RPMError> {
+        let magic = match self.reader.next_magic()? {
+            Some(magic) => magic,
+            None => return Ok(None),
+        };
+        if &magic != STRIPPED_MAGIC {
+            // only the trailer is a regular entry
+            let entry = self.reader.read_header(magic)?;
+            if !entry.is_trailer() {
+                return Err(invalid("regular entry in a stripped archive"));
+            }
+            self.reader.done = true;
+            return Ok(None);
+        }
+        let mut file_index = [0u8; 8];
+        self.reader.read_exact(&mut file_index)?;
+        let file_index = std::str::from_utf8(&file_index)
+            .ok()
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| RPMError::Malformed {
+                what: "stripped cpio entry",
+                reason: "invalid file index".to_string(),
+            })?;
+        let size =
+            *self
+                .file_sizes
+                .get(file_index as usize)
+                .ok_or_else(|| RPMError::InvalidTagIndex {
+                    tag: crate::IndexTag::RPMTAG_FILESIZES.to_string(),
+                    index: file_index,
+                    bound: self.file_sizes.len() as u32,
+                })?;
+        self.reader.skip_padding()?;
+        self.reader.remaining = size;
+        Ok(Some(file_index))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+}
+
+/// Reads the content of the current entry.
+impl<R: Read> Read for StrippedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+/// Writes `newc` archives, or stripped ones when only using
+/// [`append_stripped`](Self::append_stripped).
+pub struct Writer<W> {
+    inner: W,
+    position: usize,
+    /// Bytes of content the current entry still needs.
+    remaining: u64,
+    /// Whether the current entry is a `newc` one.
+    newc: bool,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Writer {
+            inner,
+            position: 0,
+            remaining: 0,
+            newc: false,
+        }
+    }
+
+    /// Write the header of `entry`, its `file_size` bytes of content have to be written
+    /// next.
+    pub fn start_entry(&mut self, entry: &Entry) -> Result<(), RPMError> {
+        self.finish_entry()?;
+        self.write_padded(&entry.header())?;
+        self.remaining = entry.file_size as u64;
+        self.newc = true;
+        Ok(())
+    }
+
+    /// Write `entry` with `content`, whatever the `file_size` of `entry`.
+    pub fn append(&mut self, entry: &Entry, content: &[u8]) -> Result<(), RPMError> {
+        self.start_entry(&Entry {
+            file_size: content.len() as u32,
+            ..entry.clone()
+        })?;
+        self.write_all(content)?;
+        Ok(())
+    }
+
+    /// Write a stripped entry for the file at `file_index` in the package header.
+    pub fn append_stripped(&mut self, file_index: u32, content: &[u8]) -> Result<(), RPMError> {
+        self.finish_entry()?;
+        self.write_padded(format!("{}{:08x}", "07070X", file_index).as_bytes())?;
+        self.newc = false;
+        self.remaining = content.len() as u64;
+        self.write_all(content)?;
+        Ok(())
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Write the trailer and give back the inner writer.
+    pub fn finish(mut self) -> Result<W, RPMError> {
+        self.start_entry(&Entry::new(TRAILER))?;
+        self.finish_entry()?;
+        Ok(self.inner)
+    }
+
+    fn finish_entry(&mut self) -> Result<(), RPMError> {
+        if self.remaining != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} bytes of entry content missing", self.remaining),
+            )
+            .into());
+        }
+        let padding = padding(self.position);
+        if padding > 0 {
+            self.write_padded(&[])?;
+            // payloads were written with the `cpio` crate before, which flushes here;
+            // flushing alike keeps compressed payloads the same
+            if self.newc {
+                self.inner.flush()?;
+            }
+        }
+        self.newc = false;
+        Ok(())
+    }
+
+    /// Write `data` followed by the padding to the next multiple of four.
+    fn write_padded(&mut self, data: &[u8]) -> Result<(), RPMError> {
+        self.inner.write_all(data)?;
+        self.position += data.len();
+        let padding = padding(self.position);
+        self.inner.write_all(&[0u8; 3][..padding])?;
+        self.position += padding;
+        Ok(())
+    }
+}
+
+/// Writes the content of the current entry.
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.len() as u64 > self.remaining {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "more content than the size of the entry",
+            ));
+        }
+        let written = self.inner.write(buf)?;
+        self.remaining -= written as u64;
+        self.position += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let mut writer = Writer::new(Vec::new());
+        let file = Entry {
+            ino: 1,
+            mode: 0o100644,
+            mtime: 1_600_000_000,
+            ..Entry::new(b"./etc/\xffinvalid".to_vec())
+        };
+        writer.append(&file, b"hello")?;
+        let dir = Entry {
+            ino: 2,
+            mode: 0o040755,
+            nlink: 2,
+            ..Entry::new("./etc")
+        };
+        writer.start_entry(&dir)?;
+        writer.start_entry(&Entry {
+            file_size: 4,
+            ..Entry::new("./short")
+        })?;
+        writer.write_all(b"ab")?;
+        assert!(writer.write_all(b"cde").is_err());
+        assert!(writer.finish().is_err());
+
+        let mut writer = Writer::new(Vec::new());
+        writer.append(&file, b"hello")?;
+        writer.start_entry(&dir)?;
+        let archive = writer.finish()?;
+        assert_eq!(0, archive.len() % 4);
+
+        let mut reader = Reader::new(archive.as_slice());
+        let read = reader.next_entry()?.expect("file");
+        assert_eq!(
+            Entry {
+                file_size: 5,
+                ..file
+            },
+            read
+        );
+        // the content of the file is skipped
+        assert_eq!(Some(dir), reader.next_entry()?);
+        assert_eq!(None, reader.next_entry()?);
+        assert_eq!(None, reader.next_entry()?);
+        assert!(reader.into_inner().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn stripped() -> Result<(), Box<dyn std::error::Error>> {
+        let mut writer = Writer::new(Vec::new());
+        writer.append_stripped(0, b"first")?;
+        writer.append_stripped(1, b"")?;
+        writer.append_stripped(2, b"third file")?;
+        let archive = writer.finish()?;
+
+        let mut reader = StrippedReader::new(archive.as_slice(), vec![5, 0, 10]);
+        assert_eq!(Some(0), reader.next_entry()?);
+        assert_eq!(Some(1), reader.next_entry()?);
+        assert_eq!(Some(2), reader.next_entry()?);
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+        assert_eq!("third file", content);
+        assert_eq!(None, reader.next_entry()?);
+
+        let mut reader = StrippedReader::new(archive.as_slice(), vec![5]);
+        reader.next_entry()?;
+        assert!(reader.next_entry().is_err());
+        assert!(matches!(
+            Reader::new(archive.as_slice()).next_entry(),
+            Err(RPMError::Malformed {
+                what: "newc cpio entry",
+                ..
+            })
+        ));
+        Ok(())
+    }
+}
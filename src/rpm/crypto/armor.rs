@@ -0,0 +1,220 @@
+//! ASCII armor of OpenPGP data as defined by RFC 4880, section 6.
+
+use std::fmt::Write;
+
+use crate::errors::*;
+
+/// What an armored block contains, named in its `BEGIN` and `END` lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArmorKind {
+    PublicKey,
+    PrivateKey,
+    Signature,
+    Message,
+    /// Any other label, e.g. `PGP MESSAGE, PART 1/2`.
+    Other(String),
+}
+
+impl ArmorKind {
+    pub fn label(&self) -> &str {
+        match self {
+            ArmorKind::PublicKey => "PGP PUBLIC KEY BLOCK",
+            ArmorKind::PrivateKey => "PGP PRIVATE KEY BLOCK",
+            ArmorKind::Signature => "PGP SIGNATURE",
+            ArmorKind::Message => "PGP MESSAGE",
+            ArmorKind::Other(label) => label,
+        }
+    }
+
+    fn from_label(label: &str) -> Self {
+        match label {
+            "PGP PUBLIC KEY BLOCK" => ArmorKind::PublicKey,
+            "PGP PRIVATE KEY BLOCK" => ArmorKind::PrivateKey,
+            "PGP SIGNATURE" => ArmorKind::Signature,
+            "PGP MESSAGE" => ArmorKind::Message,
+            label => ArmorKind::Other(label.to_string()),
+        }
+    }
+}
+
+/// A decoded armored block, see [`dearmor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Armored {
+    pub kind: ArmorKind,
+    /// Armor headers like `Comment` or `Version`, in order.
+    pub headers: Vec<(String, String)>,
+    pub data: Vec<u8>,
+}
+
+/// Armor binary `data`, e.g. a key exported with `gpg --export`.
+///
+/// ```rust
+/// use rpm::crypto::{armor, dearmor, ArmorKind};
+/// let armored = armor(&ArmorKind::Signature, b"signature packet");
+/// assert!(armored.starts_with("-----BEGIN PGP SIGNATURE-----\n"));
+/// assert_eq!(b"signature packet".to_vec(), dearmor(&armored).unwrap().data);
+/// ```
+pub fn armor(kind: &ArmorKind, data: &[u8]) -> String {
+    armor_with_headers(kind, &[], data)
+}
+
+/// Like [`armor`], adding armor headers such as `("Comment", "...")`.
+pub fn armor_with_headers(kind: &ArmorKind, headers: &[(&str, &str)], data: &[u8]) -> String {
+    let encoded = base64::encode(data);
+    let mut armored = String::with_capacity(encoded.len() + encoded.len() / 64 + 128);
+    let _ = writeln!(armored, "-----BEGIN {}-----", kind.label());
+    for (key, value) in headers {
+        let _ = writeln!(armored, "{}: {}", key, value);
+    }
+    armored.push('\n');
+    // base64 is ascii, so lines can be split at any byte
+    for line in encoded.as_bytes().chunks(64) {
+        armored.push_str(std::str::from_utf8(line).expect("base64 is ascii"));
+        armored.push('\n');
+    }
+    let crc = crc24(data).to_be_bytes();
+    let _ = writeln!(armored, "={}", base64::encode(&crc[1..]));
+    let _ = writeln!(armored, "-----END {}-----", kind.label());
+    armored
+}
+
+/// Decode the first armored block in `text`, text around it is ignored.
+///
+/// The checksum is optional, if present it has to match.
+pub fn dearmor(text: &str) -> Result<Armored, RPMError> {
+    let invalid = |reason: &str| RPMError::InvalidArmor(reason.to_string());
+    let mut lines = text.lines().map(str::trim_end);
+    let label = lines
+        .by_ref()
+        .find_map(|line| {
+            line.strip_prefix("-----BEGIN ")
+                .and_then(|line| line.strip_suffix("-----"))
+        })
+        .ok_or_else(|| invalid("no BEGIN line"))?;
+
+    let mut headers = Vec::new();
+    let mut body = String::new();
+    let mut in_headers = true;
+    let mut checksum = None;
+    let mut end = None;
+    for line in lines.by_ref() {
+        if let Some(end_label) = line
+            .strip_prefix("-----END ")
+            .and_then(|line| line.strip_suffix("-----"))
+        {
+            end = Some(end_label);
+            break;
+        }
+        if in_headers {
+            if line.is_empty() {
+                in_headers = false;
+                continue;
+            }
+            if let Some((key, value)) = line.split_once(": ") {
+                headers.push((key.to_string(), value.to_string()));
+                continue;
+            }
+            // tolerate armor without the empty line after the headers
+            in_headers = false;
+        }
+        match line.strip_prefix('=') {
+            Some(crc) if checksum.is_none() => checksum = Some(crc.trim().to_string()),
+            Some(_) => return Err(invalid("more than one checksum")),
+            None if checksum.is_some() => return Err(invalid("data after the checksum")),
+            None => body.push_str(line.trim()),
+        }
+    }
+    match end {
+        Some(end) if end == label => {}
+        Some(end) => return Err(invalid(&format!("BEGIN {} ends with END {}", label, end))),
+        None => return Err(invalid("no END line")),
+    }
+
+    let data = base64::decode(&body).map_err(|e| invalid(&e.to_string()))?;
+    if let Some(checksum) = checksum {
+        let expected = match base64::decode(&checksum) {
+            Ok(crc) if crc.len() == 3 => u32::from_be_bytes([0, crc[0], crc[1], crc[2]]),
+            _ => return Err(invalid(&format!("malformed checksum {:?}", checksum))),
+        };
+        let actual = crc24(&data);
+        if actual != expected {
+            return Err(invalid(&format!(
+                "checksum {:06x} does not match the data, which has {:06x}",
+                expected, actual
+            )));
+        }
+    }
+    Ok(Armored {
+        kind: ArmorKind::from_label(label),
+        headers,
+        data,
+    })
+}
+
+/// The CRC-24 checksum of armored data.
+pub fn crc24(data: &[u8]) -> u32 {
+    const INIT: u32 = 0x00B7_04CE;
+    const POLY: u32 = 0x0186_4CFB;
+    let mut crc = INIT;
+    for byte in data {
+        crc ^= (*byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x0100_0000 != 0 {
+                crc ^= POLY;
+            }
+        }
+    }
+    crc & 0x00FF_FFFF
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn armor_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let gpg_armored = std::fs::read_to_string("./test_assets/public_key.asc")?;
+        let key = dearmor(&gpg_armored)?;
+        assert_eq!(ArmorKind::PublicKey, key.kind);
+        // an old format public key packet with a two byte length
+        assert_eq!(0x99, key.data[0]);
+        // gpg writes the same lines and checksum
+        assert_eq!(gpg_armored, armor(&key.kind, &key.data));
+
+        let armored = armor_with_headers(&key.kind, &[("Comment", "test key")], &key.data);
+        let decoded = dearmor(&format!("text before\n{}text after", armored))?;
+        assert_eq!(
+            vec![("Comment".to_string(), "test key".to_string())],
+            decoded.headers
+        );
+        assert_eq!(key.data, decoded.data);
+        assert!(armored.lines().all(|line| line.len() <= 64));
+
+        let checksum = |armored: &str| {
+            armored
+                .lines()
+                .find(|line| line.starts_with('='))
+                .unwrap()
+                .to_string()
+        };
+        let corrupted =
+            armored.replace(&checksum(&armored), &checksum(&armor(&key.kind, b"other")));
+        assert!(matches!(
+            dearmor(&corrupted),
+            Err(RPMError::InvalidArmor(_))
+        ));
+        let mismatched =
+            armor(&ArmorKind::Signature, b"x").replace("END PGP SIGNATURE", "END PGP MESSAGE");
+        assert!(dearmor(&mismatched).is_err());
+        assert!(dearmor("no armor").is_err());
+
+        assert_eq!(0x00B7_04CE, crc24(b""));
+        assert_eq!(
+            ArmorKind::Other("PGP MESSAGE, PART 1/2".to_string()),
+            dearmor("-----BEGIN PGP MESSAGE, PART 1/2-----\n\nAA==\n-----END PGP MESSAGE, PART 1/2-----")?
+                .kind
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+//! Generating OpenPGP keys.
+
+use ::pgp::composed::{KeyType, SecretKeyParamsBuilder};
+use ::pgp::crypto::hash::HashAlgorithm;
+use ::pgp::types::SecretKeyTrait;
+
+use crate::errors::*;
+use crate::signature::pgp::{Signer, Verifier};
+
+/// User id of generated keys.
+pub const USER_ID: &str = "rpm signing key <rpm@localhost>";
+
+/// Ascii armored keys, as loaded by [`Signer::load_from_asc`] and
+/// [`Verifier::load_from_asc`].
+///
+/// The secret key is not protected by a passphrase.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let keys = rpm::crypto::generate_ed25519_keypair()?;
+/// let pkg = rpm::RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "")
+///     .build_and_sign(keys.signer()?)?;
+/// pkg.verify_signature(keys.verifier()?)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPair {
+    pub secret_key: String,
+    pub public_key: String,
+}
+
+impl KeyPair {
+    pub fn signer(&self) -> Result<Signer, RPMError> {
+        Signer::load_from_asc(&self.secret_key)
+    }
+
+    pub fn verifier(&self) -> Result<Verifier, RPMError> {
+        Verifier::load_from_asc(&self.public_key)
+    }
+}
+
+/// Generate an RSA key of `bits` size, at least 2048.
+///
+/// Generating large keys takes a while, especially in debug builds.
+pub fn generate_rsa_keypair(bits: u32) -> Result<KeyPair, RPMError> {
+    generate(KeyType::Rsa(bits))
+}
+
+/// Generate an Ed25519 key, which needs rpm 4.16 or later to verify.
+pub fn generate_ed25519_keypair() -> Result<KeyPair, RPMError> {
+    generate(KeyType::EdDSA)
+}
+
+fn generate(key_type: KeyType) -> Result<KeyPair, RPMError> {
+    let failed = |e: ::pgp::errors::Error| RPMError::KeyGenerationError(Box::new(e));
+    let params = SecretKeyParamsBuilder::default()
+        .key_type(key_type)
+        .can_sign(true)
+        .can_create_certificates(true)
+        .primary_user_id(USER_ID.to_string())
+        .preferred_hash_algorithms(vec![HashAlgorithm::SHA2_256].into())
+        .build()
+        .map_err(|e| RPMError::KeyGenerationError(e.into()))?;
+    let secret_key = params
+        .generate()
+        .map_err(failed)?
+        .sign(String::new)
+        .map_err(failed)?;
+    let public_key = secret_key
+        .public_key()
+        .sign(&secret_key, String::new)
+        .map_err(failed)?;
+    Ok(KeyPair {
+        secret_key: secret_key.to_armored_string(None).map_err(failed)?,
+        public_key: public_key.to_armored_string(None).map_err(failed)?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::signature::{Signing, Verifying};
+
+    #[test]
+    fn generate_keys() -> Result<(), Box<dyn std::error::Error>> {
+        let data = b"dfsdfjsd9ivnq320348934752312308205723900000580134850sdf";
+        for keys in [generate_ed25519_keypair()?, generate_rsa_keypair(2048)?] {
+            assert!(keys
+                .public_key
+                .starts_with("-----BEGIN PGP PUBLIC KEY BLOCK-----"));
+            let signature = keys.signer()?.sign(&data[..])?;
+            keys.verifier()?.verify(&data[..], &signature)?;
+            let other = generate_ed25519_keypair()?;
+            assert!(other.verifier()?.verify(&data[..], &signature).is_err());
+        }
+        assert!(matches!(
+            generate_rsa_keypair(1024),
+            Err(RPMError::KeyGenerationError(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,11 @@
+//! OpenPGP helpers for [`signature::pgp`](crate::signature::pgp) and its keys, so tests
+//! and tooling bootstrapping a repository need neither `gpg` nor an OpenPGP crate of
+//! their own.
+
+mod armor;
+pub use armor::*;
+
+#[cfg(feature = "signature-pgp")]
+mod keygen;
+#[cfg(feature = "signature-pgp")]
+pub use keygen::*;
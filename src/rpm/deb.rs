@@ -0,0 +1,337 @@
+//! Conversion of rpm packages into Debian `.deb` archives.
+//!
+//! The conversion is best effort, similar to what `alien` does:
+//! the metadata maps onto a `control` file, the shell scriptlets become
+//! maintainer scripts wrapped such that the rpm style numeric arguments
+//! are provided, and the payload is repacked into `data.tar.gz`.
+//!
+//! Dependencies are not converted, since rpm and Debian package names
+//! do not map onto each other in any reliable way.
+
+use std::io::Write;
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::payload;
+use super::{Diagnostic, Diagnostics, RPMPackage, Scriptlet};
+
+const DEBIAN_BINARY: &[u8] = b"2.0\n";
+
+/// Convert a parsed rpm into a `.deb` and write it to `out`.
+pub fn convert<W: Write>(pkg: &RPMPackage, out: &mut W) -> Result<(), RPMError> {
+    convert_with_diagnostics(pkg, out, &mut Diagnostics::new())
+}
+
+/// Like [`convert`], reporting the scriptlets that could not be converted to
+/// `diagnostics`.
+///
+/// Maintainer scripts are run by a shell, scriptlets for other interpreters like
+/// rpm's embedded `<lua>` are left out.
+pub fn convert_with_diagnostics<W: Write>(
+    pkg: &RPMPackage,
+    out: &mut W,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), RPMError> {
+    let header = &pkg.metadata.header;
+    let entries = pkg.payload_entries()?;
+    let mtime = header
+        .get_build_time()
+        .map_or(0, |time| u64::from(time.seconds()));
+
+    let installed_size: u64 = entries
+        .iter()
+        .zip(payload::hardlink_sources(&entries))
+        .filter(|(e, source)| !e.is_dir() && !e.is_symlink() && source.is_none())
+        .map(|(e, _)| e.content.len() as u64)
+        .sum();
+
+    let control = control_file(pkg, (installed_size + 1023) / 1024)?;
+
+    let mut control_tar = tar::Builder::new(libflate::gzip::Encoder::new(Vec::new())?);
+    append_file(
+        &mut control_tar,
+        "control",
+        0o644,
+        mtime,
+        control.as_bytes(),
+    )?;
+    for (scriptlet, name, args) in &[
+        (Scriptlet::PreInstall, "preinst", PREINST_ARGS),
+        (Scriptlet::PostInstall, "postinst", POSTINST_ARGS),
+        (Scriptlet::PreUninstall, "prerm", PRERM_ARGS),
+        (Scriptlet::PostUninstall, "postrm", POSTRM_ARGS),
+    ] {
+        let script = match header.get_scriptlet(*scriptlet)? {
+            Some(script) => script,
+            None => continue,
+        };
+        let interpreter = header
+            .get_scriptlet_interpreter(*scriptlet)?
+            .unwrap_or_default()
+            .join(" ");
+        if !is_shell(&interpreter) {
+            diagnostics.push(Diagnostic::UnconvertedScriptlet {
+                script: name,
+                interpreter,
+            });
+            continue;
+        }
+        let wrapped = wrap_script(&interpreter, script, args);
+        append_file(&mut control_tar, name, 0o755, mtime, wrapped.as_bytes())?;
+    }
+    let control_tar = control_tar.into_inner()?.finish().into_result()?;
+
+    let ownership = payload::file_ownership(header);
+    let mut data_tar = tar::Builder::new(libflate::gzip::Encoder::new(Vec::new())?);
+    payload::append_to_tar(&mut data_tar, &entries, |path| ownership.get(path).cloned())?;
+    let data_tar = data_tar.into_inner()?.finish().into_result()?;
+
+    out.write_all(b"!<arch>\n")?;
+    write_ar_member(out, "debian-binary", mtime, DEBIAN_BINARY)?;
+    write_ar_member(out, "control.tar.gz", mtime, &control_tar)?;
+    write_ar_member(out, "data.tar.gz", mtime, &data_tar)?;
+    Ok(())
+}
+
+/// Map an rpm architecture to the Debian name.
+pub fn debian_arch(rpm_arch: &str) -> &str {
+    match rpm_arch {
+        "noarch" => "all",
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "i386" | "i486" | "i586" | "i686" => "i386",
+        "armv7hl" | "armv7l" => "armhf",
+        "ppc64le" => "ppc64el",
+        other => other,
+    }
+}
+
+fn control_file(pkg: &RPMPackage, installed_size_kib: u64) -> Result<String, RPMError> {
+    let header = &pkg.metadata.header;
+    let name = header.get_name()?.to_lowercase().replace(
+        |c: char| !(c.is_ascii_alphanumeric() || "+-.".contains(c)),
+        "-",
+    );
+    let mut version = format!("{}-{}", header.get_version()?, header.get_release()?);
+    if let Ok(epoch) = header.get_epoch() {
+        if epoch > 0 {
+            version = format!("{}:{}", epoch, version);
+        }
+    }
+    let maintainer = header
+        .get_entry_string_data(IndexTag::RPMTAG_PACKAGER)
+        .or_else(|_| header.get_entry_string_data(IndexTag::RPMTAG_VENDOR))
+        .unwrap_or("unknown");
+    let summary = header.get_summary().unwrap_or_default();
+
+    let mut control = String::new();
+    control.push_str(&format!("Package: {}\n", name));
+    control.push_str(&format!("Version: {}\n", version));
+    control.push_str(&format!(
+        "Architecture: {}\n",
+        debian_arch(header.get_arch()?)
+    ));
+    control.push_str(&format!("Maintainer: {}\n", maintainer));
+    control.push_str(&format!("Installed-Size: {}\n", installed_size_kib));
+    if let Ok(url) = header.get_entry_string_data(IndexTag::RPMTAG_URL) {
+        control.push_str(&format!("Homepage: {}\n", url));
+    }
+    control.push_str(&format!(
+        "Description: {}\n",
+        summary.lines().next().unwrap_or("")
+    ));
+    // extended description lines are indented by one space, empty lines are a single dot
+    for line in header.get_description().unwrap_or_default().lines() {
+        if line.trim().is_empty() {
+            control.push_str(" .\n");
+        } else {
+            control.push_str(&format!(" {}\n", line));
+        }
+    }
+    Ok(control)
+}
+
+/// Translation of the Debian maintainer script arguments to the
+/// number of package instances rpm passes as `$1`.
+const PREINST_ARGS: &str = r#"  install) set -- 1 ;;
+  upgrade) set -- 2 ;;"#;
+const POSTINST_ARGS: &str = r#"  configure) if [ -z "$2" ]; then set -- 1; else set -- 2; fi ;;"#;
+const PRERM_ARGS: &str = r#"  remove) set -- 0 ;;
+  upgrade) set -- 1 ;;"#;
+const POSTRM_ARGS: &str = r#"  remove|purge) set -- 0 ;;
+  upgrade) set -- 1 ;;"#;
+
+/// Whether `interpreter`, the program and its arguments, is a shell the argument
+/// mapping of [`wrap_script`] can be prepended for.
+fn is_shell(interpreter: &str) -> bool {
+    let program = interpreter.split_whitespace().next().unwrap_or_default();
+    matches!(
+        program.rsplit('/').next(),
+        Some("sh" | "bash" | "dash" | "ksh" | "zsh")
+    )
+}
+
+fn wrap_script(interpreter: &str, script: &str, args: &str) -> String {
+    let shebang = format!("#!{}\n", interpreter);
+    format!(
+        "{}# converted from rpm scriptlet\ncase \"$1\" in\n{}\nesac\n{}\n",
+        shebang,
+        args,
+        script.strip_prefix(shebang.as_str()).unwrap_or(script)
+    )
+}
+
+fn append_file<W: Write>(
+    tar: &mut tar::Builder<W>,
+    path: &str,
+    mode: u32,
+    mtime: u64,
+    data: &[u8],
+) -> Result<(), RPMError> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_mode(mode);
+    header.set_mtime(mtime);
+    header.set_size(data.len() as u64);
+    header.set_username("root")?;
+    header.set_groupname("root")?;
+    tar.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+fn write_ar_member<W: Write>(
+    out: &mut W,
+    name: &str,
+    mtime: u64,
+    data: &[u8],
+) -> Result<(), RPMError> {
+    // fixed width 60 byte header, see ar(5)
+    writeln!(
+        out,
+        "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`",
+        name,
+        mtime,
+        0,
+        0,
+        "100644",
+        data.len()
+    )?;
+    out.write_all(data)?;
+    if data.len() % 2 != 0 {
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{RPMBuilder, RPMFileOptions};
+    use std::io::Read;
+    use std::str::FromStr;
+
+    #[test]
+    fn convert_lua_scriptlets() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "an awesome tool")
+            .pre_install_script("echo pre $1")
+            .script_interpreter(crate::Scriptlet::PreInstall, "/bin/bash")
+            .post_install_script("print('installed')")
+            .script_interpreter(crate::Scriptlet::PostInstall, crate::LUA_INTERPRETER)
+            .build()?;
+
+        let mut deb = Vec::new();
+        let mut diagnostics = Diagnostics::new();
+        convert_with_diagnostics(&pkg, &mut deb, &mut diagnostics)?;
+        assert_eq!(
+            vec![&Diagnostic::UnconvertedScriptlet {
+                script: "postinst",
+                interpreter: crate::LUA_INTERPRETER.to_string(),
+            }],
+            diagnostics.iter().collect::<Vec<_>>()
+        );
+
+        // the control tarball is the second ar member, behind the 2.0 of debian-binary
+        let offset = 8 + 60 + 4;
+        let header = std::str::from_utf8(&deb[offset..offset + 60])?;
+        assert!(header.starts_with("control.tar.gz"));
+        let size: usize = header[48..58].trim().parse()?;
+        let control_tar = libflate::gzip::Decoder::new(&deb[offset + 60..offset + 60 + size])?;
+        let mut scripts = Vec::new();
+        for entry in tar::Archive::new(control_tar).entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().to_string();
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            scripts.push((name, content));
+        }
+        assert_eq!(2, scripts.len());
+        assert_eq!("preinst", scripts[1].0);
+        assert!(scripts[1].1.starts_with("#!/bin/bash\n"));
+        assert!(scripts[1].1.ends_with("echo pre $1\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn convert_to_deb() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = RPMBuilder::new("Awesome_Tool", "1.0.0", "MIT", "x86_64", "an awesome tool")
+            .compression(crate::Compressor::from_str("gzip")?)
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/etc/awesome/config.toml"),
+            )?
+            .epoch(2)
+            .post_install_script("echo installed $1")
+            .build()?;
+
+        let mut deb = Vec::new();
+        convert(&pkg, &mut deb)?;
+        assert!(deb.starts_with(b"!<arch>\ndebian-binary   "));
+
+        // locate the control tarball by walking the ar members
+        let mut offset = 8;
+        let mut members = Vec::new();
+        while offset < deb.len() {
+            let header = std::str::from_utf8(&deb[offset..offset + 60])?;
+            let name = header[..16].trim().to_string();
+            let size: usize = header[48..58].trim().parse()?;
+            let data = deb[offset + 60..offset + 60 + size].to_vec();
+            members.push((name, data));
+            offset += 60 + size + size % 2;
+        }
+        let names: Vec<_> = members.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(
+            vec!["debian-binary", "control.tar.gz", "data.tar.gz"],
+            names
+        );
+
+        let control_tar = libflate::gzip::Decoder::new(members[1].1.as_slice())?;
+        let mut archive = tar::Archive::new(control_tar);
+        let mut control = String::new();
+        let mut postinst = String::new();
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            match entry.path()?.to_str().unwrap() {
+                "control" => entry.read_to_string(&mut control)?,
+                "postinst" => entry.read_to_string(&mut postinst)?,
+                _ => 0,
+            };
+        }
+        assert!(control.contains("Package: awesome-tool\n"));
+        assert!(control.contains("Version: 2:1.0.0-1\n"));
+        assert!(control.contains("Architecture: amd64\n"));
+        assert!(postinst.contains("echo installed $1"));
+
+        let data_tar = libflate::gzip::Decoder::new(members[2].1.as_slice())?;
+        let mut archive = tar::Archive::new(data_tar);
+        let paths = archive
+            .entries()?
+            .map(|e| Ok(e?.path()?.to_string_lossy().to_string()))
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        assert_eq!(
+            vec!["etc/", "etc/awesome/", "etc/awesome/config.toml"],
+            paths
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,329 @@
+//! Splitting debug information off into companion packages, like `find-debuginfo.sh`.
+//!
+//! The files are laid out the way gdb, dnf and debuginfod expect them:
+//!
+//! ```text
+//! /usr/lib/debug/<path>.debug                      debug file of <path>, -debuginfo
+//! /usr/lib/debug/.build-id/<xx>/<rest>.debug       link to the debug file, -debuginfo
+//! /usr/lib/.build-id/<xx>/<rest>                   link to <path>, main package
+//! /usr/src/debug/<name>-<version>-<release>.<arch> sources, -debugsource
+//! ```
+//!
+//! Unlike `debugedit`, the source paths recorded in the debug information are
+//! not rewritten, build in a directory matching the `-debugsource` location
+//! if debuggers should pick up the sources on their own.
+
+use std::path::Path;
+
+use crate::errors::*;
+
+use super::elf::{self, Elf};
+use super::{Dependency, FileMode, RPMBuilder, RPMFileOptions, Timestamp};
+
+/// Directory debug files are installed to.
+pub const DEBUG_DIR: &str = "/usr/lib/debug";
+
+/// Directory of the build-id links of the main package.
+pub const BUILD_ID_DIR: &str = "/usr/lib/.build-id";
+
+/// Directory sources of `-debugsource` packages are installed to.
+pub const SOURCE_DIR: &str = "/usr/src/debug";
+
+impl RPMBuilder {
+    /// Move the debug information and symbol tables of all ELF files added so far
+    /// into a `-debuginfo` package.
+    ///
+    /// The stripped files keep a `.gnu_debuglink` to their debug file, the main
+    /// package gains the `/usr/lib/.build-id` links and the debug package provides
+    /// `debuginfo(build-id)` for every file. Returns `None` as debug package if
+    /// no file carries debug information.
+    pub fn split_debuginfo(mut self) -> Result<(Self, Option<Self>), RPMError> {
+        let candidates = self
+            .files
+            .iter()
+            .filter(|(_, entry)| matches!(entry.mode, FileMode::Regular { .. }))
+            .filter_map(|(cpio_path, entry)| {
+                let content = entry.content.as_ref()?;
+                if !elf::is_elf(content) {
+                    return None;
+                }
+                Some((cpio_path.clone(), content.clone(), entry.modified_at))
+            })
+            .collect::<Vec<_>>();
+
+        let mut debuginfo: Option<RPMBuilder> = None;
+        for (cpio_path, content, modified_at) in candidates {
+            let path = cpio_path.trim_start_matches('.').to_string();
+            let unsupported = |reason| RPMError::UnsupportedElf {
+                path: path.clone(),
+                reason,
+            };
+            let elf = Elf::parse(&content).map_err(unsupported)?;
+            if !elf.has_debug_info() {
+                continue;
+            }
+            let build_id = elf
+                .build_id()
+                .ok_or_else(|| unsupported("no GNU build-id, link with --build-id"))?;
+            if build_id.len() < 4 {
+                return Err(unsupported("build-id too short"));
+            }
+            let file_name = Path::new(&path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let (stripped, debug) = elf
+                .strip_debug(&format!("{}.debug", file_name))
+                .map_err(unsupported)?;
+            self.replace_content(&cpio_path, stripped);
+
+            let mut package = match debuginfo.take() {
+                Some(package) => package,
+                None => self.debug_companion(
+                    "debuginfo",
+                    &format!("Debug information for package {}", self.name()),
+                )?,
+            };
+            let debug_path = format!("{}{}.debug", DEBUG_DIR, path);
+            package.add_data(
+                debug,
+                modified_at,
+                RPMFileOptions::new(debug_path.as_str())
+                    .mode(FileMode::regular(0o644))
+                    .into(),
+            )?;
+            let (prefix, rest) = build_id.split_at(2);
+            add_link(
+                &mut package,
+                &format!("{}/.build-id/{}/{}.debug", DEBUG_DIR, prefix, rest),
+                &debug_path,
+                modified_at,
+            )?;
+            debuginfo =
+                Some(package.provides(Dependency::eq("debuginfo(build-id)", build_id.as_str())));
+
+            self.add_build_id_link(&build_id, &path, modified_at)?;
+        }
+        Ok((self, debuginfo))
+    }
+
+    /// Add the `/usr/lib/.build-id` links and a `debuginfo(build-id)` provide for every ELF
+    /// file with a GNU build-id when building, without splitting off the debug information.
+    ///
+    /// This is what debuginfod clients and `dnf debuginfo-install` look for.
+    pub fn build_id_links(mut self, enabled: bool) -> Self {
+        self.build_id_links = enabled;
+        self
+    }
+
+    pub(crate) fn apply_build_id_links(&mut self) -> Result<(), RPMError> {
+        if !self.build_id_links {
+            return Ok(());
+        }
+        let found = self
+            .files
+            .iter()
+            .filter(|(_, entry)| matches!(entry.mode, FileMode::Regular { .. }))
+            .filter_map(|(cpio_path, entry)| {
+                let elf = Elf::parse(entry.content.as_ref()?).ok()?;
+                let build_id = elf.build_id().filter(|id| id.len() >= 4)?;
+                Some((
+                    build_id,
+                    cpio_path.trim_start_matches('.').to_string(),
+                    entry.modified_at,
+                ))
+            })
+            .collect::<Vec<_>>();
+        for (build_id, path, modified_at) in found {
+            self.add_build_id_link(&build_id, &path, modified_at)?;
+            self.provides
+                .push(Dependency::eq("debuginfo(build-id)", build_id.as_str()));
+        }
+        Ok(())
+    }
+
+    /// Link `/usr/lib/.build-id/<xx>/<rest>` to `path`, numbering the links
+    /// of identical files like rpm does.
+    fn add_build_id_link(
+        &mut self,
+        build_id: &str,
+        path: &str,
+        modified_at: Timestamp,
+    ) -> Result<(), RPMError> {
+        let (prefix, rest) = build_id.split_at(2);
+        let base = format!("{}/{}/{}", BUILD_ID_DIR, prefix, rest);
+        let mut link = base.clone();
+        for n in 1.. {
+            match self.files.get(&format!(".{}", link)) {
+                None => break,
+                // already linked, e.g. by splitting off the debug information
+                Some(entry) if entry.link == relative_link(&link, path) => return Ok(()),
+                Some(_) => link = format!("{}.{}", base, n),
+            }
+        }
+        add_link(self, &link, path, modified_at)
+    }
+
+    /// A `-debugsource` package with the sources of the package,
+    /// from `(source, path relative to the source directory)` pairs.
+    pub fn debugsource_package<P: AsRef<Path>>(
+        &self,
+        sources: &[(P, &str)],
+    ) -> Result<Self, RPMError> {
+        let mut package = self.debug_companion(
+            "debugsource",
+            &format!("Debug sources for package {}", self.name()),
+        )?;
+        let base = format!("{}/{}", SOURCE_DIR, self.nvra());
+        for (source, relative) in sources {
+            package = package.with_file(
+                source,
+                RPMFileOptions::new(format!("{}/{}", base, relative))
+                    .mode(FileMode::regular(0o644)),
+            )?;
+        }
+        Ok(package)
+    }
+
+    /// A [`companion`](Self::companion) for files below `/usr/lib/debug` and
+    /// `/usr/src/debug`, which are neither relocated nor get build-id links of their own.
+    fn debug_companion(&self, suffix: &str, desc: &str) -> Result<Self, RPMError> {
+        let mut package = self.companion(suffix, desc)?;
+        package.prefixes = Vec::new();
+        package.build_id_links = false;
+        Ok(package)
+    }
+}
+
+/// Add a symlink at `link` pointing to `target` relative to the directory of the link.
+fn add_link(
+    builder: &mut RPMBuilder,
+    link: &str,
+    target: &str,
+    modified_at: Timestamp,
+) -> Result<(), RPMError> {
+    let target = relative_link(link, target);
+    builder.add_data(
+        target.clone().into_bytes(),
+        modified_at,
+        RPMFileOptions::new(link)
+            .symlink(target)
+            .mode(FileMode::symlink(0o777))
+            .into(),
+    )
+}
+
+fn relative_link(link: &str, target: &str) -> String {
+    let directory = link.trim_start_matches('/').split('/').collect::<Vec<_>>();
+    let directory = &directory[..directory.len() - 1];
+    let target = target
+        .trim_start_matches('/')
+        .split('/')
+        .collect::<Vec<_>>();
+    let common = directory
+        .iter()
+        .zip(target.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut parts = vec![".."; directory.len() - common];
+    parts.extend(&target[common..]);
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::IndexTag;
+
+    #[test]
+    fn build_id_links_without_split() -> Result<(), Box<dyn std::error::Error>> {
+        let mut builder = RPMBuilder::new("awesome", "1.0.0", "MIT", "x86_64", "awesome");
+        for path in ["/usr/bin/awesome", "/usr/bin/awesome-copy"].iter() {
+            builder.add_data(
+                elf::sample(&[0x12, 0x34, 0x56]),
+                Timestamp::EPOCH,
+                RPMFileOptions::new(*path)
+                    .mode(FileMode::regular(0o755))
+                    .into(),
+            )?;
+        }
+        let pkg = builder.build_id_links(true).build()?;
+        let header = &pkg.metadata.header;
+        assert_eq!(
+            vec![
+                std::path::PathBuf::from("/usr/bin/awesome"),
+                std::path::PathBuf::from("/usr/bin/awesome-copy"),
+                std::path::PathBuf::from("/usr/lib/.build-id/12/3456"),
+                std::path::PathBuf::from("/usr/lib/.build-id/12/3456.1"),
+            ],
+            header.get_file_paths()?
+        );
+        assert!(header
+            .get_provides()?
+            .iter()
+            .any(|dep| dep.name() == "debuginfo(build-id)" && dep.version() == "123456"));
+        // the debug information itself stays in place
+        assert!(Elf::parse(&pkg.payload_entries()?[0].content)?.has_debug_info());
+        Ok(())
+    }
+
+    #[test]
+    fn split_into_debuginfo() -> Result<(), Box<dyn std::error::Error>> {
+        let mut builder = RPMBuilder::new("awesome", "1.0.0", "MIT", "x86_64", "awesome");
+        builder.add_data(
+            elf::sample(&[0xab, 0xcd, 0xef]),
+            Timestamp::EPOCH,
+            RPMFileOptions::new("/usr/bin/awesome")
+                .mode(FileMode::regular(0o755))
+                .into(),
+        )?;
+        let sources = builder.debugsource_package(&[("./test_assets/awesome.py", "src/main.c")])?;
+        let (builder, debuginfo) = builder.split_debuginfo()?;
+
+        let pkg = builder.build()?;
+        assert_eq!(
+            vec![
+                std::path::PathBuf::from("/usr/bin/awesome"),
+                std::path::PathBuf::from("/usr/lib/.build-id/ab/cdef")
+            ],
+            pkg.metadata.header.get_file_paths()?
+        );
+        let links = pkg
+            .metadata
+            .header
+            .get_entry_string_array_data(IndexTag::RPMTAG_FILELINKTOS)?;
+        assert_eq!("../../../bin/awesome", links[1]);
+        let stripped = &pkg.payload_entries()?[0].content;
+        assert!(!Elf::parse(stripped)?.has_debug_info());
+
+        let debuginfo = debuginfo
+            .expect("debug information was split off")
+            .build()?;
+        let header = &debuginfo.metadata.header;
+        assert_eq!("awesome-debuginfo", header.get_name()?);
+        assert_eq!(
+            vec![
+                std::path::PathBuf::from("/usr/lib/debug/.build-id/ab/cdef.debug"),
+                std::path::PathBuf::from("/usr/lib/debug/usr/bin/awesome.debug")
+            ],
+            header.get_file_paths()?
+        );
+        assert_eq!(
+            "../../usr/bin/awesome.debug",
+            header.get_entry_string_array_data(IndexTag::RPMTAG_FILELINKTOS)?[0]
+        );
+        assert!(header
+            .get_provides()?
+            .iter()
+            .any(|dep| dep.name() == "debuginfo(build-id)" && dep.version() == "abcdef"));
+
+        let sources = sources.build()?;
+        assert_eq!(
+            vec![std::path::PathBuf::from(
+                "/usr/src/debug/awesome-1.0.0-1.x86_64/src/main.c"
+            )],
+            sources.metadata.header.get_file_paths()?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+//! How much of a payload is shared with another one, to estimate delta sizes.
+
+use std::collections::HashSet;
+
+use sha2::Digest;
+
+/// How payloads are cut into chunks for [`DedupReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunking {
+    /// Chunks of the same size, only finds data at the same offsets.
+    Fixed(usize),
+    /// FastCDC, cutting where a rolling hash of the content matches, so data
+    /// still matches after insertions in front of it.
+    ContentDefined { min: usize, avg: usize, max: usize },
+}
+
+impl Chunking {
+    /// Content defined chunks of 2 to 64 KiB, 8 KiB on average.
+    pub fn fastcdc() -> Self {
+        Chunking::ContentDefined {
+            min: 2 * 1024,
+            avg: 8 * 1024,
+            max: 64 * 1024,
+        }
+    }
+
+    /// Cut `data` into chunks.
+    pub fn chunks<'a>(&self, data: &'a [u8]) -> Vec<&'a [u8]> {
+        match *self {
+            Chunking::Fixed(size) => data.chunks(size.max(1)).collect(),
+            Chunking::ContentDefined { min, avg, max } => {
+                let max = max.max(1);
+                let min = min.min(max);
+                let avg = avg.clamp(min, max);
+                let mut chunks = Vec::new();
+                let mut rest = data;
+                while !rest.is_empty() {
+                    let (chunk, tail) = rest.split_at(cut_point(rest, min, avg, max));
+                    chunks.push(chunk);
+                    rest = tail;
+                }
+                chunks
+            }
+        }
+    }
+}
+
+impl Default for Chunking {
+    fn default() -> Self {
+        Self::fastcdc()
+    }
+}
+
+/// Shared and unique bytes of a newer payload compared to an older one, see
+/// [`RPMPackage::dedup_against`](crate::RPMPackage::dedup_against).
+///
+/// `unique_bytes` is roughly what a delta has to carry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DedupReport {
+    pub chunking: Chunking,
+    pub old_size: u64,
+    pub new_size: u64,
+    pub old_chunks: usize,
+    pub new_chunks: usize,
+    /// Chunks of the new payload also in the old one.
+    pub shared_chunks: usize,
+    pub shared_bytes: u64,
+    pub unique_bytes: u64,
+}
+
+impl DedupReport {
+    /// Compare two uncompressed payloads.
+    pub fn between(old: &[u8], new: &[u8], chunking: Chunking) -> Self {
+        let old_chunks = chunking.chunks(old);
+        let known = old_chunks
+            .iter()
+            .map(sha2::Sha256::digest)
+            .collect::<HashSet<_>>();
+        let new_chunks = chunking.chunks(new);
+        let mut shared_chunks = 0;
+        let mut shared_bytes = 0;
+        for chunk in &new_chunks {
+            if known.contains(&sha2::Sha256::digest(chunk)) {
+                shared_chunks += 1;
+                shared_bytes += chunk.len() as u64;
+            }
+        }
+        DedupReport {
+            chunking,
+            old_size: old.len() as u64,
+            new_size: new.len() as u64,
+            old_chunks: old_chunks.len(),
+            new_chunks: new_chunks.len(),
+            shared_chunks,
+            shared_bytes,
+            unique_bytes: new.len() as u64 - shared_bytes,
+        }
+    }
+
+    /// Fraction of the new payload found in the old one, between 0 and 1.
+    pub fn shared_ratio(&self) -> f64 {
+        if self.new_size == 0 {
+            return 1.0;
+        }
+        self.shared_bytes as f64 / self.new_size as f64
+    }
+}
+
+/// Length of the next chunk of `data`, using normalized chunking: cutting is harder
+/// before `avg` and easier after it, so chunk sizes cluster around `avg`.
+fn cut_point(data: &[u8], min: usize, avg: usize, max: usize) -> usize {
+    if data.len() <= min {
+        return data.len();
+    }
+    let end = data.len().min(max);
+    let normal = end.min(avg);
+    let bits = (usize::BITS - avg.max(2).leading_zeros() - 1).clamp(2, 62);
+    // the gear hash mixes recent bytes into the high bits
+    let mask_small = !0u64 << (64 - (bits + 1));
+    let mask_large = !0u64 << (64 - (bits - 1));
+    let mut hash = 0u64;
+    for (i, byte) in data.iter().enumerate().take(end).skip(min) {
+        hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+        let mask = if i < normal { mask_small } else { mask_large };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// Random values of the gear hash, from splitmix64 so chunks are stable across versions.
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state = 0x6665_7a5f_6364_6321u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pseudo_random(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chunk_sizes() {
+        let data = pseudo_random(1024 * 1024, 1);
+        let chunking = Chunking::fastcdc();
+        let chunks = chunking.chunks(&data);
+        assert_eq!(data, chunks.concat());
+        assert!(chunks[..chunks.len() - 1]
+            .iter()
+            .all(|chunk| chunk.len() >= 2 * 1024 && chunk.len() <= 64 * 1024));
+        // around 8 KiB on average
+        assert!(chunks.len() > 1024 / 16 && chunks.len() < 1024 / 4);
+        assert_eq!(3, Chunking::Fixed(4).chunks(b"0123456789").len());
+        assert!(chunking.chunks(b"").is_empty());
+    }
+
+    #[test]
+    fn insertions_shift_fixed_chunks_only() {
+        let old = pseudo_random(512 * 1024, 2);
+        let mut new = b"inserted in front".to_vec();
+        new.extend_from_slice(&old);
+
+        let same = DedupReport::between(&old, &old, Chunking::fastcdc());
+        assert_eq!(old.len() as u64, same.shared_bytes);
+        assert_eq!(0, same.unique_bytes);
+
+        let fixed = DedupReport::between(&old, &new, Chunking::Fixed(8 * 1024));
+        assert_eq!(0, fixed.shared_chunks);
+        let cdc = DedupReport::between(&old, &new, Chunking::fastcdc());
+        assert!(cdc.shared_ratio() > 0.9, "{:?}", cdc);
+        assert_eq!(cdc.new_size, cdc.shared_bytes + cdc.unique_bytes);
+    }
+}
@@ -0,0 +1,591 @@
+//! Deltas turning one version of a package into the next, for mirrors short on bandwidth.
+//!
+//! The deltas are in a format of this crate, they are not deltarpms: `applydeltarpm`
+//! can not apply them, and only this crate can. `applydeltarpm` recompresses payloads
+//! with zlib and xz, which does not reproduce the payloads written by this crate.
+//!
+//! A delta starts with the lead and headers of the new package, so tools reading
+//! package metadata read it as the new package, followed by the instructions
+//! rebuilding the uncompressed payload from the old one. Name the files `.fzdelta`
+//! rather than `.drpm`, which tools expect to be deltarpms.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let open = |path: &str| -> Result<rpm::RPMPackage, rpm::RPMError> {
+//! #     rpm::RPMPackage::parse(&mut std::io::BufReader::new(std::fs::File::open(path)?))
+//! # };
+//! let old = open("foo-1.0.0-1.x86_64.rpm")?;
+//! let new = open("foo-1.0.1-1.x86_64.rpm")?;
+//! let delta = rpm::FezDelta::create(&old, &new)?;
+//! delta.write(&mut std::fs::File::create("foo-1.0.0-1_1.0.1-1.x86_64.fzdelta")?)?;
+//!
+//! // on the mirror
+//! let rebuilt = delta.apply(&old)?;
+//! // or on a host with the old version installed
+//! let rebuilt = delta.apply_installed(&old.metadata.header, "/")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use sha2::Digest;
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::cpio;
+use super::dedup::Chunking;
+use super::payload::CpioFormat;
+use super::{Compressor, FileMode, Header, RPMPackage, RPMPackageMetadata};
+
+const MAGIC: &[u8; 3] = b"FZD";
+
+/// Follows [`MAGIC`], bumped whenever the instructions change.
+const VERSION: u8 = b'2';
+
+/// Chunks matched between the payloads, smaller than for dedup analysis to find more.
+const CHUNKING: Chunking = Chunking::ContentDefined {
+    min: 256,
+    avg: 1024,
+    max: 16 * 1024,
+};
+
+/// A delta from an old package to a new one in the format of this crate, see the
+/// [module](self) documentation.
+#[derive(Debug, PartialEq)]
+pub struct FezDelta {
+    /// Lead and headers of the new package.
+    pub target: RPMPackageMetadata,
+    pub old_nevra: String,
+    pub new_nevra: String,
+    /// Lowercase hex sha256 of the uncompressed payload of the old package.
+    old_payload_sha256: String,
+    /// Lowercase hex sha256 of the whole new package.
+    new_sha256: String,
+    compressor: String,
+    /// Offsets in the new payload at which the compressor was flushed, they end
+    /// compressed blocks and have to be the same to reproduce the payload.
+    flushes: Vec<u64>,
+    /// Modification time in the gzip header of the new payload, gzip writes the
+    /// current time there. Zero for other compressors.
+    gzip_mtime: u32,
+    ops: Vec<DeltaOp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DeltaOp {
+    /// Bytes of the old payload.
+    Copy { offset: u64, len: u64 },
+    /// Bytes only in the new payload.
+    Add(Vec<u8>),
+}
+
+impl FezDelta {
+    /// Compute the delta from `old` to `new`.
+    ///
+    /// Fails if recompressing the payload of `new` does not give the same bytes, as
+    /// the delta could not be applied then. Packages built by this crate recompress fine.
+    pub fn create(old: &RPMPackage, new: &RPMPackage) -> Result<Self, RPMError> {
+        let compressor = new
+            .metadata
+            .header
+            .get_payload_compressor()
+            .unwrap_or("none")
+            .to_string();
+        let new_payload = new.uncompressed_payload()?;
+        let gzip_mtime = match new.content.get(4..8) {
+            Some(mtime) if compressor == "gzip" => {
+                u32::from_le_bytes([mtime[0], mtime[1], mtime[2], mtime[3]])
+            }
+            _ => 0,
+        };
+        let mut flushes = None;
+        for candidate in [newc_flushes(&new_payload), Vec::new()] {
+            if compress(&compressor, &new_payload, &candidate, gzip_mtime)? == new.content {
+                flushes = Some(candidate);
+                break;
+            }
+        }
+        let flushes = flushes.ok_or_else(|| {
+            RPMError::InvalidDelta(format!(
+                "recompressing the {} payload of {} gives different bytes",
+                compressor,
+                nevra(&new.metadata).unwrap_or_default()
+            ))
+        })?;
+        let old_payload = old.uncompressed_payload()?;
+
+        let mut target = Vec::new();
+        new.metadata.write(&mut target)?;
+        let mut new_sha256 = sha2::Sha256::new();
+        new_sha256.update(&target);
+        new_sha256.update(&new.content);
+
+        Ok(FezDelta {
+            target: RPMPackageMetadata::parse(&mut target.as_slice())?,
+            old_nevra: nevra(&old.metadata)?,
+            new_nevra: nevra(&new.metadata)?,
+            old_payload_sha256: hex::encode(sha2::Sha256::digest(&old_payload)),
+            new_sha256: hex::encode(new_sha256.finalize()),
+            compressor,
+            flushes,
+            gzip_mtime,
+            ops: diff(&old_payload, &new_payload),
+        })
+    }
+
+    /// Rebuild the new package from `old`, checking the result is identical to it and
+    /// its digests match, see [`RPMPackage::verify_digests`].
+    pub fn apply(&self, old: &RPMPackage) -> Result<RPMPackage, RPMError> {
+        let old_payload = old.uncompressed_payload()?;
+        if hex::encode(sha2::Sha256::digest(&old_payload)) != self.old_payload_sha256 {
+            return Err(RPMError::InvalidDelta(format!(
+                "delta applies to {}, not to {}",
+                self.old_nevra,
+                nevra(&old.metadata)?
+            )));
+        }
+        self.rebuild(&old_payload)
+    }
+
+    /// Like [`apply`](Self::apply) without the old package file, taking the old payload
+    /// from the files of the installed package below `root` instead. `old` is its
+    /// header, e.g. from the rpm database.
+    ///
+    /// Like [`create`](Self::create), this reproduces payloads written by this crate.
+    /// Files changed since they were installed make it fail.
+    pub fn apply_installed<P: AsRef<Path>>(
+        &self,
+        old: &Header<IndexTag>,
+        root: P,
+    ) -> Result<RPMPackage, RPMError> {
+        for format in [CpioFormat::Newc, CpioFormat::Stripped] {
+            let old_payload = installed_payload(old, root.as_ref(), format)?;
+            if hex::encode(sha2::Sha256::digest(&old_payload)) == self.old_payload_sha256 {
+                return self.rebuild(&old_payload);
+            }
+        }
+        Err(RPMError::InvalidDelta(format!(
+            "the files below {} differ from the payload of {}",
+            root.as_ref().display(),
+            self.old_nevra
+        )))
+    }
+
+    fn rebuild(&self, old_payload: &[u8]) -> Result<RPMPackage, RPMError> {
+        let mut payload = Vec::new();
+        for op in &self.ops {
+            match op {
+                DeltaOp::Copy { offset, len } => {
+                    let copied = usize::try_from(*offset)
+                        .ok()
+                        .zip(usize::try_from(*len).ok())
+                        .and_then(|(offset, len)| old_payload.get(offset..offset.checked_add(len)?))
+                        .ok_or_else(|| {
+                            RPMError::InvalidDelta(format!(
+                                "copy of {} bytes at {} is outside of the old payload",
+                                len, offset
+                            ))
+                        })?;
+                    payload.extend_from_slice(copied);
+                }
+                DeltaOp::Add(data) => payload.extend_from_slice(data),
+            }
+        }
+
+        let mut package = Vec::new();
+        self.target.write(&mut package)?;
+        package.extend_from_slice(&compress(
+            &self.compressor,
+            &payload,
+            &self.flushes,
+            self.gzip_mtime,
+        )?);
+        if hex::encode(sha2::Sha256::digest(&package)) != self.new_sha256 {
+            return Err(RPMError::InvalidDelta(format!(
+                "the rebuilt package differs from {}",
+                self.new_nevra
+            )));
+        }
+        let package = RPMPackage::parse(&mut package.as_slice())?;
+        package.verify_digests()?;
+        Ok(package)
+    }
+
+    /// Bytes of the new payload carried by the delta itself, before compressing it.
+    pub fn added_bytes(&self) -> u64 {
+        self.ops
+            .iter()
+            .map(|op| match op {
+                DeltaOp::Add(data) => data.len() as u64,
+                DeltaOp::Copy { .. } => 0,
+            })
+            .sum()
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W) -> Result<(), RPMError> {
+        self.target.write(out)?;
+        let mut body = zstd::stream::Encoder::new(out, 19)?;
+        body.write_all(MAGIC)?;
+        body.write_all(&[VERSION])?;
+        for text in [
+            &self.old_nevra,
+            &self.new_nevra,
+            &self.old_payload_sha256,
+            &self.new_sha256,
+            &self.compressor,
+        ] {
+            write_bytes(&mut body, text.as_bytes())?;
+        }
+        body.write_all(&(self.flushes.len() as u64).to_be_bytes())?;
+        for offset in &self.flushes {
+            body.write_all(&offset.to_be_bytes())?;
+        }
+        body.write_all(&self.gzip_mtime.to_be_bytes())?;
+        body.write_all(&(self.ops.len() as u64).to_be_bytes())?;
+        for op in &self.ops {
+            match op {
+                DeltaOp::Copy { offset, len } => {
+                    body.write_all(&[0])?;
+                    body.write_all(&offset.to_be_bytes())?;
+                    body.write_all(&len.to_be_bytes())?;
+                }
+                DeltaOp::Add(data) => {
+                    body.write_all(&[1])?;
+                    write_bytes(&mut body, data)?;
+                }
+            }
+        }
+        body.finish()?;
+        Ok(())
+    }
+
+    pub fn parse<R: std::io::BufRead>(input: &mut R) -> Result<Self, RPMError> {
+        let target = RPMPackageMetadata::parse(input)?;
+        let mut body = zstd::stream::Decoder::new(input)?;
+        let mut magic = [0; 4];
+        body.read_exact(&mut magic)?;
+        if magic[..3] != MAGIC[..] {
+            return Err(RPMError::InvalidDelta(
+                "not a delta of this crate".to_string(),
+            ));
+        }
+        if magic[3] != VERSION {
+            return Err(RPMError::InvalidDelta(format!(
+                "unsupported delta version {}",
+                magic[3] as char
+            )));
+        }
+        let mut text = || -> Result<String, RPMError> {
+            String::from_utf8(read_bytes(&mut body)?)
+                .map_err(|e| RPMError::InvalidDelta(e.to_string()))
+        };
+        let old_nevra = text()?;
+        let new_nevra = text()?;
+        let old_payload_sha256 = text()?;
+        let new_sha256 = text()?;
+        let compressor = text()?;
+        let mut flushes = Vec::new();
+        for _ in 0..read_u64(&mut body)? {
+            flushes.push(read_u64(&mut body)?);
+        }
+        let mut gzip_mtime = [0; 4];
+        body.read_exact(&mut gzip_mtime)?;
+        let count = read_u64(&mut body)?;
+        let mut ops = Vec::new();
+        for _ in 0..count {
+            let mut tag = [0];
+            body.read_exact(&mut tag)?;
+            ops.push(match tag[0] {
+                0 => DeltaOp::Copy {
+                    offset: read_u64(&mut body)?,
+                    len: read_u64(&mut body)?,
+                },
+                1 => DeltaOp::Add(read_bytes(&mut body)?),
+                tag => {
+                    return Err(RPMError::InvalidDelta(format!(
+                        "unknown instruction {}",
+                        tag
+                    )))
+                }
+            });
+        }
+        Ok(FezDelta {
+            target,
+            old_nevra,
+            new_nevra,
+            old_payload_sha256,
+            new_sha256,
+            compressor,
+            flushes,
+            gzip_mtime: u32::from_be_bytes(gzip_mtime),
+            ops,
+        })
+    }
+}
+
+/// Instructions building `new` from the chunks of `old`.
+fn diff(old: &[u8], new: &[u8]) -> Vec<DeltaOp> {
+    let mut known = HashMap::new();
+    let mut offset = 0;
+    for chunk in CHUNKING.chunks(old) {
+        known
+            .entry(sha2::Sha256::digest(chunk))
+            .or_insert(offset as u64);
+        offset += chunk.len();
+    }
+    let mut ops: Vec<DeltaOp> = Vec::new();
+    for chunk in CHUNKING.chunks(new) {
+        let len = chunk.len() as u64;
+        match (known.get(&sha2::Sha256::digest(chunk)), ops.last_mut()) {
+            (
+                Some(&offset),
+                Some(DeltaOp::Copy {
+                    offset: last,
+                    len: last_len,
+                }),
+            ) if *last + *last_len == offset => *last_len += len,
+            (Some(&offset), _) => ops.push(DeltaOp::Copy { offset, len }),
+            (None, Some(DeltaOp::Add(data))) => data.extend_from_slice(chunk),
+            (None, _) => ops.push(DeltaOp::Add(chunk.to_vec())),
+        }
+    }
+    ops
+}
+
+fn compress(
+    compressor: &str,
+    payload: &[u8],
+    flushes: &[u64],
+    gzip_mtime: u32,
+) -> Result<Vec<u8>, RPMError> {
+    let mut compressor: Compressor = compressor.parse()?;
+    let mut start = 0;
+    for &offset in flushes {
+        let end = usize::try_from(offset)
+            .ok()
+            .filter(|end| (start..=payload.len()).contains(end))
+            .ok_or_else(|| RPMError::InvalidDelta(format!("flush at {} out of order", offset)))?;
+        compressor.write_all(&payload[start..end])?;
+        compressor.flush()?;
+        start = end;
+    }
+    compressor.write_all(&payload[start..])?;
+    let is_gzip = matches!(compressor, Compressor::Gzip(_));
+    let mut compressed = compressor.finish_compression()?;
+    if is_gzip && compressed.len() >= 8 {
+        compressed[4..8].copy_from_slice(&gzip_mtime.to_le_bytes());
+    }
+    Ok(compressed)
+}
+
+/// Where writing a newc payload flushes the compressor: [`cpio::Writer`] flushes
+/// after the padding of entries that need some.
+fn newc_flushes(payload: &[u8]) -> Vec<u64> {
+    const HEADER_SIZE: usize = 110;
+    let align = |size: usize| (size + 3) & !3;
+    let field = |header: &[u8], index: usize| {
+        let hex = header.get(6 + index * 8..14 + index * 8)?;
+        usize::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+    };
+    let mut flushes = Vec::new();
+    let mut offset = 0;
+    while let Some(header) = payload.get(offset..offset + HEADER_SIZE) {
+        let (file_size, name_size) = match (field(header, 6), field(header, 11)) {
+            (Some(file_size), Some(name_size)) if header.starts_with(b"070701") => {
+                (file_size, name_size)
+            }
+            _ => break,
+        };
+        let header_size = align(HEADER_SIZE + name_size);
+        let end = offset + header_size + align(file_size);
+        if (header_size + file_size) % 4 != 0 {
+            flushes.push(end as u64);
+        }
+        if payload.get(offset + HEADER_SIZE..offset + HEADER_SIZE + name_size.saturating_sub(1))
+            == Some(b"TRAILER!!!".as_ref())
+        {
+            break;
+        }
+        offset = end;
+    }
+    flushes
+}
+
+/// The payload an installed package was written with, from its header and the files
+/// below `root`.
+fn installed_payload(
+    header: &Header<IndexTag>,
+    root: &Path,
+    format: CpioFormat,
+) -> Result<Vec<u8>, RPMError> {
+    let paths = header.get_file_paths()?;
+    let modes = header.get_entry_i16_array_data(IndexTag::RPMTAG_FILEMODES)?;
+    let mtimes = header.get_entry_i32_array_data(IndexTag::RPMTAG_FILEMTIMES)?;
+    let inodes = header
+        .get_entry_i32_array_data(IndexTag::RPMTAG_FILEINODES)
+        .unwrap_or_default();
+    let users = header
+        .get_entry_string_array_data(IndexTag::RPMTAG_FILEUSERNAME)
+        .unwrap_or_default();
+    let groups = header
+        .get_entry_string_array_data(IndexTag::RPMTAG_FILEGROUPNAME)
+        .unwrap_or_default();
+    let flags = header
+        .get_entry_i32_array_data(IndexTag::RPMTAG_FILEFLAGS)
+        .unwrap_or_default();
+
+    let mut archive = cpio::Writer::new(Vec::new());
+    for (index, path) in paths.iter().enumerate() {
+        if flags
+            .get(index)
+            .map_or(false, |flags| flags & RPMFILE_GHOST != 0)
+        {
+            continue;
+        }
+        let location = root.join(path.strip_prefix("/").unwrap_or(path));
+        let mode = modes.get(index).map_or(0, |mode| *mode as u16);
+        let content = match FileMode::from(mode) {
+            FileMode::Regular { .. } => std::fs::read(&location)?,
+            FileMode::SymbolicLink { .. } => std::fs::read_link(&location)?
+                .to_string_lossy()
+                .into_owned()
+                .into_bytes(),
+            _ => Vec::new(),
+        };
+        match format {
+            CpioFormat::Stripped => archive.append_stripped(index as u32, &content)?,
+            _ => {
+                let installed = std::fs::symlink_metadata(&location).ok();
+                let owner = |names: &[String], on_disk: fn(&std::fs::Metadata) -> u32| match (
+                    names.get(index),
+                    &installed,
+                ) {
+                    (Some(name), _) if name == "root" => 0,
+                    (_, Some(installed)) => on_disk(installed),
+                    _ => 0,
+                };
+                let header = cpio::Entry {
+                    ino: inodes
+                        .get(index)
+                        .map_or(index as u32 + 1, |ino| *ino as u32),
+                    mode: mode as u32,
+                    uid: owner(users, uid),
+                    gid: owner(groups, gid),
+                    mtime: mtimes.get(index).map_or(0, |mtime| *mtime as u32),
+                    ..cpio::Entry::new(format!(".{}", path.display()))
+                };
+                archive.append(&header, &content)?;
+            }
+        }
+    }
+    archive.finish()
+}
+
+#[cfg(unix)]
+fn uid(metadata: &std::fs::Metadata) -> u32 {
+    std::os::unix::fs::MetadataExt::uid(metadata)
+}
+
+#[cfg(unix)]
+fn gid(metadata: &std::fs::Metadata) -> u32 {
+    std::os::unix::fs::MetadataExt::gid(metadata)
+}
+
+#[cfg(not(unix))]
+fn uid(_: &std::fs::Metadata) -> u32 {
+    0
+}
+
+#[cfg(not(unix))]
+fn gid(_: &std::fs::Metadata) -> u32 {
+    0
+}
+
+fn nevra(metadata: &RPMPackageMetadata) -> Result<String, RPMError> {
+    let header = &metadata.header;
+    let (name, version, release, arch) = (
+        header.get_name()?,
+        header.get_version()?,
+        header.get_release()?,
+        header.get_arch()?,
+    );
+    Ok(match header.get_epoch().unwrap_or(0) {
+        0 => format!("{}-{}-{}.{}", name, version, release, arch),
+        epoch => format!("{}-{}:{}-{}.{}", name, epoch, version, release, arch),
+    })
+}
+
+fn write_bytes<W: Write>(out: &mut W, data: &[u8]) -> std::io::Result<()> {
+    out.write_all(&(data.len() as u64).to_be_bytes())?;
+    out.write_all(data)
+}
+
+fn read_u64<R: Read>(input: &mut R) -> Result<u64, RPMError> {
+    let mut buf = [0; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+fn read_bytes<R: Read>(input: &mut R) -> Result<Vec<u8>, RPMError> {
+    let len = read_u64(input)?;
+    let mut data = Vec::new();
+    // read through take, so a corrupt length fails at the end of the input instead
+    // of allocating it
+    input.take(len).read_to_end(&mut data)?;
+    if data.len() as u64 != len {
+        return Err(RPMError::InvalidDelta("truncated delta".to_string()));
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diff_copies_shared_chunks() {
+        let old = (0..64 * 1024u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect::<Vec<_>>();
+        let mut new = b"new bytes in front".to_vec();
+        new.extend_from_slice(&old[..40 * 1024]);
+        new.extend_from_slice(b"and in the middle");
+        new.extend_from_slice(&old[40 * 1024..]);
+
+        let ops = diff(&old, &new);
+        let mut rebuilt = Vec::new();
+        for op in &ops {
+            match op {
+                DeltaOp::Copy { offset, len } => {
+                    rebuilt.extend_from_slice(&old[*offset as usize..(*offset + *len) as usize])
+                }
+                DeltaOp::Add(data) => rebuilt.extend_from_slice(data),
+            }
+        }
+        assert_eq!(new, rebuilt);
+        let added: usize = ops
+            .iter()
+            .map(|op| match op {
+                DeltaOp::Add(data) => data.len(),
+                DeltaOp::Copy { .. } => 0,
+            })
+            .sum();
+        assert!(added < 8 * 1024, "{} bytes added", added);
+    }
+
+    #[test]
+    fn gzip_mtime_is_kept() -> Result<(), RPMError> {
+        let compressed = compress("gzip", b"content", &[], 1_600_000_000)?;
+        assert_eq!(1_600_000_000u32.to_le_bytes(), compressed[4..8]);
+        assert_eq!(
+            compressed,
+            compress("gzip", b"content", &[], 1_600_000_000)?
+        );
+        Ok(())
+    }
+}
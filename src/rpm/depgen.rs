@@ -0,0 +1,367 @@
+//! Dependencies derived from the payload, like rpmbuild's dependency generators.
+//!
+//! Every regular file of a package is handed to each registered [`DepGenerator`]
+//! when building, which may answer with additional provides and requires.
+//! [`ElfGenerator`] and [`ShebangGenerator`] cover what rpmbuild generates for
+//! binaries and scripts, generators for pkg-config files, python distributions
+//! or anything else can be added with [`RPMBuilder::dep_generator`].
+//!
+//! ```rust
+//! use rpm::{DepGenerator, Dependency, FileKind, GeneratedDependencies, PayloadFile};
+//!
+//! /// Provide `pkgconfig(<name>)` for every `.pc` file.
+//! struct PkgConfig;
+//!
+//! impl DepGenerator for PkgConfig {
+//!     fn generate(
+//!         &self,
+//!         file: &PayloadFile<'_>,
+//!         _content: &mut dyn std::io::Read,
+//!     ) -> Result<GeneratedDependencies, rpm::RPMError> {
+//!         let mut generated = GeneratedDependencies::default();
+//!         if let (FileKind::Data, Some(name)) = (file.kind, file.path.strip_suffix(".pc")) {
+//!             let name = name.rsplit('/').next().unwrap_or(name);
+//!             generated.provides.push(Dependency::any(format!("pkgconfig({})", name)));
+//!         }
+//!         Ok(generated)
+//!     }
+//! }
+//!
+//! let builder = rpm::RPMBuilder::new("awesome", "1.0.0", "MIT", "x86_64", "an awesome library")
+//!     .dep_generator(rpm::ElfGenerator)
+//!     .dep_generator(PkgConfig);
+//! ```
+
+use std::io::Read;
+use std::sync::Arc;
+
+use crate::errors::*;
+
+use super::elf::{self, Elf};
+use super::{Dependency, FileMode, RPMBuilder};
+
+/// What a payload file looks like, judged by its first bytes.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// An ELF binary, shared object or object file.
+    Elf,
+    /// A file starting with a `#!` line.
+    Script,
+    /// Anything else.
+    Data,
+}
+
+impl FileKind {
+    pub fn detect(content: &[u8]) -> Self {
+        if elf::is_elf(content) {
+            FileKind::Elf
+        } else if content.starts_with(b"#!") {
+            FileKind::Script
+        } else {
+            FileKind::Data
+        }
+    }
+}
+
+/// A regular file of the payload, as handed to a [`DepGenerator`].
+#[derive(Debug, Clone, Copy)]
+pub struct PayloadFile<'a> {
+    /// The absolute install path.
+    pub path: &'a str,
+    pub mode: FileMode,
+    pub kind: FileKind,
+}
+
+impl PayloadFile<'_> {
+    fn is_executable(&self) -> bool {
+        match self.mode {
+            FileMode::Regular { permissions } => permissions & 0o111 != 0,
+            _ => false,
+        }
+    }
+}
+
+/// What a [`DepGenerator`] found in a file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GeneratedDependencies {
+    pub provides: Vec<Dependency>,
+    pub requires: Vec<Dependency>,
+}
+
+/// Derives dependencies from the contents of payload files.
+///
+/// Generators are called once per regular file, `content` reads the whole file.
+/// Dependencies already declared on the builder or generated before are dropped.
+pub trait DepGenerator: Send + Sync {
+    fn generate(
+        &self,
+        file: &PayloadFile<'_>,
+        content: &mut dyn Read,
+    ) -> Result<GeneratedDependencies, RPMError>;
+}
+
+/// Provides the `SONAME` of executable shared libraries and requires the libraries
+/// executable ELF files link against, like rpm's `elfdeps`.
+///
+/// Names look like `libc.so.6()(64bit)` for 64 bit objects and `libc.so.6` for 32 bit
+/// ones, symbol versions are not looked at. Files that can not be parsed are skipped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ElfGenerator;
+
+impl DepGenerator for ElfGenerator {
+    fn generate(
+        &self,
+        file: &PayloadFile<'_>,
+        content: &mut dyn Read,
+    ) -> Result<GeneratedDependencies, RPMError> {
+        let mut generated = GeneratedDependencies::default();
+        if file.kind != FileKind::Elf || !file.is_executable() {
+            return Ok(generated);
+        }
+        let mut data = Vec::new();
+        content.read_to_end(&mut data)?;
+        let parsed = Elf::parse(&data).and_then(|elf| Ok((elf.is_64bit(), elf.dynamic()?)));
+        let (wide, dynamic) = match parsed {
+            Ok(parsed) => parsed,
+            Err(reason) => {
+                log::debug!("no dependencies generated for {}: {}", file.path, reason);
+                return Ok(generated);
+            }
+        };
+        if let Some(soname) = dynamic.soname {
+            generated.provides.push(elf_dependency(&soname, wide));
+        }
+        for needed in dynamic.needed {
+            generated.requires.push(elf_dependency(&needed, wide));
+        }
+        Ok(generated)
+    }
+}
+
+fn elf_dependency(library: &str, wide: bool) -> Dependency {
+    match wide {
+        true => Dependency::any(format!("{}()(64bit)", library)),
+        false => Dependency::any(library.to_string()),
+    }
+}
+
+/// Requires the interpreters named in the shebang lines of executable scripts.
+///
+/// Like rpmbuild's script generator, `#!/usr/bin/python3` adds `Requires: /usr/bin/python3`,
+/// such that scripts do not fail on minimal systems lacking the interpreter.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShebangGenerator;
+
+impl DepGenerator for ShebangGenerator {
+    fn generate(
+        &self,
+        file: &PayloadFile<'_>,
+        content: &mut dyn Read,
+    ) -> Result<GeneratedDependencies, RPMError> {
+        let mut generated = GeneratedDependencies::default();
+        if file.kind != FileKind::Script || !file.is_executable() {
+            return Ok(generated);
+        }
+        let mut data = Vec::new();
+        content.read_to_end(&mut data)?;
+        if let Some(interpreter) = shebang_interpreter(&data) {
+            generated.requires.push(Dependency::any(interpreter));
+        }
+        Ok(generated)
+    }
+}
+
+impl RPMBuilder {
+    /// Require the interpreters named in the shebang lines of executable scripts, off by default.
+    ///
+    /// Short for registering a [`ShebangGenerator`].
+    pub fn shebang_requires(mut self, enabled: bool) -> Self {
+        self.shebang_requires = enabled;
+        self
+    }
+
+    /// Run `generator` on every regular file of the package when building.
+    ///
+    /// Generators run in the order they were added. Subpackages defined with
+    /// [`subpackage`](Self::subpackage) run them on their own files.
+    pub fn dep_generator<G: DepGenerator + 'static>(mut self, generator: G) -> Self {
+        self.dep_generators.push(Arc::new(generator));
+        self
+    }
+
+    pub(crate) fn apply_dep_generators(&mut self) -> Result<(), RPMError> {
+        let mut generators = self.dep_generators.clone();
+        if self.shebang_requires {
+            generators.push(Arc::new(ShebangGenerator));
+        }
+        if generators.is_empty() {
+            return Ok(());
+        }
+        let mut generated = GeneratedDependencies::default();
+        for (cpio_path, entry) in self.files.iter() {
+            let content = match (entry.mode, entry.content.as_ref()) {
+                (FileMode::Regular { .. }, Some(content)) => content,
+                _ => continue,
+            };
+            let file = PayloadFile {
+                path: cpio_path.trim_start_matches('.'),
+                mode: entry.mode,
+                kind: FileKind::detect(content),
+            };
+            for generator in generators.iter() {
+                let found = generator.generate(&file, &mut &content[..])?;
+                generated.provides.extend(found.provides);
+                generated.requires.extend(found.requires);
+            }
+        }
+        for dep in generated.provides {
+            if !self.provides.contains(&dep) {
+                self.provides.push(dep);
+            }
+        }
+        for dep in generated.requires {
+            if !self.requires.iter().any(|known| known.name() == dep.name()) {
+                self.requires.push(dep);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The absolute interpreter path of a `#!` line, without arguments.
+pub(crate) fn shebang_interpreter(content: &[u8]) -> Option<String> {
+    let line = content.strip_prefix(b"#!")?;
+    let line = &line[..line.iter().position(|b| *b == b'\n').unwrap_or(line.len())];
+    let line = std::str::from_utf8(line).ok()?;
+    let interpreter = line.split_whitespace().next()?;
+    if interpreter.starts_with('/') {
+        Some(interpreter.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RPMFileOptions;
+
+    #[test]
+    fn interpreters() {
+        assert_eq!(
+            Some("/usr/bin/python3".to_string()),
+            shebang_interpreter(b"#!/usr/bin/python3\nprint()")
+        );
+        assert_eq!(
+            Some("/usr/bin/env".to_string()),
+            shebang_interpreter(b"#! /usr/bin/env bash -e\n")
+        );
+        assert_eq!(None, shebang_interpreter(b"#!bash\n"));
+        assert_eq!(None, shebang_interpreter(b"echo\n"));
+    }
+
+    #[test]
+    fn requires_for_executable_scripts() -> Result<(), Box<dyn std::error::Error>> {
+        let builder = || {
+            RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+                .with_file(
+                    "./test_assets/awesome.py",
+                    RPMFileOptions::new("/usr/bin/awesome").mode(FileMode::regular(0o755)),
+                )?
+                .with_file(
+                    "./test_assets/awesome.py",
+                    RPMFileOptions::new("/usr/share/awesome/example.py")
+                        .mode(FileMode::regular(0o644)),
+                )
+        };
+        let interpreter = shebang_interpreter(&std::fs::read("./test_assets/awesome.py")?).unwrap();
+
+        let requires = builder()?
+            .shebang_requires(true)
+            .build()?
+            .metadata
+            .header
+            .get_requires()?;
+        assert_eq!(
+            1,
+            requires
+                .iter()
+                .filter(|dep| dep.name() == interpreter)
+                .count()
+        );
+        let requires = builder()?.build()?.metadata.header.get_requires()?;
+        assert!(!requires.iter().any(|dep| dep.name() == interpreter));
+        Ok(())
+    }
+
+    struct Marker;
+
+    impl DepGenerator for Marker {
+        fn generate(
+            &self,
+            file: &PayloadFile<'_>,
+            content: &mut dyn Read,
+        ) -> Result<GeneratedDependencies, RPMError> {
+            let mut data = Vec::new();
+            content.read_to_end(&mut data)?;
+            Ok(GeneratedDependencies {
+                provides: vec![Dependency::eq(
+                    format!("marker({})", file.path),
+                    data.len().to_string(),
+                )],
+                requires: Vec::new(),
+            })
+        }
+    }
+
+    #[test]
+    fn elf_and_custom_generators() -> Result<(), Box<dyn std::error::Error>> {
+        let mut builder = RPMBuilder::new("awesome", "1.0.0", "MIT", "x86_64", "awesome");
+        let library = elf::sample(&[0x12, 0x34]);
+        builder.add_data(
+            library.clone(),
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/usr/lib64/libawesome.so.1")
+                .mode(FileMode::regular(0o755))
+                .into(),
+        )?;
+        let pkg = builder
+            .dep_generator(ElfGenerator)
+            .dep_generator(Marker)
+            .build()?;
+        let header = &pkg.metadata.header;
+
+        let provides = header.get_provides()?;
+        assert!(provides
+            .iter()
+            .any(|dep| dep.name() == "libawesome.so.1()(64bit)"));
+        assert!(provides
+            .iter()
+            .any(|dep| dep.name() == "marker(/usr/lib64/libawesome.so.1)"
+                && dep.version() == library.len().to_string()));
+        assert!(header
+            .get_requires()?
+            .iter()
+            .any(|dep| dep.name() == "libc.so.6()(64bit)"));
+        assert_eq!("libc.so.6", elf_dependency("libc.so.6", false).name());
+
+        // like elfdeps, files that are no valid ELF objects generate nothing
+        let mut builder = RPMBuilder::new("awesome", "1.0.0", "MIT", "x86_64", "awesome");
+        builder.add_data(
+            library[..library.len() / 2].to_vec(),
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/usr/lib64/libawesome.so.1")
+                .mode(FileMode::regular(0o755))
+                .into(),
+        )?;
+        let pkg = builder.dep_generator(ElfGenerator).build()?;
+        assert!(!pkg
+            .metadata
+            .header
+            .get_requires()?
+            .iter()
+            .any(|dep| dep.name().starts_with("libc.so")));
+        Ok(())
+    }
+}
@@ -0,0 +1,216 @@
+//! Non-fatal issues found while parsing or building packages.
+
+use std::fmt;
+
+use super::headers::Quirk;
+use super::messages::{Message, MessageCatalog};
+use super::resources::ResourceReport;
+
+/// A non-fatal issue, the package is usable but tools may want to point it out.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// A deviation from the current package format.
+    Quirk(Quirk),
+    /// `tag` only carries a digest rpm no longer relies on.
+    DeprecatedDigest {
+        tag: String,
+        algorithm: &'static str,
+    },
+    /// Non zero bytes in the padding behind the signature header.
+    SignaturePadding { len: usize },
+    /// A file rpm would reject or mishandle on install, written anyway as the builder
+    /// is not [`strict`](super::RPMBuilder::strict).
+    SpecViolation { path: String, reason: &'static str },
+    /// Bytes after the payload, see [`RPMPackage::trailing_data`](super::RPMPackage::trailing_data).
+    TrailingData { len: usize },
+    /// An ELF file for a machine type without rpm architecture, like a BPF object,
+    /// packaged as architecture independent by
+    /// [`build_per_arch`](super::RPMBuilder::build_per_arch).
+    UnknownMachine { path: String, machine: u16 },
+    /// A scriptlet for an interpreter other than a shell, e.g. `<lua>`, left out when
+    /// converting to a `.deb`.
+    UnconvertedScriptlet {
+        script: &'static str,
+        interpreter: String,
+    },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::Quirk(quirk) => quirk.fmt(f),
+            Diagnostic::DeprecatedDigest { tag, algorithm } => {
+                write!(f, "{} is only protected by {}", tag, algorithm)
+            }
+            Diagnostic::SignaturePadding { len } => {
+                write!(f, "{} bytes of signature padding are not zero", len)
+            }
+            Diagnostic::SpecViolation { path, reason } => {
+                write!(f, "{} violates the rpm format - {}", path, reason)
+            }
+            Diagnostic::TrailingData { len } => {
+                write!(f, "{} bytes of trailing data after the payload", len)
+            }
+            Diagnostic::UnknownMachine { path, machine } => write!(
+                f,
+                "{} is an ELF file for the machine type {} without rpm architecture, packaged as noarch",
+                path, machine
+            ),
+            Diagnostic::UnconvertedScriptlet {
+                script,
+                interpreter,
+            } => write!(
+                f,
+                "the {} scriptlet for {} is left out, only shell scriptlets are converted",
+                script, interpreter
+            ),
+        }
+    }
+}
+
+impl Diagnostic {
+    /// Stable identifier like `FEZ-W0003`, see [`Message::code`].
+    pub fn code(&self) -> &'static str {
+        self.message().code
+    }
+
+    /// The code and the arguments of the English text.
+    pub fn message(&self) -> Message {
+        let (code, args) = match self {
+            Diagnostic::Quirk(quirk) => match quirk {
+                Quirk::LeadVersion { major, minor } => (
+                    "FEZ-W0001",
+                    vec![("major", major.to_string()), ("minor", minor.to_string())],
+                ),
+                Quirk::LeadPackageType(package_type) => (
+                    "FEZ-W0002",
+                    vec![("package_type", package_type.to_string())],
+                ),
+                Quirk::LeadOs(os) => ("FEZ-W0003", vec![("os", os.to_string())]),
+                Quirk::MissingRegion { store_type } => {
+                    ("FEZ-W0004", vec![("store_type", store_type.to_string())])
+                }
+                Quirk::InvalidRegion { store_type, reason } => (
+                    "FEZ-W0005",
+                    vec![
+                        ("store_type", store_type.to_string()),
+                        ("reason", reason.to_string()),
+                    ],
+                ),
+                Quirk::UnknownTag {
+                    raw_tag,
+                    store_type,
+                } => (
+                    "FEZ-W0006",
+                    vec![
+                        ("raw_tag", raw_tag.to_string()),
+                        ("store_type", store_type.to_string()),
+                    ],
+                ),
+                Quirk::UnknownDataType {
+                    raw_tag,
+                    raw_data_type,
+                    store_type,
+                } => (
+                    "FEZ-W0007",
+                    vec![
+                        ("raw_tag", raw_tag.to_string()),
+                        ("raw_data_type", raw_data_type.to_string()),
+                        ("store_type", store_type.to_string()),
+                    ],
+                ),
+                Quirk::EntryOutOfBounds { tag } => ("FEZ-W0008", vec![("tag", tag.clone())]),
+            },
+            Diagnostic::DeprecatedDigest { tag, algorithm } => (
+                "FEZ-W0009",
+                vec![("tag", tag.clone()), ("algorithm", algorithm.to_string())],
+            ),
+            Diagnostic::SignaturePadding { len } => ("FEZ-W0010", vec![("len", len.to_string())]),
+            Diagnostic::SpecViolation { path, reason } => (
+                "FEZ-W0011",
+                vec![("path", path.clone()), ("reason", reason.to_string())],
+            ),
+            Diagnostic::TrailingData { len } => ("FEZ-W0012", vec![("len", len.to_string())]),
+            Diagnostic::UnknownMachine { path, machine } => (
+                "FEZ-W0013",
+                vec![("path", path.clone()), ("machine", machine.to_string())],
+            ),
+            Diagnostic::UnconvertedScriptlet {
+                script,
+                interpreter,
+            } => (
+                "FEZ-W0014",
+                vec![
+                    ("script", script.to_string()),
+                    ("interpreter", interpreter.clone()),
+                ],
+            ),
+        };
+        Message { code, args }
+    }
+
+    /// The text from `catalog`, or the English one if it has none.
+    pub fn localized(&self, catalog: &dyn MessageCatalog) -> String {
+        catalog
+            .render(&self.message())
+            .unwrap_or_else(|| self.to_string())
+    }
+}
+
+/// Collects [`Diagnostic`]s in the order they were found.
+///
+/// Pass one to [`RPMPackage::parse_with_options`](super::RPMPackage::parse_with_options)
+/// or [`RPMBuilder::build_with_diagnostics`](super::RPMBuilder::build_with_diagnostics),
+/// the same collector may be used for several packages.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+    /// See [`resources`](Self::resources).
+    pub(crate) resources: ResourceReport,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub(crate) fn append(&mut self, other: &mut Diagnostics) {
+        self.diagnostics.append(&mut other.diagnostics);
+        self.resources.merge(&other.resources);
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Diagnostic;
+    type IntoIter = std::vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diagnostics.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diagnostics.iter()
+    }
+}
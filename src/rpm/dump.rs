@@ -0,0 +1,364 @@
+//! Annotated byte-offset breakdown of packages, similar to `rpmdump`.
+
+use std::io::Write;
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::headers::*;
+use super::RPMPackage;
+
+/// Controls the output of [`RPMPackage::dump_structure`].
+#[derive(Debug, Clone)]
+pub struct DumpOptions {
+    hex: bool,
+    max_bytes: usize,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        DumpOptions {
+            hex: false,
+            max_bytes: 64,
+        }
+    }
+}
+
+impl DumpOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Print a hexdump of the lead, the index entries and the data of every entry.
+    pub fn hex(mut self, hex: bool) -> Self {
+        self.hex = hex;
+        self
+    }
+
+    /// Limit hexdumps to the first `bytes` of each part, `0` to dump everything.
+    /// Defaults to 64 bytes.
+    pub fn max_bytes(mut self, bytes: usize) -> Self {
+        self.max_bytes = bytes;
+        self
+    }
+}
+
+impl RPMPackage {
+    /// Print the structure of the package to `out`: lead, signature header, header and
+    /// payload with the offset of each part in the file.
+    ///
+    /// Headers are broken down into the region, the index entries and the
+    /// slices of the store each entry points to, including alignment padding.
+    pub fn dump_structure<W: Write>(
+        &self,
+        out: &mut W,
+        options: DumpOptions,
+    ) -> Result<(), RPMError> {
+        let mut lead = Vec::with_capacity(LEAD_SIZE);
+        self.metadata.lead.write(&mut lead)?;
+        dump_lead(out, &lead, &options)?;
+
+        let mut position = lead.len();
+        position += dump_header(
+            out,
+            "signature header",
+            position,
+            &self.metadata.signature,
+            &options,
+        )?;
+        let padding = (8 - self.metadata.signature.store.len() % 8) % 8;
+        if padding > 0 {
+            writeln!(out, "{:08x}  padding  {} bytes", position, padding)?;
+            position += padding;
+        }
+        position += dump_header(out, "header", position, &self.metadata.header, &options)?;
+
+        let compressor = self
+            .metadata
+            .header
+            .get_payload_compressor()
+            .unwrap_or("none");
+        writeln!(
+            out,
+            "{:08x}  payload  {} bytes, {} compressed",
+            position,
+            self.content.len(),
+            compressor
+        )?;
+        writeln!(out, "{:08x}  end", position + self.content.len())?;
+        Ok(())
+    }
+}
+
+fn dump_lead(out: &mut dyn Write, lead: &[u8], options: &DumpOptions) -> Result<(), RPMError> {
+    let u16_at = |offset: usize| u16::from_be_bytes([lead[offset], lead[offset + 1]]);
+    let name = lead[10..76]
+        .iter()
+        .take_while(|byte| **byte != 0)
+        .map(|byte| *byte as char)
+        .collect::<String>();
+    writeln!(out, "{:08x}  lead  {} bytes", 0, lead.len())?;
+    writeln!(
+        out,
+        "{:08x}    magic {}  version {}.{}  type {}  arch {}",
+        0,
+        hex::encode(&lead[0..4]),
+        lead[4],
+        lead[5],
+        u16_at(6),
+        u16_at(8)
+    )?;
+    writeln!(out, "{:08x}    name {:?}", 10, name)?;
+    writeln!(
+        out,
+        "{:08x}    os {}  signature type {}",
+        76,
+        u16_at(76),
+        u16_at(78)
+    )?;
+    hexdump(out, 0, lead, options)
+}
+
+/// Print `header` found at `start` of the file, returning its length.
+fn dump_header<T: Tag>(
+    out: &mut dyn Write,
+    name: &str,
+    start: usize,
+    header: &Header<T>,
+    options: &DumpOptions,
+) -> Result<usize, RPMError> {
+    let entries = &header.index_entries;
+    let store_start = start + 16 + entries.len() * 16;
+    writeln!(
+        out,
+        "{:08x}  {}  {} entries, {} bytes of data",
+        start,
+        name,
+        entries.len(),
+        header.store.len()
+    )?;
+    let mut intro = Vec::with_capacity(16);
+    IndexHeader::new(entries.len() as u32, header.store.len() as u32).append(&mut intro);
+    writeln!(
+        out,
+        "{:08x}    magic {}  version {}",
+        start,
+        hex::encode(&intro[0..3]),
+        intro[3]
+    )?;
+    match header.region() {
+        Ok(Some((region_entries, region_store))) => writeln!(
+            out,
+            "{:08x}    region {}  {} entries, {} bytes of data",
+            start, entries[0].tag, region_entries, region_store
+        )?,
+        Ok(None) => writeln!(out, "{:08x}    no region", start)?,
+        Err(err) => writeln!(out, "{:08x}    invalid region  {}", start, err)?,
+    }
+
+    for (index, entry) in entries.iter().enumerate() {
+        let position = start + 16 + index * 16;
+        writeln!(
+            out,
+            "{:08x}    [{}] {} ({})  {}  offset {}  count {}",
+            position,
+            index,
+            entry.tag,
+            entry.tag.to_u32().unwrap_or_default(),
+            entry.data,
+            entry.offset,
+            entry.num_items
+        )?;
+        if options.hex {
+            let mut bytes = Vec::with_capacity(16);
+            entry.append_index(&mut bytes);
+            hexdump(out, position, &bytes, options)?;
+        }
+    }
+
+    writeln!(out, "{:08x}    data", store_start)?;
+    let mut by_offset = entries.iter().collect::<Vec<_>>();
+    by_offset.sort_by_key(|entry| entry.offset);
+    let mut end = 0;
+    for entry in by_offset {
+        if entry.offset < 0 {
+            continue;
+        }
+        let offset = entry.offset as usize;
+        if offset > end {
+            writeln!(
+                out,
+                "{:08x}      +{}  padding  {} bytes",
+                store_start + end,
+                end,
+                offset - end
+            )?;
+        }
+        let len = entry.data.store_len();
+        writeln!(
+            out,
+            "{:08x}      +{}  {}  {} bytes  {}",
+            store_start + offset,
+            offset,
+            entry.tag,
+            len,
+            preview(&entry.data)
+        )?;
+        let slice = header.store.get(offset..offset + len).unwrap_or_default();
+        hexdump(out, store_start + offset, slice, options)?;
+        end = end.max(offset + len);
+    }
+    if header.store.len() > end {
+        writeln!(
+            out,
+            "{:08x}      +{}  padding  {} bytes",
+            store_start + end,
+            end,
+            header.store.len() - end
+        )?;
+    }
+    Ok(16 + entries.len() * 16 + header.store.len())
+}
+
+/// A short rendering of the values of an entry.
+pub(crate) fn preview(data: &IndexData) -> String {
+    fn list<V: std::fmt::Display>(values: &[V]) -> String {
+        let shown = values
+            .iter()
+            .take(4)
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        let more = if values.len() > shown.len() {
+            ", ..."
+        } else {
+            ""
+        };
+        format!("[{}{}]", shown.join(", "), more)
+    }
+    let truncate = |text: &str| {
+        let mut short = text.chars().take(40).collect::<String>();
+        if short.len() < text.len() {
+            short.push_str("...");
+        }
+        format!("{:?}", short)
+    };
+    match data {
+        IndexData::Null => String::new(),
+        IndexData::Char(values) | IndexData::Bin(values) => {
+            let shown = &values[..values.len().min(8)];
+            let more = if values.len() > shown.len() {
+                "..."
+            } else {
+                ""
+            };
+            format!("{}{}", hex::encode(shown), more)
+        }
+        IndexData::Int8(values) => list(values),
+        IndexData::Int16(values) => list(values),
+        IndexData::Int32(values) => list(values),
+        IndexData::Int64(values) => list(values),
+        IndexData::StringTag(value) => truncate(value),
+        IndexData::StringArray(values) | IndexData::I18NString(values) => match values.first() {
+            Some(first) if values.len() > 1 => {
+                format!("{} ... ({} strings)", truncate(first), values.len())
+            }
+            Some(first) => truncate(first),
+            None => String::new(),
+        },
+    }
+}
+
+/// Print `bytes` found at `start` of the file, 16 per line.
+fn hexdump(
+    out: &mut dyn Write,
+    start: usize,
+    bytes: &[u8],
+    options: &DumpOptions,
+) -> Result<(), RPMError> {
+    if !options.hex {
+        return Ok(());
+    }
+    let shown = match options.max_bytes {
+        0 => bytes,
+        max => &bytes[..bytes.len().min(max)],
+    };
+    for (line, chunk) in shown.chunks(16).enumerate() {
+        let hex = chunk
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ascii = chunk
+            .iter()
+            .map(|byte| match byte {
+                b' '..=b'~' => *byte as char,
+                _ => '.',
+            })
+            .collect::<String>();
+        writeln!(
+            out,
+            "{:08x}        {:<47}  |{}|",
+            start + line * 16,
+            hex,
+            ascii
+        )?;
+    }
+    if shown.len() < bytes.len() {
+        writeln!(
+            out,
+            "{:08x}        ... {} more bytes",
+            start + shown.len(),
+            bytes.len() - shown.len()
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Compressor, RPMBuilder};
+    use std::str::FromStr;
+
+    #[test]
+    fn dump_structure() -> Result<(), Box<dyn std::error::Error>> {
+        let package = RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "")
+            .compression(Compressor::from_str("gzip")?)
+            .build()?;
+        let mut out = Vec::new();
+        package.dump_structure(&mut out, DumpOptions::new())?;
+        let dump = String::from_utf8(out)?;
+        let lines = dump.lines().collect::<Vec<_>>();
+        assert_eq!("00000000  lead  96 bytes", lines[0]);
+        assert!(lines[1].starts_with("00000000    magic edabeedb  version 3.0  type 0"));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("00000060  signature header  ")));
+        assert!(lines
+            .iter()
+            .any(|line| line.contains("region RPMTAG_HEADERIMMUTABLE  ")));
+        assert!(lines
+            .iter()
+            .any(|line| line.contains("RPMTAG_NAME  4 bytes  \"foo\"")));
+
+        let mut written = Vec::new();
+        package.write(&mut written)?;
+        let end = format!("{:08x}  end", written.len());
+        assert_eq!(Some(&end.as_str()), lines.last());
+        let payload = format!(
+            "{:08x}  payload  {} bytes, gzip compressed",
+            written.len() - package.content.len(),
+            package.content.len()
+        );
+        assert!(lines.contains(&payload.as_str()));
+
+        let mut out = Vec::new();
+        package.dump_structure(&mut out, DumpOptions::new().hex(true).max_bytes(16))?;
+        let dump = String::from_utf8(out)?;
+        assert!(dump.contains(
+            "00000000        ed ab ee db 03 00 00 00 00 00 66 6f 6f 00 00 00  |..........foo...|"
+        ));
+        assert!(dump.contains("... 80 more bytes"));
+        Ok(())
+    }
+}
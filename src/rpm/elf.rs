@@ -0,0 +1,852 @@
+//! Minimal ELF inspection helpers.
+//!
+//! Only the handful of identification fields needed while packaging
+//! are looked at, plus enough of the section headers to find build-ids,
+//! library names and to move debug information into a separate file. This is not a general
+//! purpose ELF parser.
+
+use std::convert::TryFrom;
+
+/// ELF identification magic.
+pub(crate) const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+const EM_386: u16 = 3;
+const EM_PPC64: u16 = 21;
+const EM_S390: u16 = 22;
+const EM_ARM: u16 = 40;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+const EM_RISCV: u16 = 243;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_DYNAMIC: u32 = 6;
+const SHT_NOTE: u32 = 7;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_ALLOC: u64 = 0x2;
+const SHF_INFO_LINK: u64 = 0x40;
+
+const NT_GNU_BUILD_ID: u32 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_SONAME: u64 = 14;
+
+/// Largest section alignment honoured when laying out stripped and debug files.
+const MAX_ALIGN: u64 = 1 << 21;
+
+/// Check if the given bytes start with the ELF magic.
+pub(crate) fn is_elf(data: &[u8]) -> bool {
+    data.len() >= ELF_MAGIC.len() && data[..ELF_MAGIC.len()] == ELF_MAGIC
+}
+
+/// Read the `e_machine` field of an ELF file header.
+pub(crate) fn machine(data: &[u8]) -> Option<u16> {
+    if !is_elf(data) || data.len() < 20 {
+        return None;
+    }
+    let raw = [data[18], data[19]];
+    // EI_DATA, 1 is little endian, 2 is big endian
+    match data[5] {
+        1 => Some(u16::from_le_bytes(raw)),
+        2 => Some(u16::from_be_bytes(raw)),
+        _ => None,
+    }
+}
+
+/// Map the machine type of an ELF file to the matching rpm architecture name.
+pub(crate) fn rpm_arch(data: &[u8]) -> Option<&'static str> {
+    let little_endian = data.get(5) == Some(&1);
+    let arch = match machine(data)? {
+        EM_386 => "i686",
+        EM_PPC64 if little_endian => "ppc64le",
+        EM_PPC64 => "ppc64",
+        EM_S390 => "s390x",
+        EM_ARM => "armv7hl",
+        EM_X86_64 => "x86_64",
+        EM_AARCH64 => "aarch64",
+        EM_RISCV => "riscv64",
+        _ => return None,
+    };
+    Some(arch)
+}
+
+/// Word size and byte order of an ELF file, everything needed to read and write its fields.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    wide: bool,
+    little_endian: bool,
+}
+
+impl Layout {
+    /// Size of addresses and offsets.
+    fn word(self) -> usize {
+        if self.wide {
+            8
+        } else {
+            4
+        }
+    }
+
+    fn read(self, data: &[u8], offset: usize, size: usize) -> Result<u64, &'static str> {
+        let raw = slice(data, offset as u64, size as u64).ok_or("truncated ELF structure")?;
+        let mut value = 0u64;
+        for i in 0..size {
+            let byte = if self.little_endian {
+                raw[size - 1 - i]
+            } else {
+                raw[i]
+            };
+            value = (value << 8) | byte as u64;
+        }
+        Ok(value)
+    }
+
+    fn encode(self, value: u64, size: usize) -> Vec<u8> {
+        let bytes = value.to_le_bytes();
+        let mut raw = bytes[..size].to_vec();
+        if !self.little_endian {
+            raw.reverse();
+        }
+        raw
+    }
+
+    fn put(self, out: &mut Vec<u8>, value: u64, size: usize) {
+        out.extend(self.encode(value, size));
+    }
+
+    fn patch(self, out: &mut [u8], offset: usize, value: u64, size: usize) {
+        out[offset..offset + size].copy_from_slice(&self.encode(value, size));
+    }
+
+    /// Offsets of the ELF header fields that differ between 32 and 64 bit files.
+    fn header_offsets(self) -> HeaderOffsets {
+        if self.wide {
+            HeaderOffsets {
+                phoff: 32,
+                shoff: 40,
+                ehsize: 52,
+                phentsize: 54,
+                phnum: 56,
+                shentsize: 58,
+                shnum: 60,
+                shstrndx: 62,
+            }
+        } else {
+            HeaderOffsets {
+                phoff: 28,
+                shoff: 32,
+                ehsize: 40,
+                phentsize: 42,
+                phnum: 44,
+                shentsize: 46,
+                shnum: 48,
+                shstrndx: 50,
+            }
+        }
+    }
+
+    /// Section headers have the same field order for both word sizes.
+    fn write_section(self, out: &mut Vec<u8>, section: &Section, name_offset: u32) {
+        let word = self.word();
+        self.put(out, name_offset as u64, 4);
+        self.put(out, section.kind as u64, 4);
+        self.put(out, section.flags, word);
+        self.put(out, section.addr, word);
+        self.put(out, section.offset, word);
+        self.put(out, section.size, word);
+        self.put(out, section.link as u64, 4);
+        self.put(out, section.info as u64, 4);
+        self.put(out, section.align, word);
+        self.put(out, section.entsize, word);
+    }
+}
+
+struct HeaderOffsets {
+    phoff: usize,
+    shoff: usize,
+    ehsize: usize,
+    phentsize: usize,
+    phnum: usize,
+    shentsize: usize,
+    shnum: usize,
+    shstrndx: usize,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Section {
+    pub(crate) name: String,
+    name_offset: u32,
+    kind: u32,
+    flags: u64,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: u64,
+}
+
+/// What the dynamic section says about linking.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Dynamic {
+    pub(crate) soname: Option<String>,
+    pub(crate) needed: Vec<String>,
+}
+
+/// The section headers of an ELF file.
+pub(crate) struct Elf<'a> {
+    data: &'a [u8],
+    layout: Layout,
+    pub(crate) sections: Vec<Section>,
+    shstrndx: usize,
+}
+
+impl<'a> Elf<'a> {
+    pub(crate) fn parse(data: &'a [u8]) -> Result<Self, &'static str> {
+        if !is_elf(data) || data.len() < 16 {
+            return Err("not an ELF file");
+        }
+        let layout = Layout {
+            wide: match data[4] {
+                1 => false,
+                2 => true,
+                _ => return Err("unknown ELF class"),
+            },
+            little_endian: match data[5] {
+                1 => true,
+                2 => false,
+                _ => return Err("unknown ELF byte order"),
+            },
+        };
+        let word = layout.word();
+        let offsets = layout.header_offsets();
+        let shoff = layout.read(data, offsets.shoff, word)? as usize;
+        let shentsize = layout.read(data, offsets.shentsize, 2)? as usize;
+        let shnum = layout.read(data, offsets.shnum, 2)? as usize;
+        let shstrndx = layout.read(data, offsets.shstrndx, 2)? as usize;
+        if shoff == 0 || shnum == 0 {
+            return Err("no section headers");
+        }
+        if shentsize != 16 + 6 * word {
+            return Err("unexpected section header size");
+        }
+
+        let mut sections = Vec::with_capacity(shnum);
+        for index in 0..shnum {
+            let base = index
+                .checked_mul(shentsize)
+                .and_then(|offset| offset.checked_add(shoff))
+                .ok_or("section headers out of bounds")?;
+            let mut position = base;
+            let mut field = |size: usize| -> Result<u64, &'static str> {
+                let value = layout.read(data, position, size)?;
+                position += size;
+                Ok(value)
+            };
+            sections.push(Section {
+                name: String::new(),
+                name_offset: field(4)? as u32,
+                kind: field(4)? as u32,
+                flags: field(word)?,
+                addr: field(word)?,
+                offset: field(word)?,
+                size: field(word)?,
+                link: field(4)? as u32,
+                info: field(4)? as u32,
+                align: field(word)?,
+                entsize: field(word)?,
+            });
+        }
+        let mut elf = Elf {
+            data,
+            layout,
+            sections,
+            shstrndx,
+        };
+        let names = elf
+            .sections
+            .get(shstrndx)
+            .map(|section| elf.section_data(section))
+            .transpose()?
+            .ok_or("section name table out of bounds")?;
+        let names = names.to_vec();
+        for section in elf.sections.iter_mut() {
+            let start = section.name_offset as usize;
+            let name = names.get(start..).ok_or("section name out of bounds")?;
+            let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+            section.name = String::from_utf8_lossy(&name[..end]).to_string();
+        }
+        Ok(elf)
+    }
+
+    pub(crate) fn section_data(&self, section: &Section) -> Result<&'a [u8], &'static str> {
+        if section.kind == SHT_NOBITS {
+            return Ok(&[]);
+        }
+        slice(self.data, section.offset, section.size).ok_or("section out of bounds")
+    }
+
+    /// Size of the ELF header, at least the fields this module reads and patches.
+    fn header_size(&self) -> Result<usize, &'static str> {
+        let offsets = self.layout.header_offsets();
+        let ehsize = self.layout.read(self.data, offsets.ehsize, 2)? as usize;
+        if ehsize < offsets.shstrndx + 2 || ehsize > self.data.len() {
+            return Err("invalid ELF header size");
+        }
+        Ok(ehsize)
+    }
+
+    /// The GNU build-id as lowercase hex, if the file carries one.
+    pub(crate) fn build_id(&self) -> Option<String> {
+        for section in self.sections.iter().filter(|s| s.kind == SHT_NOTE) {
+            let data = self.section_data(section).ok()?;
+            let mut position = 0;
+            while position + 12 <= data.len() {
+                let namesz = self.layout.read(data, position, 4).ok()?;
+                let descsz = self.layout.read(data, position + 4, 4).ok()?;
+                let kind = self.layout.read(data, position + 8, 4).ok()? as u32;
+                let name_start = position + 12;
+                let desc_start = name_start.checked_add(padded(namesz as usize, 4)?)?;
+                let name = slice(data, name_start as u64, namesz)?;
+                let desc = slice(data, desc_start as u64, descsz)?;
+                if kind == NT_GNU_BUILD_ID && name == b"GNU\0" {
+                    return Some(hex::encode(desc));
+                }
+                position = desc_start.checked_add(padded(descsz as usize, 4)?)?;
+            }
+        }
+        None
+    }
+
+    pub(crate) fn is_64bit(&self) -> bool {
+        self.layout.wide
+    }
+
+    /// `DT_SONAME` and the `DT_NEEDED` entries, empty for statically linked files.
+    pub(crate) fn dynamic(&self) -> Result<Dynamic, &'static str> {
+        let mut dynamic = Dynamic::default();
+        let section = match self.sections.iter().find(|s| s.kind == SHT_DYNAMIC) {
+            Some(section) => section,
+            None => return Ok(dynamic),
+        };
+        let strings = self
+            .sections
+            .get(section.link as usize)
+            .ok_or("dynamic string table out of bounds")?;
+        let strings = self.section_data(strings)?;
+        let string = |offset: u64| -> Result<String, &'static str> {
+            let string = strings
+                .get(offset as usize..)
+                .ok_or("dynamic string out of bounds")?;
+            let end = string.iter().position(|b| *b == 0).unwrap_or(string.len());
+            Ok(String::from_utf8_lossy(&string[..end]).to_string())
+        };
+        let word = self.layout.word();
+        for entry in self.section_data(section)?.chunks_exact(2 * word) {
+            let value = self.layout.read(entry, word, word)?;
+            match self.layout.read(entry, 0, word)? {
+                DT_NULL => break,
+                DT_NEEDED => dynamic.needed.push(string(value)?),
+                DT_SONAME => dynamic.soname = Some(string(value)?),
+                _ => {}
+            }
+        }
+        Ok(dynamic)
+    }
+
+    pub(crate) fn has_debug_info(&self) -> bool {
+        self.sections.iter().any(|s| is_debug_section(&s.name))
+    }
+
+    /// Split into a binary without debug information and symbol table, carrying a
+    /// `.gnu_debuglink` to `debuglink`, and the matching debug file, like
+    /// `eu-strip -f` does.
+    ///
+    /// Only files with all debug sections behind the loaded ones are supported,
+    /// such that no section referenced by a symbol changes its index.
+    pub(crate) fn strip_debug(&self, debuglink: &str) -> Result<(Vec<u8>, Vec<u8>), &'static str> {
+        let moved = |section: &Section| {
+            is_debug_section(&section.name)
+                || section.name == ".symtab"
+                || section.name == ".strtab"
+        };
+        let last_alloc = self
+            .sections
+            .iter()
+            .rposition(|s| s.flags & SHF_ALLOC != 0)
+            .unwrap_or(0);
+        if self.sections.iter().enumerate().any(|(index, section)| {
+            moved(section) && (index <= last_alloc || index == self.shstrndx)
+        }) {
+            return Err("debug sections interleaved with loaded sections");
+        }
+
+        let debug = self.debug_file()?;
+
+        let layout = self.layout;
+        let word = layout.word();
+        let offsets = layout.header_offsets();
+        // everything up to the end of the loaded sections stays where it is
+        let mut prefix_end = self.header_size()? as u64;
+        let phoff = layout.read(self.data, offsets.phoff, word)?;
+        let phentsize = layout.read(self.data, offsets.phentsize, 2)?;
+        let phnum = layout.read(self.data, offsets.phnum, 2)?;
+        let out_of_bounds = "program headers out of bounds";
+        if phnum > 0 {
+            prefix_end = prefix_end.max(end(phoff, phnum * phentsize).ok_or(out_of_bounds)?);
+        }
+        for index in 0..phnum {
+            let base = end(phoff, index * phentsize)
+                .and_then(|base| usize::try_from(base).ok())
+                .ok_or(out_of_bounds)?;
+            let (offset, filesz) = if layout.wide {
+                (
+                    layout.read(self.data, base + 8, 8)?,
+                    layout.read(self.data, base + 32, 8)?,
+                )
+            } else {
+                (
+                    layout.read(self.data, base + 4, 4)?,
+                    layout.read(self.data, base + 16, 4)?,
+                )
+            };
+            prefix_end = prefix_end.max(end(offset, filesz).ok_or("segment out of bounds")?);
+        }
+        for section in self.sections.iter() {
+            if section.flags & SHF_ALLOC != 0 && section.kind != SHT_NOBITS {
+                prefix_end = prefix_end.max(section_end(section)?);
+            }
+        }
+        let mut out = slice(self.data, 0, prefix_end)
+            .ok_or("loaded segments out of bounds")?
+            .to_vec();
+
+        let mut names = vec![0u8];
+        let mut add_name = |name: &str| -> u32 {
+            if name.is_empty() {
+                return 0;
+            }
+            let offset = names.len() as u32;
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+            offset
+        };
+        let mut index_map = vec![None; self.sections.len()];
+        let mut kept = Vec::new();
+        for (index, section) in self.sections.iter().enumerate() {
+            if moved(section) {
+                continue;
+            }
+            index_map[index] = Some(kept.len() as u64);
+            let mut section = section.clone();
+            let name_offset = add_name(&section.name);
+            if index != 0
+                && index != self.shstrndx
+                && section.kind != SHT_NOBITS
+                && section.flags & SHF_ALLOC == 0
+                && section_end(&section)? > prefix_end
+            {
+                let data = self.section_data(&section)?;
+                align(&mut out, section.align)?;
+                section.offset = out.len() as u64;
+                out.extend_from_slice(data);
+            }
+            kept.push((section, name_offset));
+        }
+
+        let mut link = debuglink.as_bytes().to_vec();
+        link.push(0);
+        link.resize(padded(link.len(), 4).ok_or("debuglink too long")?, 0);
+        layout.put(&mut link, crc32(&debug) as u64, 4);
+        align(&mut out, 4)?;
+        let debuglink_section = Section {
+            name: ".gnu_debuglink".to_string(),
+            name_offset: add_name(".gnu_debuglink"),
+            kind: SHT_PROGBITS,
+            flags: 0,
+            addr: 0,
+            offset: out.len() as u64,
+            size: link.len() as u64,
+            link: 0,
+            info: 0,
+            align: 4,
+            entsize: 0,
+        };
+        out.extend(link);
+        kept.push((debuglink_section.clone(), debuglink_section.name_offset));
+
+        let shstrndx = index_map[self.shstrndx].ok_or("section name table removed")? as usize;
+        kept[shstrndx].0.offset = out.len() as u64;
+        kept[shstrndx].0.size = names.len() as u64;
+        out.extend_from_slice(&names);
+
+        align(&mut out, word as u64)?;
+        let shoff = out.len();
+        for (section, name_offset) in kept.iter_mut() {
+            section.link = index_map
+                .get(section.link as usize)
+                .copied()
+                .flatten()
+                .unwrap_or(0) as u32;
+            if section.flags & SHF_INFO_LINK != 0 {
+                section.info = index_map
+                    .get(section.info as usize)
+                    .copied()
+                    .flatten()
+                    .unwrap_or(0) as u32;
+            }
+            layout.write_section(&mut out, section, *name_offset);
+        }
+        layout.patch(&mut out, offsets.shoff, shoff as u64, word);
+        layout.patch(&mut out, offsets.shnum, kept.len() as u64, 2);
+        layout.patch(&mut out, offsets.shstrndx, shstrndx as u64, 2);
+        Ok((out, debug))
+    }
+
+    /// Keep all section headers, but only the contents debuggers are interested in.
+    fn debug_file(&self) -> Result<Vec<u8>, &'static str> {
+        let layout = self.layout;
+        let word = layout.word();
+        let offsets = layout.header_offsets();
+        let mut out = self.data[..self.header_size()?].to_vec();
+        // there is nothing to load in a debug file
+        layout.patch(&mut out, offsets.phoff, 0, word);
+        layout.patch(&mut out, offsets.phnum, 0, 2);
+
+        let mut sections = self.sections.clone();
+        for section in sections.iter_mut().skip(1) {
+            if section.flags & SHF_ALLOC != 0 && section.kind != SHT_NOTE {
+                section.kind = SHT_NOBITS;
+            }
+            if section.kind == SHT_NOBITS {
+                section.offset = out.len() as u64;
+                continue;
+            }
+            let data = self.section_data(section)?;
+            align(&mut out, section.align)?;
+            section.offset = out.len() as u64;
+            out.extend_from_slice(data);
+        }
+        align(&mut out, word as u64)?;
+        let shoff = out.len();
+        for section in sections.iter() {
+            layout.write_section(&mut out, section, section.name_offset);
+        }
+        layout.patch(&mut out, offsets.shoff, shoff as u64, word);
+        Ok(out)
+    }
+}
+
+fn is_debug_section(name: &str) -> bool {
+    name.starts_with(".debug_") || name.starts_with(".zdebug_")
+}
+
+/// `data[start..start + len]` for offsets and sizes read from the file.
+fn slice(data: &[u8], start: u64, len: u64) -> Option<&[u8]> {
+    let end = usize::try_from(end(start, len)?).ok()?;
+    data.get(usize::try_from(start).ok()?..end)
+}
+
+fn end(start: u64, len: u64) -> Option<u64> {
+    start.checked_add(len)
+}
+
+fn section_end(section: &Section) -> Result<u64, &'static str> {
+    end(section.offset, section.size).ok_or("section out of bounds")
+}
+
+fn padded(len: usize, alignment: usize) -> Option<usize> {
+    Some(len.checked_add(alignment - 1)? / alignment * alignment)
+}
+
+fn align(out: &mut Vec<u8>, alignment: u64) -> Result<(), &'static str> {
+    if alignment > MAX_ALIGN {
+        return Err("section alignment too large");
+    }
+    if alignment > 1 {
+        let len = padded(out.len(), alignment as usize).ok_or("section alignment too large")?;
+        out.resize(len, 0);
+    }
+    Ok(())
+}
+
+/// The CRC-32 used by `.gnu_debuglink`, the same one as zlib's.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Assemble a little endian 64 bit shared object `libawesome.so.1` linked against
+/// `libc.so.6`, with a build-id note, a `.text` section and some debug information.
+#[cfg(test)]
+pub(crate) fn sample(build_id: &[u8]) -> Vec<u8> {
+    let layout = Layout {
+        wide: true,
+        little_endian: true,
+    };
+    let mut names = vec![0u8];
+    let mut name = |name: &str| {
+        let offset = names.len() as u32;
+        names.extend_from_slice(name.as_bytes());
+        names.push(0);
+        offset
+    };
+    let section = |name: &str, kind, flags, link| Section {
+        name: name.to_string(),
+        name_offset: 0,
+        kind,
+        flags,
+        addr: 0,
+        offset: 0,
+        size: 0,
+        link,
+        info: 0,
+        align: 1,
+        entsize: 0,
+    };
+
+    let mut note = Vec::new();
+    for value in [4, build_id.len() as u32, NT_GNU_BUILD_ID].iter() {
+        note.extend_from_slice(&value.to_le_bytes());
+    }
+    note.extend_from_slice(b"GNU\0");
+    note.extend_from_slice(build_id);
+    note.resize(padded(note.len(), 4).unwrap(), 0);
+
+    let mut dynamic = Vec::new();
+    for (tag, value) in [(DT_NEEDED, 1), (DT_SONAME, 11), (DT_NULL, 0)].iter() {
+        layout.put(&mut dynamic, *tag, 8);
+        layout.put(&mut dynamic, *value, 8);
+    }
+
+    let contents: Vec<(Section, Vec<u8>)> = vec![
+        (section("", 0, 0, 0), Vec::new()),
+        (section(".note.gnu.build-id", SHT_NOTE, SHF_ALLOC, 0), note),
+        (
+            section(".text", SHT_PROGBITS, SHF_ALLOC | 0x4, 0),
+            vec![0xc3; 16],
+        ),
+        (
+            section(".dynstr", 3, SHF_ALLOC, 0),
+            b"\0libc.so.6\0libawesome.so.1\0".to_vec(),
+        ),
+        (
+            section(".dynamic", SHT_DYNAMIC, SHF_ALLOC | 0x1, 3),
+            dynamic,
+        ),
+        (section(".comment", SHT_PROGBITS, 0, 0), b"fez\0".to_vec()),
+        (
+            section(".debug_info", SHT_PROGBITS, 0, 0),
+            b"dwarf".to_vec(),
+        ),
+        (section(".symtab", 2, 0, 8), vec![0; 24]),
+        (section(".strtab", 3, 0, 0), vec![0]),
+        (section(".shstrtab", 3, 0, 0), Vec::new()),
+    ];
+
+    let mut out = vec![0u8; 64 + 56];
+    out[..4].copy_from_slice(&ELF_MAGIC);
+    out[4] = 2;
+    out[5] = 1;
+    out[6] = 1;
+    layout.patch(&mut out, 16, 3, 2);
+    layout.patch(&mut out, 18, EM_X86_64 as u64, 2);
+    layout.patch(&mut out, 20, 1, 4);
+    layout.patch(&mut out, 32, 64, 8);
+    layout.patch(&mut out, 52, 64, 2);
+    layout.patch(&mut out, 54, 56, 2);
+    layout.patch(&mut out, 56, 1, 2);
+    layout.patch(&mut out, 58, 64, 2);
+
+    let name_offsets = contents
+        .iter()
+        .map(|(section, _)| {
+            if section.name.is_empty() {
+                0
+            } else {
+                name(&section.name)
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut sections = Vec::new();
+    for (index, (mut section, data)) in contents.into_iter().enumerate() {
+        let data = if section.name == ".shstrtab" {
+            names.clone()
+        } else {
+            data
+        };
+        if index > 0 {
+            section.offset = out.len() as u64;
+            section.addr = if section.flags & SHF_ALLOC != 0 {
+                section.offset
+            } else {
+                0
+            };
+            section.size = data.len() as u64;
+            out.extend(data);
+        }
+        if section.name == ".dynamic" {
+            // a single segment with everything loaded
+            let mut phdr = Vec::new();
+            layout.put(&mut phdr, 1, 4);
+            layout.put(&mut phdr, 5, 4);
+            layout.put(&mut phdr, 0, 8);
+            layout.put(&mut phdr, 0, 8);
+            layout.put(&mut phdr, 0, 8);
+            layout.put(&mut phdr, out.len() as u64, 8);
+            layout.put(&mut phdr, out.len() as u64, 8);
+            layout.put(&mut phdr, 0x1000, 8);
+            out[64..120].copy_from_slice(&phdr);
+        }
+        sections.push(section);
+    }
+    align(&mut out, 8).unwrap();
+    let shoff = out.len();
+    for (section, name_offset) in sections.iter().zip(name_offsets) {
+        layout.write_section(&mut out, section, name_offset);
+    }
+    layout.patch(&mut out, 40, shoff as u64, 8);
+    layout.patch(&mut out, 60, sections.len() as u64, 2);
+    layout.patch(&mut out, 62, sections.len() as u64 - 1, 2);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn elf_machine_detection() {
+        let mut header = vec![0u8; 64];
+        header[..4].copy_from_slice(&ELF_MAGIC);
+        header[5] = 1;
+        header[18..20].copy_from_slice(&EM_X86_64.to_le_bytes());
+        assert!(is_elf(&header));
+        assert_eq!(Some("x86_64"), rpm_arch(&header));
+
+        header[5] = 2;
+        header[18..20].copy_from_slice(&EM_PPC64.to_be_bytes());
+        assert_eq!(Some("ppc64"), rpm_arch(&header));
+
+        assert!(!is_elf(b"#!/bin/sh\n"));
+        assert_eq!(None, rpm_arch(b"#!/bin/sh\n"));
+    }
+
+    #[test]
+    fn strip_debug_information() -> Result<(), Box<dyn std::error::Error>> {
+        let build_id = [0xab, 0xcd, 0xef, 0x01, 0x23];
+        let binary = sample(&build_id);
+        let elf = Elf::parse(&binary)?;
+        assert_eq!(Some("abcdef0123".to_string()), elf.build_id());
+        assert!(elf.has_debug_info());
+
+        let (stripped, debug) = elf.strip_debug("awesome.debug")?;
+        let stripped_elf = Elf::parse(&stripped)?;
+        let names = stripped_elf
+            .sections
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "",
+                ".note.gnu.build-id",
+                ".text",
+                ".dynstr",
+                ".dynamic",
+                ".comment",
+                ".shstrtab",
+                ".gnu_debuglink"
+            ],
+            names
+        );
+        assert!(!stripped_elf.has_debug_info());
+        assert_eq!(elf.build_id(), stripped_elf.build_id());
+        let link = stripped_elf.section_data(&stripped_elf.sections[7])?;
+        assert!(link.starts_with(b"awesome.debug\0"));
+        assert_eq!(&crc32(&debug).to_le_bytes(), &link[link.len() - 4..]);
+        // loaded content is untouched
+        let text = &elf.sections[2];
+        assert_eq!(elf.section_data(text)?, stripped_elf.section_data(text)?);
+
+        let debug_elf = Elf::parse(&debug)?;
+        assert_eq!(elf.sections.len(), debug_elf.sections.len());
+        assert!(debug_elf.has_debug_info());
+        assert_eq!(elf.build_id(), debug_elf.build_id());
+        assert_eq!(SHT_NOBITS, debug_elf.sections[2].kind);
+        assert_eq!(b"dwarf", debug_elf.section_data(&debug_elf.sections[6])?);
+
+        assert_eq!(0xcbf4_3926, crc32(b"123456789"));
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_headers() {
+        let layout = Layout {
+            wide: true,
+            little_endian: true,
+        };
+        let binary = sample(&[0x12, 0x34]);
+        let shoff = layout.read(&binary, 40, 8).unwrap() as usize;
+        let patched = |offset: usize, value: u64, size: usize| {
+            let mut data = binary.clone();
+            layout.patch(&mut data, offset, value, size);
+            data
+        };
+
+        assert!(Elf::parse(&binary[..20]).is_err());
+        assert!(Elf::parse(&binary[..binary.len() - 1]).is_err());
+        assert!(Elf::parse(&patched(40, u64::MAX - 8, 8)).is_err());
+        // the size of the section name table runs past the end of the address space
+        assert!(Elf::parse(&patched(shoff + 9 * 64 + 32, u64::MAX, 8)).is_err());
+
+        let data = patched(52, 4, 2);
+        assert_eq!(
+            Err("invalid ELF header size"),
+            Elf::parse(&data).unwrap().strip_debug("awesome.debug")
+        );
+        let data = patched(shoff + 2 * 64 + 32, u64::MAX - 1, 8);
+        let elf = Elf::parse(&data).unwrap();
+        assert!(elf.section_data(&elf.sections[2]).is_err());
+        assert!(elf.strip_debug("awesome.debug").is_err());
+        let data = patched(shoff + 6 * 64 + 48, u64::MAX, 8);
+        assert!(Elf::parse(&data)
+            .unwrap()
+            .strip_debug("awesome.debug")
+            .is_err());
+        let data = patched(64 + 56, u32::MAX as u64, 4);
+        assert_eq!(None, Elf::parse(&data).unwrap().build_id());
+        let data = patched(32, u64::MAX - 8, 8);
+        assert!(Elf::parse(&data)
+            .unwrap()
+            .strip_debug("awesome.debug")
+            .is_err());
+    }
+
+    #[test]
+    fn dynamic_section() -> Result<(), Box<dyn std::error::Error>> {
+        let binary = sample(&[0x12, 0x34]);
+        let elf = Elf::parse(&binary)?;
+        assert!(elf.is_64bit());
+        assert_eq!(
+            Dynamic {
+                soname: Some("libawesome.so.1".to_string()),
+                needed: vec!["libc.so.6".to_string()],
+            },
+            elf.dynamic()?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,334 @@
+//! Versions and changelogs from the tags and commits of a git checkout.
+//!
+//! Runs the `git` command line tool, which has to be installed. Tags are taken
+//! as versions with a leading `v` removed and pre-releases like `1.2.0-rc1` as
+//! `1.2.0~rc1`. Builds of commits after a tag get a
+//! snapshot version like `1.2.0^3.g1a2b3c4d5e6f`, which rpm sorts after `1.2.0`
+//! and before `1.2.1`.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let version = rpm::git::describe(".")?;
+//! let pkg = rpm::RPMBuilder::new("foo", "0", "MIT", "noarch", "A foo")
+//!     .git_version(&version)
+//!     .changelog(rpm::git::changelog(".", 10)?)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::convert::TryFrom;
+use std::path::Path;
+use std::process::Command;
+
+use crate::errors::*;
+
+use super::changelog::ChangelogEntry;
+use super::timestamp::Timestamp;
+use super::RPMBuilder;
+
+/// What `git describe` says about the checked out commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitVersion {
+    /// The newest tag the commit descends from, `None` without one.
+    pub tag: Option<String>,
+    /// Commits since the tag, or since the root without a tag.
+    pub distance: u32,
+    /// Abbreviated hash of the commit.
+    pub commit: String,
+    /// The work tree has uncommitted changes.
+    pub dirty: bool,
+}
+
+impl GitVersion {
+    /// The version of the tag, with a snapshot suffix if the commit is not tagged
+    /// or the work tree is dirty. `0` stands in for a missing tag.
+    pub fn version(&self) -> String {
+        let base = self
+            .tag
+            .as_deref()
+            .map_or_else(|| "0".to_string(), tag_version);
+        if self.tag.is_some() && self.distance == 0 && !self.dirty {
+            return base;
+        }
+        let mut version = format!("{}^{}.g{}", base, self.distance, self.commit);
+        if self.dirty {
+            version.push_str(".dirty");
+        }
+        version
+    }
+
+    /// `1`, the version carries the snapshot information.
+    pub fn release(&self) -> String {
+        "1".to_string()
+    }
+}
+
+/// The version of `tag`, without a leading `v` followed by a digit.
+///
+/// rpm versions cannot contain `-`. The first one starts a pre-release like in
+/// semantic versioning and becomes `~`, which rpm sorts before the release, so
+/// `1.2.0-rc.1` is `1.2.0~rc.1`. Any further ones become `.`.
+fn tag_version(tag: &str) -> String {
+    let version = match tag.strip_prefix('v') {
+        Some(version) if version.starts_with(|c: char| c.is_ascii_digit()) => version,
+        _ => tag,
+    };
+    match version.split_once('-') {
+        Some((release, pre_release)) => format!("{}~{}", release, pre_release.replace('-', ".")),
+        None => version.to_string(),
+    }
+}
+
+fn git<P: AsRef<Path>>(repo: P, args: &[&str]) -> Result<String, RPMError> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo.as_ref())
+        .args(args)
+        .output()
+        .map_err(|err| RPMError::Git {
+            reason: format!("failed to run git - {}", err),
+        })?;
+    if !output.status.success() {
+        return Err(RPMError::Git {
+            reason: format!(
+                "git {} failed - {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+    String::from_utf8(output.stdout).map_err(|_| RPMError::Git {
+        reason: format!("git {} printed invalid UTF-8", args.join(" ")),
+    })
+}
+
+/// Describe the commit checked out in `repo`, like `git describe --tags --long --dirty`.
+pub fn describe<P: AsRef<Path>>(repo: P) -> Result<GitVersion, RPMError> {
+    let repo = repo.as_ref();
+    let commit = git(repo, &["rev-parse", "--short=12", "HEAD"])?
+        .trim()
+        .to_string();
+    let dirty = !git(repo, &["status", "--porcelain", "--untracked-files=no"])?
+        .trim()
+        .is_empty();
+    let described = match git(repo, &["describe", "--tags", "--long", "--abbrev=12"]) {
+        Ok(described) => described,
+        // no tags at all
+        Err(_) => {
+            let distance = git(repo, &["rev-list", "--count", "HEAD"])?;
+            return Ok(GitVersion {
+                tag: None,
+                distance: parse_count(&distance)?,
+                commit,
+                dirty,
+            });
+        }
+    };
+    // <tag>-<distance>-g<commit>, the tag may contain dashes itself
+    let mut parts = described.trim().rsplitn(3, '-');
+    let (_, distance, tag) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(hash), Some(distance), Some(tag)) => (hash, distance, tag),
+        _ => {
+            return Err(RPMError::Git {
+                reason: format!("unexpected output of git describe {:?}", described.trim()),
+            })
+        }
+    };
+    Ok(GitVersion {
+        tag: Some(tag.to_string()),
+        distance: parse_count(distance)?,
+        commit,
+        dirty,
+    })
+}
+
+fn parse_count(count: &str) -> Result<u32, RPMError> {
+    count.trim().parse().map_err(|_| RPMError::Git {
+        reason: format!("invalid commit count {:?}", count.trim()),
+    })
+}
+
+/// One changelog entry per tag, newest first, listing the subjects of the commits
+/// since the previous tag. Commits after the newest tag get an entry with the
+/// snapshot version of [`describe`].
+///
+/// Only first parents are followed, so merged branches show up as their merge
+/// commit. Author and time of an entry are those of its newest commit, at most
+/// `max_entries` are returned.
+pub fn changelog<P: AsRef<Path>>(
+    repo: P,
+    max_entries: usize,
+) -> Result<Vec<ChangelogEntry>, RPMError> {
+    let repo = repo.as_ref();
+    let log = git(
+        repo,
+        &[
+            "log",
+            "--first-parent",
+            "--format=%an <%ae>%x1f%ct%x1f%s%x1f%D",
+        ],
+    )?;
+    let mut entries: Vec<ChangelogEntry> = Vec::new();
+    let mut pending_head = true;
+    for line in log.lines() {
+        let fields = line.split('\u{1f}').collect::<Vec<_>>();
+        let (author, time, subject, refs) = match fields.as_slice() {
+            [author, time, subject, refs] => (author, time, subject, refs),
+            _ => continue,
+        };
+        let tag = refs
+            .split(", ")
+            .filter_map(|name| name.strip_prefix("tag: "))
+            .next();
+        let version = match (tag, pending_head) {
+            // a dirty tree is a snapshot even on a tag
+            (_, true) => Some(describe(repo)?.version()),
+            (Some(tag), false) => Some(tag_version(tag)),
+            (None, false) => None,
+        };
+        pending_head = false;
+        if let Some(version) = version {
+            if entries.len() == max_entries {
+                break;
+            }
+            let seconds = time.parse::<i64>().map_err(|_| RPMError::Git {
+                reason: format!("invalid commit time {:?}", time),
+            })?;
+            entries.push(ChangelogEntry {
+                time: Timestamp::try_from(seconds)?,
+                author: format!("{} - {}-1", author, version),
+                text: String::new(),
+            });
+        }
+        let entry = entries.last_mut().expect("entry of the newest commit");
+        if !entry.text.is_empty() {
+            entry.text.push('\n');
+        }
+        entry.text.push_str(&format!("- {}", subject));
+    }
+    Ok(entries)
+}
+
+impl RPMBuilder {
+    /// Set version and release from [`describe`].
+    pub fn git_version(self, version: &GitVersion) -> Self {
+        self.version(version.version()).release(version.release())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(dir: &Path, args: &[&str], time: i64) {
+        let date = format!("@{} +0000", time);
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args([
+                "-c",
+                "user.name=Jane Doe",
+                "-c",
+                "user.email=jane@example.com",
+            ])
+            .args(["-c", "commit.gpgsign=false", "-c", "tag.gpgsign=false"])
+            .args(args)
+            .env("GIT_AUTHOR_DATE", &date)
+            .env("GIT_COMMITTER_DATE", &date)
+            .status()
+            .expect("git");
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    #[test]
+    fn versions() {
+        let version = |tag: Option<&str>, distance, dirty| {
+            GitVersion {
+                tag: tag.map(str::to_string),
+                distance,
+                commit: "1a2b3c4d5e6f".to_string(),
+                dirty,
+            }
+            .version()
+        };
+        assert_eq!("1.2.0", version(Some("v1.2.0"), 0, false));
+        assert_eq!("1.2.0^3.g1a2b3c4d5e6f", version(Some("1.2.0"), 3, false));
+        assert_eq!(
+            "1.2.0^0.g1a2b3c4d5e6f.dirty",
+            version(Some("v1.2.0"), 0, true)
+        );
+        assert_eq!("0^5.g1a2b3c4d5e6f", version(None, 5, false));
+        assert_eq!("version~1", tag_version("version-1"));
+        assert_eq!("1.2.0~rc1", version(Some("v1.2.0-rc1"), 0, false));
+        assert_eq!("1.2.0~rc.1", tag_version("1.2.0-rc.1"));
+        assert_eq!(
+            "1.2.0~alpha.1+build.5",
+            tag_version("v1.2.0-alpha-1+build.5")
+        );
+        assert_eq!(
+            "1.2.0~rc1^2.g1a2b3c4d5e6f",
+            version(Some("v1.2.0-rc1"), 2, false)
+        );
+    }
+
+    #[test]
+    fn repository() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = crate::tests::TestDir::new("git");
+        run(&dir, &["init", "-q"], 0);
+        let commit = |subject: &str, time| {
+            std::fs::write(dir.join("file"), subject).unwrap();
+            run(&dir, &["add", "file"], time);
+            run(&dir, &["commit", "-q", "-m", subject], time);
+        };
+        commit("Initial commit", 1_600_000_000);
+        assert_eq!(None, describe(&dir)?.tag);
+        run(&dir, &["tag", "v1.0.0"], 1_600_000_000);
+        commit("Support zstd", 1_650_000_000);
+        commit("Fix a crash", 1_684_000_000);
+        run(
+            &dir,
+            &["tag", "-a", "-m", "release", "v1.2.0"],
+            1_684_000_000,
+        );
+        assert_eq!("1.2.0", describe(&dir)?.version());
+
+        commit("Start 1.3", 1_690_000_000);
+        let snapshot = describe(&dir)?;
+        assert_eq!(Some("v1.2.0".to_string()), snapshot.tag);
+        assert_eq!((1, false), (snapshot.distance, snapshot.dirty));
+        std::fs::write(dir.join("file"), "changed")?;
+        assert!(describe(&dir)?.dirty);
+        run(&dir, &["checkout", "-q", "file"], 0);
+
+        let entries = changelog(&dir, 10)?;
+        assert_eq!(
+            vec![
+                ChangelogEntry {
+                    time: Timestamp::new(1_690_000_000),
+                    author: format!("Jane Doe <jane@example.com> - {}-1", snapshot.version()),
+                    text: "- Start 1.3".to_string(),
+                },
+                ChangelogEntry {
+                    time: Timestamp::new(1_684_000_000),
+                    author: "Jane Doe <jane@example.com> - 1.2.0-1".to_string(),
+                    text: "- Fix a crash\n- Support zstd".to_string(),
+                },
+                ChangelogEntry {
+                    time: Timestamp::new(1_600_000_000),
+                    author: "Jane Doe <jane@example.com> - 1.0.0-1".to_string(),
+                    text: "- Initial commit".to_string(),
+                },
+            ],
+            entries
+        );
+        assert_eq!(2, changelog(&dir, 2)?.len());
+
+        let pkg = RPMBuilder::new("foo", "0", "MIT", "noarch", "A foo")
+            .git_version(&snapshot)
+            .changelog(entries)
+            .build()?;
+        assert_eq!(snapshot.version(), pkg.metadata.header.get_version()?);
+        Ok(())
+    }
+}
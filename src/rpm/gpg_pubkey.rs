@@ -0,0 +1,195 @@
+//! The `gpg-pubkey` pseudo packages rpm uses to keep track of imported keys.
+//!
+//! `rpm --import` stores every key as a header only package named `gpg-pubkey`,
+//! with the short key id as version and the key creation time as release:
+//!
+//! ```text
+//! gpg-pubkey-<8 hex digit key id>-<8 hex digit creation time>
+//! ```
+
+use ::pgp::composed::{Deserializable, SignedPublicKey};
+use ::pgp::ser::Serialize;
+use ::pgp::types::KeyTrait;
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::headers::*;
+use super::timestamp::Timestamp;
+
+/// Name shared by all key pseudo packages.
+pub const NAME: &str = "gpg-pubkey";
+
+/// The parts of a key pseudo package relevant for a keyring.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKeyPackage {
+    /// Full 16 hex digit key id, lowercase.
+    pub key_id: String,
+    /// Version of the package, the last 8 hex digits of the key id.
+    pub version: String,
+    /// Release of the package, the creation time of the key as 8 hex digits.
+    pub release: String,
+    /// Primary user id, `none` if the key has none.
+    pub user_id: String,
+    /// The key in its ascii armored form, as passed to `rpm --import`.
+    pub armored: String,
+}
+
+impl PublicKeyPackage {
+    /// Inspect an ascii armored public key.
+    pub fn from_armored(armored: &str) -> Result<Self, RPMError> {
+        let key = load(armored)?;
+        let key_id = hex::encode(key.key_id().as_ref());
+        Ok(PublicKeyPackage {
+            version: key_id[key_id.len() - 8..].to_string(),
+            release: format!("{:08x}", key.primary_key.created_at().timestamp()),
+            user_id: key
+                .details
+                .users
+                .first()
+                .map(|user| user.id.id().to_string())
+                .unwrap_or_else(|| "none".to_string()),
+            key_id,
+            armored: armored.to_string(),
+        })
+    }
+
+    /// Read the key information back from a pseudo package header.
+    pub fn from_header(header: &Header<IndexTag>) -> Result<Self, RPMError> {
+        if header.get_name()? != NAME {
+            return Err(RPMError::NotAPublicKeyPackage(
+                header.get_name()?.to_string(),
+            ));
+        }
+        let mut package = Self::from_armored(header.get_description()?)?;
+        // trust the header over the key for what rpm recorded
+        package.version = header.get_version()?.to_string();
+        package.release = header.get_release()?.to_string();
+        Ok(package)
+    }
+
+    /// The `name-version-release` rpm uses to refer to the key, e.g. for `rpm -e`.
+    pub fn nvr(&self) -> String {
+        format!("{}-{}-{}", NAME, self.version, self.release)
+    }
+
+    /// Construct the pseudo package header the same way `rpm --import` does.
+    ///
+    /// `install_time` is recorded as both build and install time.
+    pub fn to_header(&self, install_time: Timestamp) -> Result<Header<IndexTag>, RPMError> {
+        let key = load(&self.armored)?;
+        let encoded = base64::encode(
+            key.to_bytes()
+                .map_err(|e| RPMError::KeyLoadError {
+                    source: Box::new(e),
+                    details: "Failed to serialize public key",
+                })?
+                .as_slice(),
+        );
+
+        let summary = format!("gpg({})", self.user_id);
+        let evr = format!("4:{}-{}", self.key_id, self.release);
+        let flags = (RPMSENSE_KEYRING | RPMSENSE_EQUAL) as i32;
+        let string =
+            |tag, value: &str| IndexEntry::new(tag, 0, IndexData::StringTag(value.to_string()));
+        let records = vec![
+            string(IndexTag::RPMTAG_HEADERI18NTABLE, "C"),
+            IndexEntry::new(
+                IndexTag::RPMTAG_PUBKEYS,
+                0,
+                IndexData::StringArray(vec![encoded]),
+            ),
+            string(IndexTag::RPMTAG_NAME, NAME),
+            string(IndexTag::RPMTAG_VERSION, &self.version),
+            string(IndexTag::RPMTAG_RELEASE, &self.release),
+            IndexEntry::new(
+                IndexTag::RPMTAG_SUMMARY,
+                0,
+                IndexData::I18NString(vec![summary.clone()]),
+            ),
+            IndexEntry::new(
+                IndexTag::RPMTAG_DESCRIPTION,
+                0,
+                IndexData::I18NString(vec![self.armored.clone()]),
+            ),
+            IndexEntry::new(
+                IndexTag::RPMTAG_BUILDTIME,
+                0,
+                IndexData::Int32(vec![install_time.to_tag()]),
+            ),
+            string(IndexTag::RPMTAG_BUILDHOST, "localhost"),
+            IndexEntry::new(
+                IndexTag::RPMTAG_INSTALLTIME,
+                0,
+                IndexData::Int32(vec![install_time.to_tag()]),
+            ),
+            IndexEntry::new(IndexTag::RPMTAG_SIZE, 0, IndexData::Int32(vec![0])),
+            string(IndexTag::RPMTAG_LICENSE, "pubkey"),
+            string(IndexTag::RPMTAG_PACKAGER, &self.user_id),
+            IndexEntry::new(
+                IndexTag::RPMTAG_GROUP,
+                0,
+                IndexData::I18NString(vec!["Public Keys".to_string()]),
+            ),
+            string(IndexTag::RPMTAG_SOURCERPM, "(none)"),
+            IndexEntry::new(
+                IndexTag::RPMTAG_PROVIDENAME,
+                0,
+                IndexData::StringArray(vec![summary, format!("gpg({})", self.version)]),
+            ),
+            IndexEntry::new(
+                IndexTag::RPMTAG_PROVIDEFLAGS,
+                0,
+                IndexData::Int32(vec![flags, flags]),
+            ),
+            IndexEntry::new(
+                IndexTag::RPMTAG_PROVIDEVERSION,
+                0,
+                IndexData::StringArray(vec![evr.clone(), evr]),
+            ),
+        ];
+        Ok(Header::from_entries(
+            records,
+            IndexTag::RPMTAG_HEADERIMMUTABLE,
+        ))
+    }
+}
+
+fn load(armored: &str) -> Result<SignedPublicKey, RPMError> {
+    let (key, _) = SignedPublicKey::from_string(armored).map_err(|e| RPMError::KeyLoadError {
+        source: Box::new(e),
+        details: "Failed to parse bytes as ascii armored key",
+    })?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pubkey_header_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let armored = std::fs::read_to_string("./test_assets/public_key.asc")?;
+        let package = PublicKeyPackage::from_armored(&armored)?;
+        assert_eq!(16, package.key_id.len());
+        assert!(package.key_id.ends_with(&package.version));
+        assert_eq!(8, package.release.len());
+
+        let header = package.to_header(Timestamp::new(1_600_000_000))?;
+        let mut raw = Vec::new();
+        header.write(&mut raw)?;
+        let header = Header::<IndexTag>::parse(&mut raw.as_slice())?;
+
+        assert_eq!(NAME, header.get_name()?);
+        assert_eq!(format!("gpg({})", package.user_id), header.get_summary()?);
+        let provides = header.get_provides()?;
+        assert_eq!(format!("gpg({})", package.version), provides[1].name());
+        assert_eq!(
+            format!("4:{}-{}", package.key_id, package.release),
+            provides[1].version()
+        );
+        assert_eq!(package, PublicKeyPackage::from_header(&header)?);
+        assert!(package.nvr().starts_with("gpg-pubkey-"));
+        Ok(())
+    }
+}
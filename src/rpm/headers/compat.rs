@@ -0,0 +1,135 @@
+//! Tolerating the historical deviations found in packages built by old tools.
+
+use std::fmt;
+
+use crate::errors::*;
+
+use super::super::diagnostics::{Diagnostic, Diagnostics};
+
+/// How strictly packages are parsed, see [`RPMPackage::parse_with_options`](crate::RPMPackage::parse_with_options).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    permissive: bool,
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept the [`Quirk`]s old rpm versions and vendor tools are known to produce
+    /// instead of failing on them, off by default.
+    ///
+    /// Entries that can not be represented, like tags unknown to this crate, are dropped.
+    /// The immutable region and with it the header digest may no longer match then.
+    pub fn permissive(mut self, permissive: bool) -> Self {
+        self.permissive = permissive;
+        self
+    }
+}
+
+/// A deviation from the current package format found while parsing.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Quirk {
+    /// Lead version other than 3.0, rpm itself reads 3.x and 4.x leads.
+    LeadVersion { major: u8, minor: u8 },
+    /// Lead package type other than binary or source.
+    LeadPackageType(u16),
+    /// Lead operating system other than Linux.
+    LeadOs(u16),
+    /// The header carries no region tag, as written by rpm before 4.0.
+    MissingRegion { store_type: &'static str },
+    /// The region tag does not describe the header, the entries are used anyway.
+    InvalidRegion {
+        store_type: &'static str,
+        reason: &'static str,
+    },
+    /// A tag unknown to this crate, the entry was dropped.
+    UnknownTag {
+        raw_tag: u32,
+        store_type: &'static str,
+    },
+    /// A data type unknown to this crate, the entry was dropped.
+    UnknownDataType {
+        raw_tag: u32,
+        raw_data_type: u32,
+        store_type: &'static str,
+    },
+    /// Entry data pointing outside the store, the entry was dropped.
+    EntryOutOfBounds { tag: String },
+}
+
+impl fmt::Display for Quirk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quirk::LeadVersion { major, minor } => write!(f, "lead version {}.{}", major, minor),
+            Quirk::LeadPackageType(package_type) => {
+                write!(f, "lead package type {}", package_type)
+            }
+            Quirk::LeadOs(os) => write!(f, "lead os {}", os),
+            Quirk::MissingRegion { store_type } => {
+                write!(f, "{} header without region tag", store_type)
+            }
+            Quirk::InvalidRegion { store_type, reason } => {
+                write!(f, "invalid {} region - {}", store_type, reason)
+            }
+            Quirk::UnknownTag {
+                raw_tag,
+                store_type,
+            } => write!(f, "unknown tag {} in {}, dropped", raw_tag, store_type),
+            Quirk::UnknownDataType {
+                raw_tag,
+                raw_data_type,
+                store_type,
+            } => write!(
+                f,
+                "unknown data type {} of tag {} in {}, dropped",
+                raw_data_type, raw_tag, store_type
+            ),
+            Quirk::EntryOutOfBounds { tag } => {
+                write!(f, "data of tag {} out of bounds, dropped", tag)
+            }
+        }
+    }
+}
+
+/// Issues seen so far while parsing, and whether to accept quirks.
+#[derive(Debug, Default)]
+pub(crate) struct QuirkLog {
+    permissive: bool,
+    pub(crate) diagnostics: Diagnostics,
+}
+
+impl QuirkLog {
+    pub(crate) fn new(options: &ParseOptions) -> Self {
+        QuirkLog {
+            permissive: options.permissive,
+            diagnostics: Diagnostics::new(),
+        }
+    }
+
+    /// A log rejecting everything that used to be rejected before quirks were known.
+    pub(crate) fn strict() -> Self {
+        Self::default()
+    }
+
+    /// Record a quirk rpm accepts as well.
+    pub(crate) fn note(&mut self, quirk: Quirk) {
+        self.report(Diagnostic::Quirk(quirk));
+    }
+
+    pub(crate) fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Record `quirk` if permissive, fail with `error` otherwise.
+    pub(crate) fn tolerate(&mut self, quirk: Quirk, error: RPMError) -> Result<(), RPMError> {
+        if self.permissive {
+            self.note(quirk);
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+}
@@ -5,12 +5,15 @@ use nom::number::complete::{be_i16, be_i32, be_i64, be_i8, be_u32, be_u8};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 
 use crate::constants::{self, *};
-use chrono::offset::TimeZone;
 use num_traits::FromPrimitive;
-use std::convert::TryInto;
+use std::borrow::Cow;
+use std::convert::{TryFrom, TryInto};
 use std::fmt;
 use std::path::PathBuf;
 
+use super::super::diagnostics::Diagnostic;
+use super::super::limits;
+use super::super::timestamp::Timestamp;
 use super::*;
 use crate::errors::*;
 
@@ -46,94 +49,150 @@ where
     T: Tag,
 {
     #[cfg(feature = "async-tokio")]
-    pub(crate) async fn parse_async<I: AsyncRead + Unpin>(
+    pub(crate) async fn parse_async_with<I: AsyncRead + Unpin>(
         input: &mut I,
+        log: &mut QuirkLog,
     ) -> Result<Header<T>, RPMError> {
         let mut buf: [u8; 16] = [0; 16];
         input.read_exact(&mut buf).await?;
         let index_header = IndexHeader::parse(&buf)?;
+        limits::check_index(&index_header)?;
         // read rest of header => each index consists of 16 bytes. The index header knows how large the store is.
         let mut buf = vec![0; (index_header.header_size + index_header.num_entries * 16) as usize];
         input.read_exact(&mut buf).await?;
-        Self::parse_header(index_header, &buf[..])
+        Self::parse_header(index_header, &buf[..], log)
     }
 
-    fn parse_header(index_header: IndexHeader, mut bytes: &[u8]) -> Result<Header<T>, RPMError> {
+    pub(crate) fn parse_header(
+        mut index_header: IndexHeader,
+        mut bytes: &[u8],
+        log: &mut QuirkLog,
+    ) -> Result<Header<T>, RPMError> {
         // parse all entries
         let mut entries: Vec<IndexEntry<T>> = Vec::new();
-        let mut buf_len = bytes.len();
         for _ in 0..index_header.num_entries {
-            let (rest, entry) = IndexEntry::parse(bytes)?;
-            entries.push(entry);
-            bytes = rest;
-            assert_eq!(16, buf_len - bytes.len());
-            buf_len = bytes.len();
+            match IndexEntry::parse(bytes) {
+                Ok((_, entry)) => entries.push(entry),
+                Err(err) => {
+                    let quirk = match &err {
+                        RPMError::InvalidTag {
+                            raw_tag,
+                            store_type,
+                        } => Quirk::UnknownTag {
+                            raw_tag: *raw_tag,
+                            store_type,
+                        },
+                        RPMError::InvalidTagDataType {
+                            raw_data_type,
+                            store_type,
+                        } => Quirk::UnknownDataType {
+                            raw_tag: u32::from_be_bytes(bytes[..4].try_into().unwrap()),
+                            raw_data_type: *raw_data_type,
+                            store_type,
+                        },
+                        _ => return Err(err),
+                    };
+                    log.tolerate(quirk, err)?;
+                }
+            }
+            bytes = bytes.get(16..).ok_or_else(|| RPMError::Malformed {
+                what: "header",
+                reason: "truncated index".to_string(),
+            })?;
         }
 
         assert_eq!(bytes.len(), index_header.header_size as usize);
 
         let store = Vec::from(bytes);
         // add data to entries
-        for entry in &mut entries {
-            let mut remaining = &bytes[entry.offset as usize..];
-            match &mut entry.data {
-                IndexData::Null => {}
-                IndexData::Char(ref mut chars) => {
-                    parse_entry_data_number(remaining, entry.num_items, chars, be_u8)?;
-                }
-                IndexData::Int8(ref mut ints) => {
-                    parse_entry_data_number(remaining, entry.num_items, ints, be_i8)?;
-                }
-                IndexData::Int16(ref mut ints) => {
-                    parse_entry_data_number(remaining, entry.num_items, ints, be_i16)?;
-                }
-                IndexData::Int32(ref mut ints) => {
-                    parse_entry_data_number(remaining, entry.num_items, ints, be_i32)?;
-                }
-                IndexData::Int64(ref mut ints) => {
-                    parse_entry_data_number(remaining, entry.num_items, ints, be_i64)?;
-                }
-                IndexData::StringTag(ref mut string) => {
-                    let (_rest, raw_string) = complete::take_till(|item| item == 0)(remaining)?;
-                    string.push_str(String::from_utf8_lossy(raw_string).as_ref());
-                }
-                IndexData::Bin(ref mut bin) => {
-                    parse_entry_data_number(remaining, entry.num_items, bin, be_u8)?;
-                }
-                IndexData::StringArray(ref mut strings) => {
-                    for _ in 0..entry.num_items {
-                        let (rest, raw_string) = complete::take_till(|item| item == 0)(remaining)?;
-                        // the null byte is still in there.. we need to cut it out.
-                        remaining = &rest[1..];
-                        let string = String::from_utf8_lossy(raw_string).to_string();
-                        strings.push(string);
-                    }
-                }
-                IndexData::I18NString(ref mut strings) => {
-                    for _ in 0..entry.num_items {
-                        let (rest, raw_string) = complete::take_till(|item| item == 0)(remaining)?;
-                        remaining = rest;
-                        let string = String::from_utf8_lossy(raw_string).to_string();
-                        strings.push(string);
-                    }
-                }
+        let mut kept = Vec::with_capacity(entries.len());
+        for mut entry in entries {
+            match parse_entry_data(&mut entry, bytes) {
+                Ok(()) => kept.push(entry),
+                Err(err) => log.tolerate(
+                    Quirk::EntryOutOfBounds {
+                        tag: entry.tag.to_string(),
+                    },
+                    err,
+                )?,
             }
         }
+        index_header.num_entries = kept.len() as u32;
 
-        Ok(Header {
+        let header = Header {
             index_header,
-            index_entries: entries,
+            index_entries: kept,
             store,
-        })
+        };
+        match header.region() {
+            Ok(Some(_)) => {}
+            Ok(None) => log.note(Quirk::MissingRegion {
+                store_type: T::type_name(),
+            }),
+            Err(RPMError::InvalidRegion { tag, reason }) => log.tolerate(
+                Quirk::InvalidRegion {
+                    store_type: T::type_name(),
+                    reason,
+                },
+                RPMError::InvalidRegion { tag, reason },
+            )?,
+            Err(err) => return Err(err),
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            store = T::type_name(),
+            entries = header.index_entries.len(),
+            size = header.store.len(),
+            "header"
+        );
+        Ok(header)
     }
+
+    #[cfg(test)]
     pub(crate) fn parse<I: std::io::BufRead>(input: &mut I) -> Result<Header<T>, RPMError> {
+        Self::parse_with(input, &mut QuirkLog::strict())
+    }
+
+    pub(crate) fn parse_with<I: std::io::BufRead>(
+        input: &mut I,
+        log: &mut QuirkLog,
+    ) -> Result<Header<T>, RPMError> {
         let mut buf: [u8; 16] = [0; 16];
         input.read_exact(&mut buf)?;
         let index_header = IndexHeader::parse(&buf)?;
+        limits::check_index(&index_header)?;
         // read rest of header => each index consists of 16 bytes. The index header knows how large the store is.
         let mut buf = vec![0; (index_header.header_size + index_header.num_entries * 16) as usize];
         input.read_exact(&mut buf)?;
-        Self::parse_header(index_header, &buf[..])
+        Self::parse_header(index_header, &buf[..], log)
+    }
+
+    /// Parse a header blob as stored in the rpm database.
+    ///
+    /// Such a blob lacks the magic, version and reserved bytes and starts
+    /// right with the number of index entries and the size of the store.
+    pub fn parse_blob(blob: &[u8]) -> Result<Header<T>, RPMError> {
+        let (rest, num_entries) = be_u32(blob)?;
+        let (rest, header_size) = be_u32(rest)?;
+        let index_header = IndexHeader {
+            magic: HEADER_MAGIC,
+            version: 1,
+            num_entries,
+            header_size,
+        };
+        limits::check_index(&index_header)?;
+        let expected = num_entries as u64 * 16 + header_size as u64;
+        if rest.len() as u64 != expected {
+            return Err(RPMError::Malformed {
+                what: "header blob",
+                reason: format!(
+                    "{} bytes of index and store, expected {}",
+                    rest.len(),
+                    expected
+                ),
+            });
+        }
+        Self::parse_header(index_header, rest, &mut QuirkLog::strict())
     }
 
     #[cfg(feature = "async-tokio")]
@@ -141,23 +200,119 @@ where
         &self,
         out: &mut W,
     ) -> Result<(), RPMError> {
-        self.index_header.write_async(out).await?;
-        for entry in &self.index_entries {
-            entry.write_index_async(out).await?;
-        }
+        out.write_all(&self.index_bytes()).await?;
         out.write_all(&self.store).await?;
         Ok(())
     }
 
-    pub(crate) fn write<W: std::io::Write>(&self, out: &mut W) -> Result<(), RPMError> {
-        self.index_header.write(out)?;
-        for entry in &self.index_entries {
-            entry.write_index(out)?;
-        }
+    /// Write the header as packages carry it, starting with the magic.
+    pub fn write<W: std::io::Write>(&self, out: &mut W) -> Result<(), RPMError> {
+        out.write_all(&self.index_bytes())?;
         out.write_all(&self.store)?;
         Ok(())
     }
 
+    /// Add `entries` behind the region, like rpm adds the tags of installed packages, so
+    /// the region and the digests over it stay valid. Tags the header has are skipped.
+    pub(crate) fn append_entries(&mut self, mut entries: Vec<IndexEntry<T>>) {
+        entries.retain(|entry| self.find_entry_or_err(&entry.tag).is_err());
+        entries.sort_by_key(|entry| entry.tag.to_u32());
+        for mut entry in entries {
+            entry.offset = self.store.len() as i32;
+            entry.offset += entry.data.append(&mut self.store) as i32;
+            self.index_entries.push(entry);
+        }
+        self.index_header =
+            IndexHeader::new(self.index_entries.len() as u32, self.store.len() as u32);
+    }
+
+    /// The header as stored in the rpm database, see [`parse_blob`](Self::parse_blob).
+    pub fn to_blob(&self) -> Vec<u8> {
+        let mut blob = Vec::with_capacity(self.serialized_len());
+        self.write(&mut blob).expect("writing to memory");
+        blob.split_off(8)
+    }
+
+    /// Number of bytes [`write`](Self::write) produces.
+    pub fn serialized_len(&self) -> usize {
+        16 + self.index_entries.len() * 16 + self.store.len()
+    }
+
+    /// Index header and entries, serialized in one go such that writers
+    /// see two writes per header instead of four per entry.
+    fn index_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(16 + self.index_entries.len() * 16);
+        self.index_header.append(&mut out);
+        for entry in &self.index_entries {
+            entry.append_index(&mut out);
+        }
+        out
+    }
+
+    /// Number of index entries and store bytes covered by the leading region tag,
+    /// `None` for legacy headers without one.
+    ///
+    /// Checks the region trailer the same way rpm does before trusting a header.
+    pub(crate) fn region(&self) -> Result<Option<(usize, usize)>, RPMError> {
+        let entry = match self.index_entries.first() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let region_tag = entry.tag.to_u32().unwrap_or(0);
+        if !(HEADER_IMAGE as u32..=HEADER_IMMUTABLE as u32).contains(&region_tag) {
+            return Ok(None);
+        }
+        let invalid = |reason| RPMError::InvalidRegion {
+            tag: entry.tag.to_string(),
+            reason,
+        };
+        let trailer = match &entry.data {
+            IndexData::Bin(trailer) if trailer.len() == 16 => trailer,
+            _ => return Err(invalid("region tag is not a 16 byte binary")),
+        };
+        let field = |index: usize| {
+            u32::from_be_bytes(trailer[index * 4..index * 4 + 4].try_into().unwrap())
+        };
+        let (tag, data_type, offset, count) = (field(0), field(1), field(2) as i32, field(3));
+        // signature headers of old rpm versions carry the image tag in the trailer
+        let legacy = region_tag == HEADER_SIGNATURES as u32 && tag == HEADER_IMAGE as u32;
+        if (tag != region_tag && !legacy) || data_type != 7 || count != 16 {
+            return Err(invalid("malformed region trailer"));
+        }
+        if offset >= 0 || offset % 16 != 0 {
+            return Err(invalid(
+                "region trailer offset is not a negative multiple of 16",
+            ));
+        }
+        let entries = (-(offset as i64) / 16) as usize;
+        let store = entry.offset as usize + 16;
+        if entries > self.index_entries.len() || store > self.store.len() {
+            return Err(invalid("region exceeds the header"));
+        }
+        Ok(Some((entries, store)))
+    }
+
+    /// The immutable region as rpm hashes it, with header magic and without
+    /// entries added after the package was built, e.g. by the rpm database.
+    pub(crate) fn immutable_region(&self) -> Result<Vec<u8>, RPMError> {
+        let (entries, store) = self
+            .region()?
+            .unwrap_or((self.index_entries.len(), self.store.len()));
+        let mut out = Vec::with_capacity(16 + entries * 16 + store);
+        IndexHeader::new(entries as u32, store as u32).append(&mut out);
+        for entry in &self.index_entries[..entries] {
+            entry.append_index(&mut out);
+        }
+        out.extend_from_slice(&self.store[..store]);
+        Ok(out)
+    }
+
+    /// SHA1 and SHA256 of the immutable region, what rpm stores as
+    /// `RPMTAG_SHA1HEADER` and `RPMTAG_SHA256HEADER` of a package.
+    pub fn header_digest(&self) -> Result<HeaderDigest, RPMError> {
+        Ok(HeaderDigest::of(&self.immutable_region()?))
+    }
+
     pub(crate) fn find_entry_or_err(&self, tag: &T) -> Result<&IndexEntry<T>, RPMError> {
         self.index_entries
             .iter()
@@ -189,6 +344,22 @@ where
             })
     }
 
+    /// Like [`get_entry_string_data`](Self::get_entry_string_data) but also accepts
+    /// i18n strings, in which case the first (default locale) value is returned.
+    pub(crate) fn get_entry_i18n_string_data(&self, tag: T) -> Result<&str, RPMError> {
+        let entry = self.find_entry_or_err(&tag)?;
+        match &entry.data {
+            IndexData::StringTag(s) => Some(s.as_str()),
+            IndexData::I18NString(strings) => strings.first().map(|s| s.as_str()),
+            _ => None,
+        }
+        .ok_or_else(|| RPMError::UnexpectedTagDataType {
+            expected_data_type: "string",
+            actual_data_type: entry.data.to_string(),
+            tag: entry.tag.to_string(),
+        })
+    }
+
     pub(crate) fn get_entry_i16_array_data(&self, tag: T) -> Result<Vec<i16>, RPMError> {
         let entry = self.find_entry_or_err(&tag)?;
         entry
@@ -261,17 +432,68 @@ where
             })
     }
 
+    /// Like [`get_entry_string_array_data`](Self::get_entry_string_array_data) with the
+    /// bytes as stored, the parsed strings replace invalid UTF-8.
+    pub(crate) fn get_entry_raw_string_array_data(&self, tag: T) -> Result<Vec<&[u8]>, RPMError> {
+        let strings = self.get_entry_string_array_data(tag)?;
+        let entry = self.find_entry_or_err(&tag)?;
+        let mut remaining = self
+            .store
+            .get(entry.offset.max(0) as usize..)
+            .unwrap_or_default();
+        let mut raw = Vec::with_capacity(strings.len());
+        for string in strings {
+            let end = remaining.iter().position(|byte| *byte == 0);
+            match end.map(|end| remaining.split_at(end)) {
+                Some((bytes, rest)) if String::from_utf8_lossy(bytes) == string.as_str() => {
+                    raw.push(bytes);
+                    remaining = &rest[1..];
+                }
+                // entries changed without updating the store
+                _ => return Ok(strings.iter().map(|string| string.as_bytes()).collect()),
+            }
+        }
+        Ok(raw)
+    }
+
     pub(crate) fn create_region_tag(tag: T, records_count: i32, offset: i32) -> IndexEntry<T> {
-        let mut header_immutable_index_data = vec![];
+        let mut header_immutable_index_data = Vec::with_capacity(16);
         let mut hie = IndexEntry::new(tag, (records_count + 1) * -16, IndexData::Bin(Vec::new()));
         hie.num_items = 16;
-        hie.write_index(&mut header_immutable_index_data)
-            .expect("unabel to write to memory buffer");
+        hie.append_index(&mut header_immutable_index_data);
         IndexEntry::new(tag, offset, IndexData::Bin(header_immutable_index_data))
     }
 
-    pub(crate) fn from_entries(mut actual_records: Vec<IndexEntry<T>>, region_tag: T) -> Self {
-        let mut store = Vec::new();
+    /// Lay out a header holding `actual_records` like rpm does: sorted by tag, in a region
+    /// of `region_tag`, which is [`IndexTag::RPMTAG_HEADERIMMUTABLE`] for the main header
+    /// and [`IndexSignatureTag::HEADER_SIGNATURES`] for signature headers.
+    ///
+    /// ```rust
+    /// # fn main() -> Result<(), rpm::RPMError> {
+    /// use rpm::{Header, IndexData, IndexEntry, IndexTag};
+    ///
+    /// let string = |tag, value: &str| IndexEntry::from_data(tag, IndexData::StringTag(value.to_string()));
+    /// let header = Header::from_entries(
+    ///     vec![
+    ///         string(IndexTag::RPMTAG_VERSION, "1.0.0"),
+    ///         string(IndexTag::RPMTAG_NAME, "record"),
+    ///     ],
+    ///     IndexTag::RPMTAG_HEADERIMMUTABLE,
+    /// );
+    /// let blob = header.to_blob();
+    /// assert_eq!(header, Header::<IndexTag>::parse_blob(&blob)?);
+    /// assert_eq!("record", header.get_name()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_entries(mut actual_records: Vec<IndexEntry<T>>, region_tag: T) -> Self {
+        // rpm looks tags up by binary search and sorts headers the same way on export
+        actual_records.sort_by_key(|record| record.tag.to_u32());
+        // size the store up front, the region tag takes another 16 bytes
+        let store_len = actual_records
+            .iter()
+            .fold(0, |len, record| record.data.aligned_end(len));
+        let mut store = Vec::with_capacity(store_len + 16);
         for record in &mut actual_records {
             record.offset = store.len() as i32;
             let alignment = record.data.append(&mut store);
@@ -300,6 +522,29 @@ where
     }
 }
 
+/// Digests over the immutable region of a header, see [`Header::header_digest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderDigest {
+    /// Lowercase hex, like `RPMSIGTAG_SHA1`.
+    pub sha1: String,
+    /// Lowercase hex, like `RPMSIGTAG_SHA256`.
+    pub sha256: String,
+}
+
+impl HeaderDigest {
+    pub(crate) fn of(header: &[u8]) -> Self {
+        let sha1 = {
+            use sha1::Digest;
+            hex::encode(sha1::Sha1::digest(header))
+        };
+        let sha256 = {
+            use sha2::Digest;
+            hex::encode(sha2::Sha256::digest(header))
+        };
+        HeaderDigest { sha1, sha256 }
+    }
+}
+
 impl Header<IndexSignatureTag> {
     /// Create a new full signature header.
     ///
@@ -312,12 +557,13 @@ impl Header<IndexSignatureTag> {
     pub(crate) fn new_signature_header(
         size: i32,
         md5sum: &[u8],
-        sha1: String,
+        digest: &HeaderDigest,
         rsa_spanning_header: &[u8],
         rsa_spanning_header_and_archive: &[u8],
     ) -> Self {
         SignatureHeaderBuilder::new()
-            .add_digest(sha1.as_str(), md5sum)
+            .add_digest(digest.sha1.as_str(), md5sum)
+            .add_sha256_digest(digest.sha256.as_str())
             .add_signature(rsa_spanning_header, rsa_spanning_header_and_archive)
             .build(size)
     }
@@ -330,22 +576,32 @@ impl Header<IndexSignatureTag> {
     #[cfg(feature = "async-tokio")]
     pub(crate) async fn parse_signature_async<I: AsyncRead + Unpin>(
         input: &mut I,
+        log: &mut QuirkLog,
     ) -> Result<Header<IndexSignatureTag>, RPMError> {
-        let result = Self::parse_async(input).await?;
+        let result = Self::parse_async_with(input, log).await?;
 
         let modulo = result.index_header.header_size % 8;
         if modulo > 0 {
             let align_size = 8 - modulo;
             let mut discard = vec![0; align_size as usize];
             input.read_exact(&mut discard).await?;
+            check_padding(&discard, log);
         }
         Ok(result)
     }
 
+    #[cfg(test)]
     pub(crate) fn parse_signature<I: std::io::BufRead>(
         input: &mut I,
     ) -> Result<Header<IndexSignatureTag>, RPMError> {
-        let result = Self::parse(input)?;
+        Self::parse_signature_with(input, &mut QuirkLog::strict())
+    }
+
+    pub(crate) fn parse_signature_with<I: std::io::BufRead>(
+        input: &mut I,
+        log: &mut QuirkLog,
+    ) -> Result<Header<IndexSignatureTag>, RPMError> {
+        let result = Self::parse_with(input, log)?;
         // this structure is aligned to 8 bytes - rest is filled up with zeros.
         // if the size of our store is not a modulo of 8, we discard bytes to align to the 8 byte boundary.
         let modulo = result.index_header.header_size % 8;
@@ -353,6 +609,7 @@ impl Header<IndexSignatureTag> {
             let align_size = 8 - modulo;
             let mut discard = vec![0; align_size as usize];
             input.read_exact(&mut discard)?;
+            check_padding(&discard, log);
         }
         Ok(result)
     }
@@ -381,6 +638,17 @@ impl Header<IndexSignatureTag> {
         Ok(())
     }
 
+    /// Add `entry`, replacing an entry with the same tag, and rebuild the region.
+    #[cfg(feature = "signature-cms")]
+    pub(crate) fn set_entry(&mut self, entry: IndexEntry<IndexSignatureTag>) {
+        let mut entries = std::mem::take(&mut self.index_entries);
+        entries.retain(|existing| {
+            existing.tag != IndexSignatureTag::HEADER_SIGNATURES && existing.tag != entry.tag
+        });
+        entries.push(entry);
+        *self = Self::from_entries(entries, IndexSignatureTag::HEADER_SIGNATURES);
+    }
+
     #[inline]
     pub fn get_file_ima_signatures(&self) -> Result<&[String], RPMError> {
         self.get_entry_string_array_data(IndexSignatureTag::RPMSIGTAG_FILESIGNATURES)
@@ -434,38 +702,224 @@ impl Header<IndexTag> {
     }
 
     #[inline]
-    pub fn get_install_time(&self) -> Result<i64, RPMError> {
-        self.get_entry_i64_data(IndexTag::RPMTAG_INSTALLTIME)
+    pub fn get_license(&self) -> Result<&str, RPMError> {
+        self.get_entry_string_data(IndexTag::RPMTAG_LICENSE)
+    }
+
+    #[inline]
+    pub fn get_summary(&self) -> Result<&str, RPMError> {
+        self.get_entry_i18n_string_data(IndexTag::RPMTAG_SUMMARY)
+    }
+
+    #[inline]
+    pub fn get_description(&self) -> Result<&str, RPMError> {
+        self.get_entry_i18n_string_data(IndexTag::RPMTAG_DESCRIPTION)
+    }
+
+    #[inline]
+    pub fn get_install_time(&self) -> Result<Timestamp, RPMError> {
+        // rpm stores it as 32 bit integer
+        match self.get_entry_i32_data(IndexTag::RPMTAG_INSTALLTIME) {
+            Ok(time) => Ok(Timestamp::from_tag(time)),
+            Err(_) => Timestamp::try_from(self.get_entry_i64_data(IndexTag::RPMTAG_INSTALLTIME)?),
+        }
+    }
+
+    #[inline]
+    pub fn get_provides(&self) -> Result<Vec<Dependency>, RPMError> {
+        self.get_dependencies(
+            IndexTag::RPMTAG_PROVIDENAME,
+            IndexTag::RPMTAG_PROVIDEFLAGS,
+            IndexTag::RPMTAG_PROVIDEVERSION,
+        )
+    }
+
+    #[inline]
+    pub fn get_requires(&self) -> Result<Vec<Dependency>, RPMError> {
+        self.get_dependencies(
+            IndexTag::RPMTAG_REQUIRENAME,
+            IndexTag::RPMTAG_REQUIREFLAGS,
+            IndexTag::RPMTAG_REQUIREVERSION,
+        )
+    }
+
+    #[inline]
+    pub fn get_conflicts(&self) -> Result<Vec<Dependency>, RPMError> {
+        self.get_dependencies(
+            IndexTag::RPMTAG_CONFLICTNAME,
+            IndexTag::RPMTAG_CONFLICTFLAGS,
+            IndexTag::RPMTAG_CONFLICTVERSION,
+        )
+    }
+
+    #[inline]
+    pub fn get_obsoletes(&self) -> Result<Vec<Dependency>, RPMError> {
+        self.get_dependencies(
+            IndexTag::RPMTAG_OBSOLETENAME,
+            IndexTag::RPMTAG_OBSOLETEFLAGS,
+            IndexTag::RPMTAG_OBSOLETEVERSION,
+        )
+    }
+
+    /// When the package was built, if recorded.
+    pub fn get_build_time(&self) -> Result<Timestamp, RPMError> {
+        self.get_entry_i32_data(IndexTag::RPMTAG_BUILDTIME)
+            .map(Timestamp::from_tag)
+    }
+
+    /// Where the sources were checked out from, see [`RPMBuilder::vcs`](crate::RPMBuilder::vcs).
+    pub fn get_vcs(&self) -> Result<&str, RPMError> {
+        self.get_entry_string_data(IndexTag::RPMTAG_VCS)
+    }
+
+    /// Link to the build which produced the package, `RPMTAG_DISTURL`.
+    pub fn get_build_url(&self) -> Result<&str, RPMError> {
+        self.get_entry_string_data(IndexTag::RPMTAG_DISTURL)
+    }
+
+    /// Times of the changelog entries, in the order of the entries.
+    pub fn get_changelog_times(&self) -> Result<Vec<Timestamp>, RPMError> {
+        Ok(self
+            .get_entry_i32_array_data(IndexTag::RPMTAG_CHANGELOGTIME)?
+            .into_iter()
+            .map(Timestamp::from_tag)
+            .collect())
+    }
+
+    /// Bytes taken by the files of the package once installed, what dnf shows as "Installed size".
+    pub fn get_installed_size(&self) -> Result<u64, RPMError> {
+        match self.get_entry_i64_data(IndexTag::RPMTAG_LONGSIZE) {
+            Ok(size) => Ok(size as u64),
+            // unsigned, despite the tag type
+            Err(RPMError::TagNotFound(_)) => self
+                .get_entry_i32_data(IndexTag::RPMTAG_SIZE)
+                .map(|size| size as u32 as u64),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The `rpmlib(...)` features of rpm the package requires, see [`Dependency::rpmlib`].
+    pub fn get_rpmlib_requires(&self) -> Result<Vec<Dependency>, RPMError> {
+        let mut requires = self.get_requires()?;
+        requires.retain(Dependency::is_rpmlib);
+        Ok(requires)
+    }
+
+    /// The prefixes the package can be relocated from, empty if it is not relocatable.
+    pub fn get_prefixes(&self) -> Result<&[String], RPMError> {
+        match self.get_entry_string_array_data(IndexTag::RPMTAG_PREFIXES) {
+            Err(RPMError::TagNotFound(_)) => Ok(&[]),
+            other => other,
+        }
+    }
+
+    /// Combine the three parallel arrays describing a dependency kind.
+    ///
+    /// A missing name tag means there are no dependencies of that kind.
+    fn get_dependencies(
+        &self,
+        name_tag: IndexTag,
+        flags_tag: IndexTag,
+        version_tag: IndexTag,
+    ) -> Result<Vec<Dependency>, RPMError> {
+        let names = match self.get_entry_string_array_data(name_tag) {
+            Ok(names) => names,
+            Err(RPMError::TagNotFound(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let flags = self.get_entry_i32_array_data(flags_tag).unwrap_or_default();
+        let versions = self
+            .get_entry_string_array_data(version_tag)
+            .unwrap_or_default();
+        Ok(names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                Dependency::new(
+                    name.clone(),
+                    flags.get(i).copied().unwrap_or_default() as u32,
+                    versions.get(i).cloned().unwrap_or_default(),
+                )
+            })
+            .collect())
     }
 
     /// Extract a the set of contained file names.
+    ///
+    /// Bytes of names that are not valid UTF-8 are replaced, see
+    /// [`get_file_paths_strict`](Self::get_file_paths_strict) and
+    /// [`get_file_path_bytes`](Self::get_file_path_bytes).
     pub fn get_file_paths(&self) -> Result<Vec<PathBuf>, RPMError> {
-        // reconstruct the messy de-constructed paths
-        let base = self.get_entry_string_array_data(IndexTag::RPMTAG_BASENAMES)?;
-        let biject = self.get_entry_i32_array_data(IndexTag::RPMTAG_DIRINDEXES)?;
-        let dirs = self.get_entry_string_array_data(IndexTag::RPMTAG_DIRNAMES)?;
+        let list = self.get_file_list()?;
+        Ok((0..list.len()).map(|index| list.path(index)).collect())
+    }
+
+    /// Like [`get_file_paths`](Self::get_file_paths), failing with
+    /// [`RPMError::NonUtf8FileName`] for names that are not valid UTF-8.
+    pub fn get_file_paths_strict(&self) -> Result<Vec<PathBuf>, RPMError> {
+        self.get_file_path_bytes()?
+            .into_iter()
+            .map(|path| match String::from_utf8(path) {
+                Ok(path) => Ok(PathBuf::from(path)),
+                Err(err) => Err(RPMError::NonUtf8FileName(
+                    String::from_utf8_lossy(err.as_bytes()).into_owned(),
+                )),
+            })
+            .collect()
+    }
 
-        let n = dirs.len();
-        let v = base
+    /// The file names as stored, names are arbitrary bytes for rpm.
+    ///
+    /// On unix, `std::os::unix::ffi::OsStrExt::from_bytes` turns them into paths.
+    pub fn get_file_path_bytes(&self) -> Result<Vec<Vec<u8>>, RPMError> {
+        let list = self.get_file_list()?;
+        if self.find_entry_or_err(&IndexTag::RPMTAG_BASENAMES).is_err() {
+            return Ok(self
+                .get_entry_raw_string_array_data(IndexTag::RPMTAG_OLDFILENAMES)?
+                .into_iter()
+                .map(<[u8]>::to_vec)
+                .collect());
+        }
+        let dirs = self.get_entry_raw_string_array_data(IndexTag::RPMTAG_DIRNAMES)?;
+        let bases = self.get_entry_raw_string_array_data(IndexTag::RPMTAG_BASENAMES)?;
+        Ok(list
+            .dir_indexes
             .iter()
-            .zip(biject.into_iter())
-            .try_fold::<Vec<PathBuf>, _, _>(
-                Vec::<PathBuf>::with_capacity(base.len()),
-                |mut acc, item| {
-                    let (base, dir_index) = item;
-                    if let Some(dir) = dirs.get(dir_index as usize) {
-                        acc.push(PathBuf::from(dir).join(base));
-                        Ok(acc)
-                    } else {
-                        Err(RPMError::InvalidTagIndex {
-                            tag: IndexTag::RPMTAG_DIRINDEXES.to_string(),
-                            index: dir_index as u32,
-                            bound: n as u32,
-                        })
-                    }
-                },
-            )?;
-        Ok(v)
+            .zip(bases)
+            .map(|(dir, base)| [dirs[*dir as usize], base].concat())
+            .collect())
+    }
+
+    /// The file names as stored, each directory only once, see [`FileList`].
+    ///
+    /// Cheaper than [`get_file_paths`](Self::get_file_paths) for packages with many files.
+    pub fn get_file_list(&self) -> Result<FileList<'_>, RPMError> {
+        // packages built by rpm before 4.0 list full paths instead
+        if self.find_entry_or_err(&IndexTag::RPMTAG_BASENAMES).is_err() {
+            if let Ok(paths) = self.get_entry_string_array_data(IndexTag::RPMTAG_OLDFILENAMES) {
+                return Ok(FileList::from_paths(paths));
+            }
+        }
+        let base_names = self.get_entry_string_array_data(IndexTag::RPMTAG_BASENAMES)?;
+        let dir_indexes = self.get_entry_i32_array_data(IndexTag::RPMTAG_DIRINDEXES)?;
+        let dir_names = self.get_entry_string_array_data(IndexTag::RPMTAG_DIRNAMES)?;
+        let dir_indexes = dir_indexes
+            .into_iter()
+            .take(base_names.len())
+            .map(|index| match dir_names.get(index as usize) {
+                Some(_) => Ok(index as u32),
+                None => Err(RPMError::InvalidTagIndex {
+                    tag: IndexTag::RPMTAG_DIRINDEXES.to_string(),
+                    index: index as u32,
+                    bound: dir_names.len() as u32,
+                }),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(FileList {
+            dir_names: Cow::Borrowed(dir_names),
+            base_names: Cow::Borrowed(&base_names[..dir_indexes.len()]),
+            dir_indexes,
+        })
     }
 
     /// The digest algorithm used per file.
@@ -530,7 +984,6 @@ impl Header<IndexTag> {
                 } else {
                     Some(FileDigest::load_from_str(algorithm, digest)?)
                 };
-                let utc = chrono::Utc;
                 acc.push(FileEntry {
                     path,
                     ownership: FileOwnership {
@@ -538,7 +991,7 @@ impl Header<IndexTag> {
                         group: group.to_owned(),
                     },
                     mode: mode.into(),
-                    modified_at: utc.timestamp(mtime as i64, 0u32),
+                    modified_at: Timestamp::from_tag(mtime).into(),
                     digest,
                     category: FileCategory::from_i32(flags).unwrap_or_default(),
                     size: size as usize,
@@ -557,26 +1010,33 @@ pub struct FileOwnership {
     group: String,
 }
 
+impl FileOwnership {
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+}
+
 /// Declaration what category this file belongs to
 /// @todo must be bitflags
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, enum_primitive_derive::Primitive)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Default, enum_primitive_derive::Primitive)]
 #[repr(i32)]
 pub enum FileCategory {
+    #[default]
     None = 0i32,
     Config = constants::RPMFILE_CONFIG,
     Doc = constants::RPMFILE_DOC,
 }
 
-impl Default for FileCategory {
-    fn default() -> Self {
-        Self::None
-    }
-}
-
 #[repr(i32)]
-#[derive(Debug, Clone, Copy, enum_primitive_derive::Primitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, enum_primitive_derive::Primitive)]
 pub enum FileDigestAlgorithm {
     // broken and very broken
+    // if the entry is missing, this is the default fallback
+    #[default]
     Md5 = constants::PGPHASHALGO_MD5,
     Sha1 = constants::PGPHASHALGO_SHA1,
     Md2 = constants::PGPHASHALGO_MD2,
@@ -593,13 +1053,6 @@ pub enum FileDigestAlgorithm {
     Sha2_224 = constants::PGPHASHALGO_SHA224,
 }
 
-impl Default for FileDigestAlgorithm {
-    fn default() -> Self {
-        // if the entry is missing, this is the default fallback
-        Self::Md5
-    }
-}
-
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub enum FileDigest {
     Md5(Vec<u8>),
@@ -626,6 +1079,91 @@ impl FileDigest {
             digest_algo => return Err(RPMError::UnsupportedFileDigestAlgorithm(digest_algo)),
         })
     }
+
+    /// The algorithm used to compute this digest.
+    pub fn algorithm(&self) -> FileDigestAlgorithm {
+        match self {
+            FileDigest::Md5(_) => FileDigestAlgorithm::Md5,
+            FileDigest::Sha2_256(_) => FileDigestAlgorithm::Sha2_256,
+            FileDigest::Sha2_384(_) => FileDigestAlgorithm::Sha2_384,
+            FileDigest::Sha2_512(_) => FileDigestAlgorithm::Sha2_512,
+            FileDigest::Sha2_224(_) => FileDigestAlgorithm::Sha2_224,
+        }
+    }
+
+    /// The raw digest bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            FileDigest::Md5(d)
+            | FileDigest::Sha2_256(d)
+            | FileDigest::Sha2_384(d)
+            | FileDigest::Sha2_512(d)
+            | FileDigest::Sha2_224(d) => d.as_slice(),
+        }
+    }
+
+    /// The digest as lowercase hex string, as stored in the header.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.as_bytes())
+    }
+}
+
+/// The file names of a header the way rpm stores them: `dir_names` holds every
+/// directory once, with a trailing `/`, and file `i` is
+/// `dir_names[dir_indexes[i]]` followed by `base_names[i]`.
+///
+/// Returned by [`Header::get_file_list`], the indexes are checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileList<'a> {
+    pub dir_names: Cow<'a, [String]>,
+    pub base_names: Cow<'a, [String]>,
+    pub dir_indexes: Vec<u32>,
+}
+
+impl<'a> FileList<'a> {
+    /// Split full paths, as listed by `RPMTAG_OLDFILENAMES`.
+    fn from_paths(paths: &[String]) -> FileList<'static> {
+        let mut dir_names = Vec::new();
+        let mut known = std::collections::HashMap::new();
+        let mut base_names = Vec::with_capacity(paths.len());
+        let mut dir_indexes = Vec::with_capacity(paths.len());
+        for path in paths {
+            let (dir, base) = path.split_at(path.rfind('/').map_or(0, |slash| slash + 1));
+            let index = *known.entry(dir).or_insert_with(|| {
+                dir_names.push(dir.to_string());
+                dir_names.len() as u32 - 1
+            });
+            dir_indexes.push(index);
+            base_names.push(base.to_string());
+        }
+        FileList {
+            dir_names: Cow::Owned(dir_names),
+            base_names: Cow::Owned(base_names),
+            dir_indexes,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.base_names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.base_names.is_empty()
+    }
+
+    /// Directory of file `index`, with a trailing `/`.
+    pub fn dir_name(&self, index: usize) -> &str {
+        &self.dir_names[self.dir_indexes[index] as usize]
+    }
+
+    pub fn path(&self, index: usize) -> PathBuf {
+        PathBuf::from(self.dir_name(index)).join(&self.base_names[index])
+    }
+
+    /// Directory and base name of every file.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        (0..self.len()).map(move |index| (self.dir_name(index), self.base_names[index].as_str()))
+    }
 }
 
 /// User facing accessor type for a file entry with contextual information
@@ -647,6 +1185,70 @@ pub struct FileEntry {
     pub digest: Option<FileDigest>,
 }
 
+fn check_padding(padding: &[u8], log: &mut QuirkLog) {
+    if padding.iter().any(|b| *b != 0) {
+        log.report(Diagnostic::SignaturePadding { len: padding.len() });
+    }
+}
+
+/// Read the data of `entry` from the header store.
+fn parse_entry_data<T: Tag>(entry: &mut IndexEntry<T>, store: &[u8]) -> Result<(), RPMError> {
+    let mut remaining = store
+        .get(entry.offset as usize..)
+        .filter(|_| entry.offset >= 0)
+        .ok_or_else(|| RPMError::InvalidTagIndex {
+            tag: entry.tag.to_string(),
+            index: entry.offset as u32,
+            bound: store.len() as u32,
+        })?;
+    match &mut entry.data {
+        IndexData::Null => {}
+        IndexData::Char(ref mut chars) => {
+            parse_entry_data_number(remaining, entry.num_items, chars, be_u8)?;
+        }
+        IndexData::Int8(ref mut ints) => {
+            parse_entry_data_number(remaining, entry.num_items, ints, be_i8)?;
+        }
+        IndexData::Int16(ref mut ints) => {
+            parse_entry_data_number(remaining, entry.num_items, ints, be_i16)?;
+        }
+        IndexData::Int32(ref mut ints) => {
+            parse_entry_data_number(remaining, entry.num_items, ints, be_i32)?;
+        }
+        IndexData::Int64(ref mut ints) => {
+            parse_entry_data_number(remaining, entry.num_items, ints, be_i64)?;
+        }
+        IndexData::StringTag(ref mut string) => {
+            let (_rest, raw_string) = complete::take_till(|item| item == 0)(remaining)?;
+            string.push_str(String::from_utf8_lossy(raw_string).as_ref());
+        }
+        IndexData::Bin(ref mut bin) => {
+            parse_entry_data_number(remaining, entry.num_items, bin, be_u8)?;
+        }
+        IndexData::StringArray(ref mut strings) => {
+            for _ in 0..entry.num_items {
+                let (rest, raw_string) = complete::take_till(|item| item == 0)(remaining)?;
+                // the null byte is still in there.. we need to cut it out.
+                remaining = rest.get(1..).ok_or_else(|| RPMError::Malformed {
+                    what: "header entry",
+                    reason: "unterminated string".to_string(),
+                })?;
+                let string = String::from_utf8_lossy(raw_string).to_string();
+                strings.push(string);
+            }
+        }
+        IndexData::I18NString(ref mut strings) => {
+            for _ in 0..entry.num_items {
+                let (rest, raw_string) = complete::take_till(|item| item == 0)(remaining)?;
+                remaining = rest;
+                let string = String::from_utf8_lossy(raw_string).to_string();
+                strings.push(string);
+            }
+        }
+    }
+    Ok(())
+}
+
 fn parse_entry_data_number<'a, T, E, F>(
     mut input: &'a [u8],
     num_items: u32,
@@ -674,7 +1276,10 @@ mod tests2 {
     fn signature_header_build() {
         let size: i32 = 209_348;
         let md5sum: &[u8] = &[22u8; 16];
-        let sha1: String = "5A884F0CB41EC3DA6D6E7FC2F6AB9DECA8826E8D".to_owned();
+        let digest = HeaderDigest {
+            sha1: "5A884F0CB41EC3DA6D6E7FC2F6AB9DECA8826E8D".to_owned(),
+            sha256: "2CF24DBA5FB0A30E26E83B2AC5B9E29E1B161E5C1FA7425E73043362938B9824".to_owned(),
+        };
         let rsa_spanning_header: &[u8] = b"111222333444";
         let rsa_spanning_header_and_archive: &[u8] = b"7777888899990000";
 
@@ -695,7 +1300,12 @@ mod tests2 {
                 IndexEntry::new(
                     IndexSignatureTag::RPMSIGTAG_SHA1,
                     offset,
-                    IndexData::StringTag(sha1.clone()),
+                    IndexData::StringTag(digest.sha1.clone()),
+                ),
+                IndexEntry::new(
+                    IndexSignatureTag::RPMSIGTAG_SHA256,
+                    offset,
+                    IndexData::StringTag(digest.sha256.clone()),
                 ),
                 IndexEntry::new(
                     IndexSignatureTag::RPMSIGTAG_RSA,
@@ -714,13 +1324,201 @@ mod tests2 {
         let built = Header::<IndexSignatureTag>::new_signature_header(
             size,
             md5sum,
-            sha1,
+            &digest,
             rsa_spanning_header,
             rsa_spanning_header_and_archive,
         );
 
         assert_eq!(built, truth);
     }
+
+    #[test]
+    fn header_blob() -> Result<(), Box<dyn std::error::Error>> {
+        let header = Header::<IndexSignatureTag>::from_entries(
+            vec![
+                IndexEntry::from_data(
+                    IndexSignatureTag::RPMSIGTAG_SHA256,
+                    IndexData::StringTag("00".repeat(32)),
+                ),
+                IndexEntry::from_data(IndexSignatureTag::RPMSIGTAG_SIZE, IndexData::Int32(vec![7])),
+                IndexEntry::from_data(
+                    IndexSignatureTag::RPMSIGTAG_MD5,
+                    IndexData::Bin(vec![1; 16]),
+                ),
+            ],
+            IndexSignatureTag::HEADER_SIGNATURES,
+        );
+        let tags = header
+            .index_entries
+            .iter()
+            .map(|entry| *entry.tag())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                IndexSignatureTag::HEADER_SIGNATURES,
+                IndexSignatureTag::RPMSIGTAG_SHA256,
+                IndexSignatureTag::RPMSIGTAG_SIZE,
+                IndexSignatureTag::RPMSIGTAG_MD5,
+            ],
+            tags
+        );
+        assert_eq!(&IndexData::Int32(vec![7]), header.index_entries[2].data());
+
+        let mut written = Vec::new();
+        header.write(&mut written)?;
+        assert_eq!(header.serialized_len(), written.len());
+        let blob = header.to_blob();
+        assert_eq!(&written[8..], blob.as_slice());
+        assert_eq!(header, Header::parse_blob(&blob)?);
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_file_names() -> Result<(), Box<dyn std::error::Error>> {
+        let header = Header::<IndexTag>::from_entries(
+            vec![IndexEntry::new(
+                IndexTag::RPMTAG_OLDFILENAMES,
+                0,
+                IndexData::StringArray(vec!["/usr/bin/awesome".to_string()]),
+            )],
+            IndexTag::RPMTAG_HEADERIMMUTABLE,
+        );
+        assert_eq!(
+            vec![PathBuf::from("/usr/bin/awesome")],
+            header.get_file_paths()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn file_list() -> Result<(), Box<dyn std::error::Error>> {
+        let strings =
+            |items: &[&str]| IndexData::StringArray(items.iter().map(|s| s.to_string()).collect());
+        let header = Header::<IndexTag>::from_entries(
+            vec![
+                IndexEntry::new(
+                    IndexTag::RPMTAG_DIRINDEXES,
+                    0,
+                    IndexData::Int32(vec![0, 1, 0]),
+                ),
+                IndexEntry::new(IndexTag::RPMTAG_BASENAMES, 0, strings(&["a", "b", "c"])),
+                IndexEntry::new(
+                    IndexTag::RPMTAG_DIRNAMES,
+                    0,
+                    strings(&["/etc/", "/usr/bin/"]),
+                ),
+            ],
+            IndexTag::RPMTAG_HEADERIMMUTABLE,
+        );
+        let list = header.get_file_list()?;
+        assert_eq!(3, list.len());
+        assert_eq!(
+            vec![("/etc/", "a"), ("/usr/bin/", "b"), ("/etc/", "c")],
+            list.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(PathBuf::from("/usr/bin/b"), list.path(1));
+
+        let legacy = Header::<IndexTag>::from_entries(
+            vec![IndexEntry::new(
+                IndexTag::RPMTAG_OLDFILENAMES,
+                0,
+                strings(&["/etc/a", "/usr/bin/b", "/etc/c"]),
+            )],
+            IndexTag::RPMTAG_HEADERIMMUTABLE,
+        );
+        assert_eq!(list, legacy.get_file_list()?);
+
+        let broken = Header::<IndexTag>::from_entries(
+            vec![
+                IndexEntry::new(IndexTag::RPMTAG_DIRINDEXES, 0, IndexData::Int32(vec![2])),
+                IndexEntry::new(IndexTag::RPMTAG_BASENAMES, 0, strings(&["a"])),
+                IndexEntry::new(IndexTag::RPMTAG_DIRNAMES, 0, strings(&["/etc/"])),
+            ],
+            IndexTag::RPMTAG_HEADERIMMUTABLE,
+        );
+        assert!(matches!(
+            broken.get_file_list(),
+            Err(RPMError::InvalidTagIndex { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn installed_size() -> Result<(), Box<dyn std::error::Error>> {
+        let with =
+            |entries| Header::<IndexTag>::from_entries(entries, IndexTag::RPMTAG_HEADERIMMUTABLE);
+        let small = with(vec![IndexEntry::new(
+            IndexTag::RPMTAG_SIZE,
+            0,
+            IndexData::Int32(vec![3_000_000_000u32 as i32]),
+        )]);
+        assert_eq!(3_000_000_000, small.get_installed_size()?);
+        let large = with(vec![IndexEntry::new(
+            IndexTag::RPMTAG_LONGSIZE,
+            0,
+            IndexData::Int64(vec![8_000_000_000]),
+        )]);
+        assert_eq!(8_000_000_000, large.get_installed_size()?);
+        Ok(())
+    }
+
+    #[test]
+    fn store_layout() -> Result<(), Box<dyn std::error::Error>> {
+        let header = Header::<IndexTag>::from_entries(
+            vec![
+                IndexEntry::new(
+                    IndexTag::RPMTAG_NAME,
+                    0,
+                    IndexData::StringTag("awesome".to_string()),
+                ),
+                IndexEntry::new(IndexTag::RPMTAG_FILEMODES, 0, IndexData::Int16(vec![1, 2])),
+                IndexEntry::new(IndexTag::RPMTAG_SIZE, 0, IndexData::Int32(vec![3])),
+                IndexEntry::new(IndexTag::RPMTAG_LONGSIZE, 0, IndexData::Int64(vec![4])),
+            ],
+            IndexTag::RPMTAG_HEADERIMMUTABLE,
+        );
+        // sorted by tag and aligned by type
+        let offsets = header
+            .index_entries
+            .iter()
+            .map(|entry| entry.offset)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![24, 0, 8, 12, 16], offsets);
+        assert_eq!(40, header.store.len());
+        assert_eq!(header.store.len(), header.store.capacity());
+
+        let mut out = Vec::new();
+        header.write(&mut out)?;
+        assert_eq!(header.serialized_len(), out.len());
+        assert_eq!(header, Header::<IndexTag>::parse(&mut out.as_slice())?);
+        Ok(())
+    }
+
+    #[test]
+    fn region_trailer_validation() -> Result<(), Box<dyn std::error::Error>> {
+        let header = Header::<IndexTag>::from_entries(
+            vec![IndexEntry::new(
+                IndexTag::RPMTAG_NAME,
+                0,
+                IndexData::StringTag("awesome".to_string()),
+            )],
+            IndexTag::RPMTAG_HEADERIMMUTABLE,
+        );
+        let mut bytes = Vec::new();
+        header.write(&mut bytes)?;
+        assert_eq!(bytes, header.immutable_region()?);
+        assert_eq!(HeaderDigest::of(&bytes), header.header_digest()?);
+        assert_eq!(header, Header::<IndexTag>::parse(&mut bytes.as_slice())?);
+
+        // the trailer sits at the end of the store, its offset says how many entries it covers
+        let trailer_offset = bytes.len() - 16 + 8;
+        bytes[trailer_offset..trailer_offset + 4].copy_from_slice(&(-48i32).to_be_bytes());
+        assert!(matches!(
+            Header::<IndexTag>::parse(&mut bytes.as_slice()),
+            Err(RPMError::InvalidRegion { .. })
+        ));
+        Ok(())
+    }
 }
 
 /// A header keeping track of all other headerr records.
@@ -771,26 +1569,13 @@ impl IndexHeader {
         })
     }
 
-    pub(crate) fn write<W: std::io::Write>(&self, out: &mut W) -> Result<(), RPMError> {
-        out.write_all(&self.magic)?;
-        out.write_all(&self.version.to_be_bytes())?;
-        out.write_all(&[0; 4])?;
-        out.write_all(&self.num_entries.to_be_bytes())?;
-        out.write_all(&self.header_size.to_be_bytes())?;
-        Ok(())
-    }
-
-    #[cfg(feature = "async-tokio")]
-    pub(crate) async fn write_async<W: tokio::io::AsyncWrite + Unpin>(
-        &self,
-        out: &mut W,
-    ) -> Result<(), RPMError> {
-        out.write_all(&self.magic).await?;
-        out.write_all(&self.version.to_be_bytes()).await?;
-        out.write_all(&[0; 4]).await?;
-        out.write_all(&self.num_entries.to_be_bytes()).await?;
-        out.write_all(&self.header_size.to_be_bytes()).await?;
-        Ok(())
+    // 16 bytes
+    pub(crate) fn append(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.magic);
+        out.push(self.version);
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&self.num_entries.to_be_bytes());
+        out.extend_from_slice(&self.header_size.to_be_bytes());
     }
 
     pub(crate) fn new(num_entries: u32, header_size: u32) -> Self {
@@ -804,8 +1589,9 @@ impl IndexHeader {
 }
 
 /// A singel entry within the [`IndexHeader`](self::IndexHeader)
+/// A tag of a [`Header`] and its data.
 #[derive(Debug, PartialEq)]
-pub(crate) struct IndexEntry<T: num::FromPrimitive> {
+pub struct IndexEntry<T: num::FromPrimitive> {
     pub(crate) tag: T,
     pub(crate) data: IndexData,
     pub(crate) offset: i32,
@@ -851,26 +1637,12 @@ impl<T: num::FromPrimitive + num::ToPrimitive + fmt::Debug + TypeName> IndexEntr
         ))
     }
 
-    #[cfg(feature = "async-tokio")]
-    pub(crate) async fn write_index_async<W: tokio::io::AsyncWrite + Unpin>(
-        &self,
-        out: &mut W,
-    ) -> Result<(), RPMError> {
-        let mut written = out.write(&self.tag.to_u32().unwrap().to_be_bytes()).await?;
-        written += out.write(&self.data.to_u32().to_be_bytes()).await?;
-        written += out.write(&self.offset.to_be_bytes()).await?;
-        written += out.write(&self.num_items.to_be_bytes()).await?;
-        assert_eq!(16, written, "there should be 16 bytes written");
-        Ok(())
-    }
-
-    pub(crate) fn write_index<W: std::io::Write>(&self, out: &mut W) -> Result<(), RPMError> {
-        let mut written = out.write(&self.tag.to_u32().unwrap().to_be_bytes())?;
-        written += out.write(&self.data.to_u32().to_be_bytes())?;
-        written += out.write(&self.offset.to_be_bytes())?;
-        written += out.write(&self.num_items.to_be_bytes())?;
-        assert_eq!(16, written, "there should be 16 bytes written");
-        Ok(())
+    // 16 bytes
+    pub(crate) fn append_index(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.tag.to_u32().unwrap().to_be_bytes());
+        out.extend_from_slice(&self.data.to_u32().to_be_bytes());
+        out.extend_from_slice(&self.offset.to_be_bytes());
+        out.extend_from_slice(&self.num_items.to_be_bytes());
     }
 
     pub(crate) fn new(tag: T, offset: i32, data: IndexData) -> IndexEntry<T> {
@@ -881,11 +1653,24 @@ impl<T: num::FromPrimitive + num::ToPrimitive + fmt::Debug + TypeName> IndexEntr
             data,
         }
     }
+
+    /// An entry to build a header of, see [`Header::from_entries`].
+    pub fn from_data(tag: T, data: IndexData) -> IndexEntry<T> {
+        Self::new(tag, 0, data)
+    }
+
+    pub fn tag(&self) -> &T {
+        &self.tag
+    }
+
+    pub fn data(&self) -> &IndexData {
+        &self.data
+    }
 }
 
 /// Data as present in a [`IndexEntry`](self::IndexEntry) .
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) enum IndexData {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexData {
     Null,
     Char(Vec<u8>),
     Int8(Vec<i8>),
@@ -917,84 +1702,73 @@ impl fmt::Display for IndexData {
 }
 
 impl IndexData {
-    pub(crate) fn append(&self, store: &mut Vec<u8>) -> u32 {
-        match &self {
+    /// Alignment of the data within the store.
+    pub(crate) fn alignment(&self) -> usize {
+        match self {
+            IndexData::Int16(_) => 2,
+            IndexData::Int32(_) => 4,
+            IndexData::Int64(_) => 8,
+            _ => 1,
+        }
+    }
+
+    /// Bytes the data takes in the store, without alignment.
+    pub(crate) fn store_len(&self) -> usize {
+        match self {
             IndexData::Null => 0,
-            IndexData::Char(d) => {
-                store.extend_from_slice(d);
-                0
-            }
-            IndexData::Int8(d) => {
-                for i in d.iter().map(|i| i.to_be_bytes()) {
-                    store.push(i[0]);
-                }
-                0
+            IndexData::Char(d) | IndexData::Bin(d) => d.len(),
+            IndexData::Int8(d) => d.len(),
+            IndexData::Int16(d) => d.len() * 2,
+            IndexData::Int32(d) => d.len() * 4,
+            IndexData::Int64(d) => d.len() * 8,
+            IndexData::StringTag(d) => d.len() + 1,
+            IndexData::StringArray(d) | IndexData::I18NString(d) => {
+                d.iter().map(|item| item.len() + 1).sum()
             }
-            IndexData::Int16(d) => {
-                // align to 2 bytes
+        }
+    }
 
-                let alignment = if store.len() % 2 != 0 {
-                    store.push(0);
-                    1
-                } else {
-                    0
-                };
-                let iter = d.iter().flat_map(|item| item.to_be_bytes().to_vec());
-                for byte in iter {
-                    store.push(byte);
+    /// Store length after appending the data to a store of `len` bytes.
+    pub(crate) fn aligned_end(&self, len: usize) -> usize {
+        let alignment = self.alignment();
+        (len + alignment - 1) / alignment * alignment + self.store_len()
+    }
+
+    pub(crate) fn append(&self, store: &mut Vec<u8>) -> u32 {
+        let unaligned = store.len();
+        let alignment = self.alignment();
+        store.resize((unaligned + alignment - 1) / alignment * alignment, 0);
+        match &self {
+            IndexData::Null => {}
+            IndexData::Char(d) | IndexData::Bin(d) => store.extend_from_slice(d),
+            IndexData::Int8(d) => store.extend(d.iter().map(|i| *i as u8)),
+            IndexData::Int16(d) => {
+                for item in d {
+                    store.extend_from_slice(&item.to_be_bytes());
                 }
-                alignment
             }
             IndexData::Int32(d) => {
-                // align to 4 bytes
-                let mut alignment = 0;
-                while store.len() % 4 > 0 {
-                    store.push(0);
-                    alignment += 1;
-                }
-                let iter = d.iter().flat_map(|item| item.to_be_bytes().to_vec());
-                for byte in iter {
-                    store.push(byte);
+                for item in d {
+                    store.extend_from_slice(&item.to_be_bytes());
                 }
-                alignment
             }
             IndexData::Int64(d) => {
-                // align to 8 bytes
-                let mut alignment = 0;
-                while store.len() % 8 > 0 {
-                    store.push(0);
-                    alignment += 1;
-                }
-                let iter = d.iter().flat_map(|item| item.to_be_bytes().to_vec());
-                for byte in iter {
-                    store.push(byte);
+                for item in d {
+                    store.extend_from_slice(&item.to_be_bytes());
                 }
-                alignment
             }
             IndexData::StringTag(d) => {
                 store.extend_from_slice(d.as_bytes());
                 store.push(0);
-                0
-            }
-            IndexData::Bin(d) => {
-                store.extend_from_slice(d);
-                0
-            }
-            IndexData::StringArray(d) => {
-                for item in d {
-                    store.extend_from_slice(item.as_bytes());
-                    store.push(0);
-                }
-                0
             }
-            IndexData::I18NString(d) => {
+            IndexData::StringArray(d) | IndexData::I18NString(d) => {
                 for item in d {
                     store.extend_from_slice(item.as_bytes());
                     store.push(0);
                 }
-                0
             }
         }
+        (store.len() - unaligned - self.store_len()) as u32
     }
 
     pub(crate) fn num_items(&self) -> u32 {
@@ -0,0 +1,315 @@
+//! Borrowed views of headers, for scanning many of them without allocating.
+
+use std::marker::PhantomData;
+
+use nom::number::complete::be_u32;
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::{Header, IndexHeader, QuirkLog, Tag};
+
+const TYPE_CHAR: u32 = 1;
+const TYPE_INT8: u32 = 2;
+const TYPE_INT32: u32 = 4;
+const TYPE_STRING: u32 = 6;
+const TYPE_BIN: u32 = 7;
+const TYPE_STRING_ARRAY: u32 = 8;
+const TYPE_I18NSTRING: u32 = 9;
+
+/// A header borrowed from the buffer it was read into.
+///
+/// Unlike [`Header`] nothing is decoded up front, accessors locate the entry
+/// and hand out `&'a str` and `&'a [u8]` pointing into the original buffer.
+/// This suits workloads looking at a few tags of a great many headers, like
+/// scanning repodata or an rpm database. Use [`to_header`](Self::to_header)
+/// for everything else.
+#[derive(Debug, Clone, Copy)]
+pub struct HeaderRef<'a, T> {
+    index: &'a [u8],
+    store: &'a [u8],
+    tag: PhantomData<T>,
+}
+
+/// The raw index entry of a [`HeaderRef`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryRef {
+    pub raw_tag: u32,
+    pub raw_data_type: u32,
+    pub offset: i32,
+    pub count: u32,
+}
+
+impl<'a, T: Tag> HeaderRef<'a, T> {
+    /// Borrow the header at the start of `input`, including its magic,
+    /// and return it together with the bytes behind it.
+    ///
+    /// Signature headers are followed by padding to a multiple of 8 bytes, which is not skipped.
+    pub fn parse(input: &'a [u8]) -> Result<(Self, &'a [u8]), RPMError> {
+        let index_header = IndexHeader::parse(input)?;
+        Self::split(
+            input.get(16..).unwrap_or_default(),
+            index_header.num_entries,
+            index_header.header_size,
+        )
+    }
+
+    /// Borrow a header blob as stored in the rpm database, see [`Header::parse_blob`].
+    pub fn parse_blob(blob: &'a [u8]) -> Result<Self, RPMError> {
+        let (rest, num_entries) = be_u32(blob)?;
+        let (rest, header_size) = be_u32(rest)?;
+        let (header, rest) = Self::split(rest, num_entries, header_size)?;
+        if !rest.is_empty() {
+            return Err(RPMError::Malformed {
+                what: "header blob",
+                reason: format!("{} trailing bytes", rest.len()),
+            });
+        }
+        Ok(header)
+    }
+
+    fn split(
+        input: &'a [u8],
+        num_entries: u32,
+        header_size: u32,
+    ) -> Result<(Self, &'a [u8]), RPMError> {
+        let index_len = num_entries as usize * 16;
+        let len = index_len + header_size as usize;
+        if input.len() < len {
+            return Err(RPMError::Malformed {
+                what: "header",
+                reason: format!(
+                    "needs {} bytes of index and store, got {}",
+                    len,
+                    input.len()
+                ),
+            });
+        }
+        let header = HeaderRef {
+            index: &input[..index_len],
+            store: &input[index_len..len],
+            tag: PhantomData,
+        };
+        Ok((header, &input[len..]))
+    }
+
+    /// Number of bytes the header takes, including the 16 bytes of magic and counts.
+    pub fn len(&self) -> usize {
+        16 + self.index.len() + self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = EntryRef> + 'a {
+        self.index.chunks_exact(16).map(|raw| EntryRef {
+            raw_tag: u32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]]),
+            raw_data_type: u32::from_be_bytes([raw[4], raw[5], raw[6], raw[7]]),
+            offset: i32::from_be_bytes([raw[8], raw[9], raw[10], raw[11]]),
+            count: u32::from_be_bytes([raw[12], raw[13], raw[14], raw[15]]),
+        })
+    }
+
+    pub fn contains(&self, tag: T) -> bool {
+        self.find(tag).is_ok()
+    }
+
+    fn find(&self, tag: T) -> Result<EntryRef, RPMError> {
+        let raw_tag = tag.to_u32().unwrap_or_default();
+        self.entries()
+            .find(|entry| entry.raw_tag == raw_tag)
+            .ok_or_else(|| RPMError::TagNotFound(tag.to_string()))
+    }
+
+    /// The entry of `tag`, which must have one of `data_types`, and the store from its offset on.
+    fn data(&self, tag: T, data_types: &[u32]) -> Result<(EntryRef, &'a [u8]), RPMError> {
+        let entry = self.find(tag)?;
+        if !data_types.contains(&entry.raw_data_type) {
+            return Err(RPMError::UnexpectedTagDataType {
+                expected_data_type: match data_types[0] {
+                    TYPE_INT32 => "i32",
+                    TYPE_STRING => "string",
+                    TYPE_BIN => "binary",
+                    _ => "string array",
+                },
+                actual_data_type: format!("type {}", entry.raw_data_type),
+                tag: tag.to_string(),
+            });
+        }
+        let store = self
+            .store
+            .get(entry.offset as usize..)
+            .filter(|_| entry.offset >= 0)
+            .ok_or_else(|| RPMError::InvalidTagIndex {
+                tag: tag.to_string(),
+                index: entry.offset as u32,
+                bound: self.store.len() as u32,
+            })?;
+        Ok((entry, store))
+    }
+
+    pub fn get_binary(&self, tag: T) -> Result<&'a [u8], RPMError> {
+        let (entry, store) = self.data(tag, &[TYPE_BIN, TYPE_CHAR, TYPE_INT8])?;
+        store
+            .get(..entry.count as usize)
+            .ok_or_else(|| out_of_bounds(tag, entry, self.store.len()))
+    }
+
+    /// A string, or the first string of an i18n string array.
+    pub fn get_string(&self, tag: T) -> Result<&'a str, RPMError> {
+        let (entry, store) = self.data(tag, &[TYPE_STRING, TYPE_I18NSTRING])?;
+        Strings { tag, store }
+            .next()
+            .ok_or_else(|| out_of_bounds(tag, entry, self.store.len()))?
+    }
+
+    pub fn get_string_array(&self, tag: T) -> Result<Vec<&'a str>, RPMError> {
+        let (entry, store) = self.data(tag, &[TYPE_STRING_ARRAY, TYPE_I18NSTRING])?;
+        let strings = Strings { tag, store }
+            .take(entry.count as usize)
+            .collect::<Result<Vec<_>, _>>()?;
+        if strings.len() != entry.count as usize {
+            return Err(out_of_bounds(tag, entry, self.store.len()));
+        }
+        Ok(strings)
+    }
+
+    pub fn get_i32(&self, tag: T) -> Result<i32, RPMError> {
+        let (entry, store) = self.data(tag, &[TYPE_INT32])?;
+        match store.get(..4) {
+            Some(raw) if entry.count > 0 => {
+                Ok(i32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]]))
+            }
+            _ => Err(out_of_bounds(tag, entry, self.store.len())),
+        }
+    }
+
+    pub fn get_i32_array(&self, tag: T) -> Result<Vec<i32>, RPMError> {
+        let (entry, store) = self.data(tag, &[TYPE_INT32])?;
+        let bytes = store
+            .get(..entry.count as usize * 4)
+            .ok_or_else(|| out_of_bounds(tag, entry, self.store.len()))?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|raw| i32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]]))
+            .collect())
+    }
+
+    /// Decode all entries into an owned [`Header`].
+    pub fn to_header(&self) -> Result<Header<T>, RPMError> {
+        let index_header =
+            IndexHeader::new((self.index.len() / 16) as u32, self.store.len() as u32);
+        let mut bytes = Vec::with_capacity(self.index.len() + self.store.len());
+        bytes.extend_from_slice(self.index);
+        bytes.extend_from_slice(self.store);
+        Header::parse_header(index_header, &bytes, &mut QuirkLog::strict())
+    }
+}
+
+impl<'a> HeaderRef<'a, IndexTag> {
+    pub fn get_name(&self) -> Result<&'a str, RPMError> {
+        self.get_string(IndexTag::RPMTAG_NAME)
+    }
+
+    /// The epoch, `0` if the package does not declare one.
+    pub fn get_epoch(&self) -> Result<i32, RPMError> {
+        match self.get_i32(IndexTag::RPMTAG_EPOCH) {
+            Err(RPMError::TagNotFound(_)) => Ok(0),
+            epoch => epoch,
+        }
+    }
+
+    pub fn get_version(&self) -> Result<&'a str, RPMError> {
+        self.get_string(IndexTag::RPMTAG_VERSION)
+    }
+
+    pub fn get_release(&self) -> Result<&'a str, RPMError> {
+        self.get_string(IndexTag::RPMTAG_RELEASE)
+    }
+
+    pub fn get_arch(&self) -> Result<&'a str, RPMError> {
+        self.get_string(IndexTag::RPMTAG_ARCH)
+    }
+
+    pub fn get_summary(&self) -> Result<&'a str, RPMError> {
+        self.get_string(IndexTag::RPMTAG_SUMMARY)
+    }
+}
+
+fn out_of_bounds<T: Tag>(tag: T, entry: EntryRef, bound: usize) -> RPMError {
+    RPMError::InvalidTagIndex {
+        tag: tag.to_string(),
+        index: entry.offset as u32,
+        bound: bound as u32,
+    }
+}
+
+/// NUL terminated strings, one after another.
+struct Strings<'a, T> {
+    tag: T,
+    store: &'a [u8],
+}
+
+impl<'a, T: Tag> Iterator for Strings<'a, T> {
+    type Item = Result<&'a str, RPMError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let end = self.store.iter().position(|b| *b == 0)?;
+        let (raw, rest) = self.store.split_at(end);
+        self.store = &rest[1..];
+        Some(std::str::from_utf8(raw).map_err(|_| RPMError::InvalidUtf8 {
+            tag: self.tag.to_string(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RPMPackage;
+
+    #[test]
+    fn borrowed_package_headers() -> Result<(), Box<dyn std::error::Error>> {
+        let raw = std::fs::read("./test_assets/389-ds-base-devel-1.3.8.4-15.el7.x86_64.rpm")?;
+        let package = RPMPackage::parse(&mut raw.as_slice())?;
+
+        let (signature, rest) = HeaderRef::<IndexSignatureTag>::parse(&raw[LEAD_SIZE..])?;
+        assert_eq!(
+            package.metadata.signature.index_entries.len(),
+            signature.entries().count()
+        );
+        let padding = (8 - signature.len() % 8) % 8;
+        let (header, payload) = HeaderRef::<IndexTag>::parse(&rest[padding..])?;
+        assert_eq!(package.content.as_slice(), payload);
+
+        let owned = &package.metadata.header;
+        assert_eq!(owned.get_name()?, header.get_name()?);
+        assert_eq!(owned.get_version()?, header.get_version()?);
+        assert_eq!(owned.get_release()?, header.get_release()?);
+        assert_eq!(owned.get_arch()?, header.get_arch()?);
+        assert_eq!(owned.get_summary()?, header.get_summary()?);
+        assert_eq!(0, header.get_epoch()?);
+        assert_eq!(
+            owned.get_entry_string_array_data(IndexTag::RPMTAG_BASENAMES)?,
+            header.get_string_array(IndexTag::RPMTAG_BASENAMES)?
+        );
+        assert_eq!(
+            owned.get_entry_i32_array_data(IndexTag::RPMTAG_DIRINDEXES)?,
+            header.get_i32_array(IndexTag::RPMTAG_DIRINDEXES)?
+        );
+        assert_eq!(
+            signature.get_string(IndexSignatureTag::RPMSIGTAG_SHA1)?,
+            package
+                .metadata
+                .signature
+                .get_entry_string_data(IndexSignatureTag::RPMSIGTAG_SHA1)?
+        );
+        assert!(matches!(
+            header.get_i32(IndexTag::RPMTAG_NAME),
+            Err(RPMError::UnexpectedTagDataType { .. })
+        ));
+        assert_eq!(owned, &header.to_header()?);
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+//! Auditing headers for the layout rpm itself writes.
+
+use std::fmt;
+
+use crate::errors::*;
+
+use super::{Header, Tag};
+
+/// A deviation from the layout rpm writes headers in.
+///
+/// rpm reads such headers fine, but tools parsing headers on their own may
+/// not, e.g. those looking tags up by binary search.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutIssue {
+    /// The header has no region tag as its first entry.
+    MissingRegion { store_type: &'static str },
+    /// The region tag does not describe the header.
+    InvalidRegion {
+        store_type: &'static str,
+        reason: &'static str,
+    },
+    /// Entries behind the region, as added by the rpm database.
+    OutsideRegion {
+        store_type: &'static str,
+        entries: usize,
+    },
+    /// `tag` does not come after the entry before it, or appears twice.
+    Unsorted {
+        store_type: &'static str,
+        tag: String,
+    },
+    /// The data of `tag` is not aligned to its type.
+    Misaligned {
+        store_type: &'static str,
+        tag: String,
+        offset: i32,
+        alignment: usize,
+    },
+    /// The data of `tag` does not follow the data of the entry before it, the
+    /// region trailer coming last.
+    DataOutOfOrder {
+        store_type: &'static str,
+        tag: String,
+    },
+    /// Unused bytes in front of the data of `tag`, beyond its alignment.
+    Gap {
+        store_type: &'static str,
+        tag: String,
+        len: usize,
+    },
+}
+
+impl fmt::Display for LayoutIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutIssue::MissingRegion { store_type } => {
+                write!(f, "{} header without region tag", store_type)
+            }
+            LayoutIssue::InvalidRegion { store_type, reason } => {
+                write!(
+                    f,
+                    "{} header has an invalid region - {}",
+                    store_type, reason
+                )
+            }
+            LayoutIssue::OutsideRegion {
+                store_type,
+                entries,
+            } => write!(
+                f,
+                "{} header has {} entries outside its region",
+                store_type, entries
+            ),
+            LayoutIssue::Unsorted { store_type, tag } => {
+                write!(f, "{} entry {} is out of order", store_type, tag)
+            }
+            LayoutIssue::Misaligned {
+                store_type,
+                tag,
+                offset,
+                alignment,
+            } => write!(
+                f,
+                "{} entry {} at offset {} is not aligned to {} bytes",
+                store_type, tag, offset, alignment
+            ),
+            LayoutIssue::DataOutOfOrder { store_type, tag } => {
+                write!(f, "data of {} entry {} is out of order", store_type, tag)
+            }
+            LayoutIssue::Gap {
+                store_type,
+                tag,
+                len,
+            } => write!(
+                f,
+                "{} unused bytes before the data of {} entry {}",
+                len, store_type, tag
+            ),
+        }
+    }
+}
+
+impl<T: Tag> Header<T> {
+    /// Everything deviating from the layout rpm writes, empty for canonical headers.
+    ///
+    /// rpm sorts entries by tag, aligns their data to its type without further
+    /// gaps, stores the data in the order of the entries and puts the region
+    /// tag first, its trailer at the end of the store.
+    pub fn audit_layout(&self) -> Vec<LayoutIssue> {
+        let store_type = T::type_name();
+        let mut issues = Vec::new();
+        let (entries, has_region) = match self.region() {
+            Ok(Some((entries, _))) => {
+                if entries < self.index_entries.len() {
+                    issues.push(LayoutIssue::OutsideRegion {
+                        store_type,
+                        entries: self.index_entries.len() - entries,
+                    });
+                }
+                // the region tag is laid out last
+                let mut entries = self.index_entries[1..entries].iter().collect::<Vec<_>>();
+                entries.push(&self.index_entries[0]);
+                (entries, true)
+            }
+            Ok(None) => {
+                issues.push(LayoutIssue::MissingRegion { store_type });
+                (self.index_entries.iter().collect(), false)
+            }
+            Err(err) => {
+                let reason = match err {
+                    RPMError::InvalidRegion { reason, .. } => reason,
+                    _ => "unreadable region",
+                };
+                issues.push(LayoutIssue::InvalidRegion { store_type, reason });
+                return issues;
+            }
+        };
+
+        let mut previous: Option<u32> = None;
+        let mut end = 0;
+        for (index, entry) in entries.iter().enumerate() {
+            let tag = entry.tag.to_string();
+            let raw_tag = entry.tag.to_u32().unwrap_or_default();
+            if !(has_region && index + 1 == entries.len()) {
+                if previous.map_or(false, |previous| raw_tag <= previous) {
+                    issues.push(LayoutIssue::Unsorted {
+                        store_type,
+                        tag: tag.clone(),
+                    });
+                }
+                previous = Some(raw_tag);
+            }
+
+            let alignment = entry.data.alignment();
+            let offset = entry.offset;
+            if offset < 0 {
+                continue;
+            }
+            if offset as usize % alignment != 0 {
+                issues.push(LayoutIssue::Misaligned {
+                    store_type,
+                    tag: tag.clone(),
+                    offset,
+                    alignment,
+                });
+            }
+            let aligned = (end + alignment - 1) / alignment * alignment;
+            match (offset as usize).cmp(&aligned) {
+                std::cmp::Ordering::Less if (offset as usize) < end => {
+                    issues.push(LayoutIssue::DataOutOfOrder { store_type, tag })
+                }
+                std::cmp::Ordering::Greater => issues.push(LayoutIssue::Gap {
+                    store_type,
+                    tag,
+                    len: offset as usize - aligned,
+                }),
+                _ => {}
+            }
+            end = end.max(offset as usize + entry.data.store_len());
+        }
+        issues
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::header::{IndexData, IndexEntry};
+    use super::*;
+    use crate::constants::*;
+    use crate::{RPMBuilder, RPMPackage};
+
+    #[test]
+    fn audit_layout() -> Result<(), Box<dyn std::error::Error>> {
+        let built = RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "")
+            .strict(true)
+            .with_file(
+                "./test_assets/awesome.toml",
+                crate::RPMFileOptions::new("/etc/awesome/config.toml"),
+            )?
+            .build()?;
+        assert_eq!(Vec::<LayoutIssue>::new(), built.metadata.audit_layout());
+        let raw = std::fs::read("./test_assets/389-ds-base-devel-1.3.8.4-15.el7.x86_64.rpm")?;
+        let foreign = RPMPackage::parse(&mut raw.as_slice())?;
+        assert_eq!(Vec::<LayoutIssue>::new(), foreign.metadata.audit_layout());
+
+        let mut header = Header::<IndexTag>::from_entries(
+            vec![
+                IndexEntry::new(
+                    IndexTag::RPMTAG_NAME,
+                    0,
+                    IndexData::StringTag("foo".to_string()),
+                ),
+                IndexEntry::new(IndexTag::RPMTAG_EPOCH, 0, IndexData::Int32(vec![1])),
+                IndexEntry::new(IndexTag::RPMTAG_SIZE, 0, IndexData::Int32(vec![2])),
+            ],
+            IndexTag::RPMTAG_HEADERIMMUTABLE,
+        );
+        assert!(header.audit_layout().is_empty());
+        header.index_entries.swap(2, 3);
+        header.index_entries[2].offset += 2;
+        let issues = header.audit_layout();
+        assert!(issues.contains(&LayoutIssue::Unsorted {
+            store_type: "IndexTag",
+            tag: "RPMTAG_EPOCH".to_string()
+        }));
+        assert!(issues.contains(&LayoutIssue::Misaligned {
+            store_type: "IndexTag",
+            tag: "RPMTAG_SIZE".to_string(),
+            offset: 10,
+            alignment: 4
+        }));
+        assert!(issues
+            .iter()
+            .any(|issue| matches!(issue, LayoutIssue::DataOutOfOrder { .. })));
+
+        header.index_entries.remove(0);
+        assert_eq!(
+            Some(&LayoutIssue::MissingRegion {
+                store_type: "IndexTag"
+            }),
+            header.audit_layout().first()
+        );
+        Ok(())
+    }
+}
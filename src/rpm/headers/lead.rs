@@ -5,6 +5,8 @@ use std::convert::TryInto;
 use crate::constants::*;
 use crate::errors::*;
 
+use super::compat::{Quirk, QuirkLog};
+
 #[cfg(feature = "async-tokio")]
 use tokio::io::AsyncWriteExt;
 
@@ -16,29 +18,33 @@ use tokio::io::AsyncWriteExt;
 /// such that compatibility is kept.
 /// This is also used by magic and other libraries
 /// to detect rpm files.
+///
+/// rpm itself ignores everything but the magic, the version and the signature type,
+/// the setters exist for legacy tools still inspecting the other fields.
+/// Changing the lead does not invalidate any signature.
+#[derive(Clone)]
 pub struct Lead {
     magic: [u8; 4],
     major: u8,
     minor: u8,
     package_type: u16,
-    arch: u16,
+    arch_num: u16,
     name: [u8; 66],
-    os: u16,
+    os_num: u16,
     signature_type: u16,
     reserved: [u8; 16],
 }
 
 impl std::fmt::Debug for Lead {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let name = String::from_utf8_lossy(&self.name);
         f.debug_struct("Lead")
             .field("magic", &self.magic)
             .field("major", &self.major)
             .field("minor", &self.minor)
             .field("package_type", &self.package_type)
-            .field("arch", &self.arch)
-            .field("name", &name)
-            .field("os", &self.os)
+            .field("arch_num", &self.arch_num)
+            .field("name", &self.name())
+            .field("os_num", &self.os_num)
             .field("signature_type", &self.signature_type)
             .field("reserved", &self.reserved)
             .finish()
@@ -46,7 +52,12 @@ impl std::fmt::Debug for Lead {
 }
 
 impl Lead {
-    pub(crate) fn parse(input: &[u8]) -> Result<Self, RPMError> {
+    /// Parse the 96 bytes at the start of an rpm file.
+    pub fn parse(input: &[u8]) -> Result<Self, RPMError> {
+        Self::parse_with(input, &mut QuirkLog::strict())
+    }
+
+    pub(crate) fn parse_with(input: &[u8], log: &mut QuirkLog) -> Result<Self, RPMError> {
         let (rest, magic) = complete::take(4usize)(input)?;
         for i in 0..magic.len() {
             if magic[i] != RPM_MAGIC[i] {
@@ -58,29 +69,37 @@ impl Lead {
             }
         }
         let (rest, major) = be_u8(rest)?;
-        if major != 3 {
-            return Err(RPMError::InvalidLeadMajorVersion(major));
-        }
         let (rest, minor) = be_u8(rest)?;
-        if minor != 0 {
-            return Err(RPMError::InvalidLeadMinorVersion(minor));
+        if major != 3 || minor != 0 {
+            let quirk = Quirk::LeadVersion { major, minor };
+            match major {
+                3 => log.tolerate(quirk, RPMError::InvalidLeadMinorVersion(minor))?,
+                4 => log.tolerate(quirk, RPMError::InvalidLeadMajorVersion(major))?,
+                _ => return Err(RPMError::InvalidLeadMajorVersion(major)),
+            }
         }
         let (rest, pkg_type) = be_u16(rest)?;
 
-        if pkg_type > 1 {
-            return Err(RPMError::InvalidLeadPKGType(pkg_type));
+        if pkg_type > RPMLEAD_SOURCE {
+            log.tolerate(
+                Quirk::LeadPackageType(pkg_type),
+                RPMError::InvalidLeadPKGType(pkg_type),
+            )?;
         }
 
-        let (rest, arch) = be_u16(rest)?;
+        let (rest, arch_num) = be_u16(rest)?;
         let (rest, name) = complete::take(66usize)(rest)?;
+        if !name.contains(&0) {
+            return Err(RPMError::InvalidLeadName);
+        }
 
-        let (rest, os) = be_u16(rest)?;
-        if os != 1 {
-            return Err(RPMError::InvalidLeadOSType(os));
+        let (rest, os_num) = be_u16(rest)?;
+        if os_num != RPMLEAD_OS_LINUX {
+            log.tolerate(Quirk::LeadOs(os_num), RPMError::InvalidLeadOSType(os_num))?;
         }
 
         let (rest, sigtype) = be_u16(rest)?;
-        if sigtype != 5 {
+        if sigtype != RPMSIGTYPE_HEADERSIG {
             return Err(RPMError::InvalidLeadSignatureType(sigtype));
         }
 
@@ -100,9 +119,9 @@ impl Lead {
             major,
             minor,
             package_type: pkg_type,
-            arch,
+            arch_num,
             name: name_arr,
-            os,
+            os_num,
             signature_type: sigtype,
             reserved: rest.try_into().unwrap(),
         })
@@ -116,61 +135,162 @@ impl Lead {
         out.write_all(&self.major.to_be_bytes()).await?;
         out.write_all(&self.minor.to_be_bytes()).await?;
         out.write_all(&self.package_type.to_be_bytes()).await?;
-        out.write_all(&self.arch.to_be_bytes()).await?;
+        out.write_all(&self.arch_num.to_be_bytes()).await?;
         out.write_all(&self.name).await?;
-        out.write_all(&self.os.to_be_bytes()).await?;
+        out.write_all(&self.os_num.to_be_bytes()).await?;
         out.write_all(&self.signature_type.to_be_bytes()).await?;
         out.write_all(&self.reserved).await?;
         Ok(())
     }
 
-    pub(crate) fn write<W: std::io::Write>(&self, out: &mut W) -> Result<(), RPMError> {
+    pub fn write<W: std::io::Write>(&self, out: &mut W) -> Result<(), RPMError> {
         out.write_all(&self.magic)?;
         out.write_all(&self.major.to_be_bytes())?;
         out.write_all(&self.minor.to_be_bytes())?;
         out.write_all(&self.package_type.to_be_bytes())?;
-        out.write_all(&self.arch.to_be_bytes())?;
+        out.write_all(&self.arch_num.to_be_bytes())?;
         out.write_all(&self.name)?;
-        out.write_all(&self.os.to_be_bytes())?;
+        out.write_all(&self.os_num.to_be_bytes())?;
         out.write_all(&self.signature_type.to_be_bytes())?;
         out.write_all(&self.reserved)?;
         Ok(())
     }
 
-    pub(crate) fn new(name: &str) -> Self {
-        let mut name_arr = [0; 66];
-        // the last byte needs to be the null terminator
-        let name_size = std::cmp::min(name_arr.len() - 1, name.len());
-
-        name_arr[..name_size].clone_from_slice(&name.as_bytes()[..name_size]);
-        Lead {
+    /// A lead for a binary package, the way rpm writes it.
+    pub fn new(name: &str) -> Self {
+        let mut lead = Lead {
             magic: RPM_MAGIC,
             major: 3,
             minor: 0,
-            package_type: 0,
-            arch: 0,
-            name: name_arr,
-            os: 1,
-            signature_type: 5,
+            package_type: RPMLEAD_BINARY,
+            arch_num: 0,
+            name: [0; 66],
+            os_num: RPMLEAD_OS_LINUX,
+            signature_type: RPMSIGTYPE_HEADERSIG,
             reserved: [0; 16],
+        };
+        lead.set_name(name);
+        lead
+    }
+
+    pub fn major(&self) -> u8 {
+        self.major
+    }
+
+    pub fn minor(&self) -> u8 {
+        self.minor
+    }
+
+    /// 0 for binary packages, 1 for source packages.
+    pub fn package_type(&self) -> u16 {
+        self.package_type
+    }
+
+    /// Architecture number from rpmrc, 1 for x86 and x86_64.
+    pub fn arch_num(&self) -> u16 {
+        self.arch_num
+    }
+
+    /// The name, up to the first null byte.
+    pub fn name(&self) -> String {
+        let end = self
+            .name
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(self.name.len());
+        String::from_utf8_lossy(&self.name[..end]).to_string()
+    }
+
+    /// Operating system number from rpmrc, 1 for Linux.
+    pub fn os_num(&self) -> u16 {
+        self.os_num
+    }
+
+    /// 5 for a signature header following the lead.
+    pub fn signature_type(&self) -> u16 {
+        self.signature_type
+    }
+
+    /// Only version 3.0 parses again.
+    pub fn set_version(&mut self, major: u8, minor: u8) {
+        self.major = major;
+        self.minor = minor;
+    }
+
+    pub fn set_package_type(&mut self, package_type: u16) {
+        self.package_type = package_type;
+    }
+
+    pub fn set_arch_num(&mut self, arch_num: u16) {
+        self.arch_num = arch_num;
+    }
+
+    /// Names longer than 65 bytes are cut at the last character boundary fitting,
+    /// the last byte is always the null terminator.
+    pub fn set_name(&mut self, name: &str) {
+        let mut size = std::cmp::min(self.name.len() - 1, name.len());
+        while !name.is_char_boundary(size) {
+            size -= 1;
         }
+        self.name = [0; 66];
+        self.name[..size].copy_from_slice(&name.as_bytes()[..size]);
+    }
+
+    pub fn set_os_num(&mut self, os_num: u16) {
+        self.os_num = os_num;
+    }
+
+    /// Only 5 parses again.
+    pub fn set_signature_type(&mut self, signature_type: u16) {
+        self.signature_type = signature_type;
     }
 }
 
 impl PartialEq for Lead {
     fn eq(&self, other: &Lead) -> bool {
-        for i in 0..self.name.len() {
-            if other.name[i] != self.name[i] {
-                return false;
-            }
-        }
-        self.magic == other.magic
+        self.name == other.name
+            && self.magic == other.magic
             && self.major == other.major
             && self.minor == other.minor
             && self.package_type == other.package_type
-            && self.arch == other.arch
-            && self.os == other.os
+            && self.arch_num == other.arch_num
+            && self.os_num == other.os_num
             && self.signature_type == other.signature_type
             && self.reserved == other.reserved
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lead_fields() -> Result<(), Box<dyn std::error::Error>> {
+        let long = "ä".repeat(40);
+        let mut lead = Lead::new(&long);
+        assert_eq!("ä".repeat(32), lead.name());
+
+        lead.set_name("awesome-1.0.0-1");
+        lead.set_arch_num(1);
+        lead.set_package_type(1);
+        let mut buf = Vec::new();
+        lead.write(&mut buf)?;
+        assert_eq!(LEAD_SIZE, buf.len());
+        let parsed = Lead::parse(&buf)?;
+        assert_eq!(lead, parsed);
+        assert_eq!("awesome-1.0.0-1", parsed.name());
+        assert_eq!(1, parsed.arch_num());
+        assert_eq!(1, parsed.package_type());
+
+        buf[10..76].copy_from_slice(&[b'a'; 66]);
+        assert!(matches!(Lead::parse(&buf), Err(RPMError::InvalidLeadName)));
+        lead.set_signature_type(1);
+        buf.clear();
+        lead.write(&mut buf)?;
+        assert!(matches!(
+            Lead::parse(&buf),
+            Err(RPMError::InvalidLeadSignatureType(1))
+        ));
+        Ok(())
+    }
+}
@@ -1,8 +1,16 @@
+mod compat;
 mod header;
+mod header_ref;
+mod layout;
 mod lead;
+mod rich;
 mod types;
 
+pub(crate) use compat::QuirkLog;
+pub use compat::{ParseOptions, Quirk};
 pub use header::*;
+pub use header_ref::{EntryRef, HeaderRef};
+pub use layout::LayoutIssue;
 pub use lead::*;
 pub use types::*;
 
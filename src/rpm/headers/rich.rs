@@ -0,0 +1,176 @@
+//! Rich (boolean) dependencies like `(foo >= 1.0 or bar)`, as understood by rpm 4.12 and later.
+
+/// Operators combining the operands of a rich dependency.
+const OPERATORS: [&str; 7] = ["and", "or", "if", "else", "unless", "with", "without"];
+
+/// Comparisons of a simple dependency within a rich one.
+const COMPARISONS: [&str; 6] = ["<", "<=", "=", "==", ">=", ">"];
+
+/// Deepest nesting of parentheses accepted, so the recursion stays bounded.
+const MAX_DEPTH: usize = 64;
+
+/// Check `text` is a rich dependency rpm accepts and format it the way rpm
+/// does, with single spaces between the tokens and none inside parentheses.
+pub(crate) fn normalize(text: &str) -> Result<String, &'static str> {
+    let tokens = tokenize(text);
+    let mut parser = Parser {
+        tokens: &tokens,
+        next: 0,
+        depth: 0,
+    };
+    let normalized = parser.expression()?;
+    if parser.next < tokens.len() {
+        return Err("trailing text after the rich dependency");
+    }
+    Ok(normalized)
+}
+
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (index, c) in text.char_indices() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if let Some(start) = start.take() {
+                tokens.push(&text[start..index]);
+            }
+            if !c.is_whitespace() {
+                tokens.push(&text[index..index + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(index);
+        }
+    }
+    if let Some(start) = start {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: &'a [&'a str],
+    next: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.next).copied()
+    }
+
+    fn take(&mut self) -> Option<&'a str> {
+        let token = self.peek();
+        self.next += 1;
+        token
+    }
+
+    /// `( <operand> [<operator> <operand>]... )`
+    fn expression(&mut self) -> Result<String, &'static str> {
+        if self.take() != Some("(") {
+            return Err("rich dependency has to start with a parenthesis");
+        }
+        if self.depth == MAX_DEPTH {
+            return Err("rich dependency nested too deeply");
+        }
+        self.depth += 1;
+        let mut parts = vec![self.operand()?];
+        let mut operators = Vec::new();
+        loop {
+            match self.take() {
+                Some(")") => break,
+                Some(operator) if OPERATORS.contains(&operator) => {
+                    check_chain(&operators, operator)?;
+                    operators.push(operator);
+                    parts.push(operator.to_string());
+                    parts.push(self.operand()?);
+                }
+                Some(_) => return Err("expected an operator like and, or, if or with"),
+                None => return Err("rich dependency without closing parenthesis"),
+            }
+        }
+        self.depth -= 1;
+        Ok(format!("({})", parts.join(" ")))
+    }
+
+    /// A nested expression or `<name> [<comparison> <version>]`.
+    fn operand(&mut self) -> Result<String, &'static str> {
+        match self.peek() {
+            Some("(") => self.expression(),
+            Some(")") | None => Err("missing operand"),
+            Some(name) if OPERATORS.contains(&name) || COMPARISONS.contains(&name) => {
+                Err("missing operand")
+            }
+            Some(name) => {
+                self.next += 1;
+                match self.peek() {
+                    Some(comparison) if COMPARISONS.contains(&comparison) => {
+                        self.next += 1;
+                        match self.take() {
+                            Some(version) if version != "(" && version != ")" => {
+                                Ok(format!("{} {} {}", name, comparison, version))
+                            }
+                            _ => Err("comparison without version"),
+                        }
+                    }
+                    _ => Ok(name.to_string()),
+                }
+            }
+        }
+    }
+}
+
+/// rpm only chains `and`, `or` and `with` with themselves, `if` and `unless` take one `else`.
+fn check_chain(previous: &[&str], operator: &str) -> Result<(), &'static str> {
+    match (previous, operator) {
+        ([], "else") => Err("else without if or unless"),
+        ([], _) => Ok(()),
+        (["if"], "else") | (["unless"], "else") => Ok(()),
+        ([first, ..], operator)
+            if *first == operator && ["and", "or", "with"].contains(&operator) =>
+        {
+            Ok(())
+        }
+        _ => Err("operators have to be the same unless separated by parentheses"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn normalize_rich_dependencies() {
+        for (text, expected) in [
+            ("(foo or bar)", "(foo or bar)"),
+            ("( pkgA  >=  1.0 or pkgB )", "(pkgA >= 1.0 or pkgB)"),
+            ("(a and b and c)", "(a and b and c)"),
+            (
+                "(foo if (bar or baz) else qux)",
+                "(foo if (bar or baz) else qux)",
+            ),
+            ("((a or b)and(c))", "((a or b) and (c))"),
+        ] {
+            assert_eq!(Ok(expected.to_string()), normalize(text), "{}", text);
+        }
+        for text in [
+            "foo",
+            "(foo or bar",
+            "(foo or)",
+            "(a and b or c)",
+            "(a else b)",
+            "(a if b else c else d)",
+            "(a without b without c)",
+            "(foo >= )",
+            "(a or b) c",
+            "(a b)",
+        ] {
+            assert!(normalize(text).is_err(), "{}", text);
+        }
+
+        let nested = |depth| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(normalize(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            Err("rich dependency nested too deeply"),
+            normalize(&nested(100_000))
+        );
+    }
+}
@@ -99,6 +99,16 @@ impl SignatureHeaderBuilder<Empty> {
 }
 
 impl SignatureHeaderBuilder<WithDigest> {
+    /// add the sha256 digest of the header only, next to the sha1 one
+    pub fn add_sha256_digest(mut self, digest_header_only: &str) -> Self {
+        self.entries.push(IndexEntry::new(
+            IndexSignatureTag::RPMSIGTAG_SHA256,
+            0i32, // filled externally later on
+            IndexData::StringTag(digest_header_only.to_string()),
+        ));
+        self
+    }
+
     /// add a signature over the header and a signature accross header and source excluding the static lead
     pub fn add_signature(
         mut self,
@@ -1,19 +1,29 @@
 //! A collection of types used in various header records.
-use crate::{constants::*, errors};
+use std::sync::Arc;
+
+use crate::{constants::*, errors, Timestamp};
 
 /// Describes a file present in the rpm file.
+#[derive(Clone)]
 pub struct RPMFileEntry {
     pub(crate) size: i32,
     pub(crate) mode: FileMode,
-    pub(crate) modified_at: i32,
+    /// The permissions were taken from the source file.
+    pub(crate) inherited_mode: bool,
+    pub(crate) modified_at: Timestamp,
     pub(crate) sha_checksum: String,
     pub(crate) link: String,
     pub(crate) flag: i32,
     pub(crate) user: String,
     pub(crate) group: String,
+    pub(crate) uid: Option<u32>,
+    pub(crate) gid: Option<u32>,
     pub(crate) base_name: String,
     pub(crate) dir: String,
-    pub(crate) content: Option<Vec<u8>>,
+    /// Shared by clones of the builder.
+    pub(crate) content: Option<Arc<[u8]>>,
+    /// The hardlink set the file belongs to, its members share one inode.
+    pub(crate) hardlink: Option<u32>,
 }
 
 #[non_exhaustive]
@@ -23,17 +33,19 @@ pub enum FileMode {
     // is the bit representation which is the same for both.
     Dir { permissions: u16 },
     Regular { permissions: u16 },
+    SymbolicLink { permissions: u16 },
     // For "Invalid" we use a larger integer since it is possible to create an invalid
     // FileMode by providing an overflowing integer.
     Invalid { raw_mode: i32, reason: &'static str },
 }
 
-// there are more file types but in the context of RPM, only regular and directory should be relevant.
+// there are more file types but in the context of RPM, only regular, directory and symlink should be relevant.
 // See https://man7.org/linux/man-pages/man7/inode.7.html section "The file type and mode"
 const FILE_TYPE_BIT_MASK: u16 = 0o170000; // bit representation = "1111000000000000"
 const PERMISSIONS_BIT_MASK: u16 = 0o7777; // bit representation = "0000111111111111"
 const REGULAR_FILE_TYPE: u16 = 0o100000; //  bit representation = "1000000000000000"
 const DIR_FILE_TYPE: u16 = 0o040000; //      bit representation = "0100000000000000"
+const SYMLINK_FILE_TYPE: u16 = 0o120000; //  bit representation = "1010000000000000"
 
 impl From<u16> for FileMode {
     fn from(raw_mode: u16) -> Self {
@@ -52,6 +64,7 @@ impl From<u16> for FileMode {
         match file_type {
             DIR_FILE_TYPE => FileMode::Dir { permissions },
             REGULAR_FILE_TYPE => FileMode::Regular { permissions },
+            SYMLINK_FILE_TYPE => FileMode::SymbolicLink { permissions },
             _ => FileMode::Invalid {
                 raw_mode: raw_mode as i32,
                 reason: "unknown file type",
@@ -96,6 +109,13 @@ impl FileMode {
         }
     }
 
+    /// Create a new SymbolicLink instance. `permissions` can be between 0 and 0o7777. Values greater will be set to 0o7777.
+    pub fn symlink(permissions: u16) -> Self {
+        FileMode::SymbolicLink {
+            permissions: permissions & PERMISSIONS_BIT_MASK,
+        }
+    }
+
     /// Usually this should be done with TryFrom, but since we already have a `From` implementation,
     /// we run into this issue: https://github.com/rust-lang/rust/issues/50133
     pub fn try_from_raw(raw: i32) -> Result<Self, errors::RPMError> {
@@ -117,9 +137,9 @@ impl FileMode {
     /// Returns the complete file mode (type and permissions)
     pub fn raw_mode(&self) -> u16 {
         match self {
-            Self::Dir { permissions } | Self::Regular { permissions } => {
-                *permissions | self.file_type()
-            }
+            Self::Dir { permissions }
+            | Self::Regular { permissions }
+            | Self::SymbolicLink { permissions } => *permissions | self.file_type(),
             Self::Invalid {
                 raw_mode,
                 reason: _,
@@ -131,6 +151,7 @@ impl FileMode {
         match self {
             Self::Dir { permissions: _ } => DIR_FILE_TYPE,
             Self::Regular { permissions: _ } => REGULAR_FILE_TYPE,
+            Self::SymbolicLink { permissions: _ } => SYMLINK_FILE_TYPE,
             Self::Invalid {
                 raw_mode,
                 reason: _,
@@ -140,7 +161,9 @@ impl FileMode {
 
     pub fn permissions(&self) -> u16 {
         match self {
-            Self::Dir { permissions } | Self::Regular { permissions } => *permissions,
+            Self::Dir { permissions }
+            | Self::Regular { permissions }
+            | Self::SymbolicLink { permissions } => *permissions,
             Self::Invalid {
                 raw_mode,
                 reason: _,
@@ -178,8 +201,10 @@ impl From<FileMode> for u16 {
 /// A subset
 pub struct RPMFileOptions {
     pub(crate) destination: String,
-    pub(crate) user: String,
-    pub(crate) group: String,
+    pub(crate) user: Option<String>,
+    pub(crate) group: Option<String>,
+    pub(crate) uid: Option<u32>,
+    pub(crate) gid: Option<u32>,
     pub(crate) symlink: String,
     pub(crate) mode: FileMode,
     pub(crate) flag: i32,
@@ -192,8 +217,10 @@ impl RPMFileOptions {
         RPMFileOptionsBuilder {
             inner: RPMFileOptions {
                 destination: dest.into(),
-                user: "root".to_string(),
-                group: "root".to_string(),
+                user: None,
+                group: None,
+                uid: None,
+                gid: None,
                 symlink: "".to_string(),
                 mode: FileMode::regular(0o664),
                 flag: 0,
@@ -209,12 +236,29 @@ pub struct RPMFileOptionsBuilder {
 
 impl RPMFileOptionsBuilder {
     pub fn user<T: Into<String>>(mut self, user: T) -> Self {
-        self.inner.user = user.into();
+        self.inner.user = Some(user.into());
         self
     }
 
     pub fn group<T: Into<String>>(mut self, group: T) -> Self {
-        self.inner.group = group.into();
+        self.inner.group = Some(group.into());
+        self
+    }
+
+    /// Numeric owner, written to the payload.
+    ///
+    /// Without a [`user`](Self::user) the number doubles as user name.
+    /// rpm resolves owners by name only and falls back to root for unknown ones.
+    pub fn uid(mut self, uid: u32) -> Self {
+        self.inner.uid = Some(uid);
+        self
+    }
+
+    /// Numeric group, written to the payload.
+    ///
+    /// Without a [`group`](Self::group) the number doubles as group name.
+    pub fn gid(mut self, gid: u32) -> Self {
+        self.inner.gid = Some(gid);
         self
     }
 
@@ -234,10 +278,33 @@ impl RPMFileOptionsBuilder {
         self
     }
 
+    /// License text, `%license` in spec files, installed even when documentation
+    /// is excluded.
+    pub fn is_license(mut self) -> Self {
+        self.inner.flag = RPMFILE_LICENSE;
+        self
+    }
+
     pub fn is_config(mut self) -> Self {
         self.inner.flag = RPMFILE_CONFIG;
         self
     }
+
+    /// Config file which is not replaced on upgrade if it was modified,
+    /// `%config(noreplace)` in spec files.
+    pub fn is_config_noreplace(mut self) -> Self {
+        self.inner.flag = RPMFILE_CONFIG | RPMFILE_NOREPLACE;
+        self
+    }
+
+    /// Owned by the package but not part of the payload, `%ghost` in spec files,
+    /// for files created on the system like logs or generated images.
+    ///
+    /// Combines with the config flags set before.
+    pub fn is_ghost(mut self) -> Self {
+        self.inner.flag |= RPMFILE_GHOST;
+        self
+    }
 }
 
 impl From<RPMFileOptionsBuilder> for RPMFileOptions {
@@ -247,6 +314,7 @@ impl From<RPMFileOptionsBuilder> for RPMFileOptions {
 }
 
 /// Description of a dependency as present in a RPM header record.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Dependency {
     pub(crate) dep_name: String,
     pub(crate) sense: u32,
@@ -302,6 +370,17 @@ impl Dependency {
         )
     }
 
+    /// A feature of rpm itself the package relies on, `rpmlib(<feature>) <= <version>`.
+    ///
+    /// `version` is the first version of rpm supporting the feature.
+    pub fn rpmlib(feature: &str, version: &str) -> Self {
+        Self::new(
+            format!("rpmlib({})", feature),
+            RPMSENSE_RPMLIB | RPMSENSE_LESS | RPMSENSE_EQUAL,
+            version.to_string(),
+        )
+    }
+
     pub fn any<T>(dep_name: T) -> Self
     where
         T: Into<String>,
@@ -309,13 +388,139 @@ impl Dependency {
         Self::new(dep_name.into(), RPMSENSE_ANY, "".to_string())
     }
 
-    fn new(dep_name: String, sense: u32, version: String) -> Self {
+    /// Any version from `min` up to but excluding `max`, as the rich dependency
+    /// `(<name> >= <min> with <name> < <max>)`.
+    ///
+    /// Unlike two separate requirements both bounds have to hold for the same
+    /// provider. Rich dependencies need rpm 4.12 or later.
+    pub fn range(dep_name: &str, min: &str, max: &str) -> Self {
+        Self::any(format!(
+            "({name} >= {min} with {name} < {max})",
+            name = dep_name,
+            min = min,
+            max = max
+        ))
+    }
+
+    /// Needed by the `%pre` scriptlet as well, `Requires(pre)` in a spec.
+    pub fn pre(mut self) -> Self {
+        self.sense |= RPMSENSE_SCRIPT_PRE;
+        self
+    }
+
+    /// Needed by the `%post` scriptlet as well, `Requires(post)` in a spec.
+    pub fn post(mut self) -> Self {
+        self.sense |= RPMSENSE_SCRIPT_POST;
+        self
+    }
+
+    /// Needed by the `%preun` scriptlet as well, `Requires(preun)` in a spec.
+    pub fn preun(mut self) -> Self {
+        self.sense |= RPMSENSE_SCRIPT_PREUN;
+        self
+    }
+
+    /// Needed by the `%postun` scriptlet as well, `Requires(postun)` in a spec.
+    pub fn postun(mut self) -> Self {
+        self.sense |= RPMSENSE_SCRIPT_POSTUN;
+        self
+    }
+
+    /// Name of the capability, e.g. a package name, a file path or `rpmlib(...)`.
+    pub fn name(&self) -> &str {
+        &self.dep_name
+    }
+
+    /// Whether this is a requirement on a feature of rpm itself, see [`rpmlib`](Self::rpmlib).
+    pub fn is_rpmlib(&self) -> bool {
+        self.dep_name.starts_with("rpmlib(")
+    }
+
+    /// The `RPMSENSE_*` flags of this dependency.
+    pub fn flags(&self) -> u32 {
+        self.sense
+    }
+
+    /// The version the flags compare against, empty if any version matches.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub(crate) fn new(dep_name: String, sense: u32, version: String) -> Self {
         Dependency {
             dep_name,
             sense,
             version,
         }
     }
+
+    /// Parse a dependency as written in a spec, e.g. `foo`, `foo >= 1.2-3` or `(pkgA >= 1.0 or pkgB)`.
+    ///
+    /// Rich dependencies are checked for the grammar rpm accepts and stored the
+    /// way rpm formats them, such that [`Display`](std::fmt::Display) gives the
+    /// same text for any spacing of the input.
+    pub fn parse(text: &str) -> Result<Self, errors::RPMError> {
+        let invalid = |reason| errors::RPMError::InvalidDependency {
+            text: text.to_string(),
+            reason,
+        };
+        let text = text.trim();
+        if text.starts_with('(') {
+            return super::rich::normalize(text).map(Self::any).map_err(invalid);
+        }
+        let mut parts = text.split_whitespace();
+        let name = parts.next().ok_or_else(|| invalid("empty dependency"))?;
+        let (operator, version) = match (parts.next(), parts.next(), parts.next()) {
+            (None, _, _) => return Ok(Self::any(name)),
+            (Some(operator), Some(version), None) => (operator, version),
+            _ => return Err(invalid("expected <name> [<operator> <version>]")),
+        };
+        Ok(match operator {
+            "<" => Self::less(name, version),
+            "<=" => Self::less_eq(name, version),
+            "=" | "==" => Self::eq(name, version),
+            ">=" => Self::greater_eq(name, version),
+            ">" => Self::greater(name, version),
+            _ => return Err(invalid("unknown operator")),
+        })
+    }
+
+    /// Whether this is a rich dependency like `(foo or bar)`, see [`parse`](Self::parse).
+    pub fn is_rich(&self) -> bool {
+        self.dep_name.starts_with('(')
+    }
+
+    /// The comparison of the flags as written in a spec, `None` if any version matches.
+    fn operator(&self) -> Option<&'static str> {
+        let compare = self.sense & (RPMSENSE_LESS | RPMSENSE_GREATER | RPMSENSE_EQUAL);
+        Some(match compare {
+            c if c == RPMSENSE_LESS => "<",
+            c if c == RPMSENSE_LESS | RPMSENSE_EQUAL => "<=",
+            c if c == RPMSENSE_EQUAL => "=",
+            c if c == RPMSENSE_GREATER | RPMSENSE_EQUAL => ">=",
+            c if c == RPMSENSE_GREATER => ">",
+            _ => return None,
+        })
+    }
+}
+
+impl std::fmt::Display for Dependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.operator() {
+            Some(operator) if !self.version.is_empty() => {
+                write!(f, "{} {} {}", self.dep_name, operator, self.version)
+            }
+            _ => write!(f, "{}", self.dep_name),
+        }
+    }
+}
+
+impl std::str::FromStr for Dependency {
+    type Err = errors::RPMError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+    }
 }
 
 mod test {
@@ -390,6 +595,7 @@ mod test {
             (0o10_0755, FileMode::regular(0o0755), REGULAR_FILE_TYPE),
             (0o10_1755, FileMode::regular(0o1755), REGULAR_FILE_TYPE),
             (0o04_0755, FileMode::dir(0o0755), DIR_FILE_TYPE),
+            (0o12_0777, FileMode::symlink(0o0777), SYMLINK_FILE_TYPE),
             (
                 0o20_0755,
                 FileMode::Invalid {
@@ -415,4 +621,49 @@ mod test {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_dependency_text() -> Result<(), Box<dyn std::error::Error>> {
+        use super::*;
+
+        let dependency = "glibc >= 2.34".parse::<Dependency>()?;
+        assert_eq!(Dependency::greater_eq("glibc", "2.34"), dependency);
+        assert_eq!("glibc >= 2.34", dependency.to_string());
+        assert_eq!(Dependency::less("openssl", "3.0"), "openssl < 3.0".parse()?);
+        assert_eq!(
+            Dependency::eq("foo", "1:1.2-3"),
+            " foo == 1:1.2-3 ".parse()?
+        );
+        assert_eq!(Dependency::any("jq"), "jq".parse()?);
+        assert_eq!("jq", Dependency::any("jq").to_string());
+        assert_eq!(
+            Dependency::any("(foo or bar)"),
+            "(foo or bar)".parse::<Dependency>()?
+        );
+        for invalid in ["", "foo >=", "foo ~> 1", "foo >= 1 2", "(foo or bar"] {
+            assert!(invalid.parse::<Dependency>().is_err(), "{}", invalid);
+        }
+
+        let rich = Dependency::parse("( pkgA  >= 1.0 or (pkgB if pkgC) )")?;
+        assert!(rich.is_rich());
+        assert_eq!("(pkgA >= 1.0 or (pkgB if pkgC))", rich.to_string());
+        assert_eq!(rich, Dependency::parse(&rich.to_string())?);
+        assert!(Dependency::parse("(a and b or c)").is_err());
+
+        assert_eq!(
+            "(python3 >= 3.9 with python3 < 4)",
+            Dependency::range("python3", "3.9", "4").name()
+        );
+        let pre = Dependency::any("shadow-utils").pre();
+        assert_eq!(RPMSENSE_SCRIPT_PRE, pre.flags());
+        assert_eq!(
+            RPMSENSE_GREATER | RPMSENSE_EQUAL | RPMSENSE_SCRIPT_POSTUN,
+            Dependency::greater_eq("systemd", "239").postun().flags()
+        );
+        assert_eq!(
+            "systemd >= 239",
+            Dependency::greater_eq("systemd", "239").post().to_string()
+        );
+        Ok(())
+    }
 }
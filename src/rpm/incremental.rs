@@ -0,0 +1,335 @@
+//! Rebuilding packages where few files changed, reusing the work of the previous build.
+//!
+//! Zstd payloads are compressed in segments, independent frames holding the cpio entries
+//! of consecutive files, which decompress as one stream. A rebuild copies the frames of
+//! segments whose uncompressed bytes did not change instead of compressing them again.
+//! File digests are always computed from the content added.
+//!
+//! ```rust
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::str::FromStr;
+//!
+//! let builder = || {
+//!     rpm::RPMBuilder::new("snapshot", "1.0.0", "MIT", "noarch", "hourly snapshot")
+//!         .compression(rpm::Compressor::from_str("zstd").unwrap())
+//!         .payload_segments(64 * 1024)
+//! };
+//! let previous = builder()
+//!     .with_file("./test_assets/awesome.toml", rpm::RPMFileOptions::new("/etc/awesome.toml"))?
+//!     .build()?;
+//! let previous = rpm::PreviousBuild::from_package(&previous)?;
+//! let rebuilt = builder()
+//!     .reuse_previous(previous)
+//!     .with_file("./test_assets/awesome.toml", rpm::RPMFileOptions::new("/etc/awesome.toml"))?
+//!     .with_file("./test_assets/awesome.py", rpm::RPMFileOptions::new("/usr/bin/awesome"))?
+//!     .build()?;
+//! # drop(rebuilt);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+
+use sha2::Digest;
+
+use crate::errors::*;
+
+use super::{RPMBuilder, RPMPackage};
+
+/// Segment size used by [`RPMBuilder::reuse_previous`] unless
+/// [`RPMBuilder::payload_segments`] set one.
+pub const DEFAULT_SEGMENT_SIZE: usize = 1024 * 1024;
+
+/// What a rebuild may take from a package built before, see [`RPMBuilder::reuse_previous`].
+#[derive(Debug, Clone, Default)]
+pub struct PreviousBuild {
+    /// Compressed frames by the lowercase hex sha256 of their content.
+    segments: HashMap<String, Vec<u8>>,
+}
+
+impl PreviousBuild {
+    /// Take the payload segments from `package`.
+    ///
+    /// Segments are only taken from zstd payloads. A payload compressed as a single
+    /// frame is one segment.
+    pub fn from_package(package: &RPMPackage) -> Result<Self, RPMError> {
+        let header = &package.metadata.header;
+        let mut previous = PreviousBuild::default();
+        if header.get_payload_compressor().ok() == Some("zstd") {
+            let mut rest = package.content.as_slice();
+            while !rest.is_empty() {
+                let frame_size =
+                    zstd::zstd_safe::find_frame_compressed_size(rest).map_err(|_| {
+                        RPMError::Io(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "invalid zstd frame in payload",
+                        ))
+                    })?;
+                let (frame, tail) = rest.split_at(frame_size);
+                let content = zstd::stream::decode_all(frame)?;
+                previous
+                    .segments
+                    .insert(hex::encode(sha2::Sha256::digest(&content)), frame.to_vec());
+                rest = tail;
+            }
+        }
+        Ok(previous)
+    }
+
+    /// Number of payload segments a rebuild may reuse.
+    pub fn segments(&self) -> usize {
+        self.segments.len()
+    }
+}
+
+/// The [`PreviousBuild`] a rebuild takes segments from.
+#[derive(Debug)]
+pub(crate) struct Reuse {
+    previous: PreviousBuild,
+}
+
+impl RPMBuilder {
+    /// Compress zstd payloads in independent frames of at least `size` bytes of cpio
+    /// data, so later builds can reuse them with [`reuse_previous`](Self::reuse_previous).
+    ///
+    /// Segments end after files whose path hashes to a multiple of four, so an
+    /// inserted or grown file only changes the segment it is in, or once they reach
+    /// four times `size`. Payloads compress a bit worse than as a single frame.
+    /// Building fails unless the payload is compressed with zstd.
+    pub fn payload_segments(mut self, size: usize) -> Self {
+        self.segment_size = Some(size.max(1));
+        self
+    }
+
+    /// Reuse the compressed segments of `previous` whose content is unchanged.
+    ///
+    /// The payload is segmented, with [`DEFAULT_SEGMENT_SIZE`] unless
+    /// [`payload_segments`](Self::payload_segments) set a size, and building fails
+    /// unless it is compressed with zstd.
+    pub fn reuse_previous(mut self, previous: PreviousBuild) -> Self {
+        self.reuse = Some(Arc::new(Reuse { previous }));
+        self
+    }
+
+    /// Whether the payload is written in segments.
+    pub(crate) fn segmented(&self) -> bool {
+        self.segment_size.is_some() || self.reuse.is_some()
+    }
+
+    /// The writer for a segmented zstd payload compressed at `level`, if the builder
+    /// writes one.
+    pub(crate) fn segment_writer(&self, level: i32) -> Option<SegmentWriter> {
+        let size = match (self.segment_size, &self.reuse) {
+            (Some(size), _) => size,
+            (None, Some(_)) => DEFAULT_SEGMENT_SIZE,
+            (None, None) => return None,
+        };
+        Some(SegmentWriter {
+            size,
+            level,
+            reuse: self.reuse.clone(),
+            buffer: Vec::new(),
+            out: Vec::new(),
+            reused: 0,
+            compressed: 0,
+        })
+    }
+}
+
+/// Collects the cpio archive and compresses it segment by segment.
+pub(crate) struct SegmentWriter {
+    size: usize,
+    level: i32,
+    reuse: Option<Arc<Reuse>>,
+    buffer: Vec<u8>,
+    out: Vec<u8>,
+    reused: usize,
+    compressed: usize,
+}
+
+impl SegmentWriter {
+    /// End the segment if it is complete, called after the entry of every file.
+    pub(crate) fn file_written(&mut self, path: &str) -> Result<(), RPMError> {
+        let ends_here = sha2::Sha256::digest(path.as_bytes())[0] % 4 == 0;
+        if (self.buffer.len() >= self.size && ends_here) || self.buffer.len() >= 4 * self.size {
+            self.cut()?;
+        }
+        Ok(())
+    }
+
+    fn cut(&mut self) -> Result<(), RPMError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let previous = self.reuse.as_ref().and_then(|reuse| {
+            let digest = hex::encode(sha2::Sha256::digest(&self.buffer));
+            reuse.previous.segments.get(&digest)
+        });
+        match previous {
+            Some(frame) => {
+                self.out.extend_from_slice(frame);
+                self.reused += 1;
+            }
+            None => {
+                let mut encoder = zstd::stream::Encoder::new(&mut self.out, self.level)?;
+                encoder.write_all(&self.buffer)?;
+                encoder.finish()?;
+                self.compressed += 1;
+            }
+        }
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Compress the last segment and return the payload and the capacity the
+    /// segment buffer grew to.
+    pub(crate) fn finish(mut self) -> Result<(Vec<u8>, usize), RPMError> {
+        self.cut()?;
+        log::debug!(
+            "payload segments: {} reused, {} compressed",
+            self.reused,
+            self.compressed
+        );
+        Ok((self.out, self.buffer.capacity()))
+    }
+}
+
+impl Write for SegmentWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    /// Segments only end between files, the cpio writer flushes after padding.
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::*;
+    use crate::{Compressor, RPMFileOptions};
+    use std::str::FromStr;
+
+    fn build(
+        contents: &[Vec<u8>],
+        previous: Option<PreviousBuild>,
+    ) -> Result<RPMPackage, RPMError> {
+        let mut builder = RPMBuilder::new("snapshot", "1.0.0", "MIT", "noarch", "snapshot")
+            .compression(Compressor::from_str("zstd")?)
+            .payload_segments(256);
+        if let Some(previous) = previous {
+            builder = builder.reuse_previous(previous);
+        }
+        for (index, content) in contents.iter().enumerate() {
+            builder.add_data(
+                content.clone(),
+                crate::Timestamp::EPOCH,
+                RPMFileOptions::new(format!("/data/{:02}", index)).into(),
+            )?;
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn reuse_segments() -> Result<(), Box<dyn std::error::Error>> {
+        let mut contents = (0..64u8)
+            .map(|index| vec![index; 200 + index as usize])
+            .collect::<Vec<_>>();
+        let first = build(&contents, None)?;
+        let previous = PreviousBuild::from_package(&first)?;
+        assert!(previous.segments() > 4, "{}", previous.segments());
+
+        contents[10] = vec![0xff; 300];
+        let rebuilt = build(&contents, Some(previous.clone()))?;
+        let entries = rebuilt.payload_entries()?;
+        assert_eq!(64, entries.len());
+        assert_eq!(contents[10], entries[10].content);
+        rebuilt.verify_digests()?;
+
+        let again = PreviousBuild::from_package(&rebuilt)?;
+        let shared = again
+            .segments
+            .keys()
+            .filter(|digest| previous.segments.contains_key(*digest))
+            .count();
+        assert!(
+            shared + 2 >= again.segments(),
+            "{} of {}",
+            shared,
+            again.segments()
+        );
+        let digests = rebuilt
+            .metadata
+            .header
+            .get_entry_string_array_data(IndexTag::RPMTAG_FILEDIGESTS)?;
+        assert_eq!(
+            hex::encode(sha2::Sha256::digest(&contents[10])),
+            digests[10]
+        );
+
+        // a file changed in place gets the digest of its new content
+        let stale = digests[20].clone();
+        contents[20] = vec![0xfe; contents[20].len()];
+        let rebuilt = build(&contents, Some(previous))?;
+        rebuilt.verify_digests()?;
+        let digests = rebuilt
+            .metadata
+            .header
+            .get_entry_string_array_data(IndexTag::RPMTAG_FILEDIGESTS)?;
+        assert_ne!(stale, digests[20]);
+        assert_eq!(
+            hex::encode(sha2::Sha256::digest(&contents[20])),
+            digests[20]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unsegmented() -> Result<(), Box<dyn std::error::Error>> {
+        let mut builder = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "foo")
+            .compression(Compressor::from_str("gzip")?);
+        builder.add_data(
+            vec![1; 64],
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/a").into(),
+        )?;
+        let pkg = builder.build()?;
+        assert_eq!(0, PreviousBuild::from_package(&pkg)?.segments());
+        assert_eq!(1, pkg.payload_entries()?.len());
+
+        let mut builder = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "foo")
+            .compression(Compressor::from_str("gzip")?)
+            .payload_segments(16);
+        builder.add_data(
+            vec![1; 64],
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/a").into(),
+        )?;
+        assert!(matches!(
+            builder.build(),
+            Err(RPMError::UnsegmentablePayload { .. })
+        ));
+        let builder = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "foo")
+            .reuse_previous(PreviousBuild::from_package(&pkg)?);
+        assert!(matches!(
+            builder.build(),
+            Err(RPMError::UnsegmentablePayload { .. })
+        ));
+
+        let mut builder = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "foo")
+            .compression(Compressor::from_str("zstd")?);
+        builder.add_data(
+            vec![1; 64],
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/a").into(),
+        )?;
+        let pkg = builder.build()?;
+        assert_eq!(1, PreviousBuild::from_package(&pkg)?.segments());
+        Ok(())
+    }
+}
@@ -0,0 +1,780 @@
+//! Unpack a package into a directory without involving rpm or a chroot.
+//!
+//! This is meant for assembling image trees, there is no rpm database
+//! being updated and file ownership is not applied.
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::accounts::Accounts;
+use super::builder::remap_path;
+use super::headers::*;
+use super::payload;
+use super::progress::{self, CancellationToken, Phase, Progress};
+use super::scriptlet::{self, FailurePolicy};
+use super::{PayloadEntry, RPMPackage, Timestamp};
+
+/// The scriptlets run as part of an installation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptletKind {
+    /// `%pre`, run before any file is unpacked.
+    PreInstall,
+    /// `%post`, run after all files are unpacked.
+    PostInstall,
+}
+
+/// A scriptlet handed to the executor of [`InstallOptions::scriptlet_executor`].
+#[derive(Debug, Clone)]
+pub struct Scriptlet<'a> {
+    pub kind: ScriptletKind,
+    /// Interpreter the script is meant for, `/bin/sh` if the package does not declare one.
+    pub interpreter: String,
+    pub body: &'a str,
+    /// Directory the package is installed into.
+    pub root: &'a Path,
+    /// The prefixes of a relocatable package after relocation,
+    /// what rpm exports as `$RPM_INSTALL_PREFIX0`, `$RPM_INSTALL_PREFIX1` and so on.
+    pub install_prefixes: &'a [String],
+    /// What rpm would do if the scriptlet failed. Errors of the executor stop the
+    /// installation either way.
+    pub failure_policy: FailurePolicy,
+}
+
+/// Owner of a file about to be unpacked, handed to the mapping of [`InstallOptions::owner_mapping`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileOwner<'a> {
+    /// Path as recorded in the package.
+    pub path: &'a str,
+    pub user: &'a str,
+    pub group: &'a str,
+    /// Id of `user` in the `etc/passwd` of the root, root for unknown users like rpm does.
+    /// The id of the payload if the root has no `etc/passwd` yet.
+    pub uid: u32,
+    /// Id of `group` in the `etc/group` of the root, chosen like [`uid`](Self::uid).
+    pub gid: u32,
+}
+
+type Executor<'e> = Box<dyn FnMut(&Scriptlet) -> Result<(), RPMError> + 'e>;
+type OwnerMapping<'e> = Box<dyn FnMut(&FileOwner) -> (u32, u32) + 'e>;
+
+/// Controls the behaviour of [`RPMPackage::install_into`].
+#[derive(Default)]
+pub struct InstallOptions<'e> {
+    overwrite: bool,
+    executor: Option<Executor<'e>>,
+    progress: Option<Box<dyn Progress + 'e>>,
+    cancellation: Option<CancellationToken>,
+    prefix: Option<String>,
+    relocations: Vec<(String, String)>,
+    owner_mapping: Option<OwnerMapping<'e>>,
+    sidecar: Option<Box<dyn std::io::Write + 'e>>,
+}
+
+impl<'e> InstallOptions<'e> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace files already present in the target directory instead of failing.
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Install the files below the first prefix of a relocatable package to `prefix` instead,
+    /// like `rpm --prefix`.
+    pub fn prefix<T: Into<String>>(mut self, prefix: T) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Install the files below the prefix `from` to `to` instead, like `rpm --relocate`.
+    ///
+    /// `from` has to be one of the prefixes the package declares.
+    pub fn relocate<F: Into<String>, T: Into<String>>(mut self, from: F, to: T) -> Self {
+        self.relocations.push((from.into(), to.into()));
+        self
+    }
+
+    /// Run the install scriptlets through `executor`.
+    ///
+    /// Without an executor scriptlets are skipped.
+    /// rpm passes the number of installed instances as `$1`, which is `1` for a fresh install.
+    pub fn scriptlet_executor<F>(mut self, executor: F) -> Self
+    where
+        F: FnMut(&Scriptlet) -> Result<(), RPMError> + 'e,
+    {
+        self.executor = Some(Box::new(executor));
+        self
+    }
+
+    /// Pick the user and group id of every file with `mapping`, e.g. to shift them into
+    /// the id range of a user namespace.
+    ///
+    /// Unpacking does not change owners, the ids end up in [`InstalledFile`]
+    /// and the [`metadata_sidecar`](Self::metadata_sidecar).
+    pub fn owner_mapping<F>(mut self, mapping: F) -> Self
+    where
+        F: FnMut(&FileOwner) -> (u32, u32) + 'e,
+    {
+        self.owner_mapping = Some(Box::new(mapping));
+        self
+    }
+
+    /// Record the owner, mode and modification time every file is meant to have
+    /// in `sidecar`, as mtree spec.
+    ///
+    /// Like the database of fakeroot or pseudo this allows unprivileged image
+    /// builders to apply the metadata afterwards, e.g. with `bsdtar` or
+    /// `mkfs.erofs`, which read mtree specs.
+    pub fn metadata_sidecar<W: std::io::Write + 'e>(mut self, sidecar: W) -> Self {
+        self.sidecar = Some(Box::new(sidecar));
+        self
+    }
+
+    /// Report every unpacked file as [`Phase::Extract`].
+    pub fn progress<P: Progress + 'e>(mut self, progress: P) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Stop unpacking with [`RPMError::Cancelled`] once `token` is cancelled,
+    /// files unpacked so far are left in place.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+}
+
+/// A file unpacked by [`RPMPackage::install_into`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledFile {
+    /// Path as recorded in the package.
+    pub path: PathBuf,
+    /// Location the file was written to.
+    pub location: PathBuf,
+    pub mode: FileMode,
+    pub user: String,
+    pub group: String,
+    /// Owner after [`InstallOptions::owner_mapping`].
+    pub uid: u32,
+    pub gid: u32,
+    pub mtime: Timestamp,
+}
+
+impl RPMPackage {
+    /// Unpack all files to `root`, as if `root` was `/`.
+    ///
+    /// Returns the unpacked files in payload order.
+    pub fn install_into<P: AsRef<Path>>(
+        &self,
+        root: P,
+        mut options: InstallOptions,
+    ) -> Result<Vec<InstalledFile>, RPMError> {
+        let root = root.as_ref();
+        let entries = self.payload_entries()?;
+        let relocations = self.relocations(&options)?;
+        let install_prefixes = self
+            .metadata
+            .header
+            .get_prefixes()?
+            .iter()
+            .map(|prefix| relocate(prefix, &relocations))
+            .collect::<Vec<_>>();
+
+        self.run_scriptlet(
+            &mut options,
+            ScriptletKind::PreInstall,
+            root,
+            &install_prefixes,
+        )?;
+
+        let accounts = Accounts::read(root);
+        let owners = self.file_owners(&entries)?;
+        if let Some(sidecar) = options.sidecar.as_mut() {
+            writeln!(sidecar, "#mtree")?;
+        }
+
+        let total_size = entries
+            .iter()
+            .map(|entry| entry.content.len() as u64)
+            .sum::<u64>();
+        let mut extracted = 0;
+        let mut installed: Vec<InstalledFile> = Vec::with_capacity(entries.len());
+        let sources = payload::hardlink_sources(&entries);
+        for (entry, source) in entries.into_iter().zip(sources) {
+            progress::check(options.cancellation.as_ref())?;
+            let path = relocate(&entry.path, &relocations);
+            let location = location_in_root(root, &path)?;
+            if let Some(parent) = location.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            if entry.is_dir() {
+                // a symlink in place of the directory might point out of `root`
+                let is_symlink = std::fs::symlink_metadata(&location)
+                    .map_or(false, |meta| meta.file_type().is_symlink());
+                if is_symlink {
+                    if !options.overwrite {
+                        return Err(RPMError::InvalidDestinationPath {
+                            path: path.to_string(),
+                            desc: "directory must not be a symbolic link",
+                        });
+                    }
+                    std::fs::remove_file(&location)?;
+                }
+                std::fs::create_dir_all(&location)?;
+            } else {
+                if std::fs::symlink_metadata(&location).is_ok() {
+                    if !options.overwrite {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::AlreadyExists,
+                            format!("{} already exists", location.display()),
+                        )
+                        .into());
+                    }
+                    std::fs::remove_file(&location)?;
+                }
+                if entry.is_symlink() {
+                    let target = String::from_utf8_lossy(&entry.content).to_string();
+                    symlink(&target, &location)?;
+                } else if let Some(source) = source {
+                    std::fs::hard_link(&installed[source].location, &location)?;
+                } else {
+                    std::fs::write(&location, &entry.content)?;
+                }
+            }
+            if !entry.is_symlink() {
+                set_permissions(&location, entry.mode.permissions())?;
+            }
+            if !entry.is_dir() && !entry.is_symlink() {
+                let mtime = filetime::FileTime::from_unix_time(i64::from(entry.mtime.seconds()), 0);
+                filetime::set_file_mtime(&location, mtime)?;
+            }
+            let (user, group) = owners
+                .get(Path::new(&entry.path))
+                .copied()
+                .unwrap_or(("root", "root"));
+            let owner = FileOwner {
+                path: &entry.path,
+                user,
+                group,
+                uid: accounts.uid(user).unwrap_or(entry.uid),
+                gid: accounts.gid(group).unwrap_or(entry.gid),
+            };
+            let (uid, gid) = match options.owner_mapping.as_mut() {
+                Some(mapping) => mapping(&owner),
+                None => (owner.uid, owner.gid),
+            };
+            if let Some(sidecar) = options.sidecar.as_mut() {
+                write_mtree_entry(sidecar, &path, &entry, &owner, uid, gid)?;
+            }
+            extracted += entry.content.len() as u64;
+            progress::report(
+                options.progress.as_deref(),
+                Phase::Extract,
+                extracted,
+                Some(total_size),
+                Some(&entry.path),
+            );
+            installed.push(InstalledFile {
+                path: PathBuf::from(&entry.path),
+                location,
+                mode: entry.mode,
+                user: user.to_string(),
+                group: group.to_string(),
+                uid,
+                gid,
+                mtime: entry.mtime,
+            });
+        }
+
+        self.run_scriptlet(
+            &mut options,
+            ScriptletKind::PostInstall,
+            root,
+            &install_prefixes,
+        )?;
+        Ok(installed)
+    }
+
+    /// The owner names the header records for each path.
+    fn file_owners(
+        &self,
+        entries: &[PayloadEntry],
+    ) -> Result<HashMap<PathBuf, (&str, &str)>, RPMError> {
+        if entries.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let header = &self.metadata.header;
+        let users = header.get_entry_string_array_data(IndexTag::RPMTAG_FILEUSERNAME)?;
+        let groups = header.get_entry_string_array_data(IndexTag::RPMTAG_FILEGROUPNAME)?;
+        Ok(
+            itertools::multizip((header.get_file_paths()?, users, groups))
+                .map(|(path, user, group)| (path, (user.as_str(), group.as_str())))
+                .collect(),
+        )
+    }
+
+    /// Check the requested relocations against the prefixes of the package.
+    fn relocations(&self, options: &InstallOptions) -> Result<Vec<(String, String)>, RPMError> {
+        let prefixes = self.metadata.header.get_prefixes()?;
+        let mut relocations = Vec::new();
+        if let Some(prefix) = &options.prefix {
+            let first = prefixes.first().ok_or_else(|| {
+                RPMError::NotRelocatable(
+                    self.metadata
+                        .header
+                        .get_name()
+                        .unwrap_or("<unknown>")
+                        .to_string(),
+                )
+            })?;
+            relocations.push((first.clone(), prefix.clone()));
+        }
+        for (from, to) in options.relocations.iter() {
+            let normalized = from.trim_end_matches('/');
+            if !prefixes.iter().any(|prefix| prefix == normalized) {
+                return Err(RPMError::NotRelocatable(from.clone()));
+            }
+            relocations.push((normalized.to_string(), to.clone()));
+        }
+        Ok(relocations)
+    }
+
+    fn run_scriptlet(
+        &self,
+        options: &mut InstallOptions,
+        kind: ScriptletKind,
+        root: &Path,
+        install_prefixes: &[String],
+    ) -> Result<(), RPMError> {
+        let executor = match options.executor.as_mut() {
+            Some(executor) => executor,
+            None => return Ok(()),
+        };
+        let (script_tag, prog_tag, scriptlet) = match kind {
+            ScriptletKind::PreInstall => (
+                IndexTag::RPMTAG_PREIN,
+                IndexTag::RPMTAG_PREINPROG,
+                scriptlet::Scriptlet::PreInstall,
+            ),
+            ScriptletKind::PostInstall => (
+                IndexTag::RPMTAG_POSTIN,
+                IndexTag::RPMTAG_POSTINPROG,
+                scriptlet::Scriptlet::PostInstall,
+            ),
+        };
+        let header = &self.metadata.header;
+        let body = match header.get_entry_string_data(script_tag) {
+            Ok(body) => body,
+            Err(_) => return Ok(()),
+        };
+        // older rpm versions store the interpreter as string, newer ones as string array
+        let interpreter = header
+            .get_entry_string_data(prog_tag)
+            .map(|prog| prog.to_string())
+            .or_else(|_| {
+                header
+                    .get_entry_string_array_data(prog_tag)
+                    .map(|prog| prog.join(" "))
+            })
+            .unwrap_or_else(|_| "/bin/sh".to_string());
+        executor(&Scriptlet {
+            kind,
+            interpreter,
+            body,
+            root,
+            install_prefixes,
+            failure_policy: scriptlet.failure_policy(header.get_scriptlet_flags(scriptlet)?),
+        })
+    }
+}
+
+/// Apply the first matching relocation to `path`.
+fn relocate(path: &str, relocations: &[(String, String)]) -> String {
+    relocations
+        .iter()
+        .find_map(|(from, to)| remap_path(path, from, to))
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Map a payload path into `root`, refusing anything that would escape it.
+///
+/// Directories below `root` the path passes through must not be symbolic links,
+/// which might point out of `root`, e.g. one installed by the package before.
+pub(crate) fn location_in_root(root: &Path, path: &str) -> Result<PathBuf, RPMError> {
+    let mut location = root.to_path_buf();
+    let mut components = Path::new(path).components().peekable();
+    while let Some(component) = components.next() {
+        match component {
+            Component::Normal(part) => location.push(part),
+            Component::RootDir | Component::CurDir => continue,
+            _ => {
+                return Err(RPMError::InvalidDestinationPath {
+                    path: path.to_string(),
+                    desc: "path must not leave the installation root",
+                })
+            }
+        }
+        let is_parent = components.peek().is_some();
+        if is_parent
+            && std::fs::symlink_metadata(&location)
+                .map_or(false, |meta| meta.file_type().is_symlink())
+        {
+            return Err(RPMError::InvalidDestinationPath {
+                path: path.to_string(),
+                desc: "path must not pass through a symbolic link",
+            });
+        }
+    }
+    Ok(location)
+}
+
+/// Describe an unpacked file as line of an mtree spec.
+fn write_mtree_entry(
+    out: &mut dyn std::io::Write,
+    path: &str,
+    entry: &PayloadEntry,
+    owner: &FileOwner,
+    uid: u32,
+    gid: u32,
+) -> Result<(), RPMError> {
+    let kind = if entry.is_dir() {
+        "dir"
+    } else if entry.is_symlink() {
+        "link"
+    } else {
+        "file"
+    };
+    write!(
+        out,
+        ".{} type={} uid={} gid={} uname={} gname={} mode={:04o} time={}.0",
+        mtree_escape(path.trim_end_matches('/').as_bytes()),
+        kind,
+        uid,
+        gid,
+        mtree_escape(owner.user.as_bytes()),
+        mtree_escape(owner.group.as_bytes()),
+        entry.mode.permissions(),
+        entry.mtime.seconds()
+    )?;
+    if entry.is_symlink() {
+        write!(out, " link={}", mtree_escape(&entry.content))?;
+    } else if !entry.is_dir() {
+        write!(out, " size={}", entry.content.len())?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// mtree encodes whitespace, its special characters and anything not ASCII as octal escapes.
+fn mtree_escape(text: &[u8]) -> String {
+    text.iter()
+        .map(|byte| match byte {
+            b'!'..=b'~' if !matches!(byte, b'\\' | b'#' | b'=') => (*byte as char).to_string(),
+            byte => format!("\\{:03o}", byte),
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn symlink(target: &str, location: &Path) -> Result<(), RPMError> {
+    std::os::unix::fs::symlink(target, location)?;
+    Ok(())
+}
+
+#[cfg(windows)]
+fn symlink(target: &str, location: &Path) -> Result<(), RPMError> {
+    std::os::windows::fs::symlink_file(target, location)?;
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(target: &str, location: &Path) -> Result<(), RPMError> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        format!(
+            "can not link {} to {} on this platform",
+            location.display(),
+            target
+        ),
+    )
+    .into())
+}
+
+#[cfg(unix)]
+fn set_permissions(location: &Path, permissions: u16) -> Result<(), RPMError> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(
+        location,
+        std::fs::Permissions::from_mode(permissions as u32),
+    )?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_permissions(_location: &Path, _permissions: u16) -> Result<(), RPMError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{RPMBuilder, RPMFileOptions};
+
+    #[test]
+    fn install_into_directory() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "an awesome tool")
+            .with_file(
+                "./test_assets/awesome.py",
+                RPMFileOptions::new("/usr/bin/awesome").mode(FileMode::regular(0o750)),
+            )?
+            .pre_install_script("echo pre")
+            .post_install_script("echo post $1")
+            .build()?;
+
+        let root = crate::tests::TestDir::new("install");
+
+        let mut ran = Vec::new();
+        let installed = pkg.install_into(
+            &root,
+            InstallOptions::new().scriptlet_executor(|scriptlet| {
+                assert_eq!("/bin/sh", scriptlet.interpreter);
+                let policy = match scriptlet.kind {
+                    ScriptletKind::PreInstall => FailurePolicy::Abort,
+                    ScriptletKind::PostInstall => FailurePolicy::Warn,
+                };
+                assert_eq!(policy, scriptlet.failure_policy);
+                assert_eq!(&*root, scriptlet.root);
+                ran.push((scriptlet.kind, scriptlet.body.to_string()));
+                Ok(())
+            }),
+        )?;
+        assert_eq!(
+            vec![
+                (ScriptletKind::PreInstall, "echo pre".to_string()),
+                (ScriptletKind::PostInstall, "echo post $1".to_string()),
+            ],
+            ran
+        );
+
+        assert_eq!(1, installed.len());
+        let location = root.join("usr/bin/awesome");
+        assert_eq!(location, installed[0].location);
+        assert_eq!(
+            std::fs::read("./test_assets/awesome.py")?,
+            std::fs::read(&location)?
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                0o750,
+                std::fs::metadata(&location)?.permissions().mode() & 0o7777
+            );
+        }
+
+        let mtime = filetime::FileTime::from_last_modification_time(&std::fs::metadata(&location)?);
+        assert_eq!(
+            i64::from(installed[0].mtime.seconds()),
+            mtime.unix_seconds()
+        );
+
+        // a second install must not silently clobber files
+        assert!(pkg.install_into(&root, InstallOptions::new()).is_err());
+        pkg.install_into(&root, InstallOptions::new().overwrite(true))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn install_relocated() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "an awesome tool")
+            .prefix("/opt/awesome")
+            .with_file(
+                "./test_assets/awesome.py",
+                RPMFileOptions::new("/opt/awesome/bin/awesome"),
+            )?
+            .pre_install_script("echo $RPM_INSTALL_PREFIX0")
+            .build()?;
+        assert_eq!(
+            &["/opt/awesome".to_string()],
+            pkg.metadata.header.get_prefixes()?
+        );
+
+        let root = crate::tests::TestDir::new("relocate");
+
+        let mut prefixes = Vec::new();
+        let installed = pkg.install_into(
+            &root,
+            InstallOptions::new()
+                .prefix("/home/user/awesome")
+                .scriptlet_executor(|scriptlet| {
+                    prefixes.extend_from_slice(scriptlet.install_prefixes);
+                    Ok(())
+                }),
+        )?;
+        assert_eq!(vec!["/home/user/awesome".to_string()], prefixes);
+        assert_eq!(PathBuf::from("/opt/awesome/bin/awesome"), installed[0].path);
+        assert_eq!(
+            root.join("home/user/awesome/bin/awesome"),
+            installed[0].location
+        );
+        assert!(installed[0].location.exists());
+
+        match pkg.install_into(&root, InstallOptions::new().relocate("/usr", "/opt")) {
+            Err(RPMError::NotRelocatable(prefix)) => assert_eq!("/usr", prefix),
+            other => panic!("expected a relocation error, got {:?}", other),
+        }
+
+        // files outside of the prefixes are refused when building
+        assert!(
+            RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "an awesome tool")
+                .prefix("/opt/awesome")
+                .with_file(
+                    "./test_assets/awesome.py",
+                    RPMFileOptions::new("/usr/bin/awesome")
+                )?
+                .build()
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn install_with_owner_mapping() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "an awesome tool")
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/etc/awesome config.toml")
+                    .mode(FileMode::regular(0o644))
+                    .user("daemon")
+                    .group("adm"),
+            )?
+            .with_file(
+                "./test_assets/awesome.py",
+                RPMFileOptions::new("/usr/bin/awesome").mode(FileMode::regular(0o755)),
+            )?
+            .build()?;
+
+        let root = crate::tests::TestDir::new("owners");
+        std::fs::create_dir_all(root.join("etc"))?;
+        std::fs::write(
+            root.join("etc/passwd"),
+            "root:x:0:0::/root:/bin/sh\ndaemon:x:2:2::/:/sbin/nologin\n",
+        )?;
+        std::fs::write(root.join("etc/group"), "root:x:0:\n")?;
+
+        let mut sidecar = Vec::new();
+        let mut owners = Vec::new();
+        let installed = pkg.install_into(
+            &root,
+            InstallOptions::new()
+                .overwrite(true)
+                .owner_mapping(|owner| {
+                    owners.push((owner.user.to_string(), owner.uid, owner.gid));
+                    (owner.uid + 100000, owner.gid + 100000)
+                })
+                .metadata_sidecar(&mut sidecar),
+        )?;
+        // unknown groups fall back to root
+        assert_eq!(
+            vec![("daemon".to_string(), 2, 0), ("root".to_string(), 0, 0)],
+            owners
+        );
+        assert_eq!("daemon", installed[0].user);
+        assert_eq!("adm", installed[0].group);
+        assert_eq!((100002, 100000), (installed[0].uid, installed[0].gid));
+
+        let sidecar = String::from_utf8(sidecar)?;
+        let lines = sidecar.lines().collect::<Vec<_>>();
+        assert_eq!("#mtree", lines[0]);
+        assert_eq!(
+            format!(
+                "./etc/awesome\\040config.toml type=file uid=100002 gid=100000 uname=daemon gname=adm mode=0644 time={}.0 size={}",
+                installed[0].mtime.seconds(),
+                std::fs::metadata("./test_assets/awesome.toml")?.len()
+            ),
+            lines[1]
+        );
+        assert!(lines[2].starts_with(
+            "./usr/bin/awesome type=file uid=100000 gid=100000 uname=root gname=root mode=0755 "
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_escaping_paths() {
+        assert!(location_in_root(Path::new("/tmp/root"), "/../etc/passwd").is_err());
+        assert_eq!(
+            PathBuf::from("/tmp/root/etc/passwd"),
+            location_in_root(Path::new("/tmp/root"), "/etc/passwd").unwrap()
+        );
+    }
+
+    #[test]
+    fn refuses_paths_through_symlinks() -> Result<(), Box<dyn std::error::Error>> {
+        let root = crate::tests::TestDir::new("symlink-escape");
+        let outside = crate::tests::TestDir::new("symlink-escape-outside");
+        let mut builder = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo");
+        builder.add_data(
+            outside.to_string_lossy().into_owned().into_bytes(),
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/a")
+                .symlink(outside.to_string_lossy())
+                .mode(crate::FileMode::symlink(0o777))
+                .into(),
+        )?;
+        builder.add_data(
+            b"root:x:0:0::/root:/bin/sh\n".to_vec(),
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/a/passwd").into(),
+        )?;
+        let pkg = builder.build()?;
+        assert!(matches!(
+            pkg.install_into(&root, InstallOptions::new()),
+            Err(RPMError::InvalidDestinationPath { .. })
+        ));
+        assert!(!outside.join("passwd").exists());
+        // the link itself may be replaced
+        assert!(location_in_root(&root, "/a").is_ok());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_directories_replaced_by_symlinks() -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::PermissionsExt;
+        let root = crate::tests::TestDir::new("symlink-dir");
+        let outside = crate::tests::TestDir::new("symlink-dir-outside");
+        let mut builder = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo");
+        builder.add_data(
+            Vec::new(),
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/a")
+                .mode(crate::FileMode::dir(0o700))
+                .into(),
+        )?;
+        let pkg = builder.build()?;
+        std::os::unix::fs::symlink(&*outside, root.join("a"))?;
+        let before = std::fs::metadata(&*outside)?.permissions().mode();
+
+        assert!(matches!(
+            pkg.install_into(&root, InstallOptions::new()),
+            Err(RPMError::InvalidDestinationPath { .. })
+        ));
+        assert_eq!(before, std::fs::metadata(&*outside)?.permissions().mode());
+
+        pkg.install_into(&root, InstallOptions::new().overwrite(true))?;
+        let installed = std::fs::symlink_metadata(root.join("a"))?;
+        assert!(installed.is_dir());
+        assert_eq!(0o700, installed.permissions().mode() & 0o7777);
+        assert_eq!(before, std::fs::metadata(&*outside)?.permissions().mode());
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+//! Packages dnf installs next to older versions instead of upgrading them, like kernels.
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::{Dependency, Header, RPMBuilder};
+
+/// Provides dnf treats as install-only when `installonlypkgs` is not configured,
+/// see dnf.conf(5). At most `installonly_limit` versions of such packages are kept.
+pub const INSTALL_ONLY_PROVIDES: &[&str] = &[
+    "kernel",
+    "kernel-PAE",
+    "installonlypkg(kernel)",
+    "installonlypkg(kernel-module)",
+    "installonlypkg(vm)",
+    "multiversion(kernel)",
+];
+
+/// Kind of install-only package, see [`RPMBuilder::install_only`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallOnly {
+    /// A kernel image, provides `installonlypkg(kernel)` and `multiversion(kernel)`,
+    /// the latter for zypper.
+    Kernel,
+    /// Modules built for one kernel release, provides `installonlypkg(kernel-module)`.
+    KernelModule,
+    /// A virtual machine image, provides `installonlypkg(vm)`.
+    Vm,
+}
+
+impl InstallOnly {
+    /// The provides marking a package of this kind.
+    pub fn provides(&self) -> &'static [&'static str] {
+        match self {
+            InstallOnly::Kernel => &["installonlypkg(kernel)", "multiversion(kernel)"],
+            InstallOnly::KernelModule => &["installonlypkg(kernel-module)"],
+            InstallOnly::Vm => &["installonlypkg(vm)"],
+        }
+    }
+}
+
+impl RPMBuilder {
+    /// Mark the package as install-only, so dnf keeps installed versions instead of
+    /// replacing them, up to its `installonly_limit`.
+    ///
+    /// Packages of every version should be built with this, dnf decides from the
+    /// package being installed.
+    pub fn install_only(mut self, kind: InstallOnly) -> Self {
+        for provide in kind.provides() {
+            self = self.provides(Dependency::any(provide.to_string()));
+        }
+        self
+    }
+
+    /// Require the kernel with `uname -r` equal to `release`, as modules built for
+    /// one kernel need. Kernels provide `kernel-uname-r` with their release.
+    pub fn for_kernel_release(self, release: &str) -> Self {
+        self.requires(Dependency::eq("kernel-uname-r", release))
+    }
+}
+
+impl Header<IndexTag> {
+    /// Whether dnf installs the package next to other versions with its default
+    /// configuration, i.e. the package provides one of [`INSTALL_ONLY_PROVIDES`].
+    pub fn is_install_only(&self) -> Result<bool, RPMError> {
+        self.is_install_only_with(INSTALL_ONLY_PROVIDES)
+    }
+
+    /// Whether the package provides one of `install_only`, as configured with
+    /// `installonlypkgs` in dnf.conf.
+    pub fn is_install_only_with<S: AsRef<str>>(
+        &self,
+        install_only: &[S],
+    ) -> Result<bool, RPMError> {
+        let provides = match self.get_provides() {
+            Ok(provides) => provides,
+            Err(RPMError::TagNotFound(_)) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        Ok(provides.iter().any(|provide| {
+            install_only
+                .iter()
+                .any(|name| name.as_ref() == provide.name())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn builder(name: &str) -> RPMBuilder {
+        RPMBuilder::new(name, "6.1.0", "GPLv2", "x86_64", "a custom kernel")
+    }
+
+    #[test]
+    fn install_only_provides() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = builder("custom-kernel-core")
+            .install_only(InstallOnly::Kernel)
+            .build()?;
+        let header = &pkg.metadata.header;
+        assert!(header.is_install_only()?);
+        assert!(header
+            .get_provides()?
+            .iter()
+            .any(|dep| dep.name() == "multiversion(kernel)"));
+
+        let pkg = builder("custom-modules")
+            .install_only(InstallOnly::KernelModule)
+            .for_kernel_release("6.1.0-1.x86_64")
+            .build()?;
+        let header = &pkg.metadata.header;
+        assert!(header.is_install_only()?);
+        assert!(header
+            .get_requires()?
+            .iter()
+            .any(|dep| dep.name() == "kernel-uname-r" && dep.version() == "6.1.0-1.x86_64"));
+
+        let pkg = builder("custom-tools").build()?;
+        assert!(!pkg.metadata.header.is_install_only()?);
+        assert!(pkg
+            .metadata
+            .header
+            .is_install_only_with(&["custom-tools"])?);
+        Ok(())
+    }
+}
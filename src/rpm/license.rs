@@ -0,0 +1,587 @@
+//! Checking `License` tags against the SPDX license expression syntax.
+//!
+//! Expressions combine license identifiers with `AND`, `OR`, `WITH` and parentheses,
+//! like `(MIT OR Apache-2.0) AND GPL-2.0-or-later WITH Classpath-exception-2.0`.
+//! Identifiers are checked against a list of common SPDX licenses and exceptions,
+//! `LicenseRef-` identifiers are always accepted.
+//!
+//! ```rust
+//! use rpm::{check_license, LicenseFinding, LicensePolicy};
+//!
+//! let policy = LicensePolicy::new();
+//! assert!(check_license("MIT OR Apache-2.0", &policy).is_empty());
+//! assert_eq!(
+//!     vec![LicenseFinding::UnknownLicense("GPLv2+".to_string())],
+//!     check_license("GPLv2+", &policy)
+//! );
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::errors::*;
+
+use super::RPMBuilder;
+
+/// Common identifiers of the SPDX license list.
+const LICENSES: &[&str] = &[
+    "0BSD",
+    "AFL-3.0",
+    "AGPL-3.0-only",
+    "AGPL-3.0-or-later",
+    "Apache-1.1",
+    "Apache-2.0",
+    "APSL-2.0",
+    "Artistic-1.0",
+    "Artistic-1.0-Perl",
+    "Artistic-2.0",
+    "BitTorrent-1.1",
+    "BlueOak-1.0.0",
+    "BSD-1-Clause",
+    "BSD-2-Clause",
+    "BSD-2-Clause-Patent",
+    "BSD-3-Clause",
+    "BSD-3-Clause-Clear",
+    "BSD-4-Clause",
+    "BSD-Source-Code",
+    "BSL-1.0",
+    "bzip2-1.0.6",
+    "CC-BY-3.0",
+    "CC-BY-4.0",
+    "CC-BY-SA-3.0",
+    "CC-BY-SA-4.0",
+    "CC0-1.0",
+    "CDDL-1.0",
+    "CDDL-1.1",
+    "CECILL-2.1",
+    "CPL-1.0",
+    "curl",
+    "ECL-2.0",
+    "EFL-2.0",
+    "EPL-1.0",
+    "EPL-2.0",
+    "EUPL-1.1",
+    "EUPL-1.2",
+    "FSFAP",
+    "FSFUL",
+    "FSFULLR",
+    "FTL",
+    "GFDL-1.1-only",
+    "GFDL-1.1-or-later",
+    "GFDL-1.2-only",
+    "GFDL-1.2-or-later",
+    "GFDL-1.3-only",
+    "GFDL-1.3-or-later",
+    "GPL-1.0-only",
+    "GPL-1.0-or-later",
+    "GPL-2.0-only",
+    "GPL-2.0-or-later",
+    "GPL-3.0-only",
+    "GPL-3.0-or-later",
+    "HPND",
+    "ICU",
+    "IJG",
+    "ImageMagick",
+    "Info-ZIP",
+    "IPA",
+    "ISC",
+    "LGPL-2.0-only",
+    "LGPL-2.0-or-later",
+    "LGPL-2.1-only",
+    "LGPL-2.1-or-later",
+    "LGPL-3.0-only",
+    "LGPL-3.0-or-later",
+    "Libpng",
+    "libpng-2.0",
+    "libtiff",
+    "LPPL-1.3c",
+    "MirOS",
+    "MIT",
+    "MIT-0",
+    "MIT-CMU",
+    "MPL-1.1",
+    "MPL-2.0",
+    "MPL-2.0-no-copyleft-exception",
+    "MS-PL",
+    "MS-RL",
+    "NCSA",
+    "ODbL-1.0",
+    "OFL-1.1",
+    "OpenSSL",
+    "OSL-3.0",
+    "PHP-3.01",
+    "PostgreSQL",
+    "PSF-2.0",
+    "Python-2.0",
+    "Python-2.0.1",
+    "QPL-1.0",
+    "Ruby",
+    "SGI-B-2.0",
+    "SISSL",
+    "Sleepycat",
+    "SSPL-1.0",
+    "TCL",
+    "Unicode-3.0",
+    "Unicode-DFS-2016",
+    "Unlicense",
+    "UPL-1.0",
+    "Vim",
+    "W3C",
+    "WTFPL",
+    "X11",
+    "XFree86-1.1",
+    "Zlib",
+    "zlib-acknowledgement",
+    "ZPL-2.1",
+];
+
+/// Common identifiers of the SPDX license exception list.
+const EXCEPTIONS: &[&str] = &[
+    "Autoconf-exception-2.0",
+    "Autoconf-exception-3.0",
+    "Bison-exception-2.2",
+    "Classpath-exception-2.0",
+    "Font-exception-2.0",
+    "GCC-exception-2.0",
+    "GCC-exception-3.1",
+    "LGPL-3.0-linking-exception",
+    "libtool-exception",
+    "Linux-syscall-note",
+    "LLVM-exception",
+    "OCaml-LGPL-linking-exception",
+    "OpenJDK-assembly-exception-1.0",
+    "openvpn-openssl-exception",
+    "Qt-GPL-exception-1.0",
+    "Qt-LGPL-exception-1.1",
+    "u-boot-exception-2.0",
+    "WxWindows-exception-3.1",
+];
+
+/// Deprecated identifiers and what replaces them.
+const DEPRECATED: &[(&str, &str)] = &[
+    ("AGPL-3.0", "AGPL-3.0-only"),
+    ("GFDL-1.3", "GFDL-1.3-only"),
+    ("GPL-2.0", "GPL-2.0-only"),
+    ("GPL-2.0+", "GPL-2.0-or-later"),
+    ("GPL-3.0", "GPL-3.0-only"),
+    ("GPL-3.0+", "GPL-3.0-or-later"),
+    ("LGPL-2.0", "LGPL-2.0-only"),
+    ("LGPL-2.0+", "LGPL-2.0-or-later"),
+    ("LGPL-2.1", "LGPL-2.1-only"),
+    ("LGPL-2.1+", "LGPL-2.1-or-later"),
+    ("LGPL-3.0", "LGPL-3.0-only"),
+    ("LGPL-3.0+", "LGPL-3.0-or-later"),
+    (
+        "wxWindows",
+        "LGPL-2.0-or-later WITH WxWindows-exception-3.1",
+    ),
+];
+
+/// A problem with a license expression, see [`check_license`].
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LicenseFinding {
+    /// The expression is malformed at byte `position`, no identifiers are checked.
+    Syntax {
+        position: usize,
+        reason: &'static str,
+    },
+    /// Neither a known SPDX identifier nor a `LicenseRef-`.
+    UnknownLicense(String),
+    /// An exception following `WITH` which is not a known SPDX exception.
+    UnknownException(String),
+    /// An SPDX identifier which is deprecated in favour of `replacement`.
+    Deprecated {
+        id: String,
+        replacement: &'static str,
+    },
+    /// A license the [`LicensePolicy`] does not allow.
+    NotAllowed(String),
+}
+
+impl fmt::Display for LicenseFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LicenseFinding::Syntax { position, reason } => {
+                write!(f, "{} at position {}", reason, position)
+            }
+            LicenseFinding::UnknownLicense(id) => write!(f, "unknown license {}", id),
+            LicenseFinding::UnknownException(id) => write!(f, "unknown exception {}", id),
+            LicenseFinding::Deprecated { id, replacement } => {
+                write!(f, "{} is deprecated, use {}", id, replacement)
+            }
+            LicenseFinding::NotAllowed(id) => write!(f, "license {} is not allowed", id),
+        }
+    }
+}
+
+type AllowHook = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Which licenses [`check_license`] accepts, by default any valid SPDX expression.
+#[derive(Clone, Default)]
+pub struct LicensePolicy {
+    known: Vec<String>,
+    allowed: Vec<String>,
+    hook: Option<AllowHook>,
+}
+
+impl fmt::Debug for LicensePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LicensePolicy")
+            .field("known", &self.known)
+            .field("allowed", &self.allowed)
+            .field("hook", &self.hook.is_some())
+            .finish()
+    }
+}
+
+impl LicensePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept the license or exception `id`, for SPDX identifiers missing from
+    /// the built-in list.
+    pub fn known<T: Into<String>>(mut self, id: T) -> Self {
+        self.known.push(id.into());
+        self
+    }
+
+    /// Only allow the licenses passed here, every other license is reported as
+    /// [`NotAllowed`](LicenseFinding::NotAllowed). Compared as written, with a
+    /// trailing `+`.
+    pub fn allow<T: Into<String>>(mut self, id: T) -> Self {
+        self.allowed.push(id.into());
+        self
+    }
+
+    /// Report licenses `allow` returns false for as [`NotAllowed`](LicenseFinding::NotAllowed),
+    /// in addition to the ones missing from the list given to [`allow`](Self::allow).
+    pub fn allow_with<F>(mut self, allow: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.hook = Some(Arc::new(allow));
+        self
+    }
+
+    fn is_known(&self, list: &[&str], id: &str) -> bool {
+        list.iter().any(|known| known.eq_ignore_ascii_case(id))
+            || self
+                .known
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(id))
+    }
+
+    fn check_license(&self, id: &str, findings: &mut Vec<LicenseFinding>) {
+        let is_ref = id.starts_with("LicenseRef-")
+            || (id.starts_with("DocumentRef-") && id.contains(":LicenseRef-"));
+        if let Some((_, replacement)) = DEPRECATED
+            .iter()
+            .find(|(deprecated, _)| deprecated.eq_ignore_ascii_case(id))
+        {
+            findings.push(LicenseFinding::Deprecated {
+                id: id.to_string(),
+                replacement,
+            });
+        } else if !is_ref && !self.is_known(LICENSES, id.strip_suffix('+').unwrap_or(id)) {
+            findings.push(LicenseFinding::UnknownLicense(id.to_string()));
+        }
+        let allowed = self.allowed.is_empty() || self.allowed.iter().any(|allowed| allowed == id);
+        if !allowed || self.hook.as_ref().map_or(false, |allow| !allow(id)) {
+            findings.push(LicenseFinding::NotAllowed(id.to_string()));
+        }
+    }
+}
+
+/// Check `expression` against the SPDX license expression syntax and `policy`.
+///
+/// Returns nothing for a valid expression of known, allowed licenses.
+pub fn check_license(expression: &str, policy: &LicensePolicy) -> Vec<LicenseFinding> {
+    let mut parser = Parser {
+        tokens: tokenize(expression),
+        next: 0,
+        end: expression.len(),
+        policy,
+        findings: Vec::new(),
+    };
+    let parsed = parser.expression().and_then(|_| match parser.peek() {
+        None => Ok(()),
+        Some((position, Token::Close)) => Err(LicenseFinding::Syntax {
+            position,
+            reason: "unbalanced closing parenthesis",
+        }),
+        Some((position, _)) => Err(LicenseFinding::Syntax {
+            position,
+            reason: "expected AND, OR or WITH",
+        }),
+    });
+    match parsed {
+        Ok(()) => parser.findings,
+        Err(syntax) => vec![syntax],
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Open,
+    Close,
+    Word(&'a str),
+}
+
+fn tokenize(expression: &str) -> Vec<(usize, Token<'_>)> {
+    let mut tokens = Vec::new();
+    let mut word_start = None;
+    for (position, c) in expression.char_indices() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if let Some(start) = word_start.take() {
+                tokens.push((start, Token::Word(&expression[start..position])));
+            }
+            match c {
+                '(' => tokens.push((position, Token::Open)),
+                ')' => tokens.push((position, Token::Close)),
+                _ => {}
+            }
+        } else if word_start.is_none() {
+            word_start = Some(position);
+        }
+    }
+    if let Some(start) = word_start {
+        tokens.push((start, Token::Word(&expression[start..])));
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token<'a>)>,
+    next: usize,
+    end: usize,
+    policy: &'a LicensePolicy,
+    findings: Vec<LicenseFinding>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<(usize, Token<'a>)> {
+        self.tokens.get(self.next).copied()
+    }
+
+    fn operator(&mut self, operator: &str) -> bool {
+        let found = matches!(self.peek(), Some((_, Token::Word(word))) if word == operator);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn expression(&mut self) -> Result<(), LicenseFinding> {
+        self.conjunction()?;
+        while self.operator("OR") {
+            self.conjunction()?;
+        }
+        Ok(())
+    }
+
+    fn conjunction(&mut self) -> Result<(), LicenseFinding> {
+        self.term()?;
+        while self.operator("AND") {
+            self.term()?;
+        }
+        Ok(())
+    }
+
+    fn term(&mut self) -> Result<(), LicenseFinding> {
+        let is_license = matches!(self.peek(), Some((_, Token::Word(_))));
+        self.atom()?;
+        if self.operator("WITH") {
+            if !is_license {
+                return Err(LicenseFinding::Syntax {
+                    position: self.tokens[self.next - 1].0,
+                    reason: "WITH must follow a license",
+                });
+            }
+            let (_, exception) = self.word("expected an exception")?;
+            if !self.policy.is_known(EXCEPTIONS, exception) {
+                self.findings
+                    .push(LicenseFinding::UnknownException(exception.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    fn atom(&mut self) -> Result<(), LicenseFinding> {
+        if let Some((position, Token::Open)) = self.peek() {
+            self.next += 1;
+            self.expression()?;
+            return match self.peek() {
+                Some((_, Token::Close)) => {
+                    self.next += 1;
+                    Ok(())
+                }
+                _ => Err(LicenseFinding::Syntax {
+                    position,
+                    reason: "unbalanced opening parenthesis",
+                }),
+            };
+        }
+        let (_, id) = self.word("expected a license")?;
+        self.policy.check_license(id, &mut self.findings);
+        Ok(())
+    }
+
+    /// The next token, which must be an identifier.
+    fn word(&mut self, reason: &'static str) -> Result<(usize, &'a str), LicenseFinding> {
+        match self.peek() {
+            Some((position, Token::Word(word))) => {
+                if matches!(word, "AND" | "OR" | "WITH") {
+                    return Err(LicenseFinding::Syntax { position, reason });
+                }
+                if matches!(word, "and" | "or" | "with") {
+                    return Err(LicenseFinding::Syntax {
+                        position,
+                        reason: "operators must be upper case",
+                    });
+                }
+                let valid = word.chars().enumerate().all(|(index, c)| {
+                    c.is_ascii_alphanumeric()
+                        || c == '-'
+                        || c == '.'
+                        || c == ':'
+                        || (c == '+' && index == word.len() - 1)
+                });
+                if !valid {
+                    return Err(LicenseFinding::Syntax {
+                        position,
+                        reason: "invalid character in identifier",
+                    });
+                }
+                self.next += 1;
+                Ok((position, word))
+            }
+            Some((position, _)) => Err(LicenseFinding::Syntax { position, reason }),
+            None => Err(LicenseFinding::Syntax {
+                position: self.end,
+                reason,
+            }),
+        }
+    }
+}
+
+impl RPMBuilder {
+    /// Refuse to build unless the license is a valid SPDX expression accepted by
+    /// `policy`, see [`check_license`].
+    pub fn license_policy(mut self, policy: LicensePolicy) -> Self {
+        self.license_policy = Some(policy);
+        self
+    }
+}
+
+/// Fail with the first finding of [`check_license`], if any.
+pub(crate) fn enforce(expression: &str, policy: &LicensePolicy) -> Result<(), RPMError> {
+    match check_license(expression, policy).into_iter().next() {
+        Some(finding) => Err(RPMError::InvalidLicense {
+            license: expression.to_string(),
+            reason: finding.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn valid_expressions() {
+        let policy = LicensePolicy::new();
+        for expression in [
+            "MIT",
+            "mit",
+            "Apache-2.0+",
+            "MIT OR Apache-2.0",
+            "(MIT OR Apache-2.0) AND BSD-3-Clause",
+            "GPL-2.0-or-later WITH Classpath-exception-2.0",
+            "LicenseRef-Proprietary AND (Zlib OR (ISC AND 0BSD))",
+            "DocumentRef-spdx-tool:LicenseRef-MIT-Style",
+        ] {
+            assert_eq!(
+                Vec::<LicenseFinding>::new(),
+                check_license(expression, &policy),
+                "{}",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        let policy = LicensePolicy::new();
+        for (expression, position, reason) in [
+            ("", 0, "expected a license"),
+            ("MIT OR", 6, "expected a license"),
+            ("MIT and ISC", 4, "expected AND"),
+            ("MIT OR and", 7, "operators must be upper case"),
+            ("(MIT OR ISC", 0, "unbalanced opening"),
+            ("MIT)", 3, "unbalanced closing"),
+            ("(MIT OR ISC) WITH LLVM-exception", 13, "WITH must follow"),
+            ("MIT, ISC", 0, "invalid character"),
+            ("MIT WITH", 8, "expected an exception"),
+        ] {
+            match check_license(expression, &policy).as_slice() {
+                [LicenseFinding::Syntax {
+                    position: found_position,
+                    reason: found_reason,
+                }] => {
+                    assert_eq!(position, *found_position, "{}", expression);
+                    assert!(found_reason.starts_with(reason), "{}", expression);
+                }
+                findings => panic!("{}: {:?}", expression, findings),
+            }
+        }
+
+        assert_eq!(
+            vec![
+                LicenseFinding::UnknownLicense("GPLv2".to_string()),
+                LicenseFinding::Deprecated {
+                    id: "GPL-2.0+".to_string(),
+                    replacement: "GPL-2.0-or-later"
+                },
+                LicenseFinding::UnknownException("Foo-exception".to_string()),
+            ],
+            check_license("GPLv2 AND GPL-2.0+ WITH Foo-exception", &policy)
+        );
+        assert!(check_license(
+            "Foo-exception AND MIT WITH Foo-exception",
+            &policy.known("Foo-exception")
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn allowlist() -> Result<(), Box<dyn std::error::Error>> {
+        let policy = LicensePolicy::new()
+            .allow("MIT")
+            .allow("Apache-2.0")
+            .allow("LicenseRef-Internal")
+            .allow_with(|id| id != "LicenseRef-Internal");
+        assert!(check_license("MIT AND Apache-2.0", &policy).is_empty());
+        assert_eq!(
+            vec![
+                LicenseFinding::NotAllowed("GPL-3.0-only".to_string()),
+                LicenseFinding::NotAllowed("LicenseRef-Internal".to_string()),
+            ],
+            check_license("GPL-3.0-only OR LicenseRef-Internal", &policy)
+        );
+
+        RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "foo")
+            .license_policy(policy.clone())
+            .build()?;
+        let rejected = RPMBuilder::new("foo", "1.0.0", "GPLv3+", "noarch", "foo")
+            .license_policy(policy)
+            .build();
+        assert!(matches!(
+            rejected,
+            Err(RPMError::InvalidLicense { reason, .. }) if reason == "unknown license GPLv3+"
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+//! Assertions on the size and content of packages.
+//!
+//! The [`Limits`] set on the builder are checked when building only. The limits of
+//! rpm itself on the size of a header are checked when parsing too, before the
+//! index and store are read.
+
+use crate::constants::IndexTag;
+use crate::errors::*;
+
+use super::headers::IndexHeader;
+use super::subpackage::matches_pattern;
+use super::{Header, RPMBuilder};
+
+/// The lead holds the name in 66 bytes, including the terminating nul byte.
+const LEAD_NAME_MAX: usize = 65;
+/// Longest file name, `name-version-release.arch.rpm` has to fit.
+const FILE_NAME_MAX: usize = 255;
+/// rpm rejects headers with more entries.
+const HEADER_TAGS_MAX: usize = 0xffff;
+/// rpm rejects headers with a larger store.
+const HEADER_DATA_MAX: usize = 0x0fff_ffff;
+
+/// Check the tags rpm truncates or rejects, whatever the limits of the builder are.
+pub(crate) fn check_package_tags(
+    name: &str,
+    version: &str,
+    release: &str,
+    arch: &str,
+    description: &str,
+) -> Result<(), RPMError> {
+    for (tag, value) in [
+        ("name", name),
+        ("version", version),
+        ("release", release),
+        ("arch", arch),
+        ("description", description),
+    ] {
+        if value.contains('\0') {
+            return Err(RPMError::InvalidTagValue {
+                tag,
+                reason: "contains a nul byte, rpm cuts it off there",
+            });
+        }
+    }
+    let file_name = format!("{}-{}-{}.{}.rpm", name, version, release, arch);
+    // the description is stored as summary too
+    for (limit, actual, max) in [
+        ("name length", name.len(), LEAD_NAME_MAX),
+        ("package file name length", file_name.len(), FILE_NAME_MAX),
+        ("description length", description.len(), HEADER_DATA_MAX / 2),
+    ] {
+        if actual > max {
+            return Err(RPMError::LimitExceeded {
+                limit,
+                actual: actual as u64,
+                max: max as u64,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Check the number of entries and the size of the store of a header.
+pub(crate) fn check_header(header: &Header<IndexTag>) -> Result<(), RPMError> {
+    check_index(&header.index_header)
+}
+
+/// Check the sizes an index header claims, before allocating for them.
+pub(crate) fn check_index(index: &IndexHeader) -> Result<(), RPMError> {
+    for (limit, actual, max) in [
+        (
+            "header entry count",
+            index.num_entries as usize,
+            HEADER_TAGS_MAX,
+        ),
+        (
+            "header store size",
+            index.header_size as usize,
+            HEADER_DATA_MAX,
+        ),
+    ] {
+        if actual > max {
+            return Err(RPMError::LimitExceeded {
+                limit,
+                actual: actual as u64,
+                max: max as u64,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// What [`RPMBuilder`] refuses to build, none of the limits is set by default.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Limits {
+    payload_size: Option<u64>,
+    file_count: Option<usize>,
+    forbidden_paths: Vec<String>,
+}
+
+impl Limits {
+    /// Check the files about to be packaged.
+    pub(crate) fn check_files<'a, I>(&self, paths: I) -> Result<(), RPMError>
+    where
+        I: ExactSizeIterator<Item = &'a str>,
+    {
+        if let Some(limit) = self.file_count {
+            if paths.len() > limit {
+                return Err(RPMError::LimitExceeded {
+                    limit: "file count",
+                    actual: paths.len() as u64,
+                    max: limit as u64,
+                });
+            }
+        }
+        for path in paths {
+            if let Some(pattern) = self
+                .forbidden_paths
+                .iter()
+                .find(|pattern| matches_pattern(pattern, path))
+            {
+                return Err(RPMError::ForbiddenPath {
+                    path: path.to_string(),
+                    pattern: pattern.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the compressed payload.
+    pub(crate) fn check_payload(&self, payload: &[u8]) -> Result<(), RPMError> {
+        match self.payload_size {
+            Some(limit) if payload.len() as u64 > limit => Err(RPMError::LimitExceeded {
+                limit: "payload size",
+                actual: payload.len() as u64,
+                max: limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl RPMBuilder {
+    /// Fail the build if the compressed payload takes more than `bytes`.
+    pub fn max_payload_size(mut self, bytes: u64) -> Self {
+        self.limits.payload_size = Some(bytes);
+        self
+    }
+
+    /// Fail the build if the package contains more than `count` files.
+    ///
+    /// Files added when building, e.g. build-id links, count as well.
+    pub fn max_file_count(mut self, count: usize) -> Self {
+        self.limits.file_count = Some(count);
+        self
+    }
+
+    /// Fail the build if any file matches one of `patterns`.
+    ///
+    /// Patterns are matched like those of [`SubPackage::files`](crate::SubPackage::files),
+    /// `/usr/local` forbids everything below it and `**/*.pyc` every compiled python file.
+    pub fn forbidden_paths<I, T>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.limits
+            .forbidden_paths
+            .extend(patterns.into_iter().map(Into::into));
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{IndexData, IndexEntry, RPMFileOptions};
+
+    #[test]
+    fn enforce_limits() -> Result<(), Box<dyn std::error::Error>> {
+        let builder = || {
+            RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "")
+                .with_file(
+                    "./test_assets/awesome.py",
+                    RPMFileOptions::new("/usr/lib/awesome/awesome.py"),
+                )?
+                .with_file(
+                    "./test_assets/awesome.toml",
+                    RPMFileOptions::new("/etc/awesome/config.toml"),
+                )
+        };
+        builder()?
+            .max_file_count(2)
+            .max_payload_size(1 << 20)
+            .forbidden_paths(["/usr/local", "**/*.pyc"])
+            .build()?;
+
+        match builder()?.max_file_count(1).build() {
+            Err(err @ RPMError::LimitExceeded { .. }) => {
+                assert_eq!("file count of 2 exceeds the limit of 1", err.to_string())
+            }
+            other => panic!("expected the file count to be exceeded, got {:?}", other),
+        }
+        assert!(matches!(
+            builder()?.max_payload_size(10).build(),
+            Err(RPMError::LimitExceeded {
+                limit: "payload size",
+                max: 10,
+                ..
+            })
+        ));
+        match builder()?.forbidden_paths(["/usr/lib/**/*.py"]).build() {
+            Err(RPMError::ForbiddenPath { path, pattern }) => {
+                assert_eq!("/usr/lib/awesome/awesome.py", path);
+                assert_eq!("/usr/lib/**/*.py", pattern);
+            }
+            other => panic!("expected a forbidden path, got {:?}", other),
+        }
+        assert!(builder()?.forbidden_paths(["/etc"]).build().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn enforce_tag_limits() -> Result<(), Box<dyn std::error::Error>> {
+        let name = "n".repeat(LEAD_NAME_MAX);
+        RPMBuilder::new(&name, "1.0.0", "MIT", "noarch", "").build()?;
+        match RPMBuilder::new(&format!("{}n", name), "1.0.0", "MIT", "noarch", "").build() {
+            Err(err @ RPMError::LimitExceeded { .. }) => {
+                assert_eq!("name length of 66 exceeds the limit of 65", err.to_string())
+            }
+            other => panic!("expected the name to be too long, got {:?}", other),
+        }
+        assert!(matches!(
+            RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "")
+                .release("1".repeat(240))
+                .build(),
+            Err(RPMError::LimitExceeded {
+                limit: "package file name length",
+                ..
+            })
+        ));
+        assert!(matches!(
+            RPMBuilder::new("foo", "1.0\0.0", "MIT", "noarch", "").build(),
+            Err(RPMError::InvalidTagValue { tag: "version", .. })
+        ));
+
+        let entries = (0..=HEADER_TAGS_MAX)
+            .map(|_| IndexEntry::new(IndexTag::RPMTAG_NAME, 0, IndexData::Int32(vec![0])))
+            .collect();
+        let header = Header::from_entries(entries, IndexTag::RPMTAG_HEADERIMMUTABLE);
+        assert!(matches!(
+            check_header(&header),
+            Err(RPMError::LimitExceeded {
+                limit: "header entry count",
+                ..
+            })
+        ));
+
+        // refused before the claimed store is allocated
+        let mut claimed = Vec::new();
+        IndexHeader {
+            magic: crate::constants::HEADER_MAGIC,
+            version: 1,
+            num_entries: 1,
+            header_size: u32::MAX,
+        }
+        .append(&mut claimed);
+        assert!(matches!(
+            Header::<IndexTag>::parse(&mut claimed.as_slice()),
+            Err(RPMError::LimitExceeded {
+                limit: "header store size",
+                ..
+            })
+        ));
+        assert!(matches!(
+            Header::<IndexTag>::parse_blob(&claimed[8..]),
+            Err(RPMError::LimitExceeded { .. })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,453 @@
+//! Checking packages against the packaging guidelines of a distribution.
+//!
+//! A [`Profile`] is a set of [`Rule`]s with a [`Severity`] each. The built-in
+//! profiles follow the guidelines of Fedora, RHEL and openSUSE, `minimal` only
+//! checks what every package should have. Custom rules can be added to any
+//! profile.
+//!
+//! ```rust
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let pkg = rpm::RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo").build()?;
+//! let profile = rpm::lint::Profile::named("minimal").expect("built-in profile");
+//! assert!(pkg.lint(&profile).is_empty());
+//!
+//! let findings = pkg.lint(&rpm::lint::Profile::suse());
+//! assert!(findings.iter().any(|finding| finding.rule == "suse-group"));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::headers::*;
+use super::license::{check_license, LicensePolicy};
+use super::text::{check_summary, DESCRIPTION_WIDTH};
+use super::RPMPackage;
+
+/// How much a [`LintFinding`] matters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A rule a package violates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintFinding {
+    /// [`Rule::id`] of the rule.
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.rule, self.message)
+    }
+}
+
+/// A check of the header of a package.
+pub trait Rule: Send + Sync {
+    /// Short kebab-case name, like `license-file`.
+    fn id(&self) -> &'static str;
+
+    /// Describe every violation of the rule, nothing if the header passes.
+    fn check(&self, header: &Header<IndexTag>) -> Vec<String>;
+}
+
+/// The summary is accepted by [`check_summary`] and does not end with a period.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Summary;
+
+impl Rule for Summary {
+    fn id(&self) -> &'static str {
+        "summary"
+    }
+
+    fn check(&self, header: &Header<IndexTag>) -> Vec<String> {
+        match header.get_summary() {
+            Ok(summary) => match check_summary(summary) {
+                Err(RPMError::InvalidTagValue { reason, .. }) => {
+                    vec![format!("summary {}", reason)]
+                }
+                _ if summary.ends_with('.') => vec!["summary ends with a period".to_string()],
+                _ => Vec::new(),
+            },
+            Err(_) => vec!["no summary".to_string()],
+        }
+    }
+}
+
+/// There is a description and none of its lines is wider than [`DESCRIPTION_WIDTH`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Description;
+
+impl Rule for Description {
+    fn id(&self) -> &'static str {
+        "description"
+    }
+
+    fn check(&self, header: &Header<IndexTag>) -> Vec<String> {
+        let description = header.get_description().unwrap_or_default();
+        if description.trim().is_empty() {
+            return vec!["no description".to_string()];
+        }
+        description
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.chars().count() > DESCRIPTION_WIDTH)
+            .map(|(number, _)| {
+                format!(
+                    "description line {} is longer than {} characters",
+                    number + 1,
+                    DESCRIPTION_WIDTH
+                )
+            })
+            .collect()
+    }
+}
+
+/// The license tag is not empty.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LicenseTag;
+
+impl Rule for LicenseTag {
+    fn id(&self) -> &'static str {
+        "license-tag"
+    }
+
+    fn check(&self, header: &Header<IndexTag>) -> Vec<String> {
+        match header.get_license() {
+            Ok(license) if !license.trim().is_empty() => Vec::new(),
+            _ => vec!["no license".to_string()],
+        }
+    }
+}
+
+/// The license tag is an SPDX expression [`check_license`] accepts with `policy`.
+#[derive(Debug, Clone, Default)]
+pub struct SpdxLicense {
+    pub policy: LicensePolicy,
+}
+
+impl Rule for SpdxLicense {
+    fn id(&self) -> &'static str {
+        "spdx-license"
+    }
+
+    fn check(&self, header: &Header<IndexTag>) -> Vec<String> {
+        match header.get_license() {
+            Ok(license) => check_license(license, &self.policy)
+                .iter()
+                .map(|finding| format!("license {:?}: {}", license, finding))
+                .collect(),
+            Err(_) => vec!["no license".to_string()],
+        }
+    }
+}
+
+/// A package with files ships its license text as a `%license` file, see
+/// [`is_license`](super::RPMFileOptionsBuilder::is_license).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LicenseFile;
+
+impl Rule for LicenseFile {
+    fn id(&self) -> &'static str {
+        "license-file"
+    }
+
+    fn check(&self, header: &Header<IndexTag>) -> Vec<String> {
+        let flags = header
+            .get_entry_i32_array_data(IndexTag::RPMTAG_FILEFLAGS)
+            .unwrap_or_default();
+        if flags.is_empty() || flags.iter().any(|flags| flags & RPMFILE_LICENSE != 0) {
+            Vec::new()
+        } else {
+            vec!["no file is marked as %license".to_string()]
+        }
+    }
+}
+
+/// No files under `/usr/local`, which belongs to the local administrator.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoUsrLocal;
+
+impl Rule for NoUsrLocal {
+    fn id(&self) -> &'static str {
+        "no-usr-local"
+    }
+
+    fn check(&self, header: &Header<IndexTag>) -> Vec<String> {
+        header
+            .get_file_paths()
+            .unwrap_or_default()
+            .iter()
+            .filter(|path| path.starts_with("/usr/local"))
+            .map(|path| format!("{} is under /usr/local", path.display()))
+            .collect()
+    }
+}
+
+/// There is at least one changelog entry.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Changelog;
+
+impl Rule for Changelog {
+    fn id(&self) -> &'static str {
+        "changelog"
+    }
+
+    fn check(&self, header: &Header<IndexTag>) -> Vec<String> {
+        match header.get_entry_i32_array_data(IndexTag::RPMTAG_CHANGELOGTIME) {
+            Ok(times) if !times.is_empty() => Vec::new(),
+            _ => vec!["no changelog entries".to_string()],
+        }
+    }
+}
+
+/// Top level groups of openSUSE, `Group` has to start with one of them.
+pub const SUSE_GROUPS: &[&str] = &[
+    "Amusements",
+    "Development",
+    "Documentation",
+    "Hardware",
+    "Metapackages",
+    "Productivity",
+    "System",
+];
+
+/// The group is one of the openSUSE groups, like `System/Libraries`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SuseGroup;
+
+impl Rule for SuseGroup {
+    fn id(&self) -> &'static str {
+        "suse-group"
+    }
+
+    fn check(&self, header: &Header<IndexTag>) -> Vec<String> {
+        let group = header
+            .get_entry_i18n_string_data(IndexTag::RPMTAG_GROUP)
+            .unwrap_or_default();
+        let top_level = group.split('/').next().unwrap_or_default();
+        if SUSE_GROUPS.contains(&top_level) && !group.ends_with('/') {
+            Vec::new()
+        } else {
+            vec![format!("group {:?} is not an openSUSE group", group)]
+        }
+    }
+}
+
+/// A named set of rules, see the [module documentation](self).
+#[derive(Clone)]
+pub struct Profile {
+    name: String,
+    rules: Vec<(Arc<dyn Rule>, Severity)>,
+}
+
+impl fmt::Debug for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Profile")
+            .field("name", &self.name)
+            .field(
+                "rules",
+                &self
+                    .rules
+                    .iter()
+                    .map(|(rule, severity)| (rule.id(), *severity))
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Profile {
+    /// An empty profile.
+    pub fn new<T: Into<String>>(name: T) -> Self {
+        Self {
+            name: name.into(),
+            rules: Vec::new(),
+        }
+    }
+
+    /// Check `rule`, reporting its findings with `severity`.
+    pub fn rule<R: Rule + 'static>(mut self, rule: R, severity: Severity) -> Self {
+        self.rules.push((Arc::new(rule), severity));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Ids of the rules in the order they are checked.
+    pub fn rules(&self) -> Vec<&'static str> {
+        self.rules.iter().map(|(rule, _)| rule.id()).collect()
+    }
+
+    /// The built-in profile `fedora`, `rhel`, `suse` or `minimal`.
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "fedora" => Some(Self::fedora()),
+            "rhel" => Some(Self::rhel()),
+            "suse" => Some(Self::suse()),
+            "minimal" => Some(Self::minimal()),
+            _ => None,
+        }
+    }
+
+    /// A summary and a license tag.
+    pub fn minimal() -> Self {
+        Self::new("minimal")
+            .rule(Summary, Severity::Error)
+            .rule(LicenseTag, Severity::Error)
+    }
+
+    /// SPDX licenses, `%license` files and a changelog.
+    pub fn fedora() -> Self {
+        Self::new("fedora")
+            .rule(Summary, Severity::Error)
+            .rule(Description, Severity::Warning)
+            .rule(SpdxLicense::default(), Severity::Error)
+            .rule(LicenseFile, Severity::Error)
+            .rule(NoUsrLocal, Severity::Error)
+            .rule(Changelog, Severity::Warning)
+    }
+
+    /// Like Fedora, but licenses may still use the old Fedora short names and
+    /// a changelog is required.
+    pub fn rhel() -> Self {
+        Self::new("rhel")
+            .rule(Summary, Severity::Error)
+            .rule(Description, Severity::Warning)
+            .rule(LicenseTag, Severity::Error)
+            .rule(LicenseFile, Severity::Error)
+            .rule(NoUsrLocal, Severity::Error)
+            .rule(Changelog, Severity::Error)
+    }
+
+    /// SPDX licenses, `%license` files and openSUSE groups. The changelog lives
+    /// in a separate `.changes` file.
+    pub fn suse() -> Self {
+        Self::new("suse")
+            .rule(Summary, Severity::Error)
+            .rule(Description, Severity::Warning)
+            .rule(SpdxLicense::default(), Severity::Error)
+            .rule(LicenseFile, Severity::Error)
+            .rule(NoUsrLocal, Severity::Error)
+            .rule(SuseGroup, Severity::Error)
+    }
+
+    /// Run every rule against `header`.
+    pub fn check(&self, header: &Header<IndexTag>) -> Vec<LintFinding> {
+        self.rules
+            .iter()
+            .flat_map(|(rule, severity)| {
+                rule.check(header)
+                    .into_iter()
+                    .map(move |message| LintFinding {
+                        rule: rule.id(),
+                        severity: *severity,
+                        message,
+                    })
+            })
+            .collect()
+    }
+}
+
+impl RPMPackage {
+    /// Check the package against `profile`.
+    pub fn lint(&self, profile: &Profile) -> Vec<LintFinding> {
+        profile.check(&self.metadata.header)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{RPMBuilder, RPMFileOptions};
+
+    fn rules(findings: &[LintFinding]) -> Vec<&'static str> {
+        findings.iter().map(|finding| finding.rule).collect()
+    }
+
+    #[test]
+    fn profiles() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = RPMBuilder::new("foo", "1.0.0", "GPL-2.0-only", "noarch", "A foo.")
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/usr/local/etc/awesome.toml"),
+            )?
+            .build()?;
+        assert_eq!(vec!["summary"], rules(&pkg.lint(&Profile::minimal())));
+        assert_eq!(
+            vec!["summary", "license-file", "no-usr-local", "changelog"],
+            rules(&pkg.lint(&Profile::fedora()))
+        );
+        let rhel = pkg.lint(&Profile::rhel());
+        assert_eq!(Severity::Error, rhel.last().expect("changelog").severity);
+        assert_eq!(
+            vec!["summary", "license-file", "no-usr-local", "suse-group"],
+            rules(&pkg.lint(&Profile::suse()))
+        );
+
+        let pkg = RPMBuilder::new("foo", "1.0.0", "GPLv2", "noarch", "A foo")
+            .group("System/Libraries")
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/usr/share/licenses/foo/LICENSE").is_license(),
+            )?
+            .add_changelog_entry("me", "init", crate::Timestamp::new(1_600_000_000))
+            .build()?;
+        assert!(pkg.lint(&Profile::rhel()).is_empty());
+        let suse = pkg.lint(&Profile::suse());
+        assert_eq!(vec!["spdx-license"], rules(&suse));
+        assert!(suse[0].message.contains("GPLv2"), "{}", suse[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn custom_rules() -> Result<(), Box<dyn std::error::Error>> {
+        struct NoTest;
+        impl Rule for NoTest {
+            fn id(&self) -> &'static str {
+                "no-test"
+            }
+            fn check(&self, header: &Header<IndexTag>) -> Vec<String> {
+                match header.get_name() {
+                    Ok(name) if name.starts_with("test") => vec![name.to_string()],
+                    _ => Vec::new(),
+                }
+            }
+        }
+
+        assert!(Profile::named("debian").is_none());
+        let profile = Profile::named("minimal")
+            .expect("minimal")
+            .rule(NoTest, Severity::Warning);
+        assert_eq!(vec!["summary", "license-tag", "no-test"], profile.rules());
+        let pkg = RPMBuilder::new("test-foo", "1.0.0", "MIT", "noarch", "A foo").build()?;
+        assert_eq!(
+            vec![LintFinding {
+                rule: "no-test",
+                severity: Severity::Warning,
+                message: "test-foo".to_string(),
+            }],
+            pkg.lint(&profile)
+        );
+        Ok(())
+    }
+}
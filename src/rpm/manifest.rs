@@ -0,0 +1,312 @@
+//! A line oriented listing of the files of a package, for diffing package contents
+//! with ordinary text tools.
+//!
+//! Every file is one line of `mode user group size sha256 path`, followed by
+//! ` -> target` for symlinks. The mode is octal and includes the file type, the
+//! digest is `-` for anything but regular files. Whitespace, backslashes and
+//! control characters in names are written as `\` and three octal digits, like
+//! `mtree` does. Lines are sorted by path.
+//!
+//! ```rust
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let pkg = rpm::RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "an awesome tool")
+//!     .with_file(
+//!         "./test_assets/awesome.toml",
+//!         rpm::RPMFileOptions::new("/etc/awesome/my config.toml").mode(0o100644),
+//!     )?
+//!     .build()?;
+//! let text = pkg.manifest()?.to_string();
+//! assert!(text.starts_with("100644 root root "));
+//! assert!(text.ends_with(" /etc/awesome/my\\040config.toml\n"));
+//! assert_eq!(pkg.manifest()?, text.parse()?);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use sha2::Digest;
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::{FileDigestAlgorithm, FileMode, RPMPackage};
+
+/// A file of a [`Manifest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Complete mode including the file type bits.
+    pub mode: u16,
+    /// Owners are names, rpm resolves them on install and packages carry no ids.
+    pub user: String,
+    pub group: String,
+    pub size: u64,
+    /// Lowercase hex, only for regular files.
+    pub sha256: Option<String>,
+    pub path: String,
+    pub link_target: Option<String>,
+}
+
+/// The files of a package, see the [module documentation](self) for the text form.
+///
+/// [`Display`](fmt::Display) writes the text form and [`FromStr`] parses it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl RPMPackage {
+    /// List the files of the package.
+    ///
+    /// Digests are taken from the header if it records SHA-256 digests and computed
+    /// from the payload otherwise. `%ghost` files have no digest.
+    pub fn manifest(&self) -> Result<Manifest, RPMError> {
+        let header = &self.metadata.header;
+        let paths = match header.get_file_paths() {
+            Ok(paths) => paths,
+            Err(RPMError::TagNotFound(_)) => return Ok(Manifest::default()),
+            Err(err) => return Err(err),
+        };
+        let modes = header.get_entry_i16_array_data(IndexTag::RPMTAG_FILEMODES)?;
+        let sizes = header
+            .get_entry_i64_array_data(IndexTag::RPMTAG_LONGFILESIZES)
+            .or_else(|_| {
+                header
+                    .get_entry_i32_array_data(IndexTag::RPMTAG_FILESIZES)
+                    .map(|sizes| sizes.into_iter().map(|size| size as u32 as i64).collect())
+            })?;
+        let users = header.get_entry_string_array_data(IndexTag::RPMTAG_FILEUSERNAME)?;
+        let groups = header.get_entry_string_array_data(IndexTag::RPMTAG_FILEGROUPNAME)?;
+        let links = header.get_entry_string_array_data(IndexTag::RPMTAG_FILELINKTOS)?;
+        let flags = header
+            .get_entry_i32_array_data(IndexTag::RPMTAG_FILEFLAGS)
+            .unwrap_or_default();
+        let digests = header
+            .get_entry_string_array_data(IndexTag::RPMTAG_FILEDIGESTS)
+            .unwrap_or_default();
+        let payload_digests = if header.get_file_digest_algorithm().unwrap_or_default()
+            == FileDigestAlgorithm::Sha2_256
+        {
+            None
+        } else {
+            Some(
+                self.payload_entries()?
+                    .into_iter()
+                    .filter(|entry| matches!(entry.mode, FileMode::Regular { .. }))
+                    .map(|entry| {
+                        let digest = hex::encode(sha2::Sha256::digest(&entry.content));
+                        (entry.path, digest)
+                    })
+                    .collect::<HashMap<_, _>>(),
+            )
+        };
+
+        let mut entries = Vec::with_capacity(paths.len());
+        for (index, path) in paths.into_iter().enumerate() {
+            let path = path.to_string_lossy().into_owned();
+            let mode = modes.get(index).map_or(0, |mode| *mode as u16);
+            let ghost = flags
+                .get(index)
+                .map_or(false, |flags| flags & RPMFILE_GHOST != 0);
+            let sha256 = match FileMode::from(mode) {
+                FileMode::Regular { .. } if !ghost => match &payload_digests {
+                    Some(payload_digests) => payload_digests.get(&path).cloned(),
+                    None => digests
+                        .get(index)
+                        .filter(|digest| !digest.is_empty())
+                        .cloned(),
+                },
+                _ => None,
+            };
+            let link_target = match FileMode::from(mode) {
+                FileMode::SymbolicLink { .. } => links.get(index).cloned(),
+                _ => None,
+            };
+            entries.push(ManifestEntry {
+                mode,
+                user: users.get(index).cloned().unwrap_or_default(),
+                group: groups.get(index).cloned().unwrap_or_default(),
+                size: sizes.get(index).map_or(0, |size| *size as u64),
+                sha256,
+                path,
+                link_target,
+            });
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Manifest { entries })
+    }
+}
+
+impl Manifest {
+    /// Parse the text form, empty lines are skipped.
+    pub fn parse(text: &str) -> Result<Self, RPMError> {
+        let mut entries = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = |reason| RPMError::InvalidManifest {
+                line: index + 1,
+                reason,
+            };
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let (fields, link_target) = match fields.len() {
+                6 => (fields, None),
+                8 if fields[6] == "->" => (
+                    fields[..6].to_vec(),
+                    Some(unescape(fields[7]).ok_or_else(|| invalid("invalid escape"))?),
+                ),
+                _ => return Err(invalid("expected mode, user, group, size, digest and path")),
+            };
+            let field = |value: &str| unescape(value).ok_or_else(|| invalid("invalid escape"));
+            entries.push(ManifestEntry {
+                mode: u16::from_str_radix(fields[0], 8).map_err(|_| invalid("invalid mode"))?,
+                user: field(fields[1])?,
+                group: field(fields[2])?,
+                size: fields[3].parse().map_err(|_| invalid("invalid size"))?,
+                sha256: match fields[4] {
+                    "-" => None,
+                    digest if digest.len() == 64 && hex::decode(digest).is_ok() => {
+                        Some(digest.to_lowercase())
+                    }
+                    _ => return Err(invalid("invalid sha256")),
+                },
+                path: field(fields[5])?,
+                link_target,
+            });
+        }
+        Ok(Manifest { entries })
+    }
+}
+
+impl FromStr for Manifest {
+    type Err = RPMError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+    }
+}
+
+impl fmt::Display for ManifestEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:06o} {} {} {} {} {}",
+            self.mode,
+            escape(&self.user),
+            escape(&self.group),
+            self.size,
+            self.sha256.as_deref().unwrap_or("-"),
+            escape(&self.path)
+        )?;
+        if let Some(target) = &self.link_target {
+            write!(f, " -> {}", escape(target))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_whitespace() || c.is_ascii_control() || c == '\\' {
+            escaped.push_str(&format!("\\{:03o}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'\\' {
+            let digits = std::str::from_utf8(tail.get(..3)?).ok()?;
+            bytes.push(u8::from_str_radix(digits, 8).ok()?);
+            rest = &tail[3..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn manifest_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let file =
+            std::fs::File::open("./test_assets/389-ds-base-devel-1.3.8.4-15.el7.x86_64.rpm")?;
+        let pkg = RPMPackage::parse(&mut std::io::BufReader::new(file))?;
+        let manifest = pkg.manifest()?;
+        assert_eq!(
+            pkg.metadata.header.get_file_paths()?.len(),
+            manifest.entries.len()
+        );
+        let text = manifest.to_string();
+        assert_eq!(manifest, text.parse()?);
+        let symlink = manifest
+            .entries
+            .iter()
+            .find(|entry| entry.link_target.is_some())
+            .expect("a symlink");
+        assert!(symlink.sha256.is_none());
+        assert!(text.contains(&format!(
+            "{} -> {}\n",
+            symlink.path,
+            symlink.link_target.as_deref().unwrap_or_default()
+        )));
+        let file = manifest
+            .entries
+            .iter()
+            .find(|entry| entry.sha256.is_some())
+            .expect("a regular file");
+        assert!(text
+            .lines()
+            .any(|line| line.starts_with("100644 root root ") && line.ends_with(&file.path)));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_manifest() {
+        let text = "\n120777 root root 4 - /usr/bin/a\\040b -> ../lib/a\\134b\n";
+        let manifest = Manifest::parse(text).expect("valid manifest");
+        assert_eq!("/usr/bin/a b", manifest.entries[0].path);
+        assert_eq!(
+            Some("../lib/a\\b"),
+            manifest.entries[0].link_target.as_deref()
+        );
+        assert_eq!(text.trim_start(), manifest.to_string());
+
+        for (line, expected) in [
+            ("100644 root root 1 - /a extra", "expected mode"),
+            ("100648 root root 1 - /a", "invalid mode"),
+            ("100644 root root x - /a", "invalid size"),
+            ("100644 root root 1 abc /a", "invalid sha256"),
+            ("100644 root root 1 - /a\\04", "invalid escape"),
+        ] {
+            match Manifest::parse(&format!("\n{}", line)) {
+                Err(RPMError::InvalidManifest { line: 2, reason }) => {
+                    assert!(reason.starts_with(expected), "{}: {}", line, reason)
+                }
+                other => panic!("{}: {:?}", line, other),
+            }
+        }
+    }
+}
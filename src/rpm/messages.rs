@@ -0,0 +1,109 @@
+//! Stable codes of errors and diagnostics, so tools can show them in other languages.
+//!
+//! ```rust
+//! use std::collections::HashMap;
+//! let mut catalog = HashMap::new();
+//! catalog.insert(
+//!     "FEZ-E0048".to_string(),
+//!     "{limit}: {actual} ist größer als {max}".to_string(),
+//! );
+//! let err = rpm::RPMError::LimitExceeded {
+//!     limit: "file count",
+//!     actual: 3,
+//!     max: 2,
+//! };
+//! assert_eq!("FEZ-E0048", err.code());
+//! assert_eq!("file count: 3 ist größer als 2", err.localized(&catalog));
+//! ```
+
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+/// An error or [`Diagnostic`](super::Diagnostic) as code and named arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// `FEZ-E` and a number for errors, `FEZ-W` for diagnostics. Codes are never
+    /// changed or reused, new variants get new ones.
+    pub code: &'static str,
+    pub args: Vec<(&'static str, String)>,
+}
+
+impl Message {
+    pub fn arg(&self, name: &str) -> Option<&str> {
+        self.args
+            .iter()
+            .find(|(arg, _)| *arg == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Replace each `{name}` in `template` with the argument `name`, unknown
+    /// arguments are kept as they are.
+    pub fn format(&self, template: &str) -> String {
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match rest
+                .find('}')
+                .and_then(|end| Some((end, self.arg(&rest[1..end])?)))
+            {
+                Some((end, value)) => {
+                    out.push_str(value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// Translated texts of [`Message`]s, e.g. backed by gettext or fluent.
+pub trait MessageCatalog {
+    /// The text of `message`, `None` falls back to the English one.
+    fn render(&self, message: &Message) -> Option<String>;
+}
+
+/// Templates by code, see [`Message::format`].
+impl<S: BuildHasher> MessageCatalog for HashMap<String, String, S> {
+    fn render(&self, message: &Message) -> Option<String> {
+        self.get(message.code)
+            .map(|template| message.format(template))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Diagnostic, RPMError};
+
+    #[test]
+    fn render_messages() {
+        let err = RPMError::SpecViolation {
+            path: "/etc/x".to_string(),
+            reason: "bad",
+        };
+        let message = err.message();
+        assert_eq!("FEZ-E0034", message.code);
+        assert_eq!(Some("/etc/x"), message.arg("path"));
+        assert_eq!(
+            "/etc/x: bad {other} {",
+            message.format("{path}: {reason} {other} {")
+        );
+
+        let empty = HashMap::<String, String>::new();
+        assert_eq!(err.to_string(), err.localized(&empty));
+        assert_eq!("FEZ-E0044", RPMError::Cancelled.code());
+
+        let diagnostic = Diagnostic::SignaturePadding { len: 3 };
+        assert_eq!("FEZ-W0010", diagnostic.code());
+        let mut catalog = HashMap::new();
+        catalog.insert("FEZ-W0010".to_string(), "{len} Bytes".to_string());
+        assert_eq!("3 Bytes", diagnostic.localized(&catalog));
+    }
+}
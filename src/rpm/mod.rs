@@ -1,15 +1,179 @@
+mod accounts;
+mod attestation;
+mod benchmark;
 mod builder;
+mod checksum;
+mod chunked;
 mod compressor;
+mod container;
+mod cpe;
+mod debuginfo;
+mod dedup;
+mod delta;
+mod depgen;
+mod diagnostics;
+mod dump;
+mod elf;
 mod headers;
+mod incremental;
+mod install;
+mod installonly;
+mod license;
+mod limits;
+mod manifest;
+mod messages;
 mod package;
+mod patch;
+mod payload;
+mod policy;
+mod progress;
+mod resources;
+mod scriptlet;
+mod session;
+mod stats;
+mod subpackage;
+mod systemd;
+mod taglog;
+mod timestamp;
+mod trust;
+#[cfg(feature = "signature-meta")]
+mod unsigned;
+mod users;
+mod verify;
 
 #[cfg(feature = "signature-meta")]
 pub mod signature;
 
+#[cfg(feature = "signature-meta")]
+pub mod crypto;
+
+#[cfg(feature = "signature-pgp")]
+pub mod gpg_pubkey;
+
+pub mod cpio;
+
+pub mod rpmdb;
+
+pub mod repo;
+
+pub mod repodata;
+
+#[cfg(feature = "remote")]
+pub mod remote;
+
+pub mod scan;
+
+#[cfg(feature = "cache")]
+pub mod cache;
+
+pub mod simple;
+
+pub mod templates;
+
+pub mod text;
+
+pub mod lint;
+
+pub mod changelog;
+
+#[cfg(feature = "git")]
+pub mod git;
+
+#[cfg(feature = "conformance")]
+pub mod conformance;
+
+#[cfg(feature = "test-util")]
+pub mod test_util;
+
+pub mod transaction;
+
+#[cfg(feature = "deb")]
+pub mod deb;
+
 pub use headers::*;
 
 pub use compressor::*;
 
+pub use container::{ContainerOptions, CONTAINER_ONLY_PATHS};
+
+pub use cpe::Cpe;
+
 pub use package::*;
 
+pub use attestation::*;
+
+pub use benchmark::{
+    Candidate, CompressionBenchmark, CompressionReport, CompressionTrial, DEFAULT_SAMPLE_SIZE,
+};
+
+pub use patch::PayloadPatch;
+
+#[cfg(feature = "tar")]
+pub use payload::OciLayer;
+pub use payload::{CpioFormat, PayloadEntry};
+
 pub use builder::*;
+
+pub use checksum::{ChecksumWriter, PackageInfo};
+
+pub use chunked::{Chunk, ChunkInfo, ChunkManifest, ChunkedWriter};
+
+pub use incremental::{PreviousBuild, DEFAULT_SEGMENT_SIZE};
+
+pub use install::*;
+
+pub use installonly::{InstallOnly, INSTALL_ONLY_PROVIDES};
+
+pub use dedup::{Chunking, DedupReport};
+
+pub use delta::FezDelta;
+
+pub use debuginfo::{BUILD_ID_DIR, DEBUG_DIR, SOURCE_DIR};
+
+pub use diagnostics::{Diagnostic, Diagnostics};
+
+pub use resources::ResourceReport;
+
+pub use license::{check_license, LicenseFinding, LicensePolicy};
+pub use manifest::{Manifest, ManifestEntry};
+
+pub use taglog::{TagLog, TagRecord};
+
+pub use timestamp::Timestamp;
+
+pub use session::{
+    BuildSession, SessionBuild, SharedDictionary, DEFAULT_DICTIONARY_SIZE, DICTIONARY_RECORD,
+};
+
+pub use trust::TrustStore;
+
+#[cfg(feature = "signature-meta")]
+pub use unsigned::UnsignedPackage;
+
+pub use messages::{Message, MessageCatalog};
+
+pub use dump::DumpOptions;
+
+pub use depgen::{
+    DepGenerator, ElfGenerator, FileKind, GeneratedDependencies, PayloadFile, ShebangGenerator,
+};
+
+pub use policy::ModePolicy;
+
+pub use progress::{CancellationToken, Phase, Progress, ProgressReader, ProgressUpdate};
+
+pub use scriptlet::{
+    escape_macros, shell_quote, FailurePolicy, FileTrigger, ScriptBody, Scriptlet, ScriptletFlags,
+    DEFAULT_FILE_TRIGGER_PRIORITY, DEFAULT_INTERPRETER, LUA_INTERPRETER, RPMSCRIPT_FLAG_CRITICAL,
+    RPMSCRIPT_FLAG_EXPAND, RPMSCRIPT_FLAG_QFORMAT,
+};
+
+pub use stats::{DirectoryStats, PackageStats};
+
+pub use subpackage::SubPackage;
+
+pub use systemd::UnitOptions;
+
+pub use users::{Uid, UserCreation};
+
+pub use verify::{FileVerification, FileVerifyOptions};
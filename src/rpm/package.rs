@@ -1,7 +1,16 @@
 #[cfg(feature = "async-tokio")]
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 
+use super::checksum::{ChecksumWriter, PackageInfo};
+use super::cpio;
+use super::dedup::{Chunking, DedupReport};
+use super::diagnostics::{Diagnostic, Diagnostics};
 use super::headers::*;
+use super::patch::PayloadPatch;
+use super::payload::{self, CpioFormat, PayloadEntry};
+#[cfg(feature = "signature-meta")]
+use super::progress::{self, CancellationToken, Phase, Progress, ProgressReader, ProgressUpdate};
+use super::resources::ResourceReport;
 
 use crate::constants::*;
 
@@ -14,6 +23,7 @@ use crate::sequential_cursor::SeqCursor;
 #[cfg(feature = "signature-meta")]
 use crate::signature;
 
+use std::convert::TryFrom;
 use std::io::Read;
 #[cfg(feature = "signature-meta")]
 use std::io::{Seek, SeekFrom};
@@ -30,30 +40,138 @@ pub struct RPMPackage {
     pub metadata: RPMPackageMetadata,
     /// The compressed or uncompressed files.
     pub content: Vec<u8>,
+    /// Bytes after the payload, see [`trailing_data`](Self::trailing_data).
+    trailing: Vec<u8>,
 }
 
 impl RPMPackage {
+    /// A package of `metadata` and its payload without trailing data.
+    pub fn new(metadata: RPMPackageMetadata, content: Vec<u8>) -> Self {
+        RPMPackage {
+            metadata,
+            content,
+            trailing: Vec::new(),
+        }
+    }
+
+    /// Split what follows the header into the payload and trailing data, using
+    /// the header and payload size of the signature header.
+    pub(crate) fn from_parts(metadata: RPMPackageMetadata, mut content: Vec<u8>) -> Self {
+        let trailing = metadata
+            .payload_size()
+            .filter(|size| *size < content.len())
+            .map(|size| content.split_off(size))
+            .unwrap_or_default();
+        RPMPackage {
+            metadata,
+            content,
+            trailing,
+        }
+    }
+
+    /// Bytes some signing and appliance tools append after the payload. They are
+    /// not covered by digests or signatures.
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing
+    }
+
+    /// Remove the trailing data, which [`write`](Self::write) preserves otherwise.
+    pub fn strip_trailing_data(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.trailing)
+    }
+
+    /// Append `data` after the payload when writing.
+    pub fn set_trailing_data(&mut self, data: Vec<u8>) {
+        self.trailing = data;
+    }
+
+    fn report_trailing_data(&self, diagnostics: &mut Diagnostics) {
+        if !self.trailing.is_empty() {
+            diagnostics.push(Diagnostic::TrailingData {
+                len: self.trailing.len(),
+            });
+        }
+    }
+
     #[cfg(feature = "async-tokio")]
     pub async fn parse_async<I: AsyncRead + Unpin>(input: &mut I) -> Result<Self, RPMError> {
         let metadata = RPMPackageMetadata::parse_async(input).await?;
         let mut content = Vec::new();
         input.read_to_end(&mut content).await?;
-        Ok(RPMPackage { metadata, content })
+        Ok(RPMPackage::from_parts(metadata, content))
     }
 
     pub fn parse<T: std::io::BufRead>(input: &mut T) -> Result<Self, RPMError> {
         let metadata = RPMPackageMetadata::parse(input)?;
         let mut content = Vec::new();
         input.read_to_end(&mut content)?;
-        Ok(RPMPackage { metadata, content })
+        Ok(RPMPackage::from_parts(metadata, content))
+    }
+
+    /// Parse like [`parse`](Self::parse), collecting non-fatal issues in `diagnostics`.
+    ///
+    /// With [`ParseOptions::permissive`], packages written by old rpm versions
+    /// and vendor tools parse as well, their [`Quirk`]s are reported instead of failing.
+    /// [`trailing_data`](Self::trailing_data) is reported as well.
+    pub fn parse_with_options<T: std::io::BufRead>(
+        input: &mut T,
+        options: &ParseOptions,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Self, RPMError> {
+        let mut log = QuirkLog::new(options);
+        let metadata = RPMPackageMetadata::parse_with(input, &mut log)?;
+        let mut content = Vec::new();
+        input.read_to_end(&mut content)?;
+        diagnostics.append(&mut log.diagnostics);
+        let pkg = RPMPackage::from_parts(metadata, content);
+        pkg.report_trailing_data(diagnostics);
+        diagnostics.record_resources(ResourceReport {
+            header_store: pkg.metadata.header_store_size(),
+            payload: pkg.content.capacity() as u64,
+            ..ResourceReport::default()
+        });
+        Ok(pkg)
+    }
+
+    #[cfg(feature = "async-tokio")]
+    pub async fn parse_async_with_options<I: AsyncRead + Unpin>(
+        input: &mut I,
+        options: &ParseOptions,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Self, RPMError> {
+        let mut log = QuirkLog::new(options);
+        let metadata = RPMPackageMetadata::parse_async_with(input, &mut log).await?;
+        let mut content = Vec::new();
+        input.read_to_end(&mut content).await?;
+        diagnostics.append(&mut log.diagnostics);
+        let pkg = RPMPackage::from_parts(metadata, content);
+        pkg.report_trailing_data(diagnostics);
+        diagnostics.record_resources(ResourceReport {
+            header_store: pkg.metadata.header_store_size(),
+            payload: pkg.content.capacity() as u64,
+            ..ResourceReport::default()
+        });
+        Ok(pkg)
     }
 
     pub fn write<W: std::io::Write>(&self, out: &mut W) -> Result<(), RPMError> {
         self.metadata.write(out)?;
         out.write_all(&self.content)?;
+        out.write_all(&self.trailing)?;
         Ok(())
     }
 
+    /// Write the package and return its size, sha256 and sha1 digest,
+    /// without reading it back. See [`ChecksumWriter`] to pick the digests.
+    pub fn write_checksummed<W: std::io::Write>(
+        &self,
+        out: &mut W,
+    ) -> Result<PackageInfo, RPMError> {
+        let mut out = ChecksumWriter::new(out);
+        self.write(&mut out)?;
+        Ok(out.finish().1)
+    }
+
     #[cfg(feature = "async-tokio")]
     pub async fn write_async<W: tokio::io::AsyncWrite + Unpin>(
         &self,
@@ -61,20 +179,170 @@ impl RPMPackage {
     ) -> Result<(), RPMError> {
         self.metadata.write_async(out).await?;
         out.write_all(&self.content).await?;
+        out.write_all(&self.trailing).await?;
         Ok(())
     }
 
+    /// Decompress the payload and read all contained cpio entries.
+    pub fn payload_entries(&self) -> Result<Vec<PayloadEntry>, RPMError> {
+        let compressor = self.metadata.header.get_payload_compressor().ok();
+        self.read_payload_entries(payload::decompress(compressor, &self.content)?)
+    }
+
+    /// Like [`payload_entries`](Self::payload_entries), recording the content the
+    /// entries hold in [`Diagnostics::resources`].
+    pub fn payload_entries_with_diagnostics(
+        &self,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Vec<PayloadEntry>, RPMError> {
+        let entries = self.payload_entries()?;
+        diagnostics.record_resources(ResourceReport {
+            decompressed_payload: entries
+                .iter()
+                .map(|entry| entry.content.capacity() as u64)
+                .sum(),
+            ..ResourceReport::default()
+        });
+        Ok(entries)
+    }
+
+    /// The entries of the decompressed payload read from `archive`.
+    pub(crate) fn read_payload_entries<R: Read>(
+        &self,
+        mut archive: R,
+    ) -> Result<Vec<PayloadEntry>, RPMError> {
+        let mut magic = [0u8; 6];
+        archive.read_exact(&mut magic)?;
+        let archive = Read::chain(std::io::Cursor::new(magic), archive);
+        let mut entries = if &magic == cpio::STRIPPED_MAGIC {
+            payload::read_stripped_entries(archive, &self.metadata.header)?
+        } else {
+            payload::read_entries(archive)?
+        };
+        payload::fill_hardlinks(&mut entries);
+        Ok(entries)
+    }
+
+    /// Compare the uncompressed payload with the one of an `older` version of the package,
+    /// e.g. to decide whether a delta is worth generating.
+    pub fn dedup_against(
+        &self,
+        older: &RPMPackage,
+        chunking: Chunking,
+    ) -> Result<DedupReport, RPMError> {
+        let old = older.uncompressed_payload()?;
+        let new = self.uncompressed_payload()?;
+        Ok(DedupReport::between(&old, &new, chunking))
+    }
+
+    pub(crate) fn uncompressed_payload(&self) -> Result<Vec<u8>, RPMError> {
+        let compressor = self.metadata.header.get_payload_compressor().ok();
+        let mut payload = Vec::new();
+        payload::decompress(compressor, &self.content)?.read_to_end(&mut payload)?;
+        Ok(payload)
+    }
+
+    /// Find out which cpio flavour the payload uses.
+    pub fn payload_cpio_format(&self) -> Result<CpioFormat, RPMError> {
+        let compressor = self.metadata.header.get_payload_compressor().ok();
+        let mut magic = [0u8; 6];
+        payload::decompress(compressor, &self.content)?.read_exact(&mut magic)?;
+        match &magic {
+            cpio::NEWC_MAGIC => Ok(CpioFormat::Newc),
+            cpio::STRIPPED_MAGIC => Ok(CpioFormat::Stripped),
+            _ => Err(RPMError::Malformed {
+                what: "cpio archive",
+                reason: format!("unknown magic {:?}", String::from_utf8_lossy(&magic)),
+            }),
+        }
+    }
+
+    /// Pair the path of every file with the digest recorded in the header.
+    ///
+    /// Directories, symlinks and other entries without content are skipped.
+    pub fn file_digests(&self) -> Result<Vec<(std::path::PathBuf, FileDigest)>, RPMError> {
+        Ok(self
+            .metadata
+            .header
+            .get_file_entries()?
+            .into_iter()
+            .filter_map(|entry| {
+                let path = entry.path;
+                entry.digest.map(|digest| (path, digest))
+            })
+            .collect())
+    }
+
+    /// Write a manifest of all files, which can be checked with `sha256sum -c`.
+    ///
+    /// Header digests are used if the package records sha256 digests,
+    /// otherwise they are computed from the payload.
+    pub fn write_sha256sums<W: std::io::Write>(&self, out: &mut W) -> Result<(), RPMError> {
+        let digests = self.file_digests()?;
+        if digests
+            .iter()
+            .all(|(_, digest)| digest.algorithm() == FileDigestAlgorithm::Sha2_256)
+        {
+            for (path, digest) in digests {
+                writeln!(out, "{}  {}", digest.to_hex(), path.display())?;
+            }
+            return Ok(());
+        }
+
+        use sha2::Digest;
+        for entry in self.payload_entries()? {
+            if entry.is_dir() || entry.is_symlink() {
+                continue;
+            }
+            let mut hasher = sha2::Sha256::default();
+            hasher.update(&entry.content);
+            writeln!(out, "{}  {}", hex::encode(hasher.finalize()), entry.path)?;
+        }
+        Ok(())
+    }
+
+    /// Remove or replace files as described by `patch`, e.g. to strip bundled test data.
+    ///
+    /// File list, sizes and digests are updated and the payload is rewritten
+    /// with the same cpio format and compressor. Signatures do not survive
+    /// this, the result only carries digests and has to be signed again.
+    pub fn patch(self, patch: &PayloadPatch) -> Result<Self, RPMError> {
+        super::patch::apply(self, patch)
+    }
+
+    /// Write the payload as an uncompressed tar archive.
+    ///
+    /// File ownership is taken from the header, implicit parent directories
+    /// are added such that the archive can be unpacked as is.
+    #[cfg(feature = "tar")]
+    pub fn payload_to_tar<W: std::io::Write>(&self, out: &mut W) -> Result<(), RPMError> {
+        let entries = self.payload_entries()?;
+        let ownership = payload::file_ownership(&self.metadata.header);
+        let mut tar = tar::Builder::new(out);
+        payload::append_to_tar(&mut tar, &entries, |path| ownership.get(path).cloned())?;
+        tar.finish()?;
+        Ok(())
+    }
+
+    /// Wrap the payload into a gzip compressed OCI image layer.
+    #[cfg(feature = "tar")]
+    pub fn payload_to_oci_layer(&self) -> Result<payload::OciLayer, RPMError> {
+        let mut tar = Vec::new();
+        self.payload_to_tar(&mut tar)?;
+        payload::OciLayer::from_tar(&tar)
+    }
+
     // TODO allow passing an external signer/verifier
 
     /// sign all headers (except for the lead) using an external key and store it as the initial header
     #[cfg(feature = "signature-meta")]
     pub fn sign<S>(&mut self, signer: S) -> Result<(), RPMError>
     where
-        S: signature::Signing<signature::algorithm::RSA, Signature = Vec<u8>>,
+        S: signature::Signing<signature::algorithm::RSA>,
     {
         // create a temporary byte repr of the header
         // and re-create all hashes
-        let mut header_bytes = Vec::<u8>::with_capacity(1024);
+        let mut header_bytes = Vec::<u8>::with_capacity(self.metadata.header.serialized_len());
         self.metadata.header.write(&mut header_bytes)?;
 
         let mut header_and_content_cursor =
@@ -87,7 +355,11 @@ impl RPMPackage {
                 // avoid loading it into memory all at once
                 // since the content could be multiple 100s of MBs
                 let mut buf = [0u8; 256];
-                while let Ok(n) = header_and_content_cursor.read(&mut buf[..]) {
+                loop {
+                    let n = header_and_content_cursor.read(&mut buf[..])?;
+                    if n == 0 {
+                        break;
+                    }
                     hasher.update(&buf[0..n]);
                 }
             }
@@ -97,13 +369,7 @@ impl RPMPackage {
 
         header_and_content_cursor.seek(SeekFrom::Start(0))?;
 
-        let digest_sha1 = {
-            use sha1::Digest;
-            let mut hasher = sha1::Sha1::default();
-            hasher.update(&header_bytes);
-            let digest = hasher.finalize();
-            hex::encode(digest)
-        };
+        let digest_header = HeaderDigest::of(&header_bytes);
 
         let rsa_signature_spanning_header_only = signer.sign(header_bytes.as_slice())?;
 
@@ -115,9 +381,9 @@ impl RPMPackage {
         self.metadata.signature = Header::<IndexSignatureTag>::new_signature_header(
             header_and_content_cursor.len() as i32,
             &digest_md5,
-            digest_sha1,
-            rsa_signature_spanning_header_only.as_slice(),
-            rsa_signature_spanning_header_and_archive.as_slice(),
+            &digest_header,
+            rsa_signature_spanning_header_only.as_ref(),
+            rsa_signature_spanning_header_and_archive.as_ref(),
         );
 
         Ok(())
@@ -129,11 +395,38 @@ impl RPMPackage {
     #[cfg(feature = "signature-meta")]
     pub fn verify_signature<V>(&self, verifier: V) -> Result<(), RPMError>
     where
-        V: signature::Verifying<signature::algorithm::RSA, Signature = Vec<u8>>,
+        V: signature::Verifying<signature::algorithm::RSA>,
+    {
+        self.verify_signature_with(verifier, &VerifyOptions::new())
+    }
+
+    /// Like [`verify_signature`](Self::verify_signature), reporting progress and
+    /// checking for cancellation while hashing as configured in `options`.
+    #[cfg(feature = "signature-meta")]
+    pub fn verify_signature_with<V>(
+        &self,
+        verifier: V,
+        options: &VerifyOptions<'_>,
+    ) -> Result<(), RPMError>
+    where
+        V: signature::Verifying<signature::algorithm::RSA>,
     {
         // TODO retval should be SIGNATURE_VERIFIED or MISMATCH, not just an error
+        let default_policy = signature::VerifyPolicy::default();
+        let policy = options.policy.unwrap_or(&default_policy);
+        policy.check_digests(&self.metadata)?;
+        if let Some((store, origin)) = options.trust {
+            let trusted = verifier
+                .fingerprint()
+                .map_or(false, |fingerprint| store.is_trusted(origin, &fingerprint));
+            if !trusted {
+                return Err(RPMError::UntrustedKey {
+                    origin: origin.to_string(),
+                });
+            }
+        }
 
-        let mut header_bytes = Vec::<u8>::with_capacity(1024);
+        let mut header_bytes = Vec::<u8>::with_capacity(self.metadata.header.serialized_len());
         self.metadata.header.write(&mut header_bytes)?;
 
         let signature_header_only = self
@@ -153,15 +446,201 @@ impl RPMPackage {
             signature_header_and_content,
         );
 
-        verifier.verify(header_bytes.as_slice(), signature_header_only)?;
+        verifier.verify_with_policy(header_bytes.as_slice(), signature_header_only, policy)?;
 
-        let header_and_content_cursor =
-            SeqCursor::new(&[header_bytes.as_slice(), self.content.as_slice()]);
+        let ignore = |_: &ProgressUpdate<'_>| {};
+        let mut header_and_content_cursor = ProgressReader::new(
+            SeqCursor::new(&[header_bytes.as_slice(), self.content.as_slice()]),
+            Phase::Verify,
+            Some((header_bytes.len() + self.content.len()) as u64),
+            options.progress.unwrap_or(&ignore),
+        );
+        if let Some(token) = &options.cancellation {
+            header_and_content_cursor = header_and_content_cursor.cancellation(token.clone());
+        }
 
-        verifier.verify(header_and_content_cursor, signature_header_and_content)?;
+        let verified = verifier.verify_with_policy(
+            header_and_content_cursor,
+            signature_header_and_content,
+            policy,
+        );
+        // the verifier only sees the read failing
+        progress::check(options.cancellation.as_ref())?;
+        verified
+    }
+
+    /// Check the header and payload against the digests in the signature header, e.g.
+    /// after rebuilding a package from a [`FezDelta`](crate::FezDelta).
+    ///
+    /// Signatures are not checked, the package needs at least one digest.
+    pub fn verify_digests(&self) -> Result<(), RPMError> {
+        let mut header_bytes = Vec::<u8>::with_capacity(self.metadata.header.serialized_len());
+        self.metadata.header.write(&mut header_bytes)?;
+        let (header_digest, md5) = crate::RPMBuilder::derive_hashes(&header_bytes, &self.content)?;
+
+        let signature = &self.metadata.signature;
+        let mut checked = 0;
+        for (tag, actual) in [
+            (IndexSignatureTag::RPMSIGTAG_SHA256, &header_digest.sha256),
+            (IndexSignatureTag::RPMSIGTAG_SHA1, &header_digest.sha1),
+        ] {
+            if let Ok(expected) = signature.get_entry_string_data(tag) {
+                if expected != actual {
+                    return Err(RPMError::DigestMismatch(format!("{} of the header", tag)));
+                }
+                checked += 1;
+            }
+        }
+        if let Ok(expected) = signature.get_entry_binary_data(IndexSignatureTag::RPMSIGTAG_MD5) {
+            if expected != md5.as_slice() {
+                return Err(RPMError::DigestMismatch(format!(
+                    "{} of header and payload",
+                    IndexSignatureTag::RPMSIGTAG_MD5
+                )));
+            }
+            checked += 1;
+        }
+        if self.verify_payload_digest()? {
+            checked += 1;
+        }
+        if checked == 0 {
+            return Err(RPMError::DigestMismatch(
+                "the package has no digests".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check the payload against `RPMTAG_PAYLOADDIGEST`, `false` if the header has none.
+    pub(crate) fn verify_payload_digest(&self) -> Result<bool, RPMError> {
+        verify_payload_digest(&self.metadata.header, &self.content)
+    }
 
+    /// Sign the header with an X.509 certificate and store the CMS signature
+    /// in [`RPMSIGTAG_CMS`](IndexSignatureTag::RPMSIGTAG_CMS), next to the
+    /// signatures already present.
+    ///
+    /// The signature covers the payload through the SHA-256 payload digest of the
+    /// header, packages without one are refused.
+    ///
+    /// rpm ignores the tag, the package still has to be signed with OpenPGP to be
+    /// installed with signature checks. [`sign`](Self::sign) replaces the whole
+    /// signature header, so call it first.
+    #[cfg(feature = "signature-cms")]
+    pub fn sign_cms<S>(&mut self, signer: S) -> Result<(), RPMError>
+    where
+        S: signature::Signing<signature::algorithm::CMS>,
+    {
+        self.require_payload_digest()?;
+        let mut header_bytes = Vec::<u8>::with_capacity(self.metadata.header.serialized_len());
+        self.metadata.header.write(&mut header_bytes)?;
+        let signature = signer.sign(header_bytes.as_slice())?;
+        self.metadata.signature.set_entry(IndexEntry::new(
+            IndexSignatureTag::RPMSIGTAG_CMS,
+            0,
+            IndexData::Bin(signature.as_ref().to_vec()),
+        ));
         Ok(())
     }
+
+    /// Verify the CMS signature stored by [`sign_cms`](Self::sign_cms), and the
+    /// payload against the payload digest of the signed header.
+    #[cfg(feature = "signature-cms")]
+    pub fn verify_cms<V>(&self, verifier: V) -> Result<(), RPMError>
+    where
+        V: signature::Verifying<signature::algorithm::CMS>,
+    {
+        let mut header_bytes = Vec::<u8>::with_capacity(self.metadata.header.serialized_len());
+        self.metadata.header.write(&mut header_bytes)?;
+        let signature = self
+            .metadata
+            .signature
+            .get_entry_binary_data(IndexSignatureTag::RPMSIGTAG_CMS)?;
+        verifier.verify(header_bytes.as_slice(), signature)?;
+        self.require_payload_digest()
+    }
+
+    #[cfg(feature = "signature-cms")]
+    fn require_payload_digest(&self) -> Result<(), RPMError> {
+        match self.verify_payload_digest()? {
+            true => Ok(()),
+            false => Err(RPMError::DigestMismatch(format!(
+                "the header has no SHA-256 {}",
+                IndexTag::RPMTAG_PAYLOADDIGEST
+            ))),
+        }
+    }
+}
+
+/// Check `content` against the `RPMTAG_PAYLOADDIGEST` of `header`, `false` if it has none.
+pub(crate) fn verify_payload_digest(
+    header: &Header<IndexTag>,
+    content: &[u8],
+) -> Result<bool, RPMError> {
+    // only sha256 is written by rpm and this crate
+    let payload_digest = header
+        .get_entry_string_array_data(IndexTag::RPMTAG_PAYLOADDIGEST)
+        .ok()
+        .and_then(|digests| digests.first());
+    let algorithm = header
+        .get_entry_i32_data(IndexTag::RPMTAG_PAYLOADDIGESTALGO)
+        .unwrap_or(8);
+    match (payload_digest, algorithm) {
+        (Some(expected), 8) => {
+            use sha2::Digest;
+            if *expected != hex::encode(sha2::Sha256::digest(content)) {
+                return Err(RPMError::DigestMismatch(format!(
+                    "{} of the payload",
+                    IndexTag::RPMTAG_PAYLOADDIGEST
+                )));
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Controls [`RPMPackage::verify_signature_with`].
+#[cfg(feature = "signature-meta")]
+#[derive(Default)]
+pub struct VerifyOptions<'p> {
+    progress: Option<&'p dyn Progress>,
+    cancellation: Option<CancellationToken>,
+    policy: Option<&'p signature::VerifyPolicy>,
+    trust: Option<(&'p crate::TrustStore, &'p str)>,
+}
+
+#[cfg(feature = "signature-meta")]
+impl<'p> VerifyOptions<'p> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report the bytes hashed as [`Phase::Verify`].
+    pub fn progress(mut self, progress: &'p dyn Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Stop hashing with [`RPMError::Cancelled`] once `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Reject packages and signatures violating `policy`, which accepts everything
+    /// by default, see [`VerifyPolicy`](signature::VerifyPolicy).
+    pub fn policy(mut self, policy: &'p signature::VerifyPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Fail with [`RPMError::UntrustedKey`] unless `store` trusts the key of the
+    /// verifier for `origin`.
+    pub fn trust(mut self, store: &'p crate::TrustStore, origin: &'p str) -> Self {
+        self.trust = Some((store, origin));
+        self
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -174,32 +653,118 @@ pub struct RPMPackageMetadata {
 impl RPMPackageMetadata {
     #[cfg(feature = "async-tokio")]
     pub async fn parse_async<T: AsyncRead + Unpin>(input: &mut T) -> Result<Self, RPMError> {
+        Self::parse_async_with(input, &mut QuirkLog::strict()).await
+    }
+
+    #[cfg(feature = "async-tokio")]
+    async fn parse_async_with<T: AsyncRead + Unpin>(
+        input: &mut T,
+        log: &mut QuirkLog,
+    ) -> Result<Self, RPMError> {
         let mut lead_buffer = [0; LEAD_SIZE];
         input.read_exact(&mut lead_buffer).await?;
-        let lead = Lead::parse(&lead_buffer)?;
-        let signature_header = Header::parse_signature_async(input).await?;
-        let header = Header::parse_async(input).await?;
-        Ok(RPMPackageMetadata {
+        let lead = Lead::parse_with(&lead_buffer, log)?;
+        let signature_header = Header::parse_signature_async(input, log).await?;
+        let header = Header::parse_async_with(input, log).await?;
+        let metadata = RPMPackageMetadata {
             lead,
             signature: signature_header,
             header,
-        })
+        };
+        metadata.check_digests(log);
+        Ok(metadata)
     }
 
     pub(crate) fn parse<T: std::io::BufRead>(input: &mut T) -> Result<Self, RPMError> {
+        Self::parse_with(input, &mut QuirkLog::strict())
+    }
+
+    pub(crate) fn parse_with<T: std::io::BufRead>(
+        input: &mut T,
+        log: &mut QuirkLog,
+    ) -> Result<Self, RPMError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("parse").entered();
         let mut lead_buffer = [0; LEAD_SIZE];
         input.read_exact(&mut lead_buffer)?;
-        let lead = Lead::parse(&lead_buffer)?;
-        let signature_header = Header::parse_signature(input)?;
-        let header = Header::parse(input)?;
-        Ok(RPMPackageMetadata {
+        let lead = Lead::parse_with(&lead_buffer, log)?;
+        let signature_header = Header::parse_signature_with(input, log)?;
+        let header = Header::parse_with(input, log)?;
+        let metadata = RPMPackageMetadata {
             lead,
             signature: signature_header,
             header,
-        })
+        };
+        metadata.check_digests(log);
+        Ok(metadata)
+    }
+
+    /// Deviations of signature and header from the layout rpm writes, see [`Header::audit_layout`].
+    pub fn audit_layout(&self) -> Vec<LayoutIssue> {
+        let mut issues = self.signature.audit_layout();
+        issues.extend(self.header.audit_layout());
+        issues
     }
 
-    pub(crate) fn write<W: std::io::Write>(&self, out: &mut W) -> Result<(), RPMError> {
+    /// Point out digests current rpm versions no longer rely on.
+    fn check_digests(&self, log: &mut QuirkLog) {
+        let has = |tag| self.signature.find_entry_or_err(&tag).is_ok();
+        if !has(IndexSignatureTag::RPMSIGTAG_SHA256) {
+            let weakest = if has(IndexSignatureTag::RPMSIGTAG_SHA1) {
+                (IndexSignatureTag::RPMSIGTAG_SHA1, "SHA1")
+            } else {
+                (IndexSignatureTag::RPMSIGTAG_MD5, "MD5")
+            };
+            if has(weakest.0) {
+                log.report(Diagnostic::DeprecatedDigest {
+                    tag: weakest.0.to_string(),
+                    algorithm: weakest.1,
+                });
+            }
+        }
+        let has_files = self
+            .header
+            .find_entry_or_err(&IndexTag::RPMTAG_FILEDIGESTS)
+            .is_ok();
+        // rpm does not encode it, if it is the default md5
+        if has_files
+            && self.header.get_file_digest_algorithm().unwrap_or_default()
+                == FileDigestAlgorithm::Md5
+        {
+            log.report(Diagnostic::DeprecatedDigest {
+                tag: IndexTag::RPMTAG_FILEDIGESTS.to_string(),
+                algorithm: "MD5",
+            });
+        }
+    }
+
+    /// Serialized size of the signature header or the header, whichever is larger.
+    pub(crate) fn header_store_size(&self) -> u64 {
+        self.signature
+            .serialized_len()
+            .max(self.header.serialized_len()) as u64
+    }
+
+    /// Length of the payload according to the header and payload size of the
+    /// signature header, `None` if it has none.
+    pub(crate) fn payload_size(&self) -> Option<usize> {
+        let size = self
+            .signature
+            .get_entry_i64_data(IndexSignatureTag::RPMSIGTAG_LONGSIGSIZE)
+            .ok()
+            .or_else(|| {
+                self.signature
+                    .get_entry_i32_data(IndexSignatureTag::RPMSIGTAG_SIZE)
+                    .ok()
+                    .map(|size| i64::from(size as u32))
+            })?;
+        // the index entries and store as read, the header has no padding
+        usize::try_from(size)
+            .ok()?
+            .checked_sub(self.header.serialized_len())
+    }
+
+    pub fn write<W: std::io::Write>(&self, out: &mut W) -> Result<(), RPMError> {
         self.lead.write(out)?;
         self.signature.write_signature(out)?;
         self.header.write(out)?;
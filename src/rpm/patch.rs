@@ -0,0 +1,324 @@
+//! Removing and replacing files of existing packages, see [`RPMPackage::patch`].
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::cpio;
+use super::headers::*;
+use super::payload::CpioFormat;
+use super::{Compressor, RPMBuilder, RPMPackage, RPMPackageMetadata};
+
+/// Tags holding one item per file, in the order of the file list.
+const PER_FILE_TAGS: &[IndexTag] = &[
+    IndexTag::RPMTAG_OLDFILENAMES,
+    IndexTag::RPMTAG_FILESIZES,
+    IndexTag::RPMTAG_FILESTATES,
+    IndexTag::RPMTAG_FILEMODES,
+    IndexTag::RPMTAG_FILEUIDS,
+    IndexTag::RPMTAG_FILEGIDS,
+    IndexTag::RPMTAG_FILERDEVS,
+    IndexTag::RPMTAG_FILEMTIMES,
+    IndexTag::RPMTAG_FILEDIGESTS,
+    IndexTag::RPMTAG_FILELINKTOS,
+    IndexTag::RPMTAG_FILEFLAGS,
+    IndexTag::RPMTAG_FILEUSERNAME,
+    IndexTag::RPMTAG_FILEGROUPNAME,
+    IndexTag::RPMTAG_FILEVERIFYFLAGS,
+    IndexTag::RPMTAG_FILEDEVICES,
+    IndexTag::RPMTAG_FILEINODES,
+    IndexTag::RPMTAG_FILELANGS,
+    IndexTag::RPMTAG_DIRINDEXES,
+    IndexTag::RPMTAG_BASENAMES,
+    IndexTag::RPMTAG_FILECOLORS,
+    IndexTag::RPMTAG_FILECLASS,
+    IndexTag::RPMTAG_FILEDEPENDSX,
+    IndexTag::RPMTAG_FILEDEPENDSN,
+    IndexTag::RPMTAG_FILECONTEXTS,
+    IndexTag::RPMTAG_FILEXATTRSX,
+    IndexTag::RPMTAG_LONGFILESIZES,
+    IndexTag::RPMTAG_FILECAPS,
+    IndexTag::RPMTAG_FILESIGNATURES,
+];
+
+type PathPredicate = Box<dyn Fn(&str) -> bool>;
+
+/// Changes to the files of a package.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let pkg = rpm::RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "")
+///     .with_file(
+///         "./test_assets/awesome.toml",
+///         rpm::RPMFileOptions::new("/usr/share/foo/tests/data.toml"),
+///     )?
+///     .build()?;
+/// let patch = rpm::PayloadPatch::new()
+///     .remove_matching(|path| path.starts_with("/usr/share/foo/tests/"));
+/// let pkg = pkg.patch(&patch)?;
+/// assert!(pkg.metadata.header.get_file_paths().is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct PayloadPatch {
+    remove: Vec<String>,
+    remove_matching: Vec<PathPredicate>,
+    replace: BTreeMap<String, Vec<u8>>,
+}
+
+impl PayloadPatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop the file installed to `path`, which has to be part of the package.
+    pub fn remove(mut self, path: impl Into<String>) -> Self {
+        self.remove.push(path.into());
+        self
+    }
+
+    /// Drop all files whose install path matches `predicate`.
+    pub fn remove_matching<F: Fn(&str) -> bool + 'static>(mut self, predicate: F) -> Self {
+        self.remove_matching.push(Box::new(predicate));
+        self
+    }
+
+    /// Replace the content of the regular file installed to `path`, keeping its other attributes.
+    pub fn replace(mut self, path: impl Into<String>, content: Vec<u8>) -> Self {
+        self.replace.insert(path.into(), content);
+        self
+    }
+
+    fn removes(&self, path: &str) -> bool {
+        self.remove.iter().any(|removed| removed == path)
+            || self.remove_matching.iter().any(|predicate| predicate(path))
+    }
+}
+
+pub(crate) fn apply(package: RPMPackage, patch: &PayloadPatch) -> Result<RPMPackage, RPMError> {
+    let format = package.payload_cpio_format()?;
+    let entries = package.payload_entries()?;
+    let RPMPackageMetadata { lead, header, .. } = package.metadata;
+
+    let paths = header
+        .get_file_paths()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    let mut positions = HashMap::with_capacity(paths.len());
+    for (index, path) in paths.iter().enumerate() {
+        positions.entry(path.as_str()).or_insert(index);
+    }
+    let position = |path: &str| positions.get(path).copied();
+    for path in &patch.remove {
+        position(path).ok_or_else(|| RPMError::FileNotFound(path.clone()))?;
+    }
+    let modes = header
+        .get_entry_i16_array_data(IndexTag::RPMTAG_FILEMODES)
+        .unwrap_or_default();
+    let mut replaced = HashMap::new();
+    if !patch.replace.is_empty() {
+        let algorithm = header.get_file_digest_algorithm().unwrap_or_default();
+        for (path, content) in &patch.replace {
+            let index = position(path).ok_or_else(|| RPMError::FileNotFound(path.clone()))?;
+            let mode = modes.get(index).map(|mode| FileMode::from(*mode as u16));
+            if !matches!(mode, Some(FileMode::Regular { .. })) {
+                return Err(RPMError::UnsupportedArchiveEntry {
+                    path: path.clone(),
+                    reason: "only regular files can be replaced",
+                });
+            }
+            replaced.insert(index, (content.as_slice(), digest(algorithm, content)?));
+        }
+    }
+
+    let keep = paths
+        .iter()
+        .map(|path| !patch.removes(path))
+        .collect::<Vec<_>>();
+    let mut new_indices = HashMap::new();
+    for (path, _) in paths.iter().zip(&keep).filter(|(_, keep)| **keep) {
+        new_indices.insert(path.as_str(), new_indices.len());
+    }
+
+    let sizes = header
+        .get_entry_i64_array_data(IndexTag::RPMTAG_LONGFILESIZES)
+        .or_else(|_| {
+            header
+                .get_entry_i32_array_data(IndexTag::RPMTAG_FILESIZES)
+                .map(|sizes| sizes.into_iter().map(i64::from).collect())
+        })
+        .unwrap_or_default();
+    let mut size_delta = 0i64;
+    for (index, size) in sizes.iter().enumerate() {
+        if !keep[index] {
+            size_delta -= size;
+        } else if let Some((content, _)) = replaced.get(&index) {
+            size_delta += content.len() as i64 - size;
+        }
+    }
+
+    let compressor_name = match header.get_payload_compressor() {
+        Ok("") | Err(_) => "none",
+        Ok(name) => name,
+    };
+    // hardlinked files carry their content on the last member that is kept
+    let mut last_links = HashMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        if entry.nlink > 1 && new_indices.contains_key(entry.path.as_str()) {
+            last_links.insert(entry.ino, index);
+        }
+    }
+    let mut archive = cpio::Writer::new(compressor_name.parse::<Compressor>()?);
+    for (entry_index, entry) in entries.iter().enumerate() {
+        let index = match new_indices.get(entry.path.as_str()) {
+            Some(index) => *index,
+            None => continue,
+        };
+        let content = match last_links.get(&entry.ino) {
+            Some(last) if entry.nlink > 1 && *last != entry_index => &[],
+            _ => position(&entry.path)
+                .and_then(|index| replaced.get(&index))
+                .map_or(entry.content.as_slice(), |(content, _)| content),
+        };
+        match format {
+            CpioFormat::Stripped => archive.append_stripped(index as u32, content)?,
+            _ => {
+                let header = cpio::Entry {
+                    ino: entry.ino,
+                    mode: entry.mode.into(),
+                    uid: entry.uid,
+                    gid: entry.gid,
+                    nlink: entry.nlink,
+                    mtime: entry.mtime.seconds(),
+                    ..cpio::Entry::new(format!(".{}", entry.path))
+                };
+                archive.append(&header, content)?;
+            }
+        }
+    }
+    let compressor = archive.finish()?;
+    let compression_level = compressor
+        .get_details()
+        .map(|details| details.compression_level);
+    let content = compressor.finish_compression()?;
+
+    let no_files_left = new_indices.is_empty();
+    let mut records = Vec::with_capacity(header.index_entries.len());
+    for mut entry in header.index_entries {
+        match entry.tag {
+            IndexTag::RPMTAG_HEADERIMMUTABLE => continue,
+            // written again below for the new payload
+            IndexTag::RPMTAG_PAYLOADDIGEST
+            | IndexTag::RPMTAG_PAYLOADDIGESTALT
+            | IndexTag::RPMTAG_PAYLOADDIGESTALGO => continue,
+            // rpm rejects empty arrays
+            IndexTag::RPMTAG_DIRNAMES if no_files_left => continue,
+            tag if PER_FILE_TAGS.contains(&tag) && entry.num_items as usize == paths.len() => {
+                if no_files_left {
+                    continue;
+                }
+                retain(&mut entry.data, &keep);
+            }
+            _ => {}
+        }
+        match (&entry.tag, &mut entry.data) {
+            (IndexTag::RPMTAG_SIZE, IndexData::Int32(size)) => {
+                size[0] = (size[0] as u32 as i64 + size_delta) as u32 as i32;
+            }
+            (IndexTag::RPMTAG_LONGSIZE, IndexData::Int64(size)) => size[0] += size_delta,
+            (IndexTag::RPMTAG_PAYLOADFLAGS, IndexData::StringTag(flags)) => {
+                *flags = compression_level.clone().unwrap_or_default();
+            }
+            (IndexTag::RPMTAG_FILESIZES, IndexData::Int32(sizes)) => {
+                for (index, (content, _)) in &replaced {
+                    sizes[new_indices[paths[*index].as_str()]] = content.len() as i32;
+                }
+            }
+            (IndexTag::RPMTAG_LONGFILESIZES, IndexData::Int64(sizes)) => {
+                for (index, (content, _)) in &replaced {
+                    sizes[new_indices[paths[*index].as_str()]] = content.len() as i64;
+                }
+            }
+            (IndexTag::RPMTAG_FILEDIGESTS, IndexData::StringArray(digests)) => {
+                for (index, (_, digest)) in &replaced {
+                    digests[new_indices[paths[*index].as_str()]] = digest.clone();
+                }
+            }
+            // signatures over the old content
+            (IndexTag::RPMTAG_FILESIGNATURES, IndexData::StringArray(signatures)) => {
+                for index in replaced.keys() {
+                    signatures[new_indices[paths[*index].as_str()]].clear();
+                }
+            }
+            _ => {}
+        }
+        entry.num_items = entry.data.num_items();
+        records.push(entry);
+    }
+    records.push(IndexEntry::new(
+        IndexTag::RPMTAG_PAYLOADDIGEST,
+        0,
+        IndexData::StringArray(vec![digest(FileDigestAlgorithm::Sha2_256, &content)?]),
+    ));
+    records.push(IndexEntry::new(
+        IndexTag::RPMTAG_PAYLOADDIGESTALGO,
+        0,
+        IndexData::Int32(vec![8]),
+    ));
+    let header = Header::from_entries(records, IndexTag::RPMTAG_HEADERIMMUTABLE);
+
+    let mut header_bytes = Vec::with_capacity(header.serialized_len());
+    header.write(&mut header_bytes)?;
+    let (header_digest, header_and_content_digest_md5) =
+        RPMBuilder::derive_hashes(&header_bytes, &content)?;
+    let signature = Header::<IndexSignatureTag>::builder()
+        .add_digest(
+            header_digest.sha1.as_str(),
+            header_and_content_digest_md5.as_slice(),
+        )
+        .add_sha256_digest(header_digest.sha256.as_str())
+        .build((header_bytes.len() + content.len()) as i32);
+
+    Ok(RPMPackage::new(
+        RPMPackageMetadata {
+            lead,
+            signature,
+            header,
+        },
+        content,
+    ))
+}
+
+/// Drop the items of per file data whose entry in `keep` is `false`.
+fn retain(data: &mut IndexData, keep: &[bool]) {
+    fn filter<T>(items: &mut Vec<T>, keep: &[bool]) {
+        let mut keep = keep.iter();
+        items.retain(|_| *keep.next().unwrap_or(&true));
+    }
+    match data {
+        IndexData::Char(items) | IndexData::Bin(items) => filter(items, keep),
+        IndexData::Int8(items) => filter(items, keep),
+        IndexData::Int16(items) => filter(items, keep),
+        IndexData::Int32(items) => filter(items, keep),
+        IndexData::Int64(items) => filter(items, keep),
+        IndexData::StringArray(items) | IndexData::I18NString(items) => filter(items, keep),
+        IndexData::Null | IndexData::StringTag(_) => {}
+    }
+}
+
+/// Hex digest of `content` as recorded in `RPMTAG_FILEDIGESTS`.
+fn digest(algorithm: FileDigestAlgorithm, content: &[u8]) -> Result<String, RPMError> {
+    use sha2::Digest;
+    Ok(match algorithm {
+        FileDigestAlgorithm::Md5 => hex::encode(md5::Md5::digest(content)),
+        FileDigestAlgorithm::Sha2_224 => hex::encode(sha2::Sha224::digest(content)),
+        FileDigestAlgorithm::Sha2_256 => hex::encode(sha2::Sha256::digest(content)),
+        FileDigestAlgorithm::Sha2_384 => hex::encode(sha2::Sha384::digest(content)),
+        FileDigestAlgorithm::Sha2_512 => hex::encode(sha2::Sha512::digest(content)),
+        other => return Err(RPMError::UnsupportedFileDigestAlgorithm(other)),
+    })
+}
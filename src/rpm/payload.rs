@@ -0,0 +1,410 @@
+//! Access to the (compressed) cpio payload of a package.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::cpio;
+use super::headers::*;
+use super::timestamp::Timestamp;
+
+/// A single member of the cpio payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadEntry {
+    /// Absolute path the entry is installed to, bytes that are not valid UTF-8 are
+    /// replaced.
+    pub path: String,
+    /// `path` as stored in the payload.
+    pub raw_path: Vec<u8>,
+    /// Complete mode including the file type bits.
+    pub mode: FileMode,
+    pub uid: u32,
+    pub gid: u32,
+    pub mtime: Timestamp,
+    pub ino: u32,
+    pub nlink: u32,
+    /// File content, for symlinks this is the link target.
+    pub content: Vec<u8>,
+}
+
+impl PayloadEntry {
+    pub fn is_dir(&self) -> bool {
+        matches!(self.mode, FileMode::Dir { .. })
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        matches!(self.mode, FileMode::SymbolicLink { .. })
+    }
+}
+
+/// Layout of the cpio archive making up the payload.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CpioFormat {
+    /// Regular `newc` cpio, every entry carries its complete metadata.
+    #[default]
+    Newc,
+    /// rpm's stripped cpio, entries only reference their index in the header
+    /// which carries all other file metadata. Readable by rpm 4.12 and later.
+    Stripped,
+}
+
+/// Give every member of a hardlink set the content only the last one carries.
+pub(crate) fn fill_hardlinks(entries: &mut [PayloadEntry]) {
+    let mut contents: BTreeMap<u32, Vec<u8>> = BTreeMap::new();
+    for entry in entries.iter().filter(|entry| is_hardlinked(entry)) {
+        if !entry.content.is_empty() {
+            contents.insert(entry.ino, entry.content.clone());
+        }
+    }
+    for entry in entries.iter_mut().filter(|entry| is_hardlinked(entry)) {
+        if let Some(content) = contents.get(&entry.ino) {
+            entry.content.clone_from(content);
+        }
+    }
+}
+
+/// For every entry the index of the earlier entry it is a hardlink to, `None` for the
+/// first member of a set and all other entries.
+pub(crate) fn hardlink_sources(entries: &[PayloadEntry]) -> Vec<Option<usize>> {
+    let mut first = BTreeMap::new();
+    entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            if !is_hardlinked(entry) {
+                return None;
+            }
+            match first.get(&entry.ino) {
+                Some(source) => Some(*source),
+                None => {
+                    first.insert(entry.ino, index);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+fn is_hardlinked(entry: &PayloadEntry) -> bool {
+    entry.nlink > 1 && matches!(entry.mode, FileMode::Regular { .. })
+}
+
+/// Read the entries of a stripped cpio stream, taking the metadata from `header`.
+pub(crate) fn read_stripped_entries<R: Read>(
+    archive: R,
+    header: &Header<IndexTag>,
+) -> Result<Vec<PayloadEntry>, RPMError> {
+    let paths = header.get_file_path_bytes()?;
+    let modes = header.get_entry_i16_array_data(IndexTag::RPMTAG_FILEMODES)?;
+    let mtimes = header.get_entry_i32_array_data(IndexTag::RPMTAG_FILEMTIMES)?;
+    let sizes = header
+        .get_entry_i64_array_data(IndexTag::RPMTAG_LONGFILESIZES)
+        .or_else(|_| {
+            header
+                .get_entry_i32_array_data(IndexTag::RPMTAG_FILESIZES)
+                .map(|sizes| sizes.into_iter().map(|size| size as i64).collect())
+        })?
+        .into_iter()
+        .map(|size| size.max(0) as u64)
+        .collect::<Vec<_>>();
+    // files sharing an inode are hardlinks, only the last one carries the content
+    let inodes = header
+        .get_entry_i32_array_data(IndexTag::RPMTAG_FILEINODES)
+        .unwrap_or_default();
+    let mut links: BTreeMap<i32, (usize, u32)> = BTreeMap::new();
+    for (index, inode) in inodes.iter().enumerate() {
+        let (last, nlink) = links.entry(*inode).or_insert((index, 0));
+        *last = index;
+        *nlink += 1;
+    }
+    let link_of = |index: usize| inodes.get(index).map(|inode| links[inode]);
+    let sizes = sizes
+        .iter()
+        .enumerate()
+        .map(|(index, size)| match link_of(index) {
+            Some((last, _)) if last != index => 0,
+            _ => *size,
+        })
+        .collect::<Vec<_>>();
+
+    let mut archive = cpio::StrippedReader::new(archive, sizes.clone());
+    let mut entries = Vec::new();
+    while let Some(file_index) = archive.next_entry()? {
+        let file_index = file_index as usize;
+        let out_of_bounds = || RPMError::InvalidTagIndex {
+            tag: IndexTag::RPMTAG_FILEMODES.to_string(),
+            index: file_index as u32,
+            bound: paths.len() as u32,
+        };
+        let path = paths.get(file_index).ok_or_else(out_of_bounds)?;
+        let mode = *modes.get(file_index).ok_or_else(out_of_bounds)?;
+        let mtime = *mtimes.get(file_index).ok_or_else(out_of_bounds)?;
+
+        let mut content = Vec::new();
+        archive.read_to_end(&mut content)?;
+        if content.len() as u64 != sizes[file_index] {
+            return Err(RPMError::Malformed {
+                what: "stripped cpio entry",
+                reason: "truncated content".to_string(),
+            });
+        }
+
+        entries.push(PayloadEntry {
+            path: String::from_utf8_lossy(path).into_owned(),
+            raw_path: path.clone(),
+            mode: FileMode::from(mode),
+            uid: 0,
+            gid: 0,
+            mtime: Timestamp::from_tag(mtime),
+            ino: inodes
+                .get(file_index)
+                .map_or(file_index as u32 + 1, |inode| *inode as u32),
+            nlink: link_of(file_index).map_or(1, |(_, nlink)| nlink),
+            content,
+        });
+    }
+    Ok(entries)
+}
+
+/// Wrap the raw payload bytes into a reader undoing the payload compression.
+///
+/// `compressor` is the value of `RPMTAG_PAYLOADCOMPRESSOR`, if present.
+pub(crate) fn decompress<'a>(
+    compressor: Option<&str>,
+    content: &'a [u8],
+) -> Result<Box<dyn Read + 'a>, RPMError> {
+    match compressor {
+        None | Some("") | Some("none") => Ok(Box::new(content)),
+        Some("gzip") => Ok(Box::new(libflate::gzip::Decoder::new(content)?)),
+        Some("zstd") => Ok(Box::new(zstd::stream::Decoder::new(content)?)),
+        Some("bzip2") => Ok(Box::new(bzip2_rs::DecoderReader::new(content))),
+        Some("xz") => Ok(Box::new(xz2::read::XzDecoder::new(content))),
+        Some("lzma") => Ok(Box::new(xz2::read::XzDecoder::new_stream(
+            content,
+            xz2::stream::Stream::new_lzma_decoder(u64::MAX).map_err(std::io::Error::from)?,
+        ))),
+        Some(other) => Err(RPMError::UnknownCompressorType(other.to_string())),
+    }
+}
+
+/// Read all entries of an uncompressed cpio stream up to the trailer.
+///
+/// Names are arbitrary bytes, see [`cpio::Reader`].
+pub(crate) fn read_entries<R: Read>(archive: R) -> Result<Vec<PayloadEntry>, RPMError> {
+    let mut archive = cpio::Reader::new(archive);
+    let mut entries = Vec::new();
+    while let Some(entry) = archive.next_entry()? {
+        let mut content = Vec::new();
+        archive.read_to_end(&mut content)?;
+        if content.len() != entry.file_size as usize {
+            return Err(RPMError::Malformed {
+                what: "newc cpio entry",
+                reason: "truncated content".to_string(),
+            });
+        }
+
+        let name = entry.name;
+        let raw_path = match name.strip_prefix(b".") {
+            Some(stripped) => stripped.to_vec(),
+            None if name.starts_with(b"/") => name,
+            None => [b"/".as_ref(), &name].concat(),
+        };
+        entries.push(PayloadEntry {
+            path: String::from_utf8_lossy(&raw_path).into_owned(),
+            raw_path,
+            mode: FileMode::from(entry.mode as u16),
+            uid: entry.uid,
+            gid: entry.gid,
+            mtime: Timestamp::new(entry.mtime),
+            ino: entry.ino,
+            nlink: entry.nlink,
+            content,
+        });
+    }
+    Ok(entries)
+}
+
+/// Collect the user and group name of every file listed in the header.
+#[cfg(feature = "tar")]
+pub(crate) fn file_ownership(
+    header: &Header<IndexTag>,
+) -> std::collections::HashMap<String, (String, String)> {
+    let users = header.get_entry_string_array_data(IndexTag::RPMTAG_FILEUSERNAME);
+    let groups = header.get_entry_string_array_data(IndexTag::RPMTAG_FILEGROUPNAME);
+    match (header.get_file_paths(), users, groups) {
+        (Ok(paths), Ok(users), Ok(groups)) => paths
+            .into_iter()
+            .zip(users.iter().zip(groups.iter()))
+            .map(|(path, (user, group))| {
+                (
+                    path.to_string_lossy().to_string(),
+                    (user.clone(), group.clone()),
+                )
+            })
+            .collect(),
+        _ => Default::default(),
+    }
+}
+
+/// Append payload entries to a tar archive, including all implicit parent directories.
+///
+/// Paths are stored relative to the root, `/usr/bin/foo` becomes `usr/bin/foo`.
+///
+/// `ownership` maps an entry path to the user and group name to record.
+#[cfg(feature = "tar")]
+pub(crate) fn append_to_tar<W, F>(
+    tar: &mut tar::Builder<W>,
+    entries: &[PayloadEntry],
+    ownership: F,
+) -> Result<(), RPMError>
+where
+    W: std::io::Write,
+    F: Fn(&str) -> Option<(String, String)>,
+{
+    let mut directories = std::collections::BTreeSet::new();
+    for entry in entries {
+        let mut parent = std::path::Path::new(&entry.path).parent();
+        while let Some(dir) = parent {
+            if dir.as_os_str().len() > 1 {
+                directories.insert(dir.to_string_lossy().to_string());
+            }
+            parent = dir.parent();
+        }
+    }
+    for entry in entries.iter().filter(|entry| entry.is_dir()) {
+        directories.remove(&entry.path);
+    }
+
+    let mtime = entries
+        .iter()
+        .map(|entry| entry.mtime)
+        .max()
+        .unwrap_or_default();
+    for dir in directories {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_mtime(u64::from(mtime.seconds()));
+        header.set_size(0);
+        header.set_username("root")?;
+        header.set_groupname("root")?;
+        tar.append_data(&mut header, format!("{}/", &dir[1..]), std::io::empty())?;
+    }
+
+    let sources = hardlink_sources(entries);
+    for (entry, source) in entries.iter().zip(sources) {
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(entry.mode.permissions() as u32);
+        header.set_uid(entry.uid as u64);
+        header.set_gid(entry.gid as u64);
+        header.set_mtime(u64::from(entry.mtime.seconds()));
+        let (user, group) =
+            ownership(&entry.path).unwrap_or_else(|| ("root".to_string(), "root".to_string()));
+        header.set_username(&user)?;
+        header.set_groupname(&group)?;
+        let path = entry.path.trim_start_matches('/');
+        if entry.is_symlink() {
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            let target = String::from_utf8_lossy(&entry.content).to_string();
+            tar.append_link(&mut header, path, target)?;
+        } else if let Some(source) = source {
+            header.set_entry_type(tar::EntryType::Link);
+            header.set_size(0);
+            let target = entries[source].path.trim_start_matches('/');
+            tar.append_link(&mut header, path, target)?;
+        } else if entry.is_dir() {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_size(0);
+            tar.append_data(&mut header, format!("{}/", path), std::io::empty())?;
+        } else {
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(entry.content.len() as u64);
+            tar.append_data(&mut header, path, entry.content.as_slice())?;
+        }
+    }
+    Ok(())
+}
+
+/// A gzip compressed tar layer as used by OCI images.
+#[cfg(feature = "tar")]
+#[derive(Debug, Clone)]
+pub struct OciLayer {
+    /// The compressed layer blob.
+    pub blob: Vec<u8>,
+    /// Digest of the compressed blob, `sha256:<hex>`, used to reference it from the manifest.
+    pub digest: String,
+    /// Digest of the uncompressed tar, `sha256:<hex>`, as listed in the image config `rootfs.diff_ids`.
+    pub diff_id: String,
+}
+
+#[cfg(feature = "tar")]
+impl OciLayer {
+    pub const MEDIA_TYPE: &'static str = "application/vnd.oci.image.layer.v1.tar+gzip";
+
+    pub(crate) fn from_tar(tar: &[u8]) -> Result<Self, RPMError> {
+        use std::io::Write;
+
+        let mut encoder = libflate::gzip::Encoder::new(Vec::new())?;
+        encoder.write_all(tar)?;
+        let blob = encoder.finish().into_result()?;
+        Ok(OciLayer {
+            digest: sha256_digest(&blob),
+            diff_id: sha256_digest(tar),
+            blob,
+        })
+    }
+}
+
+#[cfg(feature = "tar")]
+fn sha256_digest(data: &[u8]) -> String {
+    use sha2::Digest;
+
+    let mut hasher = sha2::Sha256::default();
+    hasher.update(data);
+    format!("sha256:{}", hex::encode(hasher.finalize()))
+}
+
+#[cfg(all(test, feature = "tar"))]
+mod test {
+    use crate::{RPMBuilder, RPMFileOptions};
+
+    #[test]
+    fn payload_as_tar_and_oci_layer() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "an awesome tool")
+            .with_file(
+                "./test_assets/awesome.py",
+                RPMFileOptions::new("/usr/bin/awesome").user("nobody"),
+            )?
+            .build()?;
+
+        let mut raw = Vec::new();
+        pkg.payload_to_tar(&mut raw)?;
+        let mut archive = tar::Archive::new(raw.as_slice());
+        let mut found = Vec::new();
+        for entry in archive.entries()? {
+            let entry = entry?;
+            found.push((
+                entry.path()?.to_string_lossy().to_string(),
+                entry.header().username()?.unwrap_or_default().to_string(),
+            ));
+        }
+        assert_eq!(
+            vec![
+                ("usr/".to_string(), "root".to_string()),
+                ("usr/bin/".to_string(), "root".to_string()),
+                ("usr/bin/awesome".to_string(), "nobody".to_string()),
+            ],
+            found
+        );
+
+        let layer = pkg.payload_to_oci_layer()?;
+        assert_eq!(super::sha256_digest(&raw), layer.diff_id);
+        assert_eq!(super::sha256_digest(&layer.blob), layer.digest);
+        assert_ne!(layer.digest, layer.diff_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+//! Enforcing rules on the modes of packaged files.
+
+use crate::errors::*;
+
+use super::{FileMode, RPMBuilder};
+
+const WORLD_WRITABLE: u16 = 0o002;
+const SETUID_SETGID: u16 = 0o6000;
+
+/// Rules for file modes, applied when building, see [`RPMBuilder::mode_policy`].
+///
+/// The default policy rejects world-writable as well as setuid and setgid
+/// files and changes no modes.
+#[derive(Debug, Clone, Default)]
+pub struct ModePolicy {
+    umask: u16,
+    directory_mode: Option<u16>,
+    allow_world_writable: bool,
+    allow_setid: bool,
+    exceptions: Vec<String>,
+}
+
+impl ModePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Clear the bits of `umask` from permissions taken from source files,
+    /// e.g. `0o022` for files not writable by group and others.
+    ///
+    /// Modes set explicitly through [`RPMFileOptions`](crate::RPMFileOptions) or
+    /// [`RPMBuilder::default_attributes`] are kept.
+    pub fn umask(mut self, umask: u16) -> Self {
+        self.umask = umask & 0o7777;
+        self
+    }
+
+    /// Give every directory the permissions `mode`, e.g. `0o755`.
+    pub fn directory_mode(mut self, mode: u16) -> Self {
+        self.directory_mode = Some(mode & 0o7777);
+        self
+    }
+
+    /// Accept files and directories everyone may write to.
+    pub fn allow_world_writable(mut self, allow: bool) -> Self {
+        self.allow_world_writable = allow;
+        self
+    }
+
+    /// Accept setuid and setgid files.
+    pub fn allow_setid(mut self, allow: bool) -> Self {
+        self.allow_setid = allow;
+        self
+    }
+
+    /// Leave the file or directory at `path` alone, e.g. `/usr/bin/sudo` or `/var/tmp`.
+    pub fn allow<T: Into<String>>(mut self, path: T) -> Self {
+        self.exceptions.push(path.into());
+        self
+    }
+
+    /// Apply the policy to the file at `path`, returning the mode it gets.
+    fn apply(&self, path: &str, mode: FileMode, inherited: bool) -> Result<FileMode, RPMError> {
+        if self.exceptions.iter().any(|exception| exception == path) {
+            return Ok(mode);
+        }
+        let violation = |reason| {
+            Err(RPMError::ModePolicyViolation {
+                path: path.to_string(),
+                reason,
+            })
+        };
+        let mode = match mode {
+            FileMode::Dir { permissions } => FileMode::dir(match self.directory_mode {
+                Some(directory_mode) => directory_mode,
+                None if inherited => permissions & !self.umask,
+                None => permissions,
+            }),
+            FileMode::Regular { permissions } if inherited => {
+                FileMode::regular(permissions & !self.umask)
+            }
+            FileMode::Regular { .. } => mode,
+            // symlinks carry no permissions of their own
+            mode => return Ok(mode),
+        };
+        let permissions = mode.permissions();
+        if !self.allow_world_writable && permissions & WORLD_WRITABLE != 0 {
+            return violation("world-writable");
+        }
+        if !self.allow_setid && permissions & SETUID_SETGID != 0 {
+            return violation("setuid or setgid");
+        }
+        Ok(mode)
+    }
+}
+
+impl RPMBuilder {
+    /// Enforce `policy` on all files, no matter if they were added before or after this call.
+    ///
+    /// Building fails with [`RPMError::ModePolicyViolation`] for files the policy rejects.
+    /// Subpackages built with [`build_all`](Self::build_all) follow the same policy.
+    pub fn mode_policy(mut self, policy: ModePolicy) -> Self {
+        self.mode_policy = Some(policy);
+        self
+    }
+
+    pub(crate) fn apply_mode_policy(&mut self) -> Result<(), RPMError> {
+        let policy = match &self.mode_policy {
+            Some(policy) => policy,
+            None => return Ok(()),
+        };
+        for (cpio_path, entry) in self.files.iter_mut() {
+            entry.mode = policy.apply(
+                cpio_path.trim_start_matches('.'),
+                entry.mode,
+                entry.inherited_mode,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RPMFileOptions;
+
+    #[test]
+    fn enforce_mode_policy() -> Result<(), Box<dyn std::error::Error>> {
+        let builder = || -> Result<RPMBuilder, RPMError> {
+            let mut builder = RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "").with_file(
+                "./test_assets/awesome.py",
+                RPMFileOptions::new("/usr/bin/awesome").mode(0o104_755),
+            )?;
+            builder.add_data(
+                Vec::new(),
+                crate::Timestamp::EPOCH,
+                RPMFileOptions::new("/var/spool/foo")
+                    .mode(FileMode::dir(0o777))
+                    .into(),
+            )?;
+            Ok(builder)
+        };
+        assert!(matches!(
+            builder()?.mode_policy(ModePolicy::new()).build(),
+            Err(RPMError::ModePolicyViolation { .. })
+        ));
+
+        let package = builder()?
+            .mode_policy(
+                ModePolicy::new()
+                    .directory_mode(0o755)
+                    .allow("/usr/bin/awesome"),
+            )
+            .build()?;
+        let modes = package
+            .metadata
+            .header
+            .get_file_entries()?
+            .into_iter()
+            .map(|entry| (entry.path.to_string_lossy().to_string(), entry.mode))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("/usr/bin/awesome".to_string(), FileMode::regular(0o4755)),
+                ("/var/spool/foo".to_string(), FileMode::dir(0o755)),
+            ],
+            modes
+        );
+
+        let mut policy = ModePolicy::new().umask(0o022);
+        assert_eq!(
+            FileMode::regular(0o644),
+            policy.apply("/etc/foo", FileMode::regular(0o666), true)?
+        );
+        assert!(policy
+            .apply("/etc/foo", FileMode::regular(0o666), false)
+            .is_err());
+        policy = policy.allow_world_writable(true).allow_setid(true);
+        assert_eq!(
+            FileMode::regular(0o6777),
+            policy.apply("/etc/foo", FileMode::regular(0o6777), false)?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+//! Feedback and control for long running operations, e.g. to render progress bars.
+
+use std::io::{BufRead, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::errors::*;
+
+/// The operation a [`ProgressUpdate`] belongs to.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Writing files into the compressed payload, see [`RPMBuilder::progress`](super::RPMBuilder::progress).
+    Build,
+    /// Reading a package, see [`ProgressReader`].
+    Parse,
+    /// Unpacking files, see [`InstallOptions::progress`](super::InstallOptions::progress).
+    Extract,
+    /// Hashing header and payload for signature verification.
+    Verify,
+}
+
+/// A snapshot of an operation in progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressUpdate<'a> {
+    pub phase: Phase,
+    /// Bytes processed so far in this phase.
+    pub bytes: u64,
+    /// Bytes to process in this phase, if known up front.
+    pub total: Option<u64>,
+    /// The file just processed, if the phase works on files.
+    pub file: Option<&'a str>,
+}
+
+/// Receives [`ProgressUpdate`]s, implemented for all matching closures.
+///
+/// Updates are sent after every file and at least once per phase with the final count.
+pub trait Progress: Send + Sync {
+    fn update(&self, update: &ProgressUpdate<'_>);
+}
+
+impl<F> Progress for F
+where
+    F: Fn(&ProgressUpdate<'_>) + Send + Sync,
+{
+    fn update(&self, update: &ProgressUpdate<'_>) {
+        self(update)
+    }
+}
+
+/// Aborts an operation from another thread, which then fails with [`RPMError::Cancelled`].
+///
+/// Clones share the same state. Operations check the token between files,
+/// between reads when hashing, and between chunks of files when building.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Fail with [`RPMError::Cancelled`] once `token` is cancelled.
+pub(crate) fn check(token: Option<&CancellationToken>) -> Result<(), RPMError> {
+    match token {
+        Some(token) if token.is_cancelled() => Err(RPMError::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+/// [`RPMError::Cancelled`] instead of `err` once `token` is cancelled, for errors of
+/// readers and writers which can only fail with I/O errors.
+pub(crate) fn cancelled_or(token: Option<&CancellationToken>, err: RPMError) -> RPMError {
+    check(token).err().unwrap_or(err)
+}
+
+/// Bytes passed on to the inner writer of a [`CancellableWriter`] per write.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Writes at most [`CHUNK_SIZE`] bytes at a time, failing before each write once
+/// the token is cancelled, so copying and compressing large files stops early.
+pub(crate) struct CancellableWriter<W> {
+    inner: W,
+    cancellation: Option<CancellationToken>,
+}
+
+impl<W> CancellableWriter<W> {
+    pub(crate) fn new(inner: W, cancellation: Option<CancellationToken>) -> Self {
+        CancellableWriter {
+            inner,
+            cancellation,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for CancellableWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        check(self.cancellation.as_ref())
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+        let len = buf.len().min(CHUNK_SIZE);
+        self.inner.write(&buf[..len])
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reports the bytes read through it, e.g. to follow [`RPMPackage::parse`](super::RPMPackage::parse).
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let progress = |update: &rpm::ProgressUpdate<'_>| println!("{} bytes", update.bytes);
+/// let file = std::fs::File::open("test_assets/389-ds-base-devel-1.3.8.4-15.el7.x86_64.rpm")?;
+/// let total = file.metadata()?.len();
+/// let mut reader = std::io::BufReader::new(rpm::ProgressReader::new(
+///     file,
+///     rpm::Phase::Parse,
+///     Some(total),
+///     &progress,
+/// ));
+/// let pkg = rpm::RPMPackage::parse(&mut reader)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ProgressReader<'p, R> {
+    inner: R,
+    phase: Phase,
+    bytes: u64,
+    total: Option<u64>,
+    progress: &'p dyn Progress,
+    cancellation: Option<CancellationToken>,
+}
+
+impl<'p, R> ProgressReader<'p, R> {
+    pub fn new(inner: R, phase: Phase, total: Option<u64>, progress: &'p dyn Progress) -> Self {
+        ProgressReader {
+            inner,
+            phase,
+            bytes: 0,
+            total,
+            progress,
+            cancellation: None,
+        }
+    }
+
+    /// Fail reads once `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn check(&self) -> std::io::Result<()> {
+        check(self.cancellation.as_ref())
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
+    }
+
+    fn advance(&mut self, len: usize) {
+        self.bytes += len as u64;
+        self.progress.update(&ProgressUpdate {
+            phase: self.phase,
+            bytes: self.bytes,
+            total: self.total,
+            file: None,
+        });
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.check()?;
+        let len = self.inner.read(buf)?;
+        self.advance(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for ProgressReader<'_, R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.check()?;
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.advance(amt);
+    }
+}
+
+/// Send updates to an optional receiver.
+pub(crate) fn report(
+    progress: Option<&dyn Progress>,
+    phase: Phase,
+    bytes: u64,
+    total: Option<u64>,
+    file: Option<&str>,
+) {
+    if let Some(progress) = progress {
+        progress.update(&ProgressUpdate {
+            phase,
+            bytes,
+            total,
+            file,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn reader_counts_bytes() -> std::io::Result<()> {
+        let seen = Mutex::new(Vec::new());
+        let progress = |update: &ProgressUpdate<'_>| seen.lock().unwrap().push(update.bytes);
+        let data = [7u8; 10];
+        let mut reader = ProgressReader::new(&data[..], Phase::Parse, Some(10), &progress);
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf)?;
+        reader.read_to_end(&mut Vec::new())?;
+        let seen = seen.into_inner().unwrap();
+        assert_eq!(Some(&4), seen.first());
+        assert_eq!(Some(&10), seen.last());
+
+        let token = CancellationToken::new();
+        let ignore = |_: &ProgressUpdate<'_>| {};
+        let mut reader =
+            ProgressReader::new(&data[..], Phase::Parse, None, &ignore).cancellation(token.clone());
+        reader.read_exact(&mut buf)?;
+        token.cancel();
+        assert!(reader.read_exact(&mut buf).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn writer_stops_within_a_file() {
+        /// Cancels the token once the first chunk arrived.
+        struct Cancelling(CancellationToken, Vec<u8>);
+
+        impl Write for Cancelling {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.cancel();
+                self.1.write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let token = CancellationToken::new();
+        let mut writer =
+            CancellableWriter::new(Cancelling(token.clone(), Vec::new()), Some(token.clone()));
+        let err = writer.write_all(&vec![0; 3 * CHUNK_SIZE]).unwrap_err();
+        assert_eq!(CHUNK_SIZE, writer.into_inner().1.len());
+        assert!(matches!(
+            cancelled_or(Some(&token), err.into()),
+            RPMError::Cancelled
+        ));
+    }
+}
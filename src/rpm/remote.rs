@@ -0,0 +1,315 @@
+//! Inspect packages on a web server without downloading them in full.
+//!
+//! Only lead and headers are fetched up front, using HTTP range requests.
+//! The payload is fetched once it is read.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let package = rpm::remote::RemotePackage::open("https://example.com/repo/foo-1.0-1.x86_64.rpm")?;
+//! println!("{}", package.metadata.header.get_name()?);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The requests are blocking, so calling into this module from an async
+//! runtime has to happen on a thread that may block, e.g. via `spawn_blocking`.
+
+use std::io::{BufRead, Read, Seek, SeekFrom};
+
+use reqwest::blocking::Client;
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::StatusCode;
+
+use crate::errors::*;
+
+use super::{RPMPackage, RPMPackageMetadata};
+
+/// Size of the first range requested, enough for the headers of most packages.
+const FIRST_CHUNK: u64 = 64 * 1024;
+/// Sequential reads double the range requested up to this size.
+const MAX_CHUNK: u64 = 8 * 1024 * 1024;
+
+/// A remote file read through HTTP range requests.
+///
+/// The server has to answer ranges with `206 Partial Content`, servers
+/// sending the whole file instead are rejected before the body is read.
+pub struct RangeReader {
+    client: Client,
+    url: String,
+    len: u64,
+    /// Offset of `buffer` in the file.
+    position: u64,
+    buffer: Vec<u8>,
+    consumed: usize,
+    chunk: u64,
+    fetched: u64,
+}
+
+impl RangeReader {
+    pub fn new(url: &str) -> Result<Self, RPMError> {
+        Self::with_client(Client::new(), url)
+    }
+
+    /// Send the requests through `client`, e.g. one configured with timeouts or a proxy.
+    pub fn with_client(client: Client, url: &str) -> Result<Self, RPMError> {
+        let mut reader = RangeReader {
+            client,
+            url: url.to_string(),
+            len: u64::MAX,
+            position: 0,
+            buffer: Vec::new(),
+            consumed: 0,
+            chunk: FIRST_CHUNK,
+            fetched: 0,
+        };
+        reader.fetch(0)?;
+        Ok(reader)
+    }
+
+    /// Size of the remote file.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bytes downloaded so far.
+    pub fn fetched(&self) -> u64 {
+        self.fetched
+    }
+
+    fn error(&self, reason: String) -> RPMError {
+        RPMError::Remote {
+            url: self.url.clone(),
+            reason,
+        }
+    }
+
+    fn fetch(&mut self, start: u64) -> Result<(), RPMError> {
+        let end = start.saturating_add(self.chunk).min(self.len) - 1;
+        let response = self
+            .client
+            .get(&self.url)
+            .header(RANGE, format!("bytes={}-{}", start, end))
+            .send()
+            .map_err(|err| self.error(err.to_string()))?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(self.error(format!(
+                "expected a range response, got status {}",
+                response.status()
+            )));
+        }
+        // bytes <start>-<end>/<len>
+        let len = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|range| range.to_str().ok())
+            .and_then(|range| range.rsplit('/').next())
+            .and_then(|len| len.parse().ok())
+            .ok_or_else(|| self.error("missing or unknown content range".to_string()))?;
+        let body = response
+            .bytes()
+            .map_err(|err| self.error(err.to_string()))?;
+        self.len = len;
+        self.position = start;
+        self.buffer = body.to_vec();
+        self.consumed = 0;
+        self.fetched += self.buffer.len() as u64;
+        self.chunk = (self.chunk * 2).min(MAX_CHUNK);
+        Ok(())
+    }
+}
+
+impl BufRead for RangeReader {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        let next = self.position + self.buffer.len() as u64;
+        if self.consumed == self.buffer.len() && next < self.len {
+            self.fetch(next)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+        }
+        Ok(&self.buffer[self.consumed..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.consumed = (self.consumed + amt).min(self.buffer.len());
+    }
+}
+
+impl Read for RangeReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl Seek for RangeReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let current = self.position + self.consumed as u64;
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => checked_offset(current, offset),
+            SeekFrom::End(offset) => checked_offset(self.len, offset),
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "seek before the start of the file",
+            )
+        })?;
+        let buffered = self.position..=self.position + self.buffer.len() as u64;
+        if buffered.contains(&target) {
+            self.consumed = (target - self.position) as usize;
+        } else {
+            // a new place to read from, start small again
+            self.position = target;
+            self.buffer.clear();
+            self.consumed = 0;
+            self.chunk = FIRST_CHUNK;
+        }
+        Ok(target)
+    }
+}
+
+fn checked_offset(base: u64, offset: i64) -> Option<u64> {
+    if offset < 0 {
+        base.checked_sub(offset.unsigned_abs())
+    } else {
+        base.checked_add(offset as u64)
+    }
+}
+
+/// A package on a web server, with its metadata parsed.
+pub struct RemotePackage {
+    pub metadata: RPMPackageMetadata,
+    reader: RangeReader,
+    payload_offset: u64,
+}
+
+impl RemotePackage {
+    /// Fetch and parse lead and headers of the package at `url`.
+    pub fn open(url: &str) -> Result<Self, RPMError> {
+        Self::from_reader(RangeReader::new(url)?)
+    }
+
+    pub fn from_reader(mut reader: RangeReader) -> Result<Self, RPMError> {
+        let metadata = RPMPackageMetadata::parse(&mut reader)?;
+        let payload_offset = reader.stream_position()?;
+        Ok(RemotePackage {
+            metadata,
+            reader,
+            payload_offset,
+        })
+    }
+
+    /// Size of the whole package file.
+    pub fn len(&self) -> u64 {
+        self.reader.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reader.is_empty()
+    }
+
+    /// Size of the compressed payload.
+    pub fn payload_len(&self) -> u64 {
+        self.len() - self.payload_offset
+    }
+
+    /// Bytes downloaded so far.
+    pub fn fetched(&self) -> u64 {
+        self.reader.fetched()
+    }
+
+    /// The compressed payload, rewound to its start and fetched as it is read.
+    pub fn payload(&mut self) -> Result<&mut RangeReader, RPMError> {
+        self.reader.seek(SeekFrom::Start(self.payload_offset))?;
+        Ok(&mut self.reader)
+    }
+
+    /// Fetch the payload as well.
+    pub fn into_package(mut self) -> Result<RPMPackage, RPMError> {
+        let mut content = Vec::with_capacity(self.payload_len() as usize);
+        self.payload()?.read_to_end(&mut content)?;
+        Ok(RPMPackage::from_parts(self.metadata, content))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    /// Serve `data` to range requests on a local port.
+    fn serve(data: Vec<u8>) -> std::io::Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/package.rpm", listener.local_addr()?);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut byte = [0u8];
+                while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8(request).unwrap();
+                let range = request
+                    .lines()
+                    .find_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("range: bytes=")
+                            .map(str::to_string)
+                    })
+                    .unwrap();
+                let (start, end) = range.split_once('-').unwrap();
+                let start = start.parse::<usize>().unwrap();
+                let end = end.parse::<usize>().unwrap().min(data.len() - 1);
+                write!(
+                    stream,
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    start,
+                    end,
+                    data.len(),
+                    end + 1 - start
+                )
+                .unwrap();
+                stream.write_all(&data[start..=end]).unwrap();
+            }
+        });
+        Ok(url)
+    }
+
+    #[test]
+    fn remote_package() -> Result<(), Box<dyn std::error::Error>> {
+        let raw = std::fs::read("./test_assets/389-ds-base-devel-1.3.8.4-15.el7.x86_64.rpm")?;
+        let local = RPMPackage::parse(&mut raw.as_slice())?;
+        let url = serve(raw.clone())?;
+
+        let mut remote = RemotePackage::open(&url)?;
+        assert_eq!(local.metadata, remote.metadata);
+        assert_eq!(raw.len() as u64, remote.len());
+        assert_eq!(local.content.len() as u64, remote.payload_len());
+        assert!(remote.fetched() < raw.len() as u64);
+
+        let mut magic = [0u8; 2];
+        remote.payload()?.read_exact(&mut magic)?;
+        assert_eq!(&local.content[..2], &magic);
+
+        let fetched = remote.fetched();
+        let package = remote.into_package()?;
+        assert_eq!(local.content, package.content);
+        assert!(fetched < raw.len() as u64);
+
+        let mut reader = RangeReader::new(&url)?;
+        reader.seek(SeekFrom::End(-4))?;
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail)?;
+        assert_eq!(&raw[raw.len() - 4..], tail.as_slice());
+        Ok(())
+    }
+}
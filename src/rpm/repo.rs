@@ -0,0 +1,255 @@
+//! Laying out packages for publishing them as a repository.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut mirror = rpm::repo::RepoWriter::new("/srv/mirror");
+//! for repo in ["el8/x86_64", "el8/aarch64"] {
+//!     let location = mirror.add_file(repo, "foo-1.0-1.noarch.rpm")?;
+//!     println!("{}: {} {:?}", repo, location.href, location.info.sha256);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::errors::*;
+
+use super::{ChecksumWriter, PackageInfo, RPMPackage, RPMPackageMetadata};
+use crate::constants::*;
+
+/// Where [`RepoWriter`] puts packages, relative to the repository root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// `Packages/<first letter of the name>/<file>`, as used by Fedora.
+    #[default]
+    Hashed,
+    /// `Packages/<file>`.
+    Flat,
+}
+
+/// A package placed by [`RepoWriter`], with what repodata needs to reference it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoLocation {
+    /// The repository, relative to the root of the writer.
+    pub repo: String,
+    /// Path relative to the repository, separated by `/`.
+    pub href: String,
+    pub info: PackageInfo,
+    /// Whether the file is a hardlink to an identical package placed before.
+    pub linked: bool,
+}
+
+/// Writes packages into the repositories below a common root, storing identical packages only once.
+///
+/// Packages with the same content, e.g. a noarch build published in the
+/// repositories of several architectures or releases, are hardlinked to the
+/// copy written first. File systems without hardlinks get a copy instead.
+#[derive(Debug)]
+pub struct RepoWriter {
+    root: PathBuf,
+    layout: Layout,
+    /// First file written for each sha256.
+    written: HashMap<String, PathBuf>,
+    locations: Vec<RepoLocation>,
+}
+
+impl RepoWriter {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        RepoWriter {
+            root: root.as_ref().to_path_buf(),
+            layout: Layout::default(),
+            written: HashMap::new(),
+            locations: Vec::new(),
+        }
+    }
+
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Place `package` in `repo`, a directory relative to the root, `""` for the root itself.
+    pub fn add(&mut self, repo: &str, package: &RPMPackage) -> Result<RepoLocation, RPMError> {
+        let mut out = ChecksumWriter::new(Vec::new());
+        package.write(&mut out)?;
+        let (bytes, info) = out.finish();
+        self.place(repo, &package.metadata, &bytes, info)
+    }
+
+    /// Place the package file at `path`, which is parsed for its name.
+    pub fn add_file<P: AsRef<Path>>(
+        &mut self,
+        repo: &str,
+        path: P,
+    ) -> Result<RepoLocation, RPMError> {
+        let bytes = std::fs::read(path)?;
+        let metadata = RPMPackageMetadata::parse(&mut bytes.as_slice())?;
+        let mut out = ChecksumWriter::new(std::io::sink());
+        std::io::Write::write_all(&mut out, &bytes)?;
+        let (_, info) = out.finish();
+        self.place(repo, &metadata, &bytes, info)
+    }
+
+    /// The packages placed so far, in the order they were added.
+    pub fn locations(&self) -> &[RepoLocation] {
+        &self.locations
+    }
+
+    pub fn finish(self) -> Vec<RepoLocation> {
+        self.locations
+    }
+
+    fn href(&self, metadata: &RPMPackageMetadata) -> Result<String, RPMError> {
+        let header = &metadata.header;
+        let name = header.get_name()?;
+        // rpmbuild marks source packages, their arch is the one they were built on
+        let arch = if header
+            .find_entry_or_err(&IndexTag::RPMTAG_SOURCEPACKAGE)
+            .is_ok()
+        {
+            "src"
+        } else {
+            header.get_arch()?
+        };
+        let file = format!(
+            "{}-{}-{}.{}.rpm",
+            name,
+            header.get_version()?,
+            header.get_release()?,
+            arch
+        );
+        Ok(match self.layout {
+            Layout::Hashed => {
+                let letter = name.chars().next().unwrap_or('_').to_ascii_lowercase();
+                format!("Packages/{}/{}", letter, file)
+            }
+            Layout::Flat => format!("Packages/{}", file),
+        })
+    }
+
+    fn place(
+        &mut self,
+        repo: &str,
+        metadata: &RPMPackageMetadata,
+        bytes: &[u8],
+        info: PackageInfo,
+    ) -> Result<RepoLocation, RPMError> {
+        let href = self.href(metadata)?;
+        let destination = self.root.join(repo).join(&href);
+        let sha256 = info.sha256.clone().unwrap_or_default();
+        // files may have been replaced or removed since they were written
+        let original = self
+            .written
+            .get(&sha256)
+            .filter(|original| is_intact(original, &info))
+            .cloned();
+        if original.as_ref() == Some(&destination) {
+            // the very same package again
+            return Ok(RepoLocation {
+                repo: repo.to_string(),
+                href,
+                info,
+                linked: false,
+            });
+        }
+
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        match std::fs::remove_file(&destination) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        // whatever was stored at the destination is gone
+        self.written.retain(|_, written| *written != destination);
+        let linked = original
+            .map(|original| std::fs::hard_link(original, &destination).is_ok())
+            .unwrap_or_default();
+        if !linked {
+            std::fs::write(&destination, bytes)?;
+            self.written.insert(sha256, destination);
+        }
+
+        let location = RepoLocation {
+            repo: repo.to_string(),
+            href,
+            info,
+            linked,
+        };
+        self.locations.push(location.clone());
+        Ok(location)
+    }
+}
+
+/// Whether the file at `path` still has the size and sha256 digest of `info`.
+fn is_intact(path: &Path, info: &PackageInfo) -> bool {
+    let matches = |bytes: Vec<u8>| {
+        let mut out = ChecksumWriter::new(std::io::sink());
+        std::io::Write::write_all(&mut out, &bytes).is_ok() && out.finish().1 == *info
+    };
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.len() == info.size => std::fs::read(path).map_or(false, matches),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RPMBuilder;
+
+    #[test]
+    fn hardlinks_identical_packages() -> Result<(), Box<dyn std::error::Error>> {
+        let root = crate::tests::TestDir::new("repo");
+        let mut mirror = RepoWriter::new(&root);
+
+        let package = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "").build()?;
+        let first = mirror.add("x86_64", &package)?;
+        assert_eq!("Packages/f/foo-1.0.0-1.noarch.rpm", first.href);
+        assert!(!first.linked);
+        let written = root.join("x86_64").join(&first.href);
+        assert_eq!(std::fs::metadata(&written)?.len(), first.info.size);
+
+        let again = mirror.add_file("aarch64", &written)?;
+        assert_eq!(first.href, again.href);
+        assert_eq!(first.info, again.info);
+        assert!(again.linked);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(2, std::fs::metadata(&written)?.nlink());
+        }
+        // adding it to the same repository again changes nothing
+        assert!(!mirror.add("x86_64", &package)?.linked);
+        assert_eq!(2, mirror.locations().len());
+
+        // replacing the first copy keeps identical packages from linking to it
+        let rebuilt = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "rebuilt").build()?;
+        assert!(!mirror.add("x86_64", &rebuilt)?.linked);
+        let placed = mirror.add("ppc64le", &package)?;
+        assert!(!placed.linked);
+        let mut bytes = Vec::new();
+        package.write(&mut bytes)?;
+        let copy = root.join("ppc64le").join(&placed.href);
+        assert_eq!(bytes, std::fs::read(&copy)?);
+        // nor to a copy changed behind the back of the writer
+        std::fs::write(&copy, b"truncated")?;
+        assert!(!mirror.add("s390x", &package)?.linked);
+        // which is replaced by a link to the intact one
+        assert!(mirror.add("ppc64le", &package)?.linked);
+        assert_eq!(bytes, std::fs::read(&copy)?);
+
+        let mut flat = RepoWriter::new(&root).layout(Layout::Flat);
+        let other = RPMBuilder::new("Bar", "1.0.0", "MIT", "noarch", "").build()?;
+        assert_eq!(
+            "Packages/Bar-1.0.0-1.noarch.rpm",
+            flat.add("", &other)?.href
+        );
+        assert!(root.join("Packages/Bar-1.0.0-1.noarch.rpm").exists());
+        assert_eq!(1, flat.finish().len());
+
+        Ok(())
+    }
+}
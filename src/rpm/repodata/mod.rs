@@ -0,0 +1,975 @@
+//! Reading the metadata of published repositories, e.g. to compare snapshots of a mirror.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let old = rpm::repodata::Repodata::open("/srv/mirror/el8/x86_64")?;
+//! let new = rpm::repodata::Repodata::open("/srv/staging/el8/x86_64")?;
+//! for change in rpm::repodata::diff(&old, &new) {
+//!     println!("{}", change);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod updateinfo;
+mod verify;
+mod xml;
+
+pub use updateinfo::*;
+pub use verify::*;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::Digest;
+
+use crate::errors::*;
+
+use super::Compressor;
+use xml::{escape, Event, Reader};
+
+/// A digest as found in repodata.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Checksum {
+    /// The algorithm, e.g. `sha256`.
+    pub kind: String,
+    /// Lowercase hex digest.
+    pub value: String,
+}
+
+impl Checksum {
+    /// Digest `bytes` with the algorithm of this checksum, `None` if it is not supported.
+    pub fn matches(&self, bytes: &[u8]) -> Option<bool> {
+        let digest = match self.kind.as_str() {
+            "sha256" => hex::encode(sha2::Sha256::digest(bytes)),
+            "sha512" => hex::encode(sha2::Sha512::digest(bytes)),
+            "sha" | "sha1" => hex::encode(sha1::Sha1::digest(bytes)),
+            _ => return None,
+        };
+        Some(digest.eq_ignore_ascii_case(&self.value))
+    }
+}
+
+/// A metadata file referenced by `repomd.xml`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepomdRecord {
+    /// The kind of metadata, e.g. `primary` or `filelists`.
+    pub kind: String,
+    /// Path relative to the repository.
+    pub location: String,
+    /// Digest of the file as stored.
+    pub checksum: Checksum,
+    /// Digest of the uncompressed file.
+    pub open_checksum: Option<Checksum>,
+    pub size: Option<u64>,
+    pub open_size: Option<u64>,
+    pub timestamp: Option<u64>,
+    /// Child elements without a field, e.g. `database_version` or the zchunk
+    /// `header-checksum`, as XML that is written back verbatim.
+    pub extra: Vec<String>,
+}
+
+/// The index of a repository, `repodata/repomd.xml`.
+///
+/// Besides the metadata createrepo generates, a repository can carry additional
+/// files such as the `modules.yaml` of modular repositories:
+///
+/// ```rust,no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::str::FromStr;
+///
+/// let repo = "/srv/mirror/el8/x86_64";
+/// let modules = std::fs::read("modules.yaml")?;
+/// let mut repomd = rpm::repodata::Repomd::open(repo)?;
+/// repomd.attach(repo, "modules", "modules.yaml", &modules, rpm::Compressor::from_str("gzip")?)?;
+/// repomd.write(repo)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Attaching replaces records but leaves their files in place for clients still
+/// reading the previous index, [`prune`](Repomd::prune) removes them once it is
+/// safe to.
+///
+/// Only the revision, the `<tags>` and the `<data>` records are kept, writing an
+/// index that was read drops other top-level elements. Children of a record that
+/// [`RepomdRecord`] has no field for are kept verbatim in [`RepomdRecord::extra`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Repomd {
+    pub revision: Option<String>,
+    pub tags: Vec<RepomdTag>,
+    pub records: Vec<RepomdRecord>,
+}
+
+/// An element of the `<tags>` of `repomd.xml`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepomdTag {
+    /// `content`, `repo` or `distro`.
+    pub kind: String,
+    /// The CPE of `distro` tags.
+    pub cpeid: Option<String>,
+    pub value: String,
+}
+
+impl Repomd {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read `repodata/repomd.xml` of the repository at `repo`.
+    pub fn open<P: AsRef<Path>>(repo: P) -> Result<Self, RPMError> {
+        Self::parse(&std::fs::read_to_string(
+            repo.as_ref().join("repodata").join("repomd.xml"),
+        )?)
+    }
+
+    pub fn parse(xml: &str) -> Result<Self, RPMError> {
+        let mut reader = Reader::new(xml);
+        let mut repomd = Repomd::new();
+        while let Some(event) = reader.next_event()? {
+            let tag = match event {
+                Event::Start(tag) => tag,
+                _ => continue,
+            };
+            match tag.name {
+                "revision" => repomd.revision = Some(reader.text()?.trim().to_string()),
+                "tags" => repomd.tags = parse_tags(&mut reader)?,
+                "data" => {
+                    let kind = tag
+                        .attribute("type")
+                        .ok_or_else(|| invalid("data without a type"))?
+                        .to_string();
+                    repomd.records.push(parse_record(&mut reader, kind)?);
+                }
+                _ => {}
+            }
+        }
+        Ok(repomd)
+    }
+
+    /// The first record of `kind`, e.g. `primary`.
+    pub fn record(&self, kind: &str) -> Option<&RepomdRecord> {
+        self.records.iter().find(|record| record.kind == kind)
+    }
+
+    /// Compress `content` with `compressor` into the `repodata` directory of `repo` and
+    /// reference it as metadata of `kind`, replacing any record of the same kind.
+    ///
+    /// The file is named like createrepo does, the checksum followed by `file_name`
+    /// and the extension of the compression, e.g. `<sha256>-modules.yaml.gz`.
+    pub fn attach<P: AsRef<Path>>(
+        &mut self,
+        repo: P,
+        kind: &str,
+        file_name: &str,
+        content: &[u8],
+        mut compressor: Compressor,
+    ) -> Result<&RepomdRecord, RPMError> {
+        let extension = match compressor.get_details() {
+            Some(details) if details.compression_name == "zstd" => ".zst",
+            Some(_) => ".gz",
+            None => "",
+        };
+        compressor.write_all(content)?;
+        let compressed = compressor.finish_compression()?;
+        let checksum = hex::encode(sha2::Sha256::digest(&compressed));
+        let location = format!("repodata/{}-{}{}", checksum, file_name, extension);
+        let dir = repo.as_ref().join("repodata");
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(repo.as_ref().join(&location), &compressed)?;
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let record = RepomdRecord {
+            kind: kind.to_string(),
+            location,
+            checksum: Checksum {
+                kind: "sha256".to_string(),
+                value: checksum,
+            },
+            open_checksum: Some(Checksum {
+                kind: "sha256".to_string(),
+                value: hex::encode(sha2::Sha256::digest(content)),
+            }),
+            size: Some(compressed.len() as u64),
+            open_size: Some(content.len() as u64),
+            timestamp: Some(timestamp),
+            extra: Vec::new(),
+        };
+        self.records.retain(|record| record.kind != kind);
+        self.records.push(record);
+        Ok(self.records.last().unwrap())
+    }
+
+    /// Write the index to `repodata/repomd.xml` of the repository at `repo`.
+    ///
+    /// The index is written aside and renamed, clients never see a partial one.
+    pub fn write<P: AsRef<Path>>(&self, repo: P) -> Result<(), RPMError> {
+        let dir = repo.as_ref().join("repodata");
+        std::fs::create_dir_all(&dir)?;
+        let partial = dir.join(format!(".repomd.xml.{}", std::process::id()));
+        let written = std::fs::File::create(&partial).and_then(|mut file| {
+            file.write_all(self.to_string().as_bytes())?;
+            file.sync_all()
+        });
+        match written.and_then(|_| std::fs::rename(&partial, dir.join("repomd.xml"))) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let _ = std::fs::remove_file(&partial);
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Remove the files in `repodata` of `repo` named like [`attach`](Self::attach)
+    /// names them, a checksum and a dash, that no record references, returning the
+    /// paths removed.
+    ///
+    /// Call it after [`write`](Self::write), once clients had time to fetch the files
+    /// of the previous index. Other files, like `repomd.xml.asc`, are left alone.
+    pub fn prune<P: AsRef<Path>>(&self, repo: P) -> Result<Vec<PathBuf>, RPMError> {
+        let referenced = self
+            .records
+            .iter()
+            .map(|record| repo.as_ref().join(&record.location))
+            .collect::<BTreeSet<_>>();
+        let mut removed = Vec::new();
+        for entry in std::fs::read_dir(repo.as_ref().join("repodata"))? {
+            let path = entry?.path();
+            let generated = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.split_once('-'))
+                .map_or(false, |(checksum, _)| {
+                    matches!(checksum.len(), 40 | 64 | 128)
+                        && checksum.chars().all(|c| c.is_ascii_hexdigit())
+                });
+            if generated && path.is_file() && !referenced.contains(&path) {
+                std::fs::remove_file(&path)?;
+                removed.push(path);
+            }
+        }
+        removed.sort();
+        Ok(removed)
+    }
+}
+
+impl std::fmt::Display for Repomd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            f,
+            r#"<repomd xmlns="http://linux.duke.edu/metadata/repo" xmlns:rpm="http://linux.duke.edu/metadata/rpm">"#
+        )?;
+        if let Some(revision) = &self.revision {
+            writeln!(f, "  <revision>{}</revision>", escape(revision))?;
+        }
+        if !self.tags.is_empty() {
+            writeln!(f, "  <tags>")?;
+            for tag in &self.tags {
+                match &tag.cpeid {
+                    Some(cpeid) => writeln!(
+                        f,
+                        r#"    <{name} cpeid="{}">{}</{name}>"#,
+                        escape(cpeid),
+                        escape(&tag.value),
+                        name = tag.kind
+                    )?,
+                    None => writeln!(
+                        f,
+                        "    <{name}>{}</{name}>",
+                        escape(&tag.value),
+                        name = tag.kind
+                    )?,
+                }
+            }
+            writeln!(f, "  </tags>")?;
+        }
+        for record in &self.records {
+            let checksum = |f: &mut std::fmt::Formatter<'_>, name: &str, checksum: &Checksum| {
+                writeln!(
+                    f,
+                    r#"    <{name} type="{}">{}</{name}>"#,
+                    escape(&checksum.kind),
+                    escape(&checksum.value),
+                    name = name
+                )
+            };
+            writeln!(f, r#"  <data type="{}">"#, escape(&record.kind))?;
+            checksum(f, "checksum", &record.checksum)?;
+            if let Some(open_checksum) = &record.open_checksum {
+                checksum(f, "open-checksum", open_checksum)?;
+            }
+            writeln!(f, r#"    <location href="{}"/>"#, escape(&record.location))?;
+            for (name, value) in [
+                ("timestamp", record.timestamp),
+                ("size", record.size),
+                ("open-size", record.open_size),
+            ] {
+                if let Some(value) = value {
+                    writeln!(f, "    <{name}>{}</{name}>", value, name = name)?;
+                }
+            }
+            for extra in &record.extra {
+                writeln!(f, "    {}", extra)?;
+            }
+            writeln!(f, "  </data>")?;
+        }
+        writeln!(f, "</repomd>")
+    }
+}
+
+/// A package listed in `primary.xml`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageEntry {
+    pub name: String,
+    pub epoch: u32,
+    pub version: String,
+    pub release: String,
+    pub arch: String,
+    /// Digest of the package file.
+    pub checksum: Checksum,
+    /// Path relative to the repository.
+    pub location: String,
+    /// Size of the package file.
+    pub size: Option<u64>,
+}
+
+impl PackageEntry {
+    /// `name-[epoch:]version-release.arch`, the epoch is left out if it is 0.
+    pub fn nevra(&self) -> String {
+        match self.epoch {
+            0 => format!(
+                "{}-{}-{}.{}",
+                self.name, self.version, self.release, self.arch
+            ),
+            epoch => format!(
+                "{}-{}:{}-{}.{}",
+                self.name, epoch, self.version, self.release, self.arch
+            ),
+        }
+    }
+}
+
+/// Parse the packages of a `primary.xml` document.
+pub fn parse_primary(xml: &str) -> Result<Vec<PackageEntry>, RPMError> {
+    let mut reader = Reader::new(xml);
+    let mut packages = Vec::new();
+    while let Some(event) = reader.next_event()? {
+        match event {
+            Event::Start(tag) if tag.name == "package" => {
+                packages.push(parse_package(&mut reader)?);
+            }
+            _ => {}
+        }
+    }
+    Ok(packages)
+}
+
+/// The index and the packages of a repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repodata {
+    pub repomd: Repomd,
+    pub packages: Vec<PackageEntry>,
+}
+
+impl Repodata {
+    /// Read `repodata/repomd.xml` and the primary metadata of the repository at `repo`.
+    ///
+    /// The primary metadata has to match the checksum of its record, of a type
+    /// [`Checksum::matches`] supports, and may be compressed with gzip, zstd, xz or bzip2.
+    pub fn open<P: AsRef<Path>>(repo: P) -> Result<Self, RPMError> {
+        let repo = repo.as_ref();
+        let repomd = Repomd::parse(&std::fs::read_to_string(
+            repo.join("repodata").join("repomd.xml"),
+        )?)?;
+        let primary = repomd
+            .record("primary")
+            .ok_or_else(|| invalid("repomd.xml references no primary metadata"))?;
+        let bytes = std::fs::read(repo.join(&primary.location))?;
+        match primary.checksum.matches(&bytes) {
+            Some(true) => {}
+            Some(false) => {
+                return Err(invalid(&format!(
+                    "{} does not match its {} checksum",
+                    primary.location, primary.checksum.kind
+                )))
+            }
+            None => {
+                return Err(invalid(&format!(
+                    "{} has a checksum of the unsupported type {}",
+                    primary.location, primary.checksum.kind
+                )))
+            }
+        }
+        let xml = decompress(&primary.location, bytes)?;
+        let packages = parse_primary(&xml)?;
+        Ok(Repodata { repomd, packages })
+    }
+}
+
+/// A package that differs between two snapshots of a repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackageChange {
+    Added(PackageEntry),
+    Removed(PackageEntry),
+    /// The same NEVRA with a different package file, e.g. a rebuild or a re-signed package.
+    Changed {
+        old: PackageEntry,
+        new: PackageEntry,
+    },
+}
+
+impl PackageChange {
+    /// The NEVRA of the changed package.
+    pub fn nevra(&self) -> String {
+        match self {
+            PackageChange::Added(entry)
+            | PackageChange::Removed(entry)
+            | PackageChange::Changed { new: entry, .. } => entry.nevra(),
+        }
+    }
+}
+
+impl std::fmt::Display for PackageChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackageChange::Added(entry) => write!(f, "+ {}", entry.nevra()),
+            PackageChange::Removed(entry) => write!(f, "- {}", entry.nevra()),
+            PackageChange::Changed { old, new } => write!(
+                f,
+                "~ {} {} -> {}",
+                new.nevra(),
+                old.checksum.value,
+                new.checksum.value
+            ),
+        }
+    }
+}
+
+/// The packages added, removed and changed from `old` to `new`, sorted by NEVRA.
+///
+/// Packages are matched by name, epoch, version, release and arch, and then by the
+/// checksum of the package file, so a repository may list several builds of one
+/// NEVRA. A build left without counterpart on both sides is reported as changed,
+/// packages only moved to another location are not reported.
+pub fn diff(old: &Repodata, new: &Repodata) -> Vec<PackageChange> {
+    let old = by_nevra(old);
+    let mut new = by_nevra(new);
+
+    let mut changes = Vec::new();
+    for (key, mut old_entries) in old {
+        let mut new_entries = new.remove(&key).unwrap_or_default();
+        old_entries.retain(|old_entry| {
+            match new_entries
+                .iter()
+                .position(|new_entry| same_checksum(&old_entry.checksum, &new_entry.checksum))
+            {
+                Some(idx) => {
+                    new_entries.remove(idx);
+                    false
+                }
+                None => true,
+            }
+        });
+        let mut new_entries = new_entries.into_iter();
+        for old_entry in old_entries {
+            let change = match new_entries.next() {
+                Some(new_entry) => PackageChange::Changed {
+                    old: old_entry.clone(),
+                    new: new_entry.clone(),
+                },
+                None => PackageChange::Removed(old_entry.clone()),
+            };
+            changes.push((key, change));
+        }
+        changes.extend(new_entries.map(|entry| (key, PackageChange::Added(entry.clone()))));
+    }
+    changes.extend(new.into_iter().flat_map(|(key, entries)| {
+        entries
+            .into_iter()
+            .map(move |entry| (key, PackageChange::Added(entry.clone())))
+    }));
+    changes.sort_by_key(|(key, _)| *key);
+    changes.into_iter().map(|(_, change)| change).collect()
+}
+
+/// Name, epoch, version, release and arch.
+type NevraKey<'a> = (&'a str, u32, &'a str, &'a str, &'a str);
+
+fn by_nevra(repodata: &Repodata) -> BTreeMap<NevraKey<'_>, Vec<&PackageEntry>> {
+    let mut packages = BTreeMap::<_, Vec<_>>::new();
+    for entry in repodata.packages.iter() {
+        let key = (
+            entry.name.as_str(),
+            entry.epoch,
+            entry.version.as_str(),
+            entry.release.as_str(),
+            entry.arch.as_str(),
+        );
+        packages.entry(key).or_default().push(entry);
+    }
+    packages
+}
+
+fn same_checksum(old: &Checksum, new: &Checksum) -> bool {
+    old.kind == new.kind && old.value.eq_ignore_ascii_case(&new.value)
+}
+
+fn parse_tags(reader: &mut Reader) -> Result<Vec<RepomdTag>, RPMError> {
+    let mut tags = Vec::new();
+    loop {
+        let tag = match reader.next_event()? {
+            Some(Event::Start(tag)) => tag,
+            Some(Event::End(_)) => break,
+            Some(Event::Text(_)) => continue,
+            None => return Err(invalid("unterminated tags")),
+        };
+        match tag.name {
+            "content" | "repo" | "distro" => tags.push(RepomdTag {
+                kind: tag.name.to_string(),
+                cpeid: tag.attribute("cpeid").map(str::to_string),
+                value: reader.text()?.trim().to_string(),
+            }),
+            _ => reader.skip()?,
+        }
+    }
+    Ok(tags)
+}
+
+fn parse_record(reader: &mut Reader, kind: String) -> Result<RepomdRecord, RPMError> {
+    let mut location = None;
+    let mut checksum = None;
+    let mut record = RepomdRecord {
+        kind,
+        location: String::new(),
+        checksum: Checksum {
+            kind: String::new(),
+            value: String::new(),
+        },
+        open_checksum: None,
+        size: None,
+        open_size: None,
+        timestamp: None,
+        extra: Vec::new(),
+    };
+    loop {
+        let tag = match reader.next_event()? {
+            Some(Event::Start(tag)) => tag,
+            Some(Event::End(_)) => break,
+            Some(Event::Text(_)) => continue,
+            None => return Err(invalid("unterminated data")),
+        };
+        match tag.name {
+            "location" => {
+                location = tag.attribute("href").map(str::to_string);
+                reader.skip()?;
+            }
+            "checksum" | "open-checksum" => {
+                let digest = parse_checksum(reader, tag.attribute("type"))?;
+                if tag.name == "checksum" {
+                    checksum = Some(digest);
+                } else {
+                    record.open_checksum = Some(digest);
+                }
+            }
+            "size" => record.size = Some(parse_number(reader)?),
+            "open-size" => record.open_size = Some(parse_number(reader)?),
+            "timestamp" => record.timestamp = Some(parse_number(reader)?),
+            _ => record.extra.push(reader.raw()?.to_string()),
+        }
+    }
+    record.location =
+        location.ok_or_else(|| invalid(&format!("{} data without a location", record.kind)))?;
+    record.checksum =
+        checksum.ok_or_else(|| invalid(&format!("{} data without a checksum", record.kind)))?;
+    Ok(record)
+}
+
+fn parse_package(reader: &mut Reader) -> Result<PackageEntry, RPMError> {
+    let mut name = None;
+    let mut evr = None;
+    let mut arch = None;
+    let mut checksum = None;
+    let mut location = None;
+    let mut size = None;
+    loop {
+        let tag = match reader.next_event()? {
+            Some(Event::Start(tag)) => tag,
+            Some(Event::End(_)) => break,
+            Some(Event::Text(_)) => continue,
+            None => return Err(invalid("unterminated package")),
+        };
+        match tag.name {
+            "name" => name = Some(reader.text()?.trim().to_string()),
+            "arch" => arch = Some(reader.text()?.trim().to_string()),
+            "version" => {
+                let epoch = match tag.attribute("epoch") {
+                    Some(epoch) if !epoch.is_empty() => epoch
+                        .parse()
+                        .map_err(|_| invalid(&format!("invalid epoch {:?}", epoch)))?,
+                    _ => 0,
+                };
+                evr = Some((
+                    epoch,
+                    tag.attribute("ver").unwrap_or_default().to_string(),
+                    tag.attribute("rel").unwrap_or_default().to_string(),
+                ));
+                reader.skip()?;
+            }
+            "checksum" => checksum = Some(parse_checksum(reader, tag.attribute("type"))?),
+            "location" => {
+                location = tag.attribute("href").map(str::to_string);
+                reader.skip()?;
+            }
+            "size" => {
+                size = tag.attribute("package").and_then(|size| size.parse().ok());
+                reader.skip()?;
+            }
+            _ => reader.skip()?,
+        }
+    }
+    let name = name.ok_or_else(|| invalid("package without a name"))?;
+    let missing = |what: &str| invalid(&format!("package {} without {}", name, what));
+    let (epoch, version, release) = evr.ok_or_else(|| missing("a version"))?;
+    Ok(PackageEntry {
+        epoch,
+        version,
+        release,
+        arch: arch.ok_or_else(|| missing("an arch"))?,
+        checksum: checksum.ok_or_else(|| missing("a checksum"))?,
+        location: location.ok_or_else(|| missing("a location"))?,
+        size,
+        name,
+    })
+}
+
+fn parse_checksum(reader: &mut Reader, kind: Option<&str>) -> Result<Checksum, RPMError> {
+    Ok(Checksum {
+        kind: kind
+            .ok_or_else(|| invalid("checksum without a type"))?
+            .to_string(),
+        value: reader.text()?.trim().to_ascii_lowercase(),
+    })
+}
+
+fn parse_number(reader: &mut Reader) -> Result<u64, RPMError> {
+    let text = reader.text()?;
+    text.trim()
+        .parse()
+        .map_err(|_| invalid(&format!("invalid number {:?}", text)))
+}
+
+fn decompress(location: &str, bytes: Vec<u8>) -> Result<String, RPMError> {
+    let bytes = if location.ends_with(".gz") {
+        let mut decoded = Vec::new();
+        libflate::gzip::Decoder::new(bytes.as_slice())?.read_to_end(&mut decoded)?;
+        decoded
+    } else if location.ends_with(".zst") {
+        zstd::stream::decode_all(bytes.as_slice())?
+    } else if location.ends_with(".xz") {
+        let mut decoded = Vec::new();
+        xz2::read::XzDecoder::new(bytes.as_slice())
+            .read_to_end(&mut decoded)
+            .map_err(|e| invalid(&format!("{} is not valid xz: {}", location, e)))?;
+        decoded
+    } else if location.ends_with(".bz2") {
+        let mut decoded = Vec::new();
+        bzip2_rs::DecoderReader::new(bytes.as_slice()).read_to_end(&mut decoded)?;
+        decoded
+    } else if location.ends_with(".xml") {
+        bytes
+    } else {
+        return Err(invalid(&format!(
+            "{} is compressed in an unsupported format",
+            location
+        )));
+    };
+    String::from_utf8(bytes).map_err(|_| invalid(&format!("{} is not UTF-8", location)))
+}
+
+fn invalid(reason: &str) -> RPMError {
+    RPMError::InvalidRepodata(reason.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn package_xml(name: &str, epoch: u32, version: &str, checksum: &str) -> String {
+        format!(
+            r#"<package type="rpm">
+  <name>{name}</name>
+  <arch>x86_64</arch>
+  <version epoch="{epoch}" ver="{version}" rel="1"/>
+  <checksum type="sha256" pkgid="YES">{checksum}</checksum>
+  <summary>the &lt;{name}&gt; package</summary>
+  <size package="1234" installed="4321" archive="4444"/>
+  <location href="Packages/{initial}/{name}-{version}-1.x86_64.rpm"/>
+  <format>
+    <rpm:license>MIT</rpm:license>
+    <rpm:provides><rpm:entry name="{name}" flags="EQ" epoch="{epoch}" ver="{version}" rel="1"/></rpm:provides>
+  </format>
+</package>"#,
+            name = name,
+            epoch = epoch,
+            version = version,
+            checksum = checksum,
+            initial = &name[..1],
+        )
+    }
+
+    fn write_repo(dir: &Path, packages: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let primary = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata xmlns="http://linux.duke.edu/metadata/common" xmlns:rpm="http://linux.duke.edu/metadata/rpm" packages="{}">
+{}
+</metadata>"#,
+            packages.len(),
+            packages.join("\n")
+        );
+        let mut encoder = libflate::gzip::Encoder::new(Vec::new())?;
+        encoder.write_all(primary.as_bytes())?;
+        let compressed = encoder.finish().into_result()?;
+        std::fs::create_dir_all(dir.join("repodata"))?;
+        std::fs::write(dir.join("repodata/primary.xml.gz"), &compressed)?;
+        let repomd = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<repomd xmlns="http://linux.duke.edu/metadata/repo" xmlns:rpm="http://linux.duke.edu/metadata/rpm">
+  <revision>1600000000</revision>
+  <tags>
+    <content>binary-x86_64</content>
+    <distro cpeid="cpe:/o:fedoraproject:fedora:33">Fedora 33</distro>
+  </tags>
+  <data type="primary">
+    <checksum type="sha256">{}</checksum>
+    <open-checksum type="sha256">{}</open-checksum>
+    <location href="repodata/primary.xml.gz"/>
+    <timestamp>1600000000</timestamp>
+    <size>{}</size>
+    <open-size>{}</open-size>
+  </data>
+</repomd>"#,
+            hex::encode(sha2::Sha256::digest(&compressed)),
+            hex::encode(sha2::Sha256::digest(primary.as_bytes())),
+            compressed.len(),
+            primary.len()
+        );
+        std::fs::write(dir.join("repodata/repomd.xml"), repomd)?;
+        Ok(())
+    }
+
+    #[test]
+    fn diff_repodata() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = crate::tests::TestDir::new("repodata");
+        let (a, b, c) = ("a".repeat(64), "b".repeat(64), "c".repeat(64));
+        write_repo(
+            &dir.join("old"),
+            &[
+                package_xml("bar", 0, "1.0", &a),
+                package_xml("baz", 1, "2.0", &b),
+                package_xml("foo", 0, "1.0", &c),
+            ],
+        )?;
+        write_repo(
+            &dir.join("new"),
+            &[
+                package_xml("bar", 0, "1.0", &a),
+                package_xml("baz", 1, "2.0", &c),
+                package_xml("foo", 0, "1.1", &a),
+            ],
+        )?;
+
+        let old = Repodata::open(dir.join("old"))?;
+        assert_eq!(Some("1600000000"), old.repomd.revision.as_deref());
+        let primary = old.repomd.record("primary").unwrap();
+        assert_eq!("repodata/primary.xml.gz", primary.location);
+        assert_eq!(Some(1600000000), primary.timestamp);
+        assert_eq!(
+            PackageEntry {
+                name: "baz".to_string(),
+                epoch: 1,
+                version: "2.0".to_string(),
+                release: "1".to_string(),
+                arch: "x86_64".to_string(),
+                checksum: Checksum {
+                    kind: "sha256".to_string(),
+                    value: b.clone(),
+                },
+                location: "Packages/b/baz-2.0-1.x86_64.rpm".to_string(),
+                size: Some(1234),
+            },
+            old.packages[1]
+        );
+        assert_eq!("baz-1:2.0-1.x86_64", old.packages[1].nevra());
+
+        let new = Repodata::open(dir.join("new"))?;
+        let changes = diff(&old, &new)
+            .iter()
+            .map(|change| change.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                format!("~ baz-1:2.0-1.x86_64 {} -> {}", b, c),
+                "- foo-1.0-1.x86_64".to_string(),
+                "+ foo-1.1-1.x86_64".to_string(),
+            ],
+            changes
+        );
+        assert!(diff(&new, &new).is_empty());
+
+        // two builds of the same NEVRA, one of them replaced
+        let mut rebuilt = new.clone();
+        rebuilt.packages.push(PackageEntry {
+            checksum: Checksum {
+                kind: "sha256".to_string(),
+                value: b.clone(),
+            },
+            ..new.packages[0].clone()
+        });
+        assert_eq!(
+            vec!["+ bar-1.0-1.x86_64".to_string()],
+            diff(&new, &rebuilt)
+                .iter()
+                .map(|change| change.to_string())
+                .collect::<Vec<_>>()
+        );
+        let mut replaced = rebuilt.clone();
+        replaced.packages[3].checksum.value = c.clone();
+        assert_eq!(
+            vec![format!("~ bar-1.0-1.x86_64 {} -> {}", b, c)],
+            diff(&rebuilt, &replaced)
+                .iter()
+                .map(|change| change.to_string())
+                .collect::<Vec<_>>()
+        );
+
+        let modules = b"---\ndocument: modulemd\nversion: 2\n...\n";
+        let mut repomd = Repomd::open(dir.join("new"))?;
+        let record = repomd
+            .attach(
+                dir.join("new"),
+                "modules",
+                "modules.yaml",
+                modules,
+                Compressor::from_str("gzip")?,
+            )?
+            .clone();
+        assert!(record.location.ends_with("-modules.yaml.gz"));
+        assert_eq!(Some(modules.len() as u64), record.open_size);
+        repomd.write(dir.join("new"))?;
+
+        let reopened = Repodata::open(dir.join("new"))?;
+        assert_eq!(repomd, reopened.repomd);
+        assert_eq!(
+            Some("cpe:/o:fedoraproject:fedora:33"),
+            reopened.repomd.tags[1].cpeid.as_deref()
+        );
+        assert_eq!(new.packages, reopened.packages);
+        assert_eq!(Some(&record), reopened.repomd.record("modules"));
+        let attached = std::fs::read(dir.join("new").join(&record.location))?;
+        assert_eq!(Some(true), record.checksum.matches(&attached));
+        assert_eq!(
+            modules.to_vec(),
+            decompress(&record.location, attached)?.into_bytes()
+        );
+
+        // the superseded file stays until pruned
+        let superseded = dir.join("new").join(&record.location);
+        repomd.attach(
+            dir.join("new"),
+            "modules",
+            "modules.yaml",
+            b"---\ndocument: modulemd\nversion: 3\n...\n",
+            Compressor::from_str("gzip")?,
+        )?;
+        repomd.write(dir.join("new"))?;
+        assert!(superseded.exists());
+        assert_eq!(vec![superseded], repomd.prune(dir.join("new"))?);
+        assert!(repomd.prune(dir.join("new"))?.is_empty());
+        Repodata::open(dir.join("new"))?;
+
+        let repomd_path = dir.join("new/repodata/repomd.xml");
+        let repomd_xml = std::fs::read_to_string(&repomd_path)?;
+        std::fs::write(
+            &repomd_path,
+            repomd_xml.replacen(r#"<checksum type="sha256">"#, r#"<checksum type="md4">"#, 1),
+        )?;
+        assert!(matches!(
+            Repodata::open(dir.join("new")),
+            Err(RPMError::InvalidRepodata(reason)) if reason.contains("unsupported type md4")
+        ));
+        std::fs::write(&repomd_path, repomd_xml)?;
+
+        std::fs::write(dir.join("new/repodata/primary.xml.gz"), b"garbage")?;
+        assert!(matches!(
+            Repodata::open(dir.join("new")),
+            Err(RPMError::InvalidRepodata(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn keep_unknown_record_children() -> Result<(), Box<dyn std::error::Error>> {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<repomd xmlns="http://linux.duke.edu/metadata/repo">
+  <data type="primary_db">
+    <checksum type="sha256">aa</checksum>
+    <location href="repodata/aa-primary.sqlite.bz2"/>
+    <database_version>10</database_version>
+  </data>
+  <data type="primary_zck">
+    <checksum type="sha256">bb</checksum>
+    <location href="repodata/bb-primary.xml.zck"/>
+    <header-checksum type="sha256">cc</header-checksum>
+    <header-size>1024</header-size>
+  </data>
+</repomd>"#;
+        let repomd = Repomd::parse(xml)?;
+        assert_eq!(
+            vec!["<database_version>10</database_version>".to_string()],
+            repomd.records[0].extra
+        );
+        assert_eq!(
+            vec![
+                r#"<header-checksum type="sha256">cc</header-checksum>"#.to_string(),
+                "<header-size>1024</header-size>".to_string(),
+            ],
+            repomd.records[1].extra
+        );
+
+        let dir = crate::tests::TestDir::new("repomd-extra");
+        repomd.write(&dir)?;
+        assert_eq!(repomd, Repomd::open(&dir)?);
+        // nothing but the index is left behind
+        let names = std::fs::read_dir(dir.join("repodata"))?
+            .map(|entry| Ok(entry?.file_name()))
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        assert_eq!(vec![std::ffi::OsString::from("repomd.xml")], names);
+        Ok(())
+    }
+
+    #[test]
+    fn decompress_formats() -> Result<(), Box<dyn std::error::Error>> {
+        let xml = "<metadata packages=\"0\"/>\n";
+        let xz = hex::decode(
+            "fd377a585a000004e6d6b4460200210116000000742fe5a30100183c6d657461646174612070616\
+             36b616765733d2230222f3e0a000000005e64cfdaace931a000013119591ab0821fb6f37d0100000000\
+             04595a",
+        )?;
+        let bz2 = hex::decode(
+            "425a6839314159265359977d3a07000003598000105000c0072e8a4c0020002226834f500f28534c\
+             8c4c4c4b8810d621273dcb15324aabfc5dc914e142425df4e81c",
+        )?;
+        assert_eq!(xml, decompress("primary.xml.xz", xz)?);
+        assert_eq!(xml, decompress("primary.xml.bz2", bz2)?);
+        assert_eq!(xml, decompress("primary.xml", xml.as_bytes().to_vec())?);
+        assert!(decompress("primary.xml.xz", b"garbage".to_vec()).is_err());
+        assert!(decompress("primary.xml.lz4", Vec::new()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,447 @@
+//! Advisories published as `updateinfo.xml`, read by `dnf updateinfo`.
+
+use std::path::Path;
+
+use crate::errors::*;
+
+use super::xml::escape;
+use super::{Compressor, PackageEntry, Repomd, RepomdRecord};
+
+/// What an advisory is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdvisoryKind {
+    Security,
+    Bugfix,
+    Enhancement,
+    NewPackage,
+}
+
+impl std::fmt::Display for AdvisoryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AdvisoryKind::Security => "security",
+            AdvisoryKind::Bugfix => "bugfix",
+            AdvisoryKind::Enhancement => "enhancement",
+            AdvisoryKind::NewPackage => "newpackage",
+        })
+    }
+}
+
+/// Severity of an advisory, as used by `dnf updateinfo --sec-severity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Low,
+    Moderate,
+    Important,
+    Critical,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Low => "Low",
+            Severity::Moderate => "Moderate",
+            Severity::Important => "Important",
+            Severity::Critical => "Critical",
+        })
+    }
+}
+
+/// A link from an advisory, e.g. to a CVE or a bug tracker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    href: String,
+    id: String,
+    kind: String,
+    title: String,
+}
+
+impl Reference {
+    /// A reference of `kind`, e.g. `cve`, `bugzilla` or `self`.
+    pub fn new<I: Into<String>, H: Into<String>>(kind: &str, id: I, href: H) -> Self {
+        let id = id.into();
+        Reference {
+            href: href.into(),
+            title: id.clone(),
+            id,
+            kind: kind.to_string(),
+        }
+    }
+
+    /// A reference to `cve` on the CVE list, e.g. `CVE-2021-44228`.
+    pub fn cve(cve: &str) -> Self {
+        Self::new(
+            "cve",
+            cve,
+            format!("https://www.cve.org/CVERecord?id={}", cve),
+        )
+    }
+
+    /// Defaults to the id.
+    pub fn title<T: Into<String>>(mut self, title: T) -> Self {
+        self.title = title.into();
+        self
+    }
+}
+
+/// A package fixing the issues of an advisory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AffectedPackage {
+    name: String,
+    epoch: u32,
+    version: String,
+    release: String,
+    arch: String,
+    filename: String,
+    reboot_suggested: bool,
+}
+
+impl AffectedPackage {
+    pub fn new(name: &str, epoch: u32, version: &str, release: &str, arch: &str) -> Self {
+        AffectedPackage {
+            name: name.to_string(),
+            epoch,
+            version: version.to_string(),
+            release: release.to_string(),
+            arch: arch.to_string(),
+            filename: format!("{}-{}-{}.{}.rpm", name, version, release, arch),
+            reboot_suggested: false,
+        }
+    }
+
+    /// Parse `name-[epoch:]version-release.arch`, e.g. `foo-1:1.2-3.el8.x86_64`.
+    pub fn from_nevra(nevra: &str) -> Result<Self, RPMError> {
+        let invalid = || RPMError::InvalidRepodata(format!("invalid NEVRA {:?}", nevra));
+        let (rest, arch) = nevra.rsplit_once('.').ok_or_else(invalid)?;
+        let (rest, release) = rest.rsplit_once('-').ok_or_else(invalid)?;
+        let (name, epoch_version) = rest.rsplit_once('-').ok_or_else(invalid)?;
+        let (epoch, version) = match epoch_version.split_once(':') {
+            Some((epoch, version)) => (epoch.parse().map_err(|_| invalid())?, version),
+            None => (0, epoch_version),
+        };
+        if [name, version, release, arch]
+            .iter()
+            .any(|part| part.is_empty())
+        {
+            return Err(invalid());
+        }
+        Ok(Self::new(name, epoch, version, release, arch))
+    }
+
+    /// The package as listed in the primary metadata of a repository.
+    pub fn from_entry(entry: &PackageEntry) -> Self {
+        let mut package = Self::new(
+            &entry.name,
+            entry.epoch,
+            &entry.version,
+            &entry.release,
+            &entry.arch,
+        );
+        if let Some(filename) = entry.location.rsplit('/').next() {
+            package.filename = filename.to_string();
+        }
+        package
+    }
+
+    pub fn reboot_suggested(mut self, reboot: bool) -> Self {
+        self.reboot_suggested = reboot;
+        self
+    }
+}
+
+/// An erratum for a set of packages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Advisory {
+    id: String,
+    kind: AdvisoryKind,
+    title: String,
+    from: Option<String>,
+    status: String,
+    severity: Option<Severity>,
+    issued: chrono::DateTime<chrono::Utc>,
+    updated: Option<chrono::DateTime<chrono::Utc>>,
+    release: Option<String>,
+    summary: Option<String>,
+    description: String,
+    references: Vec<Reference>,
+    collection: String,
+    packages: Vec<AffectedPackage>,
+}
+
+impl Advisory {
+    /// An advisory issued now with status `stable`.
+    pub fn new<T: Into<String>>(id: T, kind: AdvisoryKind, title: T) -> Self {
+        Advisory {
+            id: id.into(),
+            kind,
+            title: title.into(),
+            from: None,
+            status: "stable".to_string(),
+            severity: None,
+            issued: chrono::Utc::now(),
+            updated: None,
+            release: None,
+            summary: None,
+            description: String::new(),
+            references: Vec::new(),
+            collection: "default".to_string(),
+            packages: Vec::new(),
+        }
+    }
+
+    /// Contact of the issuer, e.g. `security@example.com`.
+    pub fn from<T: Into<String>>(mut self, from: T) -> Self {
+        self.from = Some(from.into());
+        self
+    }
+
+    /// E.g. `stable`, `testing` or `final`.
+    pub fn status<T: Into<String>>(mut self, status: T) -> Self {
+        self.status = status.into();
+        self
+    }
+
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.severity = Some(severity);
+        self
+    }
+
+    pub fn issued(mut self, issued: chrono::DateTime<chrono::Utc>) -> Self {
+        self.issued = issued;
+        self
+    }
+
+    pub fn updated(mut self, updated: chrono::DateTime<chrono::Utc>) -> Self {
+        self.updated = Some(updated);
+        self
+    }
+
+    /// The release the advisory applies to, e.g. `Fedora 38`.
+    pub fn release<T: Into<String>>(mut self, release: T) -> Self {
+        self.release = Some(release.into());
+        self
+    }
+
+    pub fn summary<T: Into<String>>(mut self, summary: T) -> Self {
+        self.summary = Some(summary.into());
+        self
+    }
+
+    pub fn description<T: Into<String>>(mut self, description: T) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    pub fn reference(mut self, reference: Reference) -> Self {
+        self.references.push(reference);
+        self
+    }
+
+    /// Name of the collection the packages are listed in, defaults to `default`.
+    pub fn collection<T: Into<String>>(mut self, collection: T) -> Self {
+        self.collection = collection.into();
+        self
+    }
+
+    pub fn package(mut self, package: AffectedPackage) -> Self {
+        self.packages.push(package);
+        self
+    }
+
+    fn write_xml(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let date = |date: &chrono::DateTime<chrono::Utc>| date.format("%Y-%m-%d %H:%M:%S");
+        let element = |f: &mut std::fmt::Formatter<'_>, name: &str, value: &str| {
+            writeln!(f, "    <{name}>{}</{name}>", escape(value), name = name)
+        };
+
+        write!(f, "  <update")?;
+        if let Some(from) = &self.from {
+            write!(f, r#" from="{}""#, escape(from))?;
+        }
+        writeln!(
+            f,
+            r#" status="{}" type="{}" version="2.0">"#,
+            escape(&self.status),
+            self.kind
+        )?;
+        element(f, "id", &self.id)?;
+        element(f, "title", &self.title)?;
+        writeln!(f, r#"    <issued date="{}"/>"#, date(&self.issued))?;
+        if let Some(updated) = &self.updated {
+            writeln!(f, r#"    <updated date="{}"/>"#, date(updated))?;
+        }
+        if let Some(release) = &self.release {
+            element(f, "release", release)?;
+        }
+        if let Some(severity) = self.severity {
+            element(f, "severity", &severity.to_string())?;
+        }
+        if let Some(summary) = &self.summary {
+            element(f, "summary", summary)?;
+        }
+        element(f, "description", &self.description)?;
+
+        writeln!(f, "    <references>")?;
+        for reference in &self.references {
+            writeln!(
+                f,
+                r#"      <reference href="{}" id="{}" type="{}" title="{}"/>"#,
+                escape(&reference.href),
+                escape(&reference.id),
+                escape(&reference.kind),
+                escape(&reference.title)
+            )?;
+        }
+        writeln!(f, "    </references>")?;
+
+        writeln!(f, "    <pkglist>")?;
+        writeln!(
+            f,
+            r#"      <collection short="{0}">
+        <name>{0}</name>"#,
+            escape(&self.collection)
+        )?;
+        for package in &self.packages {
+            writeln!(
+                f,
+                r#"        <package name="{}" version="{}" release="{}" epoch="{}" arch="{}">
+          <filename>{}</filename>"#,
+                escape(&package.name),
+                escape(&package.version),
+                escape(&package.release),
+                package.epoch,
+                escape(&package.arch),
+                escape(&package.filename)
+            )?;
+            if package.reboot_suggested {
+                writeln!(f, "          <reboot_suggested>True</reboot_suggested>")?;
+            }
+            writeln!(f, "        </package>")?;
+        }
+        writeln!(f, "      </collection>")?;
+        writeln!(f, "    </pkglist>")?;
+        writeln!(f, "  </update>")
+    }
+}
+
+/// The advisories of a repository, rendered as `updateinfo.xml` through
+/// [`Display`](std::fmt::Display).
+///
+/// ```rust,no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use rpm::repodata::*;
+/// use std::str::FromStr;
+///
+/// let repo = "/srv/mirror/el8/x86_64";
+/// let updates = UpdateInfo::new().advisory(
+///     Advisory::new("FEZ-2023-0001", AdvisoryKind::Security, "foo security update")
+///         .severity(Severity::Important)
+///         .description("Fixes a buffer overflow in the parser.")
+///         .reference(Reference::cve("CVE-2023-12345"))
+///         .package(AffectedPackage::from_nevra("foo-1.2-3.el8.x86_64")?),
+/// );
+/// let mut repomd = Repomd::open(repo)?;
+/// updates.attach(repo, &mut repomd, rpm::Compressor::from_str("gzip")?)?;
+/// repomd.write(repo)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateInfo {
+    advisories: Vec<Advisory>,
+}
+
+impl UpdateInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advisory(mut self, advisory: Advisory) -> Self {
+        self.advisories.push(advisory);
+        self
+    }
+
+    /// Write `updateinfo.xml` into the repodata of `repo` and reference it in `repomd`,
+    /// see [`Repomd::attach`].
+    pub fn attach<P: AsRef<Path>>(
+        &self,
+        repo: P,
+        repomd: &mut Repomd,
+        compressor: Compressor,
+    ) -> Result<RepomdRecord, RPMError> {
+        repomd
+            .attach(
+                repo,
+                "updateinfo",
+                "updateinfo.xml",
+                self.to_string().as_bytes(),
+                compressor,
+            )
+            .cloned()
+    }
+}
+
+impl std::fmt::Display for UpdateInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(f, "<updates>")?;
+        for advisory in &self.advisories {
+            advisory.write_xml(f)?;
+        }
+        writeln!(f, "</updates>")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn render_updateinfo() -> Result<(), Box<dyn std::error::Error>> {
+        let issued = chrono::Utc.with_ymd_and_hms(2023, 5, 17, 12, 0, 0).unwrap();
+        let updates = UpdateInfo::new().advisory(
+            Advisory::new("FEZ-2023-0001", AdvisoryKind::Security, "foo & bar update")
+                .from("security@example.com")
+                .severity(Severity::Important)
+                .issued(issued)
+                .description("Fixes <CVE-2023-12345>.")
+                .reference(Reference::cve("CVE-2023-12345"))
+                .package(
+                    AffectedPackage::from_nevra("foo-1:1.2-3.el8.x86_64")?.reboot_suggested(true),
+                ),
+        );
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<updates>
+  <update from="security@example.com" status="stable" type="security" version="2.0">
+    <id>FEZ-2023-0001</id>
+    <title>foo &amp; bar update</title>
+    <issued date="2023-05-17 12:00:00"/>
+    <severity>Important</severity>
+    <description>Fixes &lt;CVE-2023-12345&gt;.</description>
+    <references>
+      <reference href="https://www.cve.org/CVERecord?id=CVE-2023-12345" id="CVE-2023-12345" type="cve" title="CVE-2023-12345"/>
+    </references>
+    <pkglist>
+      <collection short="default">
+        <name>default</name>
+        <package name="foo" version="1.2" release="3.el8" epoch="1" arch="x86_64">
+          <filename>foo-1.2-3.el8.x86_64.rpm</filename>
+          <reboot_suggested>True</reboot_suggested>
+        </package>
+      </collection>
+    </pkglist>
+  </update>
+</updates>
+"#;
+        assert_eq!(expected, updates.to_string());
+
+        assert_eq!(
+            AffectedPackage::new("python3-foo", 0, "2.0", "1", "noarch"),
+            AffectedPackage::from_nevra("python3-foo-2.0-1.noarch")?
+        );
+        assert!(AffectedPackage::from_nevra("foo.x86_64").is_err());
+        assert!(AffectedPackage::from_nevra("foo-x:1-2.noarch").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+//! Checking downloaded packages against the repodata listing them.
+
+use std::io::Read;
+
+use crate::errors::*;
+#[cfg(feature = "signature-meta")]
+use crate::signature;
+use crate::RPMPackage;
+#[cfg(feature = "signature-meta")]
+use crate::VerifyOptions;
+
+use super::PackageEntry;
+
+/// Read the package `entry` describes from `reader` and check it has the size and
+/// checksum listed in the repodata.
+///
+/// Nothing is parsed before the checksum matched, packages with a checksum type
+/// that is not supported are rejected.
+pub fn verify_package<R: Read>(
+    entry: &PackageEntry,
+    mut reader: R,
+) -> Result<RPMPackage, RPMError> {
+    let mismatch = |reason: String| RPMError::RepodataMismatch {
+        location: entry.location.clone(),
+        reason,
+    };
+    // the listed size is untrusted, read one byte past it rather than reserving it
+    let mut bytes = Vec::new();
+    match entry.size {
+        Some(size) => {
+            reader
+                .take(size.saturating_add(1))
+                .read_to_end(&mut bytes)?;
+            if bytes.len() as u64 > size {
+                return Err(mismatch(format!("more than {} bytes", size)));
+            }
+            if bytes.len() as u64 != size {
+                return Err(mismatch(format!(
+                    "{} bytes instead of {}",
+                    bytes.len(),
+                    size
+                )));
+            }
+        }
+        None => {
+            reader.read_to_end(&mut bytes)?;
+        }
+    }
+    match entry.checksum.matches(&bytes) {
+        Some(true) => {}
+        Some(false) => {
+            return Err(mismatch(format!(
+                "{} checksum differs",
+                entry.checksum.kind
+            )))
+        }
+        None => {
+            return Err(mismatch(format!(
+                "checksum type {} is not supported",
+                entry.checksum.kind
+            )))
+        }
+    }
+    RPMPackage::parse(&mut bytes.as_slice())
+}
+
+/// Like [`verify_package`], then verify the signature of the package with the keys of
+/// `keyring`, of which one has to match.
+///
+/// The error of the last key is returned if none of them matches.
+#[cfg(feature = "signature-meta")]
+pub fn verify_signed_package<R, V>(
+    entry: &PackageEntry,
+    reader: R,
+    keyring: &[V],
+) -> Result<RPMPackage, RPMError>
+where
+    R: Read,
+    V: signature::Verifying<signature::algorithm::RSA>,
+{
+    verify_signed_package_with(entry, reader, keyring, &VerifyOptions::new())
+}
+
+/// Like [`verify_signed_package`], verifying as configured in `options`, e.g. only
+/// with the keys a [`TrustStore`](crate::TrustStore) trusts for the repository.
+///
+/// Errors of keys that verified nothing are preferred over
+/// [`RPMError::UntrustedKey`] of keys skipped as untrusted.
+#[cfg(feature = "signature-meta")]
+pub fn verify_signed_package_with<R, V>(
+    entry: &PackageEntry,
+    reader: R,
+    keyring: &[V],
+    options: &VerifyOptions<'_>,
+) -> Result<RPMPackage, RPMError>
+where
+    R: Read,
+    V: signature::Verifying<signature::algorithm::RSA>,
+{
+    let package = verify_package(entry, reader)?;
+    let mut error = RPMError::RepodataMismatch {
+        location: entry.location.clone(),
+        reason: "no keys to verify the signature with".to_string(),
+    };
+    for verifier in keyring {
+        match package.verify_signature_with(verifier, options) {
+            Ok(()) => return Ok(package),
+            Err(err @ RPMError::UntrustedKey { .. }) => {
+                if matches!(error, RPMError::RepodataMismatch { .. }) {
+                    error = err;
+                }
+            }
+            Err(err) => error = err,
+        }
+    }
+    Err(error)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repodata::Checksum;
+    use crate::RPMBuilder;
+    use sha2::Digest;
+
+    fn entry(bytes: &[u8]) -> PackageEntry {
+        PackageEntry {
+            name: "foo".to_string(),
+            epoch: 0,
+            version: "1.0.0".to_string(),
+            release: "1".to_string(),
+            arch: "noarch".to_string(),
+            checksum: Checksum {
+                kind: "sha256".to_string(),
+                value: hex::encode(sha2::Sha256::digest(bytes)),
+            },
+            location: "Packages/f/foo-1.0.0-1.noarch.rpm".to_string(),
+            size: Some(bytes.len() as u64),
+        }
+    }
+
+    #[test]
+    fn verify_downloaded_package() -> Result<(), Box<dyn std::error::Error>> {
+        let builder = || RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "");
+        let mut bytes = Vec::new();
+        builder().build()?.write(&mut bytes)?;
+        let listed = entry(&bytes);
+        assert_eq!(
+            "foo",
+            verify_package(&listed, bytes.as_slice())?
+                .metadata
+                .header
+                .get_name()?
+        );
+
+        let mut truncated = listed.clone();
+        truncated.size = Some(bytes.len() as u64 - 1);
+        assert!(matches!(
+            verify_package(&truncated, bytes.as_slice()),
+            Err(RPMError::RepodataMismatch { .. })
+        ));
+        let mut tampered = bytes.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            verify_package(&listed, tampered.as_slice()),
+            Err(RPMError::RepodataMismatch { .. })
+        ));
+        // neither the listed size nor the reader are trusted
+        let mut huge = listed.clone();
+        huge.size = Some(u64::MAX);
+        assert!(matches!(
+            verify_package(&huge, bytes.as_slice()),
+            Err(RPMError::RepodataMismatch { .. })
+        ));
+        assert!(matches!(
+            verify_package(&listed, std::io::repeat(0)),
+            Err(RPMError::RepodataMismatch { .. })
+        ));
+        let mut md5 = listed.clone();
+        md5.checksum.kind = "md5".to_string();
+        assert!(verify_package(&md5, bytes.as_slice()).is_err());
+
+        #[cfg(feature = "signature-pgp")]
+        {
+            use crate::signature::pgp::{Signer, Verifier};
+            let (signing_key, verification_key) = crate::signature::pgp::test::load_asc_keys();
+            let signer = Signer::load_from_asc_bytes(signing_key.as_ref())?;
+            let verifier = Verifier::load_from_asc_bytes(verification_key.as_ref())?;
+            let mut signed = Vec::new();
+            builder().build_and_sign(&signer)?.write(&mut signed)?;
+            let listed = entry(&signed);
+            verify_signed_package(&listed, signed.as_slice(), &[verifier])?;
+            // signature checks fail on unsigned packages
+            assert!(verify_signed_package(
+                &entry(&bytes),
+                bytes.as_slice(),
+                &[Verifier::load_from_asc_bytes(verification_key.as_ref())?]
+            )
+            .is_err());
+            assert!(verify_signed_package::<_, Verifier>(&listed, signed.as_slice(), &[]).is_err());
+
+            let mut store = crate::TrustStore::new();
+            let keyring = [Verifier::load_from_asc_bytes(verification_key.as_ref())?];
+            assert!(matches!(
+                verify_signed_package_with(
+                    &listed,
+                    signed.as_slice(),
+                    &keyring,
+                    &VerifyOptions::new().trust(&store, "updates")
+                ),
+                Err(RPMError::UntrustedKey { .. })
+            ));
+            store.trust(&keyring[0].fingerprint())?;
+            verify_signed_package_with(
+                &listed,
+                signed.as_slice(),
+                &keyring,
+                &VerifyOptions::new().trust(&store, "updates"),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+//! Just enough XML to read repodata: elements, attributes, text and the
+//! predefined entities. Declarations, comments and doctypes are skipped.
+
+use std::borrow::Cow;
+
+use crate::errors::*;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Event<'a> {
+    /// An opening tag, empty elements are followed by a matching [`Event::End`].
+    Start(Tag<'a>),
+    End(&'a str),
+    Text(Cow<'a, str>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Tag<'a> {
+    pub(crate) name: &'a str,
+    attributes: Vec<(&'a str, Cow<'a, str>)>,
+}
+
+impl<'a> Tag<'a> {
+    pub(crate) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| *attribute == name)
+            .map(|(_, value)| value.as_ref())
+    }
+}
+
+/// Pull parser over a complete document.
+pub(crate) struct Reader<'a> {
+    xml: &'a str,
+    position: usize,
+    /// Where the tag the last [`Event::Start`] stems from begins.
+    start: usize,
+    /// Name of the empty element the last event opened.
+    pending_end: Option<&'a str>,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(xml: &'a str) -> Self {
+        Reader {
+            xml,
+            position: 0,
+            start: 0,
+            pending_end: None,
+        }
+    }
+
+    /// The next event, `None` at the end of the document.
+    pub(crate) fn next_event(&mut self) -> Result<Option<Event<'a>>, RPMError> {
+        if let Some(name) = self.pending_end.take() {
+            return Ok(Some(Event::End(name)));
+        }
+        loop {
+            let rest = &self.xml[self.position..];
+            if rest.is_empty() {
+                return Ok(None);
+            }
+            if !rest.starts_with('<') {
+                let end = rest.find('<').unwrap_or(rest.len());
+                self.position += end;
+                return Ok(Some(Event::Text(unescape(&rest[..end])?)));
+            }
+            if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+                let end = self.find(cdata, "]]>")?;
+                self.position += "<![CDATA[".len() + end + "]]>".len();
+                return Ok(Some(Event::Text(Cow::Borrowed(&cdata[..end]))));
+            }
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                self.position += "<!--".len() + self.find(comment, "-->")? + "-->".len();
+                continue;
+            }
+            if rest.starts_with("<?") || rest.starts_with("<!") {
+                self.position += self.find(rest, ">")? + 1;
+                continue;
+            }
+
+            let end = self.find(rest, ">")?;
+            self.start = self.position;
+            self.position += end + 1;
+            let tag = &rest[1..end];
+            if let Some(name) = tag.strip_prefix('/') {
+                return Ok(Some(Event::End(name.trim())));
+            }
+            let (tag, empty) = match tag.strip_suffix('/') {
+                Some(tag) => (tag, true),
+                None => (tag, false),
+            };
+            let tag = parse_tag(tag)?;
+            if empty {
+                self.pending_end = Some(tag.name);
+            }
+            return Ok(Some(Event::Start(tag)));
+        }
+    }
+
+    /// The text up to the end of the element just opened, skipping nested elements.
+    pub(crate) fn text(&mut self) -> Result<String, RPMError> {
+        let mut text = String::new();
+        let mut depth = 0;
+        while let Some(event) = self.next_event()? {
+            match event {
+                Event::Text(content) if depth == 0 => text.push_str(&content),
+                Event::Text(_) => {}
+                Event::Start(_) => depth += 1,
+                Event::End(_) if depth == 0 => return Ok(text),
+                Event::End(_) => depth -= 1,
+            }
+        }
+        Err(invalid("unexpected end of document"))
+    }
+
+    /// Skip to the end of the element just opened.
+    pub(crate) fn skip(&mut self) -> Result<(), RPMError> {
+        self.text().map(|_| ())
+    }
+
+    /// Skip to the end of the element just opened, returning its markup verbatim.
+    pub(crate) fn raw(&mut self) -> Result<&'a str, RPMError> {
+        let start = self.start;
+        self.skip()?;
+        Ok(&self.xml[start..self.position])
+    }
+
+    fn find(&self, haystack: &str, needle: &str) -> Result<usize, RPMError> {
+        haystack.find(needle).ok_or_else(|| {
+            invalid(&format!(
+                "unterminated markup at byte {}, expected {:?}",
+                self.position, needle
+            ))
+        })
+    }
+}
+
+fn parse_tag(tag: &str) -> Result<Tag<'_>, RPMError> {
+    let name_end = tag
+        .find(|c: char| c.is_ascii_whitespace())
+        .unwrap_or(tag.len());
+    let name = &tag[..name_end];
+    if name.is_empty() {
+        return Err(invalid("element without a name"));
+    }
+    let mut attributes = Vec::new();
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let equals = rest
+            .find('=')
+            .ok_or_else(|| invalid(&format!("attribute without a value in <{}>", tag)))?;
+        let attribute = rest[..equals].trim();
+        let value = rest[equals + 1..].trim_start();
+        let quote = match value.chars().next() {
+            Some(quote @ '"') | Some(quote @ '\'') => quote,
+            _ => return Err(invalid(&format!("unquoted attribute in <{}>", tag))),
+        };
+        let end = value[1..]
+            .find(quote)
+            .ok_or_else(|| invalid(&format!("unterminated attribute in <{}>", tag)))?;
+        attributes.push((attribute, unescape(&value[1..1 + end])?));
+        rest = value[end + 2..].trim_start();
+    }
+    Ok(Tag { name, attributes })
+}
+
+fn unescape(text: &str) -> Result<Cow<'_, str>, RPMError> {
+    if !text.contains('&') {
+        return Ok(Cow::Borrowed(text));
+    }
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .ok_or_else(|| invalid("unterminated entity"))?;
+        let entity = &rest[start + 1..start + end];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity.strip_prefix("#x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+            }
+            .and_then(char::from_u32),
+        };
+        unescaped.push(character.ok_or_else(|| invalid(&format!("unknown entity &{};", entity)))?);
+        rest = &rest[start + end + 1..];
+    }
+    unescaped.push_str(rest);
+    Ok(Cow::Owned(unescaped))
+}
+
+/// Escape `text` for element content and attribute values.
+pub(crate) fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+fn invalid(reason: &str) -> RPMError {
+    RPMError::InvalidRepodata(reason.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_events() -> Result<(), Box<dyn std::error::Error>> {
+        let mut reader = Reader::new(
+            r#"<?xml version="1.0"?>
+<!-- comment --><a x="1 &amp; 2" y='&#x41;'><b/>t&lt;&#65;<![CDATA[<raw>]]></a>"#,
+        );
+        let mut events = Vec::new();
+        while let Some(event) = reader.next_event()? {
+            events.push(event);
+        }
+        assert_eq!(7, events.len());
+        match &events[1] {
+            Event::Start(tag) => {
+                assert_eq!("a", tag.name);
+                assert_eq!(Some("1 & 2"), tag.attribute("x"));
+                assert_eq!(Some("A"), tag.attribute("y"));
+                assert_eq!(None, tag.attribute("z"));
+            }
+            other => panic!("expected <a>, got {:?}", other),
+        }
+        assert_eq!(Event::End("b"), events[3]);
+        assert_eq!(Event::Text(Cow::Borrowed("t<A")), events[4]);
+        assert_eq!(Event::Text(Cow::Borrowed("<raw>")), events[5]);
+
+        let mut reader = Reader::new("<a><b>x<c>y</c>z</b></a>");
+        reader.next_event()?;
+        reader.next_event()?;
+        assert_eq!("xz", reader.text()?);
+        assert_eq!(Some(Event::End("a")), reader.next_event()?);
+
+        let escaped = escape("<a & 'b'>");
+        assert_eq!("&lt;a &amp; &apos;b&apos;&gt;", escaped);
+        assert_eq!(unescape(&escaped)?, "<a & 'b'>");
+
+        assert!(Reader::new("<a x=1>").next_event().is_err());
+        assert!(Reader::new("a &bogus; b").next_event().is_err());
+        assert!(Reader::new("<a").next_event().is_err());
+        Ok(())
+    }
+}
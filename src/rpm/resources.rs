@@ -0,0 +1,181 @@
+//! The sizes of the buffers building and parsing held, to size constrained build agents.
+//!
+//! Building, parsing and [`RPMPackage::payload_entries_with_diagnostics`] record into
+//! the [`Diagnostics`] they are given, [`RPMPackage::resources`] reports the buffers
+//! a package holds.
+//!
+//! ```rust
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut diagnostics = rpm::Diagnostics::new();
+//! let pkg = rpm::RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo")
+//!     .with_file(
+//!         "./test_assets/awesome.toml",
+//!         rpm::RPMFileOptions::new("/etc/awesome/config.toml"),
+//!     )?
+//!     .build_with_diagnostics(&mut diagnostics)?;
+//! let report = diagnostics.resources();
+//! assert!(report.staged_content > 0);
+//! report.check(64 * 1024 * 1024)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::errors::*;
+
+use super::diagnostics::Diagnostics;
+use super::RPMPackage;
+
+/// The most bytes each buffer of the builds and reads recorded was allocated, zero
+/// for those that did not happen.
+///
+/// The buffers only grow, so their final capacity is their high-water mark, spare
+/// capacity included. Only buffers of the package itself are counted, not the working
+/// memory of the compressors nor allocator overhead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceReport {
+    /// Serialized header, or the signature header if it is larger.
+    pub header_store: u64,
+    /// Content of the files staged in a builder, which is held until it is built.
+    pub staged_content: u64,
+    /// Segments of the uncompressed cpio archive, buffered only for segmented payloads.
+    pub payload_archive: u64,
+    /// The compressed payload.
+    pub payload: u64,
+    /// Content of the entries of the decompressed payload, held by
+    /// [`RPMPackage::payload_entries_with_diagnostics`].
+    pub decompressed_payload: u64,
+}
+
+impl ResourceReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes held at the same time by the most demanding of building or reading,
+    /// once its buffers are complete.
+    pub fn total(&self) -> u64 {
+        let building = self.staged_content + self.payload_archive + self.payload;
+        let reading = self.payload + self.decompressed_payload;
+        self.header_store + building.max(reading)
+    }
+
+    /// Fail with [`RPMError::LimitExceeded`] if [`total`](Self::total) is above `budget`.
+    pub fn check(&self, budget: u64) -> Result<(), RPMError> {
+        if self.total() > budget {
+            return Err(RPMError::LimitExceeded {
+                limit: "memory",
+                actual: self.total(),
+                max: budget,
+            });
+        }
+        Ok(())
+    }
+
+    /// Keep the larger size of each buffer.
+    pub fn merge(&mut self, other: &ResourceReport) {
+        self.header_store = self.header_store.max(other.header_store);
+        self.staged_content = self.staged_content.max(other.staged_content);
+        self.payload_archive = self.payload_archive.max(other.payload_archive);
+        self.payload = self.payload.max(other.payload);
+        self.decompressed_payload = self.decompressed_payload.max(other.decompressed_payload);
+    }
+}
+
+impl Diagnostics {
+    /// The buffers of the builds and parses this collector was passed to.
+    pub fn resources(&self) -> &ResourceReport {
+        &self.resources
+    }
+
+    pub(crate) fn record_resources(&mut self, report: ResourceReport) {
+        self.resources.merge(&report);
+    }
+}
+
+impl RPMPackage {
+    /// The buffers the package holds, its headers and payload.
+    pub fn resources(&self) -> ResourceReport {
+        ResourceReport {
+            header_store: self.metadata.header_store_size(),
+            payload: self.content.capacity() as u64,
+            ..ResourceReport::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ParseOptions, RPMBuilder, RPMFileOptions};
+    use std::str::FromStr;
+
+    #[test]
+    fn resources() -> Result<(), Box<dyn std::error::Error>> {
+        let content = b"line\n".repeat(10_000);
+        let mut builder = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo");
+        builder.add_data(
+            content.clone(),
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/usr/share/foo/data").into(),
+        )?;
+        let mut diagnostics = Diagnostics::new();
+        let pkg = builder.build_with_diagnostics(&mut diagnostics)?;
+        let built = *diagnostics.resources();
+        assert_eq!(content.len() as u64, built.staged_content);
+        assert_eq!(0, built.payload_archive);
+        assert_eq!(pkg.content.capacity() as u64, built.payload);
+        assert_eq!(
+            pkg.metadata.header.serialized_len() as u64,
+            built.header_store
+        );
+        assert_eq!(0, built.decompressed_payload);
+
+        let held = pkg.resources();
+        assert!(held.header_store >= built.header_store);
+        assert_eq!(built.payload, held.payload);
+
+        let mut bytes = Vec::new();
+        pkg.write(&mut bytes)?;
+        let mut parsed = Diagnostics::new();
+        let pkg =
+            RPMPackage::parse_with_options(&mut &bytes[..], &ParseOptions::new(), &mut parsed)?;
+        let parsed = *parsed.resources();
+        assert_eq!(held.header_store, parsed.header_store);
+        assert!(parsed.payload >= pkg.content.len() as u64);
+        assert_eq!(0, parsed.decompressed_payload);
+
+        let mut read = Diagnostics::new();
+        let entries = pkg.payload_entries_with_diagnostics(&mut read)?;
+        assert_eq!(1, entries.len());
+        let read = *read.resources();
+        assert!(read.decompressed_payload >= content.len() as u64);
+
+        let mut builder = RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "A foo")
+            .compression(crate::Compressor::from_str("zstd")?)
+            .payload_segments(1024);
+        builder.add_data(
+            content.clone(),
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/usr/share/foo/data").into(),
+        )?;
+        let mut segmented = Diagnostics::new();
+        builder.build_with_diagnostics(&mut segmented)?;
+        assert!(segmented.resources().payload_archive > content.len() as u64);
+
+        let mut merged = parsed;
+        merged.merge(&read);
+        assert_eq!(
+            merged.header_store + merged.payload + merged.decompressed_payload,
+            merged.total()
+        );
+        assert!(merged.check(merged.total()).is_ok());
+        assert!(matches!(
+            merged.check(merged.total() - 1),
+            Err(RPMError::LimitExceeded {
+                limit: "memory",
+                ..
+            })
+        ));
+        Ok(())
+    }
+}
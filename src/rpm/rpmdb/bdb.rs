@@ -0,0 +1,296 @@
+//! The Berkeley DB backend, used up to rpm 4.15, i.e. `/var/lib/rpm/Packages`.
+//!
+//! Only what is needed to get at the header blobs is implemented:
+//! the hash access method, its bucket pages and their overflow chains.
+//! Locks, logs and the environment files are ignored entirely.
+
+use std::convert::TryInto;
+
+use crate::constants::*;
+use crate::errors::*;
+use crate::rpm::headers::*;
+
+const HASH_MAGIC: u32 = 0x0006_1561;
+
+const PAGE_HEADER_SIZE: usize = 26;
+
+// page types
+const P_HASH_UNSORTED: u8 = 2;
+const P_OVERFLOW: u8 = 7;
+const P_HASHMETA: u8 = 8;
+const P_HASH: u8 = 13;
+
+// item types on hash pages
+const H_KEYDATA: u8 = 1;
+const H_OFFPAGE: u8 = 3;
+
+/// Read all package headers of a Berkeley DB `Packages` file.
+pub fn read_bdb<P: AsRef<std::path::Path>>(path: P) -> Result<Vec<Header<IndexTag>>, RPMError> {
+    parse_bdb(&std::fs::read(path)?)
+}
+
+/// Extract all package headers of an in memory Berkeley DB `Packages` file.
+pub fn parse_bdb(db: &[u8]) -> Result<Vec<Header<IndexTag>>, RPMError> {
+    let db = Database::new(db)?;
+    let mut headers = Vec::new();
+    for pgno in 0..db.page_count() {
+        let page = db.page(pgno)?;
+        if page[25] != P_HASH && page[25] != P_HASH_UNSORTED {
+            continue;
+        }
+        let entries = db.u16(page, 20) as usize;
+        // entries alternate between key and data
+        for item in (0..entries).step_by(2) {
+            let key = db.item(page, item)?;
+            if key.first() == Some(&H_KEYDATA) && key[1..].iter().all(|b| *b == 0) {
+                // record 0 holds bookkeeping data, not a header
+                continue;
+            }
+            let data = db.item(page, item + 1)?;
+            let blob = match data.first() {
+                Some(&H_OFFPAGE) if data.len() >= 12 => {
+                    db.overflow(db.u32(data, 4), db.u32(data, 8) as usize)?
+                }
+                Some(&H_KEYDATA) => data[1..].to_vec(),
+                _ => return Err(invalid("unsupported hash item type")),
+            };
+            headers.push(Header::parse_blob(&blob)?);
+        }
+    }
+    Ok(headers)
+}
+
+fn invalid(reason: &str) -> RPMError {
+    RPMError::InvalidDatabase(reason.to_string())
+}
+
+struct Database<'a> {
+    raw: &'a [u8],
+    page_size: usize,
+    little_endian: bool,
+}
+
+impl<'a> Database<'a> {
+    fn new(raw: &'a [u8]) -> Result<Self, RPMError> {
+        if raw.len() < 512 {
+            return Err(invalid("too short for a metadata page"));
+        }
+        // the database is written in the byte order of the machine that created it
+        let magic: [u8; 4] = raw[12..16].try_into().unwrap();
+        let little_endian = if u32::from_le_bytes(magic) == HASH_MAGIC {
+            true
+        } else if u32::from_be_bytes(magic) == HASH_MAGIC {
+            false
+        } else {
+            return Err(invalid("not a Berkeley DB hash database"));
+        };
+        let mut db = Database {
+            raw,
+            page_size: 0,
+            little_endian,
+        };
+        db.page_size = db.u32(raw, 20) as usize;
+        if raw[25] != P_HASHMETA {
+            return Err(invalid("first page is not a hash metadata page"));
+        }
+        if !(512..=65536).contains(&db.page_size) || !db.page_size.is_power_of_two() {
+            return Err(invalid("invalid page size"));
+        }
+        Ok(db)
+    }
+
+    fn page_count(&self) -> u32 {
+        (self.raw.len() / self.page_size) as u32
+    }
+
+    fn page(&self, pgno: u32) -> Result<&'a [u8], RPMError> {
+        let start = pgno as usize * self.page_size;
+        self.raw
+            .get(start..start + self.page_size)
+            .ok_or_else(|| invalid("page number out of bounds"))
+    }
+
+    fn u16(&self, data: &[u8], offset: usize) -> u16 {
+        let raw: [u8; 2] = data[offset..offset + 2].try_into().unwrap();
+        if self.little_endian {
+            u16::from_le_bytes(raw)
+        } else {
+            u16::from_be_bytes(raw)
+        }
+    }
+
+    fn u32(&self, data: &[u8], offset: usize) -> u32 {
+        let raw: [u8; 4] = data[offset..offset + 4].try_into().unwrap();
+        if self.little_endian {
+            u32::from_le_bytes(raw)
+        } else {
+            u32::from_be_bytes(raw)
+        }
+    }
+
+    /// Items are stored from the end of the page towards the index,
+    /// so an item ends where the previous one starts.
+    fn item(&self, page: &'a [u8], index: usize) -> Result<&'a [u8], RPMError> {
+        let offset_at = |index: usize| -> Result<usize, RPMError> {
+            let position = PAGE_HEADER_SIZE + index * 2;
+            if position + 2 > page.len() {
+                return Err(invalid("item index out of bounds"));
+            }
+            Ok(self.u16(page, position) as usize)
+        };
+        let start = offset_at(index)?;
+        let end = if index == 0 {
+            self.page_size
+        } else {
+            offset_at(index - 1)?
+        };
+        if start < PAGE_HEADER_SIZE || start >= end || end > page.len() {
+            return Err(invalid("item out of bounds"));
+        }
+        Ok(&page[start..end])
+    }
+
+    /// Collect `len` bytes from the chain of overflow pages starting at `pgno`.
+    fn overflow(&self, mut pgno: u32, len: usize) -> Result<Vec<u8>, RPMError> {
+        // the length is read from the database, the pages can hold no more than all of it
+        let mut data = Vec::with_capacity(len.min(self.raw.len()));
+        // every page can be visited at most once, anything longer is a loop
+        for _ in 0..self.page_count() {
+            let page = self.page(pgno)?;
+            if page[25] != P_OVERFLOW {
+                return Err(invalid("expected an overflow page"));
+            }
+            // on overflow pages the free area offset holds the number of used bytes
+            let used = self.u16(page, 22) as usize;
+            let chunk = page
+                .get(PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + used)
+                .ok_or_else(|| invalid("overflow page exceeds page size"))?;
+            if data.len() + chunk.len() > len {
+                return Err(invalid("overflow chain is longer than the item length"));
+            }
+            data.extend_from_slice(chunk);
+            pgno = self.u32(page, 16);
+            if pgno == 0 {
+                if data.len() != len {
+                    return Err(invalid("overflow chain ends before the item length"));
+                }
+                return Ok(data);
+            }
+        }
+        Err(invalid("overflow chain loops"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RPMBuilder;
+
+    const PAGE_SIZE: usize = 4096;
+
+    fn page_header(page: &mut [u8], pgno: u32, next: u32, entries: u16, offset: u16, kind: u8) {
+        page[8..12].copy_from_slice(&pgno.to_le_bytes());
+        page[16..20].copy_from_slice(&next.to_le_bytes());
+        page[20..22].copy_from_slice(&entries.to_le_bytes());
+        page[22..24].copy_from_slice(&offset.to_le_bytes());
+        page[25] = kind;
+    }
+
+    /// Lay out a little endian hash database, one bucket page at page 1
+    /// followed by the overflow pages of the blobs.
+    fn database(blobs: &[Vec<u8>]) -> Vec<u8> {
+        let mut meta = vec![0u8; PAGE_SIZE];
+        meta[12..16].copy_from_slice(&HASH_MAGIC.to_le_bytes());
+        meta[20..24].copy_from_slice(&(PAGE_SIZE as u32).to_le_bytes());
+        meta[25] = P_HASHMETA;
+
+        let mut bucket = vec![0u8; PAGE_SIZE];
+        let mut overflow_pages = Vec::new();
+        let mut end = PAGE_SIZE;
+        let mut items = Vec::new();
+        let mut records = vec![(0u32, None)];
+        records.extend(
+            blobs
+                .iter()
+                .enumerate()
+                .map(|(i, b)| (i as u32 + 1, Some(b))),
+        );
+        for (key, blob) in records {
+            let mut key_item = vec![H_KEYDATA];
+            key_item.extend_from_slice(&key.to_le_bytes());
+            let data_item = match blob {
+                None => vec![H_KEYDATA, 1, 0, 0, 0],
+                Some(blob) => {
+                    let first = 2 + overflow_pages.len() as u32;
+                    let chunks = blob
+                        .chunks(PAGE_SIZE - PAGE_HEADER_SIZE)
+                        .collect::<Vec<_>>();
+                    for (i, chunk) in chunks.iter().enumerate() {
+                        let pgno = first + i as u32;
+                        let next = if i + 1 < chunks.len() { pgno + 1 } else { 0 };
+                        let mut page = vec![0u8; PAGE_SIZE];
+                        page_header(&mut page, pgno, next, 1, chunk.len() as u16, P_OVERFLOW);
+                        page[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + chunk.len()]
+                            .copy_from_slice(chunk);
+                        overflow_pages.push(page);
+                    }
+                    let mut item = vec![H_OFFPAGE, 0, 0, 0];
+                    item.extend_from_slice(&first.to_le_bytes());
+                    item.extend_from_slice(&(blob.len() as u32).to_le_bytes());
+                    item
+                }
+            };
+            for item in [key_item, data_item].iter() {
+                end -= item.len();
+                bucket[end..end + item.len()].copy_from_slice(item);
+                items.push(end as u16);
+            }
+        }
+        for (i, offset) in items.iter().enumerate() {
+            let position = PAGE_HEADER_SIZE + i * 2;
+            bucket[position..position + 2].copy_from_slice(&offset.to_le_bytes());
+        }
+        page_header(&mut bucket, 1, 0, items.len() as u16, end as u16, P_HASH);
+
+        let mut db = meta;
+        db.extend(bucket);
+        overflow_pages.into_iter().for_each(|page| db.extend(page));
+        db
+    }
+
+    #[test]
+    fn read_bdb_packages() -> Result<(), Box<dyn std::error::Error>> {
+        let mut blobs = Vec::new();
+        for name in &["first", "second"] {
+            let pkg =
+                RPMBuilder::new(name, "1.0.0", "MIT", "noarch", &name.repeat(2000)).build()?;
+            let mut raw = Vec::new();
+            pkg.metadata.header.write(&mut raw)?;
+            blobs.push(raw[8..].to_vec());
+        }
+        // make sure the overflow chain spans multiple pages
+        assert!(blobs[0].len() > PAGE_SIZE);
+
+        let headers = parse_bdb(&database(&blobs))?;
+        let names = headers
+            .iter()
+            .map(|header| header.get_name())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(vec!["first", "second"], names);
+
+        assert!(parse_bdb(&[0u8; 4096]).is_err());
+
+        // item lengths beyond the chain are refused without allocating them
+        let db = database(&blobs);
+        let database = Database::new(&db)?;
+        let first = database.overflow(2, blobs[0].len())?;
+        assert_eq!(blobs[0], first);
+        for len in [blobs[0].len() - 1, blobs[0].len() + 1, u32::MAX as usize].iter() {
+            assert!(matches!(
+                database.overflow(2, *len),
+                Err(RPMError::InvalidDatabase(_))
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+//! Access to the database of installed packages.
+//!
+//! Every backend stores the plain header of each installed package,
+//! signatures and payload are not kept around.
+
+use crate::constants::*;
+use crate::errors::*;
+
+use crate::rpm::headers::*;
+use crate::{RPMPackage, Timestamp};
+
+mod bdb;
+pub use bdb::*;
+
+#[cfg(feature = "rpmdb-sqlite")]
+mod sqlite;
+#[cfg(feature = "rpmdb-sqlite")]
+pub use sqlite::*;
+
+/// The header rpm stores for `package` when installing it at `install_time`.
+///
+/// Like rpm, the digests and signatures of the signature header are copied into the
+/// header, and the install time and the state of each file are added.
+pub fn installed_header(
+    package: &RPMPackage,
+    install_time: Timestamp,
+) -> Result<Header<IndexTag>, RPMError> {
+    let mut header = Header::parse_blob(&package.metadata.header.to_blob())?;
+    let legacy = [
+        (IndexSignatureTag::RPMSIGTAG_SIZE, IndexTag::RPMTAG_SIGSIZE),
+        (
+            IndexSignatureTag::RPMSIGTAG_LONGSIGSIZE,
+            IndexTag::RPMTAG_LONGSIGSIZE,
+        ),
+        (IndexSignatureTag::RPMSIGTAG_MD5, IndexTag::RPMTAG_SIGMD5),
+        (
+            IndexSignatureTag::RPMSIGTAG_SHA1,
+            IndexTag::RPMTAG_SHA1HEADER,
+        ),
+        (
+            IndexSignatureTag::RPMSIGTAG_SHA256,
+            IndexTag::RPMTAG_SHA256HEADER,
+        ),
+        (IndexSignatureTag::RPMSIGTAG_RSA, IndexTag::RPMTAG_RSAHEADER),
+        (IndexSignatureTag::RPMSIGTAG_DSA, IndexTag::RPMTAG_DSAHEADER),
+        (IndexSignatureTag::RPMSIGTAG_PGP, IndexTag::RPMTAG_SIGPGP),
+        (IndexSignatureTag::RPMSIGTAG_GPG, IndexTag::RPMTAG_SIGGPG),
+    ];
+    let mut entries = legacy
+        .iter()
+        .filter_map(|(signature_tag, tag)| {
+            let entry = package.metadata.signature.find_entry_or_err(signature_tag);
+            entry
+                .ok()
+                .map(|entry| IndexEntry::from_data(*tag, entry.data.clone()))
+        })
+        .collect::<Vec<_>>();
+    entries.push(IndexEntry::from_data(
+        IndexTag::RPMTAG_INSTALLTIME,
+        IndexData::Int32(vec![install_time.to_tag()]),
+    ));
+    entries.push(IndexEntry::from_data(
+        IndexTag::RPMTAG_INSTALLTID,
+        IndexData::Int32(vec![install_time.to_tag()]),
+    ));
+    let files = header.get_file_paths().map_or(0, |paths| paths.len());
+    if files > 0 {
+        // RPMFILE_STATE_NORMAL for every file
+        entries.push(IndexEntry::from_data(
+            IndexTag::RPMTAG_FILESTATES,
+            IndexData::Char(vec![0; files]),
+        ));
+    }
+    header.append_entries(entries);
+    Ok(header)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{RPMBuilder, RPMFileOptions};
+
+    #[test]
+    fn installed_header_keeps_region() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/etc/awesome/config.toml"),
+            )?
+            .build()?;
+        let header = installed_header(&pkg, Timestamp::new(1_600_000_000))?;
+        let header = Header::<IndexTag>::parse_blob(&header.to_blob())?;
+
+        assert_eq!(Timestamp::new(1_600_000_000), header.get_install_time()?);
+        assert_eq!(
+            pkg.metadata.header.immutable_region()?,
+            header.immutable_region()?
+        );
+        assert_eq!(
+            pkg.metadata
+                .signature
+                .get_entry_string_data(IndexSignatureTag::RPMSIGTAG_SHA256)?,
+            header.get_entry_string_data(IndexTag::RPMTAG_SHA256HEADER)?
+        );
+        assert_eq!(
+            pkg.metadata.header.header_digest()?,
+            header.header_digest()?
+        );
+        assert_eq!(
+            IndexData::Char(vec![0]),
+            header.find_entry_or_err(&IndexTag::RPMTAG_FILESTATES)?.data
+        );
+        Ok(())
+    }
+}
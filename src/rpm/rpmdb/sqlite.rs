@@ -0,0 +1,277 @@
+//! The sqlite backend, default since rpm 4.16.
+
+use crate::constants::*;
+use crate::errors::*;
+
+use crate::rpm::headers::*;
+
+/// Read all package headers of a sqlite rpm database, as used by rpm 4.16 and later.
+///
+/// Usually found at `/var/lib/rpm/rpmdb.sqlite`, the database is opened read only.
+/// Headers are returned in the order the packages were installed.
+pub fn read_sqlite<P: AsRef<std::path::Path>>(path: P) -> Result<Vec<Header<IndexTag>>, RPMError> {
+    let connection = rusqlite::Connection::open_with_flags(
+        path,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    let mut statement = connection.prepare("SELECT blob FROM Packages ORDER BY hnum")?;
+    let mut rows = statement.query([])?;
+    let mut headers = Vec::new();
+    while let Some(row) = rows.next()? {
+        let blob: Vec<u8> = row.get(0)?;
+        headers.push(Header::parse_blob(&blob)?);
+    }
+    Ok(headers)
+}
+
+/// The tables rpm indexes packages in, the tag they index and whether the tag holds
+/// strings and arrays, which rpm creates indexes on `key` and `hnum` for.
+const INDEX_TABLES: &[(&str, IndexTag, bool, bool)] = &[
+    ("Name", IndexTag::RPMTAG_NAME, true, false),
+    ("Basenames", IndexTag::RPMTAG_BASENAMES, true, true),
+    ("Group", IndexTag::RPMTAG_GROUP, true, false),
+    ("Requirename", IndexTag::RPMTAG_REQUIRENAME, true, true),
+    ("Providename", IndexTag::RPMTAG_PROVIDENAME, true, true),
+    ("Conflictname", IndexTag::RPMTAG_CONFLICTNAME, true, true),
+    ("Obsoletename", IndexTag::RPMTAG_OBSOLETENAME, true, true),
+    ("Triggername", IndexTag::RPMTAG_TRIGGERNAME, true, true),
+    ("Dirnames", IndexTag::RPMTAG_DIRNAMES, true, true),
+    ("Installtid", IndexTag::RPMTAG_INSTALLTID, false, false),
+    ("Sigmd5", IndexTag::RPMTAG_SIGMD5, false, false),
+    ("Sha1header", IndexTag::RPMTAG_SHA1HEADER, true, false),
+    (
+        "Filetriggername",
+        IndexTag::RPMTAG_FILETRIGGERNAME,
+        true,
+        true,
+    ),
+    (
+        "Transfiletriggername",
+        IndexTag::RPMTAG_TRANSFILETRIGGERNAME,
+        true,
+        true,
+    ),
+    ("Recommendname", IndexTag::RPMTAG_RECOMMENDNAME, true, true),
+    ("Suggestname", IndexTag::RPMTAG_SUGGESTNAME, true, true),
+    (
+        "Supplementname",
+        IndexTag::RPMTAG_SUPPLEMENTNAME,
+        true,
+        true,
+    ),
+    ("Enhancename", IndexTag::RPMTAG_ENHANCENAME, true, true),
+];
+
+/// Add `headers` to a sqlite rpm database like rpm does on install, creating the
+/// database at `path` if needed, and return the numbers the packages got.
+///
+/// Next to the headers in `Packages`, the index tables rpm looks packages up by are
+/// filled, like `Name`, `Providename` or `Basenames`. Headers are stored as given, use
+/// [`installed_header`](super::installed_header) for the tags rpm adds on install.
+pub fn write_sqlite<P: AsRef<std::path::Path>>(
+    path: P,
+    headers: &[Header<IndexTag>],
+) -> Result<Vec<u32>, RPMError> {
+    let mut connection = rusqlite::Connection::open(path)?;
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        "CREATE TABLE IF NOT EXISTS 'Packages' (hnum INTEGER PRIMARY KEY AUTOINCREMENT, blob BLOB NOT NULL)",
+        [],
+    )?;
+    for (table, tag, string, array) in INDEX_TABLES {
+        // the type class of the tag, like rpm declares it
+        let key_type = if *tag == IndexTag::RPMTAG_INSTALLTID {
+            "INTEGER"
+        } else if *string {
+            "TEXT"
+        } else {
+            "BLOB"
+        };
+        transaction.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS '{table}' (key '{key_type}' NOT NULL, hnum INTEGER NOT NULL, idx INTEGER NOT NULL, FOREIGN KEY (hnum) REFERENCES 'Packages'(hnum))",
+                table = table,
+                key_type = key_type
+            ),
+            [],
+        )?;
+        let mut columns = Vec::new();
+        if *string {
+            columns.push("key");
+        }
+        if *array {
+            columns.push("hnum");
+        }
+        for column in columns {
+            transaction.execute(
+                &format!(
+                    "CREATE INDEX IF NOT EXISTS '{table}_{column}_idx' ON '{table}'({column} ASC)",
+                    table = table,
+                    column = column
+                ),
+                [],
+            )?;
+        }
+    }
+
+    let mut numbers = Vec::with_capacity(headers.len());
+    for header in headers {
+        transaction.execute(
+            "INSERT INTO 'Packages' (blob) VALUES (?1)",
+            [header.to_blob()],
+        )?;
+        let hnum = transaction.last_insert_rowid();
+        for (table, tag, _, _) in INDEX_TABLES {
+            let keys = match header.find_entry_or_err(tag) {
+                Ok(entry) => index_keys(&entry.data),
+                Err(_) => continue,
+            };
+            let mut statement = transaction.prepare_cached(&format!(
+                "INSERT INTO '{}' (key, hnum, idx) VALUES (?1, ?2, ?3)",
+                table
+            ))?;
+            for (idx, key) in keys.into_iter().enumerate() {
+                statement.execute(rusqlite::params![key, hnum, idx as i64])?;
+            }
+        }
+        numbers.push(hnum as u32);
+    }
+    transaction.commit()?;
+    Ok(numbers)
+}
+
+/// The keys rpm indexes for an entry, one per item.
+fn index_keys(data: &IndexData) -> Vec<rusqlite::types::Value> {
+    use rusqlite::types::Value;
+    match data {
+        IndexData::StringTag(value) => vec![Value::Text(value.clone())],
+        // only the untranslated value
+        IndexData::I18NString(values) => values.iter().take(1).cloned().map(Value::Text).collect(),
+        IndexData::StringArray(values) => values.iter().cloned().map(Value::Text).collect(),
+        IndexData::Bin(value) => vec![Value::Blob(value.clone())],
+        IndexData::Int32(values) => values
+            .iter()
+            .map(|value| Value::Integer(*value as i64))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Dependency, RPMBuilder, RPMFileOptions};
+
+    #[test]
+    fn read_sqlite_rpmdb() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = crate::tests::TestDir::new("rpmdb");
+        let path = dir.join("rpmdb.sqlite");
+        {
+            let connection = rusqlite::Connection::open(&path)?;
+            connection.execute(
+                "CREATE TABLE Packages (hnum INTEGER PRIMARY KEY AUTOINCREMENT, blob BLOB NOT NULL)",
+                [],
+            )?;
+            for name in &["first", "second"] {
+                let pkg = RPMBuilder::new(name, "1.0.0", "MIT", "noarch", name).build()?;
+                let mut raw = Vec::new();
+                pkg.metadata.header.write(&mut raw)?;
+                // the database omits magic, version and reserved bytes
+                connection.execute("INSERT INTO Packages (blob) VALUES (?1)", [&raw[8..]])?;
+            }
+        }
+
+        let headers = read_sqlite(&path)?;
+        let names = headers
+            .iter()
+            .map(|header| header.get_name())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(vec!["first", "second"], names);
+
+        Ok(())
+    }
+    #[test]
+    fn write_sqlite_rpmdb() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = crate::tests::TestDir::new("rpmdb-write");
+        let path = dir.join("rpmdb.sqlite");
+        let package = |name: &str| {
+            RPMBuilder::new(name, "1.0.0", "MIT", "noarch", name)
+                .with_file(
+                    "./test_assets/awesome.toml",
+                    RPMFileOptions::new(format!("/etc/{}/config.toml", name)),
+                )?
+                .provides(Dependency::any(format!("{}-cli", name)))
+                .build()
+        };
+        let first = super::super::installed_header(
+            &package("first")?,
+            crate::Timestamp::new(1_600_000_000),
+        )?;
+        assert_eq!(vec![1], write_sqlite(&path, &[first])?);
+        let second = super::super::installed_header(
+            &package("second")?,
+            crate::Timestamp::new(1_600_000_001),
+        )?;
+        assert_eq!(vec![2], write_sqlite(&path, &[second])?);
+
+        let headers = read_sqlite(&path)?;
+        assert_eq!("second", headers[1].get_name()?);
+        assert_eq!(
+            crate::Timestamp::new(1_600_000_001),
+            headers[1].get_install_time()?
+        );
+
+        let connection = rusqlite::Connection::open(&path)?;
+        let lookup = |sql: &str, key: &dyn rusqlite::ToSql| -> rusqlite::Result<(i64, i64)> {
+            connection.query_row(sql, [key], |row| Ok((row.get(0)?, row.get(1)?)))
+        };
+        assert_eq!(
+            (2, 0),
+            lookup("SELECT hnum, idx FROM Name WHERE key = ?1", &"second")?
+        );
+        // after `first` and `first(noarch)`
+        assert_eq!(
+            (1, 2),
+            lookup(
+                "SELECT hnum, idx FROM Providename WHERE key = ?1",
+                &"first-cli"
+            )?
+        );
+        assert_eq!(
+            (2, 0),
+            lookup(
+                "SELECT hnum, idx FROM Dirnames WHERE key = ?1",
+                &"/etc/second/"
+            )?
+        );
+        assert_eq!(
+            (2, 3),
+            lookup(
+                "SELECT count(*), sum(hnum) FROM Basenames WHERE key = ?1",
+                &"config.toml"
+            )?
+        );
+        assert_eq!(
+            (1, 0),
+            lookup(
+                "SELECT hnum, idx FROM Installtid WHERE key = ?1",
+                &1_600_000_000
+            )?
+        );
+        for (table, key_type) in [
+            ("Name", "TEXT"),
+            ("Sha1header", "TEXT"),
+            ("Sigmd5", "BLOB"),
+            ("Installtid", "INTEGER"),
+        ] {
+            let declared: String = connection.query_row(
+                "SELECT type FROM pragma_table_info(?1) WHERE name = 'key'",
+                [table],
+                |row| row.get(0),
+            )?;
+            assert_eq!(key_type, declared, "{}", table);
+        }
+
+        Ok(())
+    }
+}
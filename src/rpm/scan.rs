@@ -0,0 +1,325 @@
+//! Parse the metadata of many packages in parallel, e.g. to index a repository.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! for scanned in rpm::scan::dir("/srv/repo", rpm::scan::ScanOptions::new())? {
+//!     match scanned.metadata {
+//!         Ok(metadata) => println!("{}", metadata.header.get_name()?),
+//!         Err(err) => eprintln!("{}: {}", scanned.path.display(), err),
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::errors::*;
+
+use super::headers::{ParseOptions, QuirkLog};
+use super::{Diagnostics, RPMPackageMetadata};
+
+#[cfg(feature = "cache")]
+use super::cache::HeaderCache;
+
+/// Controls [`dir`].
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    threads: Option<usize>,
+    recursive: bool,
+    parse: ParseOptions,
+    #[cfg(feature = "cache")]
+    cache: Option<HeaderCache>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            threads: None,
+            recursive: true,
+            parse: ParseOptions::new(),
+            #[cfg(feature = "cache")]
+            cache: None,
+        }
+    }
+}
+
+impl ScanOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of packages parsed at the same time, the available parallelism by default.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads.max(1));
+        self
+    }
+
+    /// Descend into subdirectories, on by default.
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    pub fn parse_options(mut self, options: ParseOptions) -> Self {
+        self.parse = options;
+        self
+    }
+
+    /// Reuse the metadata of packages parsed by earlier scans, see [`HeaderCache`].
+    ///
+    /// Only packages parsing without diagnostics are cached, so the diagnostics of a
+    /// package are the same whether it comes from the cache or not.
+    #[cfg(feature = "cache")]
+    pub fn cache(mut self, cache: HeaderCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+}
+
+/// A package file found by [`dir`].
+#[derive(Debug)]
+pub struct ScannedPackage {
+    pub path: PathBuf,
+    /// Lead and headers, the payload is not read.
+    pub metadata: Result<RPMPackageMetadata, RPMError>,
+    pub diagnostics: Diagnostics,
+}
+
+/// The packages of a directory, yielded as soon as they are parsed.
+///
+/// Dropping it early stops the remaining work after the packages in flight.
+pub struct Scan {
+    packages: mpsc::Receiver<ScannedPackage>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Iterator for Scan {
+    type Item = ScannedPackage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.packages.recv().ok();
+        if next.is_none() {
+            for worker in self.workers.drain(..) {
+                let _ = worker.join();
+            }
+        }
+        next
+    }
+}
+
+/// Parse the metadata of every `*.rpm` file below `path` on a pool of threads.
+///
+/// Packages come in the order they finish parsing. A package failing to
+/// parse is reported with its error and does not affect the others, only
+/// failing to list `path` itself is an error.
+pub fn dir<P: AsRef<Path>>(path: P, options: ScanOptions) -> Result<Scan, RPMError> {
+    let (files, unreadable) = list(path.as_ref(), options.recursive)?;
+    let threads = options
+        .threads
+        .or_else(|| std::thread::available_parallelism().ok().map(usize::from))
+        .unwrap_or(1)
+        .min(files.len().max(1));
+
+    let (sender, packages) = mpsc::channel();
+    for (path, err) in unreadable {
+        let _ = sender.send(ScannedPackage {
+            path,
+            metadata: Err(err),
+            diagnostics: Diagnostics::new(),
+        });
+    }
+    let queue = Arc::new(Mutex::new(files.into_iter()));
+    let workers = (0..threads)
+        .map(|_| {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            let options = options.clone();
+            std::thread::spawn(move || loop {
+                let path = match queue.lock().ok().and_then(|mut queue| queue.next()) {
+                    Some(path) => path,
+                    None => return,
+                };
+                if sender.send(scan_file(path, &options)).is_err() {
+                    return;
+                }
+            })
+        })
+        .collect();
+    Ok(Scan { packages, workers })
+}
+
+fn scan_file(path: PathBuf, options: &ScanOptions) -> ScannedPackage {
+    #[cfg(feature = "cache")]
+    let key = match &options.cache {
+        Some(cache) => {
+            let key = cache.key_of(&path).ok();
+            if let Some(metadata) = key.as_ref().and_then(|key| cache.get(key)) {
+                return ScannedPackage {
+                    path,
+                    metadata: Ok(metadata),
+                    diagnostics: Diagnostics::new(),
+                };
+            }
+            key
+        }
+        None => None,
+    };
+
+    let mut log = QuirkLog::new(&options.parse);
+    let metadata = std::fs::File::open(&path)
+        .map_err(RPMError::from)
+        .and_then(|file| {
+            RPMPackageMetadata::parse_with(&mut std::io::BufReader::new(file), &mut log)
+        });
+
+    #[cfg(feature = "cache")]
+    if let (Some(cache), Some(key), Ok(metadata)) = (&options.cache, &key, &metadata) {
+        if log.diagnostics.is_empty() {
+            // a failing cache only costs the next scan time
+            let _ = cache.insert(key, metadata);
+        }
+    }
+
+    ScannedPackage {
+        path,
+        metadata,
+        diagnostics: log.diagnostics,
+    }
+}
+
+/// Package files below `root`, sorted, and the directories that could not be read.
+#[allow(clippy::type_complexity)]
+fn list(
+    root: &Path,
+    recursive: bool,
+) -> Result<(Vec<PathBuf>, Vec<(PathBuf, RPMError)>), RPMError> {
+    let mut files = Vec::new();
+    let mut unreadable = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if dir == root => return Err(err.into()),
+            Err(err) => {
+                unreadable.push((dir, err.into()));
+                continue;
+            }
+        };
+        for entry in entries {
+            // symbolic links to directories are not followed, they may form loops
+            let (path, is_dir) =
+                match entry.and_then(|entry| Ok((entry.path(), entry.file_type()?))) {
+                    Ok((path, file_type)) => (path, file_type.is_dir()),
+                    Err(err) => {
+                        unreadable.push((dir.clone(), err.into()));
+                        continue;
+                    }
+                };
+            if is_dir {
+                if recursive {
+                    pending.push(path);
+                }
+            } else if path.extension().map_or(false, |ext| ext == "rpm") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok((files, unreadable))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scan_directory() -> Result<(), Box<dyn std::error::Error>> {
+        let root = crate::tests::TestDir::new("scan");
+        std::fs::create_dir_all(root.join("nested"))?;
+        std::fs::copy(
+            "./test_assets/389-ds-base-devel-1.3.8.4-15.el7.x86_64.rpm",
+            root.join("389-ds-base-devel.rpm"),
+        )?;
+        std::fs::copy(
+            "./test_assets/monkeysphere-0.37-1.el7.noarch.rpm",
+            root.join("nested/monkeysphere.rpm"),
+        )?;
+        std::fs::write(root.join("nested/broken.rpm"), b"not a package")?;
+        std::fs::write(root.join("README"), b"ignored")?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, root.join("nested/loop"))?;
+
+        let mut scanned = dir(&root, ScanOptions::new().threads(2))?.collect::<Vec<_>>();
+        scanned.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(3, scanned.len());
+        assert_eq!(
+            "389-ds-base-devel",
+            scanned[0].metadata.as_ref().unwrap().header.get_name()?
+        );
+        assert!(scanned[1].metadata.is_err());
+        assert_eq!(
+            "monkeysphere",
+            scanned[2].metadata.as_ref().unwrap().header.get_name()?
+        );
+
+        let flat = dir(&root, ScanOptions::new().recursive(false))?.count();
+        assert_eq!(1, flat);
+        assert!(dir(root.join("missing"), ScanOptions::new()).is_err());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn scan_with_cache() -> Result<(), Box<dyn std::error::Error>> {
+        let root = crate::tests::TestDir::new("scan-cache");
+        std::fs::create_dir_all(root.join("repo"))?;
+        let pkg = crate::RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome")
+            .with_file(
+                "./test_assets/awesome.toml",
+                crate::RPMFileOptions::new("/etc/awesome/config.toml"),
+            )?
+            .build()?;
+        pkg.write(&mut std::fs::File::create(root.join("repo/awesome.rpm"))?)?;
+        std::fs::copy(
+            "./test_assets/389-ds-base-devel-1.3.8.4-15.el7.x86_64.rpm",
+            root.join("repo/389-ds-base-devel.rpm"),
+        )?;
+
+        let cache = HeaderCache::open(root.join("cache"))?;
+        let options = ScanOptions::new().cache(cache.clone());
+        let first = dir(root.join("repo"), options.clone())?.collect::<Vec<_>>();
+        assert_eq!(2, first.len());
+        // the MD5 file digests of the other package are diagnosed, so it is not cached,
+        // but the keys of both files are
+        let mut entries = std::fs::read_dir(cache.dir())?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().starts_with("stamp-")))
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        entries.sort();
+        assert_eq!(vec![false, true, true], entries);
+
+        // scans are answered from the cache, as shown by swapping its entry
+        let key = crate::cache::CacheKey::of_file(root.join("repo/awesome.rpm"))?;
+        let other = std::fs::File::open("./test_assets/monkeysphere-0.37-1.el7.noarch.rpm")?;
+        let other = RPMPackageMetadata::parse(&mut std::io::BufReader::new(other))?;
+        cache.insert(&key, &other)?;
+        let mut names = dir(root.join("repo"), options)?
+            .map(|scanned| {
+                scanned
+                    .metadata
+                    .unwrap()
+                    .header
+                    .get_name()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(vec!["389-ds-base-devel", "monkeysphere"], names);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,811 @@
+//! Scriptlets and the interpreters running them, most notably the Lua embedded in rpm.
+
+use std::collections::BTreeMap;
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::{Header, IndexData, RPMBuilder};
+
+/// The interpreter token making rpm run a scriptlet with its embedded Lua, `-p <lua>` in a spec.
+pub const LUA_INTERPRETER: &str = "<lua>";
+
+/// What rpm runs a scriptlet with if the package does not name an interpreter.
+pub const DEFAULT_INTERPRETER: &str = "/bin/sh";
+
+/// Makes rpm expand the macros of a scriptlet on install, `-e` in a spec.
+pub const RPMSCRIPT_FLAG_EXPAND: u32 = 1 << 0;
+
+/// Makes rpm expand header query formats like `%{NAME}` in a scriptlet on install,
+/// `-q` in a spec.
+pub const RPMSCRIPT_FLAG_QFORMAT: u32 = 1 << 1;
+
+/// Makes a failing scriptlet fail the package like a failing `%pre` does.
+pub const RPMSCRIPT_FLAG_CRITICAL: u32 = 1 << 2;
+
+/// The `RPMSCRIPT_FLAG_*` bits of a scriptlet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ScriptletFlags(u32);
+
+impl ScriptletFlags {
+    pub const fn from_bits(bits: u32) -> Self {
+        ScriptletFlags(bits)
+    }
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Set the bits of `flag`, one of the `RPMSCRIPT_FLAG_*` constants.
+    pub fn with(self, flag: u32) -> Self {
+        ScriptletFlags(self.0 | flag)
+    }
+
+    /// See [`RPMSCRIPT_FLAG_EXPAND`].
+    pub fn expands_macros(self) -> bool {
+        self.0 & RPMSCRIPT_FLAG_EXPAND != 0
+    }
+
+    /// See [`RPMSCRIPT_FLAG_QFORMAT`].
+    pub fn expands_query_format(self) -> bool {
+        self.0 & RPMSCRIPT_FLAG_QFORMAT != 0
+    }
+
+    /// See [`RPMSCRIPT_FLAG_CRITICAL`].
+    pub fn is_critical(self) -> bool {
+        self.0 & RPMSCRIPT_FLAG_CRITICAL != 0
+    }
+}
+
+/// What rpm does when a scriptlet exits with an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FailurePolicy {
+    /// The package is not installed or erased, the transaction reports an error
+    /// and carries on with the other packages.
+    Abort,
+    /// rpm warns and the package counts as installed or erased.
+    Warn,
+}
+
+/// The points of a transaction rpm runs scriptlets at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Scriptlet {
+    /// `%pretrans`, before anything of the transaction is installed, so on a
+    /// fresh system there may not even be a shell yet.
+    PreTrans,
+    PreInstall,
+    PostInstall,
+    PreUninstall,
+    PostUninstall,
+    /// `%posttrans`, after the whole transaction.
+    PostTrans,
+}
+
+impl Scriptlet {
+    pub const ALL: [Scriptlet; 6] = [
+        Scriptlet::PreTrans,
+        Scriptlet::PreInstall,
+        Scriptlet::PostInstall,
+        Scriptlet::PreUninstall,
+        Scriptlet::PostUninstall,
+        Scriptlet::PostTrans,
+    ];
+
+    /// The tag holding the body of the scriptlet.
+    pub fn tag(self) -> IndexTag {
+        match self {
+            Scriptlet::PreTrans => IndexTag::RPMTAG_PRETRANS,
+            Scriptlet::PreInstall => IndexTag::RPMTAG_PREIN,
+            Scriptlet::PostInstall => IndexTag::RPMTAG_POSTIN,
+            Scriptlet::PreUninstall => IndexTag::RPMTAG_PREUN,
+            Scriptlet::PostUninstall => IndexTag::RPMTAG_POSTUN,
+            Scriptlet::PostTrans => IndexTag::RPMTAG_POSTTRANS,
+        }
+    }
+
+    /// The tag holding the interpreter of the scriptlet and its arguments.
+    pub fn program_tag(self) -> IndexTag {
+        match self {
+            Scriptlet::PreTrans => IndexTag::RPMTAG_PRETRANSPROG,
+            Scriptlet::PreInstall => IndexTag::RPMTAG_PREINPROG,
+            Scriptlet::PostInstall => IndexTag::RPMTAG_POSTINPROG,
+            Scriptlet::PreUninstall => IndexTag::RPMTAG_PREUNPROG,
+            Scriptlet::PostUninstall => IndexTag::RPMTAG_POSTUNPROG,
+            Scriptlet::PostTrans => IndexTag::RPMTAG_POSTTRANSPROG,
+        }
+    }
+
+    /// The tag holding the `RPMSCRIPT_FLAG_*` bits of the scriptlet.
+    pub fn flags_tag(self) -> IndexTag {
+        match self {
+            Scriptlet::PreTrans => IndexTag::RPMTAG_PRETRANSFLAGS,
+            Scriptlet::PreInstall => IndexTag::RPMTAG_PREINFLAGS,
+            Scriptlet::PostInstall => IndexTag::RPMTAG_POSTINFLAGS,
+            Scriptlet::PreUninstall => IndexTag::RPMTAG_PREUNFLAGS,
+            Scriptlet::PostUninstall => IndexTag::RPMTAG_POSTUNFLAGS,
+            Scriptlet::PostTrans => IndexTag::RPMTAG_POSTTRANSFLAGS,
+        }
+    }
+
+    /// What a failure of the scriptlet with `flags` means for its package.
+    ///
+    /// Failures of `%pretrans`, `%pre` and `%preun` abort, failures of the
+    /// scriptlets running after files were touched only warn unless they are
+    /// [critical](ScriptletFlags::is_critical).
+    pub fn failure_policy(self, flags: ScriptletFlags) -> FailurePolicy {
+        match self {
+            Scriptlet::PreTrans | Scriptlet::PreInstall | Scriptlet::PreUninstall => {
+                FailurePolicy::Abort
+            }
+            _ if flags.is_critical() => FailurePolicy::Abort,
+            _ => FailurePolicy::Warn,
+        }
+    }
+
+    pub(crate) fn from_tag(tag: IndexTag) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|scriptlet| scriptlet.tag() == tag)
+    }
+}
+
+/// When a file trigger runs, for packages installing or removing files below its prefixes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FileTrigger {
+    /// `%filetriggerin`, after each package installing matching files, which are
+    /// passed on stdin one per line.
+    In,
+    /// `%filetriggerun`, before each package removing matching files.
+    Un,
+    /// `%filetriggerpostun`, after each package removing matching files.
+    PostUn,
+    /// `%transfiletriggerin`, once after the transaction.
+    TransIn,
+    /// `%transfiletriggerun`, once before the transaction.
+    TransUn,
+    /// `%transfiletriggerpostun`, once after the transaction, without the files.
+    TransPostUn,
+}
+
+impl FileTrigger {
+    /// Whether the trigger runs once per transaction instead of once per package.
+    pub fn is_transaction(self) -> bool {
+        matches!(
+            self,
+            FileTrigger::TransIn | FileTrigger::TransUn | FileTrigger::TransPostUn
+        )
+    }
+
+    /// The `RPMSENSE_TRIGGER*` bit of the trigger condition.
+    pub fn sense(self) -> u32 {
+        match self {
+            FileTrigger::In | FileTrigger::TransIn => RPMSENSE_TRIGGERIN,
+            FileTrigger::Un | FileTrigger::TransUn => RPMSENSE_TRIGGERUN,
+            FileTrigger::PostUn | FileTrigger::TransPostUn => RPMSENSE_TRIGGERPOSTUN,
+        }
+    }
+}
+
+/// Priority of file triggers rpmbuild uses if the spec does not set one.
+pub const DEFAULT_FILE_TRIGGER_PRIORITY: i32 = 1_000_000;
+
+/// A file trigger added with [`RPMBuilder::file_trigger`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FileTriggerScript {
+    pub(crate) trigger: FileTrigger,
+    pub(crate) prefixes: Vec<String>,
+    pub(crate) body: String,
+}
+
+/// Double every `%`, so rpm expanding the macros of a scriptlet leaves `text` as it is.
+pub fn escape_macros(text: &str) -> String {
+    text.replace('%', "%%")
+}
+
+/// Quote `arg` for a POSIX shell, unchanged if it needs no quoting.
+pub fn shell_quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r#"'\''"#))
+    }
+}
+
+/// Append `fragment` to `script` on lines of its own.
+pub(crate) fn join(script: &mut String, fragment: &str) {
+    if !script.is_empty() && !script.ends_with('\n') {
+        script.push('\n');
+    }
+    script.push_str(fragment);
+}
+
+/// A shell scriptlet composed of fragments, each starting on a new line.
+///
+/// Commands added with [`command`](Self::command) have their arguments quoted.
+/// With [`escape_macros`](Self::escape_macros) every `%` is doubled, as
+/// scriptlets set up to [expand macros](RPMBuilder::expand_script_macros)
+/// would otherwise have rpm replace them on install.
+///
+/// ```rust
+/// use rpm::ScriptBody;
+///
+/// let body = ScriptBody::new()
+///     .command("mkdir", &["-p", "/var/lib/my app"])
+///     .line("date +%s > /var/lib/stamp\n")
+///     .escape_macros(true)
+///     .build();
+/// assert_eq!("mkdir -p '/var/lib/my app'\ndate +%%s > /var/lib/stamp\n", body);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptBody {
+    script: String,
+    escape_macros: bool,
+}
+
+impl ScriptBody {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append `fragment` as written, it may span several lines.
+    pub fn line(mut self, fragment: &str) -> Self {
+        join(&mut self.script, fragment);
+        self
+    }
+
+    /// Append a command running `program` with `args`, quoted for the shell.
+    pub fn command<S: AsRef<str>>(self, program: &str, args: &[S]) -> Self {
+        let command = std::iter::once(program)
+            .chain(args.iter().map(AsRef::as_ref))
+            .map(shell_quote)
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.line(&command)
+    }
+
+    pub fn escape_macros(mut self, escape: bool) -> Self {
+        self.escape_macros = escape;
+        self
+    }
+
+    /// The body, ending with a newline unless it is empty.
+    pub fn build(self) -> String {
+        let mut script = self.script;
+        if !script.is_empty() && !script.ends_with('\n') {
+            script.push('\n');
+        }
+        if self.escape_macros {
+            escape_macros(&script)
+        } else {
+            script
+        }
+    }
+}
+
+/// Body, interpreter and flag tags of the scriptlets, in the order of [`Scriptlet::ALL`].
+pub(crate) fn entries(
+    mut scripts: BTreeMap<Scriptlet, String>,
+    mut programs: BTreeMap<Scriptlet, Vec<String>>,
+    flags: &BTreeMap<Scriptlet, ScriptletFlags>,
+) -> Vec<(IndexTag, IndexData)> {
+    let mut entries = Vec::new();
+    for scriptlet in Scriptlet::ALL.iter() {
+        let present = scripts.contains_key(scriptlet) || programs.contains_key(scriptlet);
+        if let Some(body) = scripts.remove(scriptlet) {
+            entries.push((scriptlet.tag(), IndexData::StringTag(body)));
+        }
+        match flags.get(scriptlet) {
+            Some(flags) if present && flags.bits() != 0 => entries.push((
+                scriptlet.flags_tag(),
+                IndexData::Int32(vec![flags.bits() as i32]),
+            )),
+            _ => {}
+        }
+        match programs.remove(scriptlet) {
+            // rpmbuild uses a string unless there are arguments
+            Some(mut program) if program.len() == 1 => entries.push((
+                scriptlet.program_tag(),
+                IndexData::StringTag(program.remove(0)),
+            )),
+            Some(program) => {
+                entries.push((scriptlet.program_tag(), IndexData::StringArray(program)))
+            }
+            None => {}
+        }
+    }
+    entries
+}
+
+/// The `FILETRIGGER*` and `TRANSFILETRIGGER*` tags of `triggers`, each set with one entry per
+/// trigger and one per prefix pointing back at its trigger.
+pub(crate) fn file_trigger_entries(triggers: Vec<FileTriggerScript>) -> Vec<(IndexTag, IndexData)> {
+    let mut entries = Vec::new();
+    for transaction in [false, true] {
+        let triggers = triggers
+            .iter()
+            .filter(|script| script.trigger.is_transaction() == transaction)
+            .collect::<Vec<_>>();
+        if triggers.is_empty() {
+            continue;
+        }
+        let mut names = Vec::new();
+        let mut indexes = Vec::new();
+        let mut flags = Vec::new();
+        for (index, script) in triggers.iter().enumerate() {
+            for prefix in &script.prefixes {
+                names.push(prefix.clone());
+                indexes.push(index as i32);
+                flags.push(script.trigger.sense() as i32);
+            }
+        }
+        let tags = if transaction {
+            [
+                IndexTag::RPMTAG_TRANSFILETRIGGERSCRIPTS,
+                IndexTag::RPMTAG_TRANSFILETRIGGERSCRIPTPROG,
+                IndexTag::RPMTAG_TRANSFILETRIGGERSCRIPTFLAGS,
+                IndexTag::RPMTAG_TRANSFILETRIGGERPRIORITIES,
+                IndexTag::RPMTAG_TRANSFILETRIGGERNAME,
+                IndexTag::RPMTAG_TRANSFILETRIGGERINDEX,
+                IndexTag::RPMTAG_TRANSFILETRIGGERFLAGS,
+                IndexTag::RPMTAG_TRANSFILETRIGGERVERSION,
+            ]
+        } else {
+            [
+                IndexTag::RPMTAG_FILETRIGGERSCRIPTS,
+                IndexTag::RPMTAG_FILETRIGGERSCRIPTPROG,
+                IndexTag::RPMTAG_FILETRIGGERSCRIPTFLAGS,
+                IndexTag::RPMTAG_FILETRIGGERPRIORITIES,
+                IndexTag::RPMTAG_FILETRIGGERNAME,
+                IndexTag::RPMTAG_FILETRIGGERINDEX,
+                IndexTag::RPMTAG_FILETRIGGERFLAGS,
+                IndexTag::RPMTAG_FILETRIGGERVERSION,
+            ]
+        };
+        let n = triggers.len();
+        let data = vec![
+            IndexData::StringArray(triggers.iter().map(|script| script.body.clone()).collect()),
+            IndexData::StringArray(vec![DEFAULT_INTERPRETER.to_string(); n]),
+            IndexData::Int32(vec![0; n]),
+            IndexData::Int32(vec![DEFAULT_FILE_TRIGGER_PRIORITY; n]),
+            IndexData::StringArray(names.clone()),
+            IndexData::Int32(indexes),
+            IndexData::Int32(flags),
+            IndexData::StringArray(vec![String::new(); names.len()]),
+        ];
+        entries.extend(tags.iter().copied().zip(data));
+    }
+    entries
+}
+
+/// Whether generated shell snippets may be appended to a scriptlet run by `program`.
+fn is_shell(program: &[String]) -> bool {
+    program.first().map_or(true, |program| {
+        program == DEFAULT_INTERPRETER || program == "/bin/bash"
+    })
+}
+
+impl RPMBuilder {
+    pub fn pre_trans_script<T: Into<String>>(mut self, content: T) -> Self {
+        self.scripts.insert(Scriptlet::PreTrans, content.into());
+        self
+    }
+
+    pub fn post_trans_script<T: Into<String>>(mut self, content: T) -> Self {
+        self.scripts.insert(Scriptlet::PostTrans, content.into());
+        self
+    }
+
+    /// Run `body` when other packages install or remove files below one of `prefixes`,
+    /// like `%filetriggerin -- /usr/lib/modules` and its siblings in a spec.
+    ///
+    /// Prefixes have to be absolute paths, the body is run by [`DEFAULT_INTERPRETER`].
+    /// Packages with file triggers require `rpmlib(FileTriggers)`.
+    pub fn file_trigger<S: AsRef<str>>(
+        mut self,
+        trigger: FileTrigger,
+        prefixes: &[S],
+        body: &str,
+    ) -> Self {
+        self.file_triggers.push(FileTriggerScript {
+            trigger,
+            prefixes: prefixes
+                .iter()
+                .map(|prefix| prefix.as_ref().to_string())
+                .collect(),
+            body: body.to_string(),
+        });
+        self
+    }
+
+    /// Have rpm expand the macros in the body of `scriptlet` on install, like `-e` in a spec.
+    ///
+    /// Any `%` meant literally has to be doubled then, see [`escape_macros`].
+    pub fn expand_script_macros(self, scriptlet: Scriptlet) -> Self {
+        self.add_script_flag(scriptlet, RPMSCRIPT_FLAG_EXPAND)
+    }
+
+    /// Have rpm expand header query formats like `%{VERSION}` in the body of
+    /// `scriptlet` on install, like `-q` in a spec.
+    pub fn expand_script_query_format(self, scriptlet: Scriptlet) -> Self {
+        self.add_script_flag(scriptlet, RPMSCRIPT_FLAG_QFORMAT)
+    }
+
+    /// Make a failure of `scriptlet` keep the package from being installed or erased,
+    /// see [`Scriptlet::failure_policy`].
+    pub fn critical_script(self, scriptlet: Scriptlet) -> Self {
+        self.add_script_flag(scriptlet, RPMSCRIPT_FLAG_CRITICAL)
+    }
+
+    /// Replace all flags of `scriptlet`, which are only stored if the package has it.
+    pub fn script_flags(mut self, scriptlet: Scriptlet, flags: ScriptletFlags) -> Self {
+        self.script_flags.insert(scriptlet, flags);
+        self
+    }
+
+    fn add_script_flag(mut self, scriptlet: Scriptlet, flag: u32) -> Self {
+        let flags = self.script_flags.entry(scriptlet).or_default();
+        *flags = flags.with(flag);
+        self
+    }
+
+    /// Run `scriptlet` with `program` instead of [`DEFAULT_INTERPRETER`], like `-p` in a spec.
+    ///
+    /// `program` is either [`LUA_INTERPRETER`] or an absolute path, optionally
+    /// followed by arguments separated by whitespace. An interpreter with
+    /// arguments requires `rpmlib(ScriptletInterpreterArgs)`, Lua requires
+    /// `rpmlib(BuiltinLuaScripts)`. Only interpreters other than Lua may run
+    /// without a body, e.g. `/sbin/ldconfig`.
+    ///
+    /// ```rust
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use rpm::{Scriptlet, LUA_INTERPRETER};
+    ///
+    /// let pkg = rpm::RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "")
+    ///     .pre_trans_script(r#"os.remove("/etc/foo.conf.rpmsave")"#)
+    ///     .script_interpreter(Scriptlet::PreTrans, LUA_INTERPRETER)
+    ///     .build()?;
+    /// assert_eq!(
+    ///     Some(vec![LUA_INTERPRETER.to_string()]),
+    ///     pkg.metadata.header.get_scriptlet_interpreter(Scriptlet::PreTrans)?
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn script_interpreter(mut self, scriptlet: Scriptlet, program: &str) -> Self {
+        self.script_programs.insert(
+            scriptlet,
+            program.split_whitespace().map(str::to_string).collect(),
+        );
+        self
+    }
+
+    /// Reject interpreters rpm could not run and require the rpmlib features the others need.
+    pub(crate) fn check_scripts(&mut self) -> Result<(), RPMError> {
+        let mut lua = false;
+        let mut args = false;
+        for (scriptlet, program) in self.script_programs.iter() {
+            let invalid = |reason| RPMError::InvalidScriptlet {
+                scriptlet: scriptlet.tag().to_string(),
+                reason,
+            };
+            let body = self.scripts.get(scriptlet).map_or("", String::as_str);
+            match program.first().map(String::as_str) {
+                None => return Err(invalid("the interpreter is empty")),
+                Some(LUA_INTERPRETER) => {
+                    if program.len() > 1 {
+                        return Err(invalid("the embedded Lua interpreter takes no arguments"));
+                    }
+                    if body.trim().is_empty() {
+                        return Err(invalid("a Lua scriptlet needs a body"));
+                    }
+                    lua = true;
+                }
+                Some(path) if !path.starts_with('/') => {
+                    return Err(invalid(
+                        "the interpreter has to be an absolute path or <lua>",
+                    ))
+                }
+                Some(_) => args |= program.len() > 1,
+            }
+            if self.shell_snippets.contains(scriptlet) && !is_shell(program) {
+                return Err(invalid(
+                    "shell snippets were added to a scriptlet run by another interpreter",
+                ));
+            }
+        }
+        for script in &self.file_triggers {
+            let invalid = |reason| RPMError::InvalidScriptlet {
+                scriptlet: format!("{:?} file trigger", script.trigger),
+                reason,
+            };
+            if script.prefixes.is_empty() {
+                return Err(invalid("a file trigger needs a prefix"));
+            }
+            if script
+                .prefixes
+                .iter()
+                .any(|prefix| !prefix.starts_with('/'))
+            {
+                return Err(invalid("file trigger prefixes have to be absolute paths"));
+            }
+        }
+        if !self.file_triggers.is_empty() {
+            self.require_rpmlib("FileTriggers", "4.13.0-1");
+        }
+        if lua {
+            self.require_rpmlib("BuiltinLuaScripts", "4.2.2-1");
+        }
+        if args {
+            self.require_rpmlib("ScriptletInterpreterArgs", "4.0.3-1");
+        }
+        Ok(())
+    }
+}
+
+impl Header<IndexTag> {
+    /// Body of `scriptlet`, `None` if the package has none.
+    pub fn get_scriptlet(&self, scriptlet: Scriptlet) -> Result<Option<&str>, RPMError> {
+        match self.get_entry_string_data(scriptlet.tag()) {
+            Ok(body) => Ok(Some(body)),
+            Err(RPMError::TagNotFound(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The `RPMSCRIPT_FLAG_*` bits of `scriptlet`, none if the header has no flags tag.
+    pub fn get_scriptlet_flags(&self, scriptlet: Scriptlet) -> Result<ScriptletFlags, RPMError> {
+        match self.get_entry_i32_array_data(scriptlet.flags_tag()) {
+            Ok(flags) => Ok(ScriptletFlags::from_bits(
+                flags.first().copied().unwrap_or_default() as u32,
+            )),
+            Err(RPMError::TagNotFound(_)) => Ok(ScriptletFlags::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// What a failure of `scriptlet` means for the package, `None` if the package has
+    /// no such scriptlet.
+    pub fn get_scriptlet_failure_policy(
+        &self,
+        scriptlet: Scriptlet,
+    ) -> Result<Option<FailurePolicy>, RPMError> {
+        if self.get_scriptlet_interpreter(scriptlet)?.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(
+            scriptlet.failure_policy(self.get_scriptlet_flags(scriptlet)?),
+        ))
+    }
+
+    /// Interpreter of `scriptlet` followed by its arguments, `None` if the package has no such scriptlet.
+    ///
+    /// A body without an interpreter is run by [`DEFAULT_INTERPRETER`].
+    pub fn get_scriptlet_interpreter(
+        &self,
+        scriptlet: Scriptlet,
+    ) -> Result<Option<Vec<String>>, RPMError> {
+        let entry = match self.find_entry_or_err(&scriptlet.program_tag()) {
+            Ok(entry) => entry,
+            Err(RPMError::TagNotFound(_)) => {
+                return Ok(self
+                    .get_scriptlet(scriptlet)?
+                    .map(|_| vec![DEFAULT_INTERPRETER.to_string()]))
+            }
+            Err(err) => return Err(err),
+        };
+        match &entry.data {
+            IndexData::StringTag(program) => Ok(Some(vec![program.clone()])),
+            IndexData::StringArray(program) => Ok(Some(program.clone())),
+            other => Err(RPMError::UnexpectedTagDataType {
+                expected_data_type: "string array",
+                actual_data_type: other.to_string(),
+                tag: entry.tag.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lua_scriptlets() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "")
+            .pre_trans_script("print('hello')")
+            .script_interpreter(Scriptlet::PreTrans, LUA_INTERPRETER)
+            .post_install_script("import sys")
+            .script_interpreter(Scriptlet::PostInstall, "/usr/bin/python3 -s")
+            .script_interpreter(Scriptlet::PostUninstall, "/sbin/ldconfig")
+            .post_trans_script("echo done")
+            .build()?;
+        let header = &pkg.metadata.header;
+        assert_eq!(
+            Some("print('hello')"),
+            header.get_scriptlet(Scriptlet::PreTrans)?
+        );
+        assert_eq!(
+            Some(vec!["<lua>".to_string()]),
+            header.get_scriptlet_interpreter(Scriptlet::PreTrans)?
+        );
+        assert_eq!(
+            Some(vec!["/usr/bin/python3".to_string(), "-s".to_string()]),
+            header.get_scriptlet_interpreter(Scriptlet::PostInstall)?
+        );
+        assert_eq!(None, header.get_scriptlet(Scriptlet::PostUninstall)?);
+        assert_eq!(
+            Some(vec!["/sbin/ldconfig".to_string()]),
+            header.get_scriptlet_interpreter(Scriptlet::PostUninstall)?
+        );
+        assert_eq!(
+            Some(vec![DEFAULT_INTERPRETER.to_string()]),
+            header.get_scriptlet_interpreter(Scriptlet::PostTrans)?
+        );
+        assert_eq!(
+            None,
+            header.get_scriptlet_interpreter(Scriptlet::PreInstall)?
+        );
+        let requires = header.get_requires()?;
+        assert!(requires
+            .iter()
+            .any(|dep| dep.dep_name == "rpmlib(BuiltinLuaScripts)"));
+        assert!(requires
+            .iter()
+            .any(|dep| dep.dep_name == "rpmlib(ScriptletInterpreterArgs)"));
+
+        let invalid =
+            |builder: RPMBuilder| matches!(builder.build(), Err(RPMError::InvalidScriptlet { .. }));
+        let builder = || RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "");
+        assert!(invalid(
+            builder().script_interpreter(Scriptlet::PreTrans, LUA_INTERPRETER)
+        ));
+        assert!(invalid(
+            builder()
+                .post_install_script("print(1)")
+                .script_interpreter(Scriptlet::PostInstall, "<lua> -x")
+        ));
+        assert!(invalid(
+            builder()
+                .post_install_script("print(1)")
+                .script_interpreter(Scriptlet::PostInstall, "lua")
+        ));
+        assert!(invalid(
+            builder()
+                .post_install_script("print(1)")
+                .script_interpreter(Scriptlet::PostInstall, LUA_INTERPRETER)
+                .append_script(IndexTag::RPMTAG_POSTIN, "/sbin/ldconfig")
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn file_triggers() -> Result<(), Box<dyn std::error::Error>> {
+        let builder = || RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "");
+        let pkg = builder()
+            .file_trigger(FileTrigger::In, &["/usr/lib/foo", "/usr/share/foo"], "cat")
+            .file_trigger(FileTrigger::TransPostUn, &["/usr/lib/foo"], "foo --refresh")
+            .file_trigger(FileTrigger::PostUn, &["/usr/lib/foo"], "true")
+            .build()?;
+        let header = &pkg.metadata.header;
+        assert_eq!(
+            vec!["cat", "true"],
+            header.get_entry_string_array_data(IndexTag::RPMTAG_FILETRIGGERSCRIPTS)?
+        );
+        assert_eq!(
+            vec!["/usr/lib/foo", "/usr/share/foo", "/usr/lib/foo"],
+            header.get_entry_string_array_data(IndexTag::RPMTAG_FILETRIGGERNAME)?
+        );
+        assert_eq!(
+            vec![0, 0, 1],
+            header.get_entry_i32_array_data(IndexTag::RPMTAG_FILETRIGGERINDEX)?
+        );
+        assert_eq!(
+            vec![
+                RPMSENSE_TRIGGERIN as i32,
+                RPMSENSE_TRIGGERIN as i32,
+                RPMSENSE_TRIGGERPOSTUN as i32
+            ],
+            header.get_entry_i32_array_data(IndexTag::RPMTAG_FILETRIGGERFLAGS)?
+        );
+        assert_eq!(
+            vec![DEFAULT_FILE_TRIGGER_PRIORITY],
+            header.get_entry_i32_array_data(IndexTag::RPMTAG_TRANSFILETRIGGERPRIORITIES)?
+        );
+        assert!(header
+            .get_requires()?
+            .iter()
+            .any(|dep| dep.dep_name == "rpmlib(FileTriggers)"));
+
+        assert!(matches!(
+            builder()
+                .file_trigger(FileTrigger::TransIn, &["usr/lib"], "true")
+                .build(),
+            Err(RPMError::InvalidScriptlet { .. })
+        ));
+        assert!(builder()
+            .file_trigger::<&str>(FileTrigger::TransIn, &[], "true")
+            .build()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn compose_bodies() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!("plain/path-1.0", shell_quote("plain/path-1.0"));
+        assert_eq!("''", shell_quote(""));
+        assert_eq!(r#"'it'\''s $HOME'"#, shell_quote("it's $HOME"));
+        assert_eq!("100%%", escape_macros("100%"));
+        assert_eq!("", ScriptBody::new().build());
+        assert_eq!(
+            "a\nb\nc\n",
+            ScriptBody::new().line("a").line("b\n").line("c").build()
+        );
+
+        let pkg = RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "")
+            .post_install_script(format!(
+                "echo %{{name}} installed at $(date +{})",
+                escape_macros("%s")
+            ))
+            .expand_script_macros(Scriptlet::PostInstall)
+            .build()?;
+        let header = &pkg.metadata.header;
+        assert_eq!(
+            vec![RPMSCRIPT_FLAG_EXPAND as i32],
+            header.get_entry_i32_array_data(IndexTag::RPMTAG_POSTINFLAGS)?
+        );
+        assert!(header
+            .get_entry_i32_array_data(IndexTag::RPMTAG_PREINFLAGS)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn failure_policies() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "")
+            .pre_install_script("getent group foo || groupadd -r foo")
+            .post_install_script("systemctl daemon-reload")
+            .post_uninstall_script("echo %{NAME}")
+            .expand_script_query_format(Scriptlet::PostUninstall)
+            .post_trans_script("foo --migrate")
+            .critical_script(Scriptlet::PostTrans)
+            .expand_script_macros(Scriptlet::PostTrans)
+            .script_flags(Scriptlet::PreUninstall, ScriptletFlags::from_bits(1))
+            .build()?;
+        let header = &pkg.metadata.header;
+        let flags = header.get_scriptlet_flags(Scriptlet::PostTrans)?;
+        assert_eq!(
+            RPMSCRIPT_FLAG_CRITICAL | RPMSCRIPT_FLAG_EXPAND,
+            flags.bits()
+        );
+        assert!(flags.is_critical() && flags.expands_macros() && !flags.expands_query_format());
+        assert!(header
+            .get_scriptlet_flags(Scriptlet::PostUninstall)?
+            .expands_query_format());
+        assert_eq!(
+            ScriptletFlags::default(),
+            header.get_scriptlet_flags(Scriptlet::PostInstall)?
+        );
+        // flags of scriptlets the package does not have are not stored
+        assert!(header
+            .get_entry_i32_array_data(IndexTag::RPMTAG_PREUNFLAGS)
+            .is_err());
+
+        for (scriptlet, policy) in [
+            (Scriptlet::PreInstall, Some(FailurePolicy::Abort)),
+            (Scriptlet::PostInstall, Some(FailurePolicy::Warn)),
+            (Scriptlet::PostUninstall, Some(FailurePolicy::Warn)),
+            (Scriptlet::PostTrans, Some(FailurePolicy::Abort)),
+            (Scriptlet::PreUninstall, None),
+        ] {
+            assert_eq!(
+                policy,
+                header.get_scriptlet_failure_policy(scriptlet)?,
+                "{:?}",
+                scriptlet
+            );
+        }
+        Ok(())
+    }
+}
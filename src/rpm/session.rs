@@ -0,0 +1,366 @@
+//! Building a set of related packages with a zstd dictionary trained on all of them.
+//!
+//! Many small packages with similar content, like the plugins of an application,
+//! compress far better when zstd starts from a dictionary of what they have in
+//! common.
+//!
+//! # Warning
+//!
+//! **rpm, dnf and yum cannot install these packages.** The dictionary is needed to
+//! read the payloads again and rpm has no way to load it, so every package requires
+//! `rpmlib(PayloadZstdDictionary)`, which no rpm provides, and is refused instead of
+//! failing halfway. Sessions only build after
+//! [`accept_uninstallable`](BuildSession::accept_uninstallable). Use such packages
+//! with tools that load the dictionary, for example from repodata via
+//! [`SharedDictionary::attach`].
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut session = rpm::BuildSession::new().accept_uninstallable(true);
+//! for plugin in ["csv", "json", "xml"].iter() {
+//!     session = session.package(
+//!         rpm::RPMBuilder::new(&format!("foo-{}", plugin), "1.0.0", "MIT", "noarch", "A plugin")
+//!             .with_file(
+//!                 format!("./plugins/{}.py", plugin),
+//!                 rpm::RPMFileOptions::new(format!("/usr/lib/foo/plugins/{}.py", plugin)),
+//!             )?,
+//!     );
+//! }
+//! let build = session.build()?;
+//! let mut repomd = rpm::repodata::Repomd::open("./repo")?;
+//! build.dictionary.attach(&mut repomd, "./repo")?;
+//! repomd.write("./repo")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::errors::*;
+
+use super::compressor::{CompressionKind, Compressor, PayloadCompression};
+use super::payload::PayloadEntry;
+use super::repodata::{Repomd, RepomdRecord};
+use super::{RPMBuilder, RPMPackage};
+
+/// Dictionary size of [`BuildSession::new`], the default of the zstd command line tool.
+pub const DEFAULT_DICTIONARY_SIZE: usize = 112_640;
+
+/// Content is cut into samples of at most this size for training.
+const SAMPLE_SIZE: usize = 16 * 1024;
+
+/// Training looks at no more than this multiple of the dictionary size.
+const MAX_TRAINING_RATIO: usize = 100;
+
+/// Repomd record kind of [`SharedDictionary::attach`].
+pub const DICTIONARY_RECORD: &str = "zstd_dictionary";
+
+const DICTIONARY_MAGIC: [u8; 4] = [0x37, 0xa4, 0x30, 0xec];
+
+/// A zstd dictionary payloads of a [`BuildSession`] are compressed with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedDictionary {
+    data: Vec<u8>,
+}
+
+impl SharedDictionary {
+    /// Use a dictionary trained before, e.g. to build updates of a plugin set with
+    /// the dictionary its repository already has.
+    pub fn new(data: Vec<u8>) -> Result<Self, RPMError> {
+        if data.len() < 8 || data[..4] != DICTIONARY_MAGIC {
+            return Err(RPMError::InvalidDictionary {
+                reason: "not a zstd dictionary".to_string(),
+            });
+        }
+        Ok(SharedDictionary { data })
+    }
+
+    /// Train a dictionary of at most `max_size` bytes on the file content of `builders`.
+    pub fn train<'a, I>(builders: I, max_size: usize) -> Result<Self, RPMError>
+    where
+        I: IntoIterator<Item = &'a RPMBuilder>,
+    {
+        let budget = max_size.saturating_mul(MAX_TRAINING_RATIO);
+        let mut samples = Vec::new();
+        let mut total = 0;
+        'builders: for builder in builders {
+            for content in builder
+                .files
+                .values()
+                .filter_map(|entry| entry.content.as_ref())
+            {
+                for sample in content.chunks(SAMPLE_SIZE) {
+                    if total + sample.len() > budget {
+                        break 'builders;
+                    }
+                    total += sample.len();
+                    samples.push(sample);
+                }
+            }
+        }
+        if samples.is_empty() {
+            return Err(RPMError::InvalidDictionary {
+                reason: "no file content to train on".to_string(),
+            });
+        }
+        let data = zstd::dict::from_samples(&samples, max_size).map_err(|err| {
+            RPMError::InvalidDictionary {
+                reason: format!("training failed - {}", err),
+            }
+        })?;
+        Self::new(data)
+    }
+
+    /// The id zstd writes into every frame compressed with the dictionary.
+    pub fn id(&self) -> u32 {
+        u32::from_le_bytes([self.data[4], self.data[5], self.data[6], self.data[7]])
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Write the dictionary into the `repodata` directory of `repo` and reference it
+    /// from `repomd` as [`DICTIONARY_RECORD`].
+    pub fn attach<P: AsRef<Path>>(
+        &self,
+        repomd: &mut Repomd,
+        repo: P,
+    ) -> Result<RepomdRecord, RPMError> {
+        repomd
+            .attach(
+                repo,
+                DICTIONARY_RECORD,
+                "payload.dict",
+                &self.data,
+                Compressor::None(Vec::new()),
+            )
+            .cloned()
+    }
+}
+
+/// The packages of a session and the dictionary they share.
+#[derive(Debug)]
+pub struct SessionBuild {
+    /// In the order they were added.
+    pub packages: Vec<RPMPackage>,
+    pub dictionary: SharedDictionary,
+}
+
+/// Build several packages with one zstd dictionary, see the [module documentation](self).
+///
+/// **rpm cannot install the packages**, see [`accept_uninstallable`](Self::accept_uninstallable).
+///
+/// Payloads are compressed with zstd at the [level](RPMBuilder::zstd_level) of each
+/// builder, whatever compressor it was set to. Builders writing segmented payloads keep them, without the dictionary.
+#[derive(Default)]
+pub struct BuildSession {
+    builders: Vec<RPMBuilder>,
+    dictionary: Option<SharedDictionary>,
+    dictionary_size: Option<usize>,
+    accept_uninstallable: bool,
+}
+
+impl BuildSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn package(mut self, builder: RPMBuilder) -> Self {
+        self.builders.push(builder);
+        self
+    }
+
+    /// Use `dictionary` instead of training one.
+    pub fn dictionary(mut self, dictionary: SharedDictionary) -> Self {
+        self.dictionary = Some(dictionary);
+        self
+    }
+
+    /// Upper bound of the trained dictionary, [`DEFAULT_DICTIONARY_SIZE`] unless set.
+    pub fn dictionary_size(mut self, dictionary_size: usize) -> Self {
+        self.dictionary_size = Some(dictionary_size);
+        self
+    }
+
+    /// Build although rpm cannot install the packages, which [`build`](Self::build)
+    /// refuses otherwise.
+    ///
+    /// **Only set this for packages consumed by tools that load the dictionary**, rpm
+    /// refuses them for their `rpmlib(PayloadZstdDictionary)` requirement.
+    pub fn accept_uninstallable(mut self, accept: bool) -> Self {
+        self.accept_uninstallable = accept;
+        self
+    }
+
+    /// Train the dictionary unless one was given and build every package with it.
+    ///
+    /// Fails with [`RPMError::UninstallablePayloads`] unless
+    /// [`accept_uninstallable`](Self::accept_uninstallable).
+    pub fn build(self) -> Result<SessionBuild, RPMError> {
+        if !self.accept_uninstallable {
+            return Err(RPMError::UninstallablePayloads);
+        }
+        let dictionary = match self.dictionary {
+            Some(dictionary) => dictionary,
+            None => SharedDictionary::train(
+                self.builders.iter(),
+                self.dictionary_size.unwrap_or(DEFAULT_DICTIONARY_SIZE),
+            )?,
+        };
+        let mut packages = Vec::with_capacity(self.builders.len());
+        let shared = Arc::<[u8]>::from(dictionary.data.as_slice());
+        for mut builder in self.builders {
+            if !builder.segmented() {
+                builder.compression = PayloadCompression {
+                    kind: CompressionKind::Zstd,
+                    level: builder.compression.level,
+                    dictionary: Some(shared.clone()),
+                };
+                builder.require_rpmlib("PayloadZstdDictionary", "1");
+            }
+            packages.push(builder.build()?);
+        }
+        Ok(SessionBuild {
+            packages,
+            dictionary,
+        })
+    }
+}
+
+impl RPMPackage {
+    /// Like [`payload_entries`](Self::payload_entries) for payloads compressed with the
+    /// dictionary of a [`BuildSession`].
+    pub fn payload_entries_with_dictionary(
+        &self,
+        dictionary: &SharedDictionary,
+    ) -> Result<Vec<PayloadEntry>, RPMError> {
+        match self.metadata.header.get_payload_compressor() {
+            Ok("zstd") => self.read_payload_entries(zstd::stream::Decoder::with_dictionary(
+                BufReader::new(&self.content[..]),
+                &dictionary.data,
+            )?),
+            _ => self.payload_entries(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Dependency, RPMFileOptions};
+
+    fn plugins() -> Result<Vec<RPMBuilder>, RPMError> {
+        let mut builders = Vec::new();
+        for index in 0..40 {
+            let mut builder = RPMBuilder::new(
+                &format!("foo-plugin-{}", index),
+                "1.0.0",
+                "MIT",
+                "noarch",
+                "A plugin",
+            );
+            let content = format!(
+                "[plugin]\nname = \"plugin-{0}\"\ndescription = \"Adds support for format {0}\"\n\
+                 entry_point = \"foo.plugins.format_{0}:register\"\nrequires_api = \"1.0\"\n\
+                 [plugin.options]\nenabled = true\npriority = {0}\n",
+                index
+            );
+            builder.add_data(
+                content.into_bytes(),
+                crate::Timestamp::EPOCH,
+                RPMFileOptions::new(format!("/etc/foo/plugins/{}.toml", index)).into(),
+            )?;
+            builders.push(builder);
+        }
+        Ok(builders)
+    }
+
+    #[test]
+    fn session() -> Result<(), Box<dyn std::error::Error>> {
+        let plain = plugins()?
+            .into_iter()
+            .map(|builder| {
+                builder
+                    .compression(Compressor::Zstd(zstd::stream::Encoder::new(
+                        Vec::new(),
+                        19,
+                    )?))
+                    .build()
+                    .map(|pkg| pkg.content.len())
+            })
+            .sum::<Result<usize, RPMError>>()?;
+
+        let mut session = BuildSession::new().dictionary_size(4096);
+        for builder in plugins()? {
+            session = session.package(builder);
+        }
+        assert!(matches!(
+            BuildSession::new().package(plugins()?.remove(0)).build(),
+            Err(RPMError::UninstallablePayloads)
+        ));
+        let build = session.accept_uninstallable(true).build()?;
+        assert_eq!(40, build.packages.len());
+        let shared = build
+            .packages
+            .iter()
+            .map(|pkg| pkg.content.len())
+            .sum::<usize>();
+        assert!(shared < plain, "{} >= {}", shared, plain);
+
+        let pkg = &build.packages[7];
+        assert_eq!("foo-plugin-7", pkg.metadata.header.get_name()?);
+        let entries = pkg.payload_entries_with_dictionary(&build.dictionary)?;
+        assert_eq!("/etc/foo/plugins/7.toml", entries[0].path);
+        assert!(pkg.payload_entries().is_err());
+        assert!(pkg
+            .metadata
+            .header
+            .get_requires()?
+            .contains(&Dependency::rpmlib("PayloadZstdDictionary", "1")));
+
+        // clones compress with the same level and dictionary
+        let mut builder = plugins()?.remove(7);
+        builder.compression = PayloadCompression {
+            kind: CompressionKind::Zstd,
+            level: 3,
+            dictionary: Some(Arc::from(build.dictionary.data.as_slice())),
+        };
+        let cloned = builder.clone().build()?;
+        assert_eq!(builder.build()?.content, cloned.content);
+        assert_eq!(
+            "3",
+            cloned
+                .metadata
+                .header
+                .get_entry_string_data(crate::IndexTag::RPMTAG_PAYLOADFLAGS)?
+        );
+        let entries = cloned.payload_entries_with_dictionary(&build.dictionary)?;
+        assert_eq!("/etc/foo/plugins/7.toml", entries[0].path);
+
+        let dir = crate::tests::TestDir::new("session");
+        let mut repomd = Repomd::new();
+        let record = build.dictionary.attach(&mut repomd, &dir)?;
+        assert_eq!(
+            Some(record.clone()),
+            repomd.record(DICTIONARY_RECORD).cloned()
+        );
+        let written = std::fs::read(dir.join(&record.location))?;
+        assert_eq!(build.dictionary, SharedDictionary::new(written)?);
+
+        assert!(matches!(
+            SharedDictionary::new(b"not a dictionary".to_vec()),
+            Err(RPMError::InvalidDictionary { .. })
+        ));
+        assert!(matches!(
+            BuildSession::new()
+                .package(RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo"))
+                .accept_uninstallable(true)
+                .build(),
+            Err(RPMError::InvalidDictionary { .. })
+        ));
+        Ok(())
+    }
+}
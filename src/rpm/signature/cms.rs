@@ -0,0 +1,363 @@
+//! CMS (RFC 5652) signatures with X.509 certificates using the `openssl` crate.
+//!
+//! Not understood by rpm itself, some appliance vendors sign packages this way
+//! instead of with OpenPGP keys. The signature is detached over the header and
+//! stored in [`RPMSIGTAG_CMS`](crate::IndexSignatureTag::RPMSIGTAG_CMS), see
+//! [`RPMPackage::sign_cms`](crate::RPMPackage::sign_cms).
+
+use super::traits;
+use crate::errors::RPMError;
+
+use std::io::Read;
+
+use ::openssl::cms::{CMSOptions, CmsContentInfo};
+use ::openssl::pkcs7::{Pkcs7, Pkcs7Flags};
+use ::openssl::pkey::{PKey, Private};
+use ::openssl::stack::Stack;
+use ::openssl::x509::store::X509StoreBuilder;
+use ::openssl::x509::{X509PurposeId, X509};
+
+/// Signer holding a certificate and its private key, both PEM encoded.
+#[derive(Clone, Debug)]
+pub struct Signer {
+    certificate: X509,
+    /// Intermediate certificates included in the signature.
+    chain: Vec<X509>,
+    key: PKey<Private>,
+}
+
+impl Signer {
+    /// Load the signing certificate, optionally followed by the intermediate
+    /// certificates up to the trust anchor, and the matching private key.
+    pub fn load_from_pem(certificate: &[u8], key: &[u8]) -> Result<Self, RPMError> {
+        let mut certificates = X509::stack_from_pem(certificate)
+            .map_err(|e| RPMError::KeyLoadError {
+                source: Box::new(e),
+                details: "Failed to parse PEM certificates",
+            })?
+            .into_iter();
+        let certificate = certificates.next().ok_or_else(|| RPMError::KeyLoadError {
+            source: "no certificate found".into(),
+            details: "Failed to parse PEM certificates",
+        })?;
+        let key = PKey::private_key_from_pem(key).map_err(|e| RPMError::KeyLoadError {
+            source: Box::new(e),
+            details: "Failed to parse PEM private key",
+        })?;
+        Ok(Self {
+            certificate,
+            chain: certificates.collect(),
+            key,
+        })
+    }
+}
+
+impl traits::Signing<traits::algorithm::CMS> for Signer {
+    type Signature = Vec<u8>;
+
+    /// DER encoded signature, containing the certificates but not the data.
+    fn sign<R: Read>(&self, mut data: R) -> Result<Self::Signature, RPMError> {
+        let mut content = Vec::new();
+        data.read_to_end(&mut content)?;
+        let mut chain = Stack::new().map_err(|e| RPMError::SignError(Box::new(e)))?;
+        for certificate in &self.chain {
+            chain
+                .push(certificate.clone())
+                .map_err(|e| RPMError::SignError(Box::new(e)))?;
+        }
+        CmsContentInfo::sign(
+            Some(&self.certificate),
+            Some(&self.key),
+            Some(&chain),
+            Some(&content),
+            CMSOptions::DETACHED | CMSOptions::BINARY,
+        )
+        .and_then(|signature| signature.to_der())
+        .map_err(|e| RPMError::SignError(Box::new(e)))
+    }
+}
+
+/// What a signing certificate must be issued for to be accepted by a [`Verifier`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SignerPurpose {
+    /// Code signing, the certificate needs the codeSigning extended key usage.
+    #[default]
+    CodeSigning,
+    /// Any purpose, the key usage of the certificate is not checked.
+    Any,
+}
+
+/// Verifier accepting signatures by certificates that chain up to one of its
+/// trust anchors.
+///
+/// Certificates are checked for their validity period and, by default, for being
+/// issued for code signing, see [`purpose`](Self::purpose).
+#[derive(Clone, Debug)]
+pub struct Verifier {
+    trust_anchors: Vec<X509>,
+    purpose: SignerPurpose,
+}
+
+impl Verifier {
+    /// Load the trust anchors, one or more PEM encoded certificates.
+    pub fn load_from_pem(trust_anchors: &[u8]) -> Result<Self, RPMError> {
+        let trust_anchors =
+            X509::stack_from_pem(trust_anchors).map_err(|e| RPMError::KeyLoadError {
+                source: Box::new(e),
+                details: "Failed to parse PEM certificates",
+            })?;
+        if trust_anchors.is_empty() {
+            return Err(RPMError::KeyLoadError {
+                source: "no certificate found".into(),
+                details: "Failed to parse PEM certificates",
+            });
+        }
+        Ok(Self {
+            trust_anchors,
+            purpose: SignerPurpose::default(),
+        })
+    }
+
+    /// Set what the signing certificate must be issued for.
+    pub fn purpose(mut self, purpose: SignerPurpose) -> Self {
+        self.purpose = purpose;
+        self
+    }
+
+    /// Subjects of the trust anchors, to refer to them in errors.
+    fn key_ref(&self) -> String {
+        self.trust_anchors
+            .iter()
+            .map(|anchor| {
+                anchor
+                    .subject_name()
+                    .entries()
+                    .filter_map(|entry| {
+                        let name = entry.object().nid().short_name().ok()?;
+                        Some(format!("{}={}", name, entry.data().to_string().ok()?))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+impl traits::Verifying<traits::algorithm::CMS> for Verifier {
+    type Signature = Vec<u8>;
+
+    fn verify<R: Read>(&self, mut data: R, signature: &[u8]) -> Result<(), RPMError> {
+        let mut content = Vec::new();
+        data.read_to_end(&mut content)?;
+        let failed = |e: ::openssl::error::ErrorStack| RPMError::VerificationError {
+            source: Box::new(e),
+            key_ref: self.key_ref(),
+        };
+        let mut store = X509StoreBuilder::new().map_err(failed)?;
+        for anchor in &self.trust_anchors {
+            store.add_cert(anchor.clone()).map_err(failed)?;
+        }
+        // the default purpose would require certificates for S/MIME, the key usage
+        // is checked below as OpenSSL only knows code signing since 3.2
+        store.set_purpose(X509PurposeId::ANY).map_err(failed)?;
+        let store = store.build();
+        let mut cms = CmsContentInfo::from_der(signature).map_err(failed)?;
+        cms.verify(None, Some(&store), Some(&content), None, CMSOptions::BINARY)
+            .map_err(failed)?;
+        if self.purpose == SignerPurpose::CodeSigning {
+            // the signers are looked up among the certificates in the signature
+            let certificates = Stack::<X509>::new().map_err(failed)?;
+            let signers = Pkcs7::from_der(signature)
+                .and_then(|pkcs7| pkcs7.signers(&certificates, Pkcs7Flags::empty()))
+                .map_err(failed)?;
+            let mut code_signing = !signers.is_empty();
+            for signer in &signers {
+                code_signing &= is_code_signing(&signer.to_der().map_err(failed)?);
+            }
+            if !code_signing {
+                return Err(RPMError::VerificationError {
+                    source: "signing certificate is not issued for code signing".into(),
+                    key_ref: self.key_ref(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the DER encoded `certificate` lists codeSigning in its extended key usage.
+fn is_code_signing(certificate: &[u8]) -> bool {
+    const EXTENDED_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x25];
+    const CODE_SIGNING: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x03];
+    let usages = || -> Option<bool> {
+        let (_, certificate, _) = der_element(certificate, 0x30)?;
+        let (_, mut tbs, _) = der_element(certificate, 0x30)?;
+        // the extensions are the explicitly tagged [3] field
+        let mut extensions = loop {
+            let (tag, content, rest) = der_any(tbs)?;
+            if tag == 0xa3 {
+                break der_element(content, 0x30)?.1;
+            }
+            tbs = rest;
+        };
+        while !extensions.is_empty() {
+            let (_, extension, rest) = der_element(extensions, 0x30)?;
+            extensions = rest;
+            let (_, oid, mut value) = der_element(extension, 0x06)?;
+            if oid != EXTENDED_KEY_USAGE {
+                continue;
+            }
+            if let Some((_, _, rest)) = der_element(value, 0x01) {
+                value = rest;
+            }
+            let (_, value, _) = der_element(value, 0x04)?;
+            let (_, mut purposes, _) = der_element(value, 0x30)?;
+            while !purposes.is_empty() {
+                let (_, purpose, rest) = der_element(purposes, 0x06)?;
+                if purpose == CODE_SIGNING {
+                    return Some(true);
+                }
+                purposes = rest;
+            }
+        }
+        Some(false)
+    };
+    usages().unwrap_or(false)
+}
+
+/// Split the DER element with `tag` off `input`, see [`der_any`].
+fn der_element(input: &[u8], tag: u8) -> Option<(u8, &[u8], &[u8])> {
+    der_any(input).filter(|(found, _, _)| *found == tag)
+}
+
+/// Split the first DER element off `input` into its tag, content and the rest.
+fn der_any(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, mut input) = input.split_first()?;
+    let len = if first & 0x80 == 0 {
+        first as usize
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || input.len() < count {
+            return None;
+        }
+        let (bytes, rest) = input.split_at(count);
+        input = rest;
+        bytes
+            .iter()
+            .fold(0usize, |len, byte| len << 8 | *byte as usize)
+    };
+    if input.len() < len {
+        return None;
+    }
+    let (content, rest) = input.split_at(len);
+    Some((tag, content, rest))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::signature::{Signing, Verifying};
+
+    pub(crate) fn load_pem_assets() -> (Signer, Verifier) {
+        let signer = Signer::load_from_pem(
+            include_bytes!("../../../test_assets/cms_signing_cert.pem"),
+            include_bytes!("../../../test_assets/cms_signing_key.pem"),
+        )
+        .expect("signing certificate and key load");
+        let verifier = Verifier::load_from_pem(include_bytes!("../../../test_assets/cms_ca.pem"))
+            .expect("trust anchor loads");
+        (signer, verifier)
+    }
+
+    #[test]
+    fn sign_and_verify() -> Result<(), Box<dyn std::error::Error>> {
+        let (signer, verifier) = load_pem_assets();
+        let data = b"dfasdfasdfasdf asdf asdf asdf";
+        let signature = signer.sign(&data[..])?;
+        verifier.verify(&data[..], &signature)?;
+        assert!(verifier.verify(&b"tampered"[..], &signature).is_err());
+        assert!(verifier.verify(&data[..], b"not a signature").is_err());
+
+        let other =
+            Verifier::load_from_pem(include_bytes!("../../../test_assets/cms_other_ca.pem"))?;
+        match other.verify(&data[..], &signature) {
+            Err(RPMError::VerificationError { key_ref, .. }) => {
+                assert_eq!("CN=rpm other test CA", key_ref)
+            }
+            other => panic!("expected a verification error, got {:?}", other),
+        }
+        assert!(Verifier::load_from_pem(b"").is_err());
+        Ok(())
+    }
+
+    /// A certificate authority and a leaf it issued for TLS servers rather than code
+    /// signing.
+    fn server_certificates() -> Result<(X509, Signer), ::openssl::error::ErrorStack> {
+        use ::openssl::asn1::Asn1Time;
+        use ::openssl::hash::MessageDigest;
+        use ::openssl::rsa::Rsa;
+        use ::openssl::x509::extension::{BasicConstraints, ExtendedKeyUsage, KeyUsage};
+        use ::openssl::x509::{X509Name, X509NameBuilder};
+
+        let name = |cn: &str| -> Result<X509Name, ::openssl::error::ErrorStack> {
+            let mut name = X509NameBuilder::new()?;
+            name.append_entry_by_text("CN", cn)?;
+            Ok(name.build())
+        };
+        let certificate = |subject: &X509Name,
+                           issuer: &X509Name,
+                           key: &PKey<Private>,
+                           issuer_key: &PKey<Private>,
+                           ca: bool| {
+            let mut builder = X509::builder()?;
+            builder.set_version(2)?;
+            builder.set_subject_name(subject)?;
+            builder.set_issuer_name(issuer)?;
+            builder.set_pubkey(key)?;
+            builder.set_not_before(&*Asn1Time::days_from_now(0)?)?;
+            builder.set_not_after(&*Asn1Time::days_from_now(1)?)?;
+            if ca {
+                builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+                builder.append_extension(KeyUsage::new().critical().key_cert_sign().build()?)?;
+            } else {
+                builder.append_extension(BasicConstraints::new().build()?)?;
+                builder
+                    .append_extension(KeyUsage::new().critical().digital_signature().build()?)?;
+                builder.append_extension(ExtendedKeyUsage::new().server_auth().build()?)?;
+            }
+            builder.sign(issuer_key, MessageDigest::sha256())?;
+            Ok::<_, ::openssl::error::ErrorStack>(builder.build())
+        };
+
+        let ca_key = PKey::from_rsa(Rsa::generate(2048)?)?;
+        let ca_name = name("rpm server test CA")?;
+        let ca = certificate(&ca_name, &ca_name, &ca_key, &ca_key, true)?;
+        let key = PKey::from_rsa(Rsa::generate(2048)?)?;
+        let leaf = certificate(&name("rpm test server")?, &ca_name, &key, &ca_key, false)?;
+        let signer = Signer {
+            certificate: leaf,
+            chain: Vec::new(),
+            key,
+        };
+        Ok((ca, signer))
+    }
+
+    #[test]
+    fn verify_requires_code_signing() -> Result<(), Box<dyn std::error::Error>> {
+        let (ca, signer) = server_certificates()?;
+        let verifier = Verifier::load_from_pem(&ca.to_pem()?)?;
+        let data = b"dfasdfasdfasdf asdf asdf asdf";
+        let signature = signer.sign(&data[..])?;
+        assert!(matches!(
+            verifier.verify(&data[..], &signature),
+            Err(RPMError::VerificationError { .. })
+        ));
+        verifier
+            .purpose(SignerPurpose::Any)
+            .verify(&data[..], &signature)?;
+        Ok(())
+    }
+}
@@ -1,9 +1,15 @@
 mod traits;
 pub use self::traits::*;
 
+mod policy;
+pub use self::policy::*;
+
 #[cfg(feature = "signature-pgp")]
 pub mod pgp;
 
+#[cfg(feature = "signature-cms")]
+pub mod cms;
+
 /// test helper to print signatures
 pub fn echo_signature(scope: &str, signature: &[u8]) {
     log::debug!(
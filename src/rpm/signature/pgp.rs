@@ -1,4 +1,5 @@
 use super::traits;
+use super::{DigestAlgorithm, KeyAlgorithm, VerifyPolicy};
 use crate::errors::RPMError;
 
 use std::io::{Cursor, Read};
@@ -7,11 +8,9 @@ use ::pgp::{composed::Deserializable, types::KeyTrait};
 
 use ::pgp::packet::*;
 
-fn now() -> ::chrono::DateTime<::chrono::Utc> {
-    // accuracy of serialized format is only down to seconds
-    use ::chrono::offset::TimeZone;
-    let now = ::chrono::offset::Utc::now();
-    ::chrono::offset::Utc.timestamp(now.timestamp(), 0u32)
+fn now() -> Result<::chrono::DateTime<::chrono::Utc>, RPMError> {
+    // signature creation times are unsigned seconds, like the time stamps of headers
+    Ok(crate::Timestamp::now()?.into())
 }
 
 /// Signer implementation using the `pgp` crate.
@@ -31,14 +30,22 @@ impl traits::Signing<traits::algorithm::RSA> for Signer {
     /// it internally creates a copy until crate `pgp` provides
     /// a `Read` based implementation.
     fn sign<R: Read>(&self, data: R) -> Result<Self::Signature, RPMError> {
-        let passwd_fn = String::new;
+        self.sign_at(data, now()?)
+    }
+}
 
-        let now = now();
+impl Signer {
+    fn sign_at<R: Read>(
+        &self,
+        data: R,
+        now: ::chrono::DateTime<::chrono::Utc>,
+    ) -> Result<Vec<u8>, RPMError> {
+        let passwd_fn = String::new;
 
         let sig_cfg = SignatureConfig {
             version: SignatureVersion::V4,
             typ: SignatureType::Binary,
-            pub_alg: ::pgp::crypto::public_key::PublicKeyAlgorithm::RSA,
+            pub_alg: self.secret_key.algorithm(),
             hash_alg: ::pgp::crypto::hash::HashAlgorithm::SHA2_256,
             issuer: Some(self.secret_key.key_id()),
             created: Some(now),
@@ -61,9 +68,7 @@ impl traits::Signing<traits::algorithm::RSA> for Signer {
 
         Ok(signature_bytes)
     }
-}
 
-impl Signer {
     /// load the private key for signing
     pub fn load_from_asc_bytes(input: &[u8]) -> Result<Self, RPMError> {
         // only asc loading is supported right now
@@ -92,9 +97,149 @@ impl Signer {
 #[derive(Clone, Debug)]
 pub struct Verifier {
     public_key: ::pgp::composed::signed_key::SignedPublicKey,
+    key_validity: KeyValidity,
+}
+
+/// When the key of a [`Verifier`] has to be valid, i.e. neither expired nor revoked,
+/// for signatures to verify.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum KeyValidity {
+    /// When the signature was created, packages signed before the key expired keep verifying.
+    ///
+    /// Keys revoked because they were compromised or without a reason are never valid,
+    /// superseded and retired keys are valid before their revocation.
+    #[default]
+    AtSigningTime,
+    /// When verifying, packages stop verifying once the key expired or was revoked.
+    AtVerificationTime,
+    /// Accept expired and revoked keys.
+    Ignore,
 }
 
 impl Verifier {
+    /// Check the validity of the key, see [`KeyValidity::AtSigningTime`] for the default.
+    pub fn key_validity(mut self, validity: KeyValidity) -> Self {
+        self.key_validity = validity;
+        self
+    }
+
+    /// Fingerprint of the primary key in uppercase hex, like `gpg --fingerprint`
+    /// prints it without the spaces.
+    pub fn fingerprint(&self) -> String {
+        hex::encode_upper(self.public_key.fingerprint())
+    }
+
+    /// Checks after the signature verified with the primary key or `subkey`.
+    fn check_key(
+        &self,
+        signature: &::pgp::packet::Signature,
+        subkey: Option<&::pgp::composed::signed_key::SignedPublicSubKey>,
+        policy: &VerifyPolicy,
+    ) -> Result<(), RPMError> {
+        let (key_ref, algorithm, params) = match subkey {
+            Some(subkey) => (
+                format!("{:?}", subkey.key.key_id()),
+                subkey.key.algorithm(),
+                subkey.key.public_params(),
+            ),
+            None => (
+                format!("{:?}", self.public_key.key_id()),
+                self.public_key.primary_key.algorithm(),
+                self.public_key.primary_key.public_params(),
+            ),
+        };
+        let rsa_bits = match params {
+            ::pgp::types::PublicParams::RSA { n, .. } => {
+                let n = n.as_bytes();
+                Some(n.len() * 8 - n.first().map_or(0, |byte| byte.leading_zeros() as usize))
+            }
+            _ => None,
+        };
+        policy.check_signature(
+            digest_algorithm(signature.config.hash_alg),
+            key_algorithm(algorithm),
+            rsa_bits,
+            &key_ref,
+        )?;
+        self.check_validity(signature, subkey)
+    }
+
+    /// Reject the signature if the key that verified it was not valid at the time
+    /// the policy asks for, `subkey` is `None` for the primary key.
+    fn check_validity(
+        &self,
+        signature: &::pgp::packet::Signature,
+        subkey: Option<&::pgp::composed::signed_key::SignedPublicSubKey>,
+    ) -> Result<(), RPMError> {
+        let at = match self.key_validity {
+            KeyValidity::Ignore => return Ok(()),
+            KeyValidity::AtSigningTime => match signature.created() {
+                Some(created) => *created,
+                None => now()?,
+            },
+            KeyValidity::AtVerificationTime => now()?,
+        };
+        let key_ref = match subkey {
+            Some(subkey) => format!("{:?}", subkey.key.key_id()),
+            None => format!("{:?}", self.public_key.key_id()),
+        };
+
+        // revoking or expiring the primary key invalidates its subkeys as well
+        check_revocations(&self.public_key.details.revocation_signatures, at, &key_ref)?;
+        let mut expires_at = self.public_key.expires_at();
+        if let Some(subkey) = subkey {
+            let revocations = subkey
+                .signatures
+                .iter()
+                .filter(|signature| signature.typ() == SignatureType::SubkeyRevocation)
+                .cloned()
+                .collect::<Vec<_>>();
+            check_revocations(&revocations, at, &key_ref)?;
+            expires_at = earliest(expires_at, subkey_expires_at(subkey));
+        }
+        match expires_at {
+            Some(expires_at) if at >= expires_at => Err(RPMError::KeyExpired {
+                key_ref,
+                expired_at: expires_at.to_rfc3339(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Digest over the key with all its revocation and binding signatures and the
+    /// validity mode, which changes whenever a signature verified before might
+    /// no longer verify, except for keys expiring meanwhile, see [`valid_until`](Self::valid_until).
+    #[cfg(feature = "cache")]
+    pub(crate) fn key_state(&self) -> Result<String, RPMError> {
+        use ::pgp::ser::Serialize;
+        use sha2::Digest;
+        let key = self
+            .public_key
+            .to_bytes()
+            .map_err(|e| RPMError::KeyLoadError {
+                source: Box::new(e),
+                details: "Failed to serialize public key",
+            })?;
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(&key);
+        hasher.update(format!("{:?}", self.key_validity));
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    /// With [`KeyValidity::AtVerificationTime`], the earliest time the primary key or
+    /// one of its subkeys expires, after which signatures may no longer verify.
+    #[cfg(feature = "cache")]
+    pub(crate) fn valid_until(&self) -> Option<::chrono::DateTime<::chrono::Utc>> {
+        if self.key_validity != KeyValidity::AtVerificationTime {
+            return None;
+        }
+        self.public_key
+            .public_subkeys
+            .iter()
+            .map(subkey_expires_at)
+            .fold(self.public_key.expires_at(), earliest)
+    }
+
     fn parse_signature(signature: &[u8]) -> Result<::pgp::packet::Signature, RPMError> {
         let mut cursor = Cursor::new(signature);
         let parser = ::pgp::packet::PacketParser::new(&mut cursor);
@@ -114,7 +259,16 @@ impl traits::Verifying<traits::algorithm::RSA> for Verifier {
     /// Despite the fact the API suggest zero copy pattern,
     /// it internally creates a copy until crate `pgp` provides
     /// a `Read` based implementation.
-    fn verify<R: Read>(&self, mut data: R, signature: &[u8]) -> Result<(), RPMError> {
+    fn verify<R: Read>(&self, data: R, signature: &[u8]) -> Result<(), RPMError> {
+        self.verify_with_policy(data, signature, &VerifyPolicy::default())
+    }
+
+    fn verify_with_policy<R: Read>(
+        &self,
+        mut data: R,
+        signature: &[u8],
+        policy: &VerifyPolicy,
+    ) -> Result<(), RPMError> {
         let signature = Self::parse_signature(signature)?;
 
         log::debug!("Signature issued by: {:?}", signature.issuer());
@@ -123,12 +277,13 @@ impl traits::Verifying<traits::algorithm::RSA> for Verifier {
             log::trace!("Signature has issuer ref: {:?}", key_id);
 
             if self.public_key.key_id() == *key_id {
-                return signature.verify(&self.public_key, data).map_err(|e| {
+                signature.verify(&self.public_key, data).map_err(|e| {
                     RPMError::VerificationError {
                         source: Box::new(e),
                         key_ref: format!("{:?}", key_id),
                     }
-                });
+                })?;
+                return self.check_key(&signature, None, policy);
             } else {
                 log::trace!(
                     "Signature issuer key id {:?} does not match primary keys key id: {:?}",
@@ -158,18 +313,22 @@ impl traits::Verifying<traits::algorithm::RSA> for Verifier {
                         key_ref: format!("{:?}", key_id),
                     }),
                     |previous_res, sub_key| {
-                        if previous_res.is_err() {
-                            log::trace!("Test next candidate subkey");
-                            signature.verify(sub_key, &mut data).map_err(|e| {
-                                RPMError::VerificationError {
-                                    source: Box::new(e),
-                                    key_ref: format!("{:?}", sub_key.key_id()),
-                                }
+                        let previous = match previous_res {
+                            Ok(()) => {
+                                log::trace!("Signature already verified, nop");
+                                return Ok(());
+                            }
+                            Err(previous) => previous,
+                        };
+                        log::trace!("Test next candidate subkey");
+                        signature
+                            .verify(sub_key, &mut data)
+                            .map_err(|e| RPMError::VerificationError {
+                                source: Box::new(e),
+                                key_ref: format!("{:?}", sub_key.key_id()),
                             })
-                        } else {
-                            log::trace!("Signature already verified, nop");
-                            Ok(())
-                        }
+                            .and_then(|_| self.check_key(&signature, Some(sub_key), policy))
+                            .map_err(|err| most_specific(previous, err))
                     },
                 )
         } else {
@@ -182,9 +341,14 @@ impl traits::Verifying<traits::algorithm::RSA> for Verifier {
                 .map_err(|e| RPMError::VerificationError {
                     source: Box::new(e),
                     key_ref: format!("{:?}", self.public_key.key_id()),
-                })
+                })?;
+            self.check_key(&signature, None, policy)
         }
     }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(Verifier::fingerprint(self))
+    }
 }
 
 impl Verifier {
@@ -204,10 +368,155 @@ impl Verifier {
                 details: "Failed to parse bytes as ascii armored key",
             })?;
 
-        Ok(Self { public_key })
+        Ok(Self {
+            public_key,
+            key_validity: KeyValidity::default(),
+        })
     }
 }
 
+/// Whichever of the errors of two candidate keys tells more: a key that verified
+/// the signature but was rejected, e.g. as expired, over a signature that did not
+/// verify, over no key matching at all. Ties keep the `previous` error.
+fn most_specific(previous: RPMError, next: RPMError) -> RPMError {
+    let rank = |err: &RPMError| match err {
+        RPMError::KeyNotFoundError { .. } => 0,
+        RPMError::VerificationError { .. } => 1,
+        _ => 2,
+    };
+    if rank(&next) > rank(&previous) {
+        next
+    } else {
+        previous
+    }
+}
+
+/// The hash and key algorithm of the OpenPGP `signature`, e.g. of a signature header.
+pub(crate) fn signature_algorithms(
+    signature: &[u8],
+) -> Result<(DigestAlgorithm, KeyAlgorithm), RPMError> {
+    let signature = Verifier::parse_signature(signature)?;
+    Ok((
+        digest_algorithm(signature.config.hash_alg),
+        key_algorithm(signature.config.pub_alg),
+    ))
+}
+
+fn digest_algorithm(hash: ::pgp::crypto::hash::HashAlgorithm) -> DigestAlgorithm {
+    use ::pgp::crypto::hash::HashAlgorithm;
+    match hash {
+        HashAlgorithm::MD5 => DigestAlgorithm::Md5,
+        HashAlgorithm::SHA1 => DigestAlgorithm::Sha1,
+        HashAlgorithm::SHA2_224 => DigestAlgorithm::Sha224,
+        HashAlgorithm::SHA2_256 => DigestAlgorithm::Sha256,
+        HashAlgorithm::SHA2_384 => DigestAlgorithm::Sha384,
+        HashAlgorithm::SHA2_512 => DigestAlgorithm::Sha512,
+        _ => DigestAlgorithm::Other,
+    }
+}
+
+fn key_algorithm(algorithm: ::pgp::crypto::public_key::PublicKeyAlgorithm) -> KeyAlgorithm {
+    use ::pgp::crypto::public_key::PublicKeyAlgorithm;
+    match algorithm {
+        PublicKeyAlgorithm::RSA | PublicKeyAlgorithm::RSASign => KeyAlgorithm::Rsa,
+        PublicKeyAlgorithm::DSA => KeyAlgorithm::Dsa,
+        PublicKeyAlgorithm::ECDSA => KeyAlgorithm::Ecdsa,
+        PublicKeyAlgorithm::EdDSA => KeyAlgorithm::EdDSA,
+        _ => KeyAlgorithm::Other,
+    }
+}
+
+/// When the latest binding signature of `subkey` says it expires.
+fn subkey_expires_at(
+    subkey: &::pgp::composed::signed_key::SignedPublicSubKey,
+) -> Option<::chrono::DateTime<::chrono::Utc>> {
+    subkey
+        .signatures
+        .iter()
+        .filter(|signature| signature.typ() == SignatureType::SubkeyBinding)
+        .filter_map(|signature| signature.key_expiration_time())
+        .max()
+        .map(|offset| *subkey.key.created_at() + ::chrono::Duration::seconds(offset.timestamp()))
+}
+
+fn earliest<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Fail if one of `revocations` applies at `at`.
+fn check_revocations(
+    revocations: &[::pgp::packet::Signature],
+    at: ::chrono::DateTime<::chrono::Utc>,
+    key_ref: &str,
+) -> Result<(), RPMError> {
+    for revocation in revocations {
+        let soft = matches!(
+            revocation.revocation_reason_code(),
+            Some(RevocationCode::KeySuperseded) | Some(RevocationCode::KeyRetired)
+        );
+        let applies = match revocation.created() {
+            Some(revoked_at) if soft => at >= *revoked_at,
+            _ => true,
+        };
+        if applies {
+            return Err(RPMError::KeyRevoked {
+                key_ref: key_ref.to_string(),
+                reason: revocation
+                    .revocation_reason_string()
+                    .filter(|reason| !reason.is_empty())
+                    .unwrap_or("no reason given")
+                    .to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Sign `data` with `signer` and return an ascii armored detached signature,
+/// e.g. the `repomd.xml.asc` published next to the `repomd.xml` of a repository.
+pub fn sign_detached<S, R>(signer: &S, data: R) -> Result<String, RPMError>
+where
+    S: traits::Signing<traits::algorithm::RSA>,
+    R: Read,
+{
+    let signature = signer.sign(data)?;
+    let signature = Verifier::parse_signature(signature.as_ref())?;
+    ::pgp::StandaloneSignature::new(signature)
+        .to_armored_string(None)
+        .map_err(|e| RPMError::SignError(Box::new(e)))
+}
+
+/// Sign the file at `path` and write the detached signature next to it,
+/// with `.asc` appended to the file name. Returns the path of the signature.
+pub fn sign_file_detached<S>(
+    signer: &S,
+    path: impl AsRef<std::path::Path>,
+) -> Result<std::path::PathBuf, RPMError>
+where
+    S: traits::Signing<traits::algorithm::RSA>,
+{
+    let path = path.as_ref();
+    let armored = sign_detached(signer, std::fs::File::open(path)?)?;
+    let mut signature_path = path.as_os_str().to_owned();
+    signature_path.push(".asc");
+    let signature_path = std::path::PathBuf::from(signature_path);
+    std::fs::write(&signature_path, armored)?;
+    Ok(signature_path)
+}
+
+/// Decode an ascii armored detached signature for [`Verifying::verify`](traits::Verifying::verify).
+pub fn dearmor_signature(armored: &str) -> Result<Vec<u8>, RPMError> {
+    let (signature, _) =
+        ::pgp::StandaloneSignature::from_string(armored).map_err(|_| RPMError::NoSignatureFound)?;
+    let mut bytes = Vec::with_capacity(1024);
+    ::pgp::packet::write_packet(&mut bytes, &signature.signature)
+        .map_err(|e| RPMError::SignError(Box::new(e)))?;
+    Ok(bytes)
+}
+
 #[cfg(test)]
 pub(crate) mod test {
 
@@ -267,6 +576,26 @@ pub(crate) mod test {
             .expect("failed to verify just signed signature");
     }
 
+    #[test]
+    fn detached_signature() -> Result<(), Box<dyn std::error::Error>> {
+        let (signer, verifier) = prep();
+        let dir = crate::tests::TestDir::new("repomd");
+        let repomd = dir.join("repomd.xml");
+        std::fs::write(&repomd, b"<repomd/>")?;
+
+        let asc = sign_file_detached(&signer, &repomd)?;
+        assert_eq!(dir.join("repomd.xml.asc"), asc);
+        let armored = std::fs::read_to_string(&asc)?;
+        assert!(armored.starts_with("-----BEGIN PGP SIGNATURE-----"));
+
+        let signature = dearmor_signature(&armored)?;
+        verifier.verify(&b"<repomd/>"[..], &signature)?;
+        assert!(verifier
+            .verify(&b"<repomd></repomd>"[..], &signature)
+            .is_err());
+        Ok(())
+    }
+
     #[test]
     fn verify_pgp_crate() {
         use ::pgp::types::{PublicKeyTrait, SecretKeyTrait};
@@ -298,7 +627,7 @@ pub(crate) mod test {
             [digest[0], digest[1]],
             signature,
             vec![
-                ::pgp::packet::Subpacket::SignatureCreationTime(now()),
+                ::pgp::packet::Subpacket::SignatureCreationTime(now().unwrap()),
                 ::pgp::packet::Subpacket::Issuer(signing_key.key_id()),
                 //::pgp::packet::Subpacket::SignersUserID("rpm"), TODO this would be a nice addition
             ],
@@ -330,7 +659,7 @@ pub(crate) mod test {
 
         let passwd_fn = || String::new();
 
-        let now = now();
+        let now = now().unwrap();
 
         let sig_cfg = SignatureConfig {
             version: SignatureVersion::V4,
@@ -396,6 +725,145 @@ pub(crate) mod test {
         let _signature = Verifier::parse_signature(signature).expect("It should load");
     }
 
+    #[test]
+    fn keep_most_specific_error() {
+        let expired = || RPMError::KeyExpired {
+            key_ref: "sub".to_string(),
+            expired_at: "2020-12-31T00:00:00+00:00".to_string(),
+        };
+        let not_found = || RPMError::KeyNotFoundError {
+            key_ref: "sub".to_string(),
+        };
+        let failed = || RPMError::VerificationError {
+            source: "bad signature".into(),
+            key_ref: "sub".to_string(),
+        };
+        assert!(matches!(
+            most_specific(not_found(), expired()),
+            RPMError::KeyExpired { .. }
+        ));
+        assert!(matches!(
+            most_specific(expired(), not_found()),
+            RPMError::KeyExpired { .. }
+        ));
+        assert!(matches!(
+            most_specific(expired(), failed()),
+            RPMError::KeyExpired { .. }
+        ));
+        assert!(matches!(
+            most_specific(not_found(), failed()),
+            RPMError::VerificationError { .. }
+        ));
+    }
+
+    #[test]
+    fn check_key_validity() -> Result<(), Box<dyn std::error::Error>> {
+        use ::chrono::TimeZone;
+
+        // both keys were created at 2020-01-01, the first one expired at the end of 2020,
+        // the second one was revoked without a reason
+        let expired =
+            Signer::load_from_asc(include_str!("../../../test_assets/expired_secret_key.asc"))?;
+        let expired_verifier =
+            || Verifier::load_from_asc(include_str!("../../../test_assets/expired_public_key.asc"));
+        let revoked =
+            Signer::load_from_asc(include_str!("../../../test_assets/revoked_secret_key.asc"))?;
+        let revoked_verifier =
+            || Verifier::load_from_asc(include_str!("../../../test_assets/revoked_public_key.asc"));
+        let data = b"dummy data";
+        let before_expiry = ::chrono::Utc.with_ymd_and_hms(2020, 6, 1, 0, 0, 0).unwrap();
+
+        let signature = expired.sign_at(&data[..], before_expiry)?;
+        expired_verifier()?.verify(&data[..], &signature)?;
+        match expired_verifier()?
+            .key_validity(KeyValidity::AtVerificationTime)
+            .verify(&data[..], &signature)
+        {
+            Err(RPMError::KeyExpired { expired_at, .. }) => {
+                assert_eq!("2020-12-31T00:00:00+00:00", expired_at)
+            }
+            other => panic!("expected the key to be expired, got {:?}", other),
+        }
+        expired_verifier()?
+            .key_validity(KeyValidity::Ignore)
+            .verify(&data[..], &signature)?;
+        let signature = expired.sign(&data[..])?;
+        assert!(matches!(
+            expired_verifier()?.verify(&data[..], &signature),
+            Err(RPMError::KeyExpired { .. })
+        ));
+
+        let signature = revoked.sign_at(&data[..], before_expiry)?;
+        assert!(matches!(
+            revoked_verifier()?.verify(&data[..], &signature),
+            Err(RPMError::KeyRevoked { .. })
+        ));
+        revoked_verifier()?
+            .key_validity(KeyValidity::Ignore)
+            .verify(&data[..], &signature)?;
+
+        let (signer, verifier) = prep();
+        verifier
+            .key_validity(KeyValidity::AtVerificationTime)
+            .verify(&data[..], &signer.sign(&data[..])?)?;
+        Ok(())
+    }
+
+    #[test]
+    fn check_verify_policy() -> Result<(), Box<dyn std::error::Error>> {
+        let (signer, verifier) = prep();
+        let data = b"dummy data";
+        let signature = signer.sign(&data[..])?;
+        let rsa_bits = |bits| VerifyPolicy::new().min_rsa_bits(bits);
+        verifier.verify_with_policy(&data[..], &signature, &rsa_bits(2048))?;
+        assert!(matches!(
+            verifier.verify_with_policy(&data[..], &signature, &rsa_bits(8192)),
+            Err(RPMError::PolicyViolation(_))
+        ));
+        assert!(verifier
+            .verify_with_policy(
+                &data[..],
+                &signature,
+                &VerifyPolicy::new().allow_key_algorithms(&[KeyAlgorithm::EdDSA])
+            )
+            .is_err());
+
+        let now = now()?;
+        let sha1_signature = SignatureConfig {
+            version: SignatureVersion::V4,
+            typ: SignatureType::Binary,
+            pub_alg: signer.secret_key.algorithm(),
+            hash_alg: ::pgp::crypto::hash::HashAlgorithm::SHA1,
+            issuer: Some(signer.secret_key.key_id()),
+            created: Some(now),
+            unhashed_subpackets: vec![],
+            hashed_subpackets: vec![
+                Subpacket::SignatureCreationTime(now),
+                Subpacket::Issuer(signer.secret_key.key_id()),
+            ],
+        }
+        .sign(&signer.secret_key, String::new, &data[..])?;
+        let mut sha1_signature_bytes = Vec::new();
+        ::pgp::packet::write_packet(&mut sha1_signature_bytes, &sha1_signature)?;
+        verifier.verify(&data[..], &sha1_signature_bytes)?;
+        assert!(matches!(
+            verifier.verify_with_policy(
+                &data[..],
+                &sha1_signature_bytes,
+                &VerifyPolicy::new().ban_digest(DigestAlgorithm::Sha1)
+            ),
+            Err(RPMError::PolicyViolation(_))
+        ));
+
+        let keys = crate::crypto::generate_ed25519_keypair()?;
+        keys.verifier()?.verify_with_policy(
+            &data[..],
+            &keys.signer()?.sign(&data[..])?,
+            &VerifyPolicy::strict(),
+        )?;
+        Ok(())
+    }
+
     /// TODO fill with correct data
     const RPM_SHA2_256: [u8; 32] = [
         0xd9, 0x2b, 0xfe, 0x27, 0x6e, 0x31, 0x1a, 0x67, 0xfe, 0x12, 0x87, 0x68, 0xc5, 0xdf, 0x4d,
@@ -0,0 +1,250 @@
+//! Which algorithms and key sizes signatures and digests may use.
+
+use crate::constants::*;
+use crate::errors::*;
+use crate::{FileDigestAlgorithm, RPMPackageMetadata};
+
+/// Hash algorithms of signatures and package digests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DigestAlgorithm {
+    Md5,
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+    /// Anything else, e.g. RIPEMD-160. Ban it to accept only the algorithms above.
+    Other,
+}
+
+/// Public key algorithms of signing keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyAlgorithm {
+    Rsa,
+    Dsa,
+    Ecdsa,
+    EdDSA,
+    Other,
+}
+
+/// Rules a signature has to satisfy on top of verifying, e.g. to enforce FIPS or
+/// organizational policy.
+///
+/// The default accepts everything. Set it on
+/// [`VerifyOptions::policy`](crate::VerifyOptions::policy) to check the digests of a
+/// package and its signatures, which verifiers check through
+/// [`Verifying::verify_with_policy`](crate::signature::Verifying::verify_with_policy).
+///
+/// ```rust
+/// use rpm::signature::{DigestAlgorithm, KeyAlgorithm, VerifyPolicy};
+/// let policy = VerifyPolicy::new()
+///     .ban_digest(DigestAlgorithm::Md5)
+///     .ban_digest(DigestAlgorithm::Sha1)
+///     .ban_digest(DigestAlgorithm::Other)
+///     .allow_key_algorithms(&[KeyAlgorithm::Rsa, KeyAlgorithm::EdDSA])
+///     .min_rsa_bits(3072);
+/// assert_eq!(policy, VerifyPolicy::strict());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyPolicy {
+    banned_digests: Vec<DigestAlgorithm>,
+    allowed_key_algorithms: Option<Vec<KeyAlgorithm>>,
+    min_rsa_bits: usize,
+}
+
+impl VerifyPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only SHA-2, RSA keys of at least 3072 bits or EdDSA keys.
+    pub fn strict() -> Self {
+        Self::new()
+            .ban_digest(DigestAlgorithm::Md5)
+            .ban_digest(DigestAlgorithm::Sha1)
+            .ban_digest(DigestAlgorithm::Other)
+            .allow_key_algorithms(&[KeyAlgorithm::Rsa, KeyAlgorithm::EdDSA])
+            .min_rsa_bits(3072)
+    }
+
+    /// Reject signatures over a hash of `digest`, packages carrying no header digests
+    /// but banned ones, like the MD5 only digests of old packages, and packages with
+    /// file digests of `digest`.
+    pub fn ban_digest(mut self, digest: DigestAlgorithm) -> Self {
+        if !self.banned_digests.contains(&digest) {
+            self.banned_digests.push(digest);
+        }
+        self
+    }
+
+    /// Only accept keys of these algorithms.
+    pub fn allow_key_algorithms(mut self, algorithms: &[KeyAlgorithm]) -> Self {
+        self.allowed_key_algorithms = Some(algorithms.to_vec());
+        self
+    }
+
+    pub fn min_rsa_bits(mut self, bits: usize) -> Self {
+        self.min_rsa_bits = bits;
+        self
+    }
+
+    /// Check a verified signature, `rsa_bits` is the modulus size of RSA keys.
+    pub fn check_signature(
+        &self,
+        digest: DigestAlgorithm,
+        key: KeyAlgorithm,
+        rsa_bits: Option<usize>,
+        key_ref: &str,
+    ) -> Result<(), RPMError> {
+        let violation =
+            |reason: String| RPMError::PolicyViolation(format!("key {}: {}", key_ref, reason));
+        if self.banned_digests.contains(&digest) {
+            return Err(violation(format!("signature hashes with {:?}", digest)));
+        }
+        match &self.allowed_key_algorithms {
+            Some(allowed) if !allowed.contains(&key) => {
+                return Err(violation(format!("{:?} keys are not allowed", key)))
+            }
+            _ => {}
+        }
+        match rsa_bits {
+            Some(bits) if key == KeyAlgorithm::Rsa && bits < self.min_rsa_bits => {
+                Err(violation(format!(
+                    "RSA key of {} bits, at least {} are required",
+                    bits, self.min_rsa_bits
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the package carries a header digest that is not banned, and its file
+    /// digests are not banned.
+    pub(crate) fn check_digests(&self, metadata: &RPMPackageMetadata) -> Result<(), RPMError> {
+        let signature = &metadata.signature;
+        let digests = [
+            (IndexSignatureTag::RPMSIGTAG_SHA256, DigestAlgorithm::Sha256),
+            (IndexSignatureTag::RPMSIGTAG_SHA1, DigestAlgorithm::Sha1),
+            (IndexSignatureTag::RPMSIGTAG_MD5, DigestAlgorithm::Md5),
+        ];
+        let present = digests
+            .iter()
+            .filter(|(tag, _)| signature.find_entry_or_err(tag).is_ok())
+            .map(|(_, digest)| *digest)
+            .collect::<Vec<_>>();
+        if !present
+            .iter()
+            .any(|digest| !self.banned_digests.contains(digest))
+        {
+            return Err(RPMError::PolicyViolation(format!(
+                "package only has {:?} digests",
+                present
+            )));
+        }
+
+        let header = &metadata.header;
+        if header
+            .find_entry_or_err(&IndexTag::RPMTAG_BASENAMES)
+            .is_err()
+        {
+            return Ok(());
+        }
+        // a missing tag means md5
+        let files = match header.get_file_digest_algorithm().unwrap_or_default() {
+            FileDigestAlgorithm::Md5 => DigestAlgorithm::Md5,
+            FileDigestAlgorithm::Sha1 => DigestAlgorithm::Sha1,
+            FileDigestAlgorithm::Sha2_224 => DigestAlgorithm::Sha224,
+            FileDigestAlgorithm::Sha2_256 => DigestAlgorithm::Sha256,
+            FileDigestAlgorithm::Sha2_384 => DigestAlgorithm::Sha384,
+            FileDigestAlgorithm::Sha2_512 => DigestAlgorithm::Sha512,
+            _ => DigestAlgorithm::Other,
+        };
+        if self.banned_digests.contains(&files) {
+            return Err(RPMError::PolicyViolation(format!(
+                "package has {:?} file digests",
+                files
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Header, IndexData, IndexEntry};
+
+    #[test]
+    fn check_policy() {
+        let strict = VerifyPolicy::strict();
+        use DigestAlgorithm::*;
+        use KeyAlgorithm::*;
+        assert!(VerifyPolicy::new()
+            .check_signature(Md5, Dsa, None, "k")
+            .is_ok());
+        assert!(strict.check_signature(Sha256, Rsa, Some(4096), "k").is_ok());
+        assert!(strict.check_signature(Sha512, EdDSA, None, "k").is_ok());
+        let violations = [
+            strict.check_signature(Sha1, Rsa, Some(4096), "k"),
+            strict.check_signature(Sha256, Rsa, Some(2048), "k"),
+            strict.check_signature(Sha256, Dsa, None, "k"),
+        ];
+        for violation in &violations {
+            assert!(matches!(violation, Err(RPMError::PolicyViolation(_))));
+        }
+
+        let package = |tags: &[IndexSignatureTag], file_digests: Option<i32>| {
+            let mut metadata = crate::RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "foo")
+                .with_file(
+                    "./test_assets/awesome.toml",
+                    crate::RPMFileOptions::new("/etc/awesome/config.toml"),
+                )
+                .and_then(|builder| builder.build())
+                .unwrap()
+                .metadata;
+            metadata.signature = Header::from_entries(
+                tags.iter()
+                    .map(|tag| IndexEntry::new(*tag, 0, IndexData::Bin(vec![0; 16])))
+                    .collect(),
+                IndexSignatureTag::HEADER_SIGNATURES,
+            );
+            let entries = &mut metadata.header.index_entries;
+            entries.retain(|entry| entry.tag != IndexTag::RPMTAG_FILEDIGESTALGO);
+            if let Some(algorithm) = file_digests {
+                entries.push(IndexEntry::new(
+                    IndexTag::RPMTAG_FILEDIGESTALGO,
+                    0,
+                    IndexData::Int32(vec![algorithm]),
+                ));
+            }
+            metadata
+        };
+        let sha256 = [
+            IndexSignatureTag::RPMSIGTAG_MD5,
+            IndexSignatureTag::RPMSIGTAG_SHA256,
+        ];
+        let md5_only = package(&[IndexSignatureTag::RPMSIGTAG_MD5], Some(8));
+        assert!(VerifyPolicy::new().check_digests(&md5_only).is_ok());
+        assert!(strict.check_digests(&md5_only).is_err());
+        assert!(strict.check_digests(&package(&sha256, Some(8))).is_ok());
+        // a missing algorithm means md5
+        for file_digests in [None, Some(1)] {
+            let md5_files = package(&sha256, file_digests);
+            assert!(VerifyPolicy::new().check_digests(&md5_files).is_ok());
+            assert!(matches!(
+                strict.check_digests(&md5_files),
+                Err(RPMError::PolicyViolation(_))
+            ));
+        }
+        // RIPEMD-160
+        let other_files = package(&sha256, Some(3));
+        assert!(VerifyPolicy::new()
+            .ban_digest(Md5)
+            .check_digests(&other_files)
+            .is_ok());
+        assert!(strict.check_digests(&other_files).is_err());
+        assert!(strict
+            .check_signature(DigestAlgorithm::Other, Rsa, Some(4096), "k")
+            .is_err());
+    }
+}
@@ -7,6 +7,9 @@
 use crate::errors::*;
 use std::fmt::Debug;
 use std::io::Read;
+use std::sync::Arc;
+
+use super::VerifyPolicy;
 
 pub mod algorithm {
 
@@ -21,50 +24,199 @@ pub mod algorithm {
     pub struct RSA;
 
     impl Algorithm for RSA {}
+
+    /// CMS (RFC 5652) signatures with X.509 certificates.
+    #[derive(Debug, Clone, Copy)]
+    pub struct CMS;
+
+    impl Algorithm for CMS {}
 }
 
 /// Signing trait to be implement for RPM signing.
+///
+/// `Signature` may be any byte container, e.g. `Cow<[u8]>` or a type of the
+/// signing backend, it is only read through `as_ref`.
 pub trait Signing<A>: Debug
 where
     A: algorithm::Algorithm,
-    Self::Signature: AsRef<[u8]>,
 {
-    type Signature;
+    type Signature: AsRef<[u8]>;
     fn sign<R: Read>(&self, data: R) -> Result<Self::Signature, RPMError>;
 }
 
-impl<A, T, S> Signing<A> for &T
+impl<A, T> Signing<A> for &T
+where
+    T: Signing<A> + ?Sized,
+    A: algorithm::Algorithm,
+{
+    type Signature = T::Signature;
+    fn sign<R: Read>(&self, data: R) -> Result<Self::Signature, RPMError> {
+        T::sign::<R>(self, data)
+    }
+}
+
+impl<A, T> Signing<A> for Box<T>
+where
+    T: Signing<A> + ?Sized,
+    A: algorithm::Algorithm,
+{
+    type Signature = T::Signature;
+    fn sign<R: Read>(&self, data: R) -> Result<Self::Signature, RPMError> {
+        T::sign::<R>(self, data)
+    }
+}
+
+/// Shares one signer between threads, e.g. of a server signing uploads.
+impl<A, T> Signing<A> for Arc<T>
 where
-    T: Signing<A, Signature = S>,
+    T: Signing<A> + ?Sized,
     A: algorithm::Algorithm,
-    S: AsRef<[u8]>,
 {
-    type Signature = S;
+    type Signature = T::Signature;
     fn sign<R: Read>(&self, data: R) -> Result<Self::Signature, RPMError> {
         T::sign::<R>(self, data)
     }
 }
 
+/// Object safe form of [`Signing`], implemented for all signers.
+///
+/// `Box<dyn DynSigning<A> + Send + Sync>` and `Arc` of it hold signers of any type
+/// and implement [`Signing`] again.
+pub trait DynSigning<A>: Debug
+where
+    A: algorithm::Algorithm,
+{
+    fn sign_dyn(&self, data: &mut dyn Read) -> Result<Vec<u8>, RPMError>;
+}
+
+impl<A, T> DynSigning<A> for T
+where
+    T: Signing<A>,
+    A: algorithm::Algorithm,
+{
+    fn sign_dyn(&self, data: &mut dyn Read) -> Result<Vec<u8>, RPMError> {
+        Ok(self.sign(data)?.as_ref().to_vec())
+    }
+}
+
+impl<'a, A> Signing<A> for dyn DynSigning<A> + 'a
+where
+    A: algorithm::Algorithm,
+{
+    type Signature = Vec<u8>;
+    fn sign<R: Read>(&self, mut data: R) -> Result<Self::Signature, RPMError> {
+        self.sign_dyn(&mut data)
+    }
+}
+
+impl<'a, A> Signing<A> for dyn DynSigning<A> + Send + Sync + 'a
+where
+    A: algorithm::Algorithm,
+{
+    type Signature = Vec<u8>;
+    fn sign<R: Read>(&self, mut data: R) -> Result<Self::Signature, RPMError> {
+        self.sign_dyn(&mut data)
+    }
+}
+
 /// Verification trait to be implement for RPM signature verification.
 pub trait Verifying<A>: Debug
 where
     A: algorithm::Algorithm,
-    Self::Signature: AsRef<[u8]>,
 {
-    type Signature;
+    type Signature: AsRef<[u8]>;
     fn verify<R: Read>(&self, data: R, signature: &[u8]) -> Result<(), RPMError>;
+
+    /// Uppercase hex fingerprint of the key, to check it against a
+    /// [`TrustStore`](crate::TrustStore). Verifiers without one are never trusted.
+    fn fingerprint(&self) -> Option<String> {
+        None
+    }
+
+    /// Like [`verify`](Self::verify), rejecting signatures which violate `policy`.
+    ///
+    /// Verifiers which cannot tell the algorithms of a signature refuse every policy
+    /// but the default one, which accepts everything.
+    fn verify_with_policy<R: Read>(
+        &self,
+        data: R,
+        signature: &[u8],
+        policy: &VerifyPolicy,
+    ) -> Result<(), RPMError> {
+        if *policy != VerifyPolicy::default() {
+            return Err(RPMError::PolicyViolation(format!(
+                "{:?} cannot check signatures against a policy",
+                self
+            )));
+        }
+        self.verify(data, signature)
+    }
+}
+
+impl<A, T> Verifying<A> for &T
+where
+    T: Verifying<A> + ?Sized,
+    A: algorithm::Algorithm,
+{
+    type Signature = T::Signature;
+    fn verify<R: Read>(&self, data: R, signature: &[u8]) -> Result<(), RPMError> {
+        T::verify::<R>(self, data, signature)
+    }
+    fn fingerprint(&self) -> Option<String> {
+        T::fingerprint(self)
+    }
+    fn verify_with_policy<R: Read>(
+        &self,
+        data: R,
+        signature: &[u8],
+        policy: &VerifyPolicy,
+    ) -> Result<(), RPMError> {
+        T::verify_with_policy::<R>(self, data, signature, policy)
+    }
+}
+
+impl<A, T> Verifying<A> for Box<T>
+where
+    T: Verifying<A> + ?Sized,
+    A: algorithm::Algorithm,
+{
+    type Signature = T::Signature;
+    fn verify<R: Read>(&self, data: R, signature: &[u8]) -> Result<(), RPMError> {
+        T::verify::<R>(self, data, signature)
+    }
+    fn fingerprint(&self) -> Option<String> {
+        T::fingerprint(self)
+    }
+    fn verify_with_policy<R: Read>(
+        &self,
+        data: R,
+        signature: &[u8],
+        policy: &VerifyPolicy,
+    ) -> Result<(), RPMError> {
+        T::verify_with_policy::<R>(self, data, signature, policy)
+    }
 }
 
-impl<A, T, S> Verifying<A> for &T
+impl<A, T> Verifying<A> for Arc<T>
 where
-    T: Verifying<A, Signature = S>,
+    T: Verifying<A> + ?Sized,
     A: algorithm::Algorithm,
-    S: AsRef<[u8]>,
 {
-    type Signature = S;
+    type Signature = T::Signature;
     fn verify<R: Read>(&self, data: R, signature: &[u8]) -> Result<(), RPMError> {
         T::verify::<R>(self, data, signature)
     }
+    fn fingerprint(&self) -> Option<String> {
+        T::fingerprint(self)
+    }
+    fn verify_with_policy<R: Read>(
+        &self,
+        data: R,
+        signature: &[u8],
+        policy: &VerifyPolicy,
+    ) -> Result<(), RPMError> {
+        T::verify_with_policy::<R>(self, data, signature, policy)
+    }
 }
 
 pub mod key {
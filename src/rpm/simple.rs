@@ -0,0 +1,161 @@
+//! Convenience API for the common "wrap a few files into an rpm" case.
+//!
+//! ```rust
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let rpm_bytes = rpm::simple::package(
+//!     "awesome",
+//!     "1.0.0",
+//!     &[("./test_assets/awesome.py", "/usr/bin/awesome", 0o755)],
+//! )?;
+//! # assert!(!rpm_bytes.is_empty());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::errors::*;
+
+use super::elf;
+use super::{Compressor, FileMode, RPMBuilder, RPMFileOptions};
+
+/// Architecture used if none of the files is an architecture dependent binary.
+pub const NOARCH: &str = "noarch";
+
+/// Architecture used for binaries of an unknown machine type.
+pub const FALLBACK_ARCH: &str = "x86_64";
+
+const DEFAULT_LICENSE: &str = "Unspecified";
+
+/// Build a gzip compressed rpm from a list of `(source, destination, permissions)`.
+///
+/// The architecture is `noarch` unless one of the sources is an ELF binary,
+/// in which case the architecture of that binary is used. Binaries for
+/// different architectures are refused.
+/// License is set to `Unspecified`, the summary is derived from name and version.
+pub fn package<P: AsRef<Path>>(
+    name: &str,
+    version: &str,
+    files: &[(P, &str, u16)],
+) -> Result<Vec<u8>, RPMError> {
+    let arches = files
+        .iter()
+        .map(|(source, _, _)| Ok((source.as_ref(), detect_arch(source.as_ref())?)))
+        .collect::<Result<Vec<_>, RPMError>>()?;
+    let arch = package_arch(arches)?;
+
+    let summary = format!("{} version {}", name, version);
+    let mut builder = RPMBuilder::new(name, version, DEFAULT_LICENSE, arch, &summary)
+        .compression(Compressor::from_str("gzip")?);
+    for (source, dest, permissions) in files {
+        builder = builder.with_file(
+            source,
+            RPMFileOptions::new(*dest).mode(FileMode::regular(*permissions)),
+        )?;
+    }
+
+    let pkg = builder.build()?;
+    let mut out = Vec::new();
+    pkg.write(&mut out)?;
+    Ok(out)
+}
+
+/// The architecture all binaries among the files agree on, `noarch` without any.
+fn package_arch<'a, I>(arches: I) -> Result<&'static str, RPMError>
+where
+    I: IntoIterator<Item = (&'a Path, Option<&'static str>)>,
+{
+    let mut arch = None;
+    for (path, file_arch) in arches {
+        match (arch, file_arch) {
+            (_, None) => {}
+            (None, Some(file_arch)) => arch = Some(file_arch),
+            (Some(other), Some(file_arch)) if other != file_arch => {
+                return Err(RPMError::ConflictingArch {
+                    path: path.display().to_string(),
+                    arch: file_arch.to_string(),
+                    other: other.to_string(),
+                })
+            }
+            _ => {}
+        }
+    }
+    Ok(arch.unwrap_or(NOARCH))
+}
+
+/// Look at the first bytes of a file to find out if it is architecture dependent.
+fn detect_arch(source: &Path) -> Result<Option<&'static str>, RPMError> {
+    let mut head = Vec::with_capacity(64);
+    std::fs::File::open(source)?
+        .take(64)
+        .read_to_end(&mut head)?;
+    if elf::is_elf(&head) {
+        Ok(Some(elf::rpm_arch(&head).unwrap_or(FALLBACK_ARCH)))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RPMPackage;
+
+    #[test]
+    fn simple_package_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let raw = package(
+            "awesome",
+            "1.2.3",
+            &[
+                ("./test_assets/awesome.py", "/usr/bin/awesome", 0o755),
+                (
+                    "./test_assets/awesome.toml",
+                    "/etc/awesome/config.toml",
+                    0o644,
+                ),
+            ],
+        )?;
+
+        let pkg = RPMPackage::parse(&mut raw.as_slice())?;
+        assert_eq!("awesome", pkg.metadata.header.get_name()?);
+        assert_eq!("1.2.3", pkg.metadata.header.get_version()?);
+        assert_eq!(NOARCH, pkg.metadata.header.get_arch()?);
+        assert_eq!("gzip", pkg.metadata.header.get_payload_compressor()?);
+
+        let entries = pkg.metadata.header.get_file_entries()?;
+        assert_eq!(2, entries.len());
+        assert!(
+            entries
+                .iter()
+                .any(|e| e.path == Path::new("/usr/bin/awesome")
+                    && e.mode == FileMode::regular(0o755))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn binaries_agree_on_the_arch() {
+        let (script, first, second) = (Path::new("a.py"), Path::new("a"), Path::new("b"));
+        assert_eq!(NOARCH, package_arch(vec![(script, None)]).unwrap());
+        assert_eq!(
+            "aarch64",
+            package_arch(vec![
+                (first, Some("aarch64")),
+                (script, None),
+                (second, Some("aarch64")),
+            ])
+            .unwrap()
+        );
+        match package_arch(vec![(first, Some("aarch64")), (second, Some("x86_64"))]) {
+            Err(RPMError::ConflictingArch { path, arch, other }) => {
+                assert_eq!(
+                    ("b", "x86_64", "aarch64"),
+                    (path.as_str(), arch.as_str(), other.as_str())
+                )
+            }
+            other => panic!("expected conflicting arches, got {:?}", other),
+        }
+    }
+}
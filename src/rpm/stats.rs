@@ -0,0 +1,147 @@
+//! Size and file count statistics read from the header, the payload is not touched.
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::headers::*;
+use super::RPMPackage;
+
+/// What [`RPMPackage::stats`] found out about a package.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageStats {
+    /// The installed size of the header, the file sizes summed up if it has none.
+    pub installed_size: u64,
+    pub regular_files: usize,
+    pub directories: usize,
+    pub symlinks: usize,
+    /// Entries of any other or an invalid file type.
+    pub other: usize,
+    /// Files marked as `%config`.
+    pub config_files: usize,
+    /// Files marked as `%doc`.
+    pub doc_files: usize,
+    /// Sizes and counts below each top level directory like `/usr`, entries
+    /// directly in `/` count towards `/`.
+    pub top_level: BTreeMap<String, DirectoryStats>,
+}
+
+/// Regular files below a directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirectoryStats {
+    /// Combined size of the regular files.
+    pub size: u64,
+    /// Entries of any type.
+    pub entries: usize,
+}
+
+impl PackageStats {
+    /// Tally the file entries of `header`.
+    pub fn from_header(header: &Header<IndexTag>) -> Result<Self, RPMError> {
+        let entries = match header.get_file_entries() {
+            Err(RPMError::TagNotFound(_)) => Vec::new(),
+            entries => entries?,
+        };
+        let mut stats = PackageStats::default();
+        let mut file_sizes = 0;
+        for entry in entries.iter() {
+            let size = match entry.mode {
+                FileMode::Regular { .. } => {
+                    stats.regular_files += 1;
+                    entry.size as u64
+                }
+                FileMode::Dir { .. } => {
+                    stats.directories += 1;
+                    0
+                }
+                FileMode::SymbolicLink { .. } => {
+                    stats.symlinks += 1;
+                    0
+                }
+                _ => {
+                    stats.other += 1;
+                    0
+                }
+            };
+            match entry.category {
+                FileCategory::Config => stats.config_files += 1,
+                FileCategory::Doc => stats.doc_files += 1,
+                FileCategory::None => {}
+            }
+            file_sizes += size;
+            let directory = stats.top_level.entry(top_level(&entry.path)).or_default();
+            directory.size += size;
+            directory.entries += 1;
+        }
+        stats.installed_size = header.get_installed_size().unwrap_or(file_sizes);
+        Ok(stats)
+    }
+}
+
+/// The top level directory `path` is in.
+fn top_level(path: &Path) -> String {
+    let mut components = path
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)));
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => format!("/{}", first.as_os_str().to_string_lossy()),
+        _ => "/".to_string(),
+    }
+}
+
+impl RPMPackage {
+    /// Installed size, file counts by type and the size of every top level directory,
+    /// see [`PackageStats`].
+    pub fn stats(&self) -> Result<PackageStats, RPMError> {
+        PackageStats::from_header(&self.metadata.header)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{RPMBuilder, RPMFileOptions};
+
+    #[test]
+    fn stats() -> Result<(), Box<dyn std::error::Error>> {
+        let size = std::fs::metadata("./test_assets/awesome.toml")?.len();
+        let pkg = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo")
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/etc/awesome/config.toml").is_config(),
+            )?
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/usr/share/doc/awesome/README").is_doc(),
+            )?
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/usr/bin/awesome")
+                    .symlink("/etc/awesome/config.toml")
+                    .mode(FileMode::symlink(0o777)),
+            )?
+            .build()?;
+        let stats = pkg.stats()?;
+        assert_eq!(2, stats.regular_files);
+        assert_eq!(1, stats.symlinks);
+        assert_eq!((1, 1), (stats.config_files, stats.doc_files));
+        assert_eq!(
+            vec![
+                ("/etc".to_string(), DirectoryStats { size, entries: 1 }),
+                ("/usr".to_string(), DirectoryStats { size, entries: 2 }),
+            ],
+            stats.top_level.into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            pkg.metadata.header.get_installed_size()?,
+            stats.installed_size
+        );
+
+        let empty = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo").build()?;
+        assert_eq!(0, empty.stats()?.top_level.len());
+        assert_eq!("/", top_level(Path::new("/README")));
+        Ok(())
+    }
+}
@@ -0,0 +1,656 @@
+//! Several packages from one builder, like the subpackages of a spec file.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::errors::*;
+
+use super::checksum::ChecksumWriter;
+use super::elf;
+use super::{Dependency, Diagnostic, Diagnostics, FileMode, RPMBuilder, RPMFileEntry, RPMPackage};
+
+/// A package split off from the files of a builder by [`RPMBuilder::build_all`].
+///
+/// Name, version, release, license and architecture are those of the main package,
+/// the name gets `-<suffix>` appended.
+#[derive(Debug, Clone)]
+pub struct SubPackage {
+    suffix: String,
+    desc: String,
+    patterns: Vec<String>,
+    requires: Vec<Dependency>,
+    requires_main: bool,
+}
+
+impl SubPackage {
+    pub fn new(suffix: &str, desc: &str) -> Self {
+        SubPackage {
+            suffix: suffix.to_string(),
+            desc: desc.to_string(),
+            patterns: Vec::new(),
+            requires: Vec::new(),
+            requires_main: true,
+        }
+    }
+
+    /// Move the files matching `pattern` into this package, like listing them in `%files`.
+    ///
+    /// `*` matches within a path component, `**` across components. A pattern matching
+    /// a directory takes everything below it as well, e.g. `/usr/include` or `/usr/lib64/*.so`.
+    pub fn files<T: Into<String>>(mut self, pattern: T) -> Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    pub fn requires(mut self, dep: Dependency) -> Self {
+        self.requires.push(dep);
+        self
+    }
+
+    /// Require exactly the same build of the main package, on by default,
+    /// which is what `-devel` packages usually declare.
+    pub fn requires_main(mut self, requires_main: bool) -> Self {
+        self.requires_main = requires_main;
+        self
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| matches_pattern(pattern, path))
+    }
+}
+
+impl RPMBuilder {
+    /// Define a package built from a part of the files by [`build_all`](Self::build_all).
+    ///
+    /// Files go to the first subpackage with a matching pattern, all others stay in the
+    /// main package. [`build`](Self::build) ignores subpackages and packages all files.
+    pub fn subpackage(mut self, subpackage: SubPackage) -> Self {
+        self.subpackages.push(subpackage);
+        self
+    }
+
+    /// Build the main package followed by all subpackages, in the order they were defined.
+    pub fn build_all(mut self) -> Result<Vec<RPMPackage>, RPMError> {
+        let subpackages = std::mem::take(&mut self.subpackages);
+        let mut builders = Vec::with_capacity(subpackages.len());
+        for subpackage in subpackages.iter() {
+            let mut builder = self.companion(&subpackage.suffix, &subpackage.desc)?;
+            for (cpio_path, entry) in self.take_files(|path| subpackage.matches(path)) {
+                builder.insert_file(cpio_path, entry);
+            }
+            if subpackage.requires_main {
+                builder = builder.requires(Dependency::eq(self.name(), self.evr()));
+            }
+            for dep in subpackage.requires.iter() {
+                builder = builder.requires(dep.clone());
+            }
+            builders.push(builder);
+        }
+
+        let mut packages = vec![self.build()?];
+        for builder in builders {
+            packages.push(builder.build()?);
+        }
+        Ok(packages)
+    }
+
+    /// Build the files into as many `<name>-partN` packages as needed for every
+    /// written part to stay within `budget` bytes, for stores with an upload limit.
+    ///
+    /// Parts are filled in path order, assuming content does not compress, and
+    /// each built part is measured, moving its last files to the next part while
+    /// it is too large. They are returned after the package named like the
+    /// builder, which keeps all metadata, carries no files and requires the same
+    /// build of every part. A single package is built if it fits.
+    pub fn build_split(mut self, budget: u64) -> Result<Vec<RPMPackage>, RPMError> {
+        let total = self
+            .files
+            .values()
+            .map(|entry| entry.size.max(0) as u64)
+            .sum::<u64>();
+        if total <= budget {
+            let package = self.clone().build()?;
+            if written_size(&package)? <= budget {
+                return Ok(vec![package]);
+            }
+        }
+
+        // a part without files, with the longest description
+        let overhead = self
+            .companion(
+                "part",
+                &self.part_description(u32::MAX as usize, u32::MAX as usize),
+            )?
+            .plan()?
+            .estimated_size;
+        let mut parts: Vec<Vec<(String, RPMFileEntry)>> = Vec::new();
+        let (mut header, mut payload) = (0, 0);
+        for (cpio_path, entry) in self.take_files(|_| true) {
+            let (entry_header, entry_payload) = self.estimated_entry_size(&cpio_path, &entry);
+            let entry_header = entry_header + entry.dir.len() as u64 + 1;
+            if parts.is_empty()
+                || overhead + header + entry_header + self.compressed_bound(payload + entry_payload)
+                    > budget
+            {
+                parts.push(Vec::new());
+                header = 0;
+                payload = 0;
+            }
+            header += entry_header;
+            payload += entry_payload;
+            parts.last_mut().unwrap().push((cpio_path, entry));
+        }
+
+        'split: loop {
+            let count = parts.len();
+            let mut packages = Vec::with_capacity(count + 1);
+            for index in 0..count {
+                let desc = self.part_description(index + 1, count);
+                let mut builder = self.companion(&format!("part{}", index + 1), &desc)?;
+                for (cpio_path, entry) in parts[index].iter() {
+                    builder.insert_file(cpio_path.clone(), entry.clone());
+                }
+                let package = builder.build()?;
+                let size = written_size(&package)?;
+                if size > budget {
+                    if parts[index].len() == 1 {
+                        return Err(RPMError::OverBudget {
+                            path: parts[index][0].0.trim_start_matches('.').to_string(),
+                            size,
+                            budget,
+                        });
+                    }
+                    let moved = parts[index].pop().unwrap();
+                    match parts.get_mut(index + 1) {
+                        Some(next) => next.insert(0, moved),
+                        None => parts.push(vec![moved]),
+                    }
+                    continue 'split;
+                }
+                packages.push(package);
+            }
+
+            for package in packages.iter() {
+                let requires = Dependency::eq(package.metadata.header.get_name()?, self.evr());
+                self = self.requires(requires);
+            }
+            packages.insert(0, self.build()?);
+            return Ok(packages);
+        }
+    }
+
+    fn part_description(&self, index: usize, count: usize) -> String {
+        format!(
+            "Part {} of {} of the files of {}.",
+            index,
+            count,
+            self.name()
+        )
+    }
+
+    /// Build one package per architecture from a tree holding binaries for several,
+    /// like the output of a cross compiling build.
+    ///
+    /// ELF files go to the package of their machine type. Every other file goes with
+    /// the nearest directory above it holding ELF files, if those are all of one
+    /// architecture, and to a `noarch` package `<name>-common` otherwise. The
+    /// per-architecture packages keep the name of the builder and require the same
+    /// build of the common package. They come in the order of their architecture
+    /// names, followed by the common package. Without any ELF files a single
+    /// `noarch` package is built.
+    ///
+    /// ELF files for machine types without rpm architecture, like BPF objects, go to
+    /// the common package, see [`build_per_arch_with_diagnostics`](Self::build_per_arch_with_diagnostics).
+    pub fn build_per_arch(self) -> Result<Vec<RPMPackage>, RPMError> {
+        self.build_per_arch_with_diagnostics(&mut Diagnostics::new())
+    }
+
+    /// Like [`build_per_arch`](Self::build_per_arch), reporting ELF files packaged
+    /// as `noarch` and what building the packages reports in `diagnostics`.
+    pub fn build_per_arch_with_diagnostics(
+        mut self,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Vec<RPMPackage>, RPMError> {
+        let mut arches = BTreeMap::new();
+        let mut unknown = BTreeSet::new();
+        for (cpio_path, entry) in self.files.iter() {
+            let content = match (&entry.mode, &entry.content) {
+                (FileMode::Regular { .. }, Some(content)) if elf::is_elf(content) => content,
+                _ => continue,
+            };
+            let path = cpio_path.trim_start_matches('.').to_string();
+            match elf::rpm_arch(content) {
+                Some(arch) => {
+                    arches.insert(path, arch);
+                }
+                None => {
+                    diagnostics.push(Diagnostic::UnknownMachine {
+                        path: path.clone(),
+                        machine: elf::machine(content).unwrap_or_default(),
+                    });
+                    unknown.insert(path);
+                }
+            }
+        }
+        // the ELF files reported above are expected in the noarch package
+        let check_noarch = self.check_noarch && unknown.is_empty();
+        if arches.is_empty() {
+            self.arch = "noarch".to_string();
+            self.check_noarch = check_noarch;
+            return Ok(vec![self.build_with_diagnostics(diagnostics)?]);
+        }
+
+        // architectures of the ELF files below each directory
+        let mut below: HashMap<&str, BTreeSet<&'static str>> = HashMap::new();
+        for (path, arch) in arches.iter() {
+            for dir in parents(path) {
+                below.entry(dir).or_default().insert(*arch);
+            }
+        }
+        let arch_of = |path: &str| -> Option<&'static str> {
+            if unknown.contains(path) {
+                return None;
+            }
+            if let Some(arch) = arches.get(path) {
+                return Some(arch);
+            }
+            let found = std::iter::once(path)
+                .chain(parents(path))
+                .find_map(|dir| below.get(dir))?;
+            match found.iter().collect::<Vec<_>>().as_slice() {
+                [arch] => Some(**arch),
+                _ => None,
+            }
+        };
+
+        let mut per_arch: BTreeMap<&'static str, Vec<(String, RPMFileEntry)>> = BTreeMap::new();
+        let mut common = Vec::new();
+        for (cpio_path, entry) in self.take_files(|_| true) {
+            match arch_of(cpio_path.trim_start_matches('.')) {
+                Some(arch) => per_arch.entry(arch).or_default().push((cpio_path, entry)),
+                None => common.push((cpio_path, entry)),
+            }
+        }
+
+        let common = if common.is_empty() {
+            None
+        } else {
+            let desc = format!("Architecture independent files of {}.", self.name());
+            let mut builder = self.companion("common", &desc)?;
+            builder.arch = "noarch".to_string();
+            builder.check_noarch = check_noarch;
+            for (cpio_path, entry) in common {
+                builder.insert_file(cpio_path, entry);
+            }
+            Some(builder)
+        };
+
+        let mut packages = Vec::with_capacity(per_arch.len() + 1);
+        for (arch, files) in per_arch {
+            let mut builder = self.clone();
+            builder.arch = arch.to_string();
+            for (cpio_path, entry) in files {
+                builder.insert_file(cpio_path, entry);
+            }
+            if let Some(common) = common.as_ref() {
+                builder = builder.requires(Dependency::eq(common.name(), self.evr()));
+            }
+            packages.push(builder.build_with_diagnostics(diagnostics)?);
+        }
+        if let Some(common) = common {
+            packages.push(common.build_with_diagnostics(diagnostics)?);
+        }
+        Ok(packages)
+    }
+}
+
+/// The number of bytes `package` writes.
+fn written_size(package: &RPMPackage) -> Result<u64, RPMError> {
+    let mut out = ChecksumWriter::new(std::io::sink())
+        .sha256(false)
+        .sha1(false);
+    package.write(&mut out)?;
+    Ok(out.finish().1.size)
+}
+
+/// The directories above `path`, nearest first.
+fn parents(path: &str) -> impl Iterator<Item = &str> {
+    let mut rest = path;
+    std::iter::from_fn(move || {
+        let idx = rest.rfind('/')?;
+        rest = &rest[..idx];
+        Some(if rest.is_empty() { "/" } else { rest })
+    })
+}
+
+/// Whether `pattern` matches `path` itself or any of its parent directories.
+pub(crate) fn matches_pattern(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.trim_end_matches('/');
+    let mut prefix = path;
+    loop {
+        if glob_match(pattern.as_bytes(), prefix.as_bytes()) {
+            return true;
+        }
+        match prefix.rfind('/') {
+            Some(idx) if idx > 0 => prefix = &prefix[..idx],
+            _ => return false,
+        }
+    }
+}
+
+/// Shell style matching, `*` stops at `/`, `**` does not.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => {
+            let (rest, crosses) = match rest.split_first() {
+                Some((b'*', rest)) => (rest, true),
+                _ => (rest, false),
+            };
+            for skip in 0..=text.len() {
+                if glob_match(rest, &text[skip..]) {
+                    return true;
+                }
+                if skip < text.len() && text[skip] == b'/' && !crosses {
+                    return false;
+                }
+            }
+            false
+        }
+        Some((c, rest)) => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RPMFileOptions;
+
+    #[test]
+    fn patterns() {
+        let devel = SubPackage::new("devel", "headers")
+            .files("/usr/include")
+            .files("/usr/lib64/*.so");
+        assert!(devel.matches("/usr/include/awesome/awesome.h"));
+        assert!(devel.matches("/usr/lib64/libawesome.so"));
+        assert!(!devel.matches("/usr/lib64/libawesome.so.1"));
+        assert!(!devel.matches("/usr/lib64/awesome/plugin.so"));
+        assert!(!devel.matches("/usr/includes/awesome.h"));
+
+        let doc = SubPackage::new("doc", "documentation").files("/usr/share/**.html");
+        assert!(doc.matches("/usr/share/doc/awesome/index.html"));
+    }
+
+    #[test]
+    fn build_subpackages() -> Result<(), Box<dyn std::error::Error>> {
+        let packages = RPMBuilder::new("awesome", "1.0.0", "MIT", "x86_64", "an awesome tool")
+            .with_file(
+                "./test_assets/awesome.py",
+                RPMFileOptions::new("/usr/bin/awesome"),
+            )?
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/usr/include/awesome.h"),
+            )?
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/usr/share/doc/awesome/README"),
+            )?
+            .subpackage(SubPackage::new("devel", "headers").files("/usr/include"))
+            .subpackage(
+                SubPackage::new("doc", "documentation")
+                    .files("/usr/share/doc")
+                    .requires_main(false),
+            )
+            .build_all()?;
+
+        let names = packages
+            .iter()
+            .map(|pkg| pkg.metadata.header.get_name().map(str::to_string))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(vec!["awesome", "awesome-devel", "awesome-doc"], names);
+
+        let paths = packages
+            .iter()
+            .map(|pkg| pkg.metadata.header.get_file_paths())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(vec![std::path::PathBuf::from("/usr/bin/awesome")], paths[0]);
+        assert_eq!(
+            vec![std::path::PathBuf::from("/usr/include/awesome.h")],
+            paths[1]
+        );
+        assert_eq!(
+            vec![std::path::PathBuf::from("/usr/share/doc/awesome/README")],
+            paths[2]
+        );
+
+        let requires = packages[1].metadata.header.get_requires()?;
+        assert!(requires
+            .iter()
+            .any(|dep| dep.name() == "awesome" && dep.version() == "1.0.0-1"));
+        assert!(!packages[2]
+            .metadata
+            .header
+            .get_requires()?
+            .iter()
+            .any(|dep| dep.name() == "awesome"));
+        Ok(())
+    }
+
+    #[test]
+    fn subpackages_inherit_settings() -> Result<(), Box<dyn std::error::Error>> {
+        let builder = || {
+            RPMBuilder::new("awesome", "1.0.0", "MIT", "x86_64", "an awesome tool")
+                .remap_prefix("/usr/local", "/opt/awesome")
+                .prefix("/opt/awesome")
+                .default_attributes(Some(0o600), Some("awesome"), None)
+                .with_file(
+                    "./test_assets/awesome.py",
+                    RPMFileOptions::new("/usr/local/bin/awesome"),
+                )?
+                .with_file(
+                    "./test_assets/awesome.toml",
+                    RPMFileOptions::new("/usr/local/include/awesome.h"),
+                )
+        };
+        let packages = builder()?
+            .subpackage(SubPackage::new("devel", "headers").files("/usr/local/include"))
+            .build_all()?;
+        // one file less than a single package
+        let single = written_size(&builder()?.build()?)?;
+        let parts = builder()?.build_split(single - 1)?;
+        assert_eq!(3, parts.len());
+        for pkg in [&packages[1], &parts[2]] {
+            let header = &pkg.metadata.header;
+            assert_eq!(
+                vec![std::path::PathBuf::from("/opt/awesome/include/awesome.h")],
+                header.get_file_paths()?
+            );
+            assert_eq!(&["/opt/awesome".to_string()], header.get_prefixes()?);
+            let entry = &header.get_file_entries()?[0];
+            assert_eq!("awesome", entry.ownership.user());
+            assert_eq!(0o600, entry.mode.permissions());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn build_split() -> Result<(), Box<dyn std::error::Error>> {
+        // incompressible content
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut noise = |len: usize| {
+            (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect::<Vec<u8>>()
+        };
+        let mut builder = RPMBuilder::new("awesome", "1.0.0", "MIT", "x86_64", "an awesome tool");
+        for i in 0..12 {
+            builder.add_data(
+                noise(3000),
+                crate::Timestamp::EPOCH,
+                RPMFileOptions::new(format!("/usr/share/awesome/{:02}.bin", i)).into(),
+            )?;
+        }
+        let builder =
+            builder.compression(<crate::Compressor as std::str::FromStr>::from_str("zstd")?);
+
+        let packages = builder.clone().build_split(1 << 20)?;
+        assert_eq!(1, packages.len());
+
+        let budget = 16 * 1024;
+        let packages = builder.clone().build_split(budget)?;
+        assert!(packages.len() > 2);
+        let names = packages
+            .iter()
+            .map(|pkg| pkg.metadata.header.get_name().map(str::to_string))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!("awesome", names[0]);
+        assert_eq!("awesome-part1", names[1]);
+        assert!(packages[0].metadata.header.get_file_paths().is_err());
+        let mut paths = Vec::new();
+        for package in &packages[1..] {
+            assert!(written_size(package)? <= budget);
+            paths.extend(package.metadata.header.get_file_paths()?);
+        }
+        assert_eq!(12, paths.len());
+        assert!(paths.windows(2).all(|pair| pair[0] < pair[1]));
+        let requires = packages[0].metadata.header.get_requires()?;
+        for part in &names[1..] {
+            assert!(requires
+                .iter()
+                .any(|dep| dep.name() == part && dep.version() == "1.0.0-1"));
+        }
+
+        assert!(matches!(
+            builder.build_split(2048),
+            Err(RPMError::OverBudget { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn build_per_arch() -> Result<(), Box<dyn std::error::Error>> {
+        let x86_64 = crate::rpm::elf::sample(b"x86");
+        let mut aarch64 = x86_64.clone();
+        aarch64[18..20].copy_from_slice(&183u16.to_le_bytes());
+        let mut builder = RPMBuilder::new("awesome", "1.0.0", "MIT", "x86_64", "an awesome tool");
+        for (content, path) in [
+            (&x86_64, "/opt/awesome/x86_64/bin/awesome"),
+            (&aarch64, "/opt/awesome/aarch64/bin/awesome"),
+            (&x86_64, "/usr/lib/x86_64-linux-gnu/libawesome.so.1"),
+        ] {
+            builder.add_data(
+                content.clone(),
+                crate::Timestamp::EPOCH,
+                RPMFileOptions::new(path).into(),
+            )?;
+        }
+        for path in [
+            "/opt/awesome/aarch64/etc/awesome.toml",
+            "/opt/awesome/share/awesome.toml",
+            "/usr/lib/x86_64-linux-gnu/awesome.toml",
+        ] {
+            builder.add_data(
+                b"fast = true".to_vec(),
+                crate::Timestamp::EPOCH,
+                RPMFileOptions::new(path).into(),
+            )?;
+        }
+        let packages = builder.build_per_arch()?;
+
+        let summary = packages
+            .iter()
+            .map(|pkg| {
+                let header = &pkg.metadata.header;
+                Ok((
+                    header.get_name()?.to_string(),
+                    header.get_arch()?.to_string(),
+                    header.get_file_paths()?,
+                ))
+            })
+            .collect::<Result<Vec<_>, RPMError>>()?;
+        let paths = |paths: &[&str]| {
+            paths
+                .iter()
+                .map(std::path::PathBuf::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                (
+                    "awesome".to_string(),
+                    "aarch64".to_string(),
+                    paths(&[
+                        "/opt/awesome/aarch64/bin/awesome",
+                        "/opt/awesome/aarch64/etc/awesome.toml"
+                    ])
+                ),
+                (
+                    "awesome".to_string(),
+                    "x86_64".to_string(),
+                    paths(&[
+                        "/opt/awesome/x86_64/bin/awesome",
+                        "/usr/lib/x86_64-linux-gnu/awesome.toml",
+                        "/usr/lib/x86_64-linux-gnu/libawesome.so.1"
+                    ])
+                ),
+                (
+                    "awesome-common".to_string(),
+                    "noarch".to_string(),
+                    paths(&["/opt/awesome/share/awesome.toml"])
+                ),
+            ],
+            summary
+        );
+        let requires = packages[0].metadata.header.get_requires()?;
+        assert!(requires
+            .iter()
+            .any(|dep| dep.dep_name == "awesome-common" && dep.version == "1.0.0-1"));
+
+        // BPF objects go to the common package, even next to binaries of one architecture
+        let mut bpf = x86_64.clone();
+        bpf[18..20].copy_from_slice(&247u16.to_le_bytes());
+        let mut builder = RPMBuilder::new("awesome", "1.0.0", "MIT", "x86_64", "an awesome tool");
+        builder.add_data(
+            x86_64,
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/usr/lib/awesome/awesome").into(),
+        )?;
+        builder.add_data(
+            bpf.clone(),
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/usr/lib/awesome/probe.o").into(),
+        )?;
+        let mut diagnostics = Diagnostics::new();
+        let packages = builder.build_per_arch_with_diagnostics(&mut diagnostics)?;
+        assert_eq!(2, packages.len());
+        assert_eq!("noarch", packages[1].metadata.header.get_arch()?);
+        assert_eq!(
+            paths(&["/usr/lib/awesome/probe.o"]),
+            packages[1].metadata.header.get_file_paths()?
+        );
+        assert_eq!(
+            vec![Diagnostic::UnknownMachine {
+                path: "/usr/lib/awesome/probe.o".to_string(),
+                machine: 247,
+            }],
+            diagnostics.iter().cloned().collect::<Vec<_>>()
+        );
+
+        let mut builder = RPMBuilder::new("awesome", "1.0.0", "MIT", "x86_64", "an awesome tool");
+        builder.add_data(
+            bpf,
+            crate::Timestamp::EPOCH,
+            RPMFileOptions::new("/usr/lib/awesome/probe.o").into(),
+        )?;
+        let packages = builder.build_per_arch()?;
+        assert_eq!("noarch", packages[0].metadata.header.get_arch()?);
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+//! Shipping systemd units, with the scriptlets the `%systemd_*` macros would generate.
+
+use std::path::Path;
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::{Dependency, FileMode, RPMBuilder, RPMFileOptions};
+
+/// Directory systemd looks for units shipped by packages.
+pub const SYSTEMD_UNIT_DIR: &str = "/usr/lib/systemd/system";
+
+/// How a unit added with [`RPMBuilder::with_systemd_unit`] is handled on install, upgrade and removal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnitOptions {
+    /// Enable the unit on first install, instead of leaving that decision to the
+    /// preset policy of the system, which is what `%systemd_post` does.
+    pub enable: bool,
+    /// Restart the unit on upgrade if it is running, like `%systemd_postun_with_restart`.
+    pub restart_on_upgrade: bool,
+}
+
+impl Default for UnitOptions {
+    /// Apply the presets and restart on upgrade, the behaviour most distributions ask for.
+    fn default() -> Self {
+        UnitOptions {
+            enable: false,
+            restart_on_upgrade: true,
+        }
+    }
+}
+
+impl RPMBuilder {
+    /// Install the unit file at `source` to [`SYSTEMD_UNIT_DIR`], keeping its file name.
+    ///
+    /// The unit is set up on first install, stopped and disabled on removal and
+    /// systemd is reloaded afterwards. All scriptlets are appended to the ones
+    /// already set and `systemd` is required for each of them.
+    pub fn with_systemd_unit<P: AsRef<Path>>(
+        self,
+        source: P,
+        options: UnitOptions,
+    ) -> Result<Self, RPMError> {
+        let source = source.as_ref();
+        let unit = source
+            .file_name()
+            .ok_or_else(|| RPMError::InvalidDestinationPath {
+                path: source.to_string_lossy().to_string(),
+                desc: "unit file has no file name",
+            })?
+            .to_string_lossy()
+            .to_string();
+        self.with_named_systemd_unit(&unit, source, options)
+    }
+
+    pub(crate) fn with_named_systemd_unit<P: AsRef<Path>>(
+        self,
+        unit: &str,
+        source: P,
+        options: UnitOptions,
+    ) -> Result<Self, RPMError> {
+        let mut builder = self
+            .with_file(
+                source,
+                RPMFileOptions::new(format!("{}/{}", SYSTEMD_UNIT_DIR, unit))
+                    .mode(FileMode::regular(0o644)),
+            )?
+            .append_script(IndexTag::RPMTAG_POSTIN, &post(unit, options))
+            .append_script(IndexTag::RPMTAG_PREUN, &preun(unit))
+            .append_script(IndexTag::RPMTAG_POSTUN, &postun(unit, options));
+        for sense in [
+            RPMSENSE_SCRIPT_POST,
+            RPMSENSE_SCRIPT_PREUN,
+            RPMSENSE_SCRIPT_POSTUN,
+        ]
+        .iter()
+        {
+            builder = builder.requires(Dependency::new(
+                "systemd".to_string(),
+                *sense,
+                String::new(),
+            ));
+        }
+        Ok(builder)
+    }
+}
+
+fn post(unit: &str, options: UnitOptions) -> String {
+    let action = if options.enable { "enable" } else { "preset" };
+    format!(
+        r#"if [ $1 -eq 1 ] ; then
+    # Initial installation
+    systemctl --no-reload {} {} >/dev/null 2>&1 || :
+fi
+"#,
+        action, unit
+    )
+}
+
+fn preun(unit: &str) -> String {
+    format!(
+        r#"if [ $1 -eq 0 ] ; then
+    # Package removal, not upgrade
+    systemctl --no-reload disable --now {} >/dev/null 2>&1 || :
+fi
+"#,
+        unit
+    )
+}
+
+fn postun(unit: &str, options: UnitOptions) -> String {
+    let mut script = "systemctl daemon-reload >/dev/null 2>&1 || :\n".to_string();
+    if options.restart_on_upgrade {
+        script.push_str(&format!(
+            r#"if [ $1 -ge 1 ] ; then
+    # Package upgrade, not uninstall
+    systemctl try-restart {} >/dev/null 2>&1 || :
+fi
+"#,
+            unit
+        ));
+    }
+    script
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unit_scriptlets() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "an awesome daemon")
+            .with_systemd_unit(
+                "./test_assets/awesome.toml",
+                UnitOptions {
+                    enable: true,
+                    restart_on_upgrade: false,
+                },
+            )?
+            .build()?;
+        let header = &pkg.metadata.header;
+        assert_eq!(
+            vec![std::path::PathBuf::from(
+                "/usr/lib/systemd/system/awesome.toml"
+            )],
+            header.get_file_paths()?
+        );
+        assert_eq!(
+            vec![0o100644],
+            header
+                .get_entry_i16_array_data(IndexTag::RPMTAG_FILEMODES)?
+                .iter()
+                .map(|mode| *mode as u16 as u32)
+                .collect::<Vec<_>>()
+        );
+        assert!(header
+            .get_entry_string_data(IndexTag::RPMTAG_POSTIN)?
+            .contains("enable awesome.toml"));
+        let postun = header.get_entry_string_data(IndexTag::RPMTAG_POSTUN)?;
+        assert!(postun.contains("daemon-reload"));
+        assert!(!postun.contains("try-restart"));
+        Ok(())
+    }
+}
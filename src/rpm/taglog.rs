@@ -0,0 +1,152 @@
+//! A record of every tag of a package, to archive which metadata shipped.
+
+use std::convert::TryFrom;
+use std::path::PathBuf;
+
+use sha2::Digest;
+
+use crate::errors::*;
+
+use super::attestation::json_string;
+use super::dump::preview;
+use super::headers::*;
+use super::{RPMBuilder, RPMPackageMetadata};
+
+/// A tag of a package, see [`RPMPackageMetadata::tag_log`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagRecord {
+    /// `signature` or `header`.
+    pub header: &'static str,
+    pub tag: u32,
+    pub name: String,
+    /// Type of the data, like `StringArray` or `i32`.
+    pub data_type: String,
+    pub count: u32,
+    /// Lowercase hex sha256 of the data as stored in the header, so archived records
+    /// pin the exact value.
+    pub sha256: String,
+    /// A shortened rendering of the value.
+    pub summary: String,
+}
+
+/// The tags of a package in the order they are stored, signature header first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagLog {
+    pub records: Vec<TagRecord>,
+}
+
+impl TagLog {
+    /// The log as a JSON object with a `tags` array, one object per record.
+    pub fn to_json(&self) -> String {
+        let records = self
+            .records
+            .iter()
+            .map(|record| {
+                format!(
+                    "    {{\"header\": {}, \"tag\": {}, \"name\": {}, \"type\": {}, \"count\": {}, \"sha256\": {}, \"summary\": {}}}",
+                    json_string(record.header),
+                    record.tag,
+                    json_string(&record.name),
+                    json_string(&record.data_type),
+                    record.count,
+                    json_string(&record.sha256),
+                    json_string(&record.summary)
+                )
+            })
+            .collect::<Vec<_>>();
+        if records.is_empty() {
+            return "{\n  \"tags\": []\n}\n".to_string();
+        }
+        format!("{{\n  \"tags\": [\n{}\n  ]\n}}\n", records.join(",\n"))
+    }
+}
+
+impl RPMPackageMetadata {
+    /// Record every tag of the signature header and the header.
+    pub fn tag_log(&self) -> TagLog {
+        let mut records = Vec::new();
+        records_of(&mut records, "signature", &self.signature);
+        records_of(&mut records, "header", &self.header);
+        TagLog { records }
+    }
+}
+
+fn records_of<T>(records: &mut Vec<TagRecord>, name: &'static str, header: &Header<T>)
+where
+    T: Tag,
+{
+    for entry in &header.index_entries {
+        let data = usize::try_from(entry.offset)
+            .ok()
+            .and_then(|offset| header.store.get(offset..offset + entry.data.store_len()))
+            .unwrap_or_default();
+        records.push(TagRecord {
+            header: name,
+            tag: entry.tag.to_u32().unwrap_or_default(),
+            name: entry.tag.to_string(),
+            data_type: entry.data.to_string(),
+            count: entry.num_items,
+            sha256: hex::encode(sha2::Sha256::digest(data)),
+            summary: preview(&entry.data),
+        });
+    }
+}
+
+impl RPMBuilder {
+    /// Write the [`TagLog`] of the package to `path` as JSON after a successful build.
+    pub fn tag_log<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.tag_log = Some(path.into());
+        self
+    }
+
+    /// Write the tag log if one was asked for.
+    pub(crate) fn write_tag_log(
+        path: Option<PathBuf>,
+        metadata: &RPMPackageMetadata,
+    ) -> Result<(), RPMError> {
+        if let Some(path) = path {
+            std::fs::write(path, metadata.tag_log().to_json())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tag_log() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = crate::tests::TestDir::new("taglog");
+        let path = dir.join("foo.tags.json");
+        let pkg = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "a \"quoted\" description")
+            .with_file(
+                "./test_assets/awesome.toml",
+                crate::RPMFileOptions::new("/etc/awesome/config.toml"),
+            )?
+            .tag_log(&path)
+            .build()?;
+
+        let log = pkg.metadata.tag_log();
+        assert_eq!(
+            pkg.metadata.signature.index_entries.len() + pkg.metadata.header.index_entries.len(),
+            log.records.len()
+        );
+        assert_eq!("signature", log.records[0].header);
+        let name = log
+            .records
+            .iter()
+            .find(|record| record.name == "RPMTAG_NAME")
+            .expect("name");
+        assert_eq!(1000, name.tag);
+        assert_eq!("String", name.data_type);
+        assert_eq!("\"foo\"", name.summary);
+        assert_eq!(hex::encode(sha2::Sha256::digest(b"foo\0")), name.sha256);
+
+        let json = std::fs::read_to_string(&path)?;
+        assert_eq!(log.to_json(), json);
+        assert!(json.contains(r#""name": "RPMTAG_DESCRIPTION", "type": "String", "count": 1"#));
+        assert!(json.contains(r#""summary": "\"a \\\"quoted\\\" description\"""#));
+        Ok(())
+    }
+}
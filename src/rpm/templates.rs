@@ -0,0 +1,430 @@
+//! Builder fragments for common kinds of packages.
+//!
+//! Every template takes a builder and hands it back with files, dependencies and
+//! scriptlets added, such that templates compose with each other and with anything
+//! set on the builder directly. Scriptlets are appended to the ones already set.
+//!
+//! ```rust
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let builder = rpm::RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "an awesome daemon");
+//! let builder = rpm::templates::systemd_service(
+//!     builder,
+//!     "awesome.service",
+//!     "./test_assets/awesome.toml",
+//!     Some("awesome"),
+//! )?;
+//! let pkg = builder.build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::Path;
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::{
+    shell_quote, Dependency, FileMode, FileTrigger, InstallOnly, RPMBuilder, RPMFileOptions, Uid,
+    UnitOptions,
+};
+
+pub use super::systemd::SYSTEMD_UNIT_DIR;
+
+/// Directory the kernel, its modules and modules built for it are installed below,
+/// one directory per kernel release.
+pub const MODULES_DIR: &str = "/lib/modules";
+
+/// Directory boot loaders read kernels and initramfs images from.
+pub const BOOT_DIR: &str = "/boot";
+
+/// Install `source` as systemd unit `unit`, with what `%systemd_post`,
+/// `%systemd_preun` and `%systemd_postun_with_restart` expand to as scriptlets.
+///
+/// The unit is preset on first install, stopped and disabled on removal and
+/// restarted on upgrade. If `user` is given, a system user and group of that
+/// name are created before install, see [`RPMBuilder::with_system_user`].
+pub fn systemd_service<P: AsRef<Path>>(
+    builder: RPMBuilder,
+    unit: &str,
+    source: P,
+    user: Option<&str>,
+) -> Result<RPMBuilder, RPMError> {
+    let mut builder = builder.with_named_systemd_unit(unit, source, UnitOptions::default())?;
+    if let Some(user) = user {
+        builder = builder.with_system_user(user, Uid::Dynamic)?;
+    }
+    Ok(builder)
+}
+
+/// Install shared libraries from `(source, destination)` pairs and refresh the linker
+/// cache once after a transaction installs or removes libraries in their directories,
+/// with transaction file triggers like the one of glibc.
+///
+/// Distributions shipping the glibc trigger run ldconfig once more, which is harmless.
+pub fn shared_library<P: AsRef<Path>>(
+    builder: RPMBuilder,
+    libraries: &[(P, &str)],
+) -> Result<RPMBuilder, RPMError> {
+    let mut builder = builder;
+    let mut dirs = Vec::new();
+    for (source, destination) in libraries {
+        builder = builder.with_file(
+            source,
+            RPMFileOptions::new(*destination).mode(FileMode::regular(0o755)),
+        )?;
+        let dir = match destination.rfind('/') {
+            Some(idx) => &destination[..=idx],
+            None => "/",
+        };
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    if dirs.is_empty() {
+        return Ok(builder);
+    }
+    Ok(builder
+        .file_trigger(FileTrigger::TransIn, &dirs, "/sbin/ldconfig\n")
+        .file_trigger(FileTrigger::TransPostUn, &dirs, "/sbin/ldconfig\n")
+        .requires(Dependency::any("/sbin/ldconfig")))
+}
+
+/// Install configuration files from `(source, destination)` pairs as `%config(noreplace)`,
+/// readable by everyone.
+///
+/// Local modifications survive upgrades, rpm places the new version next to
+/// a modified file as `.rpmnew` instead.
+pub fn config_only<P: AsRef<Path>>(
+    builder: RPMBuilder,
+    files: &[(P, &str)],
+) -> Result<RPMBuilder, RPMError> {
+    let mut builder = builder;
+    for (source, destination) in files {
+        builder = builder.with_file(
+            source,
+            RPMFileOptions::new(*destination)
+                .mode(FileMode::regular(0o644))
+                .is_config_noreplace(),
+        )?;
+    }
+    Ok(builder)
+}
+
+/// Install the kernel image `vmlinuz` built as `release`, i.e. its `uname -r`, like
+/// the kernel packages of Fedora and RHEL do.
+///
+/// The image is installed to [`MODULES_DIR`]`/<release>/vmlinuz`, `kernel-install`
+/// copies it to [`BOOT_DIR`] and generates the initramfs after the transaction and
+/// removes both before the package is. Both files in [`BOOT_DIR`] are owned by the
+/// package as `%ghost`s. The package is [install-only](InstallOnly::Kernel) and
+/// provides `kernel-uname-r`, for modules built with [`kernel_module`].
+/// Transaction file triggers run `depmod` whenever modules for `release` are
+/// installed or removed, modules of the kernel itself are added with
+/// [`RPMBuilder::with_file`].
+pub fn kernel<P: AsRef<Path>>(
+    builder: RPMBuilder,
+    release: &str,
+    vmlinuz: P,
+) -> Result<RPMBuilder, RPMError> {
+    let modules = format!("{}/{}", MODULES_DIR, release);
+    let image = format!("{}/vmlinuz", modules);
+    let release_arg = shell_quote(release);
+    let depmod = format!("/usr/sbin/depmod -a {} || :\n", release_arg);
+    let mut builder = builder
+        .with_file(
+            vmlinuz,
+            RPMFileOptions::new(image.as_str()).mode(FileMode::regular(0o755)),
+        )?
+        .with_ghost(
+            RPMFileOptions::new(format!("{}/vmlinuz-{}", BOOT_DIR, release))
+                .mode(FileMode::regular(0o600)),
+        )?
+        .with_ghost(
+            RPMFileOptions::new(format!("{}/initramfs-{}.img", BOOT_DIR, release))
+                .mode(FileMode::regular(0o600)),
+        )?
+        .append_script(
+            IndexTag::RPMTAG_POSTTRANS,
+            &format!(
+                "/usr/bin/kernel-install add {} {} || exit $?\n",
+                release_arg,
+                shell_quote(&image)
+            ),
+        )
+        .append_script(
+            IndexTag::RPMTAG_PREUN,
+            &format!(
+                "/usr/bin/kernel-install remove {} || exit $?\n",
+                release_arg
+            ),
+        )
+        .file_trigger(FileTrigger::TransIn, &[format!("{}/", modules)], &depmod)
+        .file_trigger(
+            FileTrigger::TransPostUn,
+            &[format!("{}/", modules)],
+            &depmod,
+        )
+        .provides(Dependency::eq("kernel-uname-r", release))
+        .install_only(InstallOnly::Kernel)
+        .requires(Dependency::any("/usr/sbin/depmod"));
+    for sense in [RPMSENSE_POSTTRANS, RPMSENSE_SCRIPT_PREUN].iter() {
+        builder = builder.requires(Dependency::new(
+            "/usr/bin/kernel-install".to_string(),
+            *sense,
+            String::new(),
+        ));
+    }
+    Ok(builder)
+}
+
+/// Install the kernel modules in `modules` for kernel `release` to
+/// [`MODULES_DIR`]`/<release>/extra/<name>/`, keeping their file names.
+/// Transaction file triggers run `depmod` once after modules for `release` are
+/// installed or removed, like those of [`kernel`].
+///
+/// With `weak_modules` the modules are also registered with `weak-modules`, which
+/// links them into newer kernels with a compatible kABI like the kmod packages
+/// of RHEL, and `/usr/sbin/weak-modules` is required for that. Without it the
+/// package requires exactly `release` and is [install-only](InstallOnly::KernelModule),
+/// such that a package per kernel can be installed.
+pub fn kernel_module<P: AsRef<Path>>(
+    builder: RPMBuilder,
+    release: &str,
+    modules: &[P],
+    weak_modules: bool,
+) -> Result<RPMBuilder, RPMError> {
+    let dir = format!("{}/{}/extra/{}", MODULES_DIR, release, builder.name());
+    let mut builder = builder;
+    let mut paths = Vec::new();
+    for source in modules {
+        let source = source.as_ref();
+        let file_name = source
+            .file_name()
+            .ok_or_else(|| RPMError::InvalidDestinationPath {
+                path: source.to_string_lossy().to_string(),
+                desc: "kernel module has no file name",
+            })?
+            .to_string_lossy()
+            .to_string();
+        let path = format!("{}/{}", dir, file_name);
+        builder = builder.with_file(
+            source,
+            RPMFileOptions::new(path.as_str()).mode(FileMode::regular(0o644)),
+        )?;
+        paths.push(shell_quote(&path));
+    }
+
+    let modules = format!("{}/{}/", MODULES_DIR, release);
+    let depmod = format!("/usr/sbin/depmod -a {} || :\n", shell_quote(release));
+    builder = builder
+        .file_trigger(FileTrigger::TransIn, &[modules.as_str()], &depmod)
+        .file_trigger(FileTrigger::TransPostUn, &[modules.as_str()], &depmod)
+        .requires(Dependency::any("/usr/sbin/depmod"));
+    if weak_modules {
+        let list = format!("printf '%s\\n' {}", paths.join(" "));
+        builder = builder
+            .append_script(
+                IndexTag::RPMTAG_POSTIN,
+                &format!("{} | /usr/sbin/weak-modules --add-modules\n", list),
+            )
+            .append_script(
+                IndexTag::RPMTAG_POSTUN,
+                &format!("{} | /usr/sbin/weak-modules --remove-modules\n", list),
+            );
+        for sense in [RPMSENSE_SCRIPT_POST, RPMSENSE_SCRIPT_POSTUN].iter() {
+            builder = builder.requires(Dependency::new(
+                "/usr/sbin/weak-modules".to_string(),
+                *sense,
+                String::new(),
+            ));
+        }
+    } else {
+        builder = builder
+            .for_kernel_release(release)
+            .install_only(InstallOnly::KernelModule);
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn builder() -> RPMBuilder {
+        RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "an awesome daemon")
+    }
+
+    #[test]
+    fn systemd_service_scriptlets() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = systemd_service(
+            builder().post_install_script("echo custom"),
+            "awesome.service",
+            "./test_assets/awesome.toml",
+            Some("awesome"),
+        )?
+        .build()?;
+        let header = &pkg.metadata.header;
+        assert_eq!(
+            vec![std::path::PathBuf::from(
+                "/usr/lib/systemd/system/awesome.service"
+            )],
+            header.get_file_paths()?
+        );
+
+        let post = header.get_entry_string_data(IndexTag::RPMTAG_POSTIN)?;
+        assert!(post.starts_with("echo custom\n"));
+        assert!(post.contains("preset awesome.service"));
+        assert!(header
+            .get_entry_string_data(IndexTag::RPMTAG_PREUN)?
+            .contains("disable --now awesome.service"));
+        assert!(header
+            .get_entry_string_data(IndexTag::RPMTAG_POSTUN)?
+            .contains("try-restart awesome.service"));
+        assert!(header
+            .get_entry_string_data(IndexTag::RPMTAG_PREIN)?
+            .contains("useradd -r -g awesome"));
+
+        let requires = header.get_requires()?;
+        let systemd = requires
+            .iter()
+            .filter(|dep| dep.name() == "systemd")
+            .fold(0, |flags, dep| flags | dep.flags());
+        assert_eq!(
+            RPMSENSE_SCRIPT_POST | RPMSENSE_SCRIPT_PREUN | RPMSENSE_SCRIPT_POSTUN,
+            systemd
+        );
+        assert!(requires
+            .iter()
+            .any(|dep| dep.name() == "/usr/sbin/useradd" && dep.flags() == RPMSENSE_SCRIPT_PRE));
+        Ok(())
+    }
+
+    #[test]
+    fn library_and_config_templates() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = shared_library(
+            builder(),
+            &[("./test_assets/awesome.py", "/usr/lib64/libawesome.so.1")],
+        )?
+        .build()?;
+        let header = &pkg.metadata.header;
+        assert!(header
+            .get_entry_string_data(IndexTag::RPMTAG_POSTUN)
+            .is_err());
+        assert_eq!(
+            vec!["/usr/lib64/"; 2],
+            header.get_entry_string_array_data(IndexTag::RPMTAG_TRANSFILETRIGGERNAME)?
+        );
+        assert_eq!(
+            vec!["/sbin/ldconfig\n"; 2],
+            header.get_entry_string_array_data(IndexTag::RPMTAG_TRANSFILETRIGGERSCRIPTS)?
+        );
+        assert!(header
+            .get_requires()?
+            .iter()
+            .any(|dep| dep.name() == "/sbin/ldconfig"));
+
+        let pkg = config_only(
+            builder(),
+            &[("./test_assets/awesome.toml", "/etc/awesome/config.toml")],
+        )?
+        .build()?;
+        let flags = pkg
+            .metadata
+            .header
+            .get_entry_i32_array_data(IndexTag::RPMTAG_FILEFLAGS)?;
+        assert_eq!(vec![RPMFILE_CONFIG | RPMFILE_NOREPLACE], flags);
+        Ok(())
+    }
+
+    #[test]
+    fn kernel_templates() -> Result<(), Box<dyn std::error::Error>> {
+        let release = "6.1.0-1.custom.x86_64";
+        let kernel_builder = RPMBuilder::new("kernel-custom", "6.1.0", "GPLv2", "x86_64", "");
+        let pkg = kernel(kernel_builder, release, "./test_assets/awesome.py")?.build()?;
+        let header = &pkg.metadata.header;
+        assert!(header.is_install_only()?);
+        let paths = header.get_file_paths()?;
+        assert_eq!(3, paths.len());
+        let flags = header.get_entry_i32_array_data(IndexTag::RPMTAG_FILEFLAGS)?;
+        let ghosts = paths
+            .iter()
+            .zip(&flags)
+            .filter(|(_, flags)| *flags & RPMFILE_GHOST != 0)
+            .map(|(path, _)| path.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "/boot/initramfs-6.1.0-1.custom.x86_64.img",
+                "/boot/vmlinuz-6.1.0-1.custom.x86_64",
+            ],
+            ghosts
+        );
+        // ghosts are not in the payload
+        let entries = pkg.payload_entries()?;
+        assert_eq!(1, entries.len());
+        assert_eq!(
+            std::path::PathBuf::from("/lib/modules/6.1.0-1.custom.x86_64/vmlinuz"),
+            entries[0].path
+        );
+        assert!(header
+            .get_entry_string_data(IndexTag::RPMTAG_POSTTRANS)?
+            .starts_with("/usr/bin/kernel-install add 6.1.0-1.custom.x86_64 "));
+        assert_eq!(
+            vec!["/lib/modules/6.1.0-1.custom.x86_64/"; 2],
+            header.get_entry_string_array_data(IndexTag::RPMTAG_TRANSFILETRIGGERNAME)?
+        );
+
+        let module_builder = RPMBuilder::new("awesome-kmod", "1.0.0", "GPLv2", "x86_64", "");
+        let pkg = kernel_module(
+            module_builder.clone(),
+            release,
+            &["./test_assets/awesome.toml"],
+            false,
+        )?
+        .build()?;
+        let header = &pkg.metadata.header;
+        assert_eq!(
+            vec![std::path::PathBuf::from(
+                "/lib/modules/6.1.0-1.custom.x86_64/extra/awesome-kmod/awesome.toml"
+            )],
+            header.get_file_paths()?
+        );
+        assert!(header.is_install_only()?);
+        assert!(header
+            .get_requires()?
+            .iter()
+            .any(|dep| dep.name() == "kernel-uname-r" && dep.version() == release));
+        assert_eq!(
+            vec!["/lib/modules/6.1.0-1.custom.x86_64/"; 2],
+            header.get_entry_string_array_data(IndexTag::RPMTAG_TRANSFILETRIGGERNAME)?
+        );
+        assert!(header
+            .get_entry_string_array_data(IndexTag::RPMTAG_TRANSFILETRIGGERSCRIPTS)?
+            .iter()
+            .all(|script| script.contains("depmod -a")));
+        assert!(header
+            .get_entry_string_data(IndexTag::RPMTAG_POSTIN)
+            .is_err());
+
+        let pkg = kernel_module(
+            module_builder,
+            release,
+            &["./test_assets/awesome.toml"],
+            true,
+        )?
+        .build()?;
+        let header = &pkg.metadata.header;
+        assert!(!header.is_install_only()?);
+        let postun = header.get_entry_string_data(IndexTag::RPMTAG_POSTUN)?;
+        assert!(postun.contains("| /usr/sbin/weak-modules --remove-modules"));
+        assert!(!postun.contains("depmod"));
+        let requires = header.get_requires()?;
+        assert!(!requires.iter().any(|dep| dep.name() == "kernel-uname-r"));
+        let weak_modules = requires
+            .iter()
+            .filter(|dep| dep.name() == "/usr/sbin/weak-modules")
+            .fold(0, |flags, dep| flags | dep.flags());
+        assert_eq!(RPMSENSE_SCRIPT_POST | RPMSENSE_SCRIPT_POSTUN, weak_modules);
+        Ok(())
+    }
+}
@@ -0,0 +1,470 @@
+//! Helpers for testing code that builds or consumes packages, enabled by the `test-util` feature.
+//!
+//! [`PackageSpec::strategy`] generates valid packages for proptest, shrinking
+//! failures to fewer and smaller files and dependencies:
+//!
+//! ```rust,ignore
+//! proptest! {
+//!     #[test]
+//!     fn roundtrip(spec in rpm::test_util::PackageSpec::strategy()) {
+//!         let package = spec.build().unwrap();
+//!         prop_assert_eq!(Vec::<String>::new(), spec.mismatches(&package));
+//!     }
+//! }
+//! ```
+//!
+//! [`PackageSpec::random`] derives such a package from a seed instead, for other
+//! sources of numbers.
+//!
+//! [`rpm_query_command`] and [`compare_rpm_query`] compare a package with what
+//! `rpm` itself reads from it, [`RPMPackage::verify_root`] with what `rpm` installed.
+
+use std::path::Path;
+
+use proptest::prelude::*;
+use rand::{Rng, SeedableRng};
+
+use crate::errors::*;
+
+use super::{Dependency, FileMode, RPMBuilder, RPMFileOptions, RPMPackage, Timestamp};
+
+const ARCHES: [&str; 3] = ["noarch", "x86_64", "aarch64"];
+const LICENSES: [&str; 3] = ["MIT", "Apache-2.0", "GPL-2.0-or-later"];
+const PERMISSIONS: [u16; 4] = [0o644, 0o755, 0o600, 0o640];
+const OWNERS: [&str; 2] = ["root", "daemon"];
+
+/// The query format [`compare_rpm_query`] expects the output of `rpm -qp --qf` in.
+pub const RPM_QUERY_FORMAT: &str = "%{NAME}\\n%{EPOCHNUM}\\n%{VERSION}\\n%{RELEASE}\\n%{ARCH}\\n\
+    [%{FILENAMES}\\t%{FILEMODES:octal}\\t%{FILESIZES}\\t%{FILEUSERNAME}\\t%{FILEGROUPNAME}\\t%{FILEDIGESTS}\\n]";
+
+/// A file of a [`PackageSpec`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSpec {
+    pub path: String,
+    pub content: Vec<u8>,
+    pub permissions: u16,
+    pub user: String,
+    pub group: String,
+    pub config: bool,
+}
+
+/// Everything needed to build a package and to check the result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageSpec {
+    pub name: String,
+    pub epoch: i32,
+    pub version: String,
+    pub release: String,
+    pub arch: String,
+    pub license: String,
+    pub description: String,
+    pub mtime: Timestamp,
+    pub files: Vec<FileSpec>,
+    pub requires: Vec<Dependency>,
+}
+
+impl PackageSpec {
+    /// A random package with a few files and dependencies, the same for the same `seed`.
+    pub fn random(seed: u64) -> Self {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let name = word(&mut rng, 3, 12);
+        let files = (0..rng.gen_range(0..6))
+            .map(|index| {
+                let mut content = vec![0u8; rng.gen_range(0..2048)];
+                rng.fill(content.as_mut_slice());
+                FileSpec {
+                    path: format!("/usr/share/{}/{}/{}.dat", name, word(&mut rng, 1, 8), index),
+                    content,
+                    permissions: PERMISSIONS[rng.gen_range(0..PERMISSIONS.len())],
+                    user: OWNERS[rng.gen_range(0..OWNERS.len())].to_string(),
+                    group: OWNERS[rng.gen_range(0..OWNERS.len())].to_string(),
+                    config: rng.gen_bool(0.2),
+                }
+            })
+            .collect();
+        let requires = (0..rng.gen_range(0..4))
+            .map(|_| {
+                let dependency = word(&mut rng, 2, 10);
+                if rng.gen_bool(0.5) {
+                    Dependency::any(dependency)
+                } else {
+                    Dependency::greater_eq(dependency, version(&mut rng))
+                }
+            })
+            .collect();
+        PackageSpec {
+            epoch: if rng.gen_bool(0.2) {
+                rng.gen_range(1..5)
+            } else {
+                0
+            },
+            version: version(&mut rng),
+            release: rng.gen_range(1..20).to_string(),
+            arch: ARCHES[rng.gen_range(0..ARCHES.len())].to_string(),
+            license: LICENSES[rng.gen_range(0..LICENSES.len())].to_string(),
+            description: format!("{} {}", word(&mut rng, 3, 8), word(&mut rng, 3, 8)),
+            mtime: Timestamp::new(rng.gen_range(1_000_000_000..1_700_000_000)),
+            name,
+            files,
+            requires,
+        }
+    }
+
+    /// Packages like [`random`](Self::random) ones, as a proptest strategy.
+    pub fn strategy() -> impl Strategy<Value = Self> {
+        let file = (
+            word_strategy(1, 8),
+            proptest::collection::vec(any::<u8>(), 0..2048),
+            proptest::sample::select(PERMISSIONS.to_vec()),
+            proptest::sample::select(OWNERS.to_vec()),
+            proptest::sample::select(OWNERS.to_vec()),
+            proptest::bool::weighted(0.2),
+        );
+        let dependency = (
+            word_strategy(2, 10),
+            proptest::option::of(version_strategy()),
+        )
+            .prop_map(|(name, version)| match version {
+                None => Dependency::any(name),
+                Some(version) => Dependency::greater_eq(name, version),
+            });
+        (
+            word_strategy(3, 12),
+            prop_oneof![4 => Just(0), 1 => 1..5i32],
+            version_strategy(),
+            1..20u32,
+            proptest::sample::select(ARCHES.to_vec()),
+            proptest::sample::select(LICENSES.to_vec()),
+            (word_strategy(3, 8), word_strategy(3, 8)),
+            1_000_000_000..1_700_000_000u32,
+            proptest::collection::vec(file, 0..6),
+            proptest::collection::vec(dependency, 0..4),
+        )
+            .prop_map(
+                |(name, epoch, version, release, arch, license, words, mtime, files, requires)| {
+                    let files = files
+                        .into_iter()
+                        .enumerate()
+                        .map(
+                            |(index, (dir, content, permissions, user, group, config))| FileSpec {
+                                path: format!("/usr/share/{}/{}/{}.dat", name, dir, index),
+                                content,
+                                permissions,
+                                user: user.to_string(),
+                                group: group.to_string(),
+                                config,
+                            },
+                        )
+                        .collect();
+                    PackageSpec {
+                        epoch,
+                        version,
+                        release: release.to_string(),
+                        arch: arch.to_string(),
+                        license: license.to_string(),
+                        description: format!("{} {}", words.0, words.1),
+                        mtime: Timestamp::new(mtime),
+                        name,
+                        files,
+                        requires,
+                    }
+                },
+            )
+    }
+
+    /// A builder for the package, to be customized further before building.
+    pub fn builder(&self) -> Result<RPMBuilder, RPMError> {
+        let mut builder = RPMBuilder::new(
+            &self.name,
+            &self.version,
+            &self.license,
+            &self.arch,
+            &self.description,
+        )
+        .epoch(self.epoch)
+        .release(&self.release);
+        for file in self.files.iter() {
+            let mut options = RPMFileOptions::new(file.path.as_str())
+                .mode(FileMode::regular(file.permissions))
+                .user(file.user.as_str())
+                .group(file.group.as_str());
+            if file.config {
+                options = options.is_config();
+            }
+            builder.add_data(file.content.clone(), self.mtime, options.into())?;
+        }
+        for dependency in self.requires.iter() {
+            builder = builder.requires(dependency.clone());
+        }
+        Ok(builder)
+    }
+
+    pub fn build(&self) -> Result<RPMPackage, RPMError> {
+        self.builder()?.build()
+    }
+
+    /// Everything `package` does not agree with the spec on, empty if it matches.
+    pub fn mismatches(&self, package: &RPMPackage) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        let header = &package.metadata.header;
+        let mut compare =
+            |what: &str, expected: String, actual: Result<String, RPMError>| match actual {
+                Ok(actual) if actual == expected => {}
+                Ok(actual) => mismatches.push(format!(
+                    "{}: expected {:?}, found {:?}",
+                    what, expected, actual
+                )),
+                Err(err) => mismatches.push(format!("{}: {}", what, err)),
+            };
+        compare("name", self.name.clone(), header.get_name().map(Into::into));
+        compare(
+            "epoch",
+            self.epoch.to_string(),
+            Ok(header.get_epoch().unwrap_or(0).to_string()),
+        );
+        compare(
+            "version",
+            self.version.clone(),
+            header.get_version().map(Into::into),
+        );
+        compare(
+            "release",
+            self.release.clone(),
+            header.get_release().map(Into::into),
+        );
+        compare("arch", self.arch.clone(), header.get_arch().map(Into::into));
+        compare(
+            "license",
+            self.license.clone(),
+            header.get_license().map(Into::into),
+        );
+
+        let mut expected_files = self
+            .files
+            .iter()
+            .map(|file| {
+                use sha2::Digest;
+                format!(
+                    "{} {:o} {}:{} {}",
+                    file.path,
+                    file.permissions,
+                    file.user,
+                    file.group,
+                    hex::encode(sha2::Sha256::digest(&file.content))
+                )
+            })
+            .collect::<Vec<_>>();
+        expected_files.sort();
+        let actual_files = if self.files.is_empty() {
+            Ok(String::new())
+        } else {
+            header.get_file_entries().map(|entries| {
+                entries
+                    .iter()
+                    .map(|entry| {
+                        format!(
+                            "{} {:o} {}:{} {}",
+                            entry.path.display(),
+                            entry.mode.permissions(),
+                            entry.ownership.user(),
+                            entry.ownership.group(),
+                            entry
+                                .digest
+                                .as_ref()
+                                .map(|digest| digest.to_hex())
+                                .unwrap_or_default()
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+        };
+        compare("files", expected_files.join("\n"), actual_files);
+
+        match header.get_requires() {
+            Ok(requires) => {
+                for dependency in self.requires.iter() {
+                    if !requires.contains(dependency) {
+                        mismatches.push(format!("requires: {} is missing", dependency));
+                    }
+                }
+            }
+            Err(err) => mismatches.push(format!("requires: {}", err)),
+        }
+        mismatches
+    }
+}
+
+fn word<R: Rng>(rng: &mut R, min: usize, max: usize) -> String {
+    const FIRST: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+    const REST: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789_";
+    let len = rng.gen_range(min..=max);
+    (0..len)
+        .map(|index| {
+            let alphabet = if index == 0 { FIRST } else { REST };
+            alphabet[rng.gen_range(0..alphabet.len())] as char
+        })
+        .collect()
+}
+
+fn version<R: Rng>(rng: &mut R) -> String {
+    (0..rng.gen_range(1..4))
+        .map(|_| rng.gen_range(0..30).to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Words as [`word`] generates them.
+fn word_strategy(min: usize, max: usize) -> impl Strategy<Value = String> {
+    proptest::string::string_regex(&format!("[a-z][a-z0-9_]{{{},{}}}", min - 1, max - 1))
+        .expect("valid pattern")
+}
+
+/// Versions as [`version`] generates them.
+fn version_strategy() -> impl Strategy<Value = String> {
+    proptest::collection::vec(0..30u32, 1..4).prop_map(|parts| {
+        parts
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(".")
+    })
+}
+
+/// `rpm -qp --qf` with [`RPM_QUERY_FORMAT`] for the package file at `path`,
+/// its output is what [`compare_rpm_query`] expects.
+pub fn rpm_query_command<P: AsRef<Path>>(path: P) -> std::process::Command {
+    let mut command = std::process::Command::new("rpm");
+    command
+        .arg("-qp")
+        .arg("--qf")
+        .arg(RPM_QUERY_FORMAT)
+        .arg(path.as_ref());
+    command
+}
+
+/// Render what `rpm` prints for [`RPM_QUERY_FORMAT`] from the header of `package`.
+pub fn render_rpm_query(package: &RPMPackage) -> Result<String, RPMError> {
+    let header = &package.metadata.header;
+    let mut out = format!(
+        "{}\n{}\n{}\n{}\n{}\n",
+        header.get_name()?,
+        header.get_epoch().unwrap_or(0),
+        header.get_version()?,
+        header.get_release()?,
+        header.get_arch()?
+    );
+    if header
+        .find_entry_or_err(&crate::IndexTag::RPMTAG_BASENAMES)
+        .is_ok()
+    {
+        for entry in header.get_file_entries()? {
+            let mode: u16 = entry.mode.into();
+            out.push_str(&format!(
+                "{}\t{:o}\t{}\t{}\t{}\t{}\n",
+                entry.path.display(),
+                mode,
+                entry.size,
+                entry.ownership.user(),
+                entry.ownership.group(),
+                entry
+                    .digest
+                    .as_ref()
+                    .map(|digest| digest.to_hex())
+                    .unwrap_or_default()
+            ));
+        }
+    }
+    Ok(out)
+}
+
+/// Compare `package` with the output of [`rpm_query_command`], returning the
+/// lines that differ as `(line, expected from rpm, rendered from package)`.
+pub fn compare_rpm_query(
+    package: &RPMPackage,
+    rpm_output: &str,
+) -> Result<Vec<(usize, String, String)>, RPMError> {
+    let rendered = render_rpm_query(package)?;
+    let expected = rpm_output.lines().collect::<Vec<_>>();
+    let actual = rendered.lines().collect::<Vec<_>>();
+    Ok((0..expected.len().max(actual.len()))
+        .filter_map(|line| {
+            let expected = expected.get(line).copied().unwrap_or_default();
+            let actual = actual.get(line).copied().unwrap_or_default();
+            if expected == actual {
+                None
+            } else {
+                Some((line + 1, expected.to_string(), actual.to_string()))
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn random_packages_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(PackageSpec::random(7), PackageSpec::random(7));
+        for seed in 0..32 {
+            let spec = PackageSpec::random(seed);
+            let package = spec.build()?;
+            let mut written = Vec::new();
+            package.write(&mut written)?;
+            let parsed = RPMPackage::parse(&mut written.as_slice())?;
+            assert_eq!(
+                Vec::<String>::new(),
+                spec.mismatches(&parsed),
+                "seed {}",
+                seed
+            );
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn strategy_packages_roundtrip(spec in PackageSpec::strategy()) {
+            let package = spec.build().unwrap();
+            let mut written = Vec::new();
+            package.write(&mut written).unwrap();
+            let parsed = RPMPackage::parse(&mut written.as_slice()).unwrap();
+            prop_assert_eq!(Vec::<String>::new(), spec.mismatches(&parsed));
+        }
+    }
+
+    #[test]
+    fn compare_with_rpm_query() -> Result<(), Box<dyn std::error::Error>> {
+        let spec = PackageSpec {
+            name: "foo".to_string(),
+            epoch: 0,
+            version: "1.0".to_string(),
+            release: "1".to_string(),
+            arch: "noarch".to_string(),
+            license: "MIT".to_string(),
+            description: "foo".to_string(),
+            mtime: Timestamp::new(1_600_000_000),
+            files: vec![FileSpec {
+                path: "/etc/foo.conf".to_string(),
+                content: b"foo".to_vec(),
+                permissions: 0o644,
+                user: "root".to_string(),
+                group: "root".to_string(),
+                config: true,
+            }],
+            requires: Vec::new(),
+        };
+        let package = spec.build()?;
+        let rpm_output = "foo\n0\n1.0\n1\nnoarch\n/etc/foo.conf\t100644\t3\troot\troot\t\
+            2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae\n";
+        assert!(compare_rpm_query(&package, rpm_output)?.is_empty());
+        assert_eq!(
+            vec![(3, "1.1".to_string(), "1.0".to_string())],
+            compare_rpm_query(&package, &rpm_output.replace("1.0", "1.1"))?
+        );
+        Ok(())
+    }
+}
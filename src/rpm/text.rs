@@ -0,0 +1,334 @@
+//! Tidying summaries and descriptions the way distribution review tools expect them.
+//!
+//! Descriptions are plain text wrapped to [`DESCRIPTION_WIDTH`] columns, rpm shows
+//! them as they are. Lines starting with whitespace are kept verbatim, lines
+//! starting with `-` or `*` are list items wrapped with a hanging indent.
+//! Descriptions taken from a README can have their markdown stripped first.
+//!
+//! ```rust
+//! let description = rpm::text::normalize_description(&rpm::text::strip_markdown(
+//!     "# Awesome\n\nAn **awesome** tool, see [the docs](https://example.com).\r\n",
+//! ));
+//! assert_eq!("Awesome\n\nAn awesome tool, see the docs (https://example.com).", description);
+//! assert!(rpm::text::check_summary("An awesome tool").is_ok());
+//! ```
+
+use crate::errors::*;
+
+use super::RPMBuilder;
+
+/// Column descriptions are wrapped at.
+pub const DESCRIPTION_WIDTH: usize = 80;
+
+/// Longest summary the Fedora packaging guidelines accept, in characters.
+pub const SUMMARY_MAX: usize = 70;
+
+/// Check that `summary` is a single line of at most [`SUMMARY_MAX`] characters.
+pub fn check_summary(summary: &str) -> Result<(), RPMError> {
+    let invalid = |reason| {
+        Err(RPMError::InvalidTagValue {
+            tag: "summary",
+            reason,
+        })
+    };
+    if summary.trim().is_empty() {
+        return invalid("is empty");
+    }
+    if summary.contains('\n') {
+        return invalid("has more than one line");
+    }
+    if summary.chars().any(char::is_control) {
+        return invalid("contains control characters");
+    }
+    if summary.chars().count() > SUMMARY_MAX {
+        return invalid("is longer than 70 characters");
+    }
+    Ok(())
+}
+
+/// Drop control characters other than newlines, expand tabs to four spaces, strip
+/// trailing whitespace and collapse runs of empty lines.
+pub fn sanitize(text: &str) -> String {
+    let text = text.replace("\r\n", "\n").replace('\t', "    ");
+    let mut lines = Vec::new();
+    for line in text.split('\n') {
+        let line = line.chars().filter(|c| !c.is_control()).collect::<String>();
+        let line = line.trim_end();
+        if line.is_empty() && lines.last().map_or(true, |last: &String| last.is_empty()) {
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+    while lines.last().map_or(false, String::is_empty) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+/// Remove the markdown markup which reads badly as plain text: headings,
+/// emphasis with `**` and `__`, inline code and links, which keep their text
+/// followed by the target in parentheses. Code blocks are indented instead.
+///
+/// Emphasis and code markers are only removed in pairs enclosing text at word
+/// boundaries, so `snake__case`, `2 ** 8` or a lone backtick are left alone.
+pub fn strip_markdown(text: &str) -> String {
+    let mut lines = Vec::new();
+    let mut in_code = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            lines.push(format!("  {}", line));
+            continue;
+        }
+        // setext heading underlines and rules
+        if trimmed.len() >= 3
+            && (trimmed.chars().all(|c| c == '=') || trimmed.chars().all(|c| c == '-'))
+        {
+            continue;
+        }
+        let line = match trimmed.trim_start_matches('#') {
+            heading if heading.len() < trimmed.len() && heading.starts_with(' ') => {
+                heading.trim_start()
+            }
+            _ => line,
+        };
+        lines.push(strip_inline(line));
+    }
+    lines.join("\n")
+}
+
+fn strip_inline(line: &str) -> String {
+    let line = strip_delimiters(line, "`");
+    let line = strip_delimiters(&line, "**");
+    let line = strip_delimiters(&line, "__");
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line.as_str();
+    while let Some(start) = rest.find('[') {
+        let image = rest[..start].ends_with('!');
+        let link = rest[start + 1..].find("](").and_then(|text_end| {
+            let text_end = start + 1 + text_end;
+            rest[text_end + 2..]
+                .find(')')
+                .map(|target_end| (text_end, text_end + 2 + target_end))
+        });
+        match link {
+            Some((text_end, target_end)) => {
+                let text = &rest[start + 1..text_end];
+                let target = &rest[text_end + 2..target_end];
+                if image {
+                    out.push_str(&rest[..start - 1]);
+                    out.push_str(text);
+                } else {
+                    out.push_str(&rest[..start]);
+                    out.push_str(text);
+                    if !target.is_empty() && target != text {
+                        out.push_str(&format!(" ({})", target));
+                    }
+                }
+                rest = &rest[target_end + 1..];
+            }
+            None => {
+                out.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Remove the pairs of `delimiter` enclosing text, opening at the start of a word
+/// and closing at its end.
+fn strip_delimiters(line: &str, delimiter: &str) -> String {
+    let is_word = |c: Option<char>| c.map_or(false, |c| c.is_alphanumeric() || c == '_');
+    let len = delimiter.len();
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    loop {
+        let open = rest.match_indices(delimiter).map(|(at, _)| at).find(|at| {
+            let after = &rest[at + len..];
+            !is_word(rest[..*at].chars().next_back())
+                && after.chars().next().map_or(false, |c| !c.is_whitespace())
+                && !after.starts_with(delimiter)
+        });
+        let open = match open {
+            Some(open) => open,
+            None => break,
+        };
+        let inner = &rest[open + len..];
+        let close = inner.match_indices(delimiter).map(|(at, _)| at).find(|at| {
+            !inner[..*at].ends_with(char::is_whitespace)
+                && !is_word(inner[at + len..].chars().next())
+        });
+        match close {
+            Some(close) => {
+                out.push_str(&rest[..open]);
+                out.push_str(&inner[..close]);
+                rest = &inner[close + len..];
+            }
+            None => break,
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Rewrap the paragraphs and list items of `text` to `width` columns.
+///
+/// Lines starting with whitespace are kept as they are, words longer than
+/// `width` get a line of their own.
+pub fn wrap(text: &str, width: usize) -> String {
+    let mut out: Vec<String> = Vec::new();
+    // words of the paragraph or list item being filled, and its indent
+    let mut words: Vec<&str> = Vec::new();
+    let mut marker = String::new();
+    let flush = |out: &mut Vec<String>, words: &mut Vec<&str>, marker: &str| {
+        if words.is_empty() {
+            return;
+        }
+        let indent = " ".repeat(marker.chars().count());
+        let mut line = marker.to_string();
+        let mut line_words = 0;
+        for word in words.drain(..) {
+            if line_words > 0 && line.chars().count() + 1 + word.chars().count() > width {
+                out.push(std::mem::replace(&mut line, indent.clone()));
+                line_words = 0;
+            }
+            if line_words > 0 {
+                line.push(' ');
+            }
+            line.push_str(word);
+            line_words += 1;
+        }
+        out.push(line);
+    };
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            flush(&mut out, &mut words, &marker);
+            out.push(String::new());
+        } else if line.starts_with(char::is_whitespace) {
+            flush(&mut out, &mut words, &marker);
+            out.push(line.to_string());
+        } else if let Some(item) = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")) {
+            flush(&mut out, &mut words, &marker);
+            marker = "- ".to_string();
+            words.extend(item.split_whitespace());
+        } else {
+            if words.is_empty() {
+                marker.clear();
+            }
+            words.extend(line.split_whitespace());
+        }
+    }
+    flush(&mut out, &mut words, &marker);
+    out.join("\n")
+}
+
+/// [`sanitize`] and [`wrap`] to [`DESCRIPTION_WIDTH`].
+pub fn normalize_description(text: &str) -> String {
+    wrap(&sanitize(text), DESCRIPTION_WIDTH)
+}
+
+impl RPMBuilder {
+    /// Normalize the description with [`normalize_description`] and reject
+    /// summaries [`check_summary`] does not accept when building.
+    pub fn normalize_text(mut self, normalize: bool) -> Self {
+        self.normalize_text = normalize;
+        self
+    }
+
+    /// Treat the description as markdown and remove its markup with
+    /// [`strip_markdown`] when building, off by default.
+    pub fn markdown_description(mut self, markdown: bool) -> Self {
+        self.markdown_description = markdown;
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn summaries() {
+        assert!(check_summary("A tool").is_ok());
+        assert!(check_summary(&"x".repeat(70)).is_ok());
+        for summary in ["", "two\nlines", "bell\u{7}", &"x".repeat(71)] {
+            assert!(
+                matches!(
+                    check_summary(summary),
+                    Err(RPMError::InvalidTagValue { tag: "summary", .. })
+                ),
+                "{:?}",
+                summary
+            );
+        }
+    }
+
+    #[test]
+    fn wrap_description() {
+        let text = "word ".repeat(30);
+        let wrapped = wrap(&text, 20);
+        assert!(wrapped.lines().all(|line| line.len() <= 20));
+        assert_eq!(30, wrapped.split_whitespace().count());
+
+        let text = "Intro line\ncontinued.\n\n- an item that is long enough to wrap\n* second\n    verbatim   code\n";
+        assert_eq!(
+            "Intro line\ncontinued.\n\n- an item that is\n  long enough to\n  wrap\n- second\n    verbatim   code",
+            wrap(text, 20)
+        );
+        assert_eq!("a\n\nb", sanitize("a\u{1b}\r\n\n\n\nb  \n\n"));
+        assert_eq!(
+            "Title\n\nUse it, logo: alt\n  cargo run",
+            normalize_description(&strip_markdown(
+                "Title\n=====\n\nUse `it`, logo: ![alt](x.png)\n```\ncargo run\n```\n"
+            ))
+        );
+    }
+
+    #[test]
+    fn balanced_markup_only() {
+        assert_eq!(
+            "bold, code and strong",
+            strip_markdown("**bold**, `code` and __strong__")
+        );
+        for text in [
+            "snake__case__name",
+            "2 ** 8 is 256, 2 ** 16 is 65536",
+            "a lone ` backtick",
+            "**unbalanced",
+            "** spaced **",
+            "glob /usr/lib/**/*.so",
+        ] {
+            assert_eq!(text, strip_markdown(text));
+        }
+    }
+
+    #[test]
+    fn builder_normalizes() -> Result<(), Box<dyn std::error::Error>> {
+        let builder = || {
+            RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "## Foo\n\n**Fast**  foo.")
+                .summary("A fast foo")
+                .normalize_text(true)
+        };
+        let pkg = builder().build()?;
+        let header = &pkg.metadata.header;
+        assert_eq!("A fast foo", header.get_summary()?);
+        assert_eq!("## Foo\n\n**Fast** foo.", header.get_description()?);
+        let pkg = builder().markdown_description(true).build()?;
+        assert_eq!("Foo\n\nFast foo.", pkg.metadata.header.get_description()?);
+
+        let multi_line = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "Foo\n\nbar")
+            .normalize_text(true)
+            .build();
+        assert!(matches!(
+            multi_line,
+            Err(RPMError::InvalidTagValue { tag: "summary", .. })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+//! Points in time as rpm stores them, unsigned seconds since 1970 in UTC.
+//!
+//! Tags like `RPMTAG_BUILDTIME`, `RPMTAG_CHANGELOGTIME` and `RPMTAG_FILEMTIMES`
+//! are 32 bit integers which rpm reads as unsigned, so they last until 2106
+//! instead of overflowing in 2038. Nothing before 1970 can be stored.
+//!
+//! ```rust
+//! use std::convert::TryFrom;
+//!
+//! let time = rpm::Timestamp::try_from(1_600_000_000i64)?;
+//! assert_eq!("2020-09-13T12:26:40Z", time.to_string());
+//! assert!(rpm::Timestamp::try_from(-1i64).is_err());
+//! # Ok::<(), rpm::RPMError>(())
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::errors::*;
+
+/// Seconds since 1970-01-01 00:00:00 UTC, see the [module documentation](self).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(u32);
+
+impl Timestamp {
+    /// 1970-01-01 00:00:00 UTC.
+    pub const EPOCH: Timestamp = Timestamp(0);
+
+    /// The last second signed 32 bit time stamps can hold, 2038-01-19 03:14:07 UTC.
+    pub const Y2038: Timestamp = Timestamp(i32::MAX as u32);
+
+    /// The last second rpm can store, 2106-02-07 06:28:15 UTC.
+    pub const MAX: Timestamp = Timestamp(u32::MAX);
+
+    pub const fn new(seconds: u32) -> Self {
+        Timestamp(seconds)
+    }
+
+    /// The current time, rounded down to seconds.
+    pub fn now() -> Result<Self, RPMError> {
+        Self::try_from(SystemTime::now())
+    }
+
+    pub fn seconds(&self) -> u32 {
+        self.0
+    }
+
+    /// Whether tools reading time stamps as signed 32 bit numbers see a date before 1970.
+    pub fn is_after_y2038(&self) -> bool {
+        *self > Self::Y2038
+    }
+
+    /// Read the data of a time stamp tag, which is stored as `i32`.
+    pub(crate) fn from_tag(raw: i32) -> Self {
+        Timestamp(raw as u32)
+    }
+
+    /// The data to store in a time stamp tag, which is `i32`.
+    pub(crate) fn to_tag(self) -> i32 {
+        self.0 as i32
+    }
+}
+
+impl fmt::Display for Timestamp {
+    /// RFC 3339 in UTC, the same regardless of locale and time zone.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time: chrono::DateTime<chrono::Utc> = (*self).into();
+        write!(f, "{}", time.format("%Y-%m-%dT%H:%M:%SZ"))
+    }
+}
+
+impl From<u32> for Timestamp {
+    fn from(seconds: u32) -> Self {
+        Timestamp(seconds)
+    }
+}
+
+impl TryFrom<i64> for Timestamp {
+    type Error = RPMError;
+
+    fn try_from(seconds: i64) -> Result<Self, Self::Error> {
+        if seconds < 0 {
+            return Err(RPMError::InvalidTimestamp {
+                seconds,
+                reason: "before 1970",
+            });
+        }
+        u32::try_from(seconds)
+            .map(Timestamp)
+            .map_err(|_| RPMError::InvalidTimestamp {
+                seconds,
+                reason: "after 2106",
+            })
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    fn from(time: Timestamp) -> Self {
+        UNIX_EPOCH + Duration::from_secs(u64::from(time.0))
+    }
+}
+
+impl TryFrom<SystemTime> for Timestamp {
+    type Error = RPMError;
+
+    /// Rounds down to seconds.
+    fn try_from(time: SystemTime) -> Result<Self, Self::Error> {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => Self::try_from(i64::try_from(since.as_secs()).unwrap_or(i64::MAX)),
+            Err(before) => Err(RPMError::InvalidTimestamp {
+                seconds: -i64::try_from(before.duration().as_secs()).unwrap_or(i64::MAX),
+                reason: "before 1970",
+            }),
+        }
+    }
+}
+
+impl From<Timestamp> for chrono::DateTime<chrono::Utc> {
+    fn from(time: Timestamp) -> Self {
+        SystemTime::from(time).into()
+    }
+}
+
+impl TryFrom<chrono::DateTime<chrono::Utc>> for Timestamp {
+    type Error = RPMError;
+
+    /// Rounds down to seconds.
+    fn try_from(time: chrono::DateTime<chrono::Utc>) -> Result<Self, Self::Error> {
+        Self::try_from(time.timestamp())
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<Timestamp> for time::OffsetDateTime {
+    fn from(time: Timestamp) -> Self {
+        SystemTime::from(time).into()
+    }
+}
+
+#[cfg(feature = "time")]
+impl TryFrom<time::OffsetDateTime> for Timestamp {
+    type Error = RPMError;
+
+    /// Rounds down to seconds, any offset is converted to UTC.
+    fn try_from(time: time::OffsetDateTime) -> Result<Self, Self::Error> {
+        Self::try_from(time.unix_timestamp())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn range() {
+        assert_eq!(Timestamp::EPOCH, Timestamp::try_from(0i64).unwrap());
+        assert_eq!(
+            Timestamp::MAX,
+            Timestamp::try_from(i64::from(u32::MAX)).unwrap()
+        );
+        for (seconds, reason) in [(-1, "before 1970"), (1i64 << 32, "after 2106")] {
+            assert!(matches!(
+                Timestamp::try_from(seconds),
+                Err(RPMError::InvalidTimestamp { reason: r, .. }) if r == reason
+            ));
+        }
+        let before = UNIX_EPOCH - Duration::from_secs(10);
+        assert!(matches!(
+            Timestamp::try_from(before),
+            Err(RPMError::InvalidTimestamp { seconds: -10, .. })
+        ));
+    }
+
+    #[test]
+    fn conversions() -> Result<(), RPMError> {
+        let time = Timestamp::new(1_600_000_000);
+        let system = SystemTime::from(time) + Duration::from_millis(999);
+        assert_eq!(time, Timestamp::try_from(system)?);
+        let utc: chrono::DateTime<chrono::Utc> = time.into();
+        assert_eq!(time, Timestamp::try_from(utc)?);
+        assert_eq!("1970-01-01T00:00:00Z", Timestamp::EPOCH.to_string());
+        assert_eq!("2106-02-07T06:28:15Z", Timestamp::MAX.to_string());
+
+        let late = Timestamp::new(i32::MAX as u32 + 1);
+        assert!(late.is_after_y2038());
+        assert!(!Timestamp::Y2038.is_after_y2038());
+        assert!(late.to_tag() < 0);
+        assert_eq!(late, Timestamp::from_tag(late.to_tag()));
+        assert_eq!("2038-01-19T03:14:08Z", late.to_string());
+        Ok(())
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn time_crate() -> Result<(), RPMError> {
+        let time = Timestamp::new(1_600_000_000);
+        let offset: time::OffsetDateTime = time.into();
+        assert_eq!(1_600_000_000, offset.unix_timestamp());
+        assert_eq!(time, Timestamp::try_from(offset)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+//! Install ordering for a set of packages.
+//!
+//! Packages are ordered such that providers are installed before the
+//! packages requiring them. Like rpm, dependency loops are broken up and
+//! reported, except for loops made up of `Requires(pre)` alone, since those
+//! have no valid order.
+//!
+//! Dependencies are matched by name only, versions are not compared.
+//! Requirements not provided by any package of the set are ignored.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::RPMPackage;
+
+/// Result of [`order`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallOrder {
+    /// Indices into the given packages, in the order they should be installed.
+    pub order: Vec<usize>,
+    /// Dependency loops that had to be broken up, as indices into the given packages.
+    pub cycles: Vec<Vec<usize>>,
+}
+
+#[derive(Clone, Copy)]
+struct Edge {
+    to: usize,
+    pre: bool,
+}
+
+/// Compute an install order from the requires and provides of `packages`.
+pub fn order(packages: &[RPMPackage]) -> Result<InstallOrder, RPMError> {
+    let mut providers: HashMap<String, Vec<usize>> = HashMap::new();
+    for (idx, pkg) in packages.iter().enumerate() {
+        let header = &pkg.metadata.header;
+        providers
+            .entry(header.get_name()?.to_string())
+            .or_default()
+            .push(idx);
+        for provide in header.get_provides()? {
+            providers
+                .entry(provide.name().to_string())
+                .or_default()
+                .push(idx);
+        }
+        if let Ok(paths) = header.get_file_paths() {
+            for path in paths {
+                providers
+                    .entry(path.to_string_lossy().to_string())
+                    .or_default()
+                    .push(idx);
+            }
+        }
+    }
+
+    // edges point from the provider to the package requiring it
+    let mut edges: Vec<Vec<Edge>> = vec![Vec::new(); packages.len()];
+    for (idx, pkg) in packages.iter().enumerate() {
+        for require in pkg.metadata.header.get_requires()? {
+            let pre = require.flags() & (RPMSENSE_PREREQ | RPMSENSE_SCRIPT_PRE) != 0;
+            let mut seen = BTreeSet::new();
+            for &provider in providers.get(require.name()).into_iter().flatten() {
+                if provider != idx && seen.insert(provider) {
+                    edges[provider].push(Edge { to: idx, pre });
+                }
+            }
+        }
+    }
+
+    let components = strongly_connected(&edges);
+    let mut component_of = vec![0; packages.len()];
+    for (c, members) in components.iter().enumerate() {
+        for &member in members {
+            component_of[member] = c;
+        }
+    }
+
+    // order the components, then the members of a component by their pre requirements
+    let component_edges = components
+        .iter()
+        .map(|members| {
+            members
+                .iter()
+                .flat_map(|&member| edges[member].iter())
+                .map(|edge| component_of[edge.to])
+                .collect::<BTreeSet<_>>()
+        })
+        .collect::<Vec<_>>();
+    let component_order = topological(
+        &(0..components.len()).collect::<Vec<_>>(),
+        |c| {
+            component_edges[c]
+                .iter()
+                .copied()
+                .filter(|&to| to != c)
+                .collect()
+        },
+        |c| components[c].iter().min().copied().unwrap_or_default(),
+    )
+    .expect("the component graph is acyclic");
+
+    let mut result = InstallOrder {
+        order: Vec::with_capacity(packages.len()),
+        cycles: Vec::new(),
+    };
+    for c in component_order {
+        let members = &components[c];
+        if members.len() == 1 {
+            result.order.push(members[0]);
+            continue;
+        }
+        let in_component = |idx: usize| component_of[idx] == c;
+        let ordered = topological(
+            members,
+            |member| {
+                edges[member]
+                    .iter()
+                    .filter(|edge| edge.pre && in_component(edge.to))
+                    .map(|edge| edge.to)
+                    .collect()
+            },
+            |member| member,
+        )
+        .ok_or_else(|| RPMError::DependencyCycle {
+            packages: members
+                .iter()
+                .map(|&member| {
+                    packages[member]
+                        .metadata
+                        .header
+                        .get_name()
+                        .unwrap_or("<unknown>")
+                        .to_string()
+                })
+                .collect(),
+        })?;
+        let mut cycle = members.clone();
+        cycle.sort_unstable();
+        result.cycles.push(cycle);
+        result.order.extend(ordered);
+    }
+    Ok(result)
+}
+
+/// Kahn's algorithm, the lowest `key` of all nodes ready to go is picked first
+/// to keep the order stable. Returns `None` if the nodes contain a cycle.
+fn topological<S, K>(nodes: &[usize], successors: S, key: K) -> Option<Vec<usize>>
+where
+    S: Fn(usize) -> Vec<usize>,
+    K: Fn(usize) -> usize,
+{
+    let successors: HashMap<usize, Vec<usize>> =
+        nodes.iter().map(|&node| (node, successors(node))).collect();
+    let mut in_degree: HashMap<usize, usize> = nodes.iter().map(|&node| (node, 0)).collect();
+    for targets in successors.values() {
+        for target in targets {
+            *in_degree.get_mut(target)? += 1;
+        }
+    }
+    let mut ready: BTreeSet<(usize, usize)> = in_degree
+        .iter()
+        .filter(|(_, &degree)| degree == 0)
+        .map(|(&node, _)| (key(node), node))
+        .collect();
+    let mut ordered = Vec::with_capacity(nodes.len());
+    while let Some(&(k, node)) = ready.iter().next() {
+        ready.remove(&(k, node));
+        ordered.push(node);
+        for target in &successors[&node] {
+            let degree = in_degree.get_mut(target)?;
+            *degree -= 1;
+            if *degree == 0 {
+                ready.insert((key(*target), *target));
+            }
+        }
+    }
+    if ordered.len() == nodes.len() {
+        Some(ordered)
+    } else {
+        None
+    }
+}
+
+/// Tarjan's algorithm for the strongly connected components, iterative so long
+/// chains of requirements do not overflow the stack.
+fn strongly_connected(edges: &[Vec<Edge>]) -> Vec<Vec<usize>> {
+    let mut index: Vec<Option<usize>> = vec![None; edges.len()];
+    let mut low = vec![0; edges.len()];
+    let mut on_stack = vec![false; edges.len()];
+    let mut stack = Vec::new();
+    let mut next = 0;
+    let mut components = Vec::new();
+    // the nodes being visited, with the next of their edges to follow
+    let mut visiting: Vec<(usize, usize)> = Vec::new();
+
+    for root in 0..edges.len() {
+        if index[root].is_some() {
+            continue;
+        }
+        visiting.push((root, 0));
+        while let Some(&mut (node, ref mut edge)) = visiting.last_mut() {
+            if *edge == 0 && index[node].is_none() {
+                index[node] = Some(next);
+                low[node] = next;
+                next += 1;
+                stack.push(node);
+                on_stack[node] = true;
+            }
+            if let Some(Edge { to, .. }) = edges[node].get(*edge) {
+                *edge += 1;
+                match index[*to] {
+                    None => visiting.push((*to, 0)),
+                    Some(to_index) if on_stack[*to] => low[node] = low[node].min(to_index),
+                    _ => {}
+                }
+                continue;
+            }
+
+            visiting.pop();
+            if let Some(&(parent, _)) = visiting.last() {
+                low[parent] = low[parent].min(low[node]);
+            }
+            if Some(low[node]) == index[node] {
+                let mut component = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+        }
+    }
+    components
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Dependency, RPMBuilder};
+
+    fn package(name: &str, requires: Vec<Dependency>) -> RPMPackage {
+        let mut builder = RPMBuilder::new(name, "1.0.0", "MIT", "noarch", name);
+        for require in requires {
+            builder = builder.requires(require);
+        }
+        builder.build().unwrap()
+    }
+
+    fn pre(name: &str) -> Dependency {
+        Dependency::new(name.to_string(), RPMSENSE_SCRIPT_PRE, String::new())
+    }
+
+    #[test]
+    fn providers_come_first() -> Result<(), Box<dyn std::error::Error>> {
+        let packages = vec![
+            package("app", vec![Dependency::any("lib")]),
+            package("lib", vec![Dependency::any("base")]),
+            package("base", vec![Dependency::any("not-in-set")]),
+        ];
+        let order = order(&packages)?;
+        assert_eq!(vec![2, 1, 0], order.order);
+        assert!(order.cycles.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn cycles_are_broken_honoring_pre() -> Result<(), Box<dyn std::error::Error>> {
+        let packages = vec![
+            package("a", vec![Dependency::any("b")]),
+            package("b", vec![pre("a")]),
+        ];
+        let order = order(&packages)?;
+        assert_eq!(vec![0, 1], order.order);
+        assert_eq!(vec![vec![0, 1]], order.cycles);
+
+        let packages = vec![package("a", vec![pre("b")]), package("b", vec![pre("a")])];
+        match super::order(&packages) {
+            Err(RPMError::DependencyCycle { packages }) => assert_eq!(2, packages.len()),
+            other => panic!("expected a cycle error, got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn long_chains_do_not_overflow() {
+        let len = 200_000;
+        let mut edges = (1..len)
+            .map(|to| vec![Edge { to, pre: false }])
+            .collect::<Vec<_>>();
+        edges.push(vec![Edge { to: 0, pre: false }]);
+        let components = strongly_connected(&edges);
+        assert_eq!(1, components.len());
+        assert_eq!(len, components[0].len());
+
+        edges.pop();
+        edges.push(Vec::new());
+        assert_eq!(len, strongly_connected(&edges).len());
+    }
+}
@@ -0,0 +1,592 @@
+//! Fingerprints of the keys packages may be signed with, kept in a JSON file.
+//!
+//! Keys are trusted for every origin, like a repository name, unless fingerprints
+//! are pinned for it. Origins with pins accept only those keys.
+//!
+//! ```json
+//! {
+//!   "trusted": [
+//!     "3E2ED16B6E2A2EE1A7FF3F2D9AB46C1E06E05E92"
+//!   ],
+//!   "pins": {
+//!     "fedora": [
+//!       "115DF9AEF857853EE8445D0A0727707EA15B79CC"
+//!     ]
+//!   }
+//! }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::errors::*;
+
+use super::attestation::json_string;
+#[cfg(feature = "signature-pgp")]
+use super::RPMPackage;
+#[cfg(feature = "signature-pgp")]
+use crate::signature;
+#[cfg(feature = "signature-pgp")]
+use crate::VerifyOptions;
+
+/// Trusted key fingerprints, see the [module documentation](self).
+///
+/// Fingerprints are kept as uppercase hex, they may be passed with spaces and in
+/// lowercase like `gpg --fingerprint` prints them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustStore {
+    trusted: BTreeSet<String>,
+    pins: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl TrustStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a store written by [`save`](Self::save).
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, RPMError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Write the store to `path`, replacing the file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), RPMError> {
+        std::fs::write(path, self.to_json())?;
+        Ok(())
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, RPMError> {
+        let invalid = |reason: &str| RPMError::InvalidTrustStore {
+            reason: reason.to_string(),
+        };
+        let mut parser = JsonParser {
+            text: json,
+            position: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.position < json.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        let fingerprints = |value: &Json| -> Result<Vec<String>, RPMError> {
+            match value {
+                Json::Array(items) => items
+                    .iter()
+                    .map(|item| match item {
+                        Json::String(fingerprint) => normalize(fingerprint),
+                        _ => Err(invalid("fingerprints must be strings")),
+                    })
+                    .collect(),
+                _ => Err(invalid("expected an array of fingerprints")),
+            }
+        };
+
+        let mut store = TrustStore::new();
+        let fields = match &value {
+            Json::Object(fields) => fields,
+            _ => return Err(invalid("expected an object")),
+        };
+        for (name, value) in fields {
+            match name.as_str() {
+                "trusted" => store.trusted.extend(fingerprints(value)?),
+                "pins" => match value {
+                    Json::Object(origins) => {
+                        for (origin, value) in origins {
+                            store
+                                .pins
+                                .entry(origin.clone())
+                                .or_default()
+                                .extend(fingerprints(value)?);
+                        }
+                    }
+                    _ => return Err(invalid("pins must map origins to fingerprints")),
+                },
+                // ignored, for comments and tools adding their own fields
+                _ => {}
+            }
+        }
+        Ok(store)
+    }
+
+    /// The store as JSON, fingerprints and origins are sorted.
+    pub fn to_json(&self) -> String {
+        let list = |fingerprints: &BTreeSet<String>, indent: &str| {
+            if fingerprints.is_empty() {
+                return "[]".to_string();
+            }
+            let items = fingerprints
+                .iter()
+                .map(|fingerprint| format!("{}  {}", indent, json_string(fingerprint)))
+                .collect::<Vec<_>>();
+            format!("[\n{}\n{}]", items.join(",\n"), indent)
+        };
+        let pins = if self.pins.is_empty() {
+            "{}".to_string()
+        } else {
+            let origins = self
+                .pins
+                .iter()
+                .map(|(origin, fingerprints)| {
+                    format!(
+                        "    {}: {}",
+                        json_string(origin),
+                        list(fingerprints, "    ")
+                    )
+                })
+                .collect::<Vec<_>>();
+            format!("{{\n{}\n  }}", origins.join(",\n"))
+        };
+        format!(
+            "{{\n  \"trusted\": {},\n  \"pins\": {}\n}}\n",
+            list(&self.trusted, "  "),
+            pins
+        )
+    }
+
+    /// Trust the key with `fingerprint` for origins without pins.
+    pub fn trust(&mut self, fingerprint: &str) -> Result<(), RPMError> {
+        self.trusted.insert(normalize(fingerprint)?);
+        Ok(())
+    }
+
+    /// Stop trusting the key with `fingerprint`, for all origins including pins.
+    pub fn distrust(&mut self, fingerprint: &str) -> Result<(), RPMError> {
+        let fingerprint = normalize(fingerprint)?;
+        self.trusted.remove(&fingerprint);
+        for pinned in self.pins.values_mut() {
+            pinned.remove(&fingerprint);
+        }
+        self.pins.retain(|_, pinned| !pinned.is_empty());
+        Ok(())
+    }
+
+    /// Accept only keys pinned for `origin` when verifying packages from there,
+    /// whether they are trusted or not.
+    pub fn pin(&mut self, origin: &str, fingerprint: &str) -> Result<(), RPMError> {
+        let fingerprint = normalize(fingerprint)?;
+        self.pins
+            .entry(origin.to_string())
+            .or_default()
+            .insert(fingerprint);
+        Ok(())
+    }
+
+    /// Remove the pins of `origin`, the trusted keys apply to it again.
+    pub fn unpin(&mut self, origin: &str) {
+        self.pins.remove(origin);
+    }
+
+    /// Whether packages from `origin` may be signed by the key with `fingerprint`.
+    ///
+    /// Malformed fingerprints are never trusted.
+    pub fn is_trusted(&self, origin: &str, fingerprint: &str) -> bool {
+        let fingerprint = match normalize(fingerprint) {
+            Ok(fingerprint) => fingerprint,
+            Err(_) => return false,
+        };
+        match self.pins.get(origin) {
+            Some(pinned) => pinned.contains(&fingerprint),
+            None => self.trusted.contains(&fingerprint),
+        }
+    }
+
+    /// The fingerprints trusted for origins without pins.
+    pub fn trusted(&self) -> impl Iterator<Item = &str> {
+        self.trusted.iter().map(String::as_str)
+    }
+
+    /// The fingerprints pinned for `origin`, if any.
+    pub fn pins(&self, origin: &str) -> impl Iterator<Item = &str> {
+        self.pins
+            .get(origin)
+            .into_iter()
+            .flat_map(|pinned| pinned.iter().map(String::as_str))
+    }
+}
+
+#[cfg(feature = "signature-pgp")]
+impl RPMPackage {
+    /// Verify the signature with the keys of `keyring` the store trusts for `origin`,
+    /// returning the fingerprint of the key that verified it.
+    ///
+    /// Fails with [`RPMError::UntrustedKey`] if none of the keys is trusted, and
+    /// with the error of the last trusted key if none of them verified the package.
+    pub fn verify_signature_trusted(
+        &self,
+        keyring: &[signature::pgp::Verifier],
+        store: &TrustStore,
+        origin: &str,
+    ) -> Result<String, RPMError> {
+        let options = VerifyOptions::new().trust(store, origin);
+        let mut result = Err(RPMError::UntrustedKey {
+            origin: origin.to_string(),
+        });
+        for verifier in keyring {
+            match self.verify_signature_with(verifier, &options) {
+                Ok(()) => return Ok(verifier.fingerprint()),
+                Err(RPMError::UntrustedKey { .. }) => {}
+                Err(err) => result = Err(err),
+            }
+        }
+        result
+    }
+}
+
+/// Uppercase hex without spaces, v4 fingerprints have 40 digits and v5 ones 64.
+fn normalize(fingerprint: &str) -> Result<String, RPMError> {
+    let normalized = fingerprint
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    if (normalized.len() == 40 || normalized.len() == 64)
+        && normalized.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        Ok(normalized)
+    } else {
+        Err(RPMError::InvalidFingerprint {
+            fingerprint: fingerprint.to_string(),
+        })
+    }
+}
+
+/// The subset of JSON a store consists of.
+enum Json {
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+    /// A number, boolean or null, which stores only have in ignored fields.
+    Scalar,
+}
+
+/// Arrays and objects nested deeper are refused, stores nest two levels.
+const MAX_DEPTH: usize = 16;
+
+struct JsonParser<'a> {
+    text: &'a str,
+    position: usize,
+    /// Arrays and objects the current position is in.
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, reason: &str) -> RPMError {
+        RPMError::InvalidTrustStore {
+            reason: format!("{} at byte {}", reason, self.position),
+        }
+    }
+
+    fn whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.whitespace();
+        self.text[self.position..].chars().next()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), RPMError> {
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected '{}'", expected)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, RPMError> {
+        if matches!(self.peek(), Some('[') | Some('{')) {
+            if self.depth == MAX_DEPTH {
+                return Err(self.error("nested too deeply"));
+            }
+            self.depth += 1;
+            let value = self.collection();
+            self.depth -= 1;
+            return value;
+        }
+        match self.peek() {
+            Some('"') => self.string().map(Json::String),
+            Some('-') | Some('0'..='9') => self.number(),
+            Some(_) => self.literal(),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    /// `true`, `false` or `null` at the current position.
+    fn literal(&mut self) -> Result<Json, RPMError> {
+        let rest = &self.text[self.position..];
+        match ["true", "false", "null"]
+            .iter()
+            .find(|literal| rest.starts_with(*literal))
+        {
+            Some(literal) => {
+                self.position += literal.len();
+                Ok(Json::Scalar)
+            }
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    /// A number at the current position, which is a minus or a digit.
+    fn number(&mut self) -> Result<Json, RPMError> {
+        let bytes = self.text.as_bytes();
+        let digits = |position: &mut usize| {
+            let start = *position;
+            while bytes.get(*position).map_or(false, u8::is_ascii_digit) {
+                *position += 1;
+            }
+            *position - start
+        };
+        let mut end = self.position;
+        if bytes[end] == b'-' {
+            end += 1;
+        }
+        let integer = digits(&mut end);
+        if integer == 0 || (integer > 1 && bytes[end - integer] == b'0') {
+            return Err(self.error("invalid number"));
+        }
+        if bytes.get(end) == Some(&b'.') {
+            end += 1;
+            if digits(&mut end) == 0 {
+                return Err(self.error("invalid number"));
+            }
+        }
+        if matches!(bytes.get(end), Some(b'e') | Some(b'E')) {
+            end += 1;
+            if matches!(bytes.get(end), Some(b'+') | Some(b'-')) {
+                end += 1;
+            }
+            if digits(&mut end) == 0 {
+                return Err(self.error("invalid number"));
+            }
+        }
+        self.position = end;
+        Ok(Json::Scalar)
+    }
+
+    /// An array starting at the current position, which is '[', or else an object.
+    fn collection(&mut self) -> Result<Json, RPMError> {
+        match self.peek() {
+            Some('[') => {
+                self.position += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(']') {
+                    self.position += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    match self.peek() {
+                        Some(',') => self.position += 1,
+                        _ => break,
+                    }
+                }
+                self.expect(']')?;
+                Ok(Json::Array(items))
+            }
+            _ => {
+                self.position += 1;
+                let mut fields = Vec::new();
+                if self.peek() == Some('}') {
+                    self.position += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    if self.peek() != Some('"') {
+                        return Err(self.error("expected a field name"));
+                    }
+                    let name = self.string()?;
+                    self.expect(':')?;
+                    fields.push((name, self.value()?));
+                    match self.peek() {
+                        Some(',') => self.position += 1,
+                        _ => break,
+                    }
+                }
+                self.expect('}')?;
+                Ok(Json::Object(fields))
+            }
+        }
+    }
+
+    /// A string starting at the current position, which is a quote.
+    fn string(&mut self) -> Result<String, RPMError> {
+        self.position += 1;
+        let text = self.text;
+        let mut string = String::new();
+        let mut chars = text[self.position..].char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.position += offset + 1;
+                    return Ok(string);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let code = match unicode_escape(&mut chars) {
+                                // characters beyond the basic plane are escaped as
+                                // a high surrogate followed by a low one
+                                Some(high @ 0xd800..=0xdbff) => {
+                                    let backslash = chars.next().map(|(_, c)| c);
+                                    let u = chars.next().map(|(_, c)| c);
+                                    match (backslash, u, unicode_escape(&mut chars)) {
+                                        (Some('\\'), Some('u'), Some(low @ 0xdc00..=0xdfff)) => {
+                                            Some(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+                                        }
+                                        _ => None,
+                                    }
+                                }
+                                code => code,
+                            };
+                            code.and_then(char::from_u32)
+                                .ok_or_else(|| self.error("invalid unicode escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    string.push(escaped);
+                }
+                c => string.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+}
+
+/// The code unit of the four hex digits following `\u`.
+fn unicode_escape(chars: &mut std::str::CharIndices) -> Option<u32> {
+    let digits = (0..4)
+        .filter_map(|_| chars.next().map(|(_, c)| c))
+        .collect::<String>();
+    u32::from_str_radix(&digits, 16).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: &str = "3E2E D16B 6E2A 2EE1 A7FF  3F2D 9AB4 6C1E 06E0 5E92";
+    const OTHER: &str = "115df9aef857853ee8445d0a0727707ea15b79cc";
+
+    #[test]
+    fn pins() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = TrustStore::new();
+        store.trust(KEY)?;
+        store.pin("fedora", OTHER)?;
+        assert!(store.is_trusted("internal", KEY));
+        assert!(!store.is_trusted("internal", OTHER));
+        assert!(store.is_trusted("fedora", &OTHER.to_uppercase()));
+        assert!(!store.is_trusted("fedora", KEY));
+        assert!(!store.is_trusted("internal", "not a fingerprint"));
+        assert!(matches!(
+            store.trust("ABCD"),
+            Err(RPMError::InvalidFingerprint { .. })
+        ));
+
+        store.distrust(OTHER)?;
+        assert_eq!(0, store.pins("fedora").count());
+        assert!(store.is_trusted("fedora", KEY));
+        Ok(())
+    }
+
+    #[test]
+    fn json_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            TrustStore::new(),
+            TrustStore::from_json(&TrustStore::new().to_json())?
+        );
+
+        let mut store = TrustStore::new();
+        store.trust(KEY)?;
+        store.pin("fedora", OTHER)?;
+        store.pin("epel \"9\"", KEY)?;
+        let json = store.to_json();
+        assert!(json.contains("\"3E2ED16B6E2A2EE1A7FF3F2D9AB46C1E06E05E92\""));
+        assert_eq!(store, TrustStore::from_json(&json)?);
+
+        let dir = crate::tests::TestDir::new("trust");
+        let path = dir.join("trust.json");
+        store.save(&path)?;
+        assert_eq!(store, TrustStore::load(&path)?);
+
+        let parsed = TrustStore::from_json(
+            r#"{"comment": "ignored", "pins": {"fedora": ["115DF9AEF857853EE8445D0A0727707EA15B79CC"]}}"#,
+        )?;
+        assert!(parsed.is_trusted("fedora", OTHER));
+        let parsed = TrustStore::from_json(
+            r#"{"version": 2, "size": -1.5e3, "enabled": true, "old": false, "note": null,
+                "pins": {"\ud83d\udd11 keys": ["115DF9AEF857853EE8445D0A0727707EA15B79CC"]}}"#,
+        )?;
+        assert!(parsed.is_trusted("\u{1f511} keys", OTHER));
+        let nested = format!(
+            "{{\"comment\": {}{}}}",
+            "[".repeat(100_000),
+            "]".repeat(100_000)
+        );
+        assert!(matches!(
+            TrustStore::from_json(&nested),
+            Err(RPMError::InvalidTrustStore { reason }) if reason.starts_with("nested too deeply")
+        ));
+        for invalid in [
+            "",
+            "[]",
+            r#"{"trusted": "ABCD"}"#,
+            r#"{"trusted": [1]}"#,
+            r#"{"trusted": []"#,
+            r#"{"trusted": []} x"#,
+            r#"{"pins": {"a": ["ABCD"]}}"#,
+            r#"{"version": 01}"#,
+            r#"{"version": 1.}"#,
+            r#"{"version": -}"#,
+            r#"{"enabled": tru}"#,
+            r#"{"comment": "\ud83d"}"#,
+            r#"{"comment": "\ud83d\u0041"}"#,
+        ] {
+            assert!(TrustStore::from_json(invalid).is_err(), "{}", invalid);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "signature-pgp")]
+    #[test]
+    fn verify_trusted() -> Result<(), Box<dyn std::error::Error>> {
+        let (signing_key, verification_key) = crate::signature::pgp::test::load_asc_keys();
+        let signer = signature::pgp::Signer::load_from_asc_bytes(&signing_key)?;
+        let verifier = signature::pgp::Verifier::load_from_asc_bytes(&verification_key)?;
+        let pkg = crate::RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "foo")
+            .build_and_sign(&signer)?;
+        let keyring = [verifier.clone()];
+
+        let mut store = TrustStore::new();
+        assert!(matches!(
+            pkg.verify_signature_trusted(&keyring, &store, "internal"),
+            Err(RPMError::UntrustedKey { origin }) if origin == "internal"
+        ));
+        store.trust(&verifier.fingerprint())?;
+        assert_eq!(
+            verifier.fingerprint(),
+            pkg.verify_signature_trusted(&keyring, &store, "internal")?
+        );
+        store.pin("fedora", OTHER)?;
+        assert!(pkg
+            .verify_signature_trusted(&keyring, &store, "fedora")
+            .is_err());
+
+        let options = VerifyOptions::new().trust(&store, "internal");
+        pkg.verify_signature_with(&verifier, &options)?;
+        assert!(matches!(
+            pkg.verify_signature_with(&verifier, &VerifyOptions::new().trust(&store, "fedora")),
+            Err(RPMError::UntrustedKey { .. })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+//! Building and signing in two steps, for release processes where the machine
+//! building a package does not hold the signing key.
+//!
+//! [`RPMBuilder::build_unsigned`] compresses the payload and computes the digests
+//! once. The [`UnsignedPackage`] is written as an rpm carrying only those digests,
+//! shipped to wherever the key lives, and [`finalize`](UnsignedPackage::finalize)d
+//! there without compressing the payload again.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let signing_key = std::fs::read("./test_assets/secret_key.asc")?;
+//! let unsigned = rpm::RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo")
+//!     .with_file(
+//!         "./test_assets/awesome.toml",
+//!         rpm::RPMFileOptions::new("/etc/awesome/config.toml"),
+//!     )?
+//!     .build_unsigned()?;
+//! unsigned.write(&mut std::fs::File::create("./foo.unsigned.rpm")?)?;
+//!
+//! // in the signing enclave
+//! let mut input = std::io::BufReader::new(std::fs::File::open("./foo.unsigned.rpm")?);
+//! let signer = rpm::signature::pgp::Signer::load_from_asc_bytes(&signing_key)?;
+//! let pkg = rpm::UnsignedPackage::parse(&mut input)?.finalize(&signer)?;
+//! pkg.write(&mut std::fs::File::create("./foo-1.0.0-1.noarch.rpm")?)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::constants::*;
+use crate::errors::*;
+use crate::sequential_cursor::SeqCursor;
+use crate::signature;
+
+use super::diagnostics::Diagnostics;
+use super::headers::*;
+use super::package::verify_payload_digest;
+use super::{RPMBuilder, RPMPackage, RPMPackageMetadata};
+
+/// A built package with its digests but without signatures, see the
+/// [module documentation](self).
+#[derive(Debug)]
+pub struct UnsignedPackage {
+    /// With a signature header holding only the digests.
+    metadata: RPMPackageMetadata,
+    header_bytes: Vec<u8>,
+    content: Vec<u8>,
+    digest: HeaderDigest,
+    md5: Vec<u8>,
+    strict: bool,
+    fips: bool,
+    tag_log: Option<PathBuf>,
+}
+
+impl UnsignedPackage {
+    /// Read a package written by [`write`](Self::write).
+    ///
+    /// The digests of the signature header must match the header and payload, and
+    /// the payload the payload digest of the header, so nothing replaced on the way
+    /// is signed. Strict and FIPS checks of the builder do not carry over, see
+    /// [`fips`](Self::fips).
+    pub fn parse<T: std::io::BufRead>(input: &mut T) -> Result<Self, RPMError> {
+        let metadata = RPMPackageMetadata::parse(input)?;
+        let mut content = Vec::new();
+        input.read_to_end(&mut content)?;
+        let mut header_bytes = Vec::with_capacity(metadata.header.serialized_len());
+        metadata.header.write(&mut header_bytes)?;
+
+        let signature = &metadata.signature;
+        let digest = HeaderDigest {
+            sha1: signature
+                .get_entry_string_data(IndexSignatureTag::RPMSIGTAG_SHA1)?
+                .to_string(),
+            sha256: signature
+                .get_entry_string_data(IndexSignatureTag::RPMSIGTAG_SHA256)?
+                .to_string(),
+        };
+        let md5 = signature
+            .get_entry_binary_data(IndexSignatureTag::RPMSIGTAG_MD5)?
+            .to_vec();
+        let (actual_digest, actual_md5) = RPMBuilder::derive_hashes(&header_bytes, &content)?;
+        if digest != actual_digest {
+            return Err(RPMError::DigestMismatch(format!(
+                "{} of the header",
+                IndexSignatureTag::RPMSIGTAG_SHA256
+            )));
+        }
+        if md5 != actual_md5 {
+            return Err(RPMError::DigestMismatch(format!(
+                "{} of header and payload",
+                IndexSignatureTag::RPMSIGTAG_MD5
+            )));
+        }
+        if !verify_payload_digest(&metadata.header, &content)? {
+            return Err(RPMError::DigestMismatch(format!(
+                "the header has no SHA-256 {}",
+                IndexTag::RPMTAG_PAYLOADDIGEST
+            )));
+        }
+        Ok(UnsignedPackage {
+            metadata,
+            header_bytes,
+            content,
+            digest,
+            md5,
+            strict: false,
+            fips: false,
+            tag_log: None,
+        })
+    }
+
+    /// Write the package with the digests but without signatures.
+    pub fn write<W: Write>(&self, out: &mut W) -> Result<(), RPMError> {
+        self.metadata.lead.write(out)?;
+        self.metadata.signature.write_signature(out)?;
+        out.write_all(&self.header_bytes)?;
+        out.write_all(&self.content)?;
+        Ok(())
+    }
+
+    /// Check the package and signatures like [`RPMBuilder::fips`] does when
+    /// [`finalize`](Self::finalize)d, e.g. for packages not built with fez.
+    pub fn fips(mut self, fips: bool) -> Self {
+        self.fips = fips;
+        self
+    }
+
+    pub fn header(&self) -> &Header<IndexTag> {
+        &self.metadata.header
+    }
+
+    /// Digests over the header, as they end up in the signature header.
+    pub fn digest(&self) -> &HeaderDigest {
+        &self.digest
+    }
+
+    /// Sign the header, and header and payload, with `signer`.
+    pub fn finalize<S>(self, signer: S) -> Result<RPMPackage, RPMError>
+    where
+        S: signature::Signing<signature::algorithm::RSA>,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("sign").entered();
+        let rsa_sig_header_only = signer.sign(self.header_bytes.as_slice())?;
+        let cursor = SeqCursor::new(&[self.header_bytes.as_slice(), self.content.as_slice()]);
+        let rsa_sig_header_and_archive = signer.sign(cursor)?;
+
+        let signature = self
+            .signature_builder()
+            .add_signature(
+                rsa_sig_header_only.as_ref(),
+                rsa_sig_header_and_archive.as_ref(),
+            )
+            .build(self.size());
+        let metadata = RPMPackageMetadata {
+            signature,
+            ..self.metadata
+        };
+        RPMBuilder::check_layout(self.strict, &metadata)?;
+        if self.fips {
+            RPMBuilder::check_fips(&metadata)?;
+        }
+        RPMBuilder::write_tag_log(self.tag_log, &metadata)?;
+        Ok(RPMPackage::new(metadata, self.content))
+    }
+
+    fn signature_builder(&self) -> SignatureHeaderBuilder<WithDigest> {
+        Header::<IndexSignatureTag>::builder()
+            .add_digest(self.digest.sha1.as_str(), self.md5.as_slice())
+            .add_sha256_digest(self.digest.sha256.as_str())
+    }
+
+    fn size(&self) -> i32 {
+        (self.header_bytes.len() + self.content.len()) as i32
+    }
+}
+
+impl RPMBuilder {
+    /// Build and compute the digests, leaving the signatures to
+    /// [`UnsignedPackage::finalize`], see [`build_and_sign`](Self::build_and_sign).
+    pub fn build_unsigned(self) -> Result<UnsignedPackage, RPMError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("build", package = %self.nvra()).entered();
+        let strict = self.strict;
+        let fips = self.fips;
+        let tag_log = self.tag_log.clone();
+        let (lead, header, content) = self.prepare_data(&mut Diagnostics::new(), true)?;
+
+        let mut header_bytes = Vec::with_capacity(header.serialized_len());
+        header.write(&mut header_bytes)?;
+        let (digest, md5) = Self::derive_hashes(&header_bytes, &content)?;
+        let signature = Header::<IndexSignatureTag>::builder()
+            .add_digest(digest.sha1.as_str(), md5.as_slice())
+            .add_sha256_digest(digest.sha256.as_str())
+            .build((header_bytes.len() + content.len()) as i32);
+        Ok(UnsignedPackage {
+            metadata: RPMPackageMetadata {
+                lead,
+                signature,
+                header,
+            },
+            header_bytes,
+            content,
+            digest,
+            md5,
+            strict,
+            fips,
+            tag_log,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "signature-pgp"))]
+mod test {
+    use super::*;
+    use crate::RPMFileOptions;
+
+    #[test]
+    fn finalize() -> Result<(), Box<dyn std::error::Error>> {
+        let (signing_key, verification_key) = crate::signature::pgp::test::load_asc_keys();
+        let signer = signature::pgp::Signer::load_from_asc_bytes(signing_key.as_ref())?;
+        let verifier = || signature::pgp::Verifier::load_from_asc_bytes(verification_key.as_ref());
+        let builder = || -> Result<RPMBuilder, RPMError> {
+            let mut builder = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo");
+            builder.add_data(
+                b"[foo]\n".to_vec(),
+                crate::Timestamp::EPOCH,
+                RPMFileOptions::new("/etc/foo/foo.toml").into(),
+            )?;
+            Ok(builder)
+        };
+
+        let unsigned = builder()?.build_unsigned()?;
+        let mut shipped = Vec::new();
+        unsigned.write(&mut shipped)?;
+        let plain = RPMPackage::parse(&mut shipped.as_slice())?;
+        plain.verify_digests()?;
+        assert!(plain.verify_signature(verifier()?).is_err());
+
+        let parsed = UnsignedPackage::parse(&mut shipped.as_slice())?;
+        assert_eq!(unsigned.digest(), parsed.digest());
+        assert_eq!("foo", parsed.header().get_name()?);
+        let pkg = parsed.finalize(&signer)?;
+        pkg.verify_digests()?;
+        pkg.verify_signature(verifier()?)?;
+        assert_eq!(unsigned.content, pkg.content);
+        let mut signed = Vec::new();
+        pkg.write(&mut signed)?;
+        RPMPackage::parse(&mut signed.as_slice())?.verify_signature(verifier()?)?;
+
+        // nor a payload replaced on the way
+        let mut bytes = Vec::new();
+        plain.metadata.write(&mut bytes)?;
+        bytes.extend_from_slice(
+            &RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "A foo")
+                .build()?
+                .content,
+        );
+        assert!(matches!(
+            UnsignedPackage::parse(&mut bytes.as_slice()),
+            Err(RPMError::DigestMismatch(_))
+        ));
+
+        // the header no longer matches the digests
+        let mut tampered = plain.metadata;
+        tampered.header = builder()?.release("2").build()?.metadata.header;
+        let mut bytes = Vec::new();
+        tampered.write(&mut bytes)?;
+        bytes.extend_from_slice(&plain.content);
+        assert!(matches!(
+            UnsignedPackage::parse(&mut bytes.as_slice()),
+            Err(RPMError::DigestMismatch(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+//! Creating the system users and groups a package relies on.
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::{Dependency, FileMode, RPMBuilder, RPMFileOptions, Timestamp};
+
+/// Directory systemd-sysusers reads the declarations shipped by packages from.
+pub const SYSUSERS_DIR: &str = "/usr/lib/sysusers.d";
+
+/// User id of a user added with [`RPMBuilder::with_system_user`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Uid {
+    /// Any free id from the system range, picked on install.
+    Dynamic,
+    /// This id for both user and group.
+    Fixed(u32),
+}
+
+/// How [`RPMBuilder::with_system_user`] creates users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UserCreation {
+    /// A `%pre` scriptlet calling `useradd` and `groupadd` unless `getent` finds them,
+    /// works on every distribution.
+    #[default]
+    Useradd,
+    /// Ship a `sysusers.d` declaration, applied by the systemd file trigger and
+    /// additionally by `systemd-sysusers` in `%pre`, such that the user exists
+    /// before files owned by it are unpacked.
+    Sysusers,
+}
+
+impl RPMBuilder {
+    /// Select how users added afterwards with [`with_system_user`](Self::with_system_user) are created.
+    pub fn user_creation(mut self, policy: UserCreation) -> Self {
+        self.user_creation = policy;
+        self
+    }
+
+    /// Create system user and group `name` on install, unless they exist already.
+    ///
+    /// The user has no login shell and `/` as home directory.
+    /// Users are never removed, as files owned by them may outlive the package.
+    pub fn with_system_user(mut self, name: &str, uid: Uid) -> Result<Self, RPMError> {
+        if name.is_empty()
+            || name.starts_with('-')
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c))
+        {
+            return Err(RPMError::InvalidOwner {
+                path: SYSUSERS_DIR.to_string(),
+                owner: name.to_string(),
+                reason: "system user names may only contain ascii letters, digits, _, - and .",
+            });
+        }
+        match self.user_creation {
+            UserCreation::Useradd => {
+                self = self.append_script(IndexTag::RPMTAG_PREIN, &useradd(name, uid));
+                for tool in ["/usr/bin/getent", "/usr/sbin/groupadd", "/usr/sbin/useradd"].iter() {
+                    self = self.requires(Dependency::new(
+                        tool.to_string(),
+                        RPMSENSE_SCRIPT_PRE,
+                        String::new(),
+                    ));
+                }
+            }
+            UserCreation::Sysusers => {
+                let path = format!("{}/{}.conf", SYSUSERS_DIR, name);
+                let declaration = sysusers(name, uid);
+                self = self.append_script(
+                    IndexTag::RPMTAG_PREIN,
+                    &format!(
+                        "systemd-sysusers --replace={} - <<'SYSUSERS'\n{}SYSUSERS\n",
+                        path, declaration
+                    ),
+                );
+                // generated files carry no modification time
+                self.add_data(
+                    declaration.into_bytes(),
+                    Timestamp::EPOCH,
+                    RPMFileOptions::new(path)
+                        .mode(FileMode::regular(0o644))
+                        .into(),
+                )?;
+                self = self
+                    .requires(Dependency::new(
+                        "/usr/bin/systemd-sysusers".to_string(),
+                        RPMSENSE_SCRIPT_PRE,
+                        String::new(),
+                    ))
+                    .provides(Dependency::any(format!("user({})", name)))
+                    .provides(Dependency::any(format!("group({})", name)));
+            }
+        }
+        Ok(self)
+    }
+}
+
+fn sysusers(name: &str, uid: Uid) -> String {
+    let id = match uid {
+        Uid::Dynamic => "-".to_string(),
+        Uid::Fixed(id) => id.to_string(),
+    };
+    format!(
+        "u {name} {id} \"{name} daemon\" / -\n",
+        name = name,
+        id = id
+    )
+}
+
+fn useradd(name: &str, uid: Uid) -> String {
+    let (gid_arg, uid_arg) = match uid {
+        Uid::Dynamic => (String::new(), String::new()),
+        Uid::Fixed(id) => (format!(" -g {}", id), format!(" -u {}", id)),
+    };
+    format!(
+        r#"getent group {name} >/dev/null || groupadd -r{gid_arg} {name}
+getent passwd {name} >/dev/null || useradd -r{uid_arg} -g {name} -d / -s /sbin/nologin -c "{name} daemon" {name}
+"#,
+        name = name,
+        gid_arg = gid_arg,
+        uid_arg = uid_arg
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn builder() -> RPMBuilder {
+        RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "an awesome daemon")
+    }
+
+    #[test]
+    fn useradd_scriptlet() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = builder()
+            .with_system_user("awesome", Uid::Fixed(812))?
+            .build()?;
+        let pre = pkg
+            .metadata
+            .header
+            .get_entry_string_data(IndexTag::RPMTAG_PREIN)?;
+        assert!(pre.contains("groupadd -r -g 812 awesome"));
+        assert!(pre.contains("useradd -r -u 812 -g awesome"));
+        assert!(builder()
+            .with_system_user("awe some", Uid::Dynamic)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sysusers_declaration() -> Result<(), Box<dyn std::error::Error>> {
+        let pkg = builder()
+            .user_creation(UserCreation::Sysusers)
+            .with_system_user("awesome", Uid::Dynamic)?
+            .build()?;
+        let header = &pkg.metadata.header;
+        assert_eq!(
+            vec![std::path::PathBuf::from("/usr/lib/sysusers.d/awesome.conf")],
+            header.get_file_paths()?
+        );
+        let entries = pkg.payload_entries()?;
+        assert_eq!(
+            "u awesome - \"awesome daemon\" / -\n",
+            String::from_utf8(entries[0].content.clone())?
+        );
+        assert!(header
+            .get_entry_string_data(IndexTag::RPMTAG_PREIN)?
+            .starts_with("systemd-sysusers --replace=/usr/lib/sysusers.d/awesome.conf -"));
+        assert!(header
+            .get_provides()?
+            .iter()
+            .any(|dep| dep.name() == "user(awesome)"));
+        Ok(())
+    }
+}
@@ -0,0 +1,428 @@
+//! Comparing the files below a root directory with the package they came from, like `rpm -V`.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::constants::*;
+use crate::errors::*;
+
+use super::accounts::Accounts;
+use super::headers::*;
+use super::install::location_in_root;
+use super::RPMPackage;
+
+/// The attributes in the order and with the letters `rpm -V` prints them.
+const ATTRIBUTES: [(u32, char); 9] = [
+    (RPMVERIFY_FILESIZE, 'S'),
+    (RPMVERIFY_MODE, 'M'),
+    (RPMVERIFY_FILEDIGEST, '5'),
+    (RPMVERIFY_RDEV, 'D'),
+    (RPMVERIFY_LINKTO, 'L'),
+    (RPMVERIFY_USER, 'U'),
+    (RPMVERIFY_GROUP, 'G'),
+    (RPMVERIFY_MTIME, 'T'),
+    (RPMVERIFY_CAPS, 'P'),
+];
+
+const FILE_TYPE_MASK: u32 = 0o170000;
+const DIR_FILE_TYPE: u32 = 0o040000;
+const SYMLINK_FILE_TYPE: u32 = 0o120000;
+const CHAR_DEVICE_TYPE: u32 = 0o020000;
+const BLOCK_DEVICE_TYPE: u32 = 0o060000;
+
+/// Controls the behaviour of [`Header::verify_root`].
+#[derive(Debug, Clone, Default)]
+pub struct FileVerifyOptions {
+    omit: u32,
+}
+
+impl FileVerifyOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Skip the `RPMVERIFY_*` attributes in `mask` for all files,
+    /// e.g. [`RPMVERIFY_MTIME`] like `rpm -V --nomtime`.
+    pub fn omit(mut self, mask: u32) -> Self {
+        self.omit |= mask;
+        self
+    }
+}
+
+/// The outcome of verifying a single file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileVerification {
+    /// Path as recorded in the package.
+    pub path: PathBuf,
+    /// Location of the file below the root.
+    pub location: PathBuf,
+    /// `RPMFILE_*` flags of the file.
+    pub flags: i32,
+    /// The file does not exist, no attribute was checked.
+    pub missing: bool,
+    /// `RPMVERIFY_*` bits of the attributes differing from the package.
+    pub failed: u32,
+    /// `RPMVERIFY_*` bits of the attributes which could not be checked,
+    /// e.g. digests of unreadable files.
+    pub unknown: u32,
+}
+
+impl FileVerification {
+    /// Whether the file matches the package, missing `%ghost` and
+    /// `%config(missingok)` files are fine.
+    pub fn is_ok(&self) -> bool {
+        if self.missing {
+            self.flags & (RPMFILE_MISSINGOK | RPMFILE_GHOST) != 0
+        } else {
+            self.failed == 0
+        }
+    }
+
+    /// The attributes as `rpm -V` prints them, e.g. `S.5....T.` for a modified file.
+    ///
+    /// Differing attributes are shown by their letter, those which could not be
+    /// checked by `?` and everything else by `.`. Missing files have no attributes.
+    pub fn attributes(&self) -> String {
+        if self.missing {
+            return String::new();
+        }
+        ATTRIBUTES
+            .iter()
+            .map(|(bit, letter)| {
+                if self.unknown & bit != 0 {
+                    '?'
+                } else if self.failed & bit != 0 {
+                    *letter
+                } else {
+                    '.'
+                }
+            })
+            .collect()
+    }
+
+    /// The marker for the kind of file `rpm -V` prints in front of the path.
+    fn marker(&self) -> char {
+        [
+            (RPMFILE_CONFIG, 'c'),
+            (RPMFILE_DOC, 'd'),
+            (RPMFILE_GHOST, 'g'),
+            (RPMFILE_LICENSE, 'l'),
+            (RPMFILE_PUBKEY, 'P'),
+            (RPMFILE_README, 'r'),
+        ]
+        .iter()
+        .find(|(flag, _)| self.flags & flag != 0)
+        .map_or(' ', |(_, marker)| *marker)
+    }
+}
+
+/// Formats the result like a line of `rpm -V`, e.g. `S.5....T.  c /etc/foo.conf`.
+impl fmt::Display for FileVerification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.missing {
+            write!(f, "missing   {} {}", self.marker(), self.path.display())
+        } else {
+            write!(
+                f,
+                "{}  {} {}",
+                self.attributes(),
+                self.marker(),
+                self.path.display()
+            )
+        }
+    }
+}
+
+impl Header<IndexTag> {
+    /// Compare every file of the package with the one installed below `root`, as if `root` was `/`.
+    ///
+    /// Attributes excluded by `%verify(not ...)`, i.e. `RPMTAG_FILEVERIFYFLAGS`, are not checked.
+    /// Owners are resolved through `etc/passwd` and `etc/group` of `root`.
+    /// File capabilities can not be read and are reported as unknown if the package sets any.
+    ///
+    /// Returns the results in header order, including the files that match.
+    pub fn verify_root<P: AsRef<Path>>(
+        &self,
+        root: P,
+        options: FileVerifyOptions,
+    ) -> Result<Vec<FileVerification>, RPMError> {
+        let root = root.as_ref();
+        let paths = self.get_file_paths()?;
+        let n = paths.len();
+        let algorithm = self.get_file_digest_algorithm().unwrap_or_default();
+        let modes = self.get_entry_i16_array_data(IndexTag::RPMTAG_FILEMODES)?;
+        let users = self.get_entry_string_array_data(IndexTag::RPMTAG_FILEUSERNAME)?;
+        let groups = self.get_entry_string_array_data(IndexTag::RPMTAG_FILEGROUPNAME)?;
+        let digests = self.get_entry_string_array_data(IndexTag::RPMTAG_FILEDIGESTS)?;
+        let mtimes = self.get_entry_i32_array_data(IndexTag::RPMTAG_FILEMTIMES)?;
+        let sizes = self
+            .get_entry_i64_array_data(IndexTag::RPMTAG_LONGFILESIZES)
+            .or_else(|_| {
+                self.get_entry_i32_array_data(IndexTag::RPMTAG_FILESIZES)
+                    .map(|sizes| sizes.into_iter().map(|size| size as u32 as i64).collect())
+            })?;
+        let flags = self.get_entry_i32_array_data(IndexTag::RPMTAG_FILEFLAGS)?;
+        let links = self.get_entry_string_array_data(IndexTag::RPMTAG_FILELINKTOS)?;
+        // everything is verified unless stated otherwise
+        let verify_flags = self
+            .get_entry_i32_array_data(IndexTag::RPMTAG_FILEVERIFYFLAGS)
+            .unwrap_or_else(|_| vec![-1; n]);
+        let rdevs = self
+            .get_entry_i16_array_data(IndexTag::RPMTAG_FILERDEVS)
+            .unwrap_or_else(|_| vec![0; n]);
+        let caps = self
+            .get_entry_string_array_data(IndexTag::RPMTAG_FILECAPS)
+            .ok();
+        for (tag, len) in [
+            (IndexTag::RPMTAG_FILEMODES, modes.len()),
+            (IndexTag::RPMTAG_FILEUSERNAME, users.len()),
+            (IndexTag::RPMTAG_FILEGROUPNAME, groups.len()),
+            (IndexTag::RPMTAG_FILEDIGESTS, digests.len()),
+            (IndexTag::RPMTAG_FILEMTIMES, mtimes.len()),
+            (IndexTag::RPMTAG_FILESIZES, sizes.len()),
+            (IndexTag::RPMTAG_FILEFLAGS, flags.len()),
+            (IndexTag::RPMTAG_FILELINKTOS, links.len()),
+            (IndexTag::RPMTAG_FILEVERIFYFLAGS, verify_flags.len()),
+            (IndexTag::RPMTAG_FILERDEVS, rdevs.len()),
+        ] {
+            if len < n {
+                return Err(RPMError::InvalidTagIndex {
+                    tag: tag.to_string(),
+                    index: len as u32,
+                    bound: n as u32,
+                });
+            }
+        }
+
+        let accounts = Accounts::read(root);
+        let mut results = Vec::with_capacity(n);
+        for (index, path) in paths.into_iter().enumerate() {
+            let location = location_in_root(root, &path.to_string_lossy())?;
+            let mut result = FileVerification {
+                path,
+                location,
+                flags: flags[index],
+                missing: false,
+                failed: 0,
+                unknown: 0,
+            };
+            let stat = match std::fs::symlink_metadata(&result.location) {
+                Ok(metadata) => Stat::from(&metadata),
+                Err(_) => {
+                    result.missing = true;
+                    results.push(result);
+                    continue;
+                }
+            };
+
+            let mode = modes[index] as u16 as u32;
+            let mut checks = verify_flags[index] as u32 & !options.omit;
+            match mode & FILE_TYPE_MASK {
+                DIR_FILE_TYPE => {
+                    checks &= !(RPMVERIFY_FILEDIGEST
+                        | RPMVERIFY_FILESIZE
+                        | RPMVERIFY_MTIME
+                        | RPMVERIFY_LINKTO
+                        | RPMVERIFY_CAPS)
+                }
+                SYMLINK_FILE_TYPE => {
+                    checks &= !(RPMVERIFY_FILEDIGEST
+                        | RPMVERIFY_FILESIZE
+                        | RPMVERIFY_MTIME
+                        | RPMVERIFY_MODE
+                        | RPMVERIFY_CAPS)
+                }
+                _ => checks &= !RPMVERIFY_LINKTO,
+            }
+            if !matches!(mode & FILE_TYPE_MASK, CHAR_DEVICE_TYPE | BLOCK_DEVICE_TYPE) {
+                checks &= !RPMVERIFY_RDEV;
+            }
+            if result.flags & RPMFILE_GHOST != 0 {
+                checks &= !(RPMVERIFY_FILEDIGEST
+                    | RPMVERIFY_FILESIZE
+                    | RPMVERIFY_MTIME
+                    | RPMVERIFY_LINKTO);
+            }
+
+            let (mut failed, mut unknown) = (0, 0);
+            let mut compare = |bit: u32, outcome: Option<bool>| {
+                if checks & bit != 0 {
+                    match outcome {
+                        Some(true) => {}
+                        Some(false) => failed |= bit,
+                        None => unknown |= bit,
+                    }
+                }
+            };
+            compare(RPMVERIFY_FILESIZE, Some(stat.size as i64 == sizes[index]));
+            compare(RPMVERIFY_MODE, stat.mode.map(|actual| actual == mode));
+            if checks & RPMVERIFY_FILEDIGEST != 0 {
+                let outcome = if stat.mode.map_or(true, |actual| {
+                    actual & FILE_TYPE_MASK == mode & FILE_TYPE_MASK
+                }) {
+                    file_digest(&result.location, algorithm)
+                        .map(|actual| actual.eq_ignore_ascii_case(&digests[index]))
+                } else {
+                    Some(false)
+                };
+                compare(RPMVERIFY_FILEDIGEST, outcome);
+            }
+            compare(
+                RPMVERIFY_RDEV,
+                stat.rdev.map(|actual| actual == rdevs[index] as u16 as u64),
+            );
+            if checks & RPMVERIFY_LINKTO != 0 {
+                let outcome = std::fs::read_link(&result.location)
+                    .map(|target| target.to_string_lossy() == links[index].as_str())
+                    .unwrap_or(false);
+                compare(RPMVERIFY_LINKTO, Some(outcome));
+            }
+            compare(
+                RPMVERIFY_USER,
+                stat.uid
+                    .and_then(|uid| accounts.user(uid))
+                    .map(|user| user == users[index]),
+            );
+            compare(
+                RPMVERIFY_GROUP,
+                stat.gid
+                    .and_then(|gid| accounts.group(gid))
+                    .map(|group| group == groups[index]),
+            );
+            compare(
+                RPMVERIFY_MTIME,
+                Some(stat.mtime == mtimes[index] as u32 as i64),
+            );
+            let has_caps = caps
+                .and_then(|caps| caps.get(index))
+                .map_or(false, |caps| !caps.is_empty());
+            compare(RPMVERIFY_CAPS, if has_caps { None } else { Some(true) });
+            result.failed = failed;
+            result.unknown = unknown;
+            results.push(result);
+        }
+        Ok(results)
+    }
+}
+
+impl RPMPackage {
+    /// Compare the files of the package with those installed below `root`,
+    /// see [`Header::verify_root`].
+    pub fn verify_root<P: AsRef<Path>>(
+        &self,
+        root: P,
+        options: FileVerifyOptions,
+    ) -> Result<Vec<FileVerification>, RPMError> {
+        self.metadata.header.verify_root(root, options)
+    }
+}
+
+/// What `lstat` reports, as far as the platform tells.
+struct Stat {
+    size: u64,
+    mtime: i64,
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    rdev: Option<u64>,
+}
+
+#[cfg(unix)]
+impl From<&std::fs::Metadata> for Stat {
+    fn from(metadata: &std::fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        Stat {
+            size: metadata.size(),
+            mtime: metadata.mtime(),
+            mode: Some(metadata.mode() & (FILE_TYPE_MASK | 0o7777)),
+            uid: Some(metadata.uid()),
+            gid: Some(metadata.gid()),
+            rdev: Some(metadata.rdev()),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl From<&std::fs::Metadata> for Stat {
+    fn from(metadata: &std::fs::Metadata) -> Self {
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs() as i64);
+        Stat {
+            size: metadata.len(),
+            mtime,
+            mode: None,
+            uid: None,
+            gid: None,
+            rdev: None,
+        }
+    }
+}
+
+/// Hex digest of the file at `location`, `None` if it can not be read or
+/// the algorithm is not supported.
+fn file_digest(location: &Path, algorithm: FileDigestAlgorithm) -> Option<String> {
+    fn hash<D: sha2::Digest>(location: &Path) -> Option<String> {
+        let content = std::fs::read(location).ok()?;
+        Some(hex::encode(D::digest(&content)))
+    }
+    match algorithm {
+        FileDigestAlgorithm::Md5 => hash::<md5::Md5>(location),
+        FileDigestAlgorithm::Sha2_224 => hash::<sha2::Sha224>(location),
+        FileDigestAlgorithm::Sha2_256 => hash::<sha2::Sha256>(location),
+        FileDigestAlgorithm::Sha2_384 => hash::<sha2::Sha384>(location),
+        FileDigestAlgorithm::Sha2_512 => hash::<sha2::Sha512>(location),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{InstallOptions, RPMBuilder, RPMFileOptions};
+
+    #[test]
+    fn verify_installed_files() -> Result<(), Box<dyn std::error::Error>> {
+        let root = crate::tests::TestDir::new("verify");
+        let package = RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "")
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/etc/awesome/config.toml").is_config(),
+            )?
+            .with_file(
+                "./test_assets/awesome.py",
+                RPMFileOptions::new("/usr/bin/awesome").mode(0o100755),
+            )?
+            .build()?;
+        package.install_into(&root, InstallOptions::new())?;
+
+        // unpacking does not apply owners
+        let options = FileVerifyOptions::new().omit(RPMVERIFY_USER | RPMVERIFY_GROUP);
+        let results = package.verify_root(&root, options.clone())?;
+        assert_eq!(2, results.len());
+        assert!(results.iter().all(FileVerification::is_ok));
+        let options = options.omit(RPMVERIFY_MTIME);
+
+        let config = root.join("etc/awesome/config.toml");
+        std::fs::write(&config, "changed")?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&config, std::fs::Permissions::from_mode(0o600))?;
+        }
+        std::fs::remove_file(root.join("usr/bin/awesome"))?;
+        let results = package.verify_root(&root, options)?;
+        let config = &results[0];
+        assert!(!config.is_ok());
+        #[cfg(unix)]
+        assert_eq!("SM5......  c /etc/awesome/config.toml", config.to_string());
+        let missing = &results[1];
+        assert!(missing.missing);
+        assert!(!missing.is_ok());
+        assert_eq!("missing     /usr/bin/awesome", missing.to_string());
+
+        Ok(())
+    }
+}
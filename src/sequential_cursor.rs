@@ -76,6 +76,13 @@ impl<'s> std::io::Seek for SeqCursor<'s> {
             }
             std::io::SeekFrom::Current(rel) => (self.position as i64 + rel) as u64,
         };
+        // reads continue where the slices are
+        let mut start = 0u64;
+        for cursor in self.cursors.iter_mut() {
+            let len = cursor.get_ref().len() as u64;
+            cursor.set_position(self.position.saturating_sub(start).min(len));
+            start += len;
+        }
         Ok(self.position)
     }
 }
@@ -105,5 +112,12 @@ mod test {
         sq.seek(std::io::SeekFrom::Current(12)).unwrap();
         sq.read(&mut buf[4..8]).unwrap();
         assert_eq!(buf[4..8].to_vec(), vec![2u8, 2u8, 3u8, 3u8]);
+
+        let mut all = Vec::new();
+        sq.read_to_end(&mut all).unwrap();
+        sq.seek(std::io::SeekFrom::Start(0)).unwrap();
+        all.clear();
+        sq.read_to_end(&mut all).unwrap();
+        assert_eq!([c1, c2, c3].concat(), all);
     }
 }
@@ -16,6 +16,46 @@ fn cargo_manifest_dir() -> std::path::PathBuf {
     std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
 }
 
+/// An empty directory below the temporary directory, removed with everything in it
+/// on drop. Unique per process and call, such that parallel tests never share one.
+pub(crate) struct TestDir(std::path::PathBuf);
+
+impl TestDir {
+    pub(crate) fn new(name: &str) -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "fez-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("creating a test directory");
+        TestDir(path)
+    }
+}
+
+impl std::ops::Deref for TestDir {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+impl AsRef<std::path::Path> for TestDir {
+    fn as_ref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
 #[test]
 fn test_rpm_file_signatures() -> Result<(), Box<dyn std::error::Error>> {
     let rpm_file_path = file_signatures_test_rpm_file_path();
@@ -305,3 +345,1654 @@ fn test_region_tag() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_header_digest() -> Result<(), Box<dyn std::error::Error>> {
+    let rpm_file = std::fs::File::open(test_rpm_file_path())?;
+    let package = RPMPackage::parse(&mut std::io::BufReader::new(rpm_file))?;
+    assert_eq!(
+        "6178620331c1fe63c5dd3da7c118058e366e37d8",
+        package.metadata.header.header_digest()?.sha1
+    );
+
+    let pkg = RPMBuilder::new("test", "1.0.0", "MIT", "x86_64", "some package")
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )?
+        .build()?;
+    let digest = pkg.metadata.header.header_digest()?;
+    let signature = &pkg.metadata.signature;
+    assert_eq!(
+        digest.sha1,
+        signature.get_entry_string_data(IndexSignatureTag::RPMSIGTAG_SHA1)?
+    );
+    assert_eq!(
+        digest.sha256,
+        signature.get_entry_string_data(IndexSignatureTag::RPMSIGTAG_SHA256)?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_permissive_parsing() -> Result<(), Box<dyn std::error::Error>> {
+    let mut raw = std::fs::read(test_rpm_file_path())?;
+    let package = RPMPackage::parse(&mut raw.as_slice())?;
+    let signature = &package.metadata.signature;
+    let signature_size = 16
+        + signature.index_header.num_entries as usize * 16
+        + signature.index_header.header_size as usize;
+    let header_start = LEAD_SIZE + (signature_size + 7) / 8 * 8;
+    // a foreign operating system in the lead and a tag nobody knows
+    raw[76..78].copy_from_slice(&2u16.to_be_bytes());
+    let second_entry = header_start + 32;
+    raw[second_entry..second_entry + 4].copy_from_slice(&99_999u32.to_be_bytes());
+
+    assert!(RPMPackage::parse(&mut raw.as_slice()).is_err());
+    assert!(RPMPackage::parse_with_options(
+        &mut raw.as_slice(),
+        &ParseOptions::new(),
+        &mut Diagnostics::new()
+    )
+    .is_err());
+    let mut diagnostics = Diagnostics::new();
+    let parsed = RPMPackage::parse_with_options(
+        &mut raw.as_slice(),
+        &ParseOptions::new().permissive(true),
+        &mut diagnostics,
+    )?;
+    assert_eq!(
+        package.metadata.header.index_entries.len() - 1,
+        parsed.metadata.header.index_entries.len()
+    );
+    assert_eq!(
+        package.metadata.header.get_name()?,
+        parsed.metadata.header.get_name()?
+    );
+    let diagnostics = diagnostics.into_iter().collect::<Vec<_>>();
+    assert_eq!(Diagnostic::Quirk(Quirk::LeadOs(2)), diagnostics[0]);
+    assert_eq!(
+        Diagnostic::Quirk(Quirk::UnknownTag {
+            raw_tag: 99_999,
+            store_type: "IndexTag"
+        }),
+        diagnostics[1]
+    );
+    assert!(matches!(
+        diagnostics[2],
+        Diagnostic::Quirk(Quirk::InvalidRegion { .. })
+    ));
+    // built before rpm 4.14, the header is only covered by SHA1
+    assert_eq!(
+        Diagnostic::DeprecatedDigest {
+            tag: "RPMSIGTAG_SHA1".to_string(),
+            algorithm: "SHA1"
+        },
+        diagnostics[3]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_trailing_data() -> Result<(), Box<dyn std::error::Error>> {
+    let raw = std::fs::read(test_rpm_file_path())?;
+    let package = RPMPackage::parse(&mut raw.as_slice())?;
+    assert!(package.trailing_data().is_empty());
+
+    let mut with_trailer = raw.clone();
+    with_trailer.extend_from_slice(b"TRAILER");
+    let mut diagnostics = Diagnostics::new();
+    let mut parsed = RPMPackage::parse_with_options(
+        &mut with_trailer.as_slice(),
+        &ParseOptions::new(),
+        &mut diagnostics,
+    )?;
+    assert_eq!(b"TRAILER", parsed.trailing_data());
+    assert_eq!(package.content, parsed.content);
+    parsed.verify_digests()?;
+    assert_eq!(
+        vec![Diagnostic::TrailingData { len: 7 }],
+        diagnostics
+            .into_iter()
+            .filter(|diagnostic| diagnostic.code() == "FEZ-W0012")
+            .collect::<Vec<_>>()
+    );
+
+    let mut written = Vec::new();
+    parsed.write(&mut written)?;
+    assert_eq!(with_trailer, written);
+    assert_eq!(b"TRAILER".to_vec(), parsed.strip_trailing_data());
+    written.clear();
+    parsed.write(&mut written)?;
+    assert_eq!(raw, written);
+    Ok(())
+}
+
+#[test]
+fn test_build_diagnostics() -> Result<(), Box<dyn std::error::Error>> {
+    let builder = || {
+        RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package").with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/link").mode(FileMode::symlink(0o777)),
+        )
+    };
+    assert!(builder()?.strict(true).build().is_err());
+
+    let mut diagnostics = Diagnostics::new();
+    let pkg = builder()?.build_with_diagnostics(&mut diagnostics)?;
+    assert_eq!(
+        vec![&Diagnostic::SpecViolation {
+            path: "/etc/awesome/link".to_string(),
+            reason: "symlink without target"
+        }],
+        diagnostics.iter().collect::<Vec<_>>()
+    );
+
+    // packages written by this crate carry only current digests
+    let mut raw = Vec::new();
+    pkg.write(&mut raw)?;
+    let mut diagnostics = Diagnostics::new();
+    RPMPackage::parse_with_options(&mut raw.as_slice(), &ParseOptions::new(), &mut diagnostics)?;
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    Ok(())
+}
+
+#[test]
+fn test_progress() -> Result<(), Box<dyn std::error::Error>> {
+    let built = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = built.clone();
+    let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )?
+        .with_file(
+            "./test_assets/awesome.py",
+            RPMFileOptions::new("/usr/bin/awesome"),
+        )?
+        .progress(move |update: &ProgressUpdate<'_>| {
+            sink.lock()
+                .unwrap()
+                .push((update.phase, update.file.map(str::to_string), update.bytes))
+        })
+        .build()?;
+    let total = (std::fs::metadata("./test_assets/awesome.toml")?.len()
+        + std::fs::metadata("./test_assets/awesome.py")?.len()) as u64;
+    let built = built.lock().unwrap().clone();
+    assert_eq!(2, built.len());
+    assert_eq!(
+        (Phase::Build, Some("/usr/bin/awesome".to_string()), total),
+        built[1]
+    );
+
+    let root = TestDir::new("progress");
+    let extracted = std::sync::Mutex::new(Vec::new());
+    pkg.install_into(
+        &root,
+        InstallOptions::new().progress(|update: &ProgressUpdate<'_>| {
+            assert_eq!(Phase::Extract, update.phase);
+            assert_eq!(Some(total), update.total);
+            extracted.lock().unwrap().push(update.bytes)
+        }),
+    )?;
+    assert_eq!(Some(&total), extracted.lock().unwrap().last());
+    Ok(())
+}
+
+#[test]
+fn test_cancellation() -> Result<(), Box<dyn std::error::Error>> {
+    let builder = || {
+        RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package").with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )
+    };
+    let token = CancellationToken::new();
+    let pkg = builder()?.cancellation(token.clone()).build()?;
+    token.cancel();
+    assert!(matches!(
+        builder()?.cancellation(token.clone()).build(),
+        Err(RPMError::Cancelled)
+    ));
+
+    let root = TestDir::new("cancel");
+    assert!(matches!(
+        pkg.install_into(&root, InstallOptions::new().cancellation(token.clone())),
+        Err(RPMError::Cancelled)
+    ));
+    assert!(!root.join("etc/awesome/config.toml").exists());
+
+    #[cfg(feature = "signature-pgp")]
+    {
+        let (signing_key, verification_key) = crate::signature::pgp::test::load_asc_keys();
+        let signer = signature::pgp::Signer::load_from_asc_bytes(signing_key.as_ref())?;
+        let signed = builder()?.build_and_sign(&signer)?;
+        let verifier = || signature::pgp::Verifier::load_from_asc_bytes(verification_key.as_ref());
+        signed.verify_signature_with(verifier()?, &VerifyOptions::new())?;
+        assert!(matches!(
+            signed.verify_signature_with(verifier()?, &VerifyOptions::new().cancellation(token)),
+            Err(RPMError::Cancelled)
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_with_archive_cpio() -> Result<(), Box<dyn std::error::Error>> {
+    // an uncompressed payload is a plain cpio archive
+    let source = RPMBuilder::new("source", "1.0.0", "MIT", "noarch", "source package")
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/opt/build/etc/awesome.toml").mode(FileMode::regular(0o600)),
+        )?
+        .build()?;
+
+    let pkg = RPMBuilder::new("target", "1.0.0", "MIT", "noarch", "target package")
+        .with_archive(
+            source.content.as_slice(),
+            ArchiveFormat::Cpio,
+            &[("/opt/build", "/")],
+        )?
+        .build()?;
+
+    let entries = pkg.metadata.header.get_file_entries()?;
+    assert_eq!(1, entries.len());
+    assert_eq!(std::path::Path::new("/etc/awesome.toml"), entries[0].path);
+    assert_eq!(FileMode::regular(0o600), entries[0].mode);
+    Ok(())
+}
+
+#[test]
+fn test_non_utf8_file_names() -> Result<(), Box<dyn std::error::Error>> {
+    let source = RPMBuilder::new("source", "1.0.0", "MIT", "noarch", "source package")
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/opt/xcafex"),
+        )?
+        .build()?;
+    let mut raw = Vec::new();
+    source.write(&mut raw)?;
+    // the name in the header and the payload, as latin-1
+    let mut position = 0;
+    while let Some(found) = raw[position..].windows(6).position(|w| w == b"xcafex") {
+        raw[position + found + 4] = 0xe9;
+        position += found + 6;
+    }
+    let pkg = RPMPackage::parse(&mut raw.as_slice())?;
+
+    let header = &pkg.metadata.header;
+    assert_eq!(
+        vec![std::path::PathBuf::from("/opt/xcaf\u{FFFD}x")],
+        header.get_file_paths()?
+    );
+    assert!(matches!(
+        header.get_file_paths_strict(),
+        Err(RPMError::NonUtf8FileName(_))
+    ));
+    assert_eq!(
+        vec![b"/opt/xcaf\xe9x".to_vec()],
+        header.get_file_path_bytes()?
+    );
+    assert_eq!(
+        b"/opt/xcaf\xe9x".to_vec(),
+        pkg.payload_entries()?[0].raw_path
+    );
+
+    let archive = || RPMBuilder::new("target", "1.0.0", "MIT", "noarch", "target package");
+    assert!(matches!(
+        archive().with_archive(pkg.content.as_slice(), ArchiveFormat::Cpio, &[]),
+        Err(RPMError::NonUtf8FileName(_))
+    ));
+    for (policy, expected) in [
+        (FileNamePolicy::Escape, "/opt/xcaf%E9x"),
+        (FileNamePolicy::Lossy, "/opt/xcaf\u{FFFD}x"),
+    ] {
+        let target = archive()
+            .file_name_policy(policy)
+            .with_archive(pkg.content.as_slice(), ArchiveFormat::Cpio, &[])?
+            .build()?;
+        assert_eq!(
+            vec![std::path::PathBuf::from(expected)],
+            target.metadata.header.get_file_paths_strict()?
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tar")]
+#[test]
+fn test_with_archive_tar() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tar = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_mode(0o750);
+    header.set_size(0);
+    tar.append_data(&mut header, "./share/awesome/", std::io::empty())?;
+
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(0o755);
+    header.set_size(5);
+    header.set_username("nobody")?;
+    tar.append_data(&mut header, "./bin/awesome", b"echo\n".as_ref())?;
+
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_mode(0o777);
+    header.set_size(0);
+    tar.append_link(&mut header, "./bin/awesome-link", "awesome")?;
+    let raw = tar.into_inner()?;
+
+    let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .with_archive(raw.as_slice(), ArchiveFormat::Tar, &[("/", "/usr")])?
+        .build()?;
+
+    let entries = pkg.metadata.header.get_file_entries()?;
+    let find = |path: &str| {
+        entries
+            .iter()
+            .find(|entry| entry.path == std::path::Path::new(path))
+            .unwrap_or_else(|| panic!("{} missing", path))
+    };
+    assert_eq!(FileMode::dir(0o750), find("/usr/share/awesome").mode);
+    let binary = find("/usr/bin/awesome");
+    assert_eq!(FileMode::regular(0o755), binary.mode);
+    assert!(pkg
+        .metadata
+        .header
+        .get_entry_string_array_data(IndexTag::RPMTAG_FILEUSERNAME)?
+        .contains(&"nobody".to_string()));
+    let link = find("/usr/bin/awesome-link");
+    assert_eq!(FileMode::symlink(0o777), link.mode);
+    assert!(link.digest.is_none());
+
+    let payload = pkg.payload_entries()?;
+    let link = payload
+        .iter()
+        .find(|entry| entry.path == "/usr/bin/awesome-link")
+        .unwrap();
+    assert!(link.is_symlink());
+    assert_eq!(b"awesome".to_vec(), link.content);
+    Ok(())
+}
+
+#[cfg(feature = "tar")]
+#[test]
+fn test_with_archive_tar_hardlinks() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tar = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(0o755);
+    header.set_size(5);
+    tar.append_data(&mut header, "./bin/awesome", b"echo\n".as_ref())?;
+    for name in ["./bin/awesome-hard", "./sbin/awesome"] {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Link);
+        header.set_mode(0o755);
+        header.set_size(0);
+        tar.append_link(&mut header, name, "./bin/awesome")?;
+    }
+    let raw = tar.into_inner()?;
+
+    for format in [CpioFormat::Newc, CpioFormat::Stripped] {
+        let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+            .cpio_format(format)
+            .with_archive(raw.as_slice(), ArchiveFormat::Tar, &[("/", "/usr")])?
+            .build()?;
+
+        let header = &pkg.metadata.header;
+        let inodes = header.get_entry_i32_array_data(IndexTag::RPMTAG_FILEINODES)?;
+        assert_eq!(3, inodes.len());
+        assert!(inodes.iter().all(|inode| *inode == inodes[0]));
+        for entry in header.get_file_entries()? {
+            assert_eq!(FileMode::regular(0o755), entry.mode);
+            assert_eq!(5, entry.size);
+        }
+        // the content is installed once
+        assert_eq!(5, header.get_installed_size()?);
+
+        let payload = pkg.payload_entries()?;
+        assert_eq!(3, payload.len());
+        assert!(payload.iter().all(|entry| entry.nlink == 3));
+        let contents = payload
+            .iter()
+            .map(|entry| entry.content.as_slice())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![b"echo\n".as_ref(); 3], contents, "{:?}", format);
+
+        let root = TestDir::new("hardlinks");
+        pkg.install_into(&root, InstallOptions::new())?;
+        for path in [
+            "usr/bin/awesome",
+            "usr/bin/awesome-hard",
+            "usr/sbin/awesome",
+        ] {
+            assert_eq!(
+                b"echo\n".as_ref(),
+                std::fs::read(root.join(path))?,
+                "{}",
+                path
+            );
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let metadata = std::fs::metadata(root.join("usr/sbin/awesome"))?;
+            assert_eq!(3, metadata.nlink());
+            assert_eq!(
+                std::fs::metadata(root.join("usr/bin/awesome"))?.ino(),
+                metadata.ino()
+            );
+        }
+
+        let mut raw = Vec::new();
+        pkg.payload_to_tar(&mut raw)?;
+        let mut archive = tar::Archive::new(raw.as_slice());
+        let mut files = Vec::new();
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let kind = entry.header().entry_type();
+            if kind.is_dir() {
+                continue;
+            }
+            files.push((
+                entry.path()?.to_string_lossy().to_string(),
+                kind,
+                entry
+                    .link_name()?
+                    .map(|target| target.to_string_lossy().to_string()),
+            ));
+        }
+        let link = Some("usr/bin/awesome".to_string());
+        assert_eq!(
+            vec![
+                ("usr/bin/awesome".to_string(), tar::EntryType::Regular, None),
+                (
+                    "usr/bin/awesome-hard".to_string(),
+                    tar::EntryType::Link,
+                    link.clone()
+                ),
+                ("usr/sbin/awesome".to_string(), tar::EntryType::Link, link),
+            ],
+            files
+        );
+    }
+
+    let mut tar = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Link);
+    header.set_mode(0o644);
+    header.set_size(0);
+    tar.append_link(&mut header, "./bin/awesome-hard", "./bin/awesome")?;
+    assert!(matches!(
+        RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package").with_archive(
+            tar.into_inner()?.as_slice(),
+            ArchiveFormat::Tar,
+            &[]
+        ),
+        Err(RPMError::UnsupportedArchiveEntry { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_xz_payload_entries() -> Result<(), Box<dyn std::error::Error>> {
+    for name in [
+        "389-ds-base-devel-1.3.8.4-15.el7.x86_64.rpm",
+        "monkeysphere-0.37-1.el7.noarch.rpm",
+    ] {
+        let rpm_file = std::fs::File::open(cargo_manifest_dir().join("test_assets").join(name))?;
+        let pkg = RPMPackage::parse(&mut std::io::BufReader::new(rpm_file))?;
+        assert_eq!("xz", pkg.metadata.header.get_payload_compressor()?);
+
+        let files = pkg.metadata.header.get_file_entries()?;
+        let entries = pkg.payload_entries()?;
+        assert_eq!(files.len(), entries.len(), "{}", name);
+        for entry in entries.iter().filter(|entry| !entry.is_dir()) {
+            let file = files
+                .iter()
+                .find(|file| file.path == std::path::Path::new(&entry.path))
+                .unwrap_or_else(|| panic!("{} not in the header of {}", entry.path, name));
+            assert_eq!(file.size, entry.content.len(), "{}", entry.path);
+        }
+        assert!(pkg.uncompressed_payload()?.starts_with(b"070701"));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_write_sha256sums() -> Result<(), Box<dyn std::error::Error>> {
+    use sha2::Digest;
+
+    let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )?
+        .build()?;
+
+    let digests = pkg.file_digests()?;
+    assert_eq!(1, digests.len());
+    assert_eq!(FileDigestAlgorithm::Sha2_256, digests[0].1.algorithm());
+
+    let mut manifest = Vec::new();
+    pkg.write_sha256sums(&mut manifest)?;
+    let mut hasher = sha2::Sha256::default();
+    hasher.update(std::fs::read("./test_assets/awesome.toml")?);
+    assert_eq!(
+        format!(
+            "{}  /etc/awesome/config.toml\n",
+            hex::encode(hasher.finalize())
+        ),
+        String::from_utf8(manifest)?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_build_to() -> Result<(), Box<dyn std::error::Error>> {
+    use sha2::Digest;
+
+    let builder = || RPMBuilder::new("test", "1.0.0", "MIT", "x86_64", "some package");
+    let mut out = Vec::new();
+    let info = builder().build_to(&mut out)?;
+
+    let mut expected = Vec::new();
+    builder().build()?.write(&mut expected)?;
+    assert_eq!(expected, out);
+    assert_eq!(out.len() as u64, info.size);
+    assert_eq!(Some(hex::encode(sha2::Sha256::digest(&out))), info.sha256);
+    assert_eq!(Some(hex::encode(sha1::Sha1::digest(&out))), info.sha1);
+    Ok(())
+}
+
+#[test]
+fn test_patch() -> Result<(), Box<dyn std::error::Error>> {
+    use sha2::Digest;
+    use std::str::FromStr;
+
+    for format in [CpioFormat::Newc, CpioFormat::Stripped] {
+        let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+            .compression(Compressor::from_str("gzip")?)
+            .cpio_format(format)
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/etc/awesome/config.toml"),
+            )?
+            .with_file(
+                "./test_assets/awesome.py",
+                RPMFileOptions::new("/usr/bin/awesome").mode(FileMode::regular(0o755)),
+            )?
+            .with_file(
+                "./test_assets/awesome.xml",
+                RPMFileOptions::new("/usr/share/awesome/tests/data.xml"),
+            )?
+            .build()?;
+        let size = pkg
+            .metadata
+            .header
+            .get_entry_i32_data(IndexTag::RPMTAG_SIZE)?;
+        let removed_size = std::fs::metadata("./test_assets/awesome.xml")?.len() as i32;
+        let config_size = std::fs::metadata("./test_assets/awesome.toml")?.len() as i32;
+
+        let patch = PayloadPatch::new()
+            .remove_matching(|path| path.contains("/tests/"))
+            .replace("/etc/awesome/config.toml", b"patched = true\n".to_vec());
+        let pkg = pkg.patch(&patch)?;
+
+        // survives a roundtrip through its serialized form
+        let mut raw = Vec::new();
+        pkg.write(&mut raw)?;
+        let pkg = RPMPackage::parse(&mut raw.as_slice())?;
+        assert_eq!(format, pkg.payload_cpio_format()?);
+        assert_eq!(
+            vec![
+                std::path::PathBuf::from("/etc/awesome/config.toml"),
+                std::path::PathBuf::from("/usr/bin/awesome")
+            ],
+            pkg.metadata.header.get_file_paths()?
+        );
+        let entries = pkg.payload_entries()?;
+        assert_eq!(2, entries.len());
+        assert_eq!(b"patched = true\n".to_vec(), entries[0].content);
+        assert_eq!(
+            hex::encode(sha2::Sha256::digest(b"patched = true\n")),
+            pkg.file_digests()?[0].1.to_hex()
+        );
+        assert_eq!(
+            vec![
+                15,
+                std::fs::metadata("./test_assets/awesome.py")?.len() as i32
+            ],
+            pkg.metadata
+                .header
+                .get_entry_i32_array_data(IndexTag::RPMTAG_FILESIZES)?
+        );
+        assert_eq!(
+            size - removed_size - config_size + 15,
+            pkg.metadata
+                .header
+                .get_entry_i32_data(IndexTag::RPMTAG_SIZE)?
+        );
+        assert_eq!(
+            pkg.metadata.header.header_digest()?.sha256,
+            pkg.metadata
+                .signature
+                .get_entry_string_data(IndexSignatureTag::RPMSIGTAG_SHA256)?
+        );
+        assert!(pkg.verify_payload_digest()?);
+    }
+
+    let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package").build()?;
+    assert!(matches!(
+        pkg.patch(&PayloadPatch::new().remove("/missing")),
+        Err(RPMError::FileNotFound(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_stripped_cpio_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+    use std::str::FromStr;
+
+    let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .compression(Compressor::from_str("gzip")?)
+        .cpio_format(CpioFormat::Stripped)
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )?
+        .with_file(
+            "./test_assets/awesome.py",
+            RPMFileOptions::new("/usr/bin/awesome").mode(FileMode::regular(0o755)),
+        )?
+        .build()?;
+    assert_eq!(CpioFormat::Stripped, pkg.payload_cpio_format()?);
+    assert!(pkg
+        .metadata
+        .header
+        .get_requires()?
+        .iter()
+        .any(|dep| dep.name() == "rpmlib(LargeFiles)"));
+
+    let entries = pkg.payload_entries()?;
+    assert_eq!(2, entries.len());
+    assert_eq!("/usr/bin/awesome", entries[1].path);
+    assert_eq!(FileMode::regular(0o755), entries[1].mode);
+    assert_eq!(
+        std::fs::read("./test_assets/awesome.py")?,
+        entries[1].content
+    );
+    assert_eq!(
+        std::fs::read("./test_assets/awesome.toml")?,
+        entries[0].content
+    );
+    Ok(())
+}
+
+#[test]
+fn test_strict_mode() -> Result<(), Box<dyn std::error::Error>> {
+    let builder = || {
+        RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package").with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml").symlink("/etc/other.toml"),
+        )
+    };
+    // not a symlink, but a link target given
+    assert!(builder()?.build().is_ok());
+    match builder()?.strict(true).build() {
+        Err(RPMError::SpecViolation { path, .. }) => {
+            assert_eq!("/etc/awesome/config.toml", path)
+        }
+        other => panic!("expected a violation, got {:?}", other.map(|_| ())),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_destination_normalization() -> Result<(), Box<dyn std::error::Error>> {
+    let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("//etc//awesome/./config.toml"),
+        )?
+        .with_file(
+            "./test_assets/awesome.py",
+            RPMFileOptions::new("/awesome.py"),
+        )?
+        .build()?;
+    let header = &pkg.metadata.header;
+    assert_eq!(
+        vec!["/", "/etc/awesome/"],
+        header.get_entry_string_array_data(IndexTag::RPMTAG_DIRNAMES)?
+    );
+    assert_eq!(
+        vec!["awesome.py", "config.toml"],
+        header.get_entry_string_array_data(IndexTag::RPMTAG_BASENAMES)?
+    );
+
+    let add = |dest: String| {
+        RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+            .with_file("./test_assets/awesome.toml", RPMFileOptions::new(dest))
+    };
+    assert!(add("/etc/../passwd".to_string()).is_err());
+    assert!(add("etc/awesome.toml".to_string()).is_err());
+    assert!(add("/".to_string()).is_err());
+    assert!(add(format!("/etc/{}", "a".repeat(256))).is_err());
+    assert!(add("/a".repeat(2048)).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_prefix_remap() -> Result<(), Box<dyn std::error::Error>> {
+    let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .with_file(
+            "./test_assets/awesome.py",
+            RPMFileOptions::new("/usr/local/bin/awesome"),
+        )?
+        .with_file(
+            "./test_assets/awesome.py",
+            RPMFileOptions::new("/usr/localized/awesome"),
+        )?
+        .requires(Dependency::any("/usr/local/bin/python3"))
+        .post_install_script("/usr/local/bin/awesome --init /usr/localized /opt/usr/local")
+        .remap_prefix("/usr/local", "/opt/app")
+        .build()?;
+    let header = &pkg.metadata.header;
+    let paths = header.get_file_paths()?;
+    assert_eq!(
+        vec![
+            std::path::PathBuf::from("/opt/app/bin/awesome"),
+            std::path::PathBuf::from("/usr/localized/awesome")
+        ],
+        paths
+    );
+    assert!(header
+        .get_requires()?
+        .iter()
+        .any(|dep| dep.name() == "/opt/app/bin/python3"));
+    assert_eq!(
+        "/opt/app/bin/awesome --init /usr/localized /opt/usr/local",
+        header.get_entry_string_data(IndexTag::RPMTAG_POSTIN)?
+    );
+
+    let collision = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .with_file(
+            "./test_assets/awesome.py",
+            RPMFileOptions::new("/opt/app/bin/awesome"),
+        )?
+        .with_file(
+            "./test_assets/awesome.py",
+            RPMFileOptions::new("/usr/local/bin/awesome"),
+        )?
+        .remap_prefix("/usr/local", "/opt/app")
+        .build();
+    match collision {
+        Err(RPMError::RemapCollision {
+            path,
+            first,
+            second,
+        }) => {
+            assert_eq!("/opt/app/bin/awesome", path);
+            assert_eq!("/opt/app/bin/awesome", first);
+            assert_eq!("/usr/local/bin/awesome", second);
+        }
+        other => panic!("expected a collision, got {:?}", other.map(|_| ())),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_default_attributes() -> Result<(), Box<dyn std::error::Error>> {
+    let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .default_attributes(Some(0o640), Some("awesome"), None)
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )?
+        .with_file(
+            "./test_assets/awesome.py",
+            RPMFileOptions::new("/usr/bin/awesome")
+                .mode(FileMode::regular(0o755))
+                .uid(1000)
+                .gid(1000),
+        )?
+        .with_file(
+            "./test_assets/awesome.py",
+            RPMFileOptions::new("/usr/bin/other").user("nobody").uid(99),
+        )?
+        .build()?;
+    let header = &pkg.metadata.header;
+    assert_eq!(
+        vec!["awesome", "1000", "nobody"],
+        header.get_entry_string_array_data(IndexTag::RPMTAG_FILEUSERNAME)?
+    );
+    assert_eq!(
+        vec!["root", "1000", "root"],
+        header.get_entry_string_array_data(IndexTag::RPMTAG_FILEGROUPNAME)?
+    );
+    let modes = header.get_entry_i16_array_data(IndexTag::RPMTAG_FILEMODES)?;
+    assert_eq!(0o640, modes[0] & 0o7777);
+    assert_eq!(0o755, modes[1] & 0o7777);
+
+    let entries = pkg.payload_entries()?;
+    assert_eq!(
+        vec![0, 1000, 99],
+        entries.iter().map(|e| e.uid).collect::<Vec<_>>()
+    );
+
+    assert!(
+        RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+            .with_file(
+                "./test_assets/awesome.py",
+                RPMFileOptions::new("/usr/bin/awesome").user("awe some"),
+            )
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_builder_host_file_modes() -> Result<(), Box<dyn std::error::Error>> {
+    let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )?
+        .build()?;
+    let modes = pkg
+        .metadata
+        .header
+        .get_entry_i16_array_data(IndexTag::RPMTAG_FILEMODES)?;
+    let mode = FileMode::from(modes[0] as u16);
+    assert_eq!(FileMode::regular(0).file_type(), mode.file_type());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let on_disk = std::fs::metadata("./test_assets/awesome.toml")?.permissions();
+        assert_eq!(on_disk.mode() as u16 & 0o7777, mode.permissions());
+    }
+    #[cfg(not(unix))]
+    assert!(matches!(mode.permissions(), 0o644 | 0o444));
+    Ok(())
+}
+
+#[test]
+fn test_builder_snapshots() -> Result<(), Box<dyn std::error::Error>> {
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    let template = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .compression(Compressor::from_str("gzip")?)
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )?;
+    assert_send_sync(&template);
+
+    let threads = (1..=3)
+        .map(|minor| {
+            let builder = template.clone().version(format!("1.{}.0", minor));
+            std::thread::spawn(move || {
+                builder
+                    .with_file(
+                        "./test_assets/awesome.py",
+                        RPMFileOptions::new(format!("/usr/bin/awesome-{}", minor)),
+                    )?
+                    .build()
+            })
+        })
+        .collect::<Vec<_>>();
+    for (minor, thread) in (1..=3).zip(threads) {
+        let pkg = thread.join().expect("build thread")?;
+        assert_eq!(format!("1.{}.0", minor), pkg.metadata.header.get_version()?);
+        assert_eq!(
+            vec![
+                PathBuf::from("/etc/awesome/config.toml"),
+                PathBuf::from(format!("/usr/bin/awesome-{}", minor)),
+            ],
+            pkg.metadata.header.get_file_paths()?
+        );
+        assert_eq!(
+            Some("gzip"),
+            pkg.metadata.header.get_payload_compressor().ok()
+        );
+    }
+    assert_eq!("1.0.0", template.build()?.metadata.header.get_version()?);
+    Ok(())
+}
+
+#[test]
+fn test_build_plan() -> Result<(), Box<dyn std::error::Error>> {
+    use std::str::FromStr;
+
+    let builder = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .compression(Compressor::from_str("zstd")?)
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )?
+        .with_file(
+            "./test_assets/awesome.py",
+            RPMFileOptions::new("/usr/bin/awesome").mode(0o100755),
+        )?
+        .requires(Dependency::any("python3"));
+
+    let plan = builder.plan()?;
+    assert_eq!(2, plan.files.len());
+    assert_eq!("/usr/bin/awesome", plan.files[1].path.to_str().unwrap());
+    assert!(plan.files[1].digest.is_some());
+    assert!(plan.diagnostics.is_empty());
+
+    let mut built = Vec::new();
+    builder.build()?.write(&mut built)?;
+    assert!(plan.estimated_size >= built.len() as u64);
+    let pkg = RPMPackage::parse(&mut built.as_slice())?;
+    let entries = |header: &Header<IndexTag>| {
+        header
+            .index_entries
+            .iter()
+            .filter(|entry| {
+                !matches!(
+                    entry.tag,
+                    IndexTag::RPMTAG_HEADERIMMUTABLE
+                        | IndexTag::RPMTAG_PAYLOADDIGEST
+                        | IndexTag::RPMTAG_PAYLOADDIGESTALGO
+                )
+            })
+            .map(|entry| (entry.tag, entry.data.clone()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(entries(&pkg.metadata.header), entries(&plan.header));
+    assert!(pkg.verify_payload_digest()?);
+    Ok(())
+}
+
+#[test]
+fn test_stable_output() -> Result<(), Box<dyn std::error::Error>> {
+    let build = |reversed: bool| -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut files = vec![
+            ("./test_assets/awesome.toml", "/etc/awesome/config.toml"),
+            ("./test_assets/awesome.py", "/usr/bin/awesome"),
+            ("./test_assets/awesome.toml", "/etc/awesome/second.toml"),
+        ];
+        let mut requires = vec![
+            Dependency::any("python3"),
+            Dependency::greater_eq("glibc", "2.17"),
+            Dependency::any("python3"),
+            Dependency::any("bash"),
+        ];
+        if reversed {
+            files.reverse();
+            requires.reverse();
+        }
+        let mut builder = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+            .provides(Dependency::any("awesome-cli"))
+            .conflicts(Dependency::less("awesome-legacy", "1.0"));
+        for (source, destination) in files {
+            builder = builder.with_file(source, RPMFileOptions::new(destination))?;
+        }
+        for dependency in requires {
+            builder = builder.requires(dependency);
+        }
+        let mut out = Vec::new();
+        builder.build()?.write(&mut out)?;
+        Ok(out)
+    };
+
+    let package = build(false)?;
+    assert_eq!(package, build(true)?);
+
+    let header = RPMPackage::parse(&mut package.as_slice())?.metadata.header;
+    let requires = header
+        .get_requires()?
+        .iter()
+        .map(|dep| dep.name().to_string())
+        .collect::<Vec<_>>();
+    let mut sorted = requires.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(sorted, requires);
+    assert_eq!(1, requires.iter().filter(|name| *name == "python3").count());
+    let paths = header.get_file_paths()?;
+    let mut sorted = paths.clone();
+    sorted.sort();
+    assert_eq!(sorted, paths);
+    Ok(())
+}
+
+#[test]
+fn test_noarch_check() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TestDir::new("noarch");
+    let binary = dir.join("awesome");
+    let mut header = vec![0u8; 64];
+    header[..4].copy_from_slice(&[0x7f, b'E', b'L', b'F']);
+    std::fs::write(&binary, header)?;
+
+    let builder = |arch| {
+        RPMBuilder::new("awesome", "1.0.0", "MIT", arch, "awesome package")
+            .with_file(&binary, RPMFileOptions::new("/usr/bin/awesome"))
+    };
+    match builder("noarch")?.build() {
+        Err(RPMError::SpecViolation { path, .. }) => assert_eq!("/usr/bin/awesome", path),
+        other => panic!("expected a violation, got {:?}", other.map(|_| ())),
+    }
+    assert!(builder("noarch")?.check_noarch(false).build().is_ok());
+    assert!(builder("x86_64")?.build().is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_rpmlib_requires() -> Result<(), Box<dyn std::error::Error>> {
+    use std::str::FromStr;
+
+    let features = |pkg: &RPMPackage| -> Result<Vec<String>, RPMError> {
+        Ok(pkg
+            .metadata
+            .header
+            .get_rpmlib_requires()?
+            .iter()
+            .map(|dep| dep.name().to_string())
+            .collect())
+    };
+
+    let empty = RPMBuilder::new("empty", "1.0.0", "MIT", "noarch", "").build()?;
+    assert_eq!(
+        vec![
+            "rpmlib(CompressedFileNames)",
+            "rpmlib(PayloadFilesHavePrefix)"
+        ],
+        features(&empty)?
+    );
+
+    let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .compression(Compressor::from_str("zstd")?)
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )?
+        .requires(Dependency::any("(foo or bar)"))
+        .build()?;
+    let requires = pkg.metadata.header.get_rpmlib_requires()?;
+    assert!(requires
+        .iter()
+        .all(|dep| dep.flags() & RPMSENSE_RPMLIB != 0 && dep.is_rpmlib()));
+    let features = features(&pkg)?;
+    for feature in [
+        "rpmlib(FileDigests)",
+        "rpmlib(PayloadIsZstd)",
+        "rpmlib(RichDependencies)",
+    ] {
+        assert!(features.iter().any(|name| name == feature), "{}", feature);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_installed_size() -> Result<(), Box<dyn std::error::Error>> {
+    let pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )?
+        .with_file(
+            "./test_assets/awesome.py",
+            RPMFileOptions::new("/usr/bin/awesome"),
+        )?
+        .build()?;
+    let expected = std::fs::metadata("./test_assets/awesome.toml")?.len()
+        + std::fs::metadata("./test_assets/awesome.py")?.len();
+    assert_eq!(expected, pkg.metadata.header.get_installed_size()?);
+
+    let empty = RPMBuilder::new("empty", "1.0.0", "MIT", "noarch", "").build()?;
+    assert_eq!(0, empty.metadata.header.get_installed_size()?);
+    Ok(())
+}
+
+#[test]
+fn test_estimated_size() -> Result<(), Box<dyn std::error::Error>> {
+    use std::str::FromStr;
+
+    for compressor in ["none", "gzip", "zstd"] {
+        let builder = RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "a package")
+            .compression(Compressor::from_str(compressor)?)
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/etc/awesome/config.toml").is_config(),
+            )?
+            .with_file(
+                "./test_assets/awesome.py",
+                RPMFileOptions::new("/usr/bin/awesome"),
+            )?
+            .requires(Dependency::any("python3"))
+            .post_install_script("echo installed")
+            .add_changelog_entry("me", "first release", Timestamp::EPOCH);
+        let estimate = builder.estimated_size();
+
+        let mut written = Vec::new();
+        builder.build()?.write(&mut written)?;
+        assert!(
+            estimate >= written.len() as u64,
+            "{} estimate {} is less than {}",
+            compressor,
+            estimate,
+            written.len()
+        );
+        assert!(
+            estimate < 2 * written.len() as u64 + 8192,
+            "{} estimate {} is far off {}",
+            compressor,
+            estimate,
+            written.len()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_planned_size_of_many_files() -> Result<(), Box<dyn std::error::Error>> {
+    use std::str::FromStr;
+
+    let mut builder = RPMBuilder::new("foo", "1.0.0", "MIT", "x86_64", "a package")
+        .compression(Compressor::from_str("zstd")?);
+    for i in 0..2000 {
+        builder.add_data(
+            format!("{}\n", i).into_bytes(),
+            Timestamp::EPOCH,
+            crate::RPMFileOptions::new(format!(
+                "/usr/share/foo/a-rather-long-directory-name-{}/file-{}.txt",
+                i % 50,
+                i
+            ))
+            .user("someone")
+            .into(),
+        )?;
+        builder = builder.requires(Dependency::greater_eq(format!("foo-part-{}", i), "1.0"));
+    }
+    let plan = builder.plan()?;
+
+    let mut written = Vec::new();
+    #[cfg(feature = "signature-pgp")]
+    {
+        let (signing_key, _) = crate::signature::pgp::test::load_asc_keys();
+        let signer = crate::signature::pgp::Signer::load_from_asc_bytes(signing_key.as_ref())?;
+        builder.build_and_sign(&signer)?.write(&mut written)?;
+    }
+    #[cfg(not(feature = "signature-pgp"))]
+    builder.build()?.write(&mut written)?;
+    assert!(
+        plan.estimated_size >= written.len() as u64,
+        "planned {} is less than {}",
+        plan.estimated_size,
+        written.len()
+    );
+    Ok(())
+}
+
+#[cfg(feature = "signature-cms")]
+#[test]
+fn test_cms_signature() -> Result<(), Box<dyn std::error::Error>> {
+    let (signer, verifier) = crate::signature::cms::test::load_pem_assets();
+    let builder = || {
+        RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package").with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )
+    };
+    let mut pkg = builder()?.build()?;
+    assert!(matches!(
+        pkg.verify_cms(&verifier),
+        Err(RPMError::TagNotFound(_))
+    ));
+    pkg.sign_cms(&signer)?;
+    // signing again replaces the signature
+    pkg.sign_cms(&signer)?;
+
+    let mut written = Vec::new();
+    pkg.write(&mut written)?;
+    let parsed = RPMPackage::parse(&mut written.as_slice())?;
+    parsed.verify_cms(&verifier)?;
+    let other = crate::signature::cms::Verifier::load_from_pem(include_bytes!(
+        "../test_assets/cms_other_ca.pem"
+    ))?;
+    assert!(parsed.verify_cms(&other).is_err());
+
+    // the signed header holds the digest of the payload
+    let mut tampered = RPMPackage::parse(&mut written.as_slice())?;
+    tampered.content = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .build()?
+        .content;
+    assert!(matches!(
+        tampered.verify_cms(&verifier),
+        Err(RPMError::DigestMismatch(_))
+    ));
+
+    #[cfg(feature = "signature-pgp")]
+    {
+        let (signing_key, verification_key) = crate::signature::pgp::test::load_asc_keys();
+        let pgp_signer = signature::pgp::Signer::load_from_asc_bytes(signing_key.as_ref())?;
+        let mut pkg = builder()?.build_and_sign(&pgp_signer)?;
+        pkg.sign_cms(&signer)?;
+        pkg.verify_cms(&verifier)?;
+        pkg.verify_signature(signature::pgp::Verifier::load_from_asc_bytes(
+            verification_key.as_ref(),
+        )?)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "signature-pgp")]
+#[test]
+fn test_shared_signers() -> Result<(), Box<dyn std::error::Error>> {
+    use signature::algorithm::RSA;
+    use signature::{DynSigning, Signing};
+    use std::borrow::Cow;
+    use std::sync::Arc;
+
+    /// Hands out the signature of the wrapped signer without owning it.
+    #[derive(Debug)]
+    struct Borrowing<S>(S);
+
+    impl<S: Signing<RSA, Signature = Vec<u8>>> Signing<RSA> for Borrowing<S> {
+        type Signature = Cow<'static, [u8]>;
+        fn sign<R: std::io::Read>(&self, data: R) -> Result<Self::Signature, RPMError> {
+            Ok(Cow::Owned(self.0.sign(data)?))
+        }
+    }
+
+    let (signing_key, verification_key) = crate::signature::pgp::test::load_asc_keys();
+    let signer = signature::pgp::Signer::load_from_asc_bytes(signing_key.as_ref())?;
+    let verifier = Arc::new(signature::pgp::Verifier::load_from_asc_bytes(
+        verification_key.as_ref(),
+    )?);
+    let builder = || {
+        RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package").with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )
+    };
+
+    let shared: Arc<dyn DynSigning<RSA> + Send + Sync> = Arc::new(Borrowing(signer));
+    let threads = (0..2)
+        .map(|_| {
+            let signer = Arc::clone(&shared);
+            std::thread::spawn(move || builder()?.build_and_sign(signer))
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        let mut pkg = thread.join().expect("signing thread")?;
+        pkg.verify_signature(Arc::clone(&verifier))?;
+        pkg.sign(Box::new(Arc::clone(&shared)))?;
+        pkg.verify_signature(&verifier)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "signature-pgp")]
+#[test]
+fn test_build_and_attest() -> Result<(), Box<dyn std::error::Error>> {
+    use sha2::Digest;
+    let (signing_key, verification_key) = crate::signature::pgp::test::load_asc_keys();
+    let signer = signature::pgp::Signer::load_from_asc_bytes(signing_key.as_ref())?;
+    let verifier = || signature::pgp::Verifier::load_from_asc_bytes(verification_key.as_ref());
+
+    let provenance = Provenance::new(
+        "https://ci.example.com/runner",
+        "https://example.com/rpm@v1",
+    );
+    let mut written = Vec::new();
+    let (info, envelope) = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )?
+        .build_and_attest(&mut written, provenance, &signer, "test key")?;
+
+    let package_sha256 = hex::encode(sha2::Sha256::digest(&written));
+    assert_eq!(Some(package_sha256.as_str()), info.sha256.as_deref());
+    RPMPackage::parse(&mut written.as_slice())?.verify_signature(verifier()?)?;
+
+    assert_eq!(IN_TOTO_PAYLOAD_TYPE, envelope.payload_type);
+    let statement = String::from_utf8(envelope.payload.clone())?;
+    assert!(statement.contains(&format!(
+        r#"{{"name":"awesome-1.0.0-1.noarch.rpm","digest":{{"sha256":"{}"}}}}"#,
+        package_sha256
+    )));
+    let material = Material::new(
+        "./test_assets/awesome.toml",
+        &std::fs::read("./test_assets/awesome.toml")?,
+    );
+    assert!(statement.contains(&format!(
+        r#"{{"uri":"./test_assets/awesome.toml","digest":{{"sha256":"{}"}}}}"#,
+        material.sha256
+    )));
+    envelope.verify(verifier()?)?;
+    assert!(envelope.to_string().contains(r#""keyid":"test key""#));
+
+    let mut tampered = envelope.clone();
+    tampered.payload_type = "text/plain".to_string();
+    assert!(tampered.verify(verifier()?).is_err());
+    Ok(())
+}
+
+#[cfg(feature = "signature-pgp")]
+#[test]
+fn test_verify_policy() -> Result<(), Box<dyn std::error::Error>> {
+    use signature::{DigestAlgorithm, VerifyPolicy};
+    let (signing_key, verification_key) = crate::signature::pgp::test::load_asc_keys();
+    let signer = signature::pgp::Signer::load_from_asc_bytes(signing_key.as_ref())?;
+    let verifier = || signature::pgp::Verifier::load_from_asc_bytes(verification_key.as_ref());
+    let signed = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .build_and_sign(&signer)?;
+
+    let policy = VerifyPolicy::new()
+        .ban_digest(DigestAlgorithm::Md5)
+        .ban_digest(DigestAlgorithm::Sha1);
+    signed.verify_signature_with(verifier()?, &VerifyOptions::new().policy(&policy))?;
+    // packages carry sha256 and sha1 digests besides md5
+    let no_digests = policy.ban_digest(DigestAlgorithm::Sha256);
+    assert!(matches!(
+        signed.verify_signature_with(verifier()?, &VerifyOptions::new().policy(&no_digests)),
+        Err(RPMError::PolicyViolation(_))
+    ));
+    assert!(matches!(
+        signed.verify_signature_with(
+            verifier()?,
+            &VerifyOptions::new().policy(&VerifyPolicy::new().min_rsa_bits(8192))
+        ),
+        Err(RPMError::PolicyViolation(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_fips_mode() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pkg = RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package")
+        .with_file(
+            "./test_assets/awesome.toml",
+            RPMFileOptions::new("/etc/awesome/config.toml"),
+        )?
+        .fips(true)
+        .build()?;
+    RPMBuilder::check_fips(&pkg.metadata)?;
+
+    // rpm assumes md5 if the algorithm is missing
+    let mut md5_files = RPMPackage::parse(
+        &mut std::fs::File::open(test_rpm_file_path()).map(std::io::BufReader::new)?,
+    )?;
+    md5_files
+        .metadata
+        .header
+        .index_entries
+        .retain(|entry| entry.tag != IndexTag::RPMTAG_FILEDIGESTALGO);
+    assert!(matches!(
+        RPMBuilder::check_fips(&md5_files.metadata),
+        Err(RPMError::BannedAlgorithm(_))
+    ));
+
+    let payload_digest = |tamper: &dyn Fn(&mut Vec<IndexEntry<IndexTag>>)| {
+        let mut pkg =
+            RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package").build()?;
+        tamper(&mut pkg.metadata.header.index_entries);
+        Ok::<_, RPMError>(RPMBuilder::check_fips(&pkg.metadata))
+    };
+    assert!(payload_digest(&|_| {})?.is_ok());
+    assert!(matches!(
+        payload_digest(
+            &|entries| entries.retain(|entry| entry.tag != IndexTag::RPMTAG_PAYLOADDIGEST)
+        )?,
+        Err(RPMError::BannedAlgorithm(_))
+    ));
+    assert!(matches!(
+        payload_digest(&|entries| {
+            for entry in entries {
+                if entry.tag == IndexTag::RPMTAG_PAYLOADDIGESTALGO {
+                    entry.data = IndexData::Int32(vec![2]);
+                }
+            }
+        })?,
+        Err(RPMError::BannedAlgorithm(_))
+    ));
+
+    pkg.metadata
+        .signature
+        .index_entries
+        .retain(|entry| entry.tag != IndexSignatureTag::RPMSIGTAG_SHA256);
+    assert!(matches!(
+        RPMBuilder::check_fips(&pkg.metadata),
+        Err(RPMError::BannedAlgorithm(_))
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "signature-pgp")]
+#[test]
+fn test_fips_mode_signatures() -> Result<(), Box<dyn std::error::Error>> {
+    use ::pgp::composed::{Deserializable, SignedSecretKey};
+    use ::pgp::crypto::hash::HashAlgorithm;
+    use ::pgp::packet::{SignatureConfig, SignatureType, SignatureVersion, Subpacket};
+    use ::pgp::types::KeyTrait;
+
+    /// Signs over SHA-1, which hosts enforcing FIPS refuse.
+    #[derive(Debug)]
+    struct Sha1Signer(SignedSecretKey);
+
+    impl signature::Signing<signature::algorithm::RSA> for Sha1Signer {
+        type Signature = Vec<u8>;
+        fn sign<R: std::io::Read>(&self, data: R) -> Result<Self::Signature, RPMError> {
+            let config = SignatureConfig {
+                version: SignatureVersion::V4,
+                typ: SignatureType::Binary,
+                pub_alg: self.0.algorithm(),
+                hash_alg: HashAlgorithm::SHA1,
+                issuer: Some(self.0.key_id()),
+                created: None,
+                unhashed_subpackets: vec![],
+                hashed_subpackets: vec![Subpacket::Issuer(self.0.key_id())],
+            };
+            let packet = config
+                .sign(&self.0, String::new, data)
+                .map_err(|e| RPMError::SignError(Box::new(e)))?;
+            let mut bytes = Vec::new();
+            ::pgp::packet::write_packet(&mut bytes, &packet)
+                .map_err(|e| RPMError::SignError(Box::new(e)))?;
+            Ok(bytes)
+        }
+    }
+
+    let (signing_key, _) = crate::signature::pgp::test::load_asc_keys();
+    let builder = || RPMBuilder::new("awesome", "1.0.0", "MIT", "noarch", "awesome package");
+    builder()
+        .fips(true)
+        .build_and_sign(signature::pgp::Signer::load_from_asc_bytes(&signing_key)?)?;
+    let (key, _) = SignedSecretKey::from_string(std::str::from_utf8(&signing_key)?)?;
+    let sha1 = Sha1Signer(key);
+    builder().build_and_sign(&sha1)?;
+    assert!(matches!(
+        builder().fips(true).build_and_sign(&sha1),
+        Err(RPMError::BannedAlgorithm(_))
+    ));
+
+    // parsed packages are only checked when asked to
+    let mut shipped = Vec::new();
+    builder().build_unsigned()?.write(&mut shipped)?;
+    UnsignedPackage::parse(&mut shipped.as_slice())?.finalize(&sha1)?;
+    assert!(matches!(
+        UnsignedPackage::parse(&mut shipped.as_slice())?
+            .fips(true)
+            .finalize(&sha1),
+        Err(RPMError::BannedAlgorithm(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_dedup_against() -> Result<(), Box<dyn std::error::Error>> {
+    use std::str::FromStr;
+
+    let build = |version: &str, extra: bool| -> Result<RPMPackage, RPMError> {
+        let mut builder = RPMBuilder::new("awesome", version, "MIT", "noarch", "awesome package")
+            .compression(Compressor::from_str("gzip")?)
+            .with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new("/etc/awesome/config.toml"),
+            )?
+            .with_file(
+                "./test_assets/awesome.py",
+                RPMFileOptions::new("/usr/bin/awesome"),
+            )?;
+        if extra {
+            builder = builder.with_file(
+                "./test_assets/awesome.xml",
+                RPMFileOptions::new("/usr/share/awesome/awesome.xml"),
+            )?;
+        }
+        builder.build()
+    };
+    let old = build("1.0.0", false)?;
+    let new = build("1.0.1", true)?;
+
+    let same = old.dedup_against(&old, Chunking::Fixed(64))?;
+    assert_eq!(1.0, same.shared_ratio());
+    let report = new.dedup_against(&old, Chunking::fastcdc())?;
+    assert!(report.new_size > report.old_size);
+    assert_eq!(report.new_size, report.shared_bytes + report.unique_bytes);
+    assert!(report.unique_bytes > 0);
+    Ok(())
+}
+
+#[test]
+fn test_delta_rpm() -> Result<(), Box<dyn std::error::Error>> {
+    use std::str::FromStr;
+
+    let build = |version: &str, files: &[&str], compressor: &str| {
+        let mut builder = RPMBuilder::new("awesome", version, "MIT", "noarch", "awesome package")
+            .compression(Compressor::from_str(compressor)?);
+        for file in files {
+            builder = builder.with_file(
+                format!("./test_assets/{}", file),
+                RPMFileOptions::new(format!("/usr/share/awesome/{}", file)),
+            )?;
+        }
+        builder.build()
+    };
+    for compressor in ["gzip", "zstd"] {
+        let old = build("1.0.0", &["awesome.toml", "awesome.py"], compressor)?;
+        let new = build(
+            "1.0.1",
+            &["awesome.toml", "awesome.py", "awesome.xml"],
+            compressor,
+        )?;
+
+        let delta = FezDelta::create(&old, &new)?;
+        assert_eq!("awesome-1.0.0-1.noarch", delta.old_nevra);
+        assert_eq!("1.0.1", delta.target.header.get_version()?);
+        let mut written = Vec::new();
+        delta.write(&mut written)?;
+        let delta = FezDelta::parse(&mut written.as_slice())?;
+
+        // deltas of the first version lack the gzip time stamp
+        let mut old_version = Vec::new();
+        delta.target.write(&mut old_version)?;
+        old_version.extend(zstd::stream::encode_all(&b"FZD1"[..], 3)?);
+        match FezDelta::parse(&mut old_version.as_slice()) {
+            Err(RPMError::InvalidDelta(reason)) => {
+                assert_eq!("unsupported delta version 1", reason)
+            }
+            other => panic!("expected an unsupported version, got {:?}", other),
+        }
+
+        let mut expected = Vec::new();
+        new.write(&mut expected)?;
+        let mut rebuilt = Vec::new();
+        delta.apply(&old)?.write(&mut rebuilt)?;
+        assert_eq!(expected, rebuilt);
+
+        assert!(matches!(delta.apply(&new), Err(RPMError::InvalidDelta(_))));
+
+        let root = TestDir::new("delta");
+        old.install_into(&root, InstallOptions::new())?;
+        let mut rebuilt = Vec::new();
+        delta
+            .apply_installed(&old.metadata.header, &root)?
+            .write(&mut rebuilt)?;
+        assert_eq!(expected, rebuilt);
+        std::fs::write(root.join("usr/share/awesome/awesome.py"), b"changed")?;
+        assert!(matches!(
+            delta.apply_installed(&old.metadata.header, &root),
+            Err(RPMError::InvalidDelta(_))
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_verify_digests() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pkg = RPMPackage::parse(
+        &mut std::fs::File::open(test_rpm_file_path()).map(std::io::BufReader::new)?,
+    )?;
+    pkg.verify_digests()?;
+    pkg.content[0] ^= 1;
+    assert!(matches!(
+        pkg.verify_digests(),
+        Err(RPMError::DigestMismatch(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_compressed_file_list() -> Result<(), Box<dyn std::error::Error>> {
+    let mut builder = RPMBuilder::new("many", "1.0.0", "MIT", "noarch", "many files");
+    for dir in 0..20 {
+        for file in 0..50 {
+            builder = builder.with_file(
+                "./test_assets/awesome.toml",
+                RPMFileOptions::new(format!("/usr/share/many/{}/file-{}.toml", dir, file)),
+            )?;
+        }
+    }
+    let pkg = builder.build()?;
+    let header = &pkg.metadata.header;
+    assert!(header
+        .find_entry_or_err(&IndexTag::RPMTAG_OLDFILENAMES)
+        .is_err());
+    let list = header.get_file_list()?;
+    assert_eq!(1000, list.len());
+    assert_eq!(20, list.dir_names.len());
+    assert_eq!("/usr/share/many/0/", list.dir_name(0));
+    assert_eq!(
+        header.get_file_paths()?,
+        (0..list.len()).map(|i| list.path(i)).collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_timestamps() -> Result<(), Box<dyn std::error::Error>> {
+    let late = Timestamp::new(i32::MAX as u32 + 100);
+    let mut builder = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "a package")
+        .build_time(late)
+        .add_changelog_entry("me", "first release", Timestamp::new(1_600_000_000))
+        .add_changelog_entry("me", "after 2038", late);
+    builder.add_data(
+        b"late".to_vec(),
+        late,
+        RPMFileOptions::new("/usr/share/foo/late").into(),
+    )?;
+    let pkg = builder.build()?;
+    let header = &pkg.metadata.header;
+    assert_eq!(late, header.get_build_time()?);
+    assert_eq!(
+        vec![Timestamp::new(1_600_000_000), late],
+        header.get_changelog_times()?
+    );
+    let entries = header.get_file_entries()?;
+    assert_eq!(
+        chrono::DateTime::<chrono::Utc>::from(late),
+        entries[0].modified_at
+    );
+
+    let unset = RPMBuilder::new("foo", "1.0.0", "MIT", "noarch", "a package").build()?;
+    assert!(unset.metadata.header.get_build_time().is_err());
+    Ok(())
+}